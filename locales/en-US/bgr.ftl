@@ -0,0 +1,200 @@
+# Messages printed by the bgr command line. Every other locale falls back to this file for
+# messages it does not translate.
+
+## Errors
+
+error = Error: { $message }
+error-batch = { $failed } of { $total } inputs failed
+error-fixtures = { $failed } of { $total } fixtures failed
+error-cancelled = Cancelled; partial model downloads are resumed next time
+error-limit-exceeded = Input { $what } { $actual } exceeds the limit of { $limit }
+error-unsupported-format = Unsupported input format { $format }: { $hint }
+failed-to-process = Failed to process { $input }: { $error }
+debug-bundle-saved = Saved a debug bundle for { $input } to { $path }
+debug-bundle-failed = Could not save a debug bundle for { $input }: { $error }
+error-on-empty = no foreground detected, and --on-empty is fail
+error-on-full = the whole image was detected as foreground, and --on-full is fail
+error-output-exists = { $path } already exists; pass --overwrite to replace it, --skip-existing to leave it, or --suffix to write beside it
+
+## Per-input warnings
+
+warning-for-input = { $input }: { $message }
+warning-icc-profile-ignored = embedded ICC profile ignored; colors are treated as sRGB
+warning-orientation-applied = EXIF orientation { $exif } applied; outputs are rotated to match
+warning-subject-touches-edge = subject touches the { $edges } edge; it may be cropped
+warning-low-confidence = low matte confidence ({ $percent }%); edges may be unreliable
+warning-empty-matte = no foreground detected
+warning-full-matte = the whole image was detected as foreground
+warning-input-kept-whole = the matte was made opaque; the input is kept whole
+warning-output-skipped = no output written (choose with --on-empty and --on-full)
+warning-output-exists = { $path } already exists; skipped
+warning-output-renamed = the output already existed; wrote { $path } instead
+warning-already-written = { $path } was written by the interrupted run; skipped
+warning-mask-resized = mask was { $width }x{ $height } and has been stretched to the image size
+warning-mask-binarized = mask was nearly black and white; stray gray values were thresholded
+warning-alpha-flattened = the output format has no transparency; flattened over { $color } (choose the color with --bg-color)
+edge-top = top
+edge-bottom = bottom
+edge-left = left
+edge-right = right
+
+## Run-wide notes
+
+low-memory-ignores-jobs = --low-memory processes one image at a time; ignoring --jobs.
+device-fallback = The { $requested } execution provider is unavailable; running on { $device }.
+compositor-fallback = The GPU compositor is unavailable ({ $error }); compositing on the CPU.
+batch-failed = { $failed } failed
+batch-existing-outputs = Outputs that already existed: { $skipped } skipped, { $renamed } written under a new name
+batch-resumed = Resumed from the journal: { $count ->
+        [one] 1 input was
+       *[other] { $count } inputs were
+    } already done
+batch-resume-hint = To continue where this batch stopped, run it again with --resume (journal: { $path })
+batch-journal-unavailable = Cannot keep the batch journal { $path } ({ $error }); --resume will not be able to continue this batch
+cancelling = Cancelling after the current step; press Ctrl-C again to exit now.
+soft-conflict = --no-binary disables thresholding, but erosion/dilation/fill-holes assume a hard mask; { $context } may be unexpected.
+
+## Models
+
+downloading-model = Downloading model: { $name } ({ $size } MB)...
+download-complete = Download complete!
+download-resuming = Resuming { $name } after { $size } MB already downloaded
+using-model = Using model { $path }
+models-directory = Models directory: { $path }
+model-removed = Removed { $name }
+model-not-downloaded = { $name } is not downloaded
+model-already-downloaded = { $name } is already downloaded
+model-downloading = Downloading { $name } ({ $size } MB)...
+model-saved = Saved to { $path }
+model-advice = Use { $name }: { $quality } quality, { $speed }, { $license }
+model-advice-restricted = Check that its license allows your use before relying on it.
+model-advice-alternatives = Then, in order: { $names }
+auto-model = --model auto selected { $name }
+license-prompt = { $name } is published under the { $license } license ({ $url }). Accept it? [y/N]
+
+## Outputs
+
+saved-foreground = Foreground PNG saved to { $path }
+saved-composite = Composited image saved to { $path }
+saved-matte = Matte PNG saved to { $path }
+saved-mask = Processed mask PNG saved to { $path }
+saved-layers = Layered file saved to { $path }
+saved-subject-layers = { $subjects ->
+        [one] 1 subject
+       *[other] { $subjects } subjects
+    } saved as layers to { $path }
+saved-selection = Selection saved to { $path }
+saved-sidecar = Sidecar saved to { $path }
+storage-chosen = Storing mattes as { $format }, the smallest within --max-error over { $count ->
+        [one] 1 sample
+       *[other] { $count } samples
+    }
+saved-svg = SVG saved to { $path }
+saved-lottie = Lottie animation saved to { $path }
+saved-summary = Summary saved to { $path }
+saved-contour-metadata = Contour metadata saved to { $path }
+saved-collision-shapes = Collision polygons saved to { $path }
+saved-video = Video with { $frames } frames saved to { $path }
+video-resumable = Stopped after { $frames } frames; run the same command again to continue.
+saved-animation = Animation with { $frames } frames saved to { $path }
+verify-recorded = Recorded { $count ->
+        [one] 1 fingerprint
+       *[other] { $count } fingerprints
+    } to { $path }
+verify-missing-golden = { $name } has no golden fingerprint; run with --update to record it.
+status-ok = ok       { $name }
+status-failed = FAILED   { $name }: { $reason }
+status-changed = CHANGED  { $name }: { $reason }
+status-missing = MISSING  { $name }
+status-new = NEW      { $name }
+status-same = same     { $name }
+fixtures-written = Fixtures and compatibility report written to { $path }
+fixtures-inverted = { $name } matches once its matte is inverted; the model may predict the background.
+fixtures-nondeterministic = Two runs on the same input gave different mattes.
+quality-target-met = { $input }: { $metric } { $score } with { $model }
+quality-target-missed = { $input }: the best { $metric } was { $score }, below the target of { $target }; keeping { $model }
+
+## Long-running modes
+
+daemon-listening = Daemon listening on { $path } ({ $sessions ->
+        [one] 1 session
+       *[other] { $sessions } sessions
+    }); use --via-daemon to connect
+serve-listening = Listening on http://{ $address } ({ $sessions ->
+        [one] 1 session
+       *[other] { $sessions } sessions
+    })
+watch-started = Watching { $input } for new images; results go to { $output }
+watch-error = Watch error: { $error }
+
+## Usage accounting
+
+usage-log-failed = Could not record usage in { $path }: { $error }
+usage-skipped-lines = Skipped { $count ->
+        [one] 1 unreadable line
+       *[other] { $count } unreadable lines
+    } in { $path }
+
+## URL inputs
+
+url-cache-stale = Could not reach { $url } ({ $error }); using the cached copy
+
+## Baselines
+
+baseline-summary = { $changed } of { $compared ->
+        [one] 1 output
+       *[other] { $compared } outputs
+    } changed against { $path }
+
+## Config file
+
+config-unreadable = Warning: ignoring the config file, which could not be read: { $error }
+config-value-ignored = Ignoring the config value of { $key } ({ $reason }); fix it with `bgr config set { $key }` or remove it with `bgr config unset { $key }`
+config-key-unset = { $key } is not set in the config file
+
+## Job files
+
+job-step = Step { $step } of { $total }: { $command }
+job-step-invalid = Step { $step } of the job is not a valid command: { $error }
+
+## Doctor
+
+doctor-version = bgr { $version } on { $os } { $arch }
+doctor-backend = Backend { $backend }
+doctor-backend-runtime = Backend { $backend }: { $version }
+doctor-backend-missing = Backend { $backend } is not in this build
+doctor-device-available = Device { $device }: available
+doctor-device-unusable = Device { $device }: in this build, but not usable on this machine
+doctor-device-not-built = Device { $device }: not in this build
+doctor-device-requested-unusable = Device { $device } was requested but is not usable on this machine
+doctor-device-requested-not-built = Device { $device } was requested but is not in this build
+doctor-models-dir = Models directory { $path } is writable
+doctor-models-dir-missing = Models directory { $path } does not exist yet; the first download creates it
+doctor-models-dir-unwritable = Models directory { $path } is not writable: { $error }
+doctor-ok = ok    { $message }
+doctor-fail = FAIL  { $message }
+doctor-preset = Preset { $name }: { $size } MB, sha256 { $checksum } matches its download
+doctor-preset-unrecorded = Preset { $name }: { $size } MB, sha256 { $checksum } (no digest was recorded at download to compare with)
+doctor-preset-corrupt = Preset { $name } has sha256 { $checksum }, but was downloaded as { $expected }
+doctor-preset-truncated = Preset { $name } is { $size } MB, but the full download is about { $expected } MB
+doctor-preset-unreadable = Preset { $name } cannot be read: { $error }
+doctor-model = Model { $name } is downloaded
+doctor-model-missing = Model { $name } is not downloaded
+doctor-model-file-missing = Model file { $path } does not exist
+doctor-smoke = Test cutout ran on { $device } in { $ms } ms
+doctor-smoke-fallback = Test cutout fell back to the CPU instead of { $device }
+doctor-smoke-failed = Test cutout failed: { $error }
+doctor-fix = Fix: { $fix }
+doctor-fix-feature = install a build with `--features { $feature }`
+doctor-fix-backend = install a build with `--features { $feature }` and run with `--backend { $backend }`
+doctor-fix-driver = install the { $device } drivers and runtime libraries, or run with `--device auto` to fall back to the CPU
+doctor-fix-models-dir = point `--models-dir` or BGR_MODELS_DIR at a writable directory
+doctor-fix-redownload = download it again with `bgr models rm { $name } && bgr models pull { $name }`
+doctor-fix-pull = download it with `bgr models pull { $name }`, or pick a downloaded preset with `--model`
+doctor-fix-model-path = pass `--model` a preset name or the path of an ONNX file
+doctor-fix-model-arch = check that the file is an ONNX matting model, and name its architecture with `--model-arch`
+doctor-problems = { $count ->
+    [one] Found 1 problem
+   *[other] Found { $count } problems
+}
+doctor-healthy = No problems found
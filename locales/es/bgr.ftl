@@ -0,0 +1,199 @@
+# Spanish messages for the bgr command line.
+
+## Errors
+
+error = Error: { $message }
+error-batch = Fallaron { $failed } de { $total } imágenes
+error-fixtures = Fallaron { $failed } de { $total } fixtures
+error-cancelled = Cancelado; las descargas de modelos a medias se reanudan la próxima vez
+error-limit-exceeded = La imagen supera el límite de { $what }: { $actual } > { $limit }
+error-unsupported-format = Formato de imagen no compatible { $format }: { $hint }
+failed-to-process = No se pudo procesar { $input }: { $error }
+debug-bundle-saved = Se guardó un paquete de depuración de { $input } en { $path }
+debug-bundle-failed = No se pudo guardar un paquete de depuración de { $input }: { $error }
+error-on-empty = no se detectó ningún primer plano, y --on-empty es fail
+error-on-full = toda la imagen se detectó como primer plano, y --on-full es fail
+error-output-exists = { $path } ya existe; usa --overwrite para reemplazarlo, --skip-existing para dejarlo o --suffix para escribir al lado
+
+## Per-input warnings
+
+warning-for-input = { $input }: { $message }
+warning-icc-profile-ignored = se ignoró el perfil ICC incrustado; los colores se tratan como sRGB
+warning-orientation-applied = se aplicó la orientación EXIF { $exif }; las salidas se giran para coincidir
+warning-subject-touches-edge = el sujeto toca el borde { $edges }; puede quedar recortado
+warning-low-confidence = confianza baja en la máscara ({ $percent } %); los bordes pueden no ser fiables
+warning-empty-matte = no se detectó ningún primer plano
+warning-full-matte = toda la imagen se detectó como primer plano
+warning-input-kept-whole = la máscara se hizo opaca; la entrada se conserva entera
+warning-output-skipped = no se escribió ninguna salida (elige con --on-empty y --on-full)
+warning-output-exists = { $path } ya existe; se omitió
+warning-output-renamed = la salida ya existía; se escribió { $path } en su lugar
+warning-already-written = la ejecución interrumpida ya escribió { $path }; se omitió
+warning-mask-resized = la máscara medía { $width }x{ $height } y se estiró al tamaño de la imagen
+warning-mask-binarized = la máscara era casi en blanco y negro; los grises sueltos se umbralizaron
+warning-alpha-flattened = el formato de salida no admite transparencia; se aplanó sobre { $color } (elige el color con --bg-color)
+edge-top = superior
+edge-bottom = inferior
+edge-left = izquierdo
+edge-right = derecho
+
+## Run-wide notes
+
+low-memory-ignores-jobs = --low-memory procesa una imagen cada vez; se ignora --jobs.
+device-fallback = El proveedor de ejecución { $requested } no está disponible; se usa { $device }.
+compositor-fallback = El compositor de GPU no está disponible ({ $error }); se compone en la CPU.
+batch-failed = { $failed } con error
+batch-existing-outputs = Salidas que ya existían: { $skipped } omitidas, { $renamed } escritas con otro nombre
+batch-resumed = Reanudado desde el registro: { $count ->
+        [one] 1 entrada ya estaba hecha
+       *[other] { $count } entradas ya estaban hechas
+    }
+batch-resume-hint = Para continuar donde se detuvo este lote, vuelve a ejecutarlo con --resume (registro: { $path })
+batch-journal-unavailable = No se puede mantener el registro del lote { $path } ({ $error }); --resume no podrá continuar este lote
+cancelling = Cancelando tras el paso actual; pulsa Ctrl-C de nuevo para salir ya.
+soft-conflict = --no-binary desactiva el umbral, pero la erosión, la dilatación y el relleno de huecos suponen una máscara binaria; el resultado ({ $context }) puede ser inesperado.
+
+## Models
+
+downloading-model = Descargando el modelo { $name } ({ $size } MB)...
+download-complete = ¡Descarga completada!
+download-resuming = Reanudando { $name } tras { $size } MB ya descargados
+using-model = Usando el modelo { $path }
+models-directory = Directorio de modelos: { $path }
+model-removed = Se eliminó { $name }
+model-not-downloaded = { $name } no está descargado
+model-already-downloaded = { $name } ya está descargado
+model-downloading = Descargando { $name } ({ $size } MB)...
+model-saved = Guardado en { $path }
+model-advice = Usa { $name }: calidad { $quality }, velocidad { $speed }, { $license }
+model-advice-restricted = Comprueba que su licencia permite tu uso antes de depender de él.
+model-advice-alternatives = Después, por orden: { $names }
+auto-model = --model auto eligió { $name }
+license-prompt = { $name } se publica bajo la licencia { $license } ({ $url }). ¿La acepta? [y/N]
+
+## Outputs
+
+saved-foreground = PNG del primer plano guardado en { $path }
+saved-composite = Imagen compuesta guardada en { $path }
+saved-matte = PNG de la máscara guardado en { $path }
+saved-mask = PNG de la máscara procesada guardado en { $path }
+saved-layers = Archivo por capas guardado en { $path }
+saved-subject-layers = { $subjects ->
+        [one] 1 sujeto guardado
+       *[other] { $subjects } sujetos guardados
+    } como capas en { $path }
+saved-selection = Selección guardada en { $path }
+saved-sidecar = Metadatos guardados en { $path }
+storage-chosen = Las máscaras se guardan como { $format }, el formato más pequeño dentro de --max-error en { $count ->
+        [one] 1 muestra
+       *[other] { $count } muestras
+    }
+saved-svg = SVG guardado en { $path }
+saved-lottie = Animación Lottie guardada en { $path }
+saved-summary = Resumen guardado en { $path }
+saved-contour-metadata = Metadatos de contorno guardados en { $path }
+saved-collision-shapes = Polígonos de colisión guardados en { $path }
+saved-video = Vídeo de { $frames } fotogramas guardado en { $path }
+video-resumable = Detenido tras { $frames } fotogramas; ejecute el mismo comando de nuevo para continuar.
+saved-animation = Animación de { $frames } fotogramas guardada en { $path }
+verify-recorded = { $count ->
+        [one] Se registró 1 huella
+       *[other] Se registraron { $count } huellas
+    } en { $path }
+verify-missing-golden = { $name } no tiene huella de referencia; ejecute con --update para registrarla.
+status-ok = ok       { $name }
+status-failed = FALLO    { $name }: { $reason }
+status-changed = CAMBIO   { $name }: { $reason }
+status-missing = FALTA    { $name }
+status-new = NUEVO    { $name }
+status-same = igual    { $name }
+fixtures-written = Fixtures e informe de compatibilidad escritos en { $path }
+fixtures-inverted = { $name } coincide al invertir su mate; puede que el modelo prediga el fondo.
+fixtures-nondeterministic = Dos ejecuciones con la misma entrada dieron mates distintos.
+quality-target-met = { $input }: { $metric } { $score } con { $model }
+quality-target-missed = { $input }: el mejor { $metric } fue { $score }, por debajo del objetivo de { $target }; se conserva { $model }
+
+## Long-running modes
+
+daemon-listening = Demonio escuchando en { $path } ({ $sessions ->
+        [one] 1 sesión
+       *[other] { $sessions } sesiones
+    }); use --via-daemon para conectarse
+serve-listening = Escuchando en http://{ $address } ({ $sessions ->
+        [one] 1 sesión
+       *[other] { $sessions } sesiones
+    })
+watch-started = Vigilando { $input } en busca de imágenes nuevas; los resultados van a { $output }
+watch-error = Error de vigilancia: { $error }
+
+## Contabilidad de uso
+
+usage-log-failed = No se pudo registrar el uso en { $path }: { $error }
+usage-skipped-lines = Se omitieron { $count ->
+        [one] 1 línea ilegible
+       *[other] { $count } líneas ilegibles
+    } en { $path }
+
+## URL inputs
+
+url-cache-stale = No se pudo acceder a { $url } ({ $error }); se usa la copia en caché
+
+## Baselines
+
+baseline-summary = { $changed } de { $compared ->
+        [one] 1 salida
+       *[other] { $compared } salidas
+    } cambiaron respecto a { $path }
+
+## Config file
+
+config-unreadable = Aviso: se ignora el archivo de configuración, que no se pudo leer: { $error }
+config-value-ignored = Se ignora el valor de configuración de { $key } ({ $reason }); corríjalo con `bgr config set { $key }` o elimínelo con `bgr config unset { $key }`
+config-key-unset = { $key } no está definido en el archivo de configuración
+
+## Job files
+
+job-step = Paso { $step } de { $total }: { $command }
+job-step-invalid = El paso { $step } del trabajo no es un comando válido: { $error }
+
+## Doctor
+
+doctor-version = bgr { $version } en { $os } { $arch }
+doctor-backend = Backend { $backend }
+doctor-backend-runtime = Backend { $backend }: { $version }
+doctor-backend-missing = El backend { $backend } no está incluido en esta compilación
+doctor-device-available = Dispositivo { $device }: disponible
+doctor-device-unusable = Dispositivo { $device }: incluido en esta compilación, pero no utilizable en este equipo
+doctor-device-not-built = Dispositivo { $device }: no incluido en esta compilación
+doctor-device-requested-unusable = Se pidió el dispositivo { $device }, pero no es utilizable en este equipo
+doctor-device-requested-not-built = Se pidió el dispositivo { $device }, pero no está incluido en esta compilación
+doctor-models-dir = El directorio de modelos { $path } admite escritura
+doctor-models-dir-missing = El directorio de modelos { $path } aún no existe; la primera descarga lo crea
+doctor-models-dir-unwritable = El directorio de modelos { $path } no admite escritura: { $error }
+doctor-ok = ok    { $message }
+doctor-fail = FALLO { $message }
+doctor-preset = Preset { $name }: { $size } MB, sha256 { $checksum } coincide con su descarga
+doctor-preset-unrecorded = Preset { $name }: { $size } MB, sha256 { $checksum } (no se registró ningún resumen al descargarlo con el que compararlo)
+doctor-preset-corrupt = El preset { $name } tiene sha256 { $checksum }, pero se descargó como { $expected }
+doctor-preset-truncated = El preset { $name } ocupa { $size } MB, pero la descarga completa ronda los { $expected } MB
+doctor-preset-unreadable = No se puede leer el preset { $name }: { $error }
+doctor-model = El modelo { $name } está descargado
+doctor-model-missing = El modelo { $name } no está descargado
+doctor-model-file-missing = El archivo de modelo { $path } no existe
+doctor-smoke = El recorte de prueba se ejecutó en { $device } en { $ms } ms
+doctor-smoke-fallback = El recorte de prueba recurrió a la CPU en lugar de { $device }
+doctor-smoke-failed = El recorte de prueba falló: { $error }
+doctor-fix = Solución: { $fix }
+doctor-fix-feature = instala una compilación con `--features { $feature }`
+doctor-fix-backend = instala una compilación con `--features { $feature }` y ejecuta con `--backend { $backend }`
+doctor-fix-driver = instala los controladores y bibliotecas de { $device }, o ejecuta con `--device auto` para recurrir a la CPU
+doctor-fix-models-dir = apunta `--models-dir` o BGR_MODELS_DIR a un directorio con permiso de escritura
+doctor-fix-redownload = descárgalo de nuevo con `bgr models rm { $name } && bgr models pull { $name }`
+doctor-fix-pull = descárgalo con `bgr models pull { $name }`, o elige un preset descargado con `--model`
+doctor-fix-model-path = pasa a `--model` un nombre de preset o la ruta de un archivo ONNX
+doctor-fix-model-arch = comprueba que el archivo sea un modelo de recorte ONNX e indica su arquitectura con `--model-arch`
+doctor-problems = { $count ->
+    [one] Se encontró 1 problema
+   *[other] Se encontraron { $count } problemas
+}
+doctor-healthy = No se encontraron problemas
@@ -0,0 +1,299 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use ndarray::{Array2, ArrayD};
+
+use crate::BgrResult;
+use crate::inference::extract_matte_hw;
+
+const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+/// Weights of the fused map and the side outputs from finest to coarsest, for U2-Net's seven
+/// outputs: the coarse sides add recall for thin structures without washing out edges.
+const DEFAULT_FUSION_WEIGHTS: [f32; 7] = [0.4, 0.2, 0.15, 0.1, 0.07, 0.05, 0.03];
+
+/// Per-channel statistics the input pixels are normalized with, after scaling them to 0..1:
+/// each channel has its mean subtracted and is divided by its standard deviation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+}
+
+impl Normalization {
+    /// ImageNet statistics, which most matting models are trained with.
+    pub const IMAGENET: Self = Self {
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+    };
+    /// ImageNet means with unit deviation, as IS-Net is trained.
+    pub const IMAGENET_MEAN_ONLY: Self = Self {
+        mean: IMAGENET_MEAN,
+        std: [1.0; 3],
+    };
+}
+
+/// What is specific to a model architecture on either side of the model run: how its input
+/// is normalized and how its outputs become a matte.
+///
+/// [`Architecture`] covers the models bgr ships presets for; implement this for a new
+/// architecture and install it with
+/// [`InferenceSettings::with_adapter`](crate::InferenceSettings::with_adapter), without
+/// touching the inference path.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{BgrResult, InferenceSettings, ModelAdapter};
+/// use ndarray::{Array2, ArrayD, Ix4};
+///
+/// /// A model whose second output holds background probabilities.
+/// struct Inverted;
+///
+/// impl ModelAdapter for Inverted {
+///     fn matte(&self, outputs: Vec<ArrayD<f32>>) -> BgrResult<Array2<f32>> {
+///         let background = outputs[1].clone().into_dimensionality::<Ix4>()?;
+///         Ok(background.slice_move(ndarray::s![0, 0, .., ..]).mapv(|p| 1.0 - p))
+///     }
+/// }
+///
+/// let settings = InferenceSettings::new("model.onnx").with_adapter(Inverted);
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub trait ModelAdapter: Send + Sync {
+    /// How input pixels are normalized; ImageNet statistics by default.
+    fn normalization(&self) -> Normalization {
+        Normalization::IMAGENET
+    }
+
+    /// Turn the model's outputs, in the order the model declares them, into an H×W matte of
+    /// foreground probabilities at model resolution.
+    fn matte(&self, outputs: Vec<ArrayD<f32>>) -> BgrResult<Array2<f32>>;
+}
+
+/// The model architectures bgr knows how to pre- and post-process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Architecture {
+    /// ImageNet normalization, with the first output used as the matte as it is. Suits any
+    /// model that ends in its own sigmoid; the default for custom models.
+    #[default]
+    Generic,
+    /// U2-Net and U2-Netp: of the fused map and six side outputs, only the fused first one
+    /// is used.
+    U2Net,
+    /// IS-Net: inputs keep unit deviation, and the first of its side outputs is the finest.
+    IsNet,
+    /// BiRefNet: the first output holds logits, turned into probabilities with a sigmoid.
+    BiRefNet,
+}
+
+impl Architecture {
+    /// All architectures.
+    pub const ALL: &'static [Architecture] = &[
+        Architecture::Generic,
+        Architecture::U2Net,
+        Architecture::IsNet,
+        Architecture::BiRefNet,
+    ];
+
+    /// Architecture name for CLI display.
+    pub fn name(self) -> &'static str {
+        match self {
+            Architecture::Generic => "generic",
+            Architecture::U2Net => "u2net",
+            Architecture::IsNet => "isnet",
+            Architecture::BiRefNet => "birefnet",
+        }
+    }
+}
+
+impl ModelAdapter for Architecture {
+    fn normalization(&self) -> Normalization {
+        match self {
+            Architecture::IsNet => Normalization::IMAGENET_MEAN_ONLY,
+            _ => Normalization::IMAGENET,
+        }
+    }
+
+    fn matte(&self, outputs: Vec<ArrayD<f32>>) -> BgrResult<Array2<f32>> {
+        let first = outputs.into_iter().next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "the model produced no output")
+        })?;
+        let matte = extract_matte_hw(first.view())?;
+        Ok(match self {
+            Architecture::BiRefNet => matte.mapv(|logit| 1.0 / (1.0 + (-logit).exp())),
+            Architecture::Generic | Architecture::U2Net | Architecture::IsNet => matte,
+        })
+    }
+}
+
+/// Fuses an architecture's fused map with its side outputs, weighted, instead of using the
+/// first output alone; meant for the U2-Net family, whose side outputs all come at full
+/// resolution.
+///
+/// Outputs of another shape than the first are left out, so a single-output model gets the
+/// same matte as [`Architecture`] alone would give.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideOutputFusion {
+    architecture: Architecture,
+    weights: Vec<f32>,
+}
+
+impl SideOutputFusion {
+    /// Fuse the outputs of `architecture` with the default weights, which favor the fused map
+    /// and the finest sides.
+    pub fn new(architecture: Architecture) -> Self {
+        Self {
+            architecture,
+            weights: DEFAULT_FUSION_WEIGHTS.to_vec(),
+        }
+    }
+
+    /// Weigh the outputs, in the order the model declares them, with `weights`; outputs past
+    /// the last weight are left out. The weights need not add up to one.
+    pub fn with_weights(mut self, weights: Vec<f32>) -> Self {
+        self.weights = weights;
+        self
+    }
+}
+
+impl ModelAdapter for SideOutputFusion {
+    fn normalization(&self) -> Normalization {
+        self.architecture.normalization()
+    }
+
+    fn matte(&self, outputs: Vec<ArrayD<f32>>) -> BgrResult<Array2<f32>> {
+        let shape = outputs.first().map(|first| first.shape().to_vec());
+        let mut fused: Option<Array2<f32>> = None;
+        let mut total = 0.0;
+        for (output, &weight) in outputs.into_iter().zip(&self.weights) {
+            if Some(output.shape()) != shape.as_deref() || weight <= 0.0 {
+                continue;
+            }
+            let matte = self.architecture.matte(vec![output])? * weight;
+            total += weight;
+            fused = Some(match fused {
+                Some(fused) => fused + matte,
+                None => matte,
+            });
+        }
+        let fused = fused.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the model produced no output to fuse",
+            )
+        })?;
+        Ok(fused / total)
+    }
+}
+
+/// A cheaply cloned handle to a [`ModelAdapter`], as carried by
+/// [`InferenceSettings`](crate::InferenceSettings).
+#[derive(Clone)]
+pub struct SharedAdapter(Arc<dyn ModelAdapter>);
+
+impl SharedAdapter {
+    pub fn new(adapter: impl ModelAdapter + 'static) -> Self {
+        Self(Arc::new(adapter))
+    }
+}
+
+impl Default for SharedAdapter {
+    fn default() -> Self {
+        Self::new(Architecture::Generic)
+    }
+}
+
+impl fmt::Debug for SharedAdapter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedAdapter")
+    }
+}
+
+impl ModelAdapter for SharedAdapter {
+    fn normalization(&self) -> Normalization {
+        self.0.normalization()
+    }
+
+    fn matte(&self, outputs: Vec<ArrayD<f32>>) -> BgrResult<Array2<f32>> {
+        self.0.matte(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    /// A `[1, 1, 2, 2]` output holding `values`.
+    fn output(values: [f32; 4]) -> ArrayD<f32> {
+        ArrayD::from_shape_vec(IxDyn(&[1, 1, 2, 2]), values.to_vec()).unwrap()
+    }
+
+    mod architecture {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn side_outputs_after_the_first_are_ignored() {
+                let outputs = vec![output([0.0, 0.25, 0.5, 1.0]), output([1.0; 4])];
+                let matte = Architecture::U2Net.matte(outputs).unwrap();
+                assert_eq!(matte.as_slice().unwrap(), &[0.0, 0.25, 0.5, 1.0]);
+            }
+
+            #[test]
+            fn birefnet_logits_become_probabilities() {
+                let matte = Architecture::BiRefNet
+                    .matte(vec![output([0.0, 20.0, -20.0, 0.0])])
+                    .unwrap();
+                assert_eq!(matte[[0, 0]], 0.5);
+                assert!(matte[[0, 1]] > 0.999);
+                assert!(matte[[1, 0]] < 0.001);
+            }
+
+            #[test]
+            fn isnet_keeps_unit_deviation() {
+                assert_eq!(Architecture::IsNet.normalization().std, [1.0; 3]);
+                assert_eq!(
+                    Architecture::Generic.normalization(),
+                    Normalization::IMAGENET
+                );
+            }
+
+            #[test]
+            fn no_output_is_an_error() {
+                assert!(Architecture::Generic.matte(Vec::new()).is_err());
+            }
+        }
+    }
+
+    mod side_output_fusion {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn outputs_are_weighted() {
+                let fusion =
+                    SideOutputFusion::new(Architecture::U2Net).with_weights(vec![3.0, 1.0]);
+                let outputs = vec![output([0.0, 1.0, 1.0, 0.0]), output([1.0; 4])];
+                let matte = fusion.matte(outputs).unwrap();
+                assert_eq!(matte.as_slice().unwrap(), &[0.25, 1.0, 1.0, 0.25]);
+            }
+
+            #[test]
+            fn outputs_of_another_shape_are_left_out() {
+                let coarse = ArrayD::from_elem(IxDyn(&[1, 1, 1, 1]), 1.0);
+                let outputs = vec![output([0.0, 0.25, 0.5, 1.0]), coarse];
+                let matte = SideOutputFusion::new(Architecture::IsNet)
+                    .with_weights(vec![1.0, 1.0])
+                    .matte(outputs)
+                    .unwrap();
+                assert_eq!(matte.as_slice().unwrap(), &[0.0, 0.25, 0.5, 1.0]);
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Delay, Frame, Frames, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::sniff::detect_format;
+use crate::{BgrError, BgrResult, InputLimits};
+
+/// The frames of an animated GIF, APNG, or WebP, composited to full-size RGBA images.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Animation, Bgr, InputLimits};
+///
+/// let bgr = Bgr::new("model.onnx");
+/// let mut session = bgr.session()?;
+/// if let Some(animation) = Animation::open("party.gif", &InputLimits::default())? {
+///     let mut frames = Vec::new();
+///     for frame in animation.into_frames() {
+///         let delay = frame.delay();
+///         let rgb = image::DynamicImage::ImageRgba8(frame.into_buffer()).into_rgb8();
+///         let foreground = session.for_rgb(rgb)?.matte().foreground()?.into_image();
+///         frames.push(image::Frame::from_parts(foreground, 0, 0, delay));
+///     }
+///     bgr::Animation::from_frames(frames).save("party.png")?;
+/// }
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+#[derive(Debug)]
+pub struct Animation {
+    frames: Vec<Frame>,
+}
+
+impl Animation {
+    /// Decode `path` if it is an animation with more than one frame, after checking it
+    /// against `limits`; still images return `None`.
+    ///
+    /// The decoded-size limit applies to all frames together.
+    pub fn open(path: impl AsRef<Path>, limits: &InputLimits) -> BgrResult<Option<Self>> {
+        let path = path.as_ref();
+        limits.check_file_size(fs::metadata(path)?.len())?;
+        let reader = detect_format(ImageReader::open(path)?)?;
+        let format = reader.format();
+        let reader = reader.into_inner();
+        let frames = match format {
+            Some(ImageFormat::Gif) => into_frames(GifDecoder::new(reader)?, limits)?,
+            Some(ImageFormat::Png) => {
+                let mut decoder = PngDecoder::new(reader)?;
+                if !decoder.is_apng()? {
+                    return Ok(None);
+                }
+                check_header(&mut decoder, limits)?;
+                decoder.apng()?.into_frames()
+            }
+            Some(ImageFormat::WebP) => {
+                let decoder = WebPDecoder::new(reader)?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                into_frames(decoder, limits)?
+            }
+            _ => return Ok(None),
+        };
+
+        let mut decoded_bytes = 0u64;
+        let frames = frames
+            .map(|frame| {
+                let frame = frame?;
+                decoded_bytes +=
+                    u64::from(frame.buffer().width()) * u64::from(frame.buffer().height()) * 4;
+                limits.check_decoded_size(decoded_bytes)?;
+                Ok(frame)
+            })
+            .collect::<BgrResult<Vec<_>>>()?;
+        Ok((frames.len() > 1).then_some(Self { frames }))
+    }
+
+    /// Whether the file at `path` holds more than one frame, judged from its container structure
+    /// alone so that no pixel data is decoded. This is how animations are recognized under
+    /// `--sandbox-decode`, where [`Animation::open`] must not run in-process.
+    pub fn is_animated(path: impl AsRef<Path>, limits: &InputLimits) -> BgrResult<bool> {
+        let path = path.as_ref();
+        limits.check_file_size(fs::metadata(path)?.len())?;
+        let bytes = fs::read(path)?;
+        Ok(if bytes.starts_with(b"GIF8") {
+            gif_frame_count(&bytes) > 1
+        } else if let Some(chunks) = bytes.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+            apng_frame_count(chunks) > 1
+        } else if bytes.len() > 20 && bytes.starts_with(b"RIFF") && &bytes[8..16] == b"WEBPVP8X" {
+            // The animation flag of the extended header.
+            bytes[20] & 0x02 != 0
+        } else {
+            false
+        })
+    }
+
+    /// Wrap processed frames, such as cutouts of each frame of an opened animation.
+    pub fn from_frames(frames: Vec<Frame>) -> Self {
+        Self { frames }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn into_frames(self) -> Vec<Frame> {
+        self.frames
+    }
+
+    /// Encode the animation to `path` as an APNG that loops forever, keeping full alpha.
+    ///
+    /// The path must end in `.png` or `.apng`; GIF and WebP animations cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> BgrResult<()> {
+        let path = path.as_ref();
+        if ImageFormat::from_path(path).ok() != Some(ImageFormat::Png) && !is_apng_path(path) {
+            return Err(BgrError::UnsupportedFormat {
+                format: "animated output other than APNG",
+                hint: "save animations with a .png extension",
+            });
+        }
+        let Some(first) = self.frames.first() else {
+            return Err(BgrError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an animation needs at least one frame",
+            )));
+        };
+        let dimensions = first.buffer().dimensions();
+        if self
+            .frames
+            .iter()
+            .any(|frame| frame.buffer().dimensions() != dimensions)
+        {
+            return Err(BgrError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "animation frames differ in size",
+            )));
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_apng(&self.frames, &mut writer).map_err(io::Error::other)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn is_apng_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("apng"))
+}
+
+/// Count the image descriptors of a GIF by skipping over its blocks, stopping at the second.
+fn gif_frame_count(bytes: &[u8]) -> usize {
+    let Some(&screen_flags) = bytes.get(10) else {
+        return 0;
+    };
+    let mut at = 13 + gif_color_table_len(screen_flags);
+    let mut frames = 0;
+    while frames < 2 {
+        at = match bytes.get(at) {
+            Some(0x21) => skip_gif_sub_blocks(bytes, at + 2),
+            Some(0x2c) => {
+                frames += 1;
+                let Some(&image_flags) = bytes.get(at + 9) else {
+                    break;
+                };
+                // The descriptor, its local color table and the LZW code size precede the data.
+                skip_gif_sub_blocks(bytes, at + 11 + gif_color_table_len(image_flags))
+            }
+            _ => break,
+        };
+    }
+    frames
+}
+
+fn gif_color_table_len(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 << ((flags & 0x07) + 1)
+    }
+}
+
+fn skip_gif_sub_blocks(bytes: &[u8], mut at: usize) -> usize {
+    while let Some(&len) = bytes.get(at) {
+        at += 1 + usize::from(len);
+        if len == 0 {
+            break;
+        }
+    }
+    at
+}
+
+/// The frame count an APNG declares in its `acTL` chunk, or 0 for a still PNG.
+fn apng_frame_count(mut chunks: &[u8]) -> u32 {
+    while chunks.len() >= 12 {
+        let len = u32::from_be_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as usize;
+        match &chunks[4..8] {
+            b"acTL" => {
+                return u32::from_be_bytes([chunks[8], chunks[9], chunks[10], chunks[11]]);
+            }
+            b"IDAT" => return 0,
+            _ => {}
+        }
+        let Some(rest) = chunks.get(len.saturating_add(12)..) else {
+            return 0;
+        };
+        chunks = rest;
+    }
+    0
+}
+
+fn check_header(decoder: &mut impl ImageDecoder, limits: &InputLimits) -> BgrResult<()> {
+    let (width, height) = decoder.dimensions();
+    limits.check_dimensions(width, height)?;
+    decoder.set_limits(limits.decoder_limits())?;
+    Ok(())
+}
+
+fn into_frames<'a, D>(mut decoder: D, limits: &InputLimits) -> BgrResult<Frames<'a>>
+where
+    D: ImageDecoder + AnimationDecoder<'a>,
+{
+    check_header(&mut decoder, limits)?;
+    Ok(decoder.into_frames())
+}
+
+/// Write full-canvas RGBA frames as an APNG that loops forever.
+fn write_apng(frames: &[Frame], writer: impl Write) -> Result<(), png::EncodingError> {
+    let (width, height) = frames[0].buffer().dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    // Every frame covers the canvas and replaces the previous one, alpha included.
+    encoder.set_blend_op(png::BlendOp::Source)?;
+    encoder.set_dispose_op(png::DisposeOp::None)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        let (numerator, denominator) = apng_delay(frame.delay());
+        writer.set_frame_delay(numerator, denominator)?;
+        writer.write_image_data(frame.buffer().as_raw())?;
+    }
+    writer.finish()
+}
+
+/// A frame delay as the APNG fraction of a second, rounded to whole milliseconds.
+fn apng_delay(delay: Delay) -> (u16, u16) {
+    let (numerator, denominator) = delay.numer_denom_ms();
+    let millis = (f64::from(numerator) / f64::from(denominator)).round();
+    (millis.clamp(0.0, f64::from(u16::MAX)) as u16, 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Rgba, RgbaImage};
+
+    /// Frames alternating between an opaque red and a transparent canvas, 100 ms apart.
+    fn blinking(count: usize) -> Vec<Frame> {
+        (0..count)
+            .map(|i| {
+                let pixel = if i % 2 == 0 {
+                    Rgba([255, 0, 0, 255])
+                } else {
+                    Rgba([0, 0, 0, 0])
+                };
+                let delay = Delay::from_numer_denom_ms(100, 1);
+                Frame::from_parts(RgbaImage::from_pixel(6, 4, pixel), 0, 0, delay)
+            })
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("bgr-animation-{}-{name}", std::process::id()))
+    }
+
+    mod animation {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn apng_round_trip_keeps_frames_and_timing() {
+                let path = temp_path("round-trip.png");
+                Animation::from_frames(blinking(3)).save(&path).unwrap();
+                let animation = Animation::open(&path, &InputLimits::default())
+                    .unwrap()
+                    .unwrap();
+                fs::remove_file(&path).unwrap();
+                let frames = animation.frames();
+                assert_eq!(frames.len(), 3);
+                assert_eq!(frames[0].buffer().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+                assert_eq!(frames[1].buffer().get_pixel(0, 0)[3], 0);
+                assert_eq!(frames[2].delay().numer_denom_ms(), (100, 1));
+            }
+
+            #[test]
+            fn gif_frames_decoded() {
+                let path = temp_path("input.gif");
+                let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])];
+                let frames = colors
+                    .map(|color| Frame::new(RgbaImage::from_pixel(6, 4, color)))
+                    .to_vec();
+                let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+                encoder.encode_frames(frames).unwrap();
+                drop(encoder);
+                let animation = Animation::open(&path, &InputLimits::default())
+                    .unwrap()
+                    .unwrap();
+                fs::remove_file(&path).unwrap();
+                let frames = animation.frames();
+                assert_eq!(frames.len(), 2);
+                assert_eq!(frames[1].buffer().get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+            }
+
+            #[test]
+            fn animations_recognized_without_decoding() {
+                let limits = InputLimits::default();
+                let apng = temp_path("sniff.png");
+                Animation::from_frames(blinking(2)).save(&apng).unwrap();
+                let gif = temp_path("sniff.gif");
+                let mut encoder = GifEncoder::new(File::create(&gif).unwrap());
+                encoder.encode_frames(blinking(2)).unwrap();
+                drop(encoder);
+                let still_gif = temp_path("still.gif");
+                RgbaImage::new(4, 4).save(&still_gif).unwrap();
+                let still_png = temp_path("sniff-still.png");
+                RgbaImage::new(4, 4).save(&still_png).unwrap();
+
+                let animated = [&apng, &gif, &still_gif, &still_png]
+                    .map(|path| Animation::is_animated(path, &limits).unwrap());
+                for path in [apng, gif, still_gif, still_png] {
+                    fs::remove_file(path).unwrap();
+                }
+                assert_eq!(animated, [true, true, false, false]);
+            }
+
+            #[test]
+            fn only_apng_written() {
+                let path = temp_path("output.gif");
+                let err = Animation::from_frames(blinking(2)).save(&path).unwrap_err();
+                assert!(matches!(err, BgrError::UnsupportedFormat { .. }));
+                assert!(!path.exists());
+            }
+
+            #[test]
+            fn still_images_are_not_animations() {
+                let path = temp_path("still.png");
+                RgbaImage::new(4, 4).save(&path).unwrap();
+                let opened = Animation::open(&path, &InputLimits::default()).unwrap();
+                fs::remove_file(&path).unwrap();
+                assert!(opened.is_none());
+            }
+
+            #[test]
+            fn decoded_size_counts_every_frame() {
+                let path = temp_path("limits.png");
+                Animation::from_frames(blinking(4)).save(&path).unwrap();
+                // Each 6x4 frame is 96 bytes, so four frames exceed the limit together.
+                let limits = InputLimits {
+                    max_decoded_bytes: Some(300),
+                    ..InputLimits::unlimited()
+                };
+                let result = Animation::open(&path, &limits);
+                fs::remove_file(&path).unwrap();
+                assert!(result.is_err());
+            }
+
+            #[test]
+            fn mismatched_frames_rejected() {
+                let mut frames = blinking(1);
+                frames.push(Frame::new(RgbaImage::new(2, 2)));
+                let path = temp_path("mismatched.png");
+                assert!(Animation::from_frames(frames).save(&path).is_err());
+                assert!(!path.exists());
+            }
+        }
+    }
+
+    mod apng_delay {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn whole_milliseconds() {
+                assert_eq!(apng_delay(Delay::from_numer_denom_ms(100, 1)), (100, 1000));
+                assert_eq!(apng_delay(Delay::from_numer_denom_ms(100, 3)), (33, 1000));
+            }
+
+            #[test]
+            fn long_delays_saturate() {
+                assert_eq!(
+                    apng_delay(Delay::from_numer_denom_ms(120_000, 1)),
+                    (u16::MAX, 1000)
+                );
+            }
+        }
+    }
+}
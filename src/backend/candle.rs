@@ -0,0 +1,453 @@
+use std::path::{Path, PathBuf};
+
+use candle_core::safetensors::{MmapedSafetensors, SliceSafetensors};
+use candle_core::{DType, Device as CandleDevice, Module, ModuleT, Tensor};
+use candle_nn::{BatchNorm, BatchNormConfig, Conv2d, Conv2dConfig, VarBuilder};
+use ndarray::{Array4, ArrayD, IxDyn};
+
+use super::InferenceBackend;
+use crate::BgrResult;
+use crate::config::{Device, InferenceSettings};
+use crate::inference::{DEFAULT_MODEL_INPUT_SPEC, ModelInputSpec};
+use crate::models::ModelError;
+
+/// A weight whose output channels tell the full U2-Net (32) from U2-Netp (16).
+const PROBE_WEIGHT: &str = "stage1.rebnconv1.conv_s1.weight";
+
+/// Pure-Rust inference with candle on U2-Net and U2-Netp safetensors checkpoints, with CUDA
+/// and Metal acceleration when candle is built with them.
+///
+/// The weights are the PyTorch `state_dict` of the U-2-Net project saved as safetensors, read
+/// from the model path with its extension swapped to `.safetensors`.
+pub(crate) struct CandleBackend {
+    model: U2Net,
+    candle_device: CandleDevice,
+    device: Device,
+}
+
+impl CandleBackend {
+    /// Each candidate accelerator for [`InferenceSettings::device`] is tried in turn; if none
+    /// initializes, the model runs on the CPU.
+    pub(crate) fn load(settings: &InferenceSettings) -> BgrResult<Self> {
+        let (candle_device, device) = select_device(settings.device);
+        let model = match &settings.model_bytes {
+            Some(bytes) => {
+                let weights = SliceSafetensors::new(bytes.as_ref())?;
+                let widths = widths_of(&weights.load(PROBE_WEIGHT, &CandleDevice::Cpu)?)?;
+                let vb =
+                    VarBuilder::from_backend(Box::new(weights), DType::F32, candle_device.clone());
+                U2Net::load(vb, widths)?
+            }
+            None => {
+                let path = weights_path(&settings.model_path);
+                if !path.exists() {
+                    return Err(ModelError::NotFound(path).into());
+                }
+                // SAFETY: the file is memory-mapped read-only and not expected to change during
+                // loading.
+                let weights = unsafe { MmapedSafetensors::new(&path)? };
+                let widths = widths_of(&weights.load(PROBE_WEIGHT, &CandleDevice::Cpu)?)?;
+                let vb =
+                    VarBuilder::from_backend(Box::new(weights), DType::F32, candle_device.clone());
+                U2Net::load(vb, widths)?
+            }
+        };
+        Ok(Self {
+            model,
+            candle_device,
+            device,
+        })
+    }
+}
+
+/// The network size whose first stage produces `probe`, the [`PROBE_WEIGHT`].
+fn widths_of(probe: &Tensor) -> BgrResult<&'static Widths> {
+    match probe.dim(0)? {
+        32 => Ok(&FULL),
+        16 => Ok(&LITE),
+        width => Err(candle_core::Error::Msg(format!(
+            "not a U2-Net checkpoint: {PROBE_WEIGHT} has {width} channels"
+        ))
+        .into()),
+    }
+}
+
+impl InferenceBackend for CandleBackend {
+    fn input_spec(&self) -> ModelInputSpec {
+        DEFAULT_MODEL_INPUT_SPEC
+    }
+
+    fn device(&self) -> Device {
+        self.device
+    }
+
+    fn run(&mut self, input: Array4<f32>) -> BgrResult<Vec<ArrayD<f32>>> {
+        let shape = input.shape().to_vec();
+        let data: Vec<f32> = input.iter().copied().collect();
+        let tensor = Tensor::from_vec(data, shape, &self.candle_device)?;
+        let matte = self.model.forward(&tensor)?;
+        let dims = matte.dims().to_vec();
+        let values = matte.flatten_all()?.to_vec1::<f32>()?;
+        Ok(vec![ArrayD::from_shape_vec(IxDyn(&dims), values)?])
+    }
+}
+
+/// The safetensors file that stands in for `model_path`.
+fn weights_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("safetensors")
+}
+
+/// Whether candle can open `device`, or `None` when it has no support for it.
+pub(crate) fn device_available(device: Device) -> Option<bool> {
+    match device {
+        Device::Cuda => cfg!(feature = "candle-cuda").then(|| CandleDevice::new_cuda(0).is_ok()),
+        Device::Metal => cfg!(feature = "candle-metal").then(|| CandleDevice::new_metal(0).is_ok()),
+        _ => None,
+    }
+}
+
+/// The first candidate accelerator candle can open, otherwise the CPU.
+fn select_device(device: Device) -> (CandleDevice, Device) {
+    for &candidate in device.candidates() {
+        let opened = match candidate {
+            Device::Cuda => CandleDevice::new_cuda(0),
+            Device::Metal => CandleDevice::new_metal(0),
+            _ => continue,
+        };
+        if let Ok(opened) = opened {
+            return (opened, candidate);
+        }
+    }
+    (CandleDevice::Cpu, Device::Cpu)
+}
+
+/// One residual U-block: how many levels it has, whether it dilates instead of pooling, and
+/// its input, middle, and output channels.
+type BlockShape = (usize, bool, usize, usize, usize);
+
+/// Channel widths of one network size.
+struct Widths {
+    encoders: [BlockShape; 6],
+    decoders: [BlockShape; 5],
+    /// Input channels of `side1` through `side6`.
+    sides: [usize; 6],
+}
+
+/// The full U2-Net.
+const FULL: Widths = Widths {
+    encoders: [
+        (7, false, 3, 32, 64),
+        (6, false, 64, 32, 128),
+        (5, false, 128, 64, 256),
+        (4, false, 256, 128, 512),
+        (4, true, 512, 256, 512),
+        (4, true, 512, 256, 512),
+    ],
+    decoders: [
+        (4, true, 1024, 256, 512),
+        (4, false, 1024, 128, 256),
+        (5, false, 512, 64, 128),
+        (6, false, 256, 32, 64),
+        (7, false, 128, 16, 64),
+    ],
+    sides: [64, 64, 128, 256, 512, 512],
+};
+
+/// U2-Netp, the small variant.
+const LITE: Widths = Widths {
+    encoders: [
+        (7, false, 3, 16, 64),
+        (6, false, 64, 16, 64),
+        (5, false, 64, 16, 64),
+        (4, false, 64, 16, 64),
+        (4, true, 64, 16, 64),
+        (4, true, 64, 16, 64),
+    ],
+    decoders: [
+        (4, true, 128, 16, 64),
+        (4, false, 128, 16, 64),
+        (5, false, 128, 16, 64),
+        (6, false, 128, 16, 64),
+        (7, false, 128, 16, 64),
+    ],
+    sides: [64; 6],
+};
+
+/// Convolution, batch norm, and ReLU (`REBNCONV` upstream).
+struct ConvBnRelu {
+    conv: Conv2d,
+    bn: BatchNorm,
+}
+
+impl ConvBnRelu {
+    fn load(
+        vb: VarBuilder,
+        in_channels: usize,
+        out_channels: usize,
+        dilation: usize,
+    ) -> candle_core::Result<Self> {
+        let config = Conv2dConfig {
+            padding: dilation,
+            dilation,
+            ..Default::default()
+        };
+        Ok(Self {
+            conv: candle_nn::conv2d(in_channels, out_channels, 3, config, vb.pp("conv_s1"))?,
+            bn: candle_nn::batch_norm(out_channels, BatchNormConfig::default(), vb.pp("bn_s1"))?,
+        })
+    }
+
+    fn forward(&self, x: &Tensor) -> candle_core::Result<Tensor> {
+        self.bn.forward_t(&self.conv.forward(x)?, false)?.relu()
+    }
+}
+
+/// A residual U-block (`RSU4` to `RSU7`, or `RSU4F` when dilated): a small encoder-decoder
+/// whose output is added to its input convolution.
+struct ResidualU {
+    input: ConvBnRelu,
+    encoders: Vec<ConvBnRelu>,
+    bottom: ConvBnRelu,
+    decoders: Vec<ConvBnRelu>,
+    dilated: bool,
+}
+
+impl ResidualU {
+    fn load(vb: VarBuilder, shape: BlockShape) -> candle_core::Result<Self> {
+        let (levels, dilated, in_channels, mid, out) = shape;
+        let dilation = |level: usize| if dilated { 1 << (level - 1) } else { 1 };
+        let input = ConvBnRelu::load(vb.pp("rebnconvin"), in_channels, out, 1)?;
+        let encoders = (1..levels)
+            .map(|level| {
+                let from = if level == 1 { out } else { mid };
+                ConvBnRelu::load(
+                    vb.pp(format!("rebnconv{level}")),
+                    from,
+                    mid,
+                    dilation(level),
+                )
+            })
+            .collect::<candle_core::Result<_>>()?;
+        let bottom_dilation = if dilated { dilation(levels) } else { 2 };
+        let bottom = ConvBnRelu::load(
+            vb.pp(format!("rebnconv{levels}")),
+            mid,
+            mid,
+            bottom_dilation,
+        )?;
+        let decoders = (1..levels)
+            .rev()
+            .map(|level| {
+                let to = if level == 1 { out } else { mid };
+                ConvBnRelu::load(
+                    vb.pp(format!("rebnconv{level}d")),
+                    mid * 2,
+                    to,
+                    dilation(level),
+                )
+            })
+            .collect::<candle_core::Result<_>>()?;
+        Ok(Self {
+            input,
+            encoders,
+            bottom,
+            decoders,
+            dilated,
+        })
+    }
+
+    fn forward(&self, x: &Tensor) -> candle_core::Result<Tensor> {
+        let input = self.input.forward(x)?;
+        let mut skips = Vec::with_capacity(self.encoders.len());
+        let mut hx = input.clone();
+        for (i, encoder) in self.encoders.iter().enumerate() {
+            let features = encoder.forward(&hx)?;
+            hx = if !self.dilated && i + 1 < self.encoders.len() {
+                pool(&features)?
+            } else {
+                features.clone()
+            };
+            skips.push(features);
+        }
+        let mut hx = self.bottom.forward(&hx)?;
+        for (decoder, skip) in self.decoders.iter().zip(skips.iter().rev()) {
+            let up = upsample_like(&hx, skip)?;
+            hx = decoder.forward(&Tensor::cat(&[&up, skip], 1)?)?;
+        }
+        hx + input
+    }
+}
+
+/// U2-Net: six residual U-block encoders, five decoders, and a fused side output per level.
+struct U2Net {
+    encoders: Vec<ResidualU>,
+    decoders: Vec<ResidualU>,
+    sides: Vec<Conv2d>,
+    fuse: Conv2d,
+}
+
+impl U2Net {
+    fn load(vb: VarBuilder, widths: &Widths) -> candle_core::Result<Self> {
+        let encoders = (1..)
+            .zip(widths.encoders)
+            .map(|(n, shape)| ResidualU::load(vb.pp(format!("stage{n}")), shape))
+            .collect::<candle_core::Result<_>>()?;
+        let decoders = (1..=5)
+            .rev()
+            .zip(widths.decoders)
+            .map(|(n, shape)| ResidualU::load(vb.pp(format!("stage{n}d")), shape))
+            .collect::<candle_core::Result<_>>()?;
+        let side_config = Conv2dConfig {
+            padding: 1,
+            ..Default::default()
+        };
+        let sides = (1..)
+            .zip(widths.sides)
+            .map(|(n, channels)| {
+                candle_nn::conv2d(channels, 1, 3, side_config, vb.pp(format!("side{n}")))
+            })
+            .collect::<candle_core::Result<_>>()?;
+        let fuse = candle_nn::conv2d(6, 1, 1, Conv2dConfig::default(), vb.pp("outconv"))?;
+        Ok(Self {
+            encoders,
+            decoders,
+            sides,
+            fuse,
+        })
+    }
+
+    /// The fused matte, in `[0, 1]`, at the input resolution.
+    fn forward(&self, x: &Tensor) -> candle_core::Result<Tensor> {
+        let mut skips = Vec::with_capacity(self.encoders.len());
+        let mut hx = x.clone();
+        for (i, encoder) in self.encoders.iter().enumerate() {
+            let features = encoder.forward(&hx)?;
+            hx = if i + 1 < self.encoders.len() {
+                pool(&features)?
+            } else {
+                features.clone()
+            };
+            skips.push(features);
+        }
+        skips.pop();
+        // Deepest first: the last encoder, then each decoder in turn.
+        let mut levels = vec![hx.clone()];
+        for (decoder, skip) in self.decoders.iter().zip(skips.iter().rev()) {
+            let up = upsample_like(&hx, skip)?;
+            hx = decoder.forward(&Tensor::cat(&[&up, skip], 1)?)?;
+            levels.push(hx.clone());
+        }
+        let first = self.sides[0].forward(&hx)?;
+        let mut maps = vec![first.clone()];
+        for (side, features) in self.sides[1..].iter().zip(levels.iter().rev().skip(1)) {
+            maps.push(upsample_like(&side.forward(features)?, &first)?);
+        }
+        let maps: Vec<&Tensor> = maps.iter().collect();
+        candle_nn::ops::sigmoid(&self.fuse.forward(&Tensor::cat(&maps, 1)?)?)
+    }
+}
+
+/// 2×2 max pooling that keeps a partial last row or column, like PyTorch's `ceil_mode`.
+fn pool(x: &Tensor) -> candle_core::Result<Tensor> {
+    let (_, _, height, width) = x.dims4()?;
+    let mut x = x.clone();
+    if height % 2 == 1 {
+        x = x.pad_with_same(2, 0, 1)?;
+    }
+    if width % 2 == 1 {
+        x = x.pad_with_same(3, 0, 1)?;
+    }
+    x.max_pool2d(2)
+}
+
+/// Bilinear resize of `x` to the height and width of `target`, without aligned corners.
+fn upsample_like(x: &Tensor, target: &Tensor) -> candle_core::Result<Tensor> {
+    let (_, _, height, width) = target.dims4()?;
+    resize_axis(&resize_axis(x, 2, height)?, 3, width)
+}
+
+/// Linear resize of one axis of an NCHW tensor to `size`.
+fn resize_axis(x: &Tensor, axis: usize, size: usize) -> candle_core::Result<Tensor> {
+    let len = x.dim(axis)?;
+    if len == size {
+        return Ok(x.clone());
+    }
+    let scale = len as f32 / size as f32;
+    let mut lower = Vec::with_capacity(size);
+    let mut upper = Vec::with_capacity(size);
+    let mut weights = Vec::with_capacity(size);
+    for i in 0..size {
+        let source = ((i as f32 + 0.5) * scale - 0.5).max(0.0);
+        let below = (source as usize).min(len - 1);
+        lower.push(below as u32);
+        upper.push((below + 1).min(len - 1) as u32);
+        weights.push(source - below as f32);
+    }
+    let device = x.device();
+    let lower = x.index_select(&Tensor::new(lower.as_slice(), device)?, axis)?;
+    let upper = x.index_select(&Tensor::new(upper.as_slice(), device)?, axis)?;
+    let mut shape = vec![1; 4];
+    shape[axis] = size;
+    let weights = Tensor::from_vec(weights, shape, device)?;
+    lower.broadcast_add(&(&upper - &lower)?.broadcast_mul(&weights)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod resize_axis {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn matches_half_pixel_bilinear() {
+                let x = Tensor::new(&[0f32, 1.0], &CandleDevice::Cpu)
+                    .unwrap()
+                    .reshape((1, 1, 1, 2))
+                    .unwrap();
+                let resized = resize_axis(&x, 3, 4).unwrap();
+                let values = resized.flatten_all().unwrap().to_vec1::<f32>().unwrap();
+                assert_eq!(values, [0.0, 0.25, 0.75, 1.0]);
+            }
+        }
+    }
+
+    mod pool {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn odd_sizes_keep_the_last_row_and_column() {
+                let x = Tensor::arange(0f32, 9.0, &CandleDevice::Cpu)
+                    .unwrap()
+                    .reshape((1, 1, 3, 3))
+                    .unwrap();
+                let pooled = pool(&x).unwrap();
+                assert_eq!(pooled.dims4().unwrap(), (1, 1, 2, 2));
+                let values = pooled.flatten_all().unwrap().to_vec1::<f32>().unwrap();
+                assert_eq!(values, [4.0, 5.0, 7.0, 8.0]);
+            }
+        }
+    }
+
+    mod weights_path {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn swaps_the_onnx_extension() {
+                assert_eq!(
+                    weights_path(Path::new("models/u2net.onnx")),
+                    PathBuf::from("models/u2net.safetensors")
+                );
+            }
+        }
+    }
+}
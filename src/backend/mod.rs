@@ -0,0 +1,149 @@
+use ndarray::{Array4, ArrayD};
+
+use crate::config::{Backend, Device, InferenceSettings};
+use crate::inference::{ChannelLayout, ModelInputSpec};
+use crate::{BgrError, BgrResult};
+
+#[cfg(feature = "backend-candle")]
+mod candle;
+#[cfg(feature = "backend-ort")]
+mod ort;
+#[cfg(feature = "backend-tract")]
+mod tract;
+
+/// Runs a matting model on a preprocessed input tensor.
+///
+/// Each backend parses the model file itself; everything before the input tensor and after the
+/// output tensors is shared by [`ModelSession`](crate::inference::ModelSession) and the
+/// settings' [`ModelAdapter`](crate::ModelAdapter).
+pub(crate) trait InferenceBackend: Send {
+    /// The input size and layout the model expects.
+    fn input_spec(&self) -> ModelInputSpec;
+
+    /// The device the model runs on.
+    fn device(&self) -> Device;
+
+    /// Run the model and return its outputs, in the order the model declares them.
+    fn run(&mut self, input: Array4<f32>) -> BgrResult<Vec<ArrayD<f32>>>;
+}
+
+/// Load the model in `settings` with the backend it selects.
+pub(crate) fn load(settings: &InferenceSettings) -> BgrResult<Box<dyn InferenceBackend>> {
+    match settings.backend {
+        #[cfg(feature = "backend-ort")]
+        Backend::Ort => Ok(Box::new(self::ort::OrtBackend::load(settings)?)),
+        #[cfg(feature = "backend-tract")]
+        Backend::Tract => Ok(Box::new(tract::TractBackend::load(settings)?)),
+        #[cfg(feature = "backend-candle")]
+        Backend::Candle => Ok(Box::new(candle::CandleBackend::load(settings)?)),
+        #[allow(unreachable_patterns)]
+        backend => Err(BgrError::BackendUnavailable(backend.name())),
+    }
+}
+
+/// See [`Backend::runtime_version`].
+pub(crate) fn runtime_version(backend: Backend) -> Option<String> {
+    match backend {
+        #[cfg(feature = "backend-ort")]
+        Backend::Ort => Some(self::ort::runtime_version()),
+        _ => None,
+    }
+}
+
+/// See [`Backend::device_available`].
+pub(crate) fn device_available(backend: Backend, device: Device) -> Option<bool> {
+    if !backend.is_compiled() {
+        return None;
+    }
+    if matches!(device, Device::Cpu | Device::Auto) {
+        return Some(true);
+    }
+    match backend {
+        #[cfg(feature = "backend-ort")]
+        Backend::Ort => self::ort::provider_available(device),
+        #[cfg(feature = "backend-candle")]
+        Backend::Candle => candle::device_available(device),
+        _ => None,
+    }
+}
+
+/// The spec of an input with dimensions `dims`, where `-1` marks a dynamic dimension.
+///
+/// Both NCHW and NHWC layouts with three channels are recognized.
+pub(crate) fn spec_from_dims(dims: &[i64]) -> Option<ModelInputSpec> {
+    if dims.len() < 4 {
+        return None;
+    }
+    infer_nchw_spec(dims).or_else(|| infer_nhwc_spec(dims))
+}
+
+/// Check for an NCHW layout and returns a matching spec when dimensions line up.
+fn infer_nchw_spec(dims: &[i64]) -> Option<ModelInputSpec> {
+    let channels = *dims.get(1)?;
+    if channels != 3 && channels != -1 {
+        return None;
+    }
+    let height = *dims.get(2)?;
+    let width = *dims.get(3)?;
+    let height = positive_dim_to_usize(height)?;
+    let width = positive_dim_to_usize(width)?;
+    Some(ModelInputSpec {
+        width,
+        height,
+        layout: ChannelLayout::Nchw,
+    })
+}
+
+/// Check for an NHWC layout and returns a matching spec when dimensions line up.
+fn infer_nhwc_spec(dims: &[i64]) -> Option<ModelInputSpec> {
+    let channels = *dims.get(3)?;
+    if channels != 3 && channels != -1 {
+        return None;
+    }
+    let height = *dims.get(1)?;
+    let width = *dims.get(2)?;
+    let height = positive_dim_to_usize(height)?;
+    let width = positive_dim_to_usize(width)?;
+    Some(ModelInputSpec {
+        width,
+        height,
+        layout: ChannelLayout::Nhwc,
+    })
+}
+
+/// Convert a positive i64 dimension to usize, returning None for non-positive or overflow.
+fn positive_dim_to_usize(dim: i64) -> Option<usize> {
+    if dim > 0 {
+        usize::try_from(dim).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod spec_from_dims {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn nchw_and_nhwc_recognized() {
+                let nchw = spec_from_dims(&[1, 3, 1024, 768]).unwrap();
+                assert_eq!((nchw.width, nchw.height), (768, 1024));
+                assert_eq!(nchw.layout, ChannelLayout::Nchw);
+                let nhwc = spec_from_dims(&[1, 320, 320, 3]).unwrap();
+                assert_eq!(nhwc.layout, ChannelLayout::Nhwc);
+            }
+
+            #[test]
+            fn dynamic_sizes_and_short_shapes_rejected() {
+                assert_eq!(spec_from_dims(&[1, 3, -1, -1]), None);
+                assert_eq!(spec_from_dims(&[3, 320, 320]), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use ndarray::{Array4, ArrayD};
+use ort::execution_providers::ExecutionProviderDispatch;
+use ort::session::Session;
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::value::Tensor;
+
+use super::{InferenceBackend, spec_from_dims};
+use crate::BgrResult;
+use crate::config::{Device, InferenceSettings};
+use crate::inference::{DEFAULT_MODEL_INPUT_SPEC, ModelInputSpec};
+
+/// Inference through the ONNX Runtime shared library, with its accelerators.
+pub(crate) struct OrtBackend {
+    session: Session,
+    input_spec: ModelInputSpec,
+    device: Device,
+}
+
+impl OrtBackend {
+    /// Each candidate accelerator for [`InferenceSettings::device`] is tried in turn; if none
+    /// initializes, the session runs on the CPU.
+    pub(crate) fn load(settings: &InferenceSettings) -> BgrResult<Self> {
+        for &device in settings.device.candidates() {
+            let Some(provider) = execution_provider(device) else {
+                continue;
+            };
+            if let Ok(builder) =
+                session_builder(settings)?.with_execution_providers([provider.error_on_failure()])
+            {
+                return Self::commit(builder, settings, device);
+            }
+        }
+        Self::commit(session_builder(settings)?, settings, Device::Cpu)
+    }
+
+    fn commit(
+        builder: SessionBuilder,
+        settings: &InferenceSettings,
+        device: Device,
+    ) -> BgrResult<Self> {
+        let session = match &settings.model_bytes {
+            Some(bytes) => builder.commit_from_memory(bytes.as_ref())?,
+            None => builder.commit_from_file(&settings.model_path)?,
+        };
+        let input_spec = determine_model_input_spec(&session);
+        Ok(Self {
+            session,
+            input_spec,
+            device,
+        })
+    }
+}
+
+impl InferenceBackend for OrtBackend {
+    fn input_spec(&self) -> ModelInputSpec {
+        self.input_spec
+    }
+
+    fn device(&self) -> Device {
+        self.device
+    }
+
+    fn run(&mut self, input: Array4<f32>) -> BgrResult<Vec<ArrayD<f32>>> {
+        let outputs = self.session.run(ort::inputs![Tensor::from_array(input)?])?;
+        (0..outputs.len())
+            .map(|index| Ok(outputs[index].try_extract_array::<f32>()?.to_owned()))
+            .collect()
+    }
+}
+
+/// Try to figure out the model input spec from the session and falls back to the default.
+fn determine_model_input_spec(session: &Session) -> ModelInputSpec {
+    infer_model_input_spec(session).unwrap_or(DEFAULT_MODEL_INPUT_SPEC)
+}
+
+/// Infer the model input spec from the ONNX session input tensor shape.
+fn infer_model_input_spec(session: &Session) -> Option<ModelInputSpec> {
+    let input = session.inputs.first()?;
+    let shape = input.input_type.tensor_shape()?;
+    let dims: &[i64] = shape;
+    spec_from_dims(dims)
+}
+
+/// Session builder with the optimisation and memory options from `settings`.
+fn session_builder(settings: &InferenceSettings) -> BgrResult<SessionBuilder> {
+    let mut builder =
+        Session::builder()?.with_optimization_level(GraphOptimizationLevel::Level3)?;
+    if let Some(n) = settings.intra_threads {
+        builder = builder.with_intra_threads(n)?;
+    }
+    if settings.low_memory {
+        builder = builder.with_memory_pattern(false)?;
+    }
+    Ok(builder)
+}
+
+/// The build information of the ONNX Runtime library in use, including its version.
+pub(crate) fn runtime_version() -> String {
+    ort::info().to_string()
+}
+
+/// Whether the execution provider for `device` can be used on this machine, or `None` when
+/// its cargo feature is not enabled.
+pub(crate) fn provider_available(device: Device) -> Option<bool> {
+    #[cfg(feature = "cuda")]
+    if device == Device::Cuda {
+        return Some(available(
+            &ort::execution_providers::CUDAExecutionProvider::default(),
+        ));
+    }
+    #[cfg(feature = "coreml")]
+    if device == Device::CoreMl {
+        return Some(available(
+            &ort::execution_providers::CoreMLExecutionProvider::default(),
+        ));
+    }
+    #[cfg(feature = "directml")]
+    if device == Device::DirectMl {
+        return Some(available(
+            &ort::execution_providers::DirectMLExecutionProvider::default(),
+        ));
+    }
+    let _ = device;
+    None
+}
+
+/// Whether ONNX Runtime reports `provider` as usable; a failed check counts as unusable.
+#[cfg(any(feature = "cuda", feature = "coreml", feature = "directml"))]
+fn available(provider: &impl ort::execution_providers::ExecutionProvider) -> bool {
+    provider.is_available().unwrap_or(false)
+}
+
+/// The ONNX Runtime execution provider for `device`, if its cargo feature is enabled.
+fn execution_provider(device: Device) -> Option<ExecutionProviderDispatch> {
+    #[cfg(feature = "cuda")]
+    if device == Device::Cuda {
+        return Some(ort::execution_providers::CUDAExecutionProvider::default().build());
+    }
+    #[cfg(feature = "coreml")]
+    if device == Device::CoreMl {
+        return Some(ort::execution_providers::CoreMLExecutionProvider::default().build());
+    }
+    #[cfg(feature = "directml")]
+    if device == Device::DirectMl {
+        return Some(ort::execution_providers::DirectMLExecutionProvider::default().build());
+    }
+    let _ = device;
+    None
+}
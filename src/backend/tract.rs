@@ -0,0 +1,70 @@
+use ndarray::{Array4, ArrayD, IxDyn};
+use tract_onnx::prelude::{
+    Datum, Framework, InferenceFact, InferenceModelExt, Tensor, TypedModel, TypedSimplePlan, tvec,
+};
+
+use super::{InferenceBackend, spec_from_dims};
+use crate::BgrResult;
+use crate::config::{Device, InferenceSettings};
+use crate::inference::{DEFAULT_MODEL_INPUT_SPEC, ModelInputSpec};
+
+/// Pure-Rust inference with tract, on the CPU and without the ONNX Runtime library.
+///
+/// The model is optimized for the one input shape it declares (or the default spec when its
+/// size is dynamic), so loading takes longer than with ONNX Runtime while runs are comparable
+/// on small models.
+pub(crate) struct TractBackend {
+    plan: TypedSimplePlan<TypedModel>,
+    input_spec: ModelInputSpec,
+}
+
+impl TractBackend {
+    /// Accelerators are not supported; every device runs on the CPU.
+    pub(crate) fn load(settings: &InferenceSettings) -> BgrResult<Self> {
+        let model = match &settings.model_bytes {
+            Some(bytes) => tract_onnx::onnx().model_for_read(&mut bytes.as_ref())?,
+            None => tract_onnx::onnx().model_for_path(&settings.model_path)?,
+        };
+        let declared: Vec<i64> = model
+            .clone()
+            .into_typed()?
+            .input_fact(0)?
+            .shape
+            .iter()
+            .map(|dim| dim.to_i64().unwrap_or(-1))
+            .collect();
+        let input_spec = spec_from_dims(&declared).unwrap_or(DEFAULT_MODEL_INPUT_SPEC);
+        let fact = InferenceFact::dt_shape(f32::datum_type(), input_spec.shape());
+        let plan = model
+            .with_input_fact(0, fact)?
+            .into_optimized()?
+            .into_runnable()?;
+        Ok(Self { plan, input_spec })
+    }
+}
+
+impl InferenceBackend for TractBackend {
+    fn input_spec(&self) -> ModelInputSpec {
+        self.input_spec
+    }
+
+    fn device(&self) -> Device {
+        Device::Cpu
+    }
+
+    fn run(&mut self, input: Array4<f32>) -> BgrResult<Vec<ArrayD<f32>>> {
+        // Copy through plain slices so tract's ndarray version need not match ours.
+        let data: Vec<f32> = input.iter().copied().collect();
+        let tensor = Tensor::from_shape(input.shape(), &data)?;
+        let outputs = self.plan.run(tvec!(tensor.into()))?;
+        outputs
+            .iter()
+            .map(|output| {
+                Ok(ArrayD::from_shape_vec(
+                    IxDyn(output.shape()),
+                    output.as_slice::<f32>()?.to_vec(),
+                )?)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,174 @@
+//! The `bgrm` container: one matte together with where it came from, for piping mattes
+//! between bgr invocations.
+//!
+//! A PNG carries only the pixels; a `bgrm` stream also records the source image, its size,
+//! the model, and the settings that shaped the matte, so a later step in a shell pipeline
+//! knows what it is working on. Samples are stored exactly as bgr holds them, 8 bits per
+//! pixel with no resampling or color conversion.
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0 | 4 | Magic `BGRM` |
+//! | 4 | 1 | Version, `1` |
+//! | 5 | 4 | Width, little-endian `u32` |
+//! | 9 | 4 | Height, little-endian `u32` |
+//! | 13 | 4 | Metadata length `n`, little-endian `u32` |
+//! | 17 | `n` | [`MatteProvenance`] as UTF-8 JSON |
+//! | 17 + `n` | width × height | Matte samples, row by row |
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use crate::limits::InputLimits;
+use crate::{BgrError, BgrResult};
+
+/// The first bytes of every `bgrm` stream.
+pub const BGRM_MAGIC: [u8; 4] = *b"BGRM";
+/// The container version this build writes and reads.
+const VERSION: u8 = 1;
+/// Metadata is a few hundred bytes; anything near this is not a `bgrm` stream.
+const MAX_METADATA_BYTES: u32 = 1 << 20;
+
+/// Where a matte came from and what was done to it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatteProvenance {
+    /// The image the matte was inferred from, as given on the command line.
+    pub source: Option<String>,
+    /// Width and height of the source image.
+    pub source_size: (u32, u32),
+    /// The model preset or file, when a model produced the matte.
+    pub model: Option<String>,
+    /// Settings that shaped the matte, such as `mask-source` or `refine`, by name.
+    pub settings: BTreeMap<String, String>,
+}
+
+/// A matte and its [`MatteProvenance`], read from or written to a `bgrm` stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatteContainer {
+    pub matte: GrayImage,
+    pub provenance: MatteProvenance,
+}
+
+impl MatteContainer {
+    /// Write the container to `writer`.
+    pub fn write_to(&self, mut writer: impl Write) -> BgrResult<()> {
+        let metadata = serde_json::to_vec(&self.provenance).map_err(io::Error::from)?;
+        let (width, height) = self.matte.dimensions();
+        let mut header = Vec::with_capacity(17);
+        header.extend_from_slice(&BGRM_MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&metadata)?;
+        writer.write_all(self.matte.as_raw())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The container as bytes.
+    pub fn encode(&self) -> BgrResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Read a container from `reader`, rejecting dimensions beyond `limits` before the matte
+    /// is allocated.
+    pub fn read_from(mut reader: impl Read, limits: &InputLimits) -> BgrResult<Self> {
+        let mut header = [0u8; 17];
+        reader.read_exact(&mut header)?;
+        if header[..4] != BGRM_MAGIC {
+            return Err(BgrError::invalid_data("not a bgrm stream"));
+        }
+        if header[4] != VERSION {
+            return Err(BgrError::invalid_data(format!(
+                "unsupported bgrm version {}",
+                header[4]
+            )));
+        }
+        let field =
+            |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("four bytes"));
+        let (width, height, metadata_len) = (field(5), field(9), field(13));
+        limits.check_dimensions(width, height)?;
+        if metadata_len > MAX_METADATA_BYTES {
+            return Err(BgrError::invalid_data("bgrm metadata is too large"));
+        }
+
+        let mut metadata = vec![0; metadata_len as usize];
+        reader.read_exact(&mut metadata)?;
+        let provenance = serde_json::from_slice(&metadata).map_err(io::Error::from)?;
+        let mut samples = vec![0; width as usize * height as usize];
+        reader.read_exact(&mut samples)?;
+        let matte = GrayImage::from_raw(width, height, samples).expect("buffer matches size");
+        Ok(Self { matte, provenance })
+    }
+
+    /// Read a container held in memory.
+    pub fn decode(bytes: &[u8], limits: &InputLimits) -> BgrResult<Self> {
+        Self::read_from(bytes, limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn container() -> MatteContainer {
+        let mut settings = BTreeMap::new();
+        settings.insert("mask-source".to_string(), "raw".to_string());
+        MatteContainer {
+            matte: GrayImage::from_fn(5, 3, |x, y| Luma([(x * 50 + y) as u8])),
+            provenance: MatteProvenance {
+                source: Some("photo.jpg".into()),
+                source_size: (5, 3),
+                model: Some("isnet".into()),
+                settings,
+            },
+        }
+    }
+
+    mod matte_container {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn round_trips_matte_and_provenance() {
+                let encoded = container().encode().unwrap();
+                assert_eq!(encoded[..4], BGRM_MAGIC);
+                let decoded = MatteContainer::decode(&encoded, &InputLimits::default()).unwrap();
+                assert_eq!(decoded, container());
+            }
+
+            #[test]
+            fn rejects_other_streams() {
+                let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01";
+                assert!(MatteContainer::decode(png, &InputLimits::default()).is_err());
+            }
+
+            #[test]
+            fn checks_limits_before_reading_samples() {
+                let encoded = container().encode().unwrap();
+                let limits = InputLimits {
+                    max_dimension: Some(4),
+                    ..InputLimits::default()
+                };
+                assert!(MatteContainer::decode(&encoded, &limits).is_err());
+            }
+
+            #[test]
+            fn truncated_stream_is_an_error() {
+                let encoded = container().encode().unwrap();
+                let truncated = &encoded[..encoded.len() - 1];
+                assert!(MatteContainer::decode(truncated, &InputLimits::default()).is_err());
+            }
+        }
+    }
+}
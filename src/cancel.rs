@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{BgrError, BgrResult};
+
+/// A shared flag that asks long operations to stop early with [`BgrError::Cancelled`].
+///
+/// Clones share the flag, so one clone can be handed to a Ctrl-C handler or a UI button while
+/// another travels with the work. Inference checks it between pipeline stages through
+/// [`InferenceSettings::cancellation`](crate::InferenceSettings::cancellation), and model
+/// downloads between chunks; an operation that is already running its model finishes that step
+/// first.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Bgr, BgrError, CancellationToken};
+///
+/// let token = CancellationToken::new();
+/// let bgr = Bgr::new("model.onnx").with_cancellation(token.clone());
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(5));
+///     token.cancel();
+/// });
+/// match bgr.for_image("huge.jpg") {
+///     Err(BgrError::Cancelled) => eprintln!("gave up"),
+///     other => other.map(drop)?,
+/// }
+/// # Ok::<_, BgrError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Return [`BgrError::Cancelled`] once the token is cancelled.
+    pub fn check(&self) -> BgrResult<()> {
+        if self.is_cancelled() {
+            Err(BgrError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod cancellation_token {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn clones_share_the_flag() {
+                let token = CancellationToken::new();
+                let handle = token.clone();
+                assert!(token.check().is_ok());
+                handle.cancel();
+                assert!(token.is_cancelled());
+                assert!(matches!(token.check(), Err(BgrError::Cancelled)));
+            }
+
+            #[test]
+            fn separate_tokens_are_independent() {
+                let cancelled = CancellationToken::new();
+                cancelled.cancel();
+                assert!(!CancellationToken::new().is_cancelled());
+            }
+
+            #[test]
+            fn cancelled_downloads_count_as_cancelled() {
+                let download = BgrError::Model(crate::models::ModelError::Cancelled);
+                assert!(download.is_cancelled());
+                assert!(BgrError::Cancelled.is_cancelled());
+                assert!(
+                    !BgrError::Batch {
+                        failed: 1,
+                        total: 2
+                    }
+                    .is_cancelled()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+
+use crate::trim::{TrimMargin, trim_to_subject};
+
+/// Where the subject sits on a canvas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Anchor {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Horizontal and vertical position as fractions of the free space: 0 at the left or top,
+    /// 1 at the right or bottom.
+    fn position(self) -> (f64, f64) {
+        match self {
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// A fixed-size canvas to place a cutout's subject on, as marketplaces require for product
+/// photos.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanvasOptions {
+    pub width: u32,
+    pub height: u32,
+    pub anchor: Anchor,
+    /// Share of the canvas width or height the subject spans, whichever is reached first,
+    /// from 0 to 1.
+    pub fill: f32,
+}
+
+impl CanvasOptions {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            anchor: Anchor::Center,
+            fill: 0.85,
+        }
+    }
+}
+
+/// Trim `cutout` to its subject, scale the subject to the canvas fill, and place it at the
+/// anchor on a transparent canvas.
+///
+/// The subject keeps its aspect ratio. Anchored edges keep the same gap as the fill leaves on
+/// a centered subject, so a bottom-anchored product stands the same distance above the edge
+/// as a centered one would. A fully transparent cutout gives an empty canvas.
+pub fn place_on_canvas(cutout: &RgbaImage, options: &CanvasOptions) -> RgbaImage {
+    let (width, height) = (options.width.max(1), options.height.max(1));
+    let mut canvas = RgbaImage::new(width, height);
+    let subject = trim_to_subject(cutout, TrimMargin::default());
+    if subject.pixels().all(|pixel| pixel[3] == 0) {
+        return canvas;
+    }
+
+    let fill = f64::from(options.fill.clamp(0.0, 1.0));
+    let (sw, sh) = subject.dimensions();
+    let scale =
+        (f64::from(width) * fill / f64::from(sw)).min(f64::from(height) * fill / f64::from(sh));
+    let scaled_width = ((f64::from(sw) * scale).round() as u32).clamp(1, width);
+    let scaled_height = ((f64::from(sh) * scale).round() as u32).clamp(1, height);
+    let scaled = imageops::resize(&subject, scaled_width, scaled_height, FilterType::Lanczos3);
+
+    // Gaps left by the fill on each side of a centered subject.
+    let gap_x = f64::from(width) * (1.0 - fill) / 2.0;
+    let gap_y = f64::from(height) * (1.0 - fill) / 2.0;
+    let (ax, ay) = options.anchor.position();
+    let place = |canvas: u32, scaled: u32, gap: f64, anchor: f64| {
+        let free = f64::from(canvas - scaled);
+        let gap = gap.min(free / 2.0);
+        (gap + (free - 2.0 * gap) * anchor).round() as i64
+    };
+    let x = place(width, scaled_width, gap_x, ax);
+    let y = place(height, scaled_height, gap_y, ay);
+    imageops::overlay(&mut canvas, &scaled, x, y);
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A 200×100 cutout with an opaque 40×20 subject at (10, 30).
+    fn cutout() -> RgbaImage {
+        RgbaImage::from_fn(200, 100, |x, y| {
+            let inside = (10..50).contains(&x) && (30..50).contains(&y);
+            Rgba([0, 120, 255, if inside { 255 } else { 0 }])
+        })
+    }
+
+    /// Inclusive bounds of the opaque pixels.
+    fn opaque_bounds(image: &RgbaImage) -> (u32, u32, u32, u32) {
+        let opaque: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] > 127)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let xs = opaque.iter().map(|&(x, _)| x);
+        let ys = opaque.iter().map(|&(_, y)| y);
+        (
+            xs.clone().min().unwrap(),
+            ys.clone().min().unwrap(),
+            xs.max().unwrap(),
+            ys.max().unwrap(),
+        )
+    }
+
+    mod place_on_canvas {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn centers_the_subject_at_the_fill() {
+                let options = CanvasOptions {
+                    fill: 0.5,
+                    ..CanvasOptions::new(100, 100)
+                };
+                let canvas = place_on_canvas(&cutout(), &options);
+                assert_eq!(canvas.dimensions(), (100, 100));
+                // 40×20 scaled to span half the width: 50×25, centered.
+                assert_eq!(opaque_bounds(&canvas), (25, 38, 74, 62));
+            }
+
+            #[test]
+            fn bottom_anchor_keeps_the_fill_gap() {
+                let options = CanvasOptions {
+                    anchor: Anchor::Bottom,
+                    fill: 0.5,
+                    ..CanvasOptions::new(100, 100)
+                };
+                let canvas = place_on_canvas(&cutout(), &options);
+                let (_, _, _, bottom) = opaque_bounds(&canvas);
+                assert_eq!(bottom, 74);
+            }
+
+            #[test]
+            fn top_left_anchor() {
+                let options = CanvasOptions {
+                    anchor: Anchor::TopLeft,
+                    fill: 1.0,
+                    ..CanvasOptions::new(80, 80)
+                };
+                let canvas = place_on_canvas(&cutout(), &options);
+                let (left, top, _, _) = opaque_bounds(&canvas);
+                assert_eq!((left, top), (0, 0));
+            }
+
+            #[test]
+            fn empty_cutout_gives_an_empty_canvas() {
+                let canvas = place_on_canvas(&RgbaImage::new(10, 10), &CanvasOptions::new(20, 30));
+                assert_eq!(canvas.dimensions(), (20, 30));
+                assert!(canvas.pixels().all(|pixel| pixel[3] == 0));
+            }
+        }
+    }
+}
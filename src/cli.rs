@@ -1,11 +1,36 @@
-use std::path::PathBuf;
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+#[cfg(any(unix, feature = "server"))]
+use std::time::Duration;
 
-use bgr::{MaskProcessingOptions, TraceOptions};
-use clap::{Args, Parser, Subcommand, ValueEnum};
+#[cfg(any(unix, feature = "server"))]
+use bgr::MatteCache;
+#[cfg(feature = "vectorizer-vtracer")]
+use bgr::TraceOptions;
+use bgr::models::{Priority, UseCase};
+use bgr::{
+    Affine, AlphaDither, Anchor, Architecture, Backend, BgrResult, CenterlineOptions,
+    CollisionOptions, ColorProfile, ColorSettings, ComponentArea, ContourOptions,
+    CoordinateOptions, CoordinateSpace, Device, InputLimits, MaskBinarize, MaskLoadOptions,
+    MaskProcessingOptions, MaskResize, MatteDepth, OutputFormat, PhysicalUnit, QualityMetric,
+    QualityTarget, RenderingIntent, SelectionFormat, SequenceOptions, StorageFormat, TrimMargin,
+    Verbosity,
+};
+use clap::builder::{ArgPredicate, PossibleValuesParser};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use image::Rgb;
 use image::imageops::FilterType;
+use tracing::level_filters::LevelFilter;
+#[cfg(feature = "vectorizer-vtracer")]
 use visioncortex::PathSimplifyMode;
+#[cfg(feature = "vectorizer-vtracer")]
 use vtracer::{ColorMode, Hierarchical};
 
+use crate::output_template::OutputTemplate;
+
 /// Command line interface definition.
 #[derive(Parser, Debug)]
 #[command(
@@ -24,7 +49,8 @@ pub struct Cli {
 
 #[derive(Args, Debug)]
 pub struct GlobalOptions {
-    /// Model name or path. Use preset names (birefnet, isnet, u2net, rmbg) or a path to an ONNX file.
+    /// Model name or path. Use preset names (birefnet, isnet, u2net, rmbg), `auto` for the best
+    /// downloaded general-purpose preset, or a path to an ONNX file.
     #[arg(
         short = 'm',
         long,
@@ -42,16 +68,416 @@ pub struct GlobalOptions {
     /// Filter used when resizing the matte back to the original resolution
     #[arg(long = "output-resample-filter", value_enum, default_value_t = ResampleFilter::Lanczos3, global = true)]
     pub output_resample_filter: ResampleFilter,
+    /// Reduce peak memory use at some speed cost (8-bit matte resizing, eager buffer release)
+    #[arg(long = "low-memory", global = true)]
+    pub low_memory: bool,
+    /// Rendering intent for converting between color profiles, on decode and encode
+    #[arg(long, value_enum, global = true, default_value_t = IntentArg::Relative)]
+    pub intent: IntentArg,
+    /// Color space for color outputs: `srgb`, `display-p3`, or the path of an ICC profile;
+    /// outputs other than sRGB embed the profile
+    #[arg(
+        long = "target-profile",
+        value_name = "PROFILE",
+        value_parser = parse_target_profile,
+        default_value = "srgb",
+        global = true
+    )]
+    pub target_profile: TargetProfile,
+    /// Inference device; falls back to CPU when the provider is unavailable
+    #[arg(long, value_enum, global = true, default_value_t = DeviceArg::Cpu)]
+    pub device: DeviceArg,
+    /// Architecture of a custom model file, which decides how its input is normalized and
+    /// its outputs become a matte; presets know their own
+    #[arg(long = "model-arch", value_enum, value_name = "ARCH", global = true)]
+    pub model_arch: Option<ArchitectureArg>,
+    /// Fuse U2-Net and IS-Net side outputs, weighted, into the matte instead of using the
+    /// final head alone, for better recall of thin structures
+    #[arg(long = "fuse-side-outputs", global = true)]
+    pub fuse_side_outputs: bool,
+    /// Inference backend (defaults to ort when it is compiled in); tract needs no ONNX Runtime
+    /// library but runs on the CPU only; candle runs U2-Net safetensors on CUDA or Metal
+    #[arg(long, value_enum, global = true)]
+    pub backend: Option<BackendArg>,
+    /// Number of images processed in parallel, each with its own model session (0 = all cores)
+    #[arg(short = 'j', long, global = true, default_value_t = 1)]
+    pub jobs: usize,
+    /// Use at most this share of the CPU, such as `50%`: fewer workers and inference threads,
+    /// plus pauses between images when even one thread is over the budget
+    #[arg(
+        long = "max-cpu",
+        value_name = "PERCENT",
+        value_parser = parse_cpu_percent,
+        global = true
+    )]
+    pub max_cpu: Option<u8>,
+    /// Go easy on the machine during long batches; shorthand for `--max-cpu 50%`
+    #[arg(long, global = true, conflicts_with = "max_cpu")]
+    pub nice: bool,
+    /// Directory for derived output files (defaults to next to each input)
+    #[arg(long = "out-dir", value_name = "DIR", global = true)]
+    pub out_dir: Option<PathBuf>,
+    /// The cache holding copies of URL inputs, whose outputs go to the current directory rather
+    /// than next to the copy.
+    #[arg(skip)]
+    pub url_cache: Option<PathBuf>,
+    /// Name derived outputs from a template instead of `<stem>-<suffix>.<ext>`, as in
+    /// `{stem}_{model}_nobg.{ext}`. Placeholders: stem, model, date (UTC, YYYY-MM-DD), size
+    /// (WIDTHxHEIGHT), hash (first 8 hex digits of the input's SHA-256), and ext. Relative
+    /// names go in `--out-dir` or next to each input; `-o` takes precedence
+    #[arg(
+        long = "output-template",
+        value_name = "TEMPLATE",
+        value_parser = OutputTemplate::parse,
+        global = true
+    )]
+    pub output_template: Option<OutputTemplate>,
+    /// Replace outputs that already exist; by default an existing output fails its input
+    #[arg(long, global = true, conflicts_with_all = ["skip_existing", "suffix"])]
+    pub overwrite: bool,
+    /// Leave inputs whose output already exists alone, before running the model, so an
+    /// interrupted batch can be rerun
+    #[arg(long = "skip-existing", global = true, conflicts_with = "suffix")]
+    pub skip_existing: bool,
+    /// Write beside an output that already exists as `<name>-1.<ext>`, `<name>-2.<ext>`, ...
+    #[arg(long, global = true)]
+    pub suffix: bool,
+    /// The `--resume` settings of the command, copied here for the batch runner.
+    #[arg(skip)]
+    pub checkpoint: ResumeArgs,
+    /// Keep a batch's sessions loaded when it ends, for a later step of a `run` job that loads
+    /// the same model on the same device.
+    #[arg(skip)]
+    pub keep_sessions: bool,
+    /// Directory where model presets are stored (defaults to the platform data directory)
+    #[arg(
+        long = "models-dir",
+        value_name = "DIR",
+        global = true,
+        env = "BGR_MODELS_DIR"
+    )]
+    pub models_dir: Option<PathBuf>,
+    /// Never download models; fail if a preset is missing locally
+    #[arg(long, global = true)]
+    pub offline: bool,
+    /// Give up on a URL input that has not downloaded within this many seconds; 0 waits
+    /// indefinitely
+    #[arg(
+        long = "fetch-timeout",
+        value_name = "SECONDS",
+        global = true,
+        default_value_t = 60
+    )]
+    pub fetch_timeout: u64,
+    /// Accept the license of a restrictively licensed preset (such as rmbg) before download,
+    /// without asking; the acceptance is recorded in the data directory
+    #[arg(long = "accept-license", global = true)]
+    pub accept_license: bool,
+    /// Only print warnings and errors
+    #[arg(short = 'q', long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print additional details; `-vv` adds pipeline stage timings, `-vvv` everything
+    #[arg(short = 'v', long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// How diagnostic logs are written to stderr; `json` prints one event per line for log
+    /// aggregation
+    #[arg(long = "log-format", value_enum, global = true, default_value_t = LogFormatArg::Text)]
+    pub log_format: LogFormatArg,
+    /// How model download and batch progress is shown; `bar` is hidden by --quiet, `json`
+    /// prints one event per line on stderr, down to each input's stages
+    #[arg(long, value_enum, global = true, default_value_t = ProgressArg::Bar)]
+    pub progress: ProgressArg,
+    /// Language for messages, such as `es` (defaults to the system locale)
+    #[arg(long, value_name = "LOCALE", global = true, env = "BGR_LANG")]
+    pub lang: Option<String>,
+    /// Fetch mattes from a running `bgr daemon` instead of loading the model (mask, cut, trace,
+    /// watch);
+    /// the daemon's model and inference settings apply
+    #[cfg(unix)]
+    #[arg(
+        long = "via-daemon",
+        value_name = "SOCKET",
+        num_args = 0..=1,
+        require_equals = true,
+        global = true
+    )]
+    pub via_daemon: Option<Option<PathBuf>>,
+    /// Append one line of usage accounting per run to PATH: counts, pixels, model, device,
+    /// and durations, never image data or file names (defaults to `usage.jsonl` in the data
+    /// directory)
+    #[arg(
+        long = "usage-log",
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        global = true,
+        env = "BGR_USAGE_LOG"
+    )]
+    pub usage_log: Option<Option<PathBuf>>,
+    /// Label recorded with each usage line, such as a team or project, for chargeback
+    #[arg(
+        long = "usage-tag",
+        value_name = "TAG",
+        global = true,
+        env = "BGR_USAGE_TAG"
+    )]
+    pub usage_tag: Option<String>,
+    /// Write each input's warnings and errors to FILE as JSON (mask, cut, trace)
+    #[arg(long, value_name = "FILE", global = true)]
+    pub report: Option<PathBuf>,
+    /// Run CMD through the shell before a batch starts (mask, cut, trace), with the inputs as
+    /// JSON on stdin; the batch does not start unless CMD succeeds
+    #[arg(long, value_name = "CMD", global = true)]
+    pub before: Option<String>,
+    /// Run CMD through the shell after a batch ends (mask, cut, trace), with its summary and
+    /// every input's outcome as JSON on stdin
+    #[arg(long, value_name = "CMD", global = true)]
+    pub after: Option<String>,
+    /// Save a debug bundle to DIR for every input that fails (mask, cut, trace): the error,
+    /// settings, a downscaled input, and statistics of the model's input tensor, to attach to
+    /// bug reports
+    #[arg(long = "debug-bundle", value_name = "DIR", global = true)]
+    pub debug_bundle: Option<PathBuf>,
+    /// Apply the `[profile.NAME]` defaults of the nearest `.bgr.toml` over its top-level ones
+    #[arg(
+        long = "project-profile",
+        value_name = "NAME",
+        global = true,
+        env = "BGR_PROFILE"
+    )]
+    pub project_profile: Option<String>,
+    /// Order a batch is processed in (mask, cut, trace); `size` groups inputs by aspect ratio,
+    /// then resolution, so each worker's consecutive images need buffers of similar size
+    #[arg(long, value_enum, global = true, default_value_t = BatchOrderArg::Input)]
+    pub order: BatchOrderArg,
+    /// Process inputs as their pixels are stored, ignoring their EXIF orientation
+    #[arg(long = "no-auto-orient", global = true)]
+    pub no_auto_orient: bool,
+    /// Decode each input in a separate worker process, for untrusted inputs
+    #[cfg(feature = "sandboxed-decode")]
+    #[arg(long = "sandbox-decode", global = true)]
+    pub sandbox_decode: bool,
+    #[command(flatten)]
+    pub limits: InputLimitArgs,
+}
+
+/// Bounds checked before decoding each input (0 disables a limit).
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Input limits")]
+pub struct InputLimitArgs {
+    /// Maximum input width or height in pixels
+    #[arg(
+        long = "max-dimension",
+        value_name = "PX",
+        global = true,
+        default_value_t = 32_768
+    )]
+    pub max_dimension: u32,
+    /// Maximum input size in megapixels
+    #[arg(
+        long = "max-megapixels",
+        value_name = "MP",
+        global = true,
+        default_value_t = 150
+    )]
+    pub max_megapixels: u64,
+    /// Maximum input file size in MiB
+    #[arg(
+        long = "max-file-size",
+        value_name = "MIB",
+        global = true,
+        default_value_t = 256
+    )]
+    pub max_file_size: u64,
+    /// Maximum memory for a decoded input in MiB
+    #[arg(
+        long = "max-decode-memory",
+        value_name = "MIB",
+        global = true,
+        default_value_t = 2048
+    )]
+    pub max_decode_memory: u64,
+}
+
+impl From<&InputLimitArgs> for InputLimits {
+    fn from(args: &InputLimitArgs) -> Self {
+        let limit = |value: u64, unit: u64| (value > 0).then(|| value.saturating_mul(unit));
+        Self {
+            max_dimension: (args.max_dimension > 0).then_some(args.max_dimension),
+            max_pixels: limit(args.max_megapixels, 1_000_000),
+            max_file_bytes: limit(args.max_file_size, 1 << 20),
+            max_decoded_bytes: limit(args.max_decode_memory, 1 << 20),
+        }
+    }
+}
+
+impl GlobalOptions {
+    /// The color output settings from `--target-profile` and `--intent`, reading an ICC file.
+    pub fn color_settings(&self) -> BgrResult<ColorSettings> {
+        let target = match &self.target_profile {
+            TargetProfile::Srgb => ColorProfile::Srgb,
+            TargetProfile::DisplayP3 => ColorProfile::DisplayP3,
+            TargetProfile::File(path) => ColorProfile::from_path(path)?,
+        };
+        Ok(ColorSettings {
+            target,
+            intent: self.intent.into(),
+        })
+    }
+
+    /// The directory derived outputs of `input` are moved into: `--out-dir`, or the current
+    /// directory for a copy of a URL input, and next to the input otherwise.
+    pub fn out_dir_for(&self, input: &Path) -> Option<&Path> {
+        self.out_dir.as_deref().or_else(|| {
+            self.url_cache
+                .as_deref()
+                .filter(|cache| input.starts_with(cache))
+                .map(|_| Path::new("."))
+        })
+    }
+
+    /// The CPU share from `--max-cpu` or `--nice`, in percent.
+    pub fn cpu_percent(&self) -> Option<u8> {
+        self.max_cpu.or(self.nice.then_some(NICE_CPU_PERCENT))
+    }
+
+    /// Resolve the progress display; `--quiet` hides the bars, but not JSON events.
+    pub fn progress_format(&self) -> ProgressArg {
+        match self.progress {
+            ProgressArg::Bar if self.quiet => ProgressArg::None,
+            format => format,
+        }
+    }
+
+    /// Resolve the verbosity from the `--quiet` and `--verbose` flags.
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose > 0 {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    /// The most detailed diagnostic logs `--quiet` and `--verbose` let through: warnings by
+    /// default, then information, stage timings, and traces with each `-v`.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::ERROR,
+            (false, 0) => LevelFilter::WARN,
+            (false, 1) => LevelFilter::INFO,
+            (false, 2) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Export only the matte/mask as a PNG
+    #[command(after_help = crate::examples::epilog("mask"))]
     Mask(MaskCommand),
     /// Remove the background and export the foreground PNG
+    #[command(after_help = crate::examples::epilog("cut"))]
     Cut(CutCommand),
     /// Trace the subject into an SVG outline
+    #[command(after_help = crate::examples::epilog("trace"))]
     Trace(TraceCommand),
+    /// Compare generated outputs against golden perceptual fingerprints
+    #[command(after_help = crate::examples::epilog("verify"))]
+    Verify(VerifyCommand),
+    /// List, download, and remove model presets
+    #[command(after_help = crate::examples::epilog("models"))]
+    Models(ModelsCommand),
+    /// Check a custom model export against bgr's pre- and post-processing
+    #[cfg(feature = "test-utils")]
+    #[command(after_help = crate::examples::epilog("fixtures"))]
+    Fixtures(FixturesCommand),
+    /// Keep the model loaded and serve `--via-daemon` clients over a unix socket
+    #[cfg(unix)]
+    #[command(after_help = crate::examples::epilog("daemon"))]
+    Daemon(DaemonCommand),
+    /// Serve an HTTP API that removes backgrounds with warm model sessions
+    #[cfg(feature = "server")]
+    #[command(after_help = crate::examples::epilog("serve"))]
+    Serve(ServeCommand),
+    /// Watch a directory and cut out new images as they appear
+    #[command(after_help = crate::examples::epilog("watch"))]
+    Watch(WatchCommand),
+    /// Remove the background from every frame of a video
+    #[cfg(feature = "video")]
+    #[command(after_help = crate::examples::epilog("video"))]
+    Video(VideoCommand),
+    /// Print copy-pasteable recipes for common workflows
+    Examples(ExamplesCommand),
+    /// Total the runs recorded with --usage-log
+    #[command(after_help = crate::examples::epilog("usage"))]
+    Usage(UsageCommand),
+    /// Show and change the persistent defaults in `config.toml`
+    #[command(after_help = crate::examples::epilog("config"))]
+    Config(ConfigCommand),
+    /// Check the backend, accelerators, and models, and run a quick test cutout
+    #[command(after_help = crate::examples::epilog("doctor"))]
+    Doctor,
+    /// Run the steps of a job file over its inputs
+    #[command(after_help = crate::examples::epilog("run"))]
+    Run(RunCommand),
+    /// Print the completion script for a shell
+    #[command(after_help = crate::examples::epilog("completions"))]
+    Completions(CompletionsCommand),
+    /// Print the man page, or write one per subcommand into a directory
+    #[command(after_help = crate::examples::epilog("manpage"))]
+    Manpage(ManpageCommand),
+    /// Decode one image from stdin to raw pixels on stdout (used by `--sandbox-decode`)
+    #[cfg(feature = "sandboxed-decode")]
+    #[command(name = bgr::DECODE_WORKER_COMMAND, hide = true)]
+    DecodeWorker,
+}
+
+impl Commands {
+    /// Whether this is a `cut --draft` run, which uses the lite preset for `--model auto`.
+    pub fn draft(&self) -> bool {
+        matches!(self, Commands::Cut(cmd) if cmd.draft)
+    }
+
+    /// The `--resume` settings of the commands that run batches through the journal.
+    pub fn checkpoint(&self) -> ResumeArgs {
+        match self {
+            Commands::Mask(cmd) => cmd.checkpoint,
+            Commands::Cut(cmd) => cmd.checkpoint,
+            Commands::Trace(cmd) => cmd.checkpoint,
+            _ => ResumeArgs::default(),
+        }
+    }
+
+    /// Whether the command runs inference and therefore needs a resolved model.
+    pub fn needs_model(&self) -> bool {
+        #[cfg(feature = "sandboxed-decode")]
+        if matches!(self, Commands::DecodeWorker) {
+            return false;
+        }
+        #[cfg(feature = "test-utils")]
+        if matches!(self, Commands::Fixtures(_)) {
+            return false;
+        }
+        match self {
+            Commands::Verify(_)
+            | Commands::Models(_)
+            | Commands::Examples(_)
+            | Commands::Usage(_)
+            | Commands::Config(_)
+            | Commands::Run(_)
+            | Commands::Doctor
+            | Commands::Completions(_)
+            | Commands::Manpage(_) => false,
+            // Supplied masks stand in for the model's mattes.
+            Commands::Mask(cmd) => !cmd.from_mask && cmd.from.is_none(),
+            Commands::Cut(cmd) => cmd.mask.is_none(),
+            Commands::Trace(cmd) => !cmd.from_mask && cmd.from.is_none(),
+            _ => true,
+        }
+    }
 }
 
 /// Resampling filters for image resizing.
@@ -79,133 +505,1195 @@ impl From<ResampleFilter> for FilterType {
 
 #[derive(Args, Debug)]
 pub struct MaskCommand {
-    /// Input image path
-    pub input: PathBuf,
-    /// Output path (defaults to `<name>-matte.png` or `<name>-mask.png`)
+    /// Input image paths, directories, or http(s) URLs
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Output path (defaults to `<name>-matte.png`, `<name>-matte.exr` with `--depth float`,
+    /// or `<name>-mask.png`)
     #[arg(short, long)]
     pub output: Option<PathBuf>,
     /// Select which mask to export
     #[arg(long = "mask-source", value_enum, default_value_t = MaskExportSource::Auto)]
     pub mask_source: MaskExportSource,
+    /// Treat the inputs as existing masks to clean up instead of running the model
+    #[arg(long = "from-mask", conflicts_with = "refine")]
+    pub from_mask: bool,
+    /// Read the inputs as mattes piped from another bgr command instead of running the
+    /// model; `-` reads stdin
+    #[arg(
+        long = "from",
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with_all = ["from_mask", "refine"]
+    )]
+    pub from: Option<MatteStreamArg>,
+    #[command(flatten)]
+    pub mask_input: MaskInputArgs,
+    /// Refine the model's matte before any mask processing
+    #[arg(long = "refine", value_enum)]
+    pub refine: Option<RefineArg>,
+    /// Save as a selection for image editors instead of a PNG (defaults to
+    /// `<name>-selection.psd` or `<name>-selection.bmp`)
+    #[arg(long = "selection", value_enum, value_name = "FORMAT")]
+    pub selection: Option<SelectionArg>,
+    /// Output format; `bgrm` keeps the matte's source, model, and settings for piping into
+    /// another bgr command, and `-o -` writes it to stdout
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = MaskFormatArg::Png,
+        conflicts_with = "selection"
+    )]
+    pub format: MaskFormatArg,
+    /// Sample precision of the raw matte: `16` writes a 16-bit PNG and `float` a 32-bit
+    /// float EXR of the model's probabilities before any thresholding (defaults to 8)
+    #[arg(
+        long = "depth",
+        value_enum,
+        value_name = "BITS",
+        conflicts_with_all = ["from_mask", "from", "refine", "selection"]
+    )]
+    pub depth: Option<MatteDepthArg>,
+    /// Store the raw matte in this format; `auto` encodes the first `--storage-sample`
+    /// inputs every way, prints their sizes and errors, and writes the batch in the smallest
+    /// format within `--max-error`
+    #[arg(
+        long = "storage",
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with_all = ["from_mask", "from", "refine", "selection", "format", "depth"]
+    )]
+    pub storage: Option<MatteStorageArg>,
+    /// How many inputs `--storage auto` benchmarks before choosing
+    #[arg(
+        long = "storage-sample",
+        value_name = "N",
+        default_value_t = 16,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "storage"
+    )]
+    pub storage_sample: u32,
+    /// Largest difference from the full-precision matte, in 8-bit levels, that `--storage
+    /// auto` accepts
+    #[arg(
+        long = "max-error",
+        value_name = "LEVELS",
+        default_value_t = 1.0,
+        requires = "storage"
+    )]
+    pub max_error: f32,
+    /// Write subject bounds, area, coverage, centroid, model, and timings next to each
+    /// output as `<output>.json`
+    #[arg(long = "json-sidecar")]
+    pub json_sidecar: bool,
+    #[command(flatten)]
+    pub extent: ExtentPolicyArgs,
+    #[command(flatten)]
+    pub checkpoint: ResumeArgs,
     #[command(flatten)]
     pub mask_processing: MaskProcessingArgs,
 }
 
 #[derive(Args, Debug)]
 pub struct CutCommand {
-    /// Input image path
-    pub input: PathBuf,
-    /// Foreground PNG output path (defaults to `<name>-foreground.png`)
+    /// Input image paths, directories, or http(s) URLs
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Foreground output path (defaults to `<name>-foreground.png`, or the `--format`
+    /// extension)
     #[arg(short, long)]
     pub output: Option<PathBuf>,
+    /// Encode the output as this format, keeping transparency (defaults to the `--output`
+    /// extension, else PNG)
+    #[arg(long = "format", value_enum, conflicts_with_all = ["palette", "draft"])]
+    pub format: Option<OutputFormatArg>,
+    /// Lossy quality from 1 to 100 for WebP, AVIF, and JPEG output (AVIF defaults to 80,
+    /// JPEG to 90)
+    #[arg(
+        long = "quality",
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        conflicts_with_all = ["lossless", "palette"]
+    )]
+    pub quality: Option<u8>,
+    /// Encode losslessly; the default for PNG, WebP, and TIFF, not available for AVIF or JPEG
+    #[arg(long = "lossless", conflicts_with = "palette")]
+    pub lossless: bool,
+    /// Write outputs without the input's EXIF data, ICC profile, and density
+    #[arg(long = "strip-metadata")]
+    pub strip_metadata: bool,
     /// Save the raw matte alongside the foreground PNG
     #[arg(long = "export-matte", value_name = "PATH", num_args = 0..=1)]
     pub export_matte: Option<Option<PathBuf>>,
     /// Save the processed binary mask alongside the foreground PNG
     #[arg(long = "export-mask", value_name = "PATH", num_args = 0..=1)]
     pub export_mask: Option<Option<PathBuf>>,
+    /// Also save one file holding the cutout, raw matte, and trimap: EXR channels or TIFF
+    /// pages, by extension (defaults to `<name>-layers.exr`)
+    #[arg(long = "output-layers", value_name = "PATH", num_args = 0..=1)]
+    pub output_layers: Option<Option<PathBuf>>,
+    /// Also save a multi-page TIFF with the input as the base page and each separate subject
+    /// of the cutout on a page of its own (defaults to `<name>-subjects.tif`)
+    #[arg(long = "subject-layers", value_name = "PATH", num_args = 0..=1)]
+    pub subject_layers: Option<Option<PathBuf>>,
+    /// Smallest subject `--subject-layers` keeps, in pixels or as a percentage like `0.5%`
+    #[arg(
+        long = "min-subject-area",
+        value_name = "AREA",
+        value_parser = parse_component_area,
+        default_value = "0.5%",
+        requires = "subject_layers"
+    )]
+    pub min_subject_area: ComponentArea,
+    /// Write the output as a paletted PNG-8 of up to COLORS colors (2-256, default 256) with
+    /// binary transparency, quantized after compositing
+    #[arg(
+        long = "palette",
+        value_name = "COLORS",
+        num_args = 0..=1,
+        default_missing_value = "256",
+        value_parser = clap::value_parser!(u16).range(2..=256)
+    )]
+    pub palette: Option<u16>,
+    /// Dither the paletted output to hide banding
+    #[arg(long = "dither", requires = "palette")]
+    pub dither: bool,
+    /// How the paletted output's binary transparency follows soft matte edges
+    #[arg(
+        long = "alpha-dither",
+        value_enum,
+        value_name = "MODE",
+        default_value_t = AlphaDitherArg::Threshold,
+        requires = "palette"
+    )]
+    pub alpha_dither: AlphaDitherArg,
+    /// Fast preview: the lite model, or `--model` when given, on a shrunken input, written to
+    /// `<name>-draft.png` without backgrounds or mask processing
+    #[arg(long = "draft", conflicts_with_all = ["final_pass", "refine", "mask"])]
+    pub draft: bool,
+    /// Final pass that runs the model only where a `--draft` output found the subject
+    /// (defaults to `<name>-draft.png`)
+    #[arg(
+        id = "final_pass",
+        long = "final",
+        value_name = "DRAFT",
+        num_args = 0..=1,
+        conflicts_with = "mask"
+    )]
+    pub final_pass: Option<Option<PathBuf>>,
+    /// Artifacts to write from the one inference, comma-separated (defaults to `cut`)
+    #[arg(
+        long = "emit",
+        value_enum,
+        value_delimiter = ',',
+        value_name = "ARTIFACTS"
+    )]
+    pub emit: Vec<EmitArg>,
+    /// Write subject bounds, area, coverage, centroid, model, and timings next to each
+    /// cutout as `<output>.json`
+    #[arg(long = "json-sidecar")]
+    pub json_sidecar: bool,
+    /// Re-estimate the color of soft edges so the old background does not show as a fringe
+    #[arg(long = "defringe")]
+    pub defringe: bool,
+    /// Crop the cutout to the subject's bounding box, dropping transparent borders
+    #[arg(long = "trim")]
+    pub trim: bool,
+    /// Border kept around the subject by `--trim`, in pixels (`16px`) or as a percentage of
+    /// the subject's longer side (`5%`)
+    #[arg(
+        long = "margin",
+        value_name = "MARGIN",
+        value_parser = parse_trim_margin,
+        requires = "trim"
+    )]
+    pub margin: Option<TrimMargin>,
+    /// Place the subject on a canvas of this size, such as `2000x2000`, scaled to `--fill`
+    #[arg(
+        long = "canvas",
+        value_name = "WxH",
+        value_parser = parse_canvas_size,
+        conflicts_with = "trim"
+    )]
+    pub canvas: Option<(u32, u32)>,
+    /// Where the subject sits on the canvas
+    #[arg(
+        long = "anchor",
+        value_enum,
+        default_value_t = Anchor::Center,
+        requires = "canvas"
+    )]
+    pub anchor: Anchor,
+    /// Share of the canvas width or height the subject spans, such as `85%`
+    #[arg(
+        long = "fill",
+        value_name = "PERCENT",
+        value_parser = parse_fill,
+        default_value = "85%",
+        requires = "canvas"
+    )]
+    pub fill: f32,
+    /// Stroke a border this many pixels wide around the subject, for die-cut sticker looks
+    #[arg(long = "outline", value_name = "PX")]
+    pub outline: Option<f32>,
+    /// Color of the `--outline` border (`#rrggbb` or `#rgb`)
+    #[arg(
+        long = "outline-color",
+        value_name = "COLOR",
+        value_parser = parse_hex_color,
+        default_value = "#ffffff",
+        requires = "outline"
+    )]
+    pub outline_color: Rgb<u8>,
+    /// Draw a soft drop shadow beneath the subject, before trimming, placing, or compositing
+    #[arg(long = "shadow")]
+    pub shadow: bool,
+    /// Shadow shift in pixels as `X,Y`; positive values go right and down
+    #[arg(
+        long = "shadow-offset",
+        value_name = "X,Y",
+        value_parser = parse_offset,
+        default_value = "8,12",
+        allow_hyphen_values = true,
+        requires = "shadow"
+    )]
+    pub shadow_offset: (i32, i32),
+    /// Shadow blur radius (Gaussian sigma) in pixels
+    #[arg(
+        long = "shadow-blur",
+        value_name = "PX",
+        value_parser = parse_blur_radius,
+        default_value_t = 10.0,
+        requires = "shadow"
+    )]
+    pub shadow_blur: f32,
+    /// Shadow opacity beneath a fully opaque subject, such as `50%`
+    #[arg(
+        long = "shadow-opacity",
+        value_name = "PERCENT",
+        value_parser = parse_opacity,
+        default_value = "50%",
+        requires = "shadow"
+    )]
+    pub shadow_opacity: f32,
+    /// Shadow color (`#rrggbb` or `#rgb`)
+    #[arg(
+        long = "shadow-color",
+        value_name = "COLOR",
+        value_parser = parse_hex_color,
+        default_value = "#000000",
+        requires = "shadow"
+    )]
+    pub shadow_color: Rgb<u8>,
+    /// Where compositing and mask blurs run; `wgpu` needs the compositor-wgpu feature and
+    /// falls back to the CPU when no GPU is found
+    #[arg(long = "compositor", value_enum, default_value_t = CompositorArg::Cpu)]
+    pub compositor: CompositorArg,
+    /// Blend the cutout over backgrounds in linear light rather than sRGB, which keeps hair
+    /// and feathered edges from darkening into halos (CPU compositor only)
+    #[arg(long = "linear-light")]
+    pub linear_light: bool,
     /// Select which mask is used for the foreground alpha channel
     #[arg(long = "alpha-source", value_enum, default_value_t = AlphaFromArg::Auto)]
     pub alpha_source: AlphaFromArg,
+    /// Composite over a solid color (`#rrggbb` or `#rgb`) instead of transparency
+    #[arg(long = "bg-color", value_name = "COLOR", value_parser = parse_hex_color, conflicts_with = "bg_image")]
+    pub bg_color: Option<Rgb<u8>>,
+    /// Composite over an image, scaled and center-cropped to fit
+    #[arg(long = "bg-image", value_name = "PATH")]
+    pub bg_image: Option<PathBuf>,
+    /// Keep the background but blur it by this radius (Gaussian sigma) in pixels, for a fake
+    /// bokeh look
+    #[arg(
+        long = "blur-background",
+        value_name = "RADIUS",
+        value_parser = parse_blur_radius,
+        conflicts_with_all = ["bg_color", "bg_image", "canvas", "trim"]
+    )]
+    pub blur_background: Option<f32>,
+    /// Apply this mask file instead of running the model
+    #[arg(long, value_name = "PATH")]
+    pub mask: Option<PathBuf>,
+    #[command(flatten)]
+    pub mask_input: MaskInputArgs,
+    /// Refine the model's matte before any mask processing
+    #[arg(long = "refine", value_enum)]
+    pub refine: Option<RefineArg>,
+    /// Escalate per image through cheaper to costlier models, each then with `--refine
+    /// matting`, until the matte scores at least VALUE on METRIC (`edge-gradient` or
+    /// `confidence`, from 0 to 1), such as `edge-gradient=0.6`
+    #[arg(
+        long = "target-quality",
+        value_name = "METRIC=VALUE",
+        value_parser = parse_quality_target,
+        conflicts_with_all = ["refine", "mask", "draft", "final_pass"]
+    )]
+    pub target_quality: Option<QualityTarget>,
+    /// Models to escalate to after `--model`, cheapest first (defaults to the downloaded
+    /// general-purpose presets, fastest first)
+    #[arg(
+        long = "escalate",
+        value_name = "MODELS",
+        value_delimiter = ',',
+        requires = "target_quality"
+    )]
+    pub escalate: Vec<String>,
+    /// Most attempts per image under `--target-quality`, the first included; the best
+    /// scoring one is kept when none meets the target
+    #[arg(
+        long = "quality-budget",
+        value_name = "ATTEMPTS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "target_quality"
+    )]
+    pub quality_budget: Option<u32>,
+    /// Compare each cutout with the output of the same name in a previous run's directory,
+    /// and write only those that changed beyond the tolerance, listed as CHANGED or NEW
+    #[arg(long = "baseline", value_name = "DIR", conflicts_with_all = ["draft", "final_pass"])]
+    pub baseline: Option<PathBuf>,
+    /// Maximum perceptual hash distance in bits (0-64) for a cutout to match its baseline
+    #[arg(
+        long = "baseline-hash-distance",
+        default_value_t = 4,
+        requires = "baseline"
+    )]
+    pub baseline_hash_distance: u32,
+    /// Maximum absolute difference in alpha coverage (0.0-1.0) for a cutout to match its
+    /// baseline
+    #[arg(
+        long = "baseline-coverage-tolerance",
+        default_value_t = 0.01,
+        requires = "baseline"
+    )]
+    pub baseline_coverage_tolerance: f64,
+    #[command(flatten)]
+    pub extent: ExtentPolicyArgs,
+    #[command(flatten)]
+    pub checkpoint: ResumeArgs,
     #[command(flatten)]
     pub mask_processing: MaskProcessingArgs,
 }
 
+impl CutCommand {
+    /// Whether `--emit` asks for `artifact`; without it only the cutout is written.
+    pub fn emits(&self, artifact: EmitArg) -> bool {
+        if self.emit.is_empty() {
+            artifact == EmitArg::Cut
+        } else {
+            self.emit.contains(&artifact)
+        }
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct TraceCommand {
-    /// Input image path
-    pub input: PathBuf,
-    /// Output SVG path (defaults to input name with `.svg`)
+    /// Input image paths, directories, or http(s) URLs
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Output SVG path (defaults to input name with `.svg`, or `.json` with `--lottie`)
     #[arg(short, long)]
     pub output: Option<PathBuf>,
     /// Which mask to use for tracing (auto prefers processed)
     #[arg(long = "mask-source", value_enum, default_value_t = MaskSourceArg::Auto)]
     pub mask_source: MaskSourceArg,
+    /// Treat the inputs as existing masks to trace instead of running the model
+    #[arg(long = "from-mask")]
+    pub from_mask: bool,
+    /// Read the inputs as mattes piped from another bgr command instead of running the
+    /// model; `-` reads stdin
+    #[arg(
+        long = "from",
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with = "from_mask"
+    )]
+    pub from: Option<MatteStreamArg>,
+    /// Trace the inputs, in order, as the frames of one animation: every outline keeps its
+    /// node count (`--max-nodes`, default 64) and starting point from frame to frame, so the
+    /// SVGs can be tweened
+    #[arg(
+        long = "sequence",
+        conflicts_with_all = ["backend", "preserve_corners", "metadata", "invert_svg", "max_vertices", "convexity"]
+    )]
+    pub sequence: bool,
+    /// Write a Lottie JSON animation instead of SVG: one file per input, or one for the whole
+    /// `--sequence`, with outlines resampled as for `--sequence`
+    #[arg(
+        long = "lottie",
+        conflicts_with_all = ["backend", "preserve_corners", "metadata", "invert_svg", "max_vertices", "convexity", "coords", "flip_y", "transform"]
+    )]
+    pub lottie: bool,
+    /// Frames per second of a `--lottie` animation
+    #[arg(long = "fps", default_value_t = 24, requires = "lottie", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+    #[command(flatten)]
+    pub mask_input: MaskInputArgs,
     #[command(flatten)]
     pub mask_processing: MaskProcessingArgs,
     #[command(flatten)]
     pub trace_options: TraceOptionsArgs,
+    #[command(flatten)]
+    pub coordinates: CoordinateArgs,
+    #[command(flatten)]
+    pub extent: ExtentPolicyArgs,
+    #[command(flatten)]
+    pub checkpoint: ResumeArgs,
 }
 
+/// Options for the `daemon` command.
+#[cfg(unix)]
 #[derive(Args, Debug)]
-pub struct MaskProcessingArgs {
-    /// Enable gaussian blur before thresholding (optionally override sigma)
-    #[arg(long = "blur", value_name = "SIGMA", num_args = 0..=1, default_missing_value = "6.0")]
-    pub blur: Option<f32>,
-    /// Threshold applied to the matte (0-255 or 0.0-1.0)
-    #[arg(long = "mask-threshold", default_value_t = 120, value_parser = parse_mask_threshold)]
-    pub mask_threshold: u8,
-    /// Apply thresholding to produce a binary mask (use `--binary enabled|disabled|auto` to choose behaviour)
+pub struct DaemonCommand {
+    /// Socket path (defaults to `$XDG_RUNTIME_DIR/bgr.sock`, or `bgr-<uid>/bgr.sock` in the
+    /// temporary directory)
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    #[command(flatten)]
+    pub cache: MatteCacheArgs,
+}
+
+/// How many recent mattes a long-running command keeps, by input content.
+#[cfg(any(unix, feature = "server"))]
+#[derive(Args, Debug)]
+pub struct MatteCacheArgs {
+    /// Keep the mattes of up to N recent inputs, so a repeated request skips inference (0 for
+    /// none)
+    #[arg(long = "cache-entries", value_name = "N", default_value_t = 0)]
+    pub entries: usize,
+    /// Seconds a cached matte is kept
     #[arg(
-        long = "binary",
-        value_enum,
-        default_value_t = BinaryOption::Auto,
-        num_args = 0..=1,
-        default_missing_value = "enabled"
+        long = "cache-ttl",
+        value_name = "SECS",
+        default_value_t = 60,
+        requires = "entries"
     )]
-    pub binary: BinaryOption,
-    #[arg(long = "dilate", value_name = "RADIUS", num_args = 0..=1, default_missing_value = "5.0")]
-    pub dilate: Option<f32>,
-    /// Fill enclosed holes in the mask before vectorization
-    #[arg(long = "fill-holes")]
-    pub fill_holes: bool,
+    pub ttl: u64,
 }
 
-impl From<&MaskProcessingArgs> for MaskProcessingOptions {
-    fn from(args: &MaskProcessingArgs) -> Self {
-        let defaults = MaskProcessingOptions::default();
-        Self {
-            binary: (args.binary == BinaryOption::Auto
-                && (args.dilate.is_some() || args.fill_holes))
-                || args.binary == BinaryOption::Enabled,
-            blur: args.blur.is_some(),
-            blur_sigma: args.blur.unwrap_or(defaults.blur_sigma),
-            mask_threshold: args.mask_threshold,
-            dilate: args.dilate.is_some(),
-            dilation_radius: args.dilate.unwrap_or(defaults.dilation_radius),
-            fill_holes: args.fill_holes,
-        }
+#[cfg(any(unix, feature = "server"))]
+impl MatteCacheArgs {
+    /// The cache these options ask for, if any.
+    pub fn cache(&self) -> Option<MatteCache> {
+        (self.entries > 0).then(|| MatteCache::new(self.entries, Duration::from_secs(self.ttl)))
     }
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
-pub enum MaskExportSource {
-    Auto,
-    Raw,
-    Processed,
+/// Options for the `watch` command.
+#[derive(Args, Debug)]
+pub struct WatchCommand {
+    /// Directory to watch, including subdirectories
+    pub dir: PathBuf,
+    /// Root of the output tree, mirroring the watched directory's layout
+    #[arg(short, long, value_name = "DIR")]
+    pub output: PathBuf,
+    /// Milliseconds a file must stay unchanged before it is processed
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub debounce: u64,
+    /// Record of processed files (defaults to `.bgr-journal.jsonl` in the output directory)
+    #[arg(long, value_name = "FILE")]
+    pub journal: Option<PathBuf>,
+    /// Select which mask is used for the foreground alpha channel
+    #[arg(long = "alpha-source", value_enum, default_value_t = AlphaFromArg::Auto)]
+    pub alpha_source: AlphaFromArg,
+    /// Composite over a solid color (`#rrggbb` or `#rgb`) instead of transparency
+    #[arg(long = "bg-color", value_name = "COLOR", value_parser = parse_hex_color)]
+    pub bg_color: Option<Rgb<u8>>,
+    /// Refine the model's matte before any mask processing
+    #[arg(long = "refine", value_enum)]
+    pub refine: Option<RefineArg>,
+    #[command(flatten)]
+    pub mask_processing: MaskProcessingArgs,
 }
 
-fn parse_mask_threshold(value: &str) -> Result<u8, String> {
-    if let Ok(int_value) = value.parse::<u8>() {
-        return Ok(int_value);
-    }
+/// Options for the `video` command.
+#[cfg(feature = "video")]
+#[derive(Args, Debug)]
+pub struct VideoCommand {
+    /// Input video file
+    pub input: PathBuf,
+    /// Output video file; its extension picks the default codec
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Output codec (defaults to vp9 for .webm, prores for .mov, h264 otherwise)
+    #[arg(long, value_enum)]
+    pub codec: Option<VideoCodecArg>,
+    /// Blend each matte with earlier frames to reduce flicker (strength 0.0-0.95)
+    #[arg(
+        long = "temporal-smooth",
+        value_name = "STRENGTH",
+        num_args = 0..=1,
+        default_missing_value = "0.6",
+        value_parser = parse_temporal_strength
+    )]
+    pub temporal_smooth: Option<f32>,
+    /// Select which mask is used for the foreground alpha channel
+    #[arg(long = "alpha-source", value_enum, default_value_t = AlphaFromArg::Auto)]
+    pub alpha_source: AlphaFromArg,
+    /// Composite over a solid color (`#rrggbb` or `#rgb`) instead of transparency
+    #[arg(long = "bg-color", value_name = "COLOR", value_parser = parse_hex_color)]
+    pub bg_color: Option<Rgb<u8>>,
+    /// Refine the model's matte before any mask processing
+    #[arg(long = "refine", value_enum)]
+    pub refine: Option<RefineArg>,
+    /// Encode in parts that an interrupted run continues from, at the first frame it had not
+    /// finished and with the same temporal smoothing state; without it, a new run starts over
+    #[arg(long)]
+    pub resume: bool,
+    #[command(flatten)]
+    pub mask_processing: MaskProcessingArgs,
+}
 
-    let float_value = value
-        .parse::<f32>()
-        .map_err(|_| format!("mask threshold must be numeric (0-255 or 0.0-1.0), got `{value}`"))?;
+/// Video codecs `bgr video` can encode.
+#[cfg(feature = "video")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum VideoCodecArg {
+    /// VP9 with an alpha channel (WebM)
+    Vp9,
+    /// ProRes 4444 with an alpha channel (QuickTime)
+    Prores,
+    /// H.264 without alpha; needs `--bg-color`
+    H264,
+}
 
-    if (0.0..=1.0).contains(&float_value) {
-        let scaled = (float_value * 255.0).round() as i32;
-        return Ok(scaled.clamp(0, 255) as u8);
-    }
+/// Options for the `serve` HTTP API.
+#[cfg(feature = "server")]
+#[derive(Args, Debug)]
+pub struct ServeCommand {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    pub bind: SocketAddr,
+    /// Allow cross-origin requests from any web page
+    #[arg(long)]
+    pub cors: bool,
+    /// Select which mask is used for the foreground alpha channel
+    #[arg(long = "alpha-source", value_enum, default_value_t = AlphaFromArg::Auto)]
+    pub alpha_source: AlphaFromArg,
+    /// Composite responses over a solid color (`#rrggbb` or `#rgb`) instead of transparency
+    #[arg(long = "bg-color", value_name = "COLOR", value_parser = parse_hex_color)]
+    pub bg_color: Option<Rgb<u8>>,
+    /// Refine the model's matte before any mask processing
+    #[arg(long = "refine", value_enum)]
+    pub refine: Option<RefineArg>,
+    /// Also load the lite model for `?mode=draft` previews
+    #[arg(long)]
+    pub draft: bool,
+    #[command(flatten)]
+    pub cache: MatteCacheArgs,
+    #[command(flatten)]
+    pub mask_processing: MaskProcessingArgs,
+}
 
-    if float_value.fract().abs() <= f32::EPSILON && (0.0..=255.0).contains(&float_value) {
-        return Ok(float_value as u8);
-    }
+#[derive(Args, Debug)]
+pub struct VerifyCommand {
+    /// Directory containing the outputs to check
+    pub dir: PathBuf,
+    /// Golden fingerprint file (JSON)
+    #[arg(long, value_name = "FILE")]
+    pub golden: PathBuf,
+    /// Record the fingerprints of `dir` into the golden file instead of comparing
+    #[arg(long)]
+    pub update: bool,
+    /// Maximum perceptual hash distance in bits (0-64)
+    #[arg(long = "max-hash-distance", default_value_t = 4)]
+    pub max_hash_distance: u32,
+    /// Maximum absolute difference in alpha coverage (0.0-1.0)
+    #[arg(long = "coverage-tolerance", default_value_t = 0.01)]
+    pub coverage_tolerance: f64,
+}
 
-    Err(format!(
-        "mask threshold {value} is out of range; expected 0-255 or 0.0-1.0"
-    ))
+#[derive(Args, Debug)]
+pub struct ExamplesCommand {
+    /// Only show the recipes for this subcommand
+    #[arg(value_parser = PossibleValuesParser::new(crate::examples::commands()))]
+    pub command: Option<String>,
 }
 
-/// The argument to specify if binary mask processing is enabled.
-#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
-pub enum BinaryOption {
-    Enabled,
+#[cfg(feature = "test-utils")]
+#[derive(Args, Debug)]
+pub struct FixturesCommand {
+    #[command(subcommand)]
+    pub action: FixturesAction,
+}
+
+#[cfg(feature = "test-utils")]
+#[derive(Subcommand, Debug)]
+pub enum FixturesAction {
+    /// Run the standard fixtures through a model and write each input, its ideal and actual
+    /// cutout, and a `report.json` into `--out-dir` (defaults to `fixtures/`)
+    Generate {
+        /// Model file or preset name
+        model: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct ModelsCommand {
+    #[command(subcommand)]
+    pub action: ModelsAction,
+}
+
+#[derive(Args, Debug)]
+pub struct UsageCommand {
+    #[command(subcommand)]
+    pub action: UsageAction,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print where the config file is read from
+    Path,
+    /// Print every value the config file sets
+    List,
+    /// Print the value of one key
+    Get {
+        #[arg(value_parser = PossibleValuesParser::new(crate::config_file::key_names()))]
+        key: String,
+    },
+    /// Set a key, checking the value as its flag would
+    Set {
+        #[arg(value_parser = PossibleValuesParser::new(crate::config_file::key_names()))]
+        key: String,
+        value: String,
+    },
+    /// Remove a key, restoring the built-in default
+    Unset {
+        #[arg(value_parser = PossibleValuesParser::new(crate::config_file::key_names()))]
+        key: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct RunCommand {
+    /// Job file: TOML, or YAML when it ends in `.yaml` or `.yml`
+    pub job: PathBuf,
+    /// Set a job variable, overriding the file's value
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = crate::job_file::parse_var)]
+    pub vars: Vec<(String, String)>,
+    /// Print each step's command line instead of running it
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsCommand {
+    /// Shell to complete in
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Args, Debug)]
+pub struct ManpageCommand {
+    /// Write `bgr.1` and a `bgr-<command>.1` page per subcommand here instead of printing
+    /// `bgr.1`
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UsageAction {
+    /// Sum runs, failures, images, megapixels, and time from the usage log, per group
+    Report {
+        /// What to total by
+        #[arg(long, value_enum, default_value_t = UsageGroupArg::Command)]
+        by: UsageGroupArg,
+        /// Print the totals as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Groups of `usage report --by`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UsageGroupArg {
+    Command,
+    Model,
+    Tag,
+    Device,
+    /// The UTC date each run finished
+    Day,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModelsAction {
+    /// List presets with size, download state, and checksum
+    List {
+        /// Add quality and speed tiers, recommended use, and license
+        #[arg(long)]
+        detailed: bool,
+    },
+    /// Recommend a preset for a kind of image
+    Advise {
+        /// Kind of image to process
+        #[arg(long = "for", value_enum, default_value_t = UseCaseArg::General)]
+        use_case: UseCaseArg,
+        /// Favor matte quality or speed
+        #[arg(long, value_enum, default_value_t = PriorityArg::Quality)]
+        prefer: PriorityArg,
+    },
+    /// Download a preset ahead of time
+    Pull {
+        /// Preset name
+        #[arg(required_unless_present = "all")]
+        preset: Option<String>,
+        /// Download every preset
+        #[arg(long, conflicts_with = "preset")]
+        all: bool,
+    },
+    /// Remove a downloaded preset
+    Rm {
+        /// Preset name
+        preset: String,
+    },
+    /// Print the models directory, or the local path of a preset
+    Path {
+        /// Preset name
+        preset: Option<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct MaskProcessingArgs {
+    /// Enable gaussian blur before thresholding (optionally override sigma)
+    #[arg(long = "blur", value_name = "SIGMA", num_args = 0..=1, default_missing_value = "6.0")]
+    pub blur: Option<f32>,
+    /// Threshold applied to the matte (0-255 or 0.0-1.0); enables `--binary` under `auto`
+    #[arg(
+        long = "mask-threshold",
+        visible_alias = "threshold",
+        default_value_t = 120,
+        value_parser = parse_mask_threshold
+    )]
+    pub mask_threshold: u8,
+    /// Apply thresholding to produce a binary mask (use `--binary enabled|disabled|auto` to choose behaviour)
+    #[arg(
+        long = "binary",
+        value_enum,
+        default_value_t = BinaryOption::Auto,
+        default_value_ifs = [("mask_threshold", ArgPredicate::IsPresent, "enabled")],
+        num_args = 0..=1,
+        default_missing_value = "enabled"
+    )]
+    pub binary: BinaryOption,
+    /// Shrink the mask by RADIUS pixels
+    #[arg(long = "erode", value_name = "RADIUS", num_args = 0..=1, default_missing_value = "2.0")]
+    pub erode: Option<f32>,
+    /// Keep only the largest connected region of the mask
+    #[arg(long = "keep-largest")]
+    pub keep_largest: bool,
+    /// Drop regions smaller than AREA (pixels, or a percentage of the image such as `0.5%`)
+    #[arg(long = "min-area", value_name = "AREA", value_parser = parse_component_area)]
+    pub min_area: Option<ComponentArea>,
+    #[arg(long = "dilate", value_name = "RADIUS", num_args = 0..=1, default_missing_value = "5.0")]
+    pub dilate: Option<f32>,
+    /// Fill enclosed holes in the mask before vectorization
+    #[arg(long = "fill-holes")]
+    pub fill_holes: bool,
+    /// Soften the final mask edge over RADIUS pixels (0 for none)
+    #[arg(long = "feather", value_name = "RADIUS", num_args = 0..=1, default_missing_value = "2.0")]
+    pub feather: Option<f32>,
+}
+
+impl From<&MaskProcessingArgs> for MaskProcessingOptions {
+    fn from(args: &MaskProcessingArgs) -> Self {
+        let defaults = MaskProcessingOptions::default();
+        Self {
+            binary: (args.binary == BinaryOption::Auto
+                && (args.erode.is_some() || args.dilate.is_some() || args.fill_holes))
+                || args.binary == BinaryOption::Enabled,
+            blur: args.blur.is_some(),
+            blur_sigma: args.blur.unwrap_or(defaults.blur_sigma),
+            mask_threshold: args.mask_threshold,
+            dilate: args.dilate.is_some(),
+            dilation_radius: args.dilate.unwrap_or(defaults.dilation_radius),
+            erode: args.erode.is_some(),
+            erosion_radius: args.erode.unwrap_or(defaults.erosion_radius),
+            keep_largest: args.keep_largest,
+            min_area: args.min_area,
+            fill_holes: args.fill_holes,
+            // `--feather 0` turns off a feather the config file sets.
+            feather: args.feather.is_some_and(|radius| radius > 0.0),
+            feather_radius: args.feather.unwrap_or(defaults.feather_radius),
+        }
+    }
+}
+
+/// How supplied mask files are read (`cut --mask`, `mask --from-mask`, `trace --from-mask`).
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Mask input")]
+pub struct MaskInputArgs {
+    /// When to threshold a supplied mask to black and white
+    #[arg(long = "mask-binarize", value_enum, default_value_t = MaskBinarizeArg::Auto)]
+    pub binarize: MaskBinarizeArg,
+    /// What to do when a supplied mask is not the size of its image
+    #[arg(long = "mask-resize", value_enum, default_value_t = MaskResizeArg::Stretch)]
+    pub resize: MaskResizeArg,
+}
+
+impl From<&MaskInputArgs> for MaskLoadOptions {
+    fn from(args: &MaskInputArgs) -> Self {
+        Self {
+            binarize: match args.binarize {
+                MaskBinarizeArg::Auto => MaskBinarize::Auto,
+                MaskBinarizeArg::Always => MaskBinarize::Always,
+                MaskBinarizeArg::Never => MaskBinarize::Never,
+            },
+            resize: match args.resize {
+                MaskResizeArg::Stretch => MaskResize::Stretch,
+                MaskResizeArg::Reject => MaskResize::Reject,
+            },
+            ..MaskLoadOptions::default()
+        }
+    }
+}
+
+/// When supplied masks are binarized.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum MaskBinarizeArg {
+    /// Only masks that are nearly black and white, with a warning
+    Auto,
+    Always,
+    Never,
+}
+
+/// How supplied masks of the wrong size are handled.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum MaskResizeArg {
+    /// Stretch to the image size, with a warning
+    Stretch,
+    /// Fail the input
+    Reject,
+}
+
+/// What to do with an input whose matte is empty or covers the whole image.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum ExtentPolicyArg {
+    /// Write nothing for the input and report it
+    Skip,
+    /// Write the input whole, as if all of it were foreground
+    Copy,
+    /// Fail the input
+    Fail,
+}
+
+/// `--on-empty` and `--on-full`: what becomes of inputs the model found nothing, or
+/// everything, to keep in.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct ExtentPolicyArgs {
+    /// What to do when the model finds no foreground, instead of writing a blank output
+    #[arg(
+        long = "on-empty",
+        value_enum,
+        value_name = "POLICY",
+        default_value_t = ExtentPolicyArg::Skip
+    )]
+    pub on_empty: ExtentPolicyArg,
+    /// What to do when the model finds foreground everywhere
+    #[arg(
+        long = "on-full",
+        value_enum,
+        value_name = "POLICY",
+        default_value_t = ExtentPolicyArg::Copy
+    )]
+    pub on_full: ExtentPolicyArg,
+}
+
+/// `--resume` and `--force-rerun`: whether a batch picks up where an interrupted run of it
+/// stopped.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct ResumeArgs {
+    /// Continue an interrupted batch, skipping the inputs its journal (under `batches/` in the
+    /// data directory) lists as written and unchanged since
+    #[arg(long)]
+    pub resume: bool,
+    /// Process every input again even with `--resume`, as set by a job file or alias, and
+    /// start a new journal
+    #[arg(long = "force-rerun")]
+    pub force_rerun: bool,
+}
+
+impl ResumeArgs {
+    /// Whether the journal of an earlier run is read.
+    pub fn resuming(self) -> bool {
+        self.resume && !self.force_rerun
+    }
+}
+
+/// Matte refinement passes.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum RefineArg {
+    /// Trimap plus color guided filter for soft, hair-friendly edges
+    Matting,
+}
+
+/// Output formats of `mask`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MaskFormatArg {
+    /// An 8-bit grayscale PNG
+    Png,
+    /// A `bgrm` stream: the matte with its source, model, and settings
+    Bgrm,
+}
+
+/// Sample precisions of `mask --depth`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MatteDepthArg {
+    /// 8-bit grayscale PNG
+    #[value(name = "8")]
+    Eight,
+    /// 16-bit grayscale PNG
+    #[value(name = "16")]
+    Sixteen,
+    /// 32-bit float EXR
+    Float,
+}
+
+impl From<MatteDepthArg> for MatteDepth {
+    /// Convert MatteDepthArg to bgr::MatteDepth.
+    fn from(value: MatteDepthArg) -> Self {
+        match value {
+            MatteDepthArg::Eight => MatteDepth::Eight,
+            MatteDepthArg::Sixteen => MatteDepth::Sixteen,
+            MatteDepthArg::Float => MatteDepth::Float,
+        }
+    }
+}
+
+/// Storage formats of `mask --storage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MatteStorageArg {
+    /// Benchmark every format on a sample of the batch and pick the smallest
+    Auto,
+    /// 8-bit grayscale PNG
+    Png8,
+    /// 16-bit grayscale PNG
+    Png16,
+    /// 32-bit float EXR
+    Exr,
+    /// 8-bit lossless WebP
+    Webp,
+    /// 8-bit run-length `bgrl`, smallest for hard-edged masks
+    Rle,
+}
+
+impl MatteStorageArg {
+    /// The format to store mattes in, or `None` for `auto`.
+    pub fn format(self) -> Option<StorageFormat> {
+        match self {
+            Self::Auto => None,
+            Self::Png8 => Some(StorageFormat::Png8),
+            Self::Png16 => Some(StorageFormat::Png16),
+            Self::Exr => Some(StorageFormat::Exr),
+            Self::Webp => Some(StorageFormat::WebP),
+            Self::Rle => Some(StorageFormat::Rle),
+        }
+    }
+}
+
+/// Streams that `--from` reads mattes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MatteStreamArg {
+    /// A `bgrm` stream from `bgr mask --format bgrm`
+    Bgrm,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MaskExportSource {
+    Auto,
+    Raw,
+    Processed,
+}
+
+pub(crate) fn parse_hex_color(value: &str) -> Result<Rgb<u8>, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let invalid = || format!("color must be `#rrggbb` or `#rgb`, got `{value}`");
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+    match hex.len() {
+        6 => Ok(Rgb([
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ])),
+        3 => {
+            let short = |i: usize| channel(&hex[i..=i]).map(|v| v * 17);
+            Ok(Rgb([short(0)?, short(1)?, short(2)?]))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a percentage within `range` such as `50%`, the sign being optional; `what` names the
+/// value in errors.
+fn parse_percent(value: &str, what: &str, range: RangeInclusive<f64>) -> Result<f64, String> {
+    let percent = value.strip_suffix('%').unwrap_or(value).trim();
+    match percent.parse::<f64>() {
+        Ok(p) if range.contains(&p) => Ok(p),
+        Ok(_) => Err(format!(
+            "{what} {value} is out of range; expected {}-{}%",
+            range.start(),
+            range.end()
+        )),
+        Err(_) => Err(format!(
+            "{what} must be a percentage such as `50%`, got `{value}`"
+        )),
+    }
+}
+
+fn parse_trim_margin(value: &str) -> Result<TrimMargin, String> {
+    if value.ends_with('%') {
+        return parse_percent(value, "margin", 0.0..=100.0).map(TrimMargin::Percent);
+    }
+    let pixels = value.strip_suffix("px").unwrap_or(value).trim();
+    pixels.parse::<u32>().map(TrimMargin::Pixels).map_err(|_| {
+        format!("margin must be pixels such as `16px` or a percentage such as `5%`, got `{value}`")
+    })
+}
+
+fn parse_quality_target(value: &str) -> Result<QualityTarget, String> {
+    let invalid =
+        || format!("target must be `METRIC=VALUE` such as `edge-gradient=0.6`, got `{value}`");
+    let (metric, target) = value.split_once('=').ok_or_else(invalid)?;
+    let metric = QualityMetric::from_name(metric.trim()).ok_or_else(|| {
+        let names: Vec<_> = QualityMetric::ALL
+            .iter()
+            .map(|metric| metric.name())
+            .collect();
+        format!(
+            "unknown quality metric `{metric}`; expected one of {}",
+            names.join(", ")
+        )
+    })?;
+    match target.trim().parse::<f32>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(QualityTarget { metric, value }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_canvas_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid =
+        || format!("canvas size must be `WIDTHxHEIGHT` such as `2000x2000`, got `{value}`");
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_offset(value: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("offset must be `X,Y` in pixels such as `8,12`, got `{value}`");
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    match (x.trim().parse(), y.trim().parse()) {
+        (Ok(x), Ok(y)) => Ok((x, y)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_opacity(value: &str) -> Result<f32, String> {
+    parse_percent(value, "opacity", 0.0..=100.0).map(|p| p as f32 / 100.0)
+}
+
+fn parse_fill(value: &str) -> Result<f32, String> {
+    parse_percent(value, "fill", 1.0..=100.0).map(|p| p as f32 / 100.0)
+}
+
+/// The CPU share `--nice` stands for, in percent.
+const NICE_CPU_PERCENT: u8 = 50;
+
+fn parse_cpu_percent(value: &str) -> Result<u8, String> {
+    parse_percent(value, "CPU share", 1.0..=100.0).map(|p| p.round() as u8)
+}
+
+fn parse_mask_threshold(value: &str) -> Result<u8, String> {
+    if let Ok(int_value) = value.parse::<u8>() {
+        return Ok(int_value);
+    }
+
+    let float_value = value
+        .parse::<f32>()
+        .map_err(|_| format!("mask threshold must be numeric (0-255 or 0.0-1.0), got `{value}`"))?;
+
+    if (0.0..=1.0).contains(&float_value) {
+        let scaled = (float_value * 255.0).round() as i32;
+        return Ok(scaled.clamp(0, 255) as u8);
+    }
+
+    if float_value.fract().abs() <= f32::EPSILON && (0.0..=255.0).contains(&float_value) {
+        return Ok(float_value as u8);
+    }
+
+    Err(format!(
+        "mask threshold {value} is out of range; expected 0-255 or 0.0-1.0"
+    ))
+}
+
+#[cfg(feature = "video")]
+fn parse_blur_radius(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(radius) if radius.is_finite() && radius >= 0.0 => Ok(radius),
+        Ok(_) => Err(format!(
+            "blur radius {value} is out of range; expected 0 or more pixels"
+        )),
+        Err(_) => Err(format!("blur radius must be numeric, got `{value}`")),
+    }
+}
+
+fn parse_temporal_strength(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(strength) if (0.0..=0.95).contains(&strength) => Ok(strength),
+        Ok(_) => Err(format!(
+            "temporal smoothing strength {value} is out of range; expected 0.0-0.95"
+        )),
+        Err(_) => Err(format!(
+            "temporal smoothing strength must be numeric, got `{value}`"
+        )),
+    }
+}
+
+fn parse_component_area(value: &str) -> Result<ComponentArea, String> {
+    if value.ends_with('%') {
+        return parse_percent(value, "minimum area", 0.0..=100.0).map(ComponentArea::Percent);
+    }
+    value
+        .parse::<u64>()
+        .map(ComponentArea::Pixels)
+        .map_err(|_| {
+            format!(
+                "minimum area must be a pixel count or a percentage such as `0.5%`, got `{value}`"
+            )
+        })
+}
+
+/// The argument to specify if binary mask processing is enabled.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum BinaryOption {
+    Enabled,
     Disabled,
     Auto,
 }
 
+/// Artifacts `cut --emit` writes from one matte.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum EmitArg {
+    /// The cutout PNG, or the composite with a background
+    Cut,
+    /// The processed mask as `<name>-mask.png`
+    Mask,
+    /// The raw matte as `<name>-matte.png`
+    Matte,
+    /// An SVG trace of the alpha with the default trace settings, as `<name>.svg`
+    Svg,
+    /// Size, subject bounds and coverage, warnings, and the files written, as
+    /// `<name>-summary.json`
+    Json,
+}
+
 /// The argument to specify which alpha source to use.
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum AlphaFromArg {
@@ -222,6 +1710,48 @@ pub enum MaskSourceArg {
     Auto,
 }
 
+/// SVG tracing backends.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum TraceBackendArg {
+    /// VTracer (requires the `vectorizer-vtracer` feature)
+    #[cfg(feature = "vectorizer-vtracer")]
+    Vtracer,
+    /// Built-in marching-squares tracer with Douglas-Peucker simplification
+    Contour,
+}
+
+/// Named tracing presets for common print and design jobs.
+///
+/// Each profile only changes the defaults of the flags it mentions, so `--profile icon --mode
+/// spline` traces with the icon settings but spline curves.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum TraceProfileArg {
+    /// Smooth, rounded outlines for die-cut stickers (speckle 8, corner threshold 90)
+    Sticker,
+    /// Cut lines with sharp corners pinned, on the contour backend (speckle 16, tolerance 0.5)
+    Dieline,
+    /// Compact polygonal outlines for icons (speckle 8, tolerance 2, precision 1)
+    Icon,
+    /// Centerline strokes for line art (stroke width 2)
+    Lineart,
+}
+
+/// Ways to attach per-contour metadata to traced output.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum ContourMetadataArg {
+    /// `data-*` attributes on each SVG path
+    Attrs,
+    /// A JSON sidecar next to the SVG
+    Json,
+}
+
+impl TraceBackendArg {
+    #[cfg(feature = "vectorizer-vtracer")]
+    const DEFAULT: Self = Self::Vtracer;
+    #[cfg(not(feature = "vectorizer-vtracer"))]
+    const DEFAULT: Self = Self::Contour;
+}
+
 /// Tracing color modes for SVG vectorization.
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum TracerColorMode {
@@ -229,6 +1759,7 @@ pub enum TracerColorMode {
     Binary,
 }
 
+#[cfg(feature = "vectorizer-vtracer")]
 impl From<TracerColorMode> for ColorMode {
     /// Convert TracerColorMode to vtracer::ColorMode.
     fn from(value: TracerColorMode) -> Self {
@@ -246,6 +1777,7 @@ pub enum TracerHierarchy {
     Cutout,
 }
 
+#[cfg(feature = "vectorizer-vtracer")]
 impl From<TracerHierarchy> for Hierarchical {
     /// Convert TracerHierarchy to vtracer::Hierarchical.
     fn from(value: TracerHierarchy) -> Self {
@@ -262,21 +1794,40 @@ pub enum TracerMode {
     None,
     Polygon,
     Spline,
+    /// Trace the mask's skeleton as open strokes instead of filled outlines
+    Centerline,
+    /// Few-vertex polygons for physics engines instead of a faithful outline
+    Collision,
 }
 
+#[cfg(feature = "vectorizer-vtracer")]
 impl From<TracerMode> for PathSimplifyMode {
     /// Convert TracerMode to vtracer::PathSimplifyMode.
+    ///
+    /// Centerline and collision tracing do not use VTracer; they map to the spline and polygon
+    /// modes.
     fn from(value: TracerMode) -> Self {
         match value {
             TracerMode::None => PathSimplifyMode::None,
             TracerMode::Polygon => PathSimplifyMode::Polygon,
-            TracerMode::Spline => PathSimplifyMode::Spline,
+            TracerMode::Spline | TracerMode::Centerline => PathSimplifyMode::Spline,
+            TracerMode::Collision => PathSimplifyMode::Polygon,
         }
     }
 }
 
 #[derive(Args, Debug)]
 pub struct TraceOptionsArgs {
+    /// Named bundle of tracing settings; explicit flags still override it
+    #[arg(long = "profile", value_enum)]
+    pub profile: Option<TraceProfileArg>,
+    /// Tracing backend (defaults to vtracer when compiled in, otherwise contour)
+    #[arg(
+        long = "backend",
+        value_enum,
+        default_value_ifs = [("profile", "dieline", "contour")]
+    )]
+    pub backend: Option<TraceBackendArg>,
     /// Tracing color mode
     #[arg(long = "color-mode", value_enum, default_value_t = TracerColorMode::Binary)]
     pub color_mode: TracerColorMode,
@@ -284,10 +1835,19 @@ pub struct TraceOptionsArgs {
     #[arg(long = "hierarchy", value_enum, default_value_t = TracerHierarchy::Stacked)]
     pub hierarchy: TracerHierarchy,
     /// Path simplification mode
-    #[arg(long = "mode", value_enum, default_value_t = TracerMode::Spline)]
+    #[arg(
+        long = "mode",
+        value_enum,
+        default_value_t = TracerMode::Spline,
+        default_value_ifs = [("profile", "icon", "polygon"), ("profile", "lineart", "centerline")]
+    )]
     pub mode: TracerMode,
     /// Speckle filter size used by the tracer
-    #[arg(long = "filter-speckle", default_value_t = 4)]
+    #[arg(
+        long = "filter-speckle",
+        default_value_t = 4,
+        default_value_ifs = [("profile", "sticker", "8"), ("profile", "dieline", "16"), ("profile", "icon", "8")]
+    )]
     pub filter_speckle: usize,
     /// Color precision override (significant bits per RGB channel)
     #[arg(long = "color-precision", default_value_t = 6)]
@@ -296,7 +1856,11 @@ pub struct TraceOptionsArgs {
     #[arg(long = "layer-difference", default_value_t = 16)]
     pub layer_difference: i32,
     /// Corner threshold override in degrees
-    #[arg(long = "corner-threshold", default_value_t = 60)]
+    #[arg(
+        long = "corner-threshold",
+        default_value_t = 60,
+        default_value_ifs = [("profile", "sticker", "90")]
+    )]
     pub corner_threshold: i32,
     /// Segment length threshold override
     #[arg(long = "length-threshold", default_value_t = 4.0)]
@@ -308,16 +1872,88 @@ pub struct TraceOptionsArgs {
     #[arg(long = "splice-threshold", default_value_t = 45)]
     pub splice_threshold: i32,
     /// Path precision override (decimal places)
-    #[arg(long = "path-precision", conflicts_with = "no_path_precision")]
+    #[arg(
+        long = "path-precision",
+        conflicts_with = "no_path_precision",
+        default_value_ifs = [("profile", "icon", "1")]
+    )]
     pub path_precision: Option<u32>,
     /// Disable explicit path precision override
     #[arg(long = "no-path-precision")]
     pub no_path_precision: bool,
+    /// Maximum nodes per path; simplification is increased until every path fits
+    #[arg(long = "max-nodes", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_nodes: Option<u32>,
     /// Invert foreground/background in the output SVG
     #[arg(long = "invert-svg")]
     pub invert_svg: bool,
+    /// Stroke width of centerline paths
+    #[arg(
+        long = "stroke-width",
+        default_value_t = 1.0,
+        default_value_ifs = [("profile", "lineart", "2")]
+    )]
+    pub stroke_width: f64,
+    /// Simplification tolerance for centerline paths, in pixels
+    #[arg(
+        long = "simplify-tolerance",
+        default_value_t = 1.0,
+        default_value_ifs = [("profile", "sticker", "1.5"), ("profile", "dieline", "0.5"), ("profile", "icon", "2")]
+    )]
+    pub simplify_tolerance: f64,
+    /// Keep sharp corners as path anchors instead of smoothing them (contour backend)
+    #[arg(
+        long = "preserve-corners",
+        default_value_ifs = [("profile", "dieline", "true")]
+    )]
+    pub preserve_corners: bool,
+    /// Record area, perimeter, bounding box, and hole flags per contour (contour backend)
+    #[arg(long = "metadata", value_enum)]
+    pub metadata: Option<ContourMetadataArg>,
+    /// Most vertices per polygon (collision mode; default 8)
+    #[arg(long = "max-vertices", value_name = "N", value_parser = clap::value_parser!(u32).range(3..))]
+    pub max_vertices: Option<u32>,
+    /// Emit convex polygons only, splitting shapes that stray over this many pixels from their
+    /// convex hull into convex pieces (collision mode)
+    #[arg(long = "convexity", value_name = "TOL")]
+    pub convexity: Option<f64>,
+}
+
+impl TraceOptionsArgs {
+    /// Whether the centerline tracer was selected.
+    pub fn centerline(&self) -> bool {
+        matches!(self.mode, TracerMode::Centerline)
+    }
+
+    /// Whether the collision-shape tracer was selected.
+    pub fn collision(&self) -> bool {
+        matches!(self.mode, TracerMode::Collision)
+    }
+
+    /// The backend to trace with: explicit, or the default for the compiled features.
+    pub fn backend(&self) -> TraceBackendArg {
+        match self.backend {
+            Some(backend) => backend,
+            // VTracer cannot pin anchors or report contours, so either selects the contour tracer.
+            None if self.preserve_corners || self.metadata.is_some() => TraceBackendArg::Contour,
+            None => TraceBackendArg::DEFAULT,
+        }
+    }
+
+    /// Decimal places for the built-in tracers, which always round coordinates.
+    fn builtin_path_precision(&self, default: u32) -> u32 {
+        if self.no_path_precision {
+            FULL_PATH_PRECISION
+        } else {
+            self.path_precision.unwrap_or(default)
+        }
+    }
 }
 
+/// Decimal places written by the built-in tracers under `--no-path-precision`.
+const FULL_PATH_PRECISION: u32 = 6;
+
+#[cfg(feature = "vectorizer-vtracer")]
 impl From<&TraceOptionsArgs> for TraceOptions {
     fn from(args: &TraceOptionsArgs) -> Self {
         let default_opts = TraceOptions::default();
@@ -338,17 +1974,428 @@ impl From<&TraceOptionsArgs> for TraceOptions {
             tracer_max_iterations: args.max_iterations,
             tracer_splice_threshold: args.splice_threshold,
             tracer_path_precision,
+            tracer_max_nodes: args.max_nodes.map(|n| n as usize),
             invert_svg: args.invert_svg,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl From<&TraceOptionsArgs> for CenterlineOptions {
+    fn from(args: &TraceOptionsArgs) -> Self {
+        let default_opts = CenterlineOptions::default();
+        Self {
+            min_length: args.length_threshold,
+            simplify_tolerance: args.simplify_tolerance,
+            stroke_width: args.stroke_width,
+            path_precision: args.builtin_path_precision(default_opts.path_precision),
+            max_nodes: args.max_nodes.map(|n| n as usize),
+            ..default_opts
+        }
+    }
+}
 
-    mod parse_mask_threshold {
-        use super::*;
+impl From<&TraceOptionsArgs> for CollisionOptions {
+    fn from(args: &TraceOptionsArgs) -> Self {
+        let default_opts = CollisionOptions::default();
+        let speckle = args.filter_speckle as f64;
+        Self {
+            min_area: speckle * speckle,
+            max_vertices: args
+                .max_vertices
+                .map_or(default_opts.max_vertices, |n| n as usize),
+            convexity: args.convexity,
+            path_precision: args.builtin_path_precision(default_opts.path_precision),
+            ..default_opts
+        }
+    }
+}
+
+impl From<&TraceOptionsArgs> for ContourOptions {
+    fn from(args: &TraceOptionsArgs) -> Self {
+        let default_opts = ContourOptions::default();
+        let speckle = args.filter_speckle as f64;
+        Self {
+            min_area: speckle * speckle,
+            simplify_tolerance: match args.mode {
+                TracerMode::None => 0.0,
+                _ => args.simplify_tolerance,
+            },
+            smooth: matches!(args.mode, TracerMode::Spline),
+            preserve_corners: args.preserve_corners,
+            corner_threshold: f64::from(args.corner_threshold),
+            path_precision: args.builtin_path_precision(default_opts.path_precision),
+            invert: args.invert_svg,
+            max_nodes: args.max_nodes.map(|n| n as usize),
+            annotate: args.metadata == Some(ContourMetadataArg::Attrs),
+            ..default_opts
+        }
+    }
+}
+
+impl From<&TraceOptionsArgs> for SequenceOptions {
+    fn from(args: &TraceOptionsArgs) -> Self {
+        let default_opts = SequenceOptions::default();
+        let speckle = args.filter_speckle as f64;
+        Self {
+            min_area: speckle * speckle,
+            nodes: args.max_nodes.map_or(default_opts.nodes, |n| n as usize),
+            smooth: matches!(args.mode, TracerMode::Spline),
+            path_precision: args.builtin_path_precision(default_opts.path_precision),
+            ..default_opts
+        }
+    }
+}
+
+/// Formats of the diagnostic logs.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum LogFormatArg {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event, with its span
+    Json,
+}
+
+/// Processing orders of a batch.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum BatchOrderArg {
+    /// As given, directories sorted by name
+    Input,
+    /// Grouped by aspect-ratio bucket, smallest images first within each
+    Size,
+}
+
+/// Progress displays for model downloads and batches.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum ProgressArg {
+    /// Progress bars with speed and time remaining
+    Bar,
+    /// Machine-readable JSON lines, also for each input and its stages
+    Json,
+    None,
+}
+
+/// Inference devices selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum DeviceArg {
+    Cpu,
+    Cuda,
+    Coreml,
+    Directml,
+    Metal,
+    Auto,
+}
+
+impl From<DeviceArg> for Device {
+    /// Convert DeviceArg to bgr::Device.
+    fn from(value: DeviceArg) -> Self {
+        match value {
+            DeviceArg::Cpu => Device::Cpu,
+            DeviceArg::Cuda => Device::Cuda,
+            DeviceArg::Coreml => Device::CoreMl,
+            DeviceArg::Directml => Device::DirectMl,
+            DeviceArg::Metal => Device::Metal,
+            DeviceArg::Auto => Device::Auto,
+        }
+    }
+}
+
+/// Kinds of images for `models advise`.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum UseCaseArg {
+    General,
+    Portrait,
+    Illustration,
+}
+
+impl From<UseCaseArg> for UseCase {
+    /// Convert UseCaseArg to bgr::models::UseCase.
+    fn from(value: UseCaseArg) -> Self {
+        match value {
+            UseCaseArg::General => UseCase::General,
+            UseCaseArg::Portrait => UseCase::Portrait,
+            UseCaseArg::Illustration => UseCase::Illustration,
+        }
+    }
+}
+
+/// What `models advise` favors.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum PriorityArg {
+    Quality,
+    Speed,
+}
+
+impl From<PriorityArg> for Priority {
+    /// Convert PriorityArg to bgr::models::Priority.
+    fn from(value: PriorityArg) -> Self {
+        match value {
+            PriorityArg::Quality => Priority::Quality,
+            PriorityArg::Speed => Priority::Speed,
+        }
+    }
+}
+
+/// Inference backends selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum BackendArg {
+    Ort,
+    Tract,
+    Candle,
+}
+
+impl From<BackendArg> for Backend {
+    /// Convert BackendArg to bgr::Backend.
+    fn from(value: BackendArg) -> Self {
+        match value {
+            BackendArg::Ort => Backend::Ort,
+            BackendArg::Tract => Backend::Tract,
+            BackendArg::Candle => Backend::Candle,
+        }
+    }
+}
+
+/// Model architectures selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum ArchitectureArg {
+    /// ImageNet normalization, first output used as it is
+    Generic,
+    /// Fused first output of U2-Net's side outputs
+    U2net,
+    /// IS-Net normalization with unit deviation
+    Isnet,
+    /// Sigmoid over BiRefNet's logits
+    Birefnet,
+}
+
+impl From<ArchitectureArg> for Architecture {
+    /// Convert ArchitectureArg to bgr::Architecture.
+    fn from(value: ArchitectureArg) -> Self {
+        match value {
+            ArchitectureArg::Generic => Architecture::Generic,
+            ArchitectureArg::U2net => Architecture::U2Net,
+            ArchitectureArg::Isnet => Architecture::IsNet,
+            ArchitectureArg::Birefnet => Architecture::BiRefNet,
+        }
+    }
+}
+
+/// Ways to make soft alpha binary for `--palette`.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum AlphaDitherArg {
+    /// Opaque from 50% alpha: hard edges
+    Threshold,
+    /// An 8×8 Bayer pattern following alpha
+    Ordered,
+    /// Floyd–Steinberg error diffusion of alpha
+    Diffusion,
+}
+
+impl From<AlphaDitherArg> for AlphaDither {
+    fn from(value: AlphaDitherArg) -> Self {
+        match value {
+            AlphaDitherArg::Threshold => AlphaDither::Threshold,
+            AlphaDitherArg::Ordered => AlphaDither::Ordered,
+            AlphaDitherArg::Diffusion => AlphaDither::Diffusion,
+        }
+    }
+}
+
+/// Rendering intents selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum IntentArg {
+    Perceptual,
+    Relative,
+    Saturation,
+    Absolute,
+}
+
+impl From<IntentArg> for RenderingIntent {
+    /// Convert IntentArg to bgr::RenderingIntent.
+    fn from(value: IntentArg) -> Self {
+        match value {
+            IntentArg::Perceptual => RenderingIntent::Perceptual,
+            IntentArg::Relative => RenderingIntent::RelativeColorimetric,
+            IntentArg::Saturation => RenderingIntent::Saturation,
+            IntentArg::Absolute => RenderingIntent::AbsoluteColorimetric,
+        }
+    }
+}
+
+/// A `--target-profile`: a built-in color space or an ICC file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TargetProfile {
+    Srgb,
+    DisplayP3,
+    File(PathBuf),
+}
+
+fn parse_target_profile(value: &str) -> Result<TargetProfile, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "srgb" => Ok(TargetProfile::Srgb),
+        "display-p3" | "p3" => Ok(TargetProfile::DisplayP3),
+        "" => Err("target profile must not be empty".to_string()),
+        _ => Ok(TargetProfile::File(PathBuf::from(value))),
+    }
+}
+
+/// Compositors selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum CompositorArg {
+    /// Composite on the CPU
+    Cpu,
+    /// Composite with compute shaders on the GPU
+    Wgpu,
+}
+
+/// Image formats `cut` can write.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum OutputFormatArg {
+    Png,
+    /// Lossless unless `--quality` is given
+    Webp,
+    /// Lossy, `--quality` 80 unless given
+    Avif,
+    Tiff,
+    /// Opaque, `--quality` 90 unless given; flattened over white without `--bg-color`
+    #[value(alias = "jpg")]
+    Jpeg,
+}
+
+impl From<OutputFormatArg> for OutputFormat {
+    fn from(value: OutputFormatArg) -> Self {
+        match value {
+            OutputFormatArg::Png => OutputFormat::Png,
+            OutputFormatArg::Webp => OutputFormat::WebP,
+            OutputFormatArg::Avif => OutputFormat::Avif,
+            OutputFormatArg::Tiff => OutputFormat::Tiff,
+            OutputFormatArg::Jpeg => OutputFormat::Jpeg,
+        }
+    }
+}
+
+/// Selection files for image editors.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum SelectionArg {
+    /// Photoshop document with the mask as an alpha channel, also read by GIMP
+    Psd,
+    /// 8-bit grayscale BMP clip channel
+    Bmp,
+}
+
+impl From<SelectionArg> for SelectionFormat {
+    /// Convert SelectionArg to bgr::SelectionFormat.
+    fn from(value: SelectionArg) -> Self {
+        match value {
+            SelectionArg::Psd => SelectionFormat::Psd,
+            SelectionArg::Bmp => SelectionFormat::Bmp,
+        }
+    }
+}
+
+/// Coordinate spaces for traced output.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum CoordinateSpaceArg {
+    Pixels,
+    Normalized,
+    Physical,
+}
+
+/// Physical units for traced output.
+#[derive(Clone, Copy, Debug, ValueEnum, PartialEq, Eq)]
+pub enum PhysicalUnitArg {
+    Mm,
+    Cm,
+    In,
+    Pt,
+}
+
+impl From<PhysicalUnitArg> for PhysicalUnit {
+    /// Convert PhysicalUnitArg to bgr::PhysicalUnit.
+    fn from(value: PhysicalUnitArg) -> Self {
+        match value {
+            PhysicalUnitArg::Mm => PhysicalUnit::Millimeters,
+            PhysicalUnitArg::Cm => PhysicalUnit::Centimeters,
+            PhysicalUnitArg::In => PhysicalUnit::Inches,
+            PhysicalUnitArg::Pt => PhysicalUnit::Points,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CoordinateArgs {
+    /// Coordinate space of the SVG paths
+    #[arg(long = "coords", value_enum, default_value_t = CoordinateSpaceArg::Pixels)]
+    pub coords: CoordinateSpaceArg,
+    /// Flip the Y axis to point up (origin bottom-left)
+    #[arg(long = "flip-y")]
+    pub flip_y: bool,
+    /// Physical unit used with `--coords physical`
+    #[arg(long = "units", value_enum, default_value_t = PhysicalUnitArg::Mm)]
+    pub units: PhysicalUnitArg,
+    /// Image resolution used with `--coords physical`
+    #[arg(long = "dpi", default_value_t = 300.0)]
+    pub dpi: f64,
+    /// Extra affine transform `a,b,c,d,e,f` applied last (SVG matrix order)
+    #[arg(
+        long = "transform",
+        value_name = "A,B,C,D,E,F",
+        allow_hyphen_values = true
+    )]
+    pub transform: Option<Affine>,
+}
+
+impl From<&CoordinateArgs> for CoordinateOptions {
+    fn from(args: &CoordinateArgs) -> Self {
+        let space = match args.coords {
+            CoordinateSpaceArg::Pixels => CoordinateSpace::Pixels,
+            CoordinateSpaceArg::Normalized => CoordinateSpace::Normalized,
+            CoordinateSpaceArg::Physical => CoordinateSpace::Physical {
+                unit: args.units.into(),
+                dpi: args.dpi,
+            },
+        };
+        Self {
+            space,
+            flip_y: args.flip_y,
+            transform: args.transform,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_hex_color {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn long_form() {
+                assert_eq!(parse_hex_color("#ff8000").unwrap(), Rgb([255, 128, 0]));
+                assert_eq!(parse_hex_color("00FF7f").unwrap(), Rgb([0, 255, 127]));
+            }
+
+            #[test]
+            fn short_form() {
+                assert_eq!(parse_hex_color("#fff").unwrap(), Rgb([255, 255, 255]));
+                assert_eq!(parse_hex_color("#1a2").unwrap(), Rgb([17, 170, 34]));
+            }
+
+            #[test]
+            fn rejects_invalid() {
+                assert!(parse_hex_color("#ffff").is_err());
+                assert!(parse_hex_color("#gggggg").is_err());
+                assert!(parse_hex_color("").is_err());
+                assert!(parse_hex_color("#ff80é").is_err());
+            }
+        }
+    }
+
+    mod parse_mask_threshold {
+        use super::*;
 
         mod unit {
             use super::*;
@@ -444,12 +2491,102 @@ mod tests {
         }
     }
 
+    mod parse_trim_margin {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn pixels_with_or_without_unit() {
+                assert_eq!(parse_trim_margin("16px").unwrap(), TrimMargin::Pixels(16));
+                assert_eq!(parse_trim_margin("16").unwrap(), TrimMargin::Pixels(16));
+            }
+
+            #[test]
+            fn percentage() {
+                assert_eq!(parse_trim_margin("5%").unwrap(), TrimMargin::Percent(5.0));
+            }
+
+            #[test]
+            fn invalid_rejected() {
+                assert!(parse_trim_margin("-4px").is_err());
+                assert!(parse_trim_margin("120%").is_err());
+                assert!(parse_trim_margin("wide").is_err());
+            }
+        }
+    }
+
+    mod parse_component_area {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn pixel_count() {
+                assert_eq!(
+                    parse_component_area("500").unwrap(),
+                    ComponentArea::Pixels(500)
+                );
+            }
+
+            #[test]
+            fn percentage() {
+                assert_eq!(
+                    parse_component_area("0.5%").unwrap(),
+                    ComponentArea::Percent(0.5)
+                );
+                assert_eq!(
+                    parse_component_area("100%").unwrap(),
+                    ComponentArea::Percent(100.0)
+                );
+            }
+
+            #[test]
+            fn invalid_rejected() {
+                assert!(parse_component_area("-5").is_err());
+                assert!(parse_component_area("1.5").is_err());
+                assert!(parse_component_area("150%").is_err());
+                assert!(parse_component_area("abc%").is_err());
+                assert!(parse_component_area("").is_err());
+            }
+        }
+    }
+
+    mod parse_percent {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn sign_is_optional_and_range_checked() {
+                assert_eq!(parse_percent("85%", "fill", 1.0..=100.0), Ok(85.0));
+                assert_eq!(parse_percent(" 50 ", "fill", 1.0..=100.0), Ok(50.0));
+                let err = parse_percent("0.5%", "fill", 1.0..=100.0).unwrap_err();
+                assert_eq!(err, "fill 0.5% is out of range; expected 1-100%");
+                assert!(parse_percent("half", "fill", 1.0..=100.0).is_err());
+                assert_eq!(parse_cpu_percent("50%"), Ok(50));
+                assert!(parse_cpu_percent("0").is_err());
+            }
+        }
+    }
+
     mod from_implementations {
         use super::*;
 
         mod unit {
             use super::*;
 
+            #[test]
+            fn device_arg_names_match_the_library() {
+                for arg in DeviceArg::value_variants() {
+                    let name = arg.to_possible_value().unwrap();
+                    assert_eq!(Device::from_name(name.get_name()), Some((*arg).into()));
+                }
+            }
+
             #[test]
             fn resample_filter_to_filter_type() {
                 assert!(matches!(
@@ -474,6 +2611,7 @@ mod tests {
                 ));
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn tracer_color_mode_to_color_mode() {
                 assert!(matches!(
@@ -486,6 +2624,7 @@ mod tests {
                 ));
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn tracer_hierarchy_to_hierarchical() {
                 assert!(matches!(
@@ -498,6 +2637,7 @@ mod tests {
                 ));
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn tracer_mode_to_path_simplify_mode() {
                 assert!(matches!(
@@ -524,8 +2664,12 @@ mod tests {
                 blur: None,
                 mask_threshold: 120,
                 binary: BinaryOption::Auto,
+                erode: None,
+                keep_largest: false,
+                min_area: None,
                 dilate: None,
                 fill_holes: false,
+                feather: None,
             }
         }
 
@@ -602,6 +2746,43 @@ mod tests {
                 assert!((opts.dilation_radius - 8.0).abs() < f32::EPSILON);
             }
 
+            #[test]
+            fn auto_with_erode_yields_binary_true() {
+                let args = MaskProcessingArgs {
+                    erode: Some(3.0),
+                    ..default_args()
+                };
+                let opts = MaskProcessingOptions::from(&args);
+                assert!(opts.binary);
+                assert!(opts.erode);
+                assert!((opts.erosion_radius - 3.0).abs() < f32::EPSILON);
+            }
+
+            #[test]
+            fn component_filters_pass_through_without_forcing_binary() {
+                let args = MaskProcessingArgs {
+                    keep_largest: true,
+                    min_area: Some(ComponentArea::Percent(1.0)),
+                    ..default_args()
+                };
+                let opts = MaskProcessingOptions::from(&args);
+                assert!(!opts.binary);
+                assert!(opts.keep_largest);
+                assert_eq!(opts.min_area, Some(ComponentArea::Percent(1.0)));
+            }
+
+            #[test]
+            fn feather_does_not_force_binary() {
+                let args = MaskProcessingArgs {
+                    feather: Some(4.0),
+                    ..default_args()
+                };
+                let opts = MaskProcessingOptions::from(&args);
+                assert!(!opts.binary);
+                assert!(opts.feather);
+                assert!((opts.feather_radius - 4.0).abs() < f32::EPSILON);
+            }
+
             #[test]
             fn threshold_passed_through() {
                 let args = MaskProcessingArgs {
@@ -619,6 +2800,8 @@ mod tests {
 
         fn default_trace_args() -> TraceOptionsArgs {
             TraceOptionsArgs {
+                profile: None,
+                backend: None,
                 color_mode: TracerColorMode::Binary,
                 hierarchy: TracerHierarchy::Stacked,
                 mode: TracerMode::Spline,
@@ -631,13 +2814,21 @@ mod tests {
                 splice_threshold: 45,
                 path_precision: None,
                 no_path_precision: false,
+                max_nodes: None,
                 invert_svg: false,
+                stroke_width: 1.0,
+                simplify_tolerance: 1.0,
+                preserve_corners: false,
+                metadata: None,
+                max_vertices: None,
+                convexity: None,
             }
         }
 
         mod unit {
             use super::*;
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn no_path_precision_clears_default() {
                 let args = TraceOptionsArgs {
@@ -648,6 +2839,7 @@ mod tests {
                 assert!(opts.tracer_path_precision.is_none());
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn path_precision_overrides_default() {
                 let args = TraceOptionsArgs {
@@ -658,6 +2850,7 @@ mod tests {
                 assert_eq!(opts.tracer_path_precision, Some(5));
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn default_path_precision_used() {
                 let args = default_trace_args();
@@ -669,6 +2862,7 @@ mod tests {
                 );
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn invert_svg_passed_through() {
                 let args = TraceOptionsArgs {
@@ -679,6 +2873,7 @@ mod tests {
                 assert!(opts.invert_svg);
             }
 
+            #[cfg(feature = "vectorizer-vtracer")]
             #[test]
             fn enum_fields_converted() {
                 let args = TraceOptionsArgs {
@@ -694,34 +2889,124 @@ mod tests {
             }
 
             #[test]
-            fn conflicting_no_path_precision_and_path_precision() {
-                // clap prevents this via conflicts_with, but test pure function priority
+            fn centerline_options_from_args() {
                 let args = TraceOptionsArgs {
-                    no_path_precision: true,
-                    path_precision: Some(5),
+                    mode: TracerMode::Centerline,
+                    length_threshold: 10.0,
+                    stroke_width: 2.5,
+                    max_nodes: Some(64),
                     ..default_trace_args()
                 };
-                let opts = TraceOptions::from(&args);
-                // no_path_precision takes priority
-                assert!(opts.tracer_path_precision.is_none());
+                assert!(args.centerline());
+                let opts = CenterlineOptions::from(&args);
+                assert_eq!(opts.min_length, 10.0);
+                assert_eq!(opts.stroke_width, 2.5);
+                assert_eq!(opts.max_nodes, Some(64));
+                assert_eq!(opts.path_precision, 2);
             }
-        }
-    }
-
-    mod clap_integration {
-        use super::*;
-        use clap::Parser;
-        use std::path::Path;
 
-        macro_rules! parse_cmd {
-            ($args:expr, $variant:ident) => {{
-                let cli = Cli::try_parse_from($args).unwrap();
-                match cli.command {
-                    Commands::$variant(cmd) => cmd,
-                    _ => panic!("expected {} command", stringify!($variant)),
-                }
-            }};
-        }
+            #[test]
+            fn preserve_corners_selects_contour_backend() {
+                let args = TraceOptionsArgs {
+                    preserve_corners: true,
+                    ..default_trace_args()
+                };
+                assert_eq!(args.backend(), TraceBackendArg::Contour);
+            }
+
+            #[test]
+            fn metadata_selects_contour_backend() {
+                let args = TraceOptionsArgs {
+                    metadata: Some(ContourMetadataArg::Json),
+                    ..default_trace_args()
+                };
+                assert_eq!(args.backend(), TraceBackendArg::Contour);
+                assert!(!ContourOptions::from(&args).annotate);
+            }
+
+            #[test]
+            fn metadata_attrs_annotates_paths() {
+                let args = TraceOptionsArgs {
+                    metadata: Some(ContourMetadataArg::Attrs),
+                    ..default_trace_args()
+                };
+                assert!(ContourOptions::from(&args).annotate);
+            }
+
+            #[test]
+            fn polygon_mode_disables_smoothing() {
+                let args = TraceOptionsArgs {
+                    mode: TracerMode::Polygon,
+                    ..default_trace_args()
+                };
+                let opts = ContourOptions::from(&args);
+                assert!(!opts.smooth);
+                assert_eq!(opts.simplify_tolerance, 1.0);
+            }
+
+            #[test]
+            fn preserve_corners_contour_options() {
+                let args = TraceOptionsArgs {
+                    preserve_corners: true,
+                    corner_threshold: 45,
+                    ..default_trace_args()
+                };
+                let opts = ContourOptions::from(&args);
+                assert!(opts.preserve_corners);
+                assert!(opts.smooth);
+                assert_eq!(opts.corner_threshold, 45.0);
+                assert_eq!(opts.min_area, 16.0);
+            }
+
+            #[test]
+            fn no_path_precision_uses_full_precision_for_builtin_tracers() {
+                let args = TraceOptionsArgs {
+                    no_path_precision: true,
+                    ..default_trace_args()
+                };
+                assert_eq!(CenterlineOptions::from(&args).path_precision, 6);
+            }
+
+            #[cfg(feature = "vectorizer-vtracer")]
+            #[test]
+            fn max_nodes_passed_through() {
+                let args = TraceOptionsArgs {
+                    max_nodes: Some(500),
+                    ..default_trace_args()
+                };
+                let opts = TraceOptions::from(&args);
+                assert_eq!(opts.tracer_max_nodes, Some(500));
+            }
+
+            #[cfg(feature = "vectorizer-vtracer")]
+            #[test]
+            fn conflicting_no_path_precision_and_path_precision() {
+                // clap prevents this via conflicts_with, but test pure function priority
+                let args = TraceOptionsArgs {
+                    no_path_precision: true,
+                    path_precision: Some(5),
+                    ..default_trace_args()
+                };
+                let opts = TraceOptions::from(&args);
+                // no_path_precision takes priority
+                assert!(opts.tracer_path_precision.is_none());
+            }
+        }
+    }
+
+    mod clap_integration {
+        use super::*;
+        use clap::Parser;
+
+        macro_rules! parse_cmd {
+            ($args:expr, $variant:ident) => {{
+                let cli = Cli::try_parse_from($args).unwrap();
+                match cli.command {
+                    Commands::$variant(cmd) => cmd,
+                    _ => panic!("expected {} command", stringify!($variant)),
+                }
+            }};
+        }
 
         // Option<Option<PathBuf>> three-state semantics
         mod optional_path_semantics {
@@ -753,6 +3038,42 @@ mod tests {
                     );
                 }
 
+                #[test]
+                fn output_layers_flag_only_or_with_path() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--output-layers"], Cut);
+                    assert!(matches!(cmd.output_layers, Some(None)));
+                    let cmd = parse_cmd!(
+                        ["outline", "cut", "in.png", "--output-layers", "shot.tiff"],
+                        Cut
+                    );
+                    assert!(
+                        matches!(&cmd.output_layers, Some(Some(p)) if p == Path::new("shot.tiff"))
+                    );
+                }
+
+                #[test]
+                fn subject_layers_flag_only_or_with_path() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--subject-layers"], Cut);
+                    assert!(matches!(cmd.subject_layers, Some(None)));
+                    assert_eq!(cmd.min_subject_area, ComponentArea::Percent(0.5));
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--subject-layers",
+                            "doc.tif",
+                            "--min-subject-area",
+                            "500"
+                        ],
+                        Cut
+                    );
+                    assert!(
+                        matches!(&cmd.subject_layers, Some(Some(p)) if p == Path::new("doc.tif"))
+                    );
+                    assert_eq!(cmd.min_subject_area, ComponentArea::Pixels(500));
+                }
+
                 #[test]
                 fn export_mask_absent_is_none() {
                     let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
@@ -880,6 +3201,36 @@ mod tests {
                     assert_eq!(cmd.mask_processing.mask_threshold, 200);
                 }
 
+                #[test]
+                fn threshold_alias_enables_binary() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--threshold", "0.5"], Cut);
+                    assert_eq!(cmd.mask_processing.mask_threshold, 128);
+                    assert_eq!(cmd.mask_processing.binary, BinaryOption::Enabled);
+                }
+
+                #[test]
+                fn threshold_defaults_leave_binary_auto() {
+                    let cmd = parse_cmd!(["outline", "mask", "in.png"], Mask);
+                    assert_eq!(cmd.mask_processing.binary, BinaryOption::Auto);
+                }
+
+                #[test]
+                fn explicit_binary_overrides_threshold() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "mask",
+                            "in.png",
+                            "--threshold",
+                            "200",
+                            "--binary",
+                            "disabled"
+                        ],
+                        Mask
+                    );
+                    assert_eq!(cmd.mask_processing.binary, BinaryOption::Disabled);
+                }
+
                 #[test]
                 fn float_threshold_scaled() {
                     let cmd = parse_cmd!(
@@ -991,5 +3342,1597 @@ mod tests {
                 }
             }
         }
+
+        // Global flags shared by every subcommand
+        mod global_flags {
+            use super::*;
+
+            mod unit {
+                use super::*;
+
+                #[test]
+                fn low_memory_defaults_off() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert!(!cli.global.low_memory);
+                }
+
+                #[test]
+                fn jobs_defaults_to_one() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.jobs, 1);
+                    assert!(cli.global.out_dir.is_none());
+                }
+
+                #[test]
+                fn jobs_and_out_dir() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "a.png",
+                        "b.png",
+                        "-j",
+                        "4",
+                        "--out-dir",
+                        "out",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.jobs, 4);
+                    assert_eq!(cli.global.out_dir.as_deref(), Some(Path::new("out")));
+                }
+
+                #[test]
+                fn only_url_copies_move_to_the_current_directory() {
+                    let mut cli = Cli::try_parse_from(["outline", "cut", "a.png"]).unwrap();
+                    cli.global.url_cache = Some(PathBuf::from("data/url-cache"));
+                    let copy = Path::new("data/url-cache/0123/shoe-01234567.jpg");
+                    assert_eq!(cli.global.out_dir_for(copy), Some(Path::new(".")));
+                    assert_eq!(cli.global.out_dir_for(Path::new("photos/a.png")), None);
+                }
+
+                #[test]
+                fn intent_and_target_profile() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.intent, IntentArg::Relative);
+                    assert_eq!(cli.global.target_profile, TargetProfile::Srgb);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--intent",
+                        "perceptual",
+                        "--target-profile",
+                        "display-p3",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.intent, IntentArg::Perceptual);
+                    assert_eq!(cli.global.target_profile, TargetProfile::DisplayP3);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--target-profile",
+                        "profiles/AdobeRGB1998.icc",
+                    ])
+                    .unwrap();
+                    assert_eq!(
+                        cli.global.target_profile,
+                        TargetProfile::File(PathBuf::from("profiles/AdobeRGB1998.icc"))
+                    );
+                }
+
+                #[test]
+                fn cpu_share_from_max_cpu_or_nice() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.cpu_percent(), None);
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png", "--max-cpu", "25%"])
+                        .unwrap();
+                    assert_eq!(cli.global.cpu_percent(), Some(25));
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png", "--nice"]).unwrap();
+                    assert_eq!(cli.global.cpu_percent(), Some(50));
+                    for bad in ["0%", "101", "half"] {
+                        assert!(
+                            Cli::try_parse_from(["outline", "cut", "in.png", "--max-cpu", bad])
+                                .is_err()
+                        );
+                    }
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--nice",
+                            "--max-cpu",
+                            "30"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn multiple_inputs_collected() {
+                    let cmd = parse_cmd!(["outline", "mask", "a.png", "b.png", "dir"], Mask);
+                    assert_eq!(cmd.inputs.len(), 3);
+                }
+
+                #[test]
+                fn input_required() {
+                    assert!(Cli::try_parse_from(["outline", "trace"]).is_err());
+                }
+
+                #[test]
+                fn verify_defaults() {
+                    let cmd = parse_cmd!(
+                        ["outline", "verify", "out", "--golden", "golden.json"],
+                        Verify
+                    );
+                    assert_eq!(cmd.dir, Path::new("out"));
+                    assert_eq!(cmd.golden, Path::new("golden.json"));
+                    assert!(!cmd.update);
+                    assert_eq!(cmd.max_hash_distance, 4);
+                }
+
+                #[test]
+                fn verify_requires_golden() {
+                    assert!(Cli::try_parse_from(["outline", "verify", "out"]).is_err());
+                }
+
+                #[test]
+                fn report_is_global() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.report, None);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "--report",
+                        "report.json",
+                        "trace",
+                        "in.png",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.report, Some(PathBuf::from("report.json")));
+                }
+
+                #[test]
+                fn batch_hooks_are_global() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!((cli.global.before, cli.global.after), (None, None));
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "--before",
+                        "tracker start",
+                        "cut",
+                        "in.png",
+                        "--after",
+                        "tracker done",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.before.as_deref(), Some("tracker start"));
+                    assert_eq!(cli.global.after.as_deref(), Some("tracker done"));
+                }
+
+                #[test]
+                fn batch_order_defaults_to_input() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.order, BatchOrderArg::Input);
+                    let cli =
+                        Cli::try_parse_from(["outline", "mask", "photos/", "--order", "size"])
+                            .unwrap();
+                    assert_eq!(cli.global.order, BatchOrderArg::Size);
+                }
+
+                #[test]
+                fn debug_bundle_is_global() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.debug_bundle, None);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "--debug-bundle",
+                        "bugs",
+                        "trace",
+                        "in.png",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.debug_bundle, Some(PathBuf::from("bugs")));
+                }
+
+                #[test]
+                fn project_profile_is_global() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--project-profile",
+                        "ecommerce",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.project_profile.as_deref(), Some("ecommerce"));
+                }
+
+                #[test]
+                fn project_profile_leaves_trace_profiles_alone() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "trace",
+                        "in.png",
+                        "--profile",
+                        "sticker",
+                        "--project-profile",
+                        "print",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.project_profile.as_deref(), Some("print"));
+                }
+
+                #[test]
+                #[cfg(unix)]
+                fn via_daemon_takes_an_optional_socket() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.via_daemon, None);
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "--via-daemon", "in.png"]).unwrap();
+                    assert_eq!(cli.global.via_daemon, Some(None));
+                    let cmd = parse_cmd!(["outline", "cut", "--via-daemon", "in.png"], Cut);
+                    assert_eq!(cmd.inputs, vec![PathBuf::from("in.png")]);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "mask",
+                        "in.png",
+                        "--via-daemon=/tmp/b.sock",
+                    ])
+                    .unwrap();
+                    assert_eq!(
+                        cli.global.via_daemon,
+                        Some(Some(PathBuf::from("/tmp/b.sock")))
+                    );
+                }
+
+                #[test]
+                #[cfg(unix)]
+                fn daemon_socket_option() {
+                    let cmd = parse_cmd!(["outline", "daemon"], Daemon);
+                    assert!(cmd.socket.is_none());
+                    let cmd =
+                        parse_cmd!(["outline", "daemon", "--socket", "/run/bgr.sock"], Daemon);
+                    assert_eq!(cmd.socket, Some(PathBuf::from("/run/bgr.sock")));
+                }
+
+                #[test]
+                fn watch_defaults_and_overrides() {
+                    let cmd = parse_cmd!(["outline", "watch", "inbox", "-o", "out"], Watch);
+                    assert_eq!(cmd.dir, PathBuf::from("inbox"));
+                    assert_eq!(cmd.output, PathBuf::from("out"));
+                    assert_eq!(cmd.debounce, 500);
+                    assert!(cmd.journal.is_none());
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "watch",
+                            "inbox",
+                            "--output",
+                            "out",
+                            "--debounce",
+                            "2000",
+                            "--journal",
+                            "done.jsonl",
+                            "--bg-color",
+                            "#000"
+                        ],
+                        Watch
+                    );
+                    assert_eq!(cmd.debounce, 2000);
+                    assert_eq!(cmd.journal, Some(PathBuf::from("done.jsonl")));
+                    assert_eq!(cmd.bg_color, Some(Rgb([0, 0, 0])));
+                }
+
+                #[test]
+                fn watch_requires_output() {
+                    assert!(Cli::try_parse_from(["outline", "watch", "inbox"]).is_err());
+                }
+
+                #[test]
+                fn examples_filter_by_known_command() {
+                    let cmd = parse_cmd!(["outline", "examples"], Examples);
+                    assert_eq!(cmd.command, None);
+                    let cmd = parse_cmd!(["outline", "examples", "trace"], Examples);
+                    assert_eq!(cmd.command.as_deref(), Some("trace"));
+                    assert!(Cli::try_parse_from(["outline", "examples", "nope"]).is_err());
+                }
+
+                #[test]
+                #[cfg(feature = "video")]
+                fn video_codec_and_background() {
+                    let cmd = parse_cmd!(["outline", "video", "in.mp4", "-o", "out.webm"], Video);
+                    assert_eq!(cmd.output, PathBuf::from("out.webm"));
+                    assert_eq!(cmd.codec, None);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "video",
+                            "in.mp4",
+                            "-o",
+                            "out.mp4",
+                            "--codec",
+                            "h264",
+                            "--bg-color",
+                            "#00ff00"
+                        ],
+                        Video
+                    );
+                    assert_eq!(cmd.codec, Some(VideoCodecArg::H264));
+                    assert_eq!(cmd.bg_color, Some(Rgb([0, 255, 0])));
+                }
+
+                #[test]
+                #[cfg(feature = "video")]
+                fn video_resume() {
+                    let cmd = parse_cmd!(["outline", "video", "in.mp4", "-o", "out.webm"], Video);
+                    assert!(!cmd.resume);
+                    let cmd = parse_cmd!(
+                        ["outline", "video", "in.mp4", "-o", "out.webm", "--resume"],
+                        Video
+                    );
+                    assert!(cmd.resume);
+                }
+
+                #[test]
+                #[cfg(feature = "video")]
+                fn video_temporal_smooth() {
+                    let cmd = parse_cmd!(["outline", "video", "in.mp4", "-o", "out.webm"], Video);
+                    assert_eq!(cmd.temporal_smooth, None);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "video",
+                            "in.mp4",
+                            "-o",
+                            "out.webm",
+                            "--temporal-smooth"
+                        ],
+                        Video
+                    );
+                    assert_eq!(cmd.temporal_smooth, Some(0.6));
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "video",
+                            "in.mp4",
+                            "-o",
+                            "out.webm",
+                            "--temporal-smooth",
+                            "0.8"
+                        ],
+                        Video
+                    );
+                    assert_eq!(cmd.temporal_smooth, Some(0.8));
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "video",
+                            "in.mp4",
+                            "-o",
+                            "out.webm",
+                            "--temporal-smooth",
+                            "1.0"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                #[cfg(feature = "server")]
+                fn serve_defaults_and_overrides() {
+                    let cmd = parse_cmd!(["outline", "serve"], Serve);
+                    assert_eq!(cmd.bind.to_string(), "127.0.0.1:8080");
+                    assert!(!cmd.cors);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "serve",
+                            "--bind",
+                            "0.0.0.0:9000",
+                            "--cors",
+                            "--bg-color",
+                            "#fff",
+                            "--fill-holes"
+                        ],
+                        Serve
+                    );
+                    assert_eq!(cmd.bind.port(), 9000);
+                    assert!(cmd.cors);
+                    assert_eq!(cmd.bg_color, Some(Rgb([255, 255, 255])));
+                    assert!(cmd.mask_processing.fill_holes);
+                }
+
+                #[test]
+                #[cfg(feature = "sandboxed-decode")]
+                fn sandbox_decode_flag_and_worker_command() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png", "--sandbox-decode"])
+                        .unwrap();
+                    assert!(cli.global.sandbox_decode);
+                    let cli = Cli::try_parse_from(["outline", bgr::DECODE_WORKER_COMMAND]).unwrap();
+                    assert!(matches!(cli.command, Commands::DecodeWorker));
+                    assert!(!cli.command.needs_model());
+                }
+
+                #[test]
+                fn auto_orient_is_on_unless_disabled() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.jpg"]).unwrap();
+                    assert!(!cli.global.no_auto_orient);
+                    let cli =
+                        Cli::try_parse_from(["outline", "mask", "in.jpg", "--no-auto-orient"])
+                            .unwrap();
+                    assert!(cli.global.no_auto_orient);
+                }
+
+                #[test]
+                fn verbosity_flags() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.verbosity(), Verbosity::Normal);
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png", "-q"]).unwrap();
+                    assert_eq!(cli.global.verbosity(), Verbosity::Quiet);
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png", "-vv"]).unwrap();
+                    assert_eq!(cli.global.verbose, 2);
+                    assert_eq!(cli.global.verbosity(), Verbosity::Verbose);
+                }
+
+                #[test]
+                fn log_level_follows_verbosity() {
+                    let level = |args: &[&str]| {
+                        let cli =
+                            Cli::try_parse_from(["outline", "cut", "in.png"].iter().chain(args))
+                                .unwrap();
+                        cli.global.log_level()
+                    };
+                    assert_eq!(level(&[]), LevelFilter::WARN);
+                    assert_eq!(level(&["-q"]), LevelFilter::ERROR);
+                    assert_eq!(level(&["-v"]), LevelFilter::INFO);
+                    assert_eq!(level(&["-vv"]), LevelFilter::DEBUG);
+                    assert_eq!(level(&["-vvvv"]), LevelFilter::TRACE);
+                    let cli =
+                        Cli::try_parse_from(["outline", "--log-format", "json", "cut", "in.png"])
+                            .unwrap();
+                    assert_eq!(cli.global.log_format, LogFormatArg::Json);
+                }
+
+                #[test]
+                fn progress_format() {
+                    let cli = Cli::try_parse_from(["outline", "models", "list"]).unwrap();
+                    assert_eq!(cli.global.progress_format(), ProgressArg::Bar);
+                    let cli = Cli::try_parse_from(["outline", "-q", "models", "list"]).unwrap();
+                    assert_eq!(cli.global.progress_format(), ProgressArg::None);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "models",
+                        "pull",
+                        "--all",
+                        "-q",
+                        "--progress=json",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.progress_format(), ProgressArg::Json);
+                }
+
+                #[test]
+                fn quiet_conflicts_with_verbose() {
+                    assert!(Cli::try_parse_from(["outline", "cut", "in.png", "-q", "-v"]).is_err());
+                }
+
+                #[test]
+                fn offline_and_models_dir() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--offline",
+                        "--models-dir",
+                        "models",
+                    ])
+                    .unwrap();
+                    assert!(cli.global.offline);
+                    assert_eq!(cli.global.models_dir.as_deref(), Some(Path::new("models")));
+                }
+
+                #[test]
+                fn fetch_timeout_defaults_to_a_minute() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "https://a.test/b.jpg"]).unwrap();
+                    assert_eq!(cli.global.fetch_timeout, 60);
+                    let argv = [
+                        "outline",
+                        "trace",
+                        "https://a.test/b.jpg",
+                        "--fetch-timeout",
+                        "0",
+                    ];
+                    assert_eq!(Cli::try_parse_from(argv).unwrap().global.fetch_timeout, 0);
+                }
+
+                #[test]
+                fn models_pull_requires_preset_or_all() {
+                    assert!(Cli::try_parse_from(["outline", "models", "pull"]).is_err());
+                    let cli = Cli::try_parse_from(["outline", "models", "pull", "--all"]).unwrap();
+                    assert!(matches!(
+                        cli.command,
+                        Commands::Models(ModelsCommand {
+                            action: ModelsAction::Pull { all: true, .. }
+                        })
+                    ));
+                }
+
+                #[test]
+                #[cfg(feature = "test-utils")]
+                fn fixtures_generate_takes_model() {
+                    assert!(Cli::try_parse_from(["outline", "fixtures", "generate"]).is_err());
+                    let cli =
+                        Cli::try_parse_from(["outline", "fixtures", "generate", "my-model.onnx"])
+                            .unwrap();
+                    assert!(!cli.command.needs_model());
+                    assert!(matches!(
+                        cli.command,
+                        Commands::Fixtures(FixturesCommand {
+                            action: FixturesAction::Generate { ref model }
+                        }) if model == "my-model.onnx"
+                    ));
+                }
+
+                #[test]
+                fn models_rm_takes_preset() {
+                    let cli = Cli::try_parse_from(["outline", "models", "rm", "isnet"]).unwrap();
+                    assert!(matches!(
+                        cli.command,
+                        Commands::Models(ModelsCommand {
+                            action: ModelsAction::Rm { ref preset }
+                        }) if preset == "isnet"
+                    ));
+                }
+
+                #[test]
+                fn models_list_detailed_and_advise() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "models", "list", "--detailed"]).unwrap();
+                    assert!(matches!(
+                        cli.command,
+                        Commands::Models(ModelsCommand {
+                            action: ModelsAction::List { detailed: true }
+                        })
+                    ));
+                    let cli = Cli::try_parse_from([
+                        "outline", "models", "advise", "--for", "portrait", "--prefer", "speed",
+                    ])
+                    .unwrap();
+                    assert!(matches!(
+                        cli.command,
+                        Commands::Models(ModelsCommand {
+                            action: ModelsAction::Advise {
+                                use_case: UseCaseArg::Portrait,
+                                prefer: PriorityArg::Speed
+                            }
+                        })
+                    ));
+                }
+
+                #[test]
+                fn models_does_not_need_model() {
+                    let cli = Cli::try_parse_from(["outline", "models", "list"]).unwrap();
+                    assert!(!cli.command.needs_model());
+                }
+
+                #[test]
+                fn trace_coordinate_flags() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "trace",
+                            "in.png",
+                            "--coords",
+                            "physical",
+                            "--units",
+                            "in",
+                            "--dpi",
+                            "150",
+                            "--flip-y",
+                            "--transform",
+                            "-1,0,0,1,10,0",
+                        ],
+                        Trace
+                    );
+                    let opts = CoordinateOptions::from(&cmd.coordinates);
+                    assert!(opts.flip_y);
+                    assert_eq!(
+                        opts.space,
+                        CoordinateSpace::Physical {
+                            unit: PhysicalUnit::Inches,
+                            dpi: 150.0
+                        }
+                    );
+                    assert_eq!(opts.transform.map(|t| t.a), Some(-1.0));
+                }
+
+                #[test]
+                fn cut_bg_color() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--bg-color", "#fff"], Cut);
+                    assert_eq!(cmd.bg_color, Some(Rgb([255, 255, 255])));
+                }
+
+                #[test]
+                fn default_input_limits_match_library() {
+                    let cli = Cli::try_parse_from(["outline", "mask", "in.png"]).unwrap();
+                    assert_eq!(
+                        InputLimits::from(&cli.global.limits),
+                        InputLimits::default()
+                    );
+                }
+
+                #[test]
+                fn zero_disables_input_limit() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--max-dimension",
+                        "0",
+                        "--max-file-size",
+                        "8",
+                    ])
+                    .unwrap();
+                    let limits = InputLimits::from(&cli.global.limits);
+                    assert_eq!(limits.max_dimension, None);
+                    assert_eq!(limits.max_file_bytes, Some(8 << 20));
+                }
+
+                #[test]
+                fn refine_matting_on_mask_and_cut() {
+                    let mask =
+                        parse_cmd!(["outline", "mask", "in.png", "--refine", "matting"], Mask);
+                    assert_eq!(mask.refine, Some(RefineArg::Matting));
+                    let cut = parse_cmd!(["outline", "cut", "in.png", "--refine", "matting"], Cut);
+                    assert_eq!(cut.refine, Some(RefineArg::Matting));
+                }
+
+                #[test]
+                fn cut_palette_colors() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
+                    assert_eq!(cmd.palette, None);
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--palette"], Cut);
+                    assert_eq!(cmd.palette, Some(256));
+                    let cmd = parse_cmd!(
+                        ["outline", "cut", "in.png", "--palette", "16", "--dither"],
+                        Cut
+                    );
+                    assert_eq!(cmd.palette, Some(16));
+                    assert!(cmd.dither);
+                    assert!(
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--palette", "1"])
+                            .is_err()
+                    );
+                    assert!(Cli::try_parse_from(["outline", "cut", "in.png", "--dither"]).is_err());
+                }
+
+                #[test]
+                fn cut_alpha_dither_needs_a_palette() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--palette"], Cut);
+                    assert_eq!(cmd.alpha_dither, AlphaDitherArg::Threshold);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--palette",
+                            "--alpha-dither",
+                            "ordered"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(cmd.alpha_dither, AlphaDitherArg::Ordered);
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--alpha-dither",
+                            "diffusion"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn emit_lists_artifacts() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
+                    assert!(cmd.emits(EmitArg::Cut));
+                    assert!(!cmd.emits(EmitArg::Svg));
+                    let cmd =
+                        parse_cmd!(["outline", "cut", "in.png", "--emit", "mask,svg,json"], Cut);
+                    assert_eq!(cmd.emit, [EmitArg::Mask, EmitArg::Svg, EmitArg::Json]);
+                    assert!(!cmd.emits(EmitArg::Cut));
+                    assert!(
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--emit", "gif"]).is_err()
+                    );
+                }
+
+                #[test]
+                fn margin_requires_trim() {
+                    let cmd = parse_cmd!(
+                        ["outline", "cut", "in.png", "--trim", "--margin", "5%"],
+                        Cut
+                    );
+                    assert!(cmd.trim);
+                    assert_eq!(cmd.margin, Some(TrimMargin::Percent(5.0)));
+                    assert!(
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--margin", "8px"])
+                            .is_err()
+                    );
+                }
+
+                #[test]
+                fn canvas_anchor_and_fill() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--canvas",
+                            "2000x1500",
+                            "--anchor",
+                            "bottom",
+                            "--fill",
+                            "90%"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(cmd.canvas, Some((2000, 1500)));
+                    assert_eq!(cmd.anchor, Anchor::Bottom);
+                    assert_eq!(cmd.fill, 0.9);
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--canvas", "800x800"], Cut);
+                    assert_eq!(cmd.fill, 0.85);
+                    for bad in [
+                        ["--canvas", "800"],
+                        ["--canvas", "0x10"],
+                        ["--anchor", "top"],
+                    ] {
+                        let mut args = vec!["outline", "cut", "in.png"];
+                        args.extend(bad);
+                        assert!(Cli::try_parse_from(args).is_err());
+                    }
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline", "cut", "in.png", "--canvas", "9x9", "--trim"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn defringe_flag() {
+                    assert!(!parse_cmd!(["outline", "cut", "in.png"], Cut).defringe);
+                    assert!(parse_cmd!(["outline", "cut", "in.png", "--defringe"], Cut).defringe);
+                }
+
+                #[test]
+                fn outline_width_and_color() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--outline", "12"], Cut);
+                    assert_eq!(cmd.outline, Some(12.0));
+                    assert_eq!(cmd.outline_color, Rgb([255, 255, 255]));
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--outline",
+                            "6",
+                            "--outline-color",
+                            "#ff0080"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(cmd.outline_color, Rgb([0xff, 0x00, 0x80]));
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--outline-color",
+                            "#000"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn shadow_controls() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--shadow"], Cut);
+                    assert!(cmd.shadow);
+                    assert_eq!(cmd.shadow_offset, (8, 12));
+                    assert_eq!(cmd.shadow_opacity, 0.5);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--shadow",
+                            "--shadow-offset",
+                            "-4,20",
+                            "--shadow-blur",
+                            "6",
+                            "--shadow-opacity",
+                            "30%",
+                            "--shadow-color",
+                            "#336"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(cmd.shadow_offset, (-4, 20));
+                    assert_eq!(cmd.shadow_blur, 6.0);
+                    assert_eq!(cmd.shadow_opacity, 0.3);
+                    assert_eq!(cmd.shadow_color, Rgb([0x33, 0x33, 0x66]));
+                    assert!(
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--shadow-blur", "4"])
+                            .is_err()
+                    );
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--shadow",
+                            "--shadow-blur=-2"
+                        ])
+                        .is_err()
+                    );
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--shadow",
+                            "--shadow-opacity",
+                            "150%"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn compositor_defaults_to_cpu() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
+                    assert_eq!(cmd.compositor, CompositorArg::Cpu);
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--compositor", "wgpu"], Cut);
+                    assert_eq!(cmd.compositor, CompositorArg::Wgpu);
+                    assert!(!cmd.linear_light);
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--linear-light"], Cut);
+                    assert!(cmd.linear_light);
+                }
+
+                #[test]
+                fn json_sidecar_flag() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
+                    assert!(!cmd.json_sidecar);
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--json-sidecar"], Cut);
+                    assert!(cmd.json_sidecar);
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--json-sidecar"], Mask);
+                    assert!(cmd.json_sidecar);
+                }
+
+                #[test]
+                fn draft_and_final_passes() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--draft"], Cut);
+                    assert!(cmd.draft);
+                    assert!(Commands::Cut(cmd).draft());
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--final"], Cut);
+                    assert_eq!(cmd.final_pass, Some(None));
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--final", "d.png"], Cut);
+                    assert_eq!(cmd.final_pass, Some(Some(PathBuf::from("d.png"))));
+                    assert!(
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--draft", "--final"])
+                            .is_err()
+                    );
+                }
+
+                #[test]
+                fn mask_selection_formats() {
+                    let cmd = parse_cmd!(["outline", "mask", "in.png"], Mask);
+                    assert_eq!(cmd.selection, None);
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--selection", "psd"], Mask);
+                    assert_eq!(cmd.selection, Some(SelectionArg::Psd));
+                    assert_eq!(
+                        SelectionFormat::from(SelectionArg::Bmp),
+                        SelectionFormat::Bmp
+                    );
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--selection", "bmp"], Mask);
+                    assert_eq!(cmd.selection, Some(SelectionArg::Bmp));
+                }
+
+                #[test]
+                fn supplied_masks_skip_the_model() {
+                    let cut = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--mask",
+                        "in-mask.png",
+                        "--mask-resize",
+                        "reject",
+                    ])
+                    .unwrap();
+                    assert!(!cut.command.needs_model());
+                    let Commands::Cut(cmd) = cut.command else {
+                        panic!("expected Cut command");
+                    };
+                    assert_eq!(cmd.mask.as_deref(), Some(Path::new("in-mask.png")));
+                    assert_eq!(
+                        MaskLoadOptions::from(&cmd.mask_input).resize,
+                        MaskResize::Reject
+                    );
+
+                    let trace = Cli::try_parse_from([
+                        "outline",
+                        "trace",
+                        "logo.png",
+                        "--from-mask",
+                        "--mask-binarize",
+                        "never",
+                    ])
+                    .unwrap();
+                    assert!(!trace.command.needs_model());
+                    let mask =
+                        Cli::try_parse_from(["outline", "mask", "in.png", "--from-mask"]).unwrap();
+                    assert!(!mask.command.needs_model());
+                    let Commands::Trace(cmd) = trace.command else {
+                        panic!("expected Trace command");
+                    };
+                    assert_eq!(cmd.mask_input.binarize, MaskBinarizeArg::Never);
+                }
+
+                #[test]
+                fn mask_input_defaults() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.png"], Cut);
+                    assert_eq!(
+                        MaskLoadOptions::from(&cmd.mask_input),
+                        MaskLoadOptions::default()
+                    );
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert!(cli.command.needs_model());
+                }
+
+                #[test]
+                fn from_mask_conflicts_with_refine() {
+                    let result = Cli::try_parse_from([
+                        "outline",
+                        "mask",
+                        "in.png",
+                        "--from-mask",
+                        "--refine",
+                        "matting",
+                    ]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn bgrm_pipes_from_mask_to_trace() {
+                    let cli = Cli::try_parse_from([
+                        "outline", "mask", "in.png", "--format", "bgrm", "-o", "-",
+                    ])
+                    .unwrap();
+                    assert!(cli.command.needs_model());
+                    let Commands::Mask(cmd) = cli.command else {
+                        panic!("expected Mask command");
+                    };
+                    assert_eq!(cmd.format, MaskFormatArg::Bgrm);
+                    assert_eq!(cmd.output.as_deref(), Some(Path::new("-")));
+
+                    let trace =
+                        Cli::try_parse_from(["outline", "trace", "-", "--from", "bgrm"]).unwrap();
+                    assert!(!trace.command.needs_model());
+                    let Commands::Trace(cmd) = trace.command else {
+                        panic!("expected Trace command");
+                    };
+                    assert_eq!(cmd.from, Some(MatteStreamArg::Bgrm));
+
+                    let result = Cli::try_parse_from([
+                        "outline",
+                        "mask",
+                        "in.png",
+                        "--format",
+                        "bgrm",
+                        "--selection",
+                        "psd",
+                    ]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn mask_depth() {
+                    let cmd = parse_cmd!(["outline", "mask", "in.png"], Mask);
+                    assert_eq!(cmd.depth, None);
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--depth", "16"], Mask);
+                    assert_eq!(cmd.depth, Some(MatteDepthArg::Sixteen));
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--depth", "float"], Mask);
+                    assert_eq!(cmd.depth.map(MatteDepth::from), Some(MatteDepth::Float));
+
+                    for conflicting in [["--refine", "matting"], ["--selection", "psd"]] {
+                        let mut args = vec!["outline", "mask", "in.png", "--depth", "16"];
+                        args.extend(conflicting);
+                        assert!(Cli::try_parse_from(args).is_err());
+                    }
+                    let result =
+                        Cli::try_parse_from(["outline", "mask", "in.png", "--depth", "32"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn mask_storage() {
+                    let argv = [
+                        "outline",
+                        "mask",
+                        "in/",
+                        "--storage",
+                        "auto",
+                        "--max-error",
+                        "0.5",
+                    ];
+                    let cmd = parse_cmd!(argv, Mask);
+                    assert_eq!(cmd.storage.and_then(MatteStorageArg::format), None);
+                    assert_eq!((cmd.storage_sample, cmd.max_error), (16, 0.5));
+                    let cmd = parse_cmd!(["outline", "mask", "in.png", "--storage", "rle"], Mask);
+                    assert_eq!(
+                        cmd.storage.and_then(MatteStorageArg::format),
+                        Some(StorageFormat::Rle)
+                    );
+
+                    for conflicting in [["--depth", "16"], ["--format", "bgrm"]] {
+                        let mut args = vec!["outline", "mask", "in.png", "--storage", "webp"];
+                        args.extend(conflicting);
+                        assert!(Cli::try_parse_from(args).is_err());
+                    }
+                    let result =
+                        Cli::try_parse_from(["outline", "mask", "in.png", "--max-error", "2"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn cut_target_quality() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--target-quality",
+                            "edge-gradient=0.6",
+                            "--escalate",
+                            "u2netp,isnet",
+                            "--quality-budget",
+                            "3"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(
+                        cmd.target_quality,
+                        Some(QualityTarget {
+                            metric: QualityMetric::EdgeGradient,
+                            value: 0.6
+                        })
+                    );
+                    assert_eq!(cmd.escalate, ["u2netp", "isnet"]);
+                    assert_eq!(cmd.quality_budget, Some(3));
+                    for target in ["sharpness=0.5", "confidence=2", "edge-gradient"] {
+                        let result = Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--target-quality",
+                            target,
+                        ]);
+                        assert!(result.is_err(), "{target}");
+                    }
+                    let result =
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--quality-budget", "2"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn cut_output_format() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "cut",
+                            "in.png",
+                            "--format",
+                            "webp",
+                            "--quality",
+                            "85"
+                        ],
+                        Cut
+                    );
+                    assert_eq!(cmd.format, Some(OutputFormatArg::Webp));
+                    assert_eq!(cmd.quality, Some(85));
+                    let cmd = parse_cmd!(["outline", "cut", "in.png", "--format", "jpg"], Cut);
+                    assert_eq!(cmd.format, Some(OutputFormatArg::Jpeg));
+                    for conflicting in [
+                        vec!["--quality", "80", "--lossless"],
+                        vec!["--quality", "0"],
+                        vec!["--format", "avif", "--palette"],
+                    ] {
+                        let mut args = vec!["outline", "cut", "in.png"];
+                        args.extend(conflicting);
+                        assert!(Cli::try_parse_from(args).is_err());
+                    }
+                }
+
+                #[test]
+                fn cut_strip_metadata() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.jpg"], Cut);
+                    assert!(!cmd.strip_metadata);
+                    let cmd = parse_cmd!(["outline", "cut", "in.jpg", "--strip-metadata"], Cut);
+                    assert!(cmd.strip_metadata);
+                }
+
+                #[test]
+                fn cut_baseline() {
+                    let cmd = parse_cmd!(["outline", "cut", "in.jpg", "--baseline", "old"], Cut);
+                    assert_eq!(cmd.baseline, Some(PathBuf::from("old")));
+                    assert_eq!(cmd.baseline_hash_distance, 4);
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.jpg",
+                            "--baseline-hash-distance",
+                            "8"
+                        ])
+                        .is_err()
+                    );
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "cut",
+                            "in.jpg",
+                            "--baseline",
+                            "old",
+                            "--draft"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn cut_blur_background() {
+                    let cmd =
+                        parse_cmd!(["outline", "cut", "in.png", "--blur-background", "12"], Cut);
+                    assert_eq!(cmd.blur_background, Some(12.0));
+                    for other in [["--bg-color", "#fff"], ["--canvas", "800x800"]] {
+                        let mut args = vec!["outline", "cut", "in.png", "--blur-background", "12"];
+                        args.extend(other);
+                        assert!(Cli::try_parse_from(args).is_err());
+                    }
+                }
+
+                #[test]
+                fn cut_bg_color_conflicts_with_bg_image() {
+                    let result = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--bg-color",
+                        "#fff",
+                        "--bg-image",
+                        "studio.jpg",
+                    ]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn trace_backend_contour() {
+                    let cmd = parse_cmd!(
+                        ["outline", "trace", "in.png", "--backend", "contour"],
+                        Trace
+                    );
+                    assert_eq!(cmd.trace_options.backend(), TraceBackendArg::Contour);
+                }
+
+                #[cfg(feature = "vectorizer-vtracer")]
+                #[test]
+                fn trace_backend_defaults_to_vtracer() {
+                    let cmd = parse_cmd!(["outline", "trace", "in.png"], Trace);
+                    assert_eq!(cmd.trace_options.backend(), TraceBackendArg::Vtracer);
+                }
+
+                #[test]
+                fn trace_metadata_json() {
+                    let cmd =
+                        parse_cmd!(["outline", "trace", "in.png", "--metadata", "json"], Trace);
+                    assert_eq!(cmd.trace_options.metadata, Some(ContourMetadataArg::Json));
+                }
+
+                #[test]
+                fn trace_collision_mode() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "trace",
+                            "in.png",
+                            "--mode",
+                            "collision",
+                            "--max-vertices",
+                            "6",
+                            "--convexity",
+                            "1.5"
+                        ],
+                        Trace
+                    );
+                    assert!(cmd.trace_options.collision());
+                    let options = CollisionOptions::from(&cmd.trace_options);
+                    assert_eq!(options.max_vertices, 6);
+                    assert_eq!(options.convexity, Some(1.5));
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "trace",
+                            "in.png",
+                            "--mode",
+                            "collision",
+                            "--max-vertices",
+                            "2"
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn trace_profile_dieline() {
+                    let cmd = parse_cmd!(
+                        ["outline", "trace", "in.png", "--profile", "dieline"],
+                        Trace
+                    );
+                    let args = &cmd.trace_options;
+                    assert_eq!(args.backend(), TraceBackendArg::Contour);
+                    assert!(args.preserve_corners);
+                    assert_eq!(args.filter_speckle, 16);
+                    assert_eq!(args.simplify_tolerance, 0.5);
+                }
+
+                #[test]
+                fn trace_profile_lineart_uses_centerline() {
+                    let cmd = parse_cmd!(
+                        ["outline", "trace", "in.png", "--profile", "lineart"],
+                        Trace
+                    );
+                    assert!(cmd.trace_options.centerline());
+                    assert_eq!(cmd.trace_options.stroke_width, 2.0);
+                }
+
+                #[test]
+                fn trace_profile_yields_to_explicit_flags() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "trace",
+                            "in.png",
+                            "--profile",
+                            "icon",
+                            "--mode",
+                            "spline",
+                            "--filter-speckle",
+                            "2"
+                        ],
+                        Trace
+                    );
+                    let args = &cmd.trace_options;
+                    assert!(matches!(args.mode, TracerMode::Spline));
+                    assert_eq!(args.filter_speckle, 2);
+                    assert_eq!(args.path_precision, Some(1));
+                }
+
+                #[test]
+                fn trace_coordinates_default_to_pixels() {
+                    let cmd = parse_cmd!(["outline", "trace", "in.png"], Trace);
+                    assert!(CoordinateOptions::from(&cmd.coordinates).is_identity());
+                }
+
+                #[test]
+                fn zero_max_nodes_rejected() {
+                    let result =
+                        Cli::try_parse_from(["outline", "trace", "in.png", "--max-nodes", "0"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn invalid_transform_rejected() {
+                    let result =
+                        Cli::try_parse_from(["outline", "trace", "in.png", "--transform", "1,2"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn device_defaults_to_cpu() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(Device::from(cli.global.device), Device::Cpu);
+                }
+
+                #[test]
+                fn device_after_subcommand() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "mask", "in.png", "--device", "coreml"])
+                            .unwrap();
+                    assert_eq!(Device::from(cli.global.device), Device::CoreMl);
+                }
+
+                #[test]
+                fn unknown_device_rejected() {
+                    let result =
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--device", "tpu"]);
+                    assert!(result.is_err());
+                }
+
+                #[test]
+                fn backend_defaults_to_the_library_default() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.backend, None);
+                    let cli =
+                        Cli::try_parse_from(["outline", "mask", "in.png", "--backend", "tract"])
+                            .unwrap();
+                    assert_eq!(cli.global.backend.map(Backend::from), Some(Backend::Tract));
+                }
+
+                #[test]
+                fn candle_backend_on_metal() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "--backend",
+                        "candle",
+                        "--device",
+                        "metal",
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.global.backend.map(Backend::from), Some(Backend::Candle));
+                    assert_eq!(Device::from(cli.global.device), Device::Metal);
+                }
+
+                #[test]
+                fn accept_license_after_subcommand() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "models",
+                        "pull",
+                        "rmbg",
+                        "--accept-license",
+                    ])
+                    .unwrap();
+                    assert!(cli.global.accept_license);
+                }
+
+                #[test]
+                fn low_memory_after_subcommand() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--low-memory"]).unwrap();
+                    assert!(cli.global.low_memory);
+                }
+
+                #[test]
+                fn model_arch_for_custom_models() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "in.png"]).unwrap();
+                    assert_eq!(cli.global.model_arch, None);
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "cut",
+                        "in.png",
+                        "-m",
+                        "custom.onnx",
+                        "--model-arch",
+                        "birefnet",
+                    ])
+                    .unwrap();
+                    assert_eq!(
+                        cli.global.model_arch.map(Architecture::from),
+                        Some(Architecture::BiRefNet)
+                    );
+                    assert!(!cli.global.fuse_side_outputs);
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "in.png", "--fuse-side-outputs"])
+                            .unwrap();
+                    assert!(cli.global.fuse_side_outputs);
+                }
+
+                #[test]
+                fn usage_log_does_not_take_the_input() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "--usage-log", "in.png"]).unwrap();
+                    assert_eq!(cli.global.usage_log, Some(None));
+                    let Commands::Cut(cmd) = cli.command else {
+                        panic!("expected Cut command");
+                    };
+                    assert_eq!(cmd.inputs, [PathBuf::from("in.png")]);
+
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "usage",
+                        "report",
+                        "--usage-log=team.jsonl",
+                        "--by",
+                        "tag",
+                    ])
+                    .unwrap();
+                    assert_eq!(
+                        cli.global.usage_log,
+                        Some(Some(PathBuf::from("team.jsonl")))
+                    );
+                    assert!(!cli.command.needs_model());
+                    let Commands::Usage(UsageCommand {
+                        action: UsageAction::Report { by, json },
+                    }) = cli.command
+                    else {
+                        panic!("expected Usage command");
+                    };
+                    assert_eq!(by, UsageGroupArg::Tag);
+                    assert!(!json);
+                }
+
+                #[test]
+                fn config_keys_are_checked() {
+                    let cli = Cli::try_parse_from(["outline", "config", "set", "model", "isnet"])
+                        .unwrap();
+                    assert!(!cli.command.needs_model());
+                    let Commands::Config(ConfigCommand {
+                        action: ConfigAction::Set { key, value },
+                    }) = cli.command
+                    else {
+                        panic!("expected Config command");
+                    };
+                    assert_eq!((key.as_str(), value.as_str()), ("model", "isnet"));
+                    assert!(Cli::try_parse_from(["outline", "config", "get", "colour"]).is_err());
+                }
+
+                #[test]
+                fn job_variables_are_name_value_pairs() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "run",
+                            "job.toml",
+                            "--var",
+                            "out=dist",
+                            "--dry-run"
+                        ],
+                        Run
+                    );
+                    assert_eq!(cmd.vars, [("out".to_string(), "dist".to_string())]);
+                    assert!(cmd.dry_run);
+                    assert!(
+                        Cli::try_parse_from(["outline", "run", "job.toml", "--var", "out"])
+                            .is_err()
+                    );
+                }
+
+                #[test]
+                fn doctor_checks_the_model_itself() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "doctor", "--device", "cuda"]).unwrap();
+                    assert!(matches!(cli.command, Commands::Doctor));
+                    assert!(!cli.command.needs_model());
+                    assert_eq!(cli.global.device, DeviceArg::Cuda);
+                }
+
+                #[test]
+                fn completions_and_manpages_need_no_model() {
+                    let cmd = parse_cmd!(["outline", "completions", "powershell"], Completions);
+                    assert_eq!(cmd.shell, Shell::PowerShell);
+                    let cmd = parse_cmd!(["outline", "completions", "elvish"], Completions);
+                    assert_eq!(cmd.shell, Shell::Elvish);
+                    assert!(Cli::try_parse_from(["outline", "completions", "tcsh"]).is_err());
+                    let cli = Cli::try_parse_from(["outline", "manpage", "--dir", "man"]).unwrap();
+                    assert!(!cli.command.needs_model());
+                    let Commands::Manpage(cmd) = cli.command else {
+                        panic!("expected Manpage command");
+                    };
+                    assert_eq!(cmd.dir, Some(PathBuf::from("man")));
+                }
+
+                #[test]
+                fn output_templates_are_checked_when_parsed() {
+                    let cli = Cli::try_parse_from([
+                        "outline",
+                        "mask",
+                        "a.jpg",
+                        "--output-template",
+                        "{stem}_{model}.{ext}",
+                    ])
+                    .unwrap();
+                    assert!(cli.global.output_template.is_some());
+                    assert!(
+                        Cli::try_parse_from([
+                            "outline",
+                            "--output-template",
+                            "{stem}_{mdoel}.{ext}",
+                            "cut",
+                            "a.jpg",
+                        ])
+                        .is_err()
+                    );
+                }
+
+                #[test]
+                fn empty_mattes_are_skipped_by_default() {
+                    let cmd = parse_cmd!(["outline", "cut", "a.jpg"], Cut);
+                    assert_eq!(cmd.extent.on_empty, ExtentPolicyArg::Skip);
+                    assert_eq!(cmd.extent.on_full, ExtentPolicyArg::Copy);
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "mask",
+                            "a.jpg",
+                            "--on-empty",
+                            "fail",
+                            "--on-full",
+                            "skip"
+                        ],
+                        Mask
+                    );
+                    assert_eq!(cmd.extent.on_empty, ExtentPolicyArg::Fail);
+                    assert_eq!(cmd.extent.on_full, ExtentPolicyArg::Skip);
+                    let cmd =
+                        parse_cmd!(["outline", "trace", "a.jpg", "--on-empty", "copy"], Trace);
+                    assert_eq!(cmd.extent.on_empty, ExtentPolicyArg::Copy);
+                }
+
+                #[test]
+                fn sequences_take_the_node_count_from_max_nodes() {
+                    let cmd = parse_cmd!(
+                        [
+                            "outline",
+                            "trace",
+                            "f1.png",
+                            "f2.png",
+                            "--sequence",
+                            "--max-nodes",
+                            "40"
+                        ],
+                        Trace
+                    );
+                    assert!(cmd.sequence);
+                    assert_eq!(SequenceOptions::from(&cmd.trace_options).nodes, 40);
+                    let argv = ["outline", "trace", "f1.png", "--sequence", "--invert-svg"];
+                    assert!(Cli::try_parse_from(argv).is_err());
+                }
+
+                #[test]
+                fn lottie_output_rejects_svg_coordinates() {
+                    let cmd = parse_cmd!(
+                        ["outline", "trace", "a.png", "--lottie", "--fps", "30"],
+                        Trace
+                    );
+                    assert!(cmd.lottie && cmd.fps == 30);
+                    let argv = ["outline", "trace", "a.png", "--lottie", "--flip-y"];
+                    assert!(Cli::try_parse_from(argv).is_err());
+                    assert!(
+                        Cli::try_parse_from(["outline", "trace", "a.png", "--fps", "30"]).is_err()
+                    );
+                }
+
+                #[test]
+                fn force_rerun_wins_over_resume() {
+                    let cli =
+                        Cli::try_parse_from(["outline", "cut", "photos/", "--resume"]).unwrap();
+                    assert!(cli.command.checkpoint().resuming());
+                    let argv = ["outline", "mask", "photos/", "--resume", "--force-rerun"];
+                    let cli = Cli::try_parse_from(argv).unwrap();
+                    assert!(!cli.command.checkpoint().resuming());
+                }
+
+                #[test]
+                fn existing_output_policies_are_exclusive() {
+                    let cli = Cli::try_parse_from(["outline", "cut", "a.jpg", "--skip-existing"])
+                        .unwrap();
+                    assert!(cli.global.skip_existing && !cli.global.overwrite);
+                    for pair in [
+                        ["--overwrite", "--skip-existing"],
+                        ["--overwrite", "--suffix"],
+                        ["--skip-existing", "--suffix"],
+                    ] {
+                        let argv = ["outline", "mask", "a.jpg", pair[0], pair[1]];
+                        assert!(Cli::try_parse_from(argv).is_err());
+                    }
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,488 @@
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "color-management")]
+use image::ImageEncoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, RgbImage};
+
+use crate::BgrError;
+use crate::encode::{
+    Compression, DEFAULT_JPEG_QUALITY, EncodeOptions, OutputFormat, encode_output,
+};
+use crate::foreground::encode_image;
+use crate::limits::limited_decoder_from_bytes;
+use crate::{BgrResult, InputLimits};
+
+/// How colors outside the destination gamut are brought into it, as defined by the ICC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderingIntent {
+    /// Compress the whole gamut, keeping the relation between colors; suits photos.
+    Perceptual,
+    /// Keep in-gamut colors exact and clip the rest, relative to the media white.
+    #[default]
+    RelativeColorimetric,
+    /// Keep colors vivid at the cost of accuracy; suits charts and logos.
+    Saturation,
+    /// Like relative colorimetric, but without adapting the white point.
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    /// The intent's ICC number, as sent to a sandboxed decoder.
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            RenderingIntent::Perceptual => 0,
+            RenderingIntent::RelativeColorimetric => 1,
+            RenderingIntent::Saturation => 2,
+            RenderingIntent::AbsoluteColorimetric => 3,
+        }
+    }
+
+    /// The intent with ICC number `byte`.
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(RenderingIntent::Perceptual),
+            1 => Some(RenderingIntent::RelativeColorimetric),
+            2 => Some(RenderingIntent::Saturation),
+            3 => Some(RenderingIntent::AbsoluteColorimetric),
+            _ => None,
+        }
+    }
+}
+
+/// An RGB color space that outputs are converted to and tagged with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// The working space of the pipeline; outputs stay untagged, which viewers read as sRGB.
+    #[default]
+    Srgb,
+    /// The wide-gamut space of recent displays: DCI-P3 primaries, D65 white, sRGB curve.
+    DisplayP3,
+    /// An ICC profile, such as one read from a `.icc` file.
+    Icc(Vec<u8>),
+}
+
+impl ColorProfile {
+    /// Read an ICC profile from `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> BgrResult<Self> {
+        Ok(ColorProfile::Icc(std::fs::read(path)?))
+    }
+}
+
+/// The color space and rendering intent outputs are encoded with.
+///
+/// Decoding converts inputs with an embedded ICC profile to sRGB, the space the models were
+/// trained on, and compositing happens there too. Encoding then converts to `target` and
+/// embeds its profile. Converting needs the `color-management` feature; without it, embedded
+/// profiles are ignored and only sRGB targets are accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorSettings {
+    pub target: ColorProfile,
+    pub intent: RenderingIntent,
+}
+
+/// Encode `image`, whose pixels are sRGB, as `format` in `settings.target`.
+///
+/// Formats that cannot embed a profile (PNG, JPEG, WebP, and TIFF can) fail for targets other
+/// than sRGB rather than writing pixels that viewers would misread.
+pub fn encode_for_profile(
+    image: DynamicImage,
+    format: ImageFormat,
+    settings: &ColorSettings,
+) -> BgrResult<Vec<u8>> {
+    match &settings.target {
+        ColorProfile::Srgb => encode_image(image, format),
+        #[cfg(feature = "color-management")]
+        target => {
+            managed::encode_tagged(image, format, DEFAULT_JPEG_QUALITY, target, settings.intent)
+        }
+        #[cfg(not(feature = "color-management"))]
+        _ => Err(unavailable()),
+    }
+}
+
+/// Encode `image`, whose pixels are sRGB, as `options` asks in `settings.target`.
+///
+/// A quality is kept for JPEG; lossy WebP and AVIF cannot carry a profile, so they fail for
+/// targets other than sRGB, as [`encode_for_profile`] does for formats without one.
+pub fn encode_output_for_profile(
+    image: &DynamicImage,
+    options: &EncodeOptions,
+    settings: &ColorSettings,
+) -> BgrResult<Vec<u8>> {
+    if settings.target == ColorProfile::Srgb {
+        return encode_output(image, options);
+    }
+    options.check()?;
+    let jpeg_quality = match (options.format, options.compression) {
+        (OutputFormat::Jpeg, Compression::Quality(quality)) => quality,
+        (_, Compression::Quality(_)) => {
+            return Err(BgrError::invalid_input(
+                "only JPEG keeps a quality when a color profile is embedded",
+            ));
+        }
+        _ => DEFAULT_JPEG_QUALITY,
+    };
+    #[cfg(feature = "color-management")]
+    {
+        managed::encode_tagged(
+            image.clone(),
+            options.format.image_format(),
+            jpeg_quality,
+            &settings.target,
+            settings.intent,
+        )
+    }
+    #[cfg(not(feature = "color-management"))]
+    {
+        let _ = jpeg_quality;
+        Err(unavailable())
+    }
+}
+
+/// Save `image`, whose pixels are sRGB, to `path` in `settings.target`, in the format the
+/// extension names.
+pub fn save_for_profile(
+    image: DynamicImage,
+    path: impl AsRef<Path>,
+    settings: &ColorSettings,
+) -> BgrResult<()> {
+    let path = path.as_ref();
+    if settings.target == ColorProfile::Srgb {
+        image.save(path)?;
+        return Ok(());
+    }
+    let format = ImageFormat::from_path(path)?;
+    std::fs::write(path, encode_for_profile(image, format, settings)?)?;
+    Ok(())
+}
+
+/// Open the image at `path` as sRGB, converting from its embedded profile with `intent`.
+///
+/// Meant for images that join the pipeline besides the inputs, such as backgrounds.
+pub fn open_srgb(path: impl AsRef<Path>, intent: RenderingIntent) -> BgrResult<RgbImage> {
+    let decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    decode_to_srgb(decoder, intent)
+}
+
+/// Decode an encoded image held in memory as sRGB within `limits`, as [`open_srgb`] opens a
+/// file.
+///
+/// Meant for backgrounds that arrive over the network alongside the input.
+pub fn decode_srgb(
+    bytes: &[u8],
+    limits: &InputLimits,
+    intent: RenderingIntent,
+) -> BgrResult<RgbImage> {
+    decode_to_srgb(limited_decoder_from_bytes(bytes, limits)?, intent)
+}
+
+fn decode_to_srgb(decoder: impl ImageDecoder, intent: RenderingIntent) -> BgrResult<RgbImage> {
+    #[cfg(feature = "color-management")]
+    {
+        let mut decoder = decoder;
+        let profile = decoder.icc_profile()?;
+        let mut rgb = DynamicImage::from_decoder(decoder)?.into_rgb8();
+        if let Some(profile) = profile {
+            to_working_space(&mut rgb, &profile, intent)?;
+        }
+        Ok(rgb)
+    }
+    #[cfg(not(feature = "color-management"))]
+    {
+        let _ = intent;
+        Ok(DynamicImage::from_decoder(decoder)?.into_rgb8())
+    }
+}
+
+/// Convert `rgb` from the embedded profile `icc` to sRGB in place.
+#[cfg(feature = "color-management")]
+pub(crate) fn to_working_space(
+    rgb: &mut RgbImage,
+    icc: &[u8],
+    intent: RenderingIntent,
+) -> BgrResult<()> {
+    let source = managed::Profile::new_icc(icc).map_err(managed::color_error)?;
+    managed::convert_rgb(rgb, &source, &managed::Profile::new_srgb(), intent)
+}
+
+/// The sRGB transfer function, inverted: an encoded value in `[0, 1]` to linear light.
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The sRGB transfer function: linear light in `[0, 1]` to an encoded value.
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(not(feature = "color-management"))]
+fn unavailable() -> crate::BgrError {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "color profiles other than sRGB need bgr built with the color-management feature",
+    )
+    .into()
+}
+
+#[cfg(feature = "color-management")]
+mod managed {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::png::PngEncoder;
+    use image::codecs::tiff::TiffEncoder;
+    use image::codecs::webp::WebPEncoder;
+    use image::{ImageError, RgbaImage};
+    pub(super) use lcms2::Profile;
+    use lcms2::{CIExyY, CIExyYTRIPLE, Flags, Intent, PixelFormat, ToneCurve, Transform};
+
+    pub(super) fn color_error(err: lcms2::Error) -> BgrError {
+        BgrError::Color(err.to_string())
+    }
+
+    fn lcms_intent(intent: RenderingIntent) -> Intent {
+        match intent {
+            RenderingIntent::Perceptual => Intent::Perceptual,
+            RenderingIntent::RelativeColorimetric => Intent::RelativeColorimetric,
+            RenderingIntent::Saturation => Intent::Saturation,
+            RenderingIntent::AbsoluteColorimetric => Intent::AbsoluteColorimetric,
+        }
+    }
+
+    /// Display P3, built from its primaries since lcms has no preset for it.
+    fn display_p3() -> BgrResult<Profile> {
+        let xy = |x, y| CIExyY { x, y, Y: 1.0 };
+        let primaries = CIExyYTRIPLE {
+            Red: xy(0.680, 0.320),
+            Green: xy(0.265, 0.690),
+            Blue: xy(0.150, 0.060),
+        };
+        // The sRGB transfer function as ICC parametric curve type 4.
+        let curve =
+            ToneCurve::new_parametric(4, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])
+                .map_err(color_error)?;
+        Profile::new_rgb(&xy(0.3127, 0.3290), &primaries, &[&curve, &curve, &curve])
+            .map_err(color_error)
+    }
+
+    /// The lcms profile of `target` and the bytes to embed for it.
+    fn target_profile(target: &ColorProfile) -> BgrResult<(Profile, Vec<u8>)> {
+        let profile = match target {
+            ColorProfile::Srgb => Profile::new_srgb(),
+            ColorProfile::DisplayP3 => display_p3()?,
+            ColorProfile::Icc(bytes) => {
+                return Ok((Profile::new_icc(bytes).map_err(color_error)?, bytes.clone()));
+            }
+        };
+        let icc = profile.icc().map_err(color_error)?;
+        Ok((profile, icc))
+    }
+
+    pub(super) fn convert_rgb(
+        rgb: &mut RgbImage,
+        from: &Profile,
+        to: &Profile,
+        intent: RenderingIntent,
+    ) -> BgrResult<()> {
+        let transform: Transform<[u8; 3], [u8; 3]> = Transform::new(
+            from,
+            PixelFormat::RGB_8,
+            to,
+            PixelFormat::RGB_8,
+            lcms_intent(intent),
+        )
+        .map_err(color_error)?;
+        apply(&transform, rgb);
+        Ok(())
+    }
+
+    fn convert_rgba(
+        rgba: &mut RgbaImage,
+        from: &Profile,
+        to: &Profile,
+        intent: RenderingIntent,
+    ) -> BgrResult<()> {
+        let transform: Transform<[u8; 4], [u8; 4]> = Transform::new_flags(
+            from,
+            PixelFormat::RGBA_8,
+            to,
+            PixelFormat::RGBA_8,
+            lcms_intent(intent),
+            Flags::COPY_ALPHA,
+        )
+        .map_err(color_error)?;
+        apply(&transform, rgba);
+        Ok(())
+    }
+
+    /// Run `transform` over the pixels of `image`.
+    fn apply<const N: usize>(transform: &Transform<[u8; N], [u8; N]>, image: &mut [u8]) {
+        let mut pixels: Vec<[u8; N]> = image
+            .chunks_exact(N)
+            .map(|pixel| pixel.try_into().expect("chunks have N samples"))
+            .collect();
+        transform.transform_in_place(&mut pixels);
+        for (samples, pixel) in image.chunks_exact_mut(N).zip(&pixels) {
+            samples.copy_from_slice(pixel);
+        }
+    }
+
+    pub(super) fn encode_tagged(
+        image: DynamicImage,
+        format: ImageFormat,
+        jpeg_quality: u8,
+        target: &ColorProfile,
+        intent: RenderingIntent,
+    ) -> BgrResult<Vec<u8>> {
+        let (profile, icc) = target_profile(target)?;
+        let srgb = Profile::new_srgb();
+        let image = match image {
+            DynamicImage::ImageRgb8(mut rgb) => {
+                convert_rgb(&mut rgb, &srgb, &profile, intent)?;
+                DynamicImage::ImageRgb8(rgb)
+            }
+            other if other.color().has_alpha() => {
+                let mut rgba = other.into_rgba8();
+                convert_rgba(&mut rgba, &srgb, &profile, intent)?;
+                DynamicImage::ImageRgba8(rgba)
+            }
+            other => {
+                let mut rgb = other.into_rgb8();
+                convert_rgb(&mut rgb, &srgb, &profile, intent)?;
+                DynamicImage::ImageRgb8(rgb)
+            }
+        };
+
+        let mut encoded = Cursor::new(Vec::new());
+        match format {
+            ImageFormat::Png => write_tagged(PngEncoder::new(&mut encoded), &image, icc)?,
+            ImageFormat::Jpeg => write_tagged(
+                JpegEncoder::new_with_quality(&mut encoded, jpeg_quality),
+                &image,
+                icc,
+            )?,
+            ImageFormat::WebP => {
+                write_tagged(WebPEncoder::new_lossless(&mut encoded), &image, icc)?
+            }
+            ImageFormat::Tiff => write_tagged(TiffEncoder::new(&mut encoded), &image, icc)?,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{other:?} cannot embed a color profile; use PNG, JPEG, WebP, or TIFF"),
+                )
+                .into());
+            }
+        }
+        Ok(encoded.into_inner())
+    }
+
+    fn write_tagged(
+        mut encoder: impl ImageEncoder,
+        image: &DynamicImage,
+        icc: Vec<u8>,
+    ) -> BgrResult<()> {
+        encoder
+            .set_icc_profile(icc)
+            .map_err(ImageError::Unsupported)?;
+        image.write_with_encoder(encoder)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    mod rendering_intent {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn byte_round_trip() {
+                for intent in [
+                    RenderingIntent::Perceptual,
+                    RenderingIntent::RelativeColorimetric,
+                    RenderingIntent::Saturation,
+                    RenderingIntent::AbsoluteColorimetric,
+                ] {
+                    assert_eq!(RenderingIntent::from_byte(intent.to_byte()), Some(intent));
+                }
+                assert_eq!(RenderingIntent::from_byte(4), None);
+            }
+        }
+    }
+
+    mod encode_for_profile {
+        use super::*;
+
+        fn image() -> DynamicImage {
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])))
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn srgb_target_is_untagged() {
+                let png = encode_for_profile(image(), ImageFormat::Png, &ColorSettings::default())
+                    .unwrap();
+                assert_eq!(png, encode_image(image(), ImageFormat::Png).unwrap());
+            }
+
+            #[cfg(feature = "color-management")]
+            #[test]
+            fn display_p3_is_converted_and_tagged() {
+                let settings = ColorSettings {
+                    target: ColorProfile::DisplayP3,
+                    ..ColorSettings::default()
+                };
+                let png = encode_for_profile(image(), ImageFormat::Png, &settings).unwrap();
+                let mut decoder =
+                    image::codecs::png::PngDecoder::new(std::io::Cursor::new(&png)).unwrap();
+                assert!(decoder.icc_profile().unwrap().is_some());
+                // sRGB red sits inside P3, so it loses saturation there.
+                let decoded = image::load_from_memory(&png).unwrap().into_rgb8();
+                let Rgb([r, g, b]) = *decoded.get_pixel(0, 0);
+                assert!(r < 255 && g > 0 && b > 0, "{r} {g} {b}");
+            }
+
+            #[cfg(feature = "color-management")]
+            #[test]
+            fn untaggable_format_is_rejected() {
+                let settings = ColorSettings {
+                    target: ColorProfile::DisplayP3,
+                    ..ColorSettings::default()
+                };
+                assert!(encode_for_profile(image(), ImageFormat::Bmp, &settings).is_err());
+            }
+
+            #[cfg(feature = "color-management")]
+            #[test]
+            fn srgb_round_trip_through_the_working_space() {
+                let srgb = lcms2::Profile::new_srgb().icc().unwrap();
+                let mut rgb = RgbImage::from_pixel(2, 2, Rgb([12, 140, 230]));
+                to_working_space(&mut rgb, &srgb, RenderingIntent::Perceptual).unwrap();
+                let Rgb(pixel) = *rgb.get_pixel(1, 1);
+                for (got, want) in pixel.into_iter().zip([12, 140, 230]) {
+                    assert!(got.abs_diff(want) <= 1, "{pixel:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bgr::verify::{Fingerprint, Mismatch, Tolerance};
+use bgr::{BgrResult, Context};
+use image::DynamicImage;
+
+use crate::cli::CutCommand;
+use crate::i18n::tr;
+
+use super::utils::report;
+
+/// The outputs of a previous run that `--baseline` compares fresh cutouts with.
+pub struct Baseline {
+    dir: PathBuf,
+    tolerance: Tolerance,
+    /// Outputs compared so far.
+    compared: AtomicUsize,
+    /// Outputs that changed beyond the tolerance, or that the baseline lacks.
+    changed: AtomicUsize,
+}
+
+/// How a fresh output compares with its baseline.
+#[derive(Debug, PartialEq)]
+pub enum Comparison {
+    Unchanged,
+    Changed(Vec<Mismatch>),
+    /// The baseline has no output of that name.
+    New,
+}
+
+impl Baseline {
+    /// The baseline `cmd` compares with, or `None` without `--baseline`.
+    pub fn new(cmd: &CutCommand) -> Option<Self> {
+        let dir = cmd.baseline.clone()?;
+        Some(Self {
+            dir,
+            tolerance: Tolerance {
+                max_hash_distance: cmd.baseline_hash_distance,
+                max_coverage_delta: cmd.baseline_coverage_tolerance,
+            },
+            compared: AtomicUsize::new(0),
+            changed: AtomicUsize::new(0),
+        })
+    }
+
+    /// Compare `image`, about to be written to `output`, with the baseline output of the same
+    /// file name, listing it as `CHANGED` or `NEW` unless it is unchanged.
+    pub fn compare(
+        &self,
+        ctx: &Context,
+        output: &Path,
+        image: &DynamicImage,
+    ) -> BgrResult<Comparison> {
+        let name = output
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let previous = self.dir.join(&name);
+        let comparison = if previous.is_file() {
+            let mismatches = Fingerprint::of_path(&previous)?
+                .compare(&Fingerprint::of_image(image), &self.tolerance);
+            if mismatches.is_empty() {
+                Comparison::Unchanged
+            } else {
+                Comparison::Changed(mismatches)
+            }
+        } else {
+            Comparison::New
+        };
+        self.compared.fetch_add(1, Ordering::Relaxed);
+        match &comparison {
+            Comparison::Unchanged => report(ctx, tr!("status-same", name = name.as_str())),
+            Comparison::Changed(mismatches) => {
+                for mismatch in mismatches {
+                    let reason = mismatch.to_string();
+                    report(
+                        ctx,
+                        tr!("status-changed", name = name.as_str(), reason = reason),
+                    );
+                }
+            }
+            Comparison::New => report(ctx, tr!("status-new", name = name.as_str())),
+        }
+        if comparison != Comparison::Unchanged {
+            self.changed.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(comparison)
+    }
+
+    /// Report how many outputs changed against the baseline.
+    pub fn summarize(&self, ctx: &Context) {
+        report(
+            ctx,
+            tr!(
+                "baseline-summary",
+                changed = self.changed.load(Ordering::Relaxed),
+                compared = self.compared.load(Ordering::Relaxed),
+                path = self.dir.display().to_string()
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn cutout(width: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 32, |x, _| {
+            Rgba([200, 200, 200, if x < width { 255 } else { 0 }])
+        }))
+    }
+
+    mod baseline {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn compares_with_the_same_named_output() {
+                let temp = tempfile::tempdir().unwrap();
+                let dir = temp.path();
+                cutout(16).save(dir.join("a-foreground.png")).unwrap();
+                let baseline = Baseline {
+                    dir: dir.to_path_buf(),
+                    tolerance: Tolerance::default(),
+                    compared: AtomicUsize::new(0),
+                    changed: AtomicUsize::new(0),
+                };
+                let ctx = Context::new();
+
+                let output = Path::new("out/a-foreground.png");
+                let same = baseline.compare(&ctx, output, &cutout(16)).unwrap();
+                assert_eq!(same, Comparison::Unchanged);
+                let shrunk = baseline.compare(&ctx, output, &cutout(4)).unwrap();
+                assert!(matches!(shrunk, Comparison::Changed(_)));
+                let new = baseline
+                    .compare(&ctx, Path::new("b-foreground.png"), &cutout(16))
+                    .unwrap();
+                assert_eq!(new, Comparison::New);
+
+                assert_eq!(baseline.compared.load(Ordering::Relaxed), 3);
+                assert_eq!(baseline.changed.load(Ordering::Relaxed), 2);
+            }
+        }
+    }
+}
@@ -0,0 +1,1009 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use bgr::DaemonClient;
+use bgr::{
+    BatchProgress, Bgr, BgrError, BgrResult, BgrSession, CancellationToken, DebugBundle, Device,
+    InferencedMatte, InputEvent, PreciseMatte, PreviewOptions, ProgressSink, SharedProgress,
+    Verbosity, Warning,
+};
+use image::{GrayImage, ImageFormat, RgbImage};
+use serde::Serialize;
+
+use crate::cli::{BatchOrderArg, GlobalOptions, MatteStreamArg};
+use crate::i18n::{self, tr};
+
+use super::checkpoint;
+use super::hooks::{BatchFinished, BatchStarted, run_hook};
+use super::progress::batch_progress;
+use super::utils::is_stdio;
+
+/// Expand the input arguments into a list of image files.
+/// Directories contribute every supported image directly inside them, sorted by name.
+pub fn collect_inputs(inputs: &[PathBuf], kind: InputKind) -> BgrResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(input)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file() && kind.accepts(path))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(input.clone());
+        }
+    }
+
+    if files.is_empty() {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no input images found",
+        )));
+    }
+    Ok(files)
+}
+
+/// The files a directory input expands to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Images the image crate can decode.
+    Image,
+    /// Masks, for `--from-mask`: images, and `bgrl` run-length mattes.
+    Mask,
+    /// `bgrm` streams, for `--from bgrm`.
+    MatteContainer,
+}
+
+impl InputKind {
+    /// The kind of input a command reads, given its `--from` and `--from-mask`.
+    pub fn read_by(from: Option<MatteStreamArg>, from_mask: bool) -> Self {
+        match from {
+            Some(MatteStreamArg::Bgrm) => Self::MatteContainer,
+            None if from_mask => Self::Mask,
+            None => Self::Image,
+        }
+    }
+
+    fn accepts(self, path: &Path) -> bool {
+        let extension_is = |name: &str| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case(name))
+        };
+        match self {
+            Self::Image => is_supported_image(path),
+            Self::Mask => is_supported_image(path) || extension_is("bgrl"),
+            Self::MatteContainer => extension_is("bgrm"),
+        }
+    }
+}
+
+/// Check whether the path has an extension the image crate can decode.
+pub fn is_supported_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled())
+}
+
+/// Reject an option that names a single output file when several inputs are processed.
+pub fn ensure_single_input(inputs: &[PathBuf], flag: &str, present: bool) -> BgrResult<()> {
+    if present && inputs.len() > 1 {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{flag} names a single file but {} inputs were given; use --out-dir instead",
+                inputs.len()
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// Aspect-ratio buckets per doubling of width over height for `--order size`.
+const ASPECT_BUCKETS_PER_OCTAVE: f64 = 4.0;
+
+/// The order to start `inputs` in, as indices into them.
+///
+/// `--order size` reads each input's dimensions from its header and sorts by aspect-ratio
+/// bucket, then pixel count; inputs whose size cannot be read come last, as given.
+fn processing_order(inputs: &[PathBuf], order: BatchOrderArg) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..inputs.len()).collect();
+    if order == BatchOrderArg::Size {
+        let buckets: Vec<Option<(i64, u64)>> =
+            inputs.iter().map(|input| size_bucket(input)).collect();
+        indices.sort_by_key(|&index| (buckets[index].is_none(), buckets[index]));
+    }
+    indices
+}
+
+/// The aspect-ratio bucket and pixel count of the image at `path`.
+fn size_bucket(path: &Path) -> Option<(i64, u64)> {
+    let (width, height) = image::image_dimensions(path).ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let aspect = (f64::from(width) / f64::from(height)).log2();
+    let bucket = (aspect * ASPECT_BUCKETS_PER_OCTAVE).round() as i64;
+    Some((bucket, u64::from(width) * u64::from(height)))
+}
+
+/// Resolve the number of worker threads for a batch of `total` inputs.
+pub fn effective_jobs(requested: usize, low_memory: bool, total: usize) -> usize {
+    if low_memory {
+        return 1;
+    }
+    let requested = if requested == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        requested
+    };
+    requested.clamp(1, total.max(1))
+}
+
+/// The CPU share a run may use under `--max-cpu` or `--nice`, in cores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuBudget {
+    cores: f64,
+}
+
+impl CpuBudget {
+    /// The budget set on the command line, or `None` when the whole machine may be used.
+    pub fn from_global(global: &GlobalOptions) -> Option<Self> {
+        let available = thread::available_parallelism().map_or(1, |n| n.get());
+        global
+            .cpu_percent()
+            .map(|percent| Self::new(percent, available))
+    }
+
+    fn new(percent: u8, available: usize) -> Self {
+        Self {
+            cores: available as f64 * f64::from(percent) / 100.0,
+        }
+    }
+
+    /// Fit `--jobs` and `--intra-threads` in the budget's whole cores.
+    ///
+    /// An explicit `--intra-threads` is kept; the pauses of [`CpuBudget::pause`] make up for it.
+    pub fn limit(self, global: &mut GlobalOptions) {
+        let cores = (self.cores.floor() as usize).max(1);
+        global.jobs = match global.jobs {
+            0 => cores,
+            jobs => jobs.min(cores),
+        };
+        let workers = if global.low_memory { 1 } else { global.jobs };
+        global.intra_threads.get_or_insert((cores / workers).max(1));
+    }
+
+    /// How long a worker rests after being busy for `busy` while the run keeps `threads`
+    /// threads busy, so the average load stays within the budget.
+    pub fn pause(self, busy: Duration, threads: usize) -> Duration {
+        let share = self.cores / threads.max(1) as f64;
+        if share >= 1.0 {
+            Duration::ZERO
+        } else {
+            busy.mul_f64(1.0 / share - 1.0)
+        }
+    }
+}
+
+/// Sleep for `duration`, waking early when the run is cancelled.
+fn rest(cancel: &CancellationToken, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !cancel.is_cancelled() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(Duration::from_millis(100)));
+    }
+}
+
+/// Sessions that batches left loaded under [`GlobalOptions::keep_sessions`], for a later
+/// batch that loads the same model on the same device.
+static KEPT_SESSIONS: Mutex<Vec<BgrSession>> = Mutex::new(Vec::new());
+
+/// Take a kept session that can serve `bgr`, if there is one.
+fn reuse_kept_session(bgr: &Bgr) -> Option<BgrSession> {
+    let mut kept = KEPT_SESSIONS.lock().expect("kept sessions lock poisoned");
+    let mut reused = None;
+    for session in std::mem::take(&mut *kept) {
+        if reused.is_some() {
+            kept.push(session);
+            continue;
+        }
+        match session.reuse_for(bgr) {
+            Ok(session) => reused = Some(session),
+            Err(session) => kept.push(session),
+        }
+    }
+    reused
+}
+
+/// Keep the loaded sessions among `sources` for a later batch.
+fn keep_sessions(sources: Vec<MatteSource>) {
+    let mut kept = KEPT_SESSIONS.lock().expect("kept sessions lock poisoned");
+    kept.extend(sources.into_iter().filter_map(|source| match source {
+        MatteSource::Session(session) => Some(session),
+        #[cfg(unix)]
+        MatteSource::Daemon(_) => None,
+    }));
+}
+
+/// Unload every session kept for a later batch.
+pub fn release_kept_sessions() {
+    KEPT_SESSIONS
+        .lock()
+        .expect("kept sessions lock poisoned")
+        .clear();
+}
+
+/// Where a worker gets its mattes: a model loaded in this process, or a running daemon.
+pub enum MatteSource {
+    Session(BgrSession),
+    #[cfg(unix)]
+    Daemon(DaemonClient),
+}
+
+impl MatteSource {
+    /// Load a session, reusing a kept one under [`GlobalOptions::keep_sessions`], or connect to
+    /// the daemon under `--via-daemon`.
+    pub fn open(bgr: &Bgr, global: &GlobalOptions) -> BgrResult<Self> {
+        #[cfg(unix)]
+        if let Some(socket) = &global.via_daemon {
+            let socket = socket.clone().unwrap_or_else(bgr::default_socket_path);
+            return Ok(MatteSource::Daemon(bgr.daemon_client(socket)?));
+        }
+        if global.keep_sessions
+            && let Some(session) = reuse_kept_session(bgr)
+        {
+            return Ok(MatteSource::Session(session));
+        }
+        Ok(MatteSource::Session(bgr.session()?))
+    }
+
+    /// Run inference for a single input.
+    pub fn for_image(&mut self, input: &Path) -> BgrResult<InferencedMatte> {
+        match self {
+            MatteSource::Session(session) => session.for_image(input),
+            #[cfg(unix)]
+            MatteSource::Daemon(client) => client.for_image(input),
+        }
+    }
+
+    /// Run inference for a single input, keeping the matte's full precision.
+    ///
+    /// The daemon protocol carries 8-bit mattes, so this needs a local session.
+    pub fn for_image_precise(&mut self, input: &Path) -> BgrResult<PreciseMatte> {
+        match self {
+            MatteSource::Session(session) => session.for_image_precise(input),
+            #[cfg(unix)]
+            MatteSource::Daemon(_) => Err(BgrError::Daemon(
+                "--depth 16, --depth float, and --storage cannot be used with --via-daemon".into(),
+            )),
+        }
+    }
+
+    /// Run low-resolution preview inference for a single input.
+    pub fn for_image_draft(
+        &mut self,
+        input: &Path,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        match self {
+            MatteSource::Session(session) => session.for_image_draft(input, options),
+            #[cfg(unix)]
+            MatteSource::Daemon(_) => Err(BgrError::Daemon(
+                "--draft cannot be used with --via-daemon".into(),
+            )),
+        }
+    }
+
+    /// Run the final pass over a draft's matte for a single input.
+    pub fn for_image_with_prior(
+        &mut self,
+        input: &Path,
+        prior: &GrayImage,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        match self {
+            MatteSource::Session(session) => session.for_image_with_prior(input, prior, options),
+            #[cfg(unix)]
+            MatteSource::Daemon(_) => Err(BgrError::Daemon(
+                "--final cannot be used with --via-daemon".into(),
+            )),
+        }
+    }
+
+    /// Run inference for an already decoded frame, such as one frame of an animation.
+    ///
+    /// The daemon protocol sends file paths, so frames are only supported by a local session.
+    pub fn for_rgb(&mut self, rgb: RgbImage) -> BgrResult<InferencedMatte> {
+        match self {
+            MatteSource::Session(session) => session.for_rgb(rgb),
+            #[cfg(unix)]
+            MatteSource::Daemon(_) => Err(BgrError::Daemon(
+                "animated inputs cannot be processed with --via-daemon".into(),
+            )),
+        }
+    }
+
+    /// Describe how `input` failed with `error`; a daemon's settings are not known here.
+    fn debug_bundle(&self, input: &Path, error: &BgrError) -> DebugBundle {
+        match self {
+            MatteSource::Session(session) => session.debug_bundle(input, error),
+            #[cfg(unix)]
+            MatteSource::Daemon(_) => {
+                DebugBundle::new(input, error).with_setting("via_daemon", true)
+            }
+        }
+    }
+}
+
+/// Save a `--debug-bundle` for an input that failed, logging where it went. A bundle that
+/// cannot be written is only warned about, so the input's own error is still the one reported.
+fn save_debug_bundle(dir: &Path, bundle: DebugBundle, input: &Path) {
+    let command_line: Vec<String> = std::env::args().collect();
+    let bundle = bundle.with_setting("command_line", command_line.join(" "));
+    let input = input.display().to_string();
+    match bundle.write(dir) {
+        Ok(path) => tracing::warn!(
+            "{}",
+            tr!(
+                "debug-bundle-saved",
+                input = input,
+                path = path.display().to_string()
+            )
+        ),
+        Err(err) => tracing::warn!(
+            "{}",
+            tr!(
+                "debug-bundle-failed",
+                input = input,
+                error = err.to_string()
+            )
+        ),
+    }
+}
+
+/// What happened to one input, as written to `--report`.
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    input: PathBuf,
+    warnings: Vec<Warning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl FileReport {
+    fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Whether the input was left alone because its output already existed.
+    fn skipped(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::OutputExists { .. }))
+            || self.resumed()
+    }
+
+    /// Whether the run being resumed had already written the input's output.
+    fn resumed(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::AlreadyWritten { .. }))
+    }
+
+    /// Whether the output was written under a new name beside an existing one.
+    fn renamed(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::OutputRenamed { .. }))
+    }
+
+    /// Record the outcome of processing `input`, printing its warnings.
+    fn new(input: &Path, result: &BgrResult<Vec<Warning>>) -> Self {
+        let (warnings, error) = match result {
+            Ok(warnings) => (warnings.clone(), None),
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+        print_warnings(input, &warnings);
+        Self {
+            input: input.to_path_buf(),
+            warnings,
+            error,
+        }
+    }
+}
+
+/// Log the warnings for one input, which `--quiet` silences.
+pub fn print_warnings(input: &Path, warnings: &[Warning]) {
+    for warning in warnings {
+        tracing::warn!(
+            "{}",
+            tr!(
+                "warning-for-input",
+                input = input.display().to_string(),
+                message = i18n::warning(warning)
+            )
+        );
+    }
+}
+
+/// The message printed when one input of a batch fails.
+pub fn failed_to_process(input: &Path, err: &BgrError) -> String {
+    tr!(
+        "failed-to-process",
+        input = input.display().to_string(),
+        error = i18n::describe(err)
+    )
+}
+
+/// Run the `--after` hook, when given, on the outcome of a batch of `total` inputs.
+fn run_after_hook(
+    global: &GlobalOptions,
+    reports: &[FileReport],
+    total: usize,
+    started: Instant,
+) -> BgrResult<()> {
+    let Some(command) = &global.after else {
+        return Ok(());
+    };
+    let failed = reports.iter().filter(|report| report.failed()).count();
+    let summary = BatchFinished {
+        total,
+        succeeded: reports.len() - failed,
+        failed,
+        skipped: reports.iter().filter(|report| report.skipped()).count(),
+        cancelled: super::cancellation().is_cancelled(),
+        seconds: started.elapsed().as_secs_f64(),
+        files: reports,
+    };
+    run_hook("--after", command, &summary)
+}
+
+/// Sum up the inputs `--resume` and `--skip-existing` left alone and `--suffix` renamed,
+/// whose warnings scrolled past one by one.
+fn report_existing_outputs(global: &GlobalOptions, reports: &[FileReport]) {
+    let resumed = reports.iter().filter(|report| report.resumed()).count();
+    let skipped = reports.iter().filter(|report| report.skipped()).count() - resumed;
+    let renamed = reports.iter().filter(|report| report.renamed()).count();
+    if global.verbosity() <= Verbosity::Quiet {
+        return;
+    }
+    if resumed > 0 {
+        eprintln!("{}", tr!("batch-resumed", count = resumed));
+    }
+    // On stderr like the rest of the run's chatter, so `-o -` and pipes see only outputs.
+    if skipped + renamed > 0 {
+        eprintln!(
+            "{}",
+            tr!(
+                "batch-existing-outputs",
+                skipped = skipped,
+                renamed = renamed
+            )
+        );
+    }
+}
+
+/// Write the outcome of every input to `--report`, when requested.
+fn write_report(global: &GlobalOptions, reports: &[FileReport]) -> BgrResult<()> {
+    let Some(path) = &global.report else {
+        return Ok(());
+    };
+    let json = serde_json::to_string_pretty(reports).map_err(io::Error::from)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Run `process` for every input, spreading the work over `--jobs` workers.
+///
+/// Each worker owns its own [`MatteSource`], so the model is loaded (or the daemon connected)
+/// once per worker rather than once per image. A single input propagates its error directly; in
+/// a batch, failures are reported as they happen and summarized as [`BgrError::Batch`] at the
+/// end. Warnings returned by `process` are printed per input and, like failures, written to
+/// `--report` in input order.
+pub fn run_batch<F>(
+    bgr: &Bgr,
+    inputs: &[PathBuf],
+    global: &GlobalOptions,
+    process: F,
+) -> BgrResult<()>
+where
+    F: Fn(&mut MatteSource, &Path) -> BgrResult<Vec<Warning>> + Sync,
+{
+    prepare_outputs(inputs, global, None)?;
+    let jobs = if inputs.len() == 1 {
+        1
+    } else {
+        if global.low_memory && global.jobs != 1 {
+            tracing::warn!("{}", tr!("low-memory-ignores-jobs"));
+        }
+        effective_jobs(global.jobs, global.low_memory, inputs.len())
+    };
+    let mut sessions = (0..jobs)
+        .map(|_| MatteSource::open(bgr, global))
+        .collect::<BgrResult<Vec<_>>>()?;
+    if let Some(session) = sessions.first() {
+        warn_on_device_fallback(Device::from(global.device), session);
+    }
+    let result = run_workers(&mut sessions, inputs, global, |source, input| {
+        let result = process(source, input);
+        if let (Err(err), Some(dir)) = (&result, &global.debug_bundle)
+            && !err.is_cancelled()
+        {
+            save_debug_bundle(dir, source.debug_bundle(input, err), input);
+        }
+        result
+    });
+    if global.keep_sessions {
+        keep_sessions(sessions);
+    }
+    result
+}
+
+/// Run `process` for every input like [`run_batch`], for work that needs no model, such as
+/// tracing mask files.
+pub fn run_batch_without_model<F>(
+    inputs: &[PathBuf],
+    global: &GlobalOptions,
+    process: F,
+) -> BgrResult<()>
+where
+    F: Fn(&Path) -> BgrResult<Vec<Warning>> + Sync,
+{
+    prepare_outputs(inputs, global, None)?;
+    let jobs = effective_jobs(global.jobs, global.low_memory, inputs.len());
+    run_workers(&mut vec![(); jobs], inputs, global, |_, input| {
+        let result = process(input);
+        if let (Err(err), Some(dir)) = (&result, &global.debug_bundle)
+            && !err.is_cancelled()
+        {
+            save_debug_bundle(dir, DebugBundle::new(input, err), input);
+        }
+        result
+    })
+}
+
+/// Share `inputs` among workers, each with its own state `S`, and report the outcomes.
+///
+/// After Ctrl-C no new inputs are started; the report covers the inputs that were.
+fn run_workers<S, F>(
+    workers: &mut [S],
+    inputs: &[PathBuf],
+    global: &GlobalOptions,
+    process: F,
+) -> BgrResult<()>
+where
+    S: Send,
+    F: Fn(&mut S, &Path) -> BgrResult<Vec<Warning>> + Sync,
+{
+    if let Some(command) = &global.before {
+        let summary = BatchStarted {
+            inputs,
+            total: inputs.len(),
+        };
+        run_hook("--before", command, &summary)?;
+    }
+    let batch_started = Instant::now();
+    let progress = batch_progress(global);
+    if let [input] = inputs
+        && let Some(worker) = workers.first_mut()
+    {
+        let result = process_reported(&progress, worker, &process, input, 0, 1);
+        let reports = [FileReport::new(input, &result)];
+        let written = write_report(global, &reports);
+        let hooked = run_after_hook(global, &reports, 1, batch_started);
+        return result.and(written).and(hooked);
+    }
+    checkpoint::begin(global, inputs);
+    let cancel = super::cancellation();
+    let budget = CpuBudget::from_global(global);
+    let threads = workers.len() * global.intra_threads.unwrap_or(1);
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(inputs.len()));
+    let order = processing_order(inputs, global.order);
+
+    thread::scope(|scope| {
+        for worker in workers.iter_mut() {
+            let (next, done, failed) = (&next, &done, &failed);
+            let (reports, process, progress, order) = (&reports, &process, &progress, &order);
+            scope.spawn(move || {
+                while !cancel.is_cancelled() {
+                    let Some(&index) = order.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let input = &inputs[index];
+                    let started = Instant::now();
+                    let result =
+                        process_reported(progress, worker, process, input, index, inputs.len());
+                    if let Some(budget) = budget {
+                        rest(cancel, budget.pause(started.elapsed(), threads));
+                    }
+                    if let Err(err) = &result
+                        && !err.is_cancelled()
+                    {
+                        eprintln!("{}", failed_to_process(input, err));
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    let report = FileReport::new(input, &result);
+                    reports.lock().unwrap().push((index, report));
+                    progress.batch(BatchProgress {
+                        done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                        failed: failed.load(Ordering::Relaxed),
+                        total: inputs.len(),
+                    });
+                }
+            });
+        }
+    });
+
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|&(index, _)| index);
+    let reports: Vec<FileReport> = reports.into_iter().map(|(_, report)| report).collect();
+    let complete = reports.len() == inputs.len() && reports.iter().all(|report| !report.failed());
+    checkpoint::finish(complete);
+    report_existing_outputs(global, &reports);
+    write_report(global, &reports)?;
+    run_after_hook(global, &reports, inputs.len(), batch_started)?;
+
+    cancel.check()?;
+    match failed.into_inner() {
+        0 => Ok(()),
+        failed => Err(BgrError::Batch {
+            failed,
+            total: inputs.len(),
+        }),
+    }
+}
+
+/// Run `process` for the `index`th of `total` inputs, telling `progress` when it starts and
+/// how it ends.
+fn process_reported<S, F>(
+    progress: &SharedProgress,
+    worker: &mut S,
+    process: &F,
+    input: &Path,
+    index: usize,
+    total: usize,
+) -> BgrResult<Vec<Warning>>
+where
+    F: Fn(&mut S, &Path) -> BgrResult<Vec<Warning>>,
+{
+    let input = input.to_path_buf();
+    progress.input(&InputEvent::FileStarted {
+        input: input.clone(),
+        index,
+        total,
+    });
+    let started = Instant::now();
+    let result = process(worker, &input);
+    progress.input(&match &result {
+        Ok(_) => InputEvent::FileFinished {
+            input,
+            seconds: started.elapsed().as_secs_f64(),
+        },
+        Err(err) => InputEvent::FileFailed {
+            input,
+            error: err.to_string(),
+        },
+    });
+    result
+}
+
+/// Create `--out-dir` and, for batches, check that every output directory is writable and
+/// has room, before any model is loaded. `output` is the one file a batch is written to under
+/// `-o`, such as a `--sequence --lottie` animation.
+///
+/// Outputs are assumed to take at least as much space as their inputs, which is true of
+/// lossless cutouts of compressed photos; the check catches full disks, not tight margins.
+pub fn prepare_outputs(
+    inputs: &[PathBuf],
+    global: &GlobalOptions,
+    output: Option<&Path>,
+) -> BgrResult<()> {
+    if let Some(dir) = &global.out_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    if inputs.len() < 2 {
+        return Ok(());
+    }
+    let output_dir = output
+        .filter(|path| !is_stdio(path))
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        });
+    let budget = output_budget(inputs, |input| {
+        output_dir.or_else(|| global.out_dir_for(input))
+    });
+    for (dir, needed) in budget {
+        check_writable(&dir)?;
+        let available = fs4::available_space(&dir)?;
+        if available < needed {
+            return Err(BgrError::Io(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "{} has {} MB free but this batch needs about {} MB; free some space or choose another --out-dir",
+                    dir.display(),
+                    available / 1_000_000,
+                    needed.div_ceil(1_000_000)
+                ),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The directories outputs are written to, each with the total size of its inputs.
+fn output_budget<'a>(
+    inputs: &'a [PathBuf],
+    out_dir: impl Fn(&'a Path) -> Option<&'a Path>,
+) -> Vec<(PathBuf, u64)> {
+    let mut budget: Vec<(PathBuf, u64)> = Vec::new();
+    for input in inputs {
+        let dir = match out_dir(input) {
+            Some(dir) => dir.to_path_buf(),
+            None => match input.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+        };
+        let size = std::fs::metadata(input).map_or(0, |metadata| metadata.len());
+        match budget.iter_mut().find(|(known, _)| *known == dir) {
+            Some((_, total)) => *total += size,
+            None => budget.push((dir, size)),
+        }
+    }
+    budget
+}
+
+/// Fail with an actionable message unless a file can be created in `dir`.
+fn check_writable(dir: &Path) -> BgrResult<()> {
+    bgr::models::check_writable(dir).map_err(|err| {
+        BgrError::Io(io::Error::new(
+            err.kind(),
+            format!(
+                "cannot write to {}: {err}; check its permissions or choose another --out-dir",
+                dir.display()
+            ),
+        ))
+    })
+}
+
+/// Tell the user when an explicitly requested accelerator could not be used.
+fn warn_on_device_fallback(requested: Device, source: &MatteSource) {
+    let MatteSource::Session(session) = source else {
+        return;
+    };
+    if !matches!(requested, Device::Cpu | Device::Auto) && session.device() != requested {
+        tracing::warn!(
+            "{}",
+            tr!(
+                "device-fallback",
+                requested = requested.to_string(),
+                device = session.device().to_string()
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod effective_jobs {
+        use super::*;
+
+        #[test]
+        fn capped_by_input_count() {
+            assert_eq!(effective_jobs(8, false, 3), 3);
+        }
+
+        #[test]
+        fn at_least_one() {
+            assert_eq!(effective_jobs(1, false, 0), 1);
+        }
+
+        #[test]
+        fn zero_uses_available_cores() {
+            let cores = thread::available_parallelism().map_or(1, |n| n.get());
+            assert_eq!(effective_jobs(0, false, 1000), cores.min(1000));
+        }
+
+        #[test]
+        fn low_memory_forces_serial() {
+            assert_eq!(effective_jobs(8, true, 100), 1);
+        }
+    }
+
+    mod collect_inputs {
+        use super::*;
+
+        #[test]
+        fn directories_expand_to_the_kind_read() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            for name in ["a.png", "b.bgrm", "c.txt", "d.bgrl"] {
+                std::fs::write(dir.join(name), b"").unwrap();
+            }
+            let dirs = [dir.to_path_buf()];
+            assert_eq!(
+                collect_inputs(&dirs, InputKind::Image).unwrap(),
+                [dir.join("a.png")]
+            );
+            assert_eq!(
+                collect_inputs(&dirs, InputKind::MatteContainer).unwrap(),
+                [dir.join("b.bgrm")]
+            );
+            assert_eq!(
+                collect_inputs(&dirs, InputKind::Mask).unwrap(),
+                [dir.join("a.png"), dir.join("d.bgrl")]
+            );
+        }
+    }
+
+    mod processing_order {
+        use super::*;
+
+        #[test]
+        fn size_groups_by_aspect_then_area() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let inputs: Vec<PathBuf> = [(64, 32), (16, 16), (128, 64), (32, 32)]
+                .iter()
+                .enumerate()
+                .map(|(i, &(width, height))| {
+                    let path = dir.join(format!("{i}.png"));
+                    RgbImage::new(width, height).save(&path).unwrap();
+                    path
+                })
+                .chain([dir.join("missing.png")])
+                .collect();
+            assert_eq!(
+                processing_order(&inputs, BatchOrderArg::Size),
+                [1, 3, 0, 2, 4]
+            );
+            assert_eq!(
+                processing_order(&inputs, BatchOrderArg::Input),
+                [0, 1, 2, 3, 4]
+            );
+        }
+    }
+
+    mod cpu_budget {
+        use super::*;
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        #[test]
+        fn caps_workers_and_threads() {
+            let mut cli = Cli::try_parse_from(["outline", "cut", "a.png", "-j", "0"]).unwrap();
+            CpuBudget::new(50, 8).limit(&mut cli.global);
+            assert_eq!(cli.global.jobs, 4);
+            assert_eq!(cli.global.intra_threads, Some(1));
+
+            let mut cli = Cli::try_parse_from(["outline", "cut", "a.png"]).unwrap();
+            CpuBudget::new(50, 8).limit(&mut cli.global);
+            assert_eq!(cli.global.jobs, 1);
+            assert_eq!(cli.global.intra_threads, Some(4));
+        }
+
+        #[test]
+        fn paces_only_over_budget() {
+            let busy = Duration::from_secs(1);
+            assert_eq!(CpuBudget::new(50, 8).pause(busy, 4), Duration::ZERO);
+            // Half of one core: rest as long as the work took.
+            assert_eq!(CpuBudget::new(50, 1).pause(busy, 1), busy);
+            // An explicit 8 threads on a 4-core budget.
+            assert_eq!(CpuBudget::new(50, 8).pause(busy, 8), busy);
+        }
+    }
+
+    mod file_report {
+        use super::*;
+
+        #[test]
+        fn error_only_serialized_on_failure() {
+            let ok = FileReport::new(Path::new("a.png"), &Ok(vec![Warning::EmptyMatte]));
+            assert_eq!(
+                serde_json::to_string(&ok).unwrap(),
+                r#"{"input":"a.png","warnings":[{"kind":"empty_matte"}]}"#
+            );
+            let failed = FileReport::new(
+                Path::new("b.png"),
+                &Err(BgrError::Trace("no contours".into())),
+            );
+            let json = serde_json::to_value(&failed).unwrap();
+            assert_eq!(json["warnings"], serde_json::json!([]));
+            assert!(json["error"].as_str().unwrap().contains("no contours"));
+        }
+    }
+
+    mod output_budget {
+        use super::*;
+
+        #[test]
+        fn grouped_by_directory() {
+            let inputs = vec![
+                PathBuf::from("a.png"),
+                PathBuf::from("shots/b.png"),
+                PathBuf::from("c.png"),
+            ];
+            let dirs: Vec<PathBuf> = output_budget(&inputs, |_| None)
+                .into_iter()
+                .map(|(dir, _)| dir)
+                .collect();
+            assert_eq!(dirs, [PathBuf::from("."), PathBuf::from("shots")]);
+        }
+
+        #[test]
+        fn out_dir_collects_every_input() {
+            let inputs = vec![PathBuf::from("a/x.png"), PathBuf::from("b/y.png")];
+            let budget = output_budget(&inputs, |_| Some(Path::new("out")));
+            assert_eq!(budget.len(), 1);
+            assert_eq!(budget[0].0, PathBuf::from("out"));
+        }
+
+        #[test]
+        fn input_sizes_summed() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let inputs: Vec<PathBuf> = [3usize, 4]
+                .iter()
+                .enumerate()
+                .map(|(i, &len)| {
+                    let path = dir.join(format!("{i}.png"));
+                    std::fs::write(&path, vec![0; len]).unwrap();
+                    path
+                })
+                .collect();
+            let budget = output_budget(&inputs, |_| None);
+            assert_eq!(budget, [(dir.to_path_buf(), 7)]);
+        }
+    }
+
+    mod check_writable {
+        use super::*;
+
+        #[test]
+        fn temp_dir_is_writable() {
+            assert!(check_writable(&std::env::temp_dir()).is_ok());
+        }
+
+        #[test]
+        fn missing_dir_names_the_directory() {
+            let dir = std::env::temp_dir().join("bgr-no-such-output-dir");
+            let err = check_writable(&dir).unwrap_err();
+            assert!(err.to_string().contains("bgr-no-such-output-dir"));
+        }
+    }
+
+    mod ensure_single_input {
+        use super::*;
+
+        #[test]
+        fn single_input_allows_explicit_output() {
+            let inputs = vec![PathBuf::from("a.png")];
+            assert!(ensure_single_input(&inputs, "--output", true).is_ok());
+        }
+
+        #[test]
+        fn batch_rejects_explicit_output() {
+            let inputs = vec![PathBuf::from("a.png"), PathBuf::from("b.png")];
+            assert!(ensure_single_input(&inputs, "--output", true).is_err());
+        }
+
+        #[test]
+        fn batch_without_explicit_output_ok() {
+            let inputs = vec![PathBuf::from("a.png"), PathBuf::from("b.png")];
+            assert!(ensure_single_input(&inputs, "--output", false).is_ok());
+        }
+    }
+}
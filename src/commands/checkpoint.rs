@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use bgr::Verbosity;
+use bgr::models::default_data_dir;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cli::GlobalOptions;
+use crate::i18n::tr;
+
+use super::utils::is_stdio;
+
+/// Directory of the data dir holding one journal per batch, named after what it writes.
+const JOURNALS_DIR: &str = "batches";
+
+/// The journal of the batch this process is running, if it is running one.
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+
+/// One line of the journal: an input whose main output was written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    input: PathBuf,
+    /// The input's size and modification time when it was processed.
+    stamp: InputStamp,
+    /// The output the input was asked to write; the journal is keyed by it.
+    output: PathBuf,
+    /// Where it was written, which differs under `--suffix`.
+    written: PathBuf,
+}
+
+/// What tells an input apart from a later edit of it, read from its metadata alone so
+/// recording an input costs no read of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct InputStamp {
+    len: u64,
+    modified_ns: u128,
+}
+
+impl InputStamp {
+    fn of(input: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(input)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH);
+        Ok(Self {
+            len: metadata.len(),
+            modified_ns: modified.map_or(0, |since| since.as_nanos()),
+        })
+    }
+}
+
+/// Append-only record of the inputs a batch has finished, so a rerun with `--resume` skips
+/// them.
+struct Journal {
+    path: PathBuf,
+    file: File,
+    done: HashMap<PathBuf, JournalEntry>,
+    resuming: bool,
+    quiet: bool,
+}
+
+impl Journal {
+    /// Start a journal at `path`, reading the entries already there when `resuming` and
+    /// starting over otherwise.
+    fn open(path: &Path, resuming: bool) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if !resuming {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                file: File::create(path)?,
+                done: HashMap::new(),
+                resuming,
+                quiet: false,
+            });
+        }
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        // A line cut short by a crash mid-write is simply forgotten.
+        let done = text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<JournalEntry>(line).ok())
+            .map(|entry| (entry.output.clone(), entry))
+            .collect();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if !text.is_empty() && !text.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            done,
+            resuming,
+            quiet: false,
+        })
+    }
+
+    fn record(&mut self, entry: JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::from)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.done.insert(entry.output.clone(), entry);
+        Ok(())
+    }
+}
+
+/// Where the journal of a batch of `inputs` into `out_dir` is kept: in the data directory,
+/// named after the working directory, the output directory, and the inputs, so batches
+/// writing different outputs never share one and a read-only working directory is no
+/// obstacle.
+fn journal_path(inputs: &[PathBuf], out_dir: Option<&Path>) -> PathBuf {
+    let mut hasher = Sha256::new();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    for path in [cwd.as_path(), out_dir.unwrap_or(Path::new("."))]
+        .into_iter()
+        .chain(inputs.iter().map(PathBuf::as_path))
+    {
+        hasher.update(path.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+    }
+    let digest = format!("{:x}", hasher.finalize());
+    default_data_dir()
+        .join(JOURNALS_DIR)
+        .join(format!("{}.jsonl", &digest[..16]))
+}
+
+/// Start the journal of a batch of `inputs`: read under `--resume`, and created afresh
+/// otherwise. A journal that cannot be opened only costs the batch its `--resume`.
+pub fn begin(global: &GlobalOptions, inputs: &[PathBuf]) {
+    let path = journal_path(inputs, global.out_dir.as_deref());
+    let resuming = global.checkpoint.resuming();
+    let journal = match Journal::open(&path, resuming) {
+        Ok(journal) => journal,
+        Err(err) => {
+            tracing::warn!(
+                "{}",
+                tr!(
+                    "batch-journal-unavailable",
+                    path = path.display().to_string(),
+                    error = err.to_string()
+                )
+            );
+            return;
+        }
+    };
+    let quiet = global.verbosity() <= Verbosity::Quiet;
+    *JOURNAL.lock().unwrap() = Some(Journal { quiet, ..journal });
+}
+
+/// Close the batch's journal, removing it when every input was done and pointing at
+/// `--resume` otherwise.
+pub fn finish(complete: bool) {
+    let Some(journal) = JOURNAL.lock().unwrap().take() else {
+        return;
+    };
+    if complete {
+        let _ = fs::remove_file(&journal.path);
+    } else if !journal.quiet {
+        eprintln!(
+            "{}",
+            tr!(
+                "batch-resume-hint",
+                path = journal.path.display().to_string()
+            )
+        );
+    }
+}
+
+/// Whether the batch is resuming an earlier run, whose interrupted outputs may be replaced.
+pub fn resuming() -> bool {
+    JOURNAL
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|journal| journal.resuming)
+}
+
+/// Where the run being resumed wrote `output` from `input`, when it did, the input has not
+/// changed since, and the file is still there.
+pub fn already_written(input: &Path, output: &Path) -> Option<PathBuf> {
+    let entry = JOURNAL.lock().unwrap().as_ref()?.done.get(output)?.clone();
+    let unchanged = entry.input == input
+        && entry.written.exists()
+        && InputStamp::of(input).is_ok_and(|stamp| stamp == entry.stamp);
+    unchanged.then_some(entry.written)
+}
+
+/// Note in the batch's journal that `input` wrote `output`, asked for as `requested`.
+pub fn record(input: &Path, requested: &Path, output: &Path) -> io::Result<()> {
+    if is_stdio(output) || JOURNAL.lock().unwrap().is_none() {
+        return Ok(());
+    }
+    // An input that cannot be read again, such as stdin, cannot be skipped on resume either.
+    let Ok(stamp) = InputStamp::of(input) else {
+        return Ok(());
+    };
+    let entry = JournalEntry {
+        input: input.to_path_buf(),
+        stamp,
+        output: requested.to_path_buf(),
+        written: output.to_path_buf(),
+    };
+    match JOURNAL.lock().unwrap().as_mut() {
+        Some(journal) => journal.record(entry),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(output: &str) -> JournalEntry {
+        JournalEntry {
+            input: PathBuf::from("a.jpg"),
+            stamp: InputStamp {
+                len: 1,
+                modified_ns: 0,
+            },
+            output: PathBuf::from(output),
+            written: PathBuf::from(output),
+        }
+    }
+
+    mod journal {
+        use super::*;
+
+        #[test]
+        fn resuming_keeps_entries_and_a_new_run_drops_them() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let path = dir.join("batches").join("journal.jsonl");
+            let mut journal = Journal::open(&path, false).unwrap();
+            journal.record(entry("a-foreground.png")).unwrap();
+            drop(journal);
+            let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+            torn.write_all(b"{\"input\":\"b.j").unwrap();
+
+            let journal = Journal::open(&path, true).unwrap();
+            assert!(journal.done.contains_key(Path::new("a-foreground.png")));
+            assert_eq!(journal.done.len(), 1);
+            drop(journal);
+
+            let journal = Journal::open(&path, false).unwrap();
+            assert!(journal.done.is_empty());
+            assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        }
+
+        #[test]
+        fn batches_writing_different_outputs_keep_separate_journals() {
+            let inputs = [PathBuf::from("a.jpg"), PathBuf::from("b.jpg")];
+            let path = journal_path(&inputs, None);
+            assert_eq!(path, journal_path(&inputs, None));
+            assert_ne!(path, journal_path(&inputs[..1], None));
+            assert_ne!(path, journal_path(&inputs, Some(Path::new("out"))));
+            assert!(path.starts_with(default_data_dir()));
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use bgr::BgrResult;
+use clap::CommandFactory;
+
+use crate::cli::{Cli, CompletionsCommand};
+
+/// Print the completion script for the requested shell.
+pub fn run(cmd: CompletionsCommand) -> BgrResult<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(cmd.shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use bgr::{BgrError, BgrResult};
+use clap::CommandFactory;
+
+use crate::cli::{Cli, ConfigAction, ConfigCommand};
+use crate::config_file::{self, ConfigFile};
+use crate::i18n::tr;
+
+/// Show or change the persistent defaults in the config file.
+pub fn run(cmd: ConfigCommand) -> BgrResult<()> {
+    if let ConfigAction::Path = cmd.action {
+        println!("{}", ConfigFile::default_path().display());
+        return Ok(());
+    }
+    let mut config = ConfigFile::load()?;
+    match cmd.action {
+        ConfigAction::Path => unreachable!("handled above"),
+        ConfigAction::List => {
+            for (name, value) in config.entries() {
+                println!("{name} = {value}");
+            }
+        }
+        ConfigAction::Get { key } => match config.get(&key) {
+            Some(value) => println!("{value}"),
+            None => return Err(BgrError::invalid_input(tr!("config-key-unset", key = key))),
+        },
+        ConfigAction::Set { key, value } => {
+            let key = config_file::key(&key).expect("clap accepts only known keys");
+            // A bad value is reported the way clap reports a bad flag.
+            key.check(&Cli::command(), &value)
+                .unwrap_or_else(|err| err.exit());
+            config.set(key, &value);
+            config.save()?;
+        }
+        ConfigAction::Unset { key } => {
+            if config.unset(&key) {
+                config.save()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod check_value {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn values_are_checked_like_flags() {
+                let root = Cli::command();
+                let check = |name, value| config_file::key(name).unwrap().check(&root, value);
+                assert!(check("device", "cuda").is_ok());
+                assert!(check("device", "abacus").is_err());
+                assert!(check("format", "webp").is_ok());
+                assert!(check("feather", "soft").is_err());
+                assert!(check("models-dir", "/srv/models").is_ok());
+            }
+        }
+    }
+}
@@ -1,64 +1,882 @@
-use bgr::{BgrResult, MaskHandle, MatteHandle};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use crate::cli::{AlphaFromArg, CutCommand, GlobalOptions};
+use bgr::{
+    Animation, Background, BgrError, BgrResult, CanvasOptions, ColorProfile, ColorSettings,
+    Compositor, Compression, Context, CpuCompositor, EncodeOptions, ImageMetadata, InferencedMatte,
+    InputLimits, LinearCompositor, MaskHandle, MaskVectorizer, MatteHandle, OutputFormat,
+    OutputLayers, PaletteOptions, PreviewOptions, RenderingIntent, ShadowOptions, StrokeOptions,
+    SubjectLayers, Warning, draft_matte, encode_for_profile, encode_output_for_profile,
+    encode_paletted_png, open_srgb, place_on_canvas, trim_to_subject,
+};
+#[cfg(not(feature = "vectorizer-vtracer"))]
+use bgr::{ContourOptions, ContourVectorizer};
+#[cfg(feature = "vectorizer-vtracer")]
+use bgr::{TraceOptions, VtracerSvgVectorizer};
+use image::{DynamicImage, Frame, GrayImage, ImageFormat, Luma, Rgb, Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::cli::{CompositorArg, CutCommand, EmitArg, GlobalOptions};
+use crate::i18n::tr;
 
+use super::baseline::{Baseline, Comparison};
+use super::batch::{
+    InputKind, MatteSource, collect_inputs, ensure_single_input, run_batch, run_batch_without_model,
+};
+use super::escalation::Escalation;
+use super::sidecar::{Stopwatch, SubjectStats, alpha_channel, local_model, write_sidecar};
 use super::utils::{
-    build_bgr, derive_variant_path, processing_requested, resolve_alpha_source,
-    resolve_export_path, warn_if_soft_conflict,
+    ExtentOutcome, MaskChoice, apply_extent_policy, build_bgr, derive_svg_path,
+    derive_variant_path, primary_output_path, processing_requested, refine_matte,
+    reject_target_profile, relocate, report, resolve_alpha_source, resolve_export_path,
+    side_output_path, warn_if_soft_conflict, write_claimed,
 };
 
+/// The color cutouts are flattened over for outputs without alpha, such as JPEG.
+const FLATTEN_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
 /// The main function to run the cut command.
-pub fn run(global: &GlobalOptions, cmd: CutCommand) -> BgrResult<()> {
-    let bgr = build_bgr(global, &cmd.mask_processing)?;
-    let session = bgr.for_image(&cmd.input)?;
-    let matte = session.matte();
-    let output_path = cmd
-        .output
-        .clone()
-        .unwrap_or_else(|| derive_variant_path(&cmd.input, "foreground", "png"));
-
-    let save_mask_path = resolve_export_path(&cmd.export_matte, &cmd.input, "matte");
-    let save_processed_mask_path = resolve_export_path(&cmd.export_mask, &cmd.input, "mask");
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: CutCommand) -> BgrResult<()> {
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let inputs = collect_inputs(&cmd.inputs, InputKind::Image)?;
+    ensure_single_input(&inputs, "--output", cmd.output.is_some())?;
+    ensure_single_input(
+        &inputs,
+        "--export-matte <PATH>",
+        matches!(cmd.export_matte, Some(Some(_))),
+    )?;
+    ensure_single_input(
+        &inputs,
+        "--export-mask <PATH>",
+        matches!(cmd.export_mask, Some(Some(_))),
+    )?;
+    ensure_single_input(
+        &inputs,
+        "--output-layers <PATH>",
+        matches!(cmd.output_layers, Some(Some(_))),
+    )?;
+    ensure_single_input(
+        &inputs,
+        "--subject-layers <PATH>",
+        matches!(cmd.subject_layers, Some(Some(_))),
+    )?;
+    ensure_single_input(
+        &inputs,
+        "--final <DRAFT>",
+        matches!(cmd.final_pass, Some(Some(_))),
+    )?;
+    ensure_single_input(&inputs, "--mask", cmd.mask.is_some())?;
 
-    let mut processed_mask: Option<MaskHandle> = None;
     let processing_requested = processing_requested(&cmd.mask_processing);
-
     let alpha_source = resolve_alpha_source(cmd.alpha_source, processing_requested);
 
-    let needs_processed_mask =
-        matches!(alpha_source, AlphaFromArg::Processed) || cmd.export_mask.is_some();
+    let needs_processed_mask = matches!(alpha_source, MaskChoice::Processed)
+        || cmd.export_mask.is_some()
+        || cmd.emits(EmitArg::Mask);
     if needs_processed_mask {
         warn_if_soft_conflict(&cmd.mask_processing, "processed output");
     }
 
+    // Load a background image once and fit it to each input.
+    let background = match (&cmd.bg_color, &cmd.bg_image) {
+        (Some(color), _) => Some(Background::Color(*color)),
+        (None, Some(path)) => Some(Background::Image(open_srgb(path, global.intent.into())?)),
+        (None, None) => None,
+    };
+
+    // `--final` reads its prior from the draft's alpha, which must be the model's own.
+    if cmd.draft {
+        let unsupported = [
+            ("--bg-color or --bg-image", background.is_some()),
+            ("mask processing options", processing_requested),
+        ];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, used)| *used) {
+            return Err(BgrError::invalid_input(format!(
+                "--draft does not support {flag}; apply them in the --final pass"
+            )));
+        }
+    }
+    if cmd.output_layers.is_some() || cmd.subject_layers.is_some() {
+        reject_target_profile(global, "--output-layers and --subject-layers files")?;
+    }
+    let color = global.color_settings()?;
+    let encoding = resolve_encoding(&cmd, &color)?;
+    let escalation = Escalation::new(ctx, global, &cmd, inputs.len())?;
+    let compositor = select_compositor(cmd.compositor, cmd.linear_light)?;
+    // Inputs decoded in the sandbox are not parsed in this process for their metadata or frames.
+    #[cfg(feature = "sandboxed-decode")]
+    let sandboxed = global.sandbox_decode;
+    #[cfg(not(feature = "sandboxed-decode"))]
+    let sandboxed = false;
+    let carry_metadata = !cmd.strip_metadata && !sandboxed;
+    let plan = CutPlan {
+        cmd: &cmd,
+        compositor: compositor.as_ref(),
+        alpha_source,
+        background: background.as_ref(),
+        palette: cmd.palette.map(|colors| PaletteOptions {
+            colors,
+            dither: cmd.dither,
+            alpha_dither: cmd.alpha_dither.into(),
+            ..PaletteOptions::default()
+        }),
+        preview: PreviewOptions::default(),
+        limits: (&global.limits).into(),
+        color,
+        encoding,
+        escalation,
+        carry_metadata,
+        sandboxed,
+        baseline: Baseline::new(&cmd),
+    };
+    if let Some(mask) = &cmd.mask {
+        let options = (&cmd.mask_input).into();
+        return run_batch_without_model(&inputs, global, |input| {
+            write_claimed(
+                global,
+                input,
+                output_path(ctx, global, &plan, input)?,
+                |output| {
+                    let stopwatch = Stopwatch::start();
+                    let inferred = bgr.for_image_with_mask(input, mask, &options)?;
+                    write_cut(
+                        ctx,
+                        global,
+                        &plan,
+                        input,
+                        output,
+                        inferred,
+                        stopwatch.inferred(),
+                    )
+                },
+            )
+        });
+    }
+    let result = run_batch(&bgr, &inputs, global, |session, input| {
+        cut_one(session, ctx, global, &plan, input)
+    });
+    if let Some(baseline) = &plan.baseline {
+        baseline.summarize(ctx);
+    }
+    result
+}
+
+/// How every input of a `cut` run is processed.
+struct CutPlan<'a> {
+    cmd: &'a CutCommand,
+    /// Composites the cutout and blurs masks.
+    compositor: &'a dyn Compositor,
+    alpha_source: MaskChoice,
+    background: Option<&'a Background>,
+    /// Quantize the output to a PNG-8 palette.
+    palette: Option<PaletteOptions>,
+    /// Draft shrinking and final-pass cropping for `--draft` and `--final`.
+    preview: PreviewOptions,
+    /// Limits for decoding animated inputs, which bypass the session's own decoder.
+    limits: InputLimits,
+    /// The color profile the cutout or composite is encoded in.
+    color: ColorSettings,
+    /// The encoder `--format`, `--quality`, and `--lossless` ask for, or the one a PNG, WebP,
+    /// AVIF, TIFF, or JPEG output path names; otherwise the path's extension picks the encoder.
+    encoding: Option<EncodeOptions>,
+    /// The model and refinement ladder of `--target-quality`.
+    escalation: Option<Escalation>,
+    /// Carry the input's EXIF data, ICC profile, and density over to the cutout.
+    carry_metadata: bool,
+    /// Inputs are decoded in the sandbox, so animated ones are recognized but never decoded.
+    sandboxed: bool,
+    /// The previous run whose unchanged outputs are not written again.
+    baseline: Option<Baseline>,
+}
+
+/// Decide how the output is encoded from `--format`, the `--output` extension, `--quality`,
+/// and `--lossless`.
+fn resolve_encoding(cmd: &CutCommand, color: &ColorSettings) -> BgrResult<Option<EncodeOptions>> {
+    let named = cmd.output.as_deref().and_then(OutputFormat::from_path);
+    let format = match (cmd.format.map(OutputFormat::from), named) {
+        (Some(format), Some(named)) if format != named => {
+            return Err(BgrError::invalid_input(format!(
+                "--format {} does not match the output extension, which names {}",
+                format.extension(),
+                named.extension()
+            )));
+        }
+        (format, named) => format.or(named),
+    };
+    let compression = match (cmd.quality, cmd.lossless) {
+        (Some(quality), _) => Compression::Quality(quality),
+        (None, true) => Compression::Lossless,
+        (None, false) => Compression::Default,
+    };
+    let format = match format {
+        Some(format) => format,
+        None if compression == Compression::Default => return Ok(None),
+        None if cmd.output.is_none() => OutputFormat::Png,
+        None => {
+            return Err(BgrError::invalid_input(
+                "--quality and --lossless need a PNG, WebP, AVIF, TIFF, or JPEG output",
+            ));
+        }
+    };
+    if cmd.palette.is_some() && format != OutputFormat::Png {
+        return Err(BgrError::invalid_input("--palette writes PNG output only"));
+    }
+    if matches!(compression, Compression::Quality(_))
+        && format != OutputFormat::Jpeg
+        && color.target != ColorProfile::Srgb
+    {
+        return Err(BgrError::invalid_input(
+            "--quality with --target-profile needs JPEG output",
+        ));
+    }
+    let options = EncodeOptions {
+        format,
+        compression,
+    };
+    options.check()?;
+    Ok(Some(options))
+}
+
+/// The compositor `--compositor` and `--linear-light` select, falling back to the CPU when
+/// the GPU cannot start.
+fn select_compositor(arg: CompositorArg, linear_light: bool) -> BgrResult<Box<dyn Compositor>> {
+    match arg {
+        CompositorArg::Cpu if linear_light => Ok(Box::new(LinearCompositor)),
+        CompositorArg::Cpu => Ok(Box::new(CpuCompositor)),
+        CompositorArg::Wgpu if linear_light => Err(BgrError::invalid_input(
+            "--linear-light composites on the CPU; drop --compositor wgpu",
+        )),
+        #[cfg(feature = "compositor-wgpu")]
+        CompositorArg::Wgpu => match bgr::WgpuCompositor::new() {
+            Ok(gpu) => Ok(Box::new(gpu)),
+            Err(err) => {
+                tracing::warn!("{}", tr!("compositor-fallback", error = err.to_string()));
+                Ok(Box::new(CpuCompositor))
+            }
+        },
+        #[cfg(not(feature = "compositor-wgpu"))]
+        CompositorArg::Wgpu => Err(BgrError::invalid_input(
+            "--compositor wgpu requires a build with the compositor-wgpu feature",
+        )),
+    }
+}
+
+/// Cut a single input unless its output exists.
+fn cut_one(
+    session: &mut MatteSource,
+    ctx: &Context,
+    global: &GlobalOptions,
+    plan: &CutPlan<'_>,
+    input: &Path,
+) -> BgrResult<Vec<Warning>> {
+    write_claimed(
+        global,
+        input,
+        output_path(ctx, global, plan, input)?,
+        |output| cut_into(session, ctx, global, plan, input, output),
+    )
+}
+
+/// Cut a single input into `output`, frame by frame if it is animated.
+fn cut_into(
+    session: &mut MatteSource,
+    ctx: &Context,
+    global: &GlobalOptions,
+    plan: &CutPlan<'_>,
+    input: &Path,
+    output: &Path,
+) -> BgrResult<Vec<Warning>> {
+    if plan.sandboxed {
+        if Animation::is_animated(input, &plan.limits)? {
+            return Err(BgrError::invalid_input(
+                "animated inputs are not decoded in the sandbox; drop --sandbox-decode to cut them",
+            ));
+        }
+    } else if let Some(animation) = Animation::open(input, &plan.limits)? {
+        return cut_animation(session, ctx, plan, animation, output);
+    }
+    let stopwatch = Stopwatch::start();
+    let inferred = if let Some(escalation) = &plan.escalation {
+        let escalated = escalation.run(session, global, input)?;
+        let target = escalation.target();
+        let model = if escalated.refined {
+            format!("{} + matting", escalated.model)
+        } else {
+            escalated.model
+        };
+        let (input, metric) = (input.display().to_string(), target.metric.name());
+        let score = format!("{:.3}", escalated.score);
+        if escalated.met {
+            report(
+                ctx,
+                tr!(
+                    "quality-target-met",
+                    input = input,
+                    metric = metric,
+                    score = score,
+                    model = model
+                ),
+            );
+        } else {
+            tracing::warn!(
+                "{}",
+                tr!(
+                    "quality-target-missed",
+                    input = input,
+                    metric = metric,
+                    score = score,
+                    target = target.value.to_string(),
+                    model = model
+                )
+            );
+        }
+        escalated.matte
+    } else if plan.cmd.draft {
+        session.for_image_draft(input, &plan.preview)?
+    } else if let Some(draft) = &plan.cmd.final_pass {
+        let draft = draft.clone().unwrap_or_else(|| draft_path(global, input));
+        let prior = draft_matte(&image::open(&draft)?);
+        session.for_image_with_prior(input, &prior, &plan.preview)?
+    } else {
+        session.for_image(input)?
+    };
+    write_cut(
+        ctx,
+        global,
+        plan,
+        input,
+        output,
+        inferred,
+        stopwatch.inferred(),
+    )
+}
+
+/// Where the cutout of `input` is written.
+fn output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    plan: &CutPlan<'_>,
+    input: &Path,
+) -> BgrResult<PathBuf> {
+    if let Some(output) = &plan.cmd.output {
+        return Ok(output.clone());
+    }
+    // `--final` finds the draft by its derived name.
+    if plan.cmd.draft {
+        return Ok(draft_path(global, input));
+    }
+    let extension = plan
+        .encoding
+        .map_or("png", |options| options.format.extension());
+    primary_output_path(
+        ctx,
+        global,
+        input,
+        derive_variant_path(input, "foreground", extension),
+    )
+}
+
+/// Save the cutout or composite with the planned encoder, or as the path's extension names,
+/// carrying over `metadata`.
+fn save_output(
+    image: DynamicImage,
+    path: &Path,
+    plan: &CutPlan<'_>,
+    metadata: &ImageMetadata,
+) -> BgrResult<()> {
+    let _span = tracing::debug_span!("encode", path = %path.display()).entered();
+    let format = match &plan.encoding {
+        Some(options) => options.format.image_format(),
+        None => ImageFormat::from_path(path)?,
+    };
+    let color = output_color(plan, metadata, format);
+    let encoded = match &plan.encoding {
+        Some(options) => encode_output_for_profile(&image, options, &color)?,
+        None => encode_for_profile(image, format, &color)?,
+    };
+    fs::write(path, metadata.embed(encoded))?;
+    Ok(())
+}
+
+/// The color settings an output is encoded with: with `--target-profile` left at sRGB, the
+/// input's own profile, converted back into and embedded, when the output can embed it.
+fn output_color(
+    plan: &CutPlan<'_>,
+    metadata: &ImageMetadata,
+    format: ImageFormat,
+) -> ColorSettings {
+    let embeddable = matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Tiff
+    );
+    let lossy = plan
+        .encoding
+        .is_some_and(|options| matches!(options.compression, Compression::Quality(_)));
+    match &metadata.icc {
+        Some(icc) if plan.color.target == ColorProfile::Srgb && embeddable && !lossy => {
+            ColorSettings {
+                target: ColorProfile::Icc(icc.clone()),
+                intent: plan.color.intent,
+            }
+        }
+        _ => plan.color.clone(),
+    }
+}
+
+/// The metadata the cutout of `input` carries over. A profile that decoding could not apply
+/// is dropped, as the pixels cannot be converted back into it.
+fn input_metadata(
+    global: &GlobalOptions,
+    plan: &CutPlan<'_>,
+    input: &Path,
+    warnings: &[Warning],
+) -> BgrResult<ImageMetadata> {
+    if !plan.carry_metadata || !plan.cmd.emits(EmitArg::Cut) {
+        return Ok(ImageMetadata::default());
+    }
+    let mut metadata = ImageMetadata::read(input, &plan.limits)?;
+    if !global.no_auto_orient {
+        metadata = metadata.upright();
+    }
+    if !cfg!(feature = "color-management") || warnings.contains(&Warning::IccProfileIgnored) {
+        metadata.icc = None;
+    }
+    Ok(metadata)
+}
+
+/// Where `--draft` writes, and `--final` reads, the preview of `input`.
+fn draft_path(global: &GlobalOptions, input: &Path) -> PathBuf {
+    relocate(
+        derive_variant_path(input, "draft", "png"),
+        global.out_dir_for(input),
+    )
+}
+
+/// Write the foreground of one input to `output_path` plus any requested exports.
+fn write_cut(
+    ctx: &Context,
+    global: &GlobalOptions,
+    plan: &CutPlan<'_>,
+    input: &Path,
+    output_path: &Path,
+    session: InferencedMatte,
+    stopwatch: Stopwatch,
+) -> BgrResult<Vec<Warning>> {
+    let CutPlan {
+        cmd,
+        compositor,
+        alpha_source,
+        background,
+        ref palette,
+        ..
+    } = *plan;
+    let session = match apply_extent_policy(&cmd.extent, session)? {
+        ExtentOutcome::Write(session) => session,
+        ExtentOutcome::Skip(warnings) => return Ok(warnings),
+    };
+    let matte = refine_matte(session.matte(), cmd.refine)?;
+
+    let side = |suffix: &str, extension: &str| {
+        let derived = derive_variant_path(input, suffix, extension);
+        side_output_path(ctx, global, input, suffix, derived)
+    };
+    let emitted = |artifact: EmitArg, suffix: &str| {
+        cmd.emits(artifact).then(|| side(suffix, "png")).transpose()
+    };
+    let save_mask_path = match resolve_export_path(&cmd.export_matte, || side("matte", "png"))? {
+        None => emitted(EmitArg::Matte, "matte")?,
+        path => path,
+    };
+    let save_processed_mask_path =
+        match resolve_export_path(&cmd.export_mask, || side("mask", "png"))? {
+            None => emitted(EmitArg::Mask, "mask")?,
+            path => path,
+        };
+    let mut written = Vec::new();
+    let layers_path = resolve_export_path(&cmd.output_layers, || side("layers", "exr"))?;
+    let subjects_path = resolve_export_path(&cmd.subject_layers, || side("subjects", "tif"))?;
+
+    let mut processed_mask: Option<MaskHandle> = None;
+
     let mut ensure_processed = |matte: &MatteHandle| -> BgrResult<MaskHandle> {
         if let Some(mask) = &processed_mask {
             Ok(mask.clone())
         } else {
-            let mask = matte.clone().processed()?;
+            let mask = matte.clone().processed_on(compositor)?;
             processed_mask = Some(mask.clone());
             Ok(mask)
         }
     };
 
     let foreground = match alpha_source {
-        AlphaFromArg::Raw => matte.foreground()?,
-        AlphaFromArg::Processed => ensure_processed(&matte)?.foreground()?,
-        AlphaFromArg::Auto => unreachable!(),
+        MaskChoice::Raw => matte.foreground_with(compositor)?,
+        MaskChoice::Processed => ensure_processed(&matte)?.foreground_with(compositor)?,
+    };
+    let foreground = if cmd.defringe {
+        foreground.defringed()
+    } else {
+        foreground
     };
+    let blurred = cmd
+        .blur_background
+        .map(|radius| foreground.blurred_background(radius));
+    // Formats without alpha get the cutout flattened, over white unless `--bg-color` says
+    // otherwise.
+    let flattened = plan
+        .encoding
+        .filter(|options| background.is_none() && blurred.is_none() && !options.format.has_alpha())
+        .map(|_| Background::Color(FLATTEN_COLOR));
+    let background = blurred.as_ref().or(background).or(flattened.as_ref());
+    // Only the cutout is stroked, shadowed, trimmed, or placed; layers and mask exports keep
+    // the input's frame and alpha.
+    let stroked = cmd.outline.map(|width| {
+        foreground.with_stroke(&StrokeOptions {
+            width,
+            color: cmd.outline_color,
+        })
+    });
+    let styled = stroked.as_ref().unwrap_or(&foreground);
+    let shadowed = cmd.shadow.then(|| {
+        styled.with_shadow(&ShadowOptions {
+            offset_x: cmd.shadow_offset.0,
+            offset_y: cmd.shadow_offset.1,
+            blur: cmd.shadow_blur,
+            opacity: cmd.shadow_opacity,
+            color: cmd.shadow_color,
+        })
+    });
+    let cast = shadowed.as_ref().unwrap_or(styled);
+    let placed = if let Some(options) = canvas_options(cmd) {
+        Some(cast.on_canvas(&options))
+    } else if cmd.trim {
+        Some(cast.trimmed(cmd.margin.unwrap_or_default()))
+    } else {
+        None
+    };
+    let cutout = placed.as_ref().unwrap_or(cast);
+    // The sidecar and summary measure the subject, not its shadow.
+    let subject = || match &shadowed {
+        Some(shadowed) => subject_alpha(cmd, styled.image(), shadowed.image()),
+        None => alpha_channel(cutout.image()),
+    };
+    let composite = match background {
+        Some(background) if cmd.emits(EmitArg::Cut) => {
+            Some(cutout.composite_with(background, compositor)?)
+        }
+        _ => None,
+    };
+    // An output that matches its baseline is not written again, nor are its exports.
+    if let (Some(baseline), true) = (&plan.baseline, cmd.emits(EmitArg::Cut)) {
+        let image = match &composite {
+            Some(composite) => DynamicImage::ImageRgb8(composite.clone()),
+            None => DynamicImage::ImageRgba8(cutout.image().clone()),
+        };
+        if baseline.compare(ctx, output_path, &image)? == Comparison::Unchanged {
+            return Ok(session.warnings().to_vec());
+        }
+    }
+    let metadata = input_metadata(global, plan, input, session.warnings())?;
+
+    match composite {
+        _ if !cmd.emits(EmitArg::Cut) => {}
+        Some(composite) => {
+            match palette {
+                Some(options) => {
+                    let rgba = DynamicImage::ImageRgb8(composite).into_rgba8();
+                    fs::write(
+                        output_path,
+                        metadata.embed(encode_paletted_png(&rgba, options)?),
+                    )?;
+                }
+                None => save_output(
+                    DynamicImage::ImageRgb8(composite),
+                    output_path,
+                    plan,
+                    &metadata,
+                )?,
+            }
+            report(
+                ctx,
+                tr!("saved-composite", path = output_path.display().to_string()),
+            );
+            written.push(output_path.to_path_buf());
+        }
+        None => {
+            match palette {
+                Some(options) => fs::write(
+                    output_path,
+                    metadata.embed(cutout.encode_paletted(options)?),
+                )?,
+                None => save_output(
+                    DynamicImage::ImageRgba8(cutout.image().clone()),
+                    output_path,
+                    plan,
+                    &metadata,
+                )?,
+            }
+            report(
+                ctx,
+                tr!("saved-foreground", path = output_path.display().to_string()),
+            );
+            written.push(output_path.to_path_buf());
+        }
+    }
+
+    let mut warnings = session.warnings().to_vec();
+    if flattened.is_some() && cmd.emits(EmitArg::Cut) {
+        warnings.push(Warning::AlphaFlattened {
+            color: FLATTEN_COLOR.0,
+        });
+    }
+
+    if cmd.json_sidecar && cmd.emits(EmitArg::Cut) {
+        let model = local_model(ctx, global).filter(|_| cmd.mask.is_none());
+        write_sidecar(
+            ctx,
+            input,
+            output_path,
+            &subject(),
+            model,
+            &warnings,
+            stopwatch,
+        )?;
+    }
 
-    foreground.save(&output_path)?;
-    println!("Foreground PNG saved to {}", output_path.display());
+    if let Some(path) = &layers_path {
+        OutputLayers::new(foreground.image().clone(), matte.raw())?.save(path)?;
+        report(ctx, tr!("saved-layers", path = path.display().to_string()));
+        written.push(path.clone());
+    }
+
+    if let Some(path) = &subjects_path {
+        let (width, height) = foreground.image().dimensions();
+        let min_pixels = cmd.min_subject_area.pixels(width, height);
+        let layers =
+            SubjectLayers::new(session.rgb_image().clone(), foreground.image(), min_pixels)?;
+        layers.save(path)?;
+        report(
+            ctx,
+            tr!(
+                "saved-subject-layers",
+                path = path.display().to_string(),
+                subjects = layers.subjects.len()
+            ),
+        );
+        written.push(path.clone());
+    }
 
     if let Some(path) = &save_mask_path {
         matte.clone().save(path)?;
-        println!("Matte PNG saved to {}", path.display());
+        report(ctx, tr!("saved-matte", path = path.display().to_string()));
+        written.push(path.clone());
     }
 
     if let Some(path) = &save_processed_mask_path {
         ensure_processed(&matte)?.save(path)?;
-        println!("Processed mask PNG saved to {}", path.display());
+        report(ctx, tr!("saved-mask", path = path.display().to_string()));
+        written.push(path.clone());
     }
 
-    Ok(())
+    if cmd.emits(EmitArg::Svg) {
+        let path = side_output_path(ctx, global, input, "outline", derive_svg_path(input))?;
+        let alpha = match alpha_source {
+            MaskChoice::Raw => matte.raw(),
+            MaskChoice::Processed => ensure_processed(&matte)?.raw(),
+        };
+        fs::write(&path, trace_svg(&alpha)?)?;
+        report(ctx, tr!("saved-svg", path = path.display().to_string()));
+        written.push(path);
+    }
+
+    if cmd.emits(EmitArg::Json) {
+        let path = side("summary", "json")?;
+        let (width, height) = cutout.image().dimensions();
+        let summary = Summary {
+            input,
+            width,
+            height,
+            subject: SubjectStats::measure(&subject()),
+            warnings: &warnings,
+            outputs: &written,
+        };
+        let json = serde_json::to_string_pretty(&summary).map_err(io::Error::from)?;
+        fs::write(&path, json)?;
+        report(ctx, tr!("saved-summary", path = path.display().to_string()));
+    }
+
+    Ok(warnings)
+}
+
+/// `--canvas` as placement options, when given.
+fn canvas_options(cmd: &CutCommand) -> Option<CanvasOptions> {
+    cmd.canvas.map(|(width, height)| CanvasOptions {
+        anchor: cmd.anchor,
+        fill: cmd.fill,
+        ..CanvasOptions::new(width, height)
+    })
+}
+
+/// The alpha of `subject` alone in the frame of the placed cutout cast from it as `shadowed`.
+///
+/// Trimming and canvas placement follow the shadowed alpha, so they are repeated on a copy of
+/// `shadowed` that carries the subject's alpha in its red channel.
+fn subject_alpha(cmd: &CutCommand, subject: &RgbaImage, shadowed: &RgbaImage) -> GrayImage {
+    let probe = RgbaImage::from_fn(shadowed.width(), shadowed.height(), |x, y| {
+        Rgba([
+            subject.get_pixel(x, y)[3],
+            0,
+            0,
+            shadowed.get_pixel(x, y)[3],
+        ])
+    });
+    let placed = if let Some(options) = canvas_options(cmd) {
+        place_on_canvas(&probe, &options)
+    } else if cmd.trim {
+        trim_to_subject(&probe, cmd.margin.unwrap_or_default())
+    } else {
+        probe
+    };
+    GrayImage::from_fn(placed.width(), placed.height(), |x, y| {
+        let pixel = placed.get_pixel(x, y);
+        Luma([pixel[0].min(pixel[3])])
+    })
+}
+
+/// Trace `alpha` with the default backend and settings of the trace command.
+fn trace_svg(alpha: &GrayImage) -> BgrResult<String> {
+    #[cfg(feature = "vectorizer-vtracer")]
+    {
+        VtracerSvgVectorizer.vectorize(alpha, &TraceOptions::default())
+    }
+    #[cfg(not(feature = "vectorizer-vtracer"))]
+    {
+        Ok(ContourVectorizer
+            .vectorize(alpha, &ContourOptions::default())?
+            .svg)
+    }
+}
+
+/// What `--emit json` records about one input.
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    input: &'a Path,
+    width: u32,
+    height: u32,
+    #[serde(flatten)]
+    subject: SubjectStats,
+    warnings: &'a [Warning],
+    /// The other artifacts written for the input.
+    outputs: &'a [PathBuf],
+}
+
+/// The options [`cut_animation`] cannot honor, each with a test for whether it is in use.
+const ANIMATION_UNSUPPORTED: &[(&str, fn(&CutPlan<'_>) -> bool)] = &[
+    ("--export-matte", |plan| plan.cmd.export_matte.is_some()),
+    ("--export-mask", |plan| plan.cmd.export_mask.is_some()),
+    ("--output-layers", |plan| plan.cmd.output_layers.is_some()),
+    ("--subject-layers", |plan| plan.cmd.subject_layers.is_some()),
+    ("--palette", |plan| plan.cmd.palette.is_some()),
+    ("--draft", |plan| plan.cmd.draft),
+    ("--final", |plan| plan.cmd.final_pass.is_some()),
+    ("--json-sidecar", |plan| plan.cmd.json_sidecar),
+    ("--trim", |plan| plan.cmd.trim),
+    ("--canvas", |plan| plan.cmd.canvas.is_some()),
+    ("--outline", |plan| plan.cmd.outline.is_some()),
+    ("--shadow", |plan| plan.cmd.shadow),
+    ("--target-profile", |plan| {
+        plan.color.target != ColorProfile::Srgb
+    }),
+    ("--intent", |plan| {
+        plan.color.intent != RenderingIntent::default()
+    }),
+    ("--target-quality", |plan| plan.escalation.is_some()),
+    ("--baseline", |plan| plan.baseline.is_some()),
+    (
+        "--format other than `png`, --quality, or --lossless",
+        |plan| {
+            plan.encoding
+                .is_some_and(|options| options != EncodeOptions::new(OutputFormat::Png))
+        },
+    ),
+    ("--emit other than `cut`", |plan| {
+        plan.cmd
+            .emit
+            .iter()
+            .any(|&artifact| artifact != EmitArg::Cut)
+    }),
+];
+
+/// Cut every frame of an animated input and write an animated PNG with the same timing.
+///
+/// Warnings are merged across frames, each reported once.
+fn cut_animation(
+    session: &mut MatteSource,
+    ctx: &Context,
+    plan: &CutPlan<'_>,
+    animation: Animation,
+    output_path: &Path,
+) -> BgrResult<Vec<Warning>> {
+    if let Some((flag, _)) = ANIMATION_UNSUPPORTED.iter().find(|(_, used)| used(plan)) {
+        return Err(BgrError::invalid_input(format!(
+            "animated inputs do not support {flag}"
+        )));
+    }
+    let cmd = plan.cmd;
+
+    let mut warnings: Vec<Warning> = Vec::new();
+    let mut frames = Vec::with_capacity(animation.frames().len());
+    for frame in animation.into_frames() {
+        let delay = frame.delay();
+        let source = frame.into_buffer();
+        let source_alpha: Vec<u8> = source.pixels().map(|pixel| pixel[3]).collect();
+        let inferred = session.for_rgb(DynamicImage::ImageRgba8(source).into_rgb8())?;
+        for warning in inferred.warnings() {
+            if !warnings.contains(warning) {
+                warnings.push(warning.clone());
+            }
+        }
+        let matte = refine_matte(inferred.matte(), cmd.refine)?;
+        let foreground = match plan.alpha_source {
+            MaskChoice::Raw => matte.foreground_with(plan.compositor)?,
+            MaskChoice::Processed => matte
+                .processed_on(plan.compositor)?
+                .foreground_with(plan.compositor)?,
+        };
+        let foreground = if cmd.defringe {
+            foreground.defringed()
+        } else {
+            foreground
+        };
+        let blurred = cmd
+            .blur_background
+            .map(|radius| foreground.blurred_background(radius));
+        let image = match blurred.as_ref().or(plan.background) {
+            Some(background) => {
+                DynamicImage::ImageRgb8(foreground.composite_with(background, plan.compositor)?)
+                    .into_rgba8()
+            }
+            None => {
+                let mut image = foreground.into_image();
+                // Pixels the source frame left transparent stay transparent.
+                for (pixel, alpha) in image.pixels_mut().zip(source_alpha) {
+                    pixel[3] = (u16::from(pixel[3]) * u16::from(alpha) / 255) as u8;
+                }
+                image
+            }
+        };
+        frames.push(Frame::from_parts(image, 0, 0, delay));
+    }
+
+    let count = frames.len();
+    Animation::from_frames(frames).save(output_path)?;
+    report(
+        ctx,
+        tr!(
+            "saved-animation",
+            path = output_path.display().to_string(),
+            frames = count
+        ),
+    );
+    Ok(warnings)
 }
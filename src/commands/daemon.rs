@@ -0,0 +1,27 @@
+use bgr::{Bgr, BgrResult, Context, Daemon};
+
+use crate::cli::{DaemonCommand, GlobalOptions};
+use crate::i18n::tr;
+
+use super::utils::report;
+
+/// The main function to run the daemon command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: DaemonCommand) -> BgrResult<()> {
+    let socket = cmd.socket.unwrap_or_else(bgr::default_socket_path);
+    // Load every session before binding so clients never wait on a cold model.
+    let pool = Bgr::from_context(ctx).session_pool(global.jobs)?;
+    let sessions = pool.size();
+    let mut daemon = Daemon::bind(socket, pool)?;
+    if let Some(cache) = cmd.cache.cache() {
+        daemon = daemon.with_cache(cache);
+    }
+    report(
+        ctx,
+        tr!(
+            "daemon-listening",
+            path = daemon.path().display().to_string(),
+            sessions = sessions
+        ),
+    );
+    daemon.serve()
+}
@@ -0,0 +1,355 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use bgr::models::{AUTO_MODEL, ModelPreset, file_sha256, recorded_sha256};
+use bgr::{Backend, Bgr, BgrError, BgrResult, Context, Device};
+use image::{Rgb, RgbImage};
+
+use crate::cli::GlobalOptions;
+use crate::i18n::tr;
+
+/// Devices with an accelerator to probe.
+const ACCELERATORS: [Device; 4] = [
+    Device::Cuda,
+    Device::CoreMl,
+    Device::DirectMl,
+    Device::Metal,
+];
+/// Side of the synthetic image the smoke test cuts out.
+const SMOKE_SIZE: u32 = 64;
+/// A downloaded preset smaller than this share of its listed size was cut off mid-download.
+const TRUNCATED_SHARE: f64 = 0.5;
+
+/// One thing the doctor looked at, and how to fix it when it is a problem.
+struct Check {
+    message: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(message: String) -> Self {
+        Self { message, fix: None }
+    }
+
+    fn problem(message: String, fix: String) -> Self {
+        Self {
+            message,
+            fix: Some(fix),
+        }
+    }
+
+    /// Print the finding, returning whether it is a problem.
+    fn print(&self) -> bool {
+        match &self.fix {
+            None => println!("  {}", tr!("doctor-ok", message = self.message.as_str())),
+            Some(fix) => {
+                println!("  {}", tr!("doctor-fail", message = self.message.as_str()));
+                println!("        {}", tr!("doctor-fix", fix = fix.as_str()));
+            }
+        }
+        self.fix.is_some()
+    }
+}
+
+/// The main function to run the doctor command: report the backend, accelerators, and
+/// models, then cut out a synthetic image with the run's model and settings.
+pub fn run(ctx: &Context, global: &GlobalOptions) -> BgrResult<()> {
+    println!(
+        "{}",
+        tr!(
+            "doctor-version",
+            version = env!("CARGO_PKG_VERSION"),
+            os = std::env::consts::OS,
+            arch = std::env::consts::ARCH
+        )
+    );
+    let settings = ctx.inference_settings();
+    let mut problems = 0;
+    let mut report = |check: Check| problems += usize::from(check.print());
+    backend_checks(settings.backend, settings.device)
+        .into_iter()
+        .for_each(&mut report);
+    models_checks(ctx).into_iter().for_each(&mut report);
+    let model = model_check(ctx, global);
+    let model_found = model.fix.is_none();
+    report(model);
+    // The smoke test would download a missing preset, which is not the doctor's call.
+    if model_found {
+        report(smoke_check(ctx, global));
+    }
+
+    if problems > 0 {
+        return Err(BgrError::Io(io::Error::other(tr!(
+            "doctor-problems",
+            count = problems
+        ))));
+    }
+    println!("{}", tr!("doctor-healthy"));
+    Ok(())
+}
+
+/// Whether the backend is compiled in, and which accelerators it can use here. Only the
+/// requested device can be a problem.
+fn backend_checks(backend: Backend, requested: Device) -> Vec<Check> {
+    if !backend.is_compiled() {
+        return vec![Check::problem(
+            tr!("doctor-backend-missing", backend = backend.name()),
+            tr!(
+                "doctor-fix-feature",
+                feature = format!("backend-{}", backend.name())
+            ),
+        )];
+    }
+    let mut checks = vec![Check::ok(match backend.runtime_version() {
+        Some(version) => tr!(
+            "doctor-backend-runtime",
+            backend = backend.name(),
+            version = version
+        ),
+        None => tr!("doctor-backend", backend = backend.name()),
+    })];
+    for device in ACCELERATORS {
+        let name = device.to_string();
+        let check = match (backend.device_available(device), device == requested) {
+            (Some(true), _) => Check::ok(tr!("doctor-device-available", device = name)),
+            (Some(false), false) => Check::ok(tr!("doctor-device-unusable", device = name)),
+            (None, false) => Check::ok(tr!("doctor-device-not-built", device = name)),
+            (Some(false), true) => Check::problem(
+                tr!("doctor-device-requested-unusable", device = name.as_str()),
+                tr!("doctor-fix-driver", device = name),
+            ),
+            (None, true) => Check::problem(
+                tr!("doctor-device-requested-not-built", device = name),
+                accelerator_fix(backend, device),
+            ),
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+/// How to get a build whose `backend`, or another one, runs on `device`.
+fn accelerator_fix(backend: Backend, device: Device) -> String {
+    let (feature, needs) = match (device, backend) {
+        (Device::Cuda, Backend::Candle) => ("candle-cuda", Backend::Candle),
+        (Device::Cuda, _) => ("cuda", Backend::Ort),
+        (Device::CoreMl, _) => ("coreml", Backend::Ort),
+        (Device::DirectMl, _) => ("directml", Backend::Ort),
+        _ => ("candle-metal", Backend::Candle),
+    };
+    if needs == backend {
+        tr!("doctor-fix-feature", feature = feature)
+    } else {
+        tr!(
+            "doctor-fix-backend",
+            feature = feature,
+            backend = needs.name()
+        )
+    }
+}
+
+/// Whether the models directory is writable, and the size and checksum of every downloaded
+/// preset, compared with the digest recorded when it was downloaded.
+fn models_checks(ctx: &Context) -> Vec<Check> {
+    let dir = ctx.models_dir();
+    let path = dir.display().to_string();
+    if !dir.exists() {
+        return vec![Check::ok(tr!("doctor-models-dir-missing", path = path))];
+    }
+    let mut checks = vec![match probe_writable(dir) {
+        Ok(()) => Check::ok(tr!("doctor-models-dir", path = path)),
+        Err(err) => Check::problem(
+            tr!(
+                "doctor-models-dir-unwritable",
+                path = path,
+                error = err.to_string()
+            ),
+            tr!("doctor-fix-models-dir"),
+        ),
+    }];
+    for preset in ModelPreset::ALL {
+        let file = preset.local_path(dir);
+        let Ok(metadata) = fs::metadata(&file) else {
+            continue;
+        };
+        let size_mb = metadata.len() as f64 / 1e6;
+        let name = preset.name();
+        let check = if size_mb < f64::from(preset.size_mb()) * TRUNCATED_SHARE {
+            Check::problem(
+                tr!(
+                    "doctor-preset-truncated",
+                    name = name,
+                    size = format!("{size_mb:.1}"),
+                    expected = preset.size_mb()
+                ),
+                tr!("doctor-fix-redownload", name = name),
+            )
+        } else {
+            checksum_check(name, &file, size_mb, recorded_sha256(preset, dir))
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+/// Compare the SHA-256 of a preset's `file` with the digest `recorded` at download, which
+/// was checked against the one HuggingFace publishes.
+fn checksum_check(name: &str, file: &Path, size_mb: f64, recorded: Option<String>) -> Check {
+    let size = format!("{size_mb:.0}");
+    match file_sha256(file) {
+        Ok(checksum) => match recorded {
+            Some(expected) if expected != checksum => Check::problem(
+                tr!(
+                    "doctor-preset-corrupt",
+                    name = name,
+                    expected = expected,
+                    checksum = checksum
+                ),
+                tr!("doctor-fix-redownload", name = name),
+            ),
+            Some(_) => Check::ok(tr!(
+                "doctor-preset",
+                name = name,
+                size = size,
+                checksum = checksum
+            )),
+            None => Check::ok(tr!(
+                "doctor-preset-unrecorded",
+                name = name,
+                size = size,
+                checksum = checksum
+            )),
+        },
+        Err(err) => Check::problem(
+            tr!(
+                "doctor-preset-unreadable",
+                name = name,
+                error = err.to_string()
+            ),
+            tr!("doctor-fix-redownload", name = name),
+        ),
+    }
+}
+
+/// Create and remove a file in `dir`, as a download would.
+fn probe_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".bgr-doctor-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(probe)
+}
+
+/// Whether the model `--model` names is on disk.
+fn model_check(ctx: &Context, global: &GlobalOptions) -> Check {
+    let preset = match global.model.as_str() {
+        AUTO_MODEL => Some(ModelPreset::auto(ctx.models_dir())),
+        name => ModelPreset::from_str(name),
+    };
+    match preset {
+        Some(preset) if preset.is_downloaded(ctx.models_dir()) => {
+            Check::ok(tr!("doctor-model", name = preset.name()))
+        }
+        Some(preset) => Check::problem(
+            tr!("doctor-model-missing", name = preset.name()),
+            tr!("doctor-fix-pull", name = preset.name()),
+        ),
+        None if Path::new(&global.model).is_file() => {
+            Check::ok(tr!("doctor-model", name = global.model.as_str()))
+        }
+        None => Check::problem(
+            tr!("doctor-model-file-missing", path = global.model.as_str()),
+            tr!("doctor-fix-model-path"),
+        ),
+    }
+}
+
+/// Load the model and cut out a synthetic image, as the first input of a run would.
+fn smoke_check(ctx: &Context, global: &GlobalOptions) -> Check {
+    let requested = ctx.inference_settings().device;
+    let started = Instant::now();
+    let device = ctx.resolve_model(&global.model).and_then(|model_path| {
+        let mut settings = ctx.inference_settings().clone();
+        settings.model_path = model_path;
+        let mut session =
+            Bgr::from_context(&ctx.clone().with_inference_settings(settings)).session()?;
+        session.for_rgb(smoke_image())?;
+        Ok(session.device())
+    });
+    match device {
+        // `auto` falls back to the CPU by design; an explicit accelerator should not.
+        Ok(Device::Cpu) if !matches!(requested, Device::Cpu | Device::Auto) => Check::problem(
+            tr!("doctor-smoke-fallback", device = requested.to_string()),
+            tr!("doctor-fix-driver", device = requested.to_string()),
+        ),
+        Ok(device) => Check::ok(tr!(
+            "doctor-smoke",
+            device = device.to_string(),
+            ms = started.elapsed().as_millis() as u64
+        )),
+        Err(err) => {
+            let preset = ModelPreset::from_str(&global.model);
+            Check::problem(
+                tr!("doctor-smoke-failed", error = err.to_string()),
+                match preset {
+                    Some(preset) => tr!("doctor-fix-redownload", name = preset.name()),
+                    None => tr!("doctor-fix-model-arch"),
+                },
+            )
+        }
+    }
+}
+
+/// A red disc on blue, small enough to cut out in a moment on any device.
+fn smoke_image() -> RgbImage {
+    let center = SMOKE_SIZE as f32 / 2.0;
+    RgbImage::from_fn(SMOKE_SIZE, SMOKE_SIZE, |x, y| {
+        let distance = (x as f32 - center).hypot(y as f32 - center);
+        if distance < center / 2.0 {
+            Rgb([220, 40, 40])
+        } else {
+            Rgb([40, 60, 200])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod accelerator_fix {
+        use super::*;
+
+        #[test]
+        fn names_the_feature_and_backend() {
+            assert!(accelerator_fix(Backend::Ort, Device::Cuda).contains("--features cuda"));
+            assert!(
+                accelerator_fix(Backend::Candle, Device::Cuda).contains("--features candle-cuda")
+            );
+            let metal = accelerator_fix(Backend::Ort, Device::Metal);
+            assert!(metal.contains("candle-metal") && metal.contains("--backend candle"));
+        }
+    }
+
+    mod checksum_check {
+        use super::*;
+
+        #[test]
+        fn fails_when_the_file_differs_from_its_recorded_digest() {
+            let temp = tempfile::tempdir().unwrap();
+            let file = temp.path().join("u2netp.onnx");
+            fs::write(&file, b"abc").unwrap();
+            let actual = file_sha256(&file).unwrap();
+
+            assert!(
+                checksum_check("u2netp", &file, 0.0, Some(actual))
+                    .fix
+                    .is_none()
+            );
+            assert!(checksum_check("u2netp", &file, 0.0, None).fix.is_none());
+            let corrupt = checksum_check("u2netp", &file, 0.0, Some("ab12".to_string()));
+            assert!(corrupt.fix.is_some());
+            assert!(corrupt.message.contains("ab12"));
+        }
+    }
+}
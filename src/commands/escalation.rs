@@ -0,0 +1,184 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use bgr::models::{ModelPreset, Priority, UseCase};
+use bgr::{
+    Bgr, BgrResult, Context, InferencedMatte, MaskProcessingOptions, MattingOptions, QualityMetric,
+    QualityTarget, SessionPool,
+};
+
+use crate::cli::{CutCommand, GlobalOptions};
+
+use super::batch::{MatteSource, effective_jobs};
+use super::utils::{requested_model, settings_for_model};
+
+/// A model to escalate to, which is resolved, downloaded if need be, and loaded the first time
+/// an image needs it.
+struct Rung {
+    name: String,
+    pool: Mutex<Option<Arc<SessionPool>>>,
+}
+
+/// The `--target-quality` ladder: `--model`, then each `--escalate` model, each tried as is
+/// and then, for `edge-gradient`, refined with alpha matting.
+pub struct Escalation {
+    ctx: Context,
+    mask_processing: MaskProcessingOptions,
+    target: QualityTarget,
+    /// Most attempts per image.
+    budget: usize,
+    primary: String,
+    rungs: Vec<Rung>,
+    /// Sessions per escalation model, one for each batch worker.
+    pool_size: usize,
+}
+
+/// The matte an escalation settled on.
+pub struct Escalated {
+    pub matte: InferencedMatte,
+    pub model: String,
+    /// Whether the matte was refined with alpha matting.
+    pub refined: bool,
+    pub score: f32,
+    /// Whether the score reached the target; otherwise this is the best of the budget.
+    pub met: bool,
+}
+
+impl Escalation {
+    /// The ladder `cmd` asks for, or `None` without `--target-quality`.
+    pub fn new(
+        ctx: &Context,
+        global: &GlobalOptions,
+        cmd: &CutCommand,
+        inputs: usize,
+    ) -> BgrResult<Option<Self>> {
+        let Some(target) = cmd.target_quality else {
+            return Ok(None);
+        };
+        let names = if cmd.escalate.is_empty() {
+            default_models(requested_model(ctx, global), ctx)
+        } else {
+            cmd.escalate.clone()
+        };
+        let rungs: Vec<Rung> = names
+            .into_iter()
+            .map(|name| Rung {
+                name,
+                pool: Mutex::new(None),
+            })
+            .collect();
+        let attempts = refinements(target.metric).len() * (rungs.len() + 1);
+        Ok(Some(Self {
+            ctx: ctx.clone(),
+            mask_processing: (&cmd.mask_processing).into(),
+            target,
+            budget: cmd
+                .quality_budget
+                .map_or(attempts, |budget| attempts.min(budget as usize)),
+            primary: requested_model(ctx, global).to_string(),
+            rungs,
+            pool_size: effective_jobs(global.jobs, global.low_memory, inputs),
+        }))
+    }
+
+    /// The score each image is escalated towards.
+    pub fn target(&self) -> QualityTarget {
+        self.target
+    }
+
+    /// Climb the ladder for `input` until a matte meets the target or the budget runs out,
+    /// returning the first matte that met it or else the best scoring one.
+    pub fn run(
+        &self,
+        session: &mut MatteSource,
+        global: &GlobalOptions,
+        input: &Path,
+    ) -> BgrResult<Escalated> {
+        let mut attempts = 0;
+        let mut best: Option<Escalated> = None;
+        for step in 0..=self.rungs.len() {
+            if attempts == self.budget {
+                break;
+            }
+            let (model, inferred) = match step {
+                0 => (&self.primary, session.for_image(input)?),
+                _ => {
+                    let rung = &self.rungs[step - 1];
+                    let pool = self.pool(rung, global)?;
+                    (&rung.name, pool.run(|pooled| pooled.for_image(input))?)
+                }
+            };
+            for &refined in refinements(self.target.metric) {
+                if attempts == self.budget {
+                    break;
+                }
+                attempts += 1;
+                let matte = if refined {
+                    inferred
+                        .clone()
+                        .refine_matting(&MattingOptions::default())?
+                } else {
+                    inferred.clone()
+                };
+                let score = matte.quality(self.target.metric);
+                let met = score >= self.target.value;
+                if best.as_ref().is_none_or(|best| score > best.score) {
+                    best = Some(Escalated {
+                        matte,
+                        model: model.clone(),
+                        refined,
+                        score,
+                        met,
+                    });
+                }
+                if met {
+                    return Ok(best.expect("just set"));
+                }
+            }
+        }
+        Ok(best.expect("the budget allows at least one attempt"))
+    }
+
+    /// The sessions of `rung`, one per batch worker, resolving its model on first use.
+    fn pool(&self, rung: &Rung, global: &GlobalOptions) -> BgrResult<Arc<SessionPool>> {
+        let mut pool = rung.pool.lock().expect("session pool lock poisoned");
+        if let Some(pool) = pool.as_ref() {
+            return Ok(Arc::clone(pool));
+        }
+        let settings = settings_for_model(&self.ctx, global, &rung.name)?;
+        let ctx = self.ctx.clone().with_inference_settings(settings);
+        let bgr =
+            Bgr::from_context(&ctx).with_default_mask_processing(self.mask_processing.clone());
+        let loaded = Arc::new(bgr.session_pool(self.pool_size)?);
+        *pool = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+}
+
+/// Whether to try each model's matte as is and refined. Alpha matting only softens a matte's
+/// edges, so it can never raise `confidence` and is skipped for it.
+fn refinements(metric: QualityMetric) -> &'static [bool] {
+    match metric {
+        QualityMetric::EdgeGradient => &[false, true],
+        QualityMetric::Confidence => &[false],
+    }
+}
+
+/// The downloaded general-purpose presets, fastest first, that are at least as good as
+/// `primary`; presets with a restrictive license are left out.
+fn default_models(primary: &str, ctx: &Context) -> Vec<String> {
+    let primary = ModelPreset::from_str(primary);
+    ModelPreset::ranked(UseCase::General, Priority::Speed)
+        .into_iter()
+        .filter(|&preset| {
+            let meta = preset.metadata();
+            meta.use_case == UseCase::General
+                && !meta.license.is_restrictive()
+                && preset.is_downloaded(ctx.models_dir())
+                && primary.is_none_or(|primary| {
+                    preset != primary && meta.quality >= primary.metadata().quality
+                })
+        })
+        .map(|preset| preset.name().to_string())
+        .collect()
+}
@@ -0,0 +1,26 @@
+use bgr::BgrResult;
+
+use crate::cli::ExamplesCommand;
+use crate::examples;
+
+/// The main function to run the examples command.
+pub fn run(cmd: ExamplesCommand) -> BgrResult<()> {
+    let commands = match cmd.command {
+        Some(command) => vec![command],
+        None => examples::commands()
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+    };
+    let sections: Vec<String> = commands
+        .iter()
+        .map(|command| {
+            format!(
+                "{command}:\n{}",
+                examples::render(&examples::for_command(command))
+            )
+        })
+        .collect();
+    println!("{}", sections.join("\n"));
+    Ok(())
+}
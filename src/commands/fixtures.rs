@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bgr::testing::{GoldenHarness, fixtures};
+use bgr::{Bgr, BgrError, BgrResult, Context};
+
+use crate::cli::{FixturesAction, FixturesCommand, GlobalOptions};
+use crate::i18n::tr;
+
+use super::utils::{report, settings_for_model};
+
+/// The main function to run the fixtures command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: FixturesCommand) -> BgrResult<()> {
+    match cmd.action {
+        FixturesAction::Generate { model } => generate(ctx, global, &model),
+    }
+}
+
+/// Cut out the standard fixtures with `model`, write every input with its ideal and actual
+/// cutout plus `report.json`, and fail when any cutout misses its ideal one.
+fn generate(ctx: &Context, global: &GlobalOptions, model: &str) -> BgrResult<()> {
+    let settings = settings_for_model(ctx, global, model)?;
+    let bgr = Bgr::from_context(&ctx.clone().with_inference_settings(settings));
+    let dir = global
+        .out_dir
+        .as_deref()
+        .unwrap_or_else(|| Path::new("fixtures"));
+    fs::create_dir_all(dir)?;
+
+    let fixtures = fixtures();
+    let compatibility = GoldenHarness::new(&bgr)?.report(&fixtures)?;
+    for (fixture, result) in fixtures.iter().zip(&compatibility.fixtures) {
+        fixture
+            .image
+            .save(dir.join(format!("{}.png", fixture.name)))?;
+        fixture
+            .expected
+            .save(dir.join(format!("{}-expected.png", fixture.name)))?;
+        result
+            .cutout
+            .save(dir.join(format!("{}-actual.png", fixture.name)))?;
+        if result.passed() {
+            report(ctx, tr!("status-ok", name = fixture.name));
+        } else {
+            let mismatches = result.mismatches.join("; ");
+            report(
+                ctx,
+                tr!("status-failed", name = fixture.name, reason = mismatches),
+            );
+        }
+        if result.inverted {
+            tracing::warn!("{}", tr!("fixtures-inverted", name = fixture.name));
+        }
+    }
+    if !compatibility.deterministic {
+        tracing::warn!("{}", tr!("fixtures-nondeterministic"));
+    }
+
+    let path = dir.join("report.json");
+    fs::write(
+        &path,
+        serde_json::to_string_pretty(&compatibility).map_err(io::Error::from)?,
+    )?;
+    report(
+        ctx,
+        tr!("fixtures-written", path = path.display().to_string()),
+    );
+
+    if compatibility.passed() {
+        return Ok(());
+    }
+    let failed = compatibility
+        .fixtures
+        .iter()
+        .filter(|result| !result.passed())
+        .count();
+    // A nondeterministic model fails the run even when every cutout matched.
+    Err(BgrError::FixturesFailed {
+        failed: failed.max(1),
+        total: compatibility.fixtures.len(),
+    })
+}
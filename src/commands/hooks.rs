@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use bgr::{BgrError, BgrResult};
+use serde::Serialize;
+
+use super::batch::FileReport;
+
+/// What a `--before` hook reads on stdin.
+#[derive(Debug, Serialize)]
+pub struct BatchStarted<'a> {
+    pub inputs: &'a [PathBuf],
+    pub total: usize,
+}
+
+/// What an `--after` hook reads on stdin.
+#[derive(Debug, Serialize)]
+pub struct BatchFinished<'a> {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Inputs left alone under `--skip-existing`, counted among `succeeded`.
+    pub skipped: usize,
+    /// Whether Ctrl-C stopped the batch; `files` then covers only the inputs it started.
+    pub cancelled: bool,
+    pub seconds: f64,
+    pub files: &'a [FileReport],
+}
+
+/// Run `command` through the shell with `summary` as JSON on stdin, failing unless it exits
+/// successfully. `flag` names the hook in the error.
+pub fn run_hook(flag: &str, command: &str, summary: &impl Serialize) -> BgrResult<()> {
+    let json = serde_json::to_vec(summary).map_err(io::Error::from)?;
+    let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its input may exit before reading it.
+        match stdin.write_all(&json) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {}
+        }
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(BgrError::Io(io::Error::other(format!(
+            "{flag} hook `{command}` failed with {status}"
+        ))))
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod run_hook {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            #[cfg(unix)]
+            fn hooks_read_the_summary_on_stdin() {
+                let temp = tempfile::tempdir().unwrap();
+                let path = temp.path().join("summary.json");
+                let inputs = [PathBuf::from("a.jpg")];
+                let summary = BatchStarted {
+                    inputs: &inputs,
+                    total: 1,
+                };
+                run_hook("--before", &format!("cat > '{}'", path.display()), &summary).unwrap();
+                assert_eq!(
+                    std::fs::read_to_string(&path).unwrap(),
+                    r#"{"inputs":["a.jpg"],"total":1}"#
+                );
+            }
+
+            #[test]
+            #[cfg(unix)]
+            fn failing_hooks_are_errors() {
+                let summary = BatchStarted {
+                    inputs: &[],
+                    total: 0,
+                };
+                let err = run_hook("--after", "exit 3", &summary).unwrap_err();
+                assert!(err.to_string().contains("--after hook `exit 3` failed"));
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use bgr::BgrResult;
+use clap::CommandFactory;
+use clap_mangen::Man;
+
+use crate::cli::{Cli, ManpageCommand};
+
+/// Print the man page, or write the pages of every subcommand into a directory.
+pub fn run(cmd: ManpageCommand) -> BgrResult<()> {
+    let command = Cli::command();
+    match cmd.dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            clap_mangen::generate_to(command, dir)?;
+        }
+        None => Man::new(command).render(&mut std::io::stdout())?,
+    }
+    Ok(())
+}
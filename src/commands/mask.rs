@@ -1,45 +1,446 @@
-use bgr::BgrResult;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::cli::{GlobalOptions, MaskCommand, MaskExportSource};
+use bgr::{
+    Bgr, BgrError, BgrResult, ComponentArea, Context, InferencedMatte, MaskProcessingOptions,
+    MatteContainer, MatteDepth, MatteProvenance, PreciseMatte, SelectionFormat, StorageBenchmark,
+    StorageFormat, Verbosity, Warning,
+};
+
+use crate::cli::{GlobalOptions, MaskCommand, MaskFormatArg, MatteStorageArg};
+use crate::i18n::tr;
 
+use super::batch::{
+    InputKind, MatteSource, collect_inputs, ensure_single_input, run_batch, run_batch_without_model,
+};
+use super::sidecar::{Stopwatch, local_model, write_sidecar};
 use super::utils::{
-    build_bgr, derive_variant_path, processing_requested, resolve_mask_export_source,
-    warn_if_soft_conflict,
+    ExtentOutcome, MaskChoice, apply_extent_policy, build_bgr, container_input,
+    derive_variant_path, is_stdio, primary_output_path, processing_requested, read_matte_container,
+    refine_matte, report, resolve_mask_export_source, warn_if_soft_conflict, write_claimed,
 };
 
-// Resolved by helper in utils now.
+/// One input of a mask run and where its output goes, with the provenance of a matte piped
+/// in from another command.
+struct MaskInput<'a> {
+    path: &'a Path,
+    output: &'a Path,
+    upstream: Option<MatteProvenance>,
+}
+
+impl<'a> MaskInput<'a> {
+    fn new(path: &'a Path, output: &'a Path) -> Self {
+        Self {
+            path,
+            output,
+            upstream: None,
+        }
+    }
+}
 
 /// The main function to run the mask command.
-pub fn run(global: &GlobalOptions, cmd: MaskCommand) -> BgrResult<()> {
-    let bgr = build_bgr(global, &cmd.mask_processing)?;
-    let session = bgr.for_image(&cmd.input)?;
-    let matte = session.matte();
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: MaskCommand) -> BgrResult<()> {
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let inputs = collect_inputs(&cmd.inputs, InputKind::read_by(cmd.from, cmd.from_mask))?;
+    ensure_single_input(&inputs, "--output", cmd.output.is_some())?;
+    if cmd.output.as_deref().is_some_and(is_stdio) && cmd.format != MaskFormatArg::Bgrm {
+        return Err(BgrError::invalid_input(
+            "-o - writes to stdout only with --format bgrm",
+        ));
+    }
+
     let mask_source =
         resolve_mask_export_source(cmd.mask_source, processing_requested(&cmd.mask_processing));
+    if matches!(mask_source, MaskChoice::Processed) {
+        warn_if_soft_conflict(&cmd.mask_processing, "output");
+    }
 
-    let default_suffix = match mask_source {
-        MaskExportSource::Processed => "mask",
-        MaskExportSource::Raw => "matte",
-        MaskExportSource::Auto => unreachable!(),
+    let depth = cmd.depth.map(MatteDepth::from).unwrap_or_default();
+    if depth != MatteDepth::Eight || cmd.storage.is_some() {
+        if cmd.format == MaskFormatArg::Bgrm {
+            return Err(BgrError::invalid_input(
+                "bgrm streams hold 8-bit mattes; drop --depth",
+            ));
+        }
+        if matches!(mask_source, MaskChoice::Processed) {
+            return Err(BgrError::invalid_input(
+                "--depth and --storage write the raw matte; drop the mask processing flags or use --mask-source raw",
+            ));
+        }
+        let (format, sampled) = match cmd.storage.map(MatteStorageArg::format) {
+            Some(None) => choose_storage(ctx, &bgr, global, &cmd, &inputs)?,
+            Some(Some(format)) => (format, HashMap::new()),
+            None => (StorageFormat::from(depth), HashMap::new()),
+        };
+        let sampled = Mutex::new(sampled);
+        let extension = format.extension();
+        return run_batch(&bgr, &inputs, global, |session, input| {
+            let output = output_path(ctx, global, &cmd, mask_source, extension, input)?;
+            write_claimed(global, input, output, |output| {
+                // The mattes `--storage auto` benchmarked are written rather than inferred again.
+                let sample = sampled.lock().unwrap().remove(input);
+                let (matte, stopwatch) = match sample {
+                    Some((matte, inference)) => (matte, Stopwatch::inferred_earlier(inference)),
+                    None => {
+                        let stopwatch = Stopwatch::start();
+                        let matte = session.for_image_precise(input)?;
+                        (matte, stopwatch.inferred())
+                    }
+                };
+                let input = MaskInput::new(input, output);
+                precise_one(ctx, global, &cmd, format, input, matte, stopwatch)
+            })
+        });
+    }
+    let extension = depth.extension();
+    if cmd.from.is_some() {
+        return run_batch_without_model(&inputs, global, |input| {
+            let stopwatch = Stopwatch::start();
+            let container = read_matte_container(ctx, input)?;
+            let path = container_input(input, &container);
+            let output = output_path(ctx, global, &cmd, mask_source, extension, &path)?;
+            write_claimed(global, input, output, |output| {
+                let upstream = Some(container.provenance.clone());
+                let inferred = bgr.for_matte_container(container)?;
+                let stopwatch = stopwatch.inferred();
+                let input = MaskInput {
+                    path: &path,
+                    output,
+                    upstream,
+                };
+                mask_one(ctx, global, &cmd, mask_source, input, inferred, stopwatch)
+            })
+        });
+    }
+    if cmd.from_mask {
+        let options = (&cmd.mask_input).into();
+        return run_batch_without_model(&inputs, global, |input| {
+            let output = output_path(ctx, global, &cmd, mask_source, extension, input)?;
+            write_claimed(global, input, output, |output| {
+                let stopwatch = Stopwatch::start();
+                let inferred = bgr.for_mask(input, &options)?;
+                let stopwatch = stopwatch.inferred();
+                mask_one(
+                    ctx,
+                    global,
+                    &cmd,
+                    mask_source,
+                    MaskInput::new(input, output),
+                    inferred,
+                    stopwatch,
+                )
+            })
+        });
+    }
+    run_batch(&bgr, &inputs, global, |session, input| {
+        let output = output_path(ctx, global, &cmd, mask_source, extension, input)?;
+        write_claimed(global, input, output, |output| {
+            let stopwatch = Stopwatch::start();
+            let inferred = session.for_image(input)?;
+            let stopwatch = stopwatch.inferred();
+            mask_one(
+                ctx,
+                global,
+                &cmd,
+                mask_source,
+                MaskInput::new(input, output),
+                inferred,
+                stopwatch,
+            )
+        })
+    })
+}
+
+/// Where the matte, mask, or selection of `input` is written.
+fn output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &MaskCommand,
+    mask_source: MaskChoice,
+    matte_extension: &str,
+    input: &Path,
+) -> BgrResult<PathBuf> {
+    if let Some(output) = &cmd.output {
+        return Ok(output.clone());
+    }
+    let format_extension = match cmd.format {
+        MaskFormatArg::Png => matte_extension,
+        MaskFormatArg::Bgrm => "bgrm",
     };
-    let output_path = cmd
-        .output
-        .clone()
-        .unwrap_or_else(|| derive_variant_path(&cmd.input, default_suffix, "png"));
-
-    match mask_source {
-        MaskExportSource::Processed => {
-            warn_if_soft_conflict(&cmd.mask_processing, "output");
-            let mask = matte.clone().processed()?;
-            mask.save(&output_path)?;
-            println!("Processed mask PNG saved to {}", output_path.display());
+    let (suffix, extension) = match (cmd.selection.map(SelectionFormat::from), mask_source) {
+        (Some(format), _) => ("selection", format.extension()),
+        (None, MaskChoice::Processed) => ("mask", format_extension),
+        (None, MaskChoice::Raw) => ("matte", format_extension),
+    };
+    primary_output_path(
+        ctx,
+        global,
+        input,
+        derive_variant_path(input, suffix, extension),
+    )
+}
+
+/// Export the matte or processed mask for a single input.
+fn mask_one(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &MaskCommand,
+    mask_source: MaskChoice,
+    input: MaskInput,
+    session: InferencedMatte,
+    stopwatch: Stopwatch,
+) -> BgrResult<Vec<Warning>> {
+    let session = match apply_extent_policy(&cmd.extent, session)? {
+        ExtentOutcome::Write(session) => session,
+        ExtentOutcome::Skip(warnings) => return Ok(warnings),
+    };
+    let matte = refine_matte(session.matte(), cmd.refine)?;
+    let MaskInput {
+        path: input,
+        output: output_path,
+        upstream,
+    } = input;
+    let selection = cmd.selection.map(SelectionFormat::from);
+
+    let written = if cmd.format == MaskFormatArg::Bgrm {
+        let (samples, processing) = match mask_source {
+            MaskChoice::Processed => {
+                let mask = matte.clone().processed()?;
+                let options = (&cmd.mask_processing).into();
+                (mask.raw(), Some(processing_summary(&options)))
+            }
+            MaskChoice::Raw => (matte.raw(), None),
+        };
+        let mut provenance = upstream.unwrap_or_else(|| MatteProvenance {
+            source: Some(input.display().to_string()),
+            source_size: samples.dimensions(),
+            model: local_model(ctx, global)
+                .filter(|_| !cmd.from_mask)
+                .map(str::to_string),
+            ..MatteProvenance::default()
+        });
+        let settings = &mut provenance.settings;
+        let source = match processing {
+            Some(processing) => {
+                settings.insert("processing".into(), processing);
+                "processed"
+            }
+            None => "raw",
+        };
+        settings.insert("mask-source".into(), source.into());
+        if let Some(refine) = cmd.refine {
+            settings.insert("refine".into(), format!("{refine:?}").to_lowercase());
+        }
+        let container = MatteContainer {
+            matte: samples.clone(),
+            provenance,
+        };
+        if is_stdio(output_path) {
+            container.write_to(io::stdout().lock())?;
+        } else {
+            fs::write(output_path, container.encode()?)?;
+            let path = output_path.display().to_string();
+            match mask_source {
+                MaskChoice::Processed => report(ctx, tr!("saved-mask", path = path)),
+                _ => report(ctx, tr!("saved-matte", path = path)),
+            }
         }
-        MaskExportSource::Auto => unreachable!(),
-        MaskExportSource::Raw => {
-            matte.save(&output_path)?;
-            println!("Matte PNG saved to {}", output_path.display());
+        samples
+    } else if let Some(format) = selection {
+        let written = match mask_source {
+            MaskChoice::Processed => {
+                let mask = matte.clone().processed()?;
+                mask.save_selection(output_path, format)?;
+                mask.raw()
+            }
+            MaskChoice::Raw => {
+                matte.save_selection(output_path, format)?;
+                matte.raw()
+            }
+        };
+        report(
+            ctx,
+            tr!("saved-selection", path = output_path.display().to_string()),
+        );
+        written
+    } else {
+        match mask_source {
+            MaskChoice::Processed => {
+                let mask = matte.clone().processed()?;
+                mask.save(output_path)?;
+                report(
+                    ctx,
+                    tr!("saved-mask", path = output_path.display().to_string()),
+                );
+                mask.raw()
+            }
+            MaskChoice::Raw => {
+                let raw = matte.raw();
+                matte.save(output_path)?;
+                report(
+                    ctx,
+                    tr!("saved-matte", path = output_path.display().to_string()),
+                );
+                raw
+            }
         }
+    };
+
+    if cmd.json_sidecar && !is_stdio(output_path) {
+        let model = local_model(ctx, global).filter(|_| !cmd.from_mask);
+        write_sidecar(
+            ctx,
+            input,
+            output_path,
+            &written,
+            model,
+            session.warnings(),
+            stopwatch,
+        )?;
     }
 
-    Ok(())
+    Ok(session.warnings().to_vec())
+}
+
+/// Export the full-precision raw matte for a single input in `format`.
+fn precise_one(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &MaskCommand,
+    format: StorageFormat,
+    input: MaskInput,
+    matte: PreciseMatte,
+    stopwatch: Stopwatch,
+) -> BgrResult<Vec<Warning>> {
+    let MaskInput {
+        path: input,
+        output: output_path,
+        ..
+    } = input;
+    fs::write(output_path, format.encode(&matte)?)?;
+    report(
+        ctx,
+        tr!("saved-matte", path = output_path.display().to_string()),
+    );
+    if cmd.json_sidecar {
+        let written = matte.to_luma8();
+        write_sidecar(
+            ctx,
+            input,
+            output_path,
+            &written,
+            local_model(ctx, global),
+            matte.warnings(),
+            stopwatch,
+        )?;
+    }
+    Ok(matte.warnings().to_vec())
+}
+
+/// Benchmark every storage format on the first `--storage-sample` inputs, print how each
+/// did, and pick the smallest within `--max-error`, returning the sampled mattes with their
+/// inference times for the batch to write.
+fn choose_storage(
+    ctx: &Context,
+    bgr: &Bgr,
+    global: &GlobalOptions,
+    cmd: &MaskCommand,
+    inputs: &[PathBuf],
+) -> BgrResult<(StorageFormat, SampledMattes)> {
+    let mut source = MatteSource::open(bgr, global)?;
+    let mut benchmark = StorageBenchmark::default();
+    let mut sampled = HashMap::new();
+    let mut failure = None;
+    for input in inputs.iter().take(cmd.storage_sample as usize) {
+        // An input that cannot be read is left for the batch to report, unless none can.
+        let stopwatch = Stopwatch::start();
+        match source.for_image_precise(input) {
+            Ok(matte) => {
+                let inference = stopwatch.inferred().inference();
+                benchmark.add(&matte)?;
+                sampled.insert(input.clone(), (matte, inference));
+            }
+            Err(err) => failure = Some(err),
+        }
+    }
+    if let (0, Some(err)) = (benchmark.mattes(), failure) {
+        return Err(err);
+    }
+    let format = benchmark
+        .choose(cmd.max_error)
+        .ok_or_else(|| BgrError::invalid_input("no storage format keeps within --max-error"))?;
+    if ctx.verbosity() > Verbosity::Quiet {
+        print_storage_table(&benchmark);
+    }
+    report(
+        ctx,
+        tr!(
+            "storage-chosen",
+            format = format.name(),
+            count = benchmark.mattes()
+        ),
+    );
+    Ok((format, sampled))
+}
+
+/// Mattes inferred while choosing a storage format, with how long each took.
+type SampledMattes = HashMap<PathBuf, (PreciseMatte, Duration)>;
+
+fn print_storage_table(benchmark: &StorageBenchmark) {
+    let results = benchmark.results();
+    let png8 = results
+        .iter()
+        .find(|result| result.format == StorageFormat::Png8)
+        .map_or(0, |result| result.bytes);
+    println!(
+        "{:<8} {:>12} {:>7} {:>9} {:>9} {:>10}",
+        "FORMAT", "BYTES", "RATIO", "MAX_ERR", "RMS_ERR", "ENCODE_MS"
+    );
+    for result in results {
+        println!(
+            "{:<8} {:>12} {:>7.2} {:>9.3} {:>9.3} {:>10.1}",
+            result.format.name(),
+            result.bytes,
+            result.bytes as f64 / png8.max(1) as f64,
+            result.max_error,
+            result.rms_error(),
+            result.encode_time.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+/// A short description of the mask processing steps, such as `blur=6,threshold=120,fill-holes`.
+fn processing_summary(options: &MaskProcessingOptions) -> String {
+    let mut steps = Vec::new();
+    if options.blur {
+        steps.push(format!("blur={}", options.blur_sigma));
+    }
+    if options.binary {
+        steps.push(format!("threshold={}", options.mask_threshold));
+    }
+    if options.erode {
+        steps.push(format!("erode={}", options.erosion_radius));
+    }
+    if options.dilate {
+        steps.push(format!("dilate={}", options.dilation_radius));
+    }
+    if options.fill_holes {
+        steps.push("fill-holes".to_string());
+    }
+    if options.keep_largest {
+        steps.push("keep-largest".to_string());
+    }
+    match options.min_area {
+        Some(ComponentArea::Pixels(pixels)) => steps.push(format!("min-area={pixels}")),
+        Some(ComponentArea::Percent(percent)) => steps.push(format!("min-area={percent}%")),
+        None => {}
+    }
+    if options.feather {
+        steps.push(format!("feather={}", options.feather_radius));
+    }
+    steps.join(",")
 }
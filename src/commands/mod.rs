@@ -1,22 +1,156 @@
+mod baseline;
+mod batch;
+mod checkpoint;
+mod completions;
+mod config;
 mod cut;
+#[cfg(unix)]
+mod daemon;
+mod doctor;
+mod escalation;
+mod examples;
+#[cfg(feature = "test-utils")]
+mod fixtures;
+mod hooks;
+mod manpage;
 mod mask;
+mod models;
+mod progress;
+mod remote;
+mod run;
+#[cfg(feature = "server")]
+mod serve;
+mod sidecar;
 mod trace;
+mod usage;
 mod utils;
+mod verify;
+#[cfg(feature = "video")]
+mod video;
+mod watch;
+
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use crate::cli::{Cli, Commands, GlobalOptions};
-use bgr::BgrResult;
+use crate::i18n::tr;
+use bgr::{BgrResult, CancellationToken, Context};
+
+/// Cancelled by the first Ctrl-C during a command that stops gracefully.
+static CANCELLATION: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// The token every inference, batch, and download of this process watches.
+pub fn cancellation() -> &'static CancellationToken {
+    &CANCELLATION
+}
 
 /// The main function to run the command based on CLI input.
 pub fn run(cli: Cli) -> BgrResult<()> {
-    let Cli { global, command } = cli;
-    dispatch(&global, command)
+    let Cli {
+        mut global,
+        command,
+    } = cli;
+    if stops_gracefully(&command) {
+        install_ctrlc_handler(&global);
+    }
+    // An explicit `--model` runs the draft instead of the lite preset.
+    if command.draft() && global.model == bgr::models::AUTO_MODEL {
+        global.model = bgr::DRAFT_PRESET.name().to_string();
+    }
+    global.checkpoint = command.checkpoint();
+    if let Some(budget) = batch::CpuBudget::from_global(&global) {
+        budget.limit(&mut global);
+    }
+    let started = Instant::now();
+    let ctx = utils::build_context(&global, needs_model(&global, &command))?;
+    let command = remote::fetch_url_inputs(&ctx, &mut global, command)?;
+    let accounted = usage::accounted_name(&command).filter(|_| global.usage_log.is_some());
+    let Some(name) = accounted else {
+        return dispatch(&ctx, &global, command);
+    };
+    let meter = usage::UsageMeter::default();
+    let settings = ctx
+        .inference_settings()
+        .clone()
+        .add_telemetry(meter.clone());
+    let ctx = ctx.with_inference_settings(settings);
+    let result = dispatch(&ctx, &global, command);
+    meter.append(&ctx, &global, name, started, result.is_ok());
+    result
+}
+
+/// Mask, cut, trace, and watch get their mattes from the daemon under `--via-daemon`, so they
+/// load no model of their own.
+fn needs_model(global: &GlobalOptions, command: &Commands) -> bool {
+    #[cfg(unix)]
+    if global.via_daemon.is_some()
+        && matches!(
+            command,
+            Commands::Mask(_) | Commands::Cut(_) | Commands::Trace(_) | Commands::Watch(_)
+        )
+    {
+        return false;
+    }
+    #[cfg(not(unix))]
+    let _ = global;
+    command.needs_model()
+}
+
+/// Commands that finish or abandon their current step on Ctrl-C and still write their
+/// report. Servers and watchers keep the default handler and exit at once.
+fn stops_gracefully(command: &Commands) -> bool {
+    match command {
+        Commands::Mask(_) | Commands::Cut(_) | Commands::Trace(_) | Commands::Models(_) => true,
+        #[cfg(feature = "video")]
+        Commands::Video(_) => true,
+        _ => false,
+    }
+}
+
+/// Cancel on the first Ctrl-C and exit on the second, for when the current step is slow.
+fn install_ctrlc_handler(global: &GlobalOptions) {
+    let quiet = global.quiet;
+    let interrupted = AtomicBool::new(false);
+    // Without a handler Ctrl-C still stops the process, just not gracefully.
+    let _ = ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        CANCELLATION.cancel();
+        if !quiet {
+            eprintln!("{}", tr!("cancelling"));
+        }
+    });
 }
 
 /// Dispatch the command to the appropriate handler.
-fn dispatch(global: &GlobalOptions, command: Commands) -> BgrResult<()> {
+fn dispatch(ctx: &Context, global: &GlobalOptions, command: Commands) -> BgrResult<()> {
     match command {
-        Commands::Mask(cmd) => mask::run(global, cmd),
-        Commands::Cut(cmd) => cut::run(global, cmd),
-        Commands::Trace(cmd) => trace::run(global, cmd),
+        Commands::Mask(cmd) => mask::run(ctx, global, cmd),
+        Commands::Cut(cmd) => cut::run(ctx, global, cmd),
+        Commands::Trace(cmd) => trace::run(ctx, global, cmd),
+        Commands::Verify(cmd) => verify::run(ctx, cmd),
+        Commands::Models(cmd) => models::run(ctx, global, cmd),
+        #[cfg(feature = "test-utils")]
+        Commands::Fixtures(cmd) => fixtures::run(ctx, global, cmd),
+        #[cfg(unix)]
+        Commands::Daemon(cmd) => daemon::run(ctx, global, cmd),
+        #[cfg(feature = "server")]
+        Commands::Serve(cmd) => serve::run(ctx, global, cmd),
+        Commands::Watch(cmd) => watch::run(ctx, global, cmd),
+        #[cfg(feature = "video")]
+        Commands::Video(cmd) => video::run(ctx, cmd),
+        Commands::Examples(cmd) => examples::run(cmd),
+        Commands::Usage(cmd) => usage::run(ctx, global, cmd),
+        Commands::Config(cmd) => config::run(cmd),
+        Commands::Run(cmd) => run::run(ctx, global, cmd),
+        Commands::Doctor => doctor::run(ctx, global),
+        Commands::Completions(cmd) => completions::run(cmd),
+        Commands::Manpage(cmd) => manpage::run(cmd),
+        #[cfg(feature = "sandboxed-decode")]
+        Commands::DecodeWorker => {
+            bgr::run_decode_worker(std::io::stdin().lock(), std::io::stdout().lock())
+        }
     }
 }
@@ -0,0 +1,168 @@
+use std::io;
+
+use bgr::models::{ModelError, ModelPreset, Priority, UseCase, file_sha256, remove_model};
+use bgr::{BgrError, BgrResult, Context};
+
+use crate::cli::{GlobalOptions, ModelsAction, ModelsCommand};
+use crate::i18n::tr;
+
+use super::progress::download_with_progress;
+use super::utils::{confirm_licenses, report};
+
+/// The main function to run the models command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: ModelsCommand) -> BgrResult<()> {
+    match cmd.action {
+        ModelsAction::List { detailed } => list(ctx, detailed),
+        ModelsAction::Advise { use_case, prefer } => {
+            advise(use_case.into(), prefer.into());
+            Ok(())
+        }
+        ModelsAction::Pull { preset, all } => {
+            let presets = if all {
+                ModelPreset::ALL.to_vec()
+            } else {
+                vec![parse_preset(preset.as_deref().unwrap_or_default())?]
+            };
+            pull(ctx, global, &presets)
+        }
+        ModelsAction::Rm { preset } => {
+            let preset = parse_preset(&preset)?;
+            if remove_model(preset, ctx.models_dir())? {
+                report(ctx, tr!("model-removed", name = preset.name()));
+            } else {
+                report(ctx, tr!("model-not-downloaded", name = preset.name()));
+            }
+            Ok(())
+        }
+        ModelsAction::Path { preset } => {
+            match preset {
+                Some(name) => {
+                    let preset = parse_preset(&name)?;
+                    println!("{}", preset.local_path(ctx.models_dir()).display());
+                }
+                None => println!("{}", ctx.models_dir().display()),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print every preset with its size, download state, and checksum, and with `detailed` its
+/// metadata.
+fn list(ctx: &Context, detailed: bool) -> BgrResult<()> {
+    println!(
+        "{}",
+        tr!(
+            "models-directory",
+            path = ctx.models_dir().display().to_string()
+        )
+    );
+    let details = |quality: &str, speed: &str, use_case: &str, license: &str| {
+        if detailed {
+            format!("{quality:<9} {speed:<9} {use_case:<13} {license:<27} ")
+        } else {
+            String::new()
+        }
+    };
+    println!(
+        "{:<14} {:>7}  {:<10}  {:<64}  {}DESCRIPTION",
+        "NAME",
+        "SIZE",
+        "STATE",
+        "SHA256",
+        details("QUALITY", "SPEED", "USE", "LICENSE")
+    );
+    for preset in ModelPreset::ALL {
+        let path = preset.local_path(ctx.models_dir());
+        let (state, checksum) = if path.exists() {
+            ("downloaded", file_sha256(&path)?)
+        } else {
+            ("missing", "-".to_string())
+        };
+        let meta = preset.metadata();
+        println!(
+            "{:<14} {:>4} MB  {:<10}  {:<64}  {}{}",
+            preset.name(),
+            preset.size_mb(),
+            state,
+            checksum,
+            details(
+                &meta.quality.to_string(),
+                &meta.speed.to_string(),
+                &meta.use_case.to_string(),
+                &meta.license.to_string()
+            ),
+            preset.description()
+        );
+    }
+    Ok(())
+}
+
+/// Print the recommended preset for `use_case`, then the others in order.
+fn advise(use_case: UseCase, priority: Priority) {
+    let ranked = ModelPreset::ranked(use_case, priority);
+    let best = ranked[0];
+    let meta = best.metadata();
+    println!(
+        "{}",
+        tr!(
+            "model-advice",
+            name = best.name(),
+            quality = meta.quality.to_string(),
+            speed = meta.speed.to_string(),
+            license = meta.license.to_string()
+        )
+    );
+    if meta.license.is_restrictive() {
+        println!("{}", tr!("model-advice-restricted"));
+    }
+    let others: Vec<&str> = ranked[1..].iter().map(|preset| preset.name()).collect();
+    println!(
+        "{}",
+        tr!("model-advice-alternatives", names = others.join(", "))
+    );
+}
+
+/// Download the presets that are not already present, all at once.
+fn pull(ctx: &Context, global: &GlobalOptions, presets: &[ModelPreset]) -> BgrResult<()> {
+    let mut missing = Vec::new();
+    for &preset in presets {
+        if preset.is_downloaded(ctx.models_dir()) {
+            report(ctx, tr!("model-already-downloaded", name = preset.name()));
+        } else {
+            missing.push(preset);
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if ctx.offline() {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "cannot download {} while --offline is set",
+                missing[0].name()
+            ),
+        )));
+    }
+    confirm_licenses(ctx, &missing, global)?;
+    for preset in &missing {
+        report(
+            ctx,
+            tr!(
+                "model-downloading",
+                name = preset.name(),
+                size = preset.size_mb()
+            ),
+        );
+    }
+    for path in download_with_progress(&missing, ctx.models_dir(), global)? {
+        report(ctx, tr!("model-saved", path = path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Parse a preset name, reporting the known presets on failure.
+fn parse_preset(name: &str) -> BgrResult<ModelPreset> {
+    ModelPreset::from_str(name).ok_or_else(|| ModelError::UnknownPreset(name.to_string()).into())
+}
@@ -0,0 +1,310 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bgr::models::{ModelPreset, download_models_sync};
+use bgr::{
+    BatchProgress, BgrResult, DownloadEvent, InputEvent, InputId, NoProgress, ProgressSink,
+    SharedProgress, Stage,
+};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+use crate::cli::{GlobalOptions, ProgressArg};
+use crate::i18n::tr;
+
+use super::cancellation;
+
+/// Minimum time between two JSON progress lines for the same model.
+const JSON_INTERVAL: Duration = Duration::from_millis(250);
+
+thread_local! {
+    /// The batch input this thread is processing, which its stage lines name.
+    static CURRENT_INPUT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Download `presets` concurrently, showing progress as selected by `--progress` and `--quiet`.
+pub fn download_with_progress(
+    presets: &[ModelPreset],
+    models_dir: &Path,
+    global: &GlobalOptions,
+) -> BgrResult<Vec<PathBuf>> {
+    let cancel = cancellation();
+    let paths = match global.progress_format() {
+        ProgressArg::Bar => download_models_sync(presets, models_dir, &BarSink::default(), cancel),
+        ProgressArg::Json => {
+            download_models_sync(presets, models_dir, &JsonSink::default(), cancel)
+        }
+        ProgressArg::None => download_models_sync(presets, models_dir, &NoProgress, cancel),
+    };
+    Ok(paths?)
+}
+
+/// The sink for batch progress, as selected by `--progress` and `--quiet`.
+pub fn batch_progress(global: &GlobalOptions) -> SharedProgress {
+    match global.progress_format() {
+        ProgressArg::Bar => SharedProgress::new(BarSink::default()),
+        ProgressArg::Json => SharedProgress::new(JsonSink::default()),
+        ProgressArg::None => SharedProgress::new(NoProgress),
+    }
+}
+
+/// The sink for the pipeline stages of each input: JSON lines under `--progress json`, as the
+/// bars show whole inputs only.
+pub fn stage_progress(global: &GlobalOptions) -> SharedProgress {
+    match global.progress_format() {
+        ProgressArg::Json => SharedProgress::new(JsonSink::default()),
+        ProgressArg::Bar | ProgressArg::None => SharedProgress::new(NoProgress),
+    }
+}
+
+/// One progress bar per model, with speed and time remaining, and one for a batch.
+#[derive(Default)]
+struct BarSink {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<&'static str, ProgressBar>>,
+    batch: Mutex<Option<ProgressBar>>,
+}
+
+impl ProgressSink for BarSink {
+    fn download(&self, event: &DownloadEvent) {
+        let mut bars = self.bars.lock().unwrap();
+        match event {
+            DownloadEvent::Started {
+                model,
+                total,
+                resumed_from,
+            } => {
+                let bar = match total {
+                    Some(total) => ProgressBar::new(*total).with_style(bar_style()),
+                    None => ProgressBar::new_spinner().with_style(spinner_style()),
+                };
+                let bar = self.multi.add(bar.with_prefix(*model));
+                if *resumed_from > 0 {
+                    bar.println(tr!(
+                        "download-resuming",
+                        name = *model,
+                        size = resumed_from / 1_000_000
+                    ));
+                    bar.reset_eta();
+                }
+                bar.set_position(*resumed_from);
+                bars.insert(*model, bar);
+            }
+            DownloadEvent::Progress {
+                model, downloaded, ..
+            } => {
+                if let Some(bar) = bars.get(model) {
+                    bar.set_position(*downloaded);
+                }
+            }
+            DownloadEvent::Finished { model, .. } => {
+                if let Some(bar) = bars.remove(model) {
+                    bar.finish();
+                }
+            }
+            DownloadEvent::Failed { model, .. } => {
+                if let Some(bar) = bars.remove(model) {
+                    bar.abandon();
+                }
+            }
+        }
+    }
+
+    fn batch(&self, progress: BatchProgress) {
+        let mut batch = self.batch.lock().unwrap();
+        let bar = batch.get_or_insert_with(|| {
+            self.multi
+                .add(ProgressBar::new(progress.total as u64).with_style(batch_style()))
+        });
+        bar.set_position(progress.done as u64);
+        if progress.failed > 0 {
+            bar.set_message(tr!("batch-failed", failed = progress.failed));
+        }
+        if progress.done == progress.total {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn bar_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{prefix:>14} [{bar:30.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+    )
+    .unwrap()
+    .progress_chars("#>-")
+}
+
+fn batch_style() -> ProgressStyle {
+    ProgressStyle::with_template("[{bar:30.cyan/blue}] {pos}/{len} ETA {eta} {msg}")
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{prefix:>14} {spinner:.green} {bytes} {bytes_per_sec}").unwrap()
+}
+
+/// Every event as a JSON line on stderr, with download progress lines throttled per model.
+#[derive(Default)]
+struct JsonSink {
+    last_progress: Mutex<HashMap<&'static str, Instant>>,
+}
+
+/// Stage and batch lines share the `event` tag of download and input lines.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonLine {
+    Stage {
+        /// Tells apart the stages of inputs processed at the same time.
+        input_id: InputId,
+        /// The input whose stage this is, unless it is not part of a batch.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        input: Option<PathBuf>,
+        stage: Stage,
+    },
+    Batch {
+        #[serde(flatten)]
+        progress: BatchProgress,
+        /// Share of the inputs done, from 0 to 100.
+        percent: f64,
+    },
+}
+
+impl JsonLine {
+    fn batch(progress: BatchProgress) -> Self {
+        let percent = progress.done as f64 * 100.0 / progress.total.max(1) as f64;
+        Self::Batch {
+            progress,
+            percent: (percent * 10.0).round() / 10.0,
+        }
+    }
+}
+
+fn print_json(line: &impl Serialize) {
+    if let Ok(line) = serde_json::to_string(line) {
+        eprintln!("{line}");
+    }
+}
+
+impl JsonSink {
+    /// Whether `event` should be printed now.
+    fn due(&self, event: &DownloadEvent) -> bool {
+        let DownloadEvent::Progress { model, .. } = event else {
+            return true;
+        };
+        let now = Instant::now();
+        let mut last = self.last_progress.lock().unwrap();
+        match last.get(model) {
+            Some(previous) if now.duration_since(*previous) < JSON_INTERVAL => false,
+            _ => {
+                last.insert(*model, now);
+                true
+            }
+        }
+    }
+}
+
+impl ProgressSink for JsonSink {
+    fn download(&self, event: &DownloadEvent) {
+        if self.due(event) {
+            print_json(event);
+        }
+    }
+
+    fn stage(&self, input_id: InputId, stage: Stage) {
+        let input = CURRENT_INPUT.with_borrow(Clone::clone);
+        print_json(&JsonLine::Stage {
+            input_id,
+            input,
+            stage,
+        });
+    }
+
+    fn input(&self, event: &InputEvent) {
+        CURRENT_INPUT.set(match event {
+            InputEvent::FileStarted { input, .. } => Some(input.clone()),
+            InputEvent::FileFinished { .. } | InputEvent::FileFailed { .. } => None,
+        });
+        print_json(event);
+    }
+
+    fn batch(&self, progress: BatchProgress) {
+        print_json(&JsonLine::batch(progress));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod json_sink {
+        use super::*;
+
+        fn progress(model: &'static str) -> DownloadEvent {
+            DownloadEvent::Progress {
+                model,
+                downloaded: 1,
+                total: None,
+            }
+        }
+
+        #[test]
+        fn progress_throttled_per_model() {
+            let sink = JsonSink::default();
+            assert!(sink.due(&progress("isnet")));
+            assert!(!sink.due(&progress("isnet")));
+            assert!(sink.due(&progress("u2net")));
+        }
+
+        #[test]
+        fn other_events_always_printed() {
+            let sink = JsonSink::default();
+            let finished = DownloadEvent::Finished {
+                model: "isnet",
+                path: PathBuf::from("isnet.onnx"),
+            };
+            assert!(sink.due(&finished));
+            assert!(sink.due(&finished));
+        }
+
+        #[test]
+        fn batch_lines_tagged_like_downloads() {
+            let line = JsonLine::batch(BatchProgress {
+                done: 2,
+                failed: 1,
+                total: 6,
+            });
+            assert_eq!(
+                serde_json::to_string(&line).unwrap(),
+                r#"{"event":"batch","done":2,"failed":1,"total":6,"percent":33.3}"#
+            );
+        }
+
+        #[test]
+        fn stage_lines_name_the_current_input() {
+            let sink = JsonSink::default();
+            sink.input(&InputEvent::FileStarted {
+                input: PathBuf::from("a.jpg"),
+                index: 0,
+                total: 2,
+            });
+            let line = JsonLine::Stage {
+                input_id: InputId(3),
+                input: CURRENT_INPUT.with_borrow(Clone::clone),
+                stage: Stage::Inference,
+            };
+            assert_eq!(
+                serde_json::to_string(&line).unwrap(),
+                r#"{"event":"stage","input_id":3,"input":"a.jpg","stage":"inference"}"#
+            );
+            sink.input(&InputEvent::FileFinished {
+                input: PathBuf::from("a.jpg"),
+                seconds: 1.5,
+            });
+            assert_eq!(CURRENT_INPUT.with_borrow(Clone::clone), None);
+        }
+    }
+}
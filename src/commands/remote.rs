@@ -0,0 +1,321 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bgr::{BgrError, BgrResult, Context, InputLimits};
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::cli::{Commands, GlobalOptions};
+use crate::i18n::tr;
+
+/// Directory inside the data directory that holds fetched URL inputs.
+const URL_CACHE_DIR: &str = "url-cache";
+/// Name of a fetched input whose URL path ends without a file name.
+const FALLBACK_NAME: &str = "image";
+
+/// The validators a cached response was served with, for revalidating it.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Replace the URL inputs of mask, cut, and trace with local copies, fetched into the data
+/// directory's URL cache or revalidated there with `ETag` and `Last-Modified`, so unchanged
+/// images are not downloaded again. The outputs of the copies go to the current directory
+/// unless `--out-dir` says otherwise, as the copies live in the cache; other inputs keep
+/// theirs next to them.
+///
+/// Downloads are held to `--max-file-size` and `--fetch-timeout`.
+pub fn fetch_url_inputs(
+    ctx: &Context,
+    global: &mut GlobalOptions,
+    mut command: Commands,
+) -> BgrResult<Commands> {
+    let inputs = match &mut command {
+        Commands::Mask(cmd) => &mut cmd.inputs,
+        Commands::Cut(cmd) => &mut cmd.inputs,
+        Commands::Trace(cmd) => &mut cmd.inputs,
+        _ => return Ok(command),
+    };
+    if !inputs.iter().any(|input| url(input).is_some()) {
+        return Ok(command);
+    }
+    let cache = UrlCache {
+        dir: ctx.data_dir().join(URL_CACHE_DIR),
+        offline: ctx.offline(),
+        limits: (&global.limits).into(),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut client = reqwest::Client::builder();
+    if global.fetch_timeout > 0 {
+        client = client.timeout(Duration::from_secs(global.fetch_timeout));
+    }
+    let client = client.build().map_err(io::Error::other)?;
+    runtime.block_on(async {
+        for input in inputs.iter_mut() {
+            if let Some(url) = url(input) {
+                *input = cache.fetch(&client, &url).await?;
+            }
+        }
+        Ok::<_, BgrError>(())
+    })?;
+    global.url_cache = Some(cache.dir);
+    Ok(command)
+}
+
+/// The URL an input names, if it is an `http` or `https` one.
+fn url(input: &Path) -> Option<String> {
+    let input = input.to_str()?;
+    (input.starts_with("http://") || input.starts_with("https://")).then(|| input.to_string())
+}
+
+struct UrlCache {
+    dir: PathBuf,
+    offline: bool,
+    limits: InputLimits,
+}
+
+impl UrlCache {
+    /// The cached copy of `url`, downloaded or refreshed when the server has a newer one. A
+    /// cached copy is used as it is offline, and with a warning when the server is unreachable.
+    async fn fetch(&self, client: &reqwest::Client, url: &str) -> BgrResult<PathBuf> {
+        let key = cache_key(url);
+        let path = self.dir.join(&key).join(file_name(url, &key));
+        let entry_path = self.dir.join(format!("{key}.json"));
+        let cached = fs::read(&entry_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<CacheEntry>(&json).ok())
+            .filter(|_| path.is_file());
+        if self.offline {
+            return match cached {
+                Some(_) => Ok(path),
+                None => Err(fetch_error(url, "not cached, and downloads are disabled")),
+            };
+        }
+
+        let mut request = client.get(url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, modified);
+            }
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) if cached.is_some() => {
+                tracing::warn!(
+                    "{}",
+                    tr!("url-cache-stale", url = url, error = err.to_string())
+                );
+                return Ok(path);
+            }
+            Err(err) => return Err(fetch_error(url, err)),
+        };
+        if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() {
+            return Ok(path);
+        }
+        if !response.status().is_success() {
+            return Err(fetch_error(url, format!("HTTP {}", response.status())));
+        }
+        if let Some(length) = response.content_length() {
+            self.limits.check_file_size(length)?;
+        }
+        let header = |name: reqwest::header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let entry = CacheEntry {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        let dir = path.parent().expect("cached inputs sit in a directory");
+        fs::create_dir_all(dir)?;
+        let temp_path = dir.join(".download");
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut downloaded = 0;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|err| fetch_error(url, err))?;
+            downloaded += chunk.len() as u64;
+            self.limits.check_file_size(downloaded)?;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&temp_path, &path).await?;
+        fs::write(
+            &entry_path,
+            serde_json::to_vec(&entry).map_err(io::Error::from)?,
+        )?;
+        Ok(path)
+    }
+}
+
+/// The cache directory name for `url`.
+fn cache_key(url: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(url.as_bytes()));
+    digest[..16].to_string()
+}
+
+/// The file name a copy of `url` is saved under, which names the outputs derived from it:
+/// the last path segment, without its query, limited to characters safe on every platform,
+/// with the first 8 characters of the URL's cache `key` before its extension, so URLs ending
+/// in the same name do not write the same outputs.
+fn file_name(url: &str, key: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    let name: String = path
+        .split_once('/')
+        .and_then(|(_, path)| path.rsplit('/').next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect();
+    let name = if name.trim_matches('.').is_empty() {
+        FALLBACK_NAME
+    } else {
+        name.as_str()
+    };
+    let tag = &key[..8];
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}-{tag}.{extension}"),
+        _ => format!("{name}-{tag}"),
+    }
+}
+
+fn fetch_error(url: &str, error: impl ToString) -> BgrError {
+    BgrError::Io(io::Error::other(format!(
+        "cannot fetch {url}: {}",
+        error.to_string()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod file_name {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            const KEY: &str = "0123456789abcdef";
+
+            #[test]
+            fn last_segment_without_query() {
+                assert_eq!(
+                    file_name("https://cdn.example.com/img/shoe.jpg?w=800#top", KEY),
+                    "shoe-01234567.jpg"
+                );
+                assert_eq!(
+                    file_name("http://example.com/a/b%20c.png", KEY),
+                    "b20c-01234567.png"
+                );
+            }
+
+            #[test]
+            fn falls_back_without_a_name() {
+                let fallback = format!("{FALLBACK_NAME}-01234567");
+                assert_eq!(file_name("https://example.com", KEY), fallback);
+                assert_eq!(file_name("https://example.com/photos/", KEY), fallback);
+                assert_eq!(file_name("https://example.com/..", KEY), fallback);
+            }
+
+            #[test]
+            fn same_names_differ_by_url() {
+                let (a, b) = (
+                    "https://a.example.com/shoe.jpg",
+                    "https://b.example.com/shoe.jpg",
+                );
+                assert_ne!(file_name(a, &cache_key(a)), file_name(b, &cache_key(b)));
+            }
+        }
+    }
+
+    mod url {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn only_http_and_https_are_urls() {
+                assert!(url(Path::new("https://example.com/a.jpg")).is_some());
+                assert!(url(Path::new("http://example.com/a.jpg")).is_some());
+                assert!(url(Path::new("photos/a.jpg")).is_none());
+                assert!(url(Path::new("ftp://example.com/a.jpg")).is_none());
+            }
+
+            #[test]
+            fn each_url_gets_its_own_directory() {
+                let (a, b) = (
+                    cache_key("https://example.com/a.jpg"),
+                    cache_key("https://example.com/a.jpg?v=2"),
+                );
+                assert_eq!(a.len(), 16);
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    mod url_cache {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn offline_uses_the_cached_copy_or_fails() {
+                let temp = tempfile::tempdir().unwrap();
+                let dir = temp.path();
+                let cache = UrlCache {
+                    dir: dir.to_path_buf(),
+                    offline: true,
+                    limits: InputLimits::default(),
+                };
+                let url = "https://example.com/shoe.jpg";
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                let client = reqwest::Client::new();
+                assert!(runtime.block_on(cache.fetch(&client, url)).is_err());
+
+                let key = cache_key(url);
+                fs::create_dir_all(dir.join(&key)).unwrap();
+                let name = file_name(url, &key);
+                fs::write(dir.join(&key).join(&name), b"jpeg").unwrap();
+                let entry = CacheEntry {
+                    url: url.to_string(),
+                    etag: Some("\"v1\"".to_string()),
+                    last_modified: None,
+                };
+                fs::write(
+                    dir.join(format!("{key}.json")),
+                    serde_json::to_vec(&entry).unwrap(),
+                )
+                .unwrap();
+                let path = runtime.block_on(cache.fetch(&client, url)).unwrap();
+                assert_eq!(path, dir.join(key).join(name));
+            }
+        }
+    }
+}
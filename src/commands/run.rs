@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use bgr::{BgrError, BgrResult, Context};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+
+use crate::cli::{Cli, GlobalOptions, RunCommand};
+use crate::examples::command_line;
+use crate::i18n::tr;
+use crate::job_file::JobFile;
+
+use super::batch::release_kept_sessions;
+use super::utils::report;
+
+/// The main function to run the run command.
+///
+/// Each step runs as its own subcommand, and its sessions stay loaded for later steps that
+/// use the same model on the same device. Steps read their defaults from the job alone, not
+/// from `config.toml` or a project's `.bgr.toml`, so a job runs the same wherever it is
+/// checked out; where models live and what runs them are the machine's, so `--models-dir`,
+/// `--device`, and `--backend` carry over from the `run` command unless a step sets them.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: RunCommand) -> BgrResult<()> {
+    let job = JobFile::load(&cmd.job)?;
+    let vars: BTreeMap<String, String> = cmd.vars.into_iter().collect();
+    let steps = job.step_args(&vars)?;
+    if cmd.dry_run {
+        for args in &steps {
+            println!("{}", command_line(&args[..]));
+        }
+        return Ok(());
+    }
+
+    // Check every step before the first runs, so a typo in the last one wastes no work.
+    let clis = steps
+        .iter()
+        .enumerate()
+        .map(|(index, args)| {
+            let argv = std::iter::once("bgr").chain(args.iter().map(String::as_str));
+            let parsed = Cli::command()
+                .try_get_matches_from(argv)
+                .and_then(|matches| Ok((Cli::from_arg_matches(&matches)?, matches)));
+            parsed.map_err(|err| {
+                // clap's first line names the problem; the rest is usage for a terminal.
+                let rendered = err.to_string();
+                let error = rendered.lines().next().unwrap_or_default();
+                let error = error.strip_prefix("error: ").unwrap_or(error);
+                let message = tr!("job-step-invalid", step = index + 1, error = error);
+                BgrError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+            })
+        })
+        .collect::<BgrResult<Vec<_>>>()?;
+
+    // Paths in a job are relative to it, as it is kept next to its assets.
+    if let Some(dir) = cmd.job.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::env::set_current_dir(dir)?;
+    }
+    let total = clis.len();
+    let mut result = Ok(());
+    for (index, ((cli, matches), args)) in clis.into_iter().zip(&steps).enumerate() {
+        result = run_step(ctx, global, cli, &matches, args, index, total);
+        if result.is_err() {
+            break;
+        }
+    }
+    release_kept_sessions();
+    result
+}
+
+/// Run one step of a job, under the machine options of the `run` command it did not set.
+fn run_step(
+    ctx: &Context,
+    global: &GlobalOptions,
+    mut cli: Cli,
+    matches: &ArgMatches,
+    args: &[String],
+    index: usize,
+    total: usize,
+) -> BgrResult<()> {
+    let set = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if !set("models_dir") {
+        cli.global.models_dir.clone_from(&global.models_dir);
+    }
+    if !set("device") {
+        cli.global.device = global.device;
+    }
+    if !set("backend") {
+        cli.global.backend = global.backend;
+    }
+    // How chatty a run is stays up to whoever started it.
+    cli.global.quiet |= global.quiet;
+    cli.global.verbose = cli.global.verbose.max(global.verbose);
+    cli.global.keep_sessions = true;
+    report(
+        ctx,
+        tr!(
+            "job-step",
+            step = index + 1,
+            total = total,
+            command = command_line(args)
+        ),
+    );
+    super::run(cli)
+}
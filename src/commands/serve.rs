@@ -0,0 +1,523 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Query, Request, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use bgr::{
+    Background, Bgr, BgrError, BgrResult, CacheKey, Context, DRAFT_PRESET, InferencedMatte,
+    InputLimits, MatteCache, PreviewOptions, RenderingIntent, SessionPool, Warning, decode_srgb,
+    draft_matte,
+};
+use image::{ImageError, ImageFormat, Rgb, RgbImage};
+use serde::Deserialize;
+use tower_http::cors::CorsLayer;
+
+use crate::cli::{GlobalOptions, RefineArg, ServeCommand, parse_hex_color};
+use crate::i18n::{self, tr};
+
+use super::utils::{
+    MaskChoice, build_bgr, processing_requested, refine_matte, reject_target_profile, report,
+    resolve_alpha_source, settings_for_model, warn_if_soft_conflict,
+};
+
+/// Room for multipart boundaries and headers on top of the image itself.
+const MULTIPART_OVERHEAD: usize = 64 << 10;
+/// Response header carrying the input's warnings as a JSON array, when there are any.
+const WARNINGS_HEADER: &str = "x-bgr-warnings";
+
+/// Everything a request needs, shared by all connections.
+struct ServeState {
+    pool: SessionPool,
+    /// Sessions of the lite model for `?mode=draft`, under `--draft`.
+    draft_pool: Option<SessionPool>,
+    preview: PreviewOptions,
+    alpha_source: MaskChoice,
+    background: Option<Background>,
+    refine: Option<RefineArg>,
+    /// Recent mattes by upload, under `--cache-entries`.
+    cache: Option<MatteCache>,
+    /// Limits and intent for decoding uploaded backgrounds, as for inputs.
+    limits: InputLimits,
+    intent: RenderingIntent,
+}
+
+/// The main function to run the serve command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: ServeCommand) -> BgrResult<()> {
+    reject_target_profile(global, "HTTP responses")?;
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let alpha_source =
+        resolve_alpha_source(cmd.alpha_source, processing_requested(&cmd.mask_processing));
+    if matches!(alpha_source, MaskChoice::Processed) {
+        warn_if_soft_conflict(&cmd.mask_processing, "responses");
+    }
+
+    // Load every session before accepting connections so the first requests aren't slow.
+    let pool = bgr.session_pool(global.jobs)?;
+    let draft_pool = if cmd.draft {
+        let settings = settings_for_model(ctx, global, DRAFT_PRESET.name())?;
+        let draft = Bgr::from_context(&ctx.clone().with_inference_settings(settings));
+        Some(draft.session_pool(global.jobs)?)
+    } else {
+        None
+    };
+    let sessions = pool.size();
+    let body_limit = ctx
+        .inference_settings()
+        .limits
+        .max_file_bytes
+        .and_then(|max| usize::try_from(max).ok())
+        .map(|max| max.saturating_add(MULTIPART_OVERHEAD));
+    let state = Arc::new(ServeState {
+        pool,
+        draft_pool,
+        preview: PreviewOptions::default(),
+        alpha_source,
+        background: cmd.bg_color.map(Background::Color),
+        refine: cmd.refine,
+        cache: cmd.cache.cache(),
+        limits: ctx.inference_settings().limits.clone(),
+        intent: global.intent.into(),
+    });
+    let mut app = router(state, body_limit);
+    if cmd.cors {
+        app = app.layer(CorsLayer::permissive());
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(cmd.bind).await?;
+        report(
+            ctx,
+            tr!(
+                "serve-listening",
+                address = listener.local_addr()?.to_string(),
+                sessions = sessions
+            ),
+        );
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await
+    })?;
+    Ok(())
+}
+
+fn router(state: Arc<ServeState>, body_limit: Option<usize>) -> Router {
+    Router::new()
+        .route("/remove", post(remove))
+        .route("/health", get(|| async { "ok" }))
+        .layer(body_limit.map_or_else(DefaultBodyLimit::disable, DefaultBodyLimit::max))
+        .with_state(state)
+}
+
+/// Which pass a `/remove` request asks for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// The full model on the whole image.
+    #[default]
+    Standard,
+    /// The lite model on a shrunken image, for a quick preview.
+    Draft,
+    /// The full model where the `prior` field, a draft response, found the subject.
+    Final,
+}
+
+impl Mode {
+    /// What sets this pass apart in a cache key.
+    fn tag(self) -> &'static [u8] {
+        match self {
+            Self::Standard => b"standard",
+            Self::Draft => b"draft",
+            Self::Final => b"final",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RemoveParams {
+    #[serde(default)]
+    mode: Mode,
+    /// A solid color (`#rrggbb` or `#rgb`), or two for a top-to-bottom gradient
+    /// (`#ffffff,#d0d0d0`), to composite this response over in place of `--bg-color`.
+    bg: Option<String>,
+}
+
+/// An uploaded image, the draft it refines for `?mode=final`, and the image to composite it
+/// over.
+struct Upload {
+    image: Bytes,
+    prior: Option<Bytes>,
+    background: Option<Bytes>,
+}
+
+/// What one request asks to composite its cutout over.
+#[derive(Debug, Clone, PartialEq)]
+enum RequestBackground {
+    Color(Rgb<u8>),
+    /// From the first color at the top to the second at the bottom.
+    Gradient(Rgb<u8>, Rgb<u8>),
+    /// An encoded image, decoded off the runtime and fitted to the cutout.
+    Image(Bytes),
+}
+
+impl RequestBackground {
+    /// Parse a `?bg=` value: one color, or two separated by a comma for a gradient.
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(',') {
+            Some((top, bottom)) => Ok(Self::Gradient(
+                parse_hex_color(top.trim())?,
+                parse_hex_color(bottom.trim())?,
+            )),
+            None => parse_hex_color(spec.trim()).map(Self::Color),
+        }
+    }
+
+    /// The background for a cutout of `width` × `height`.
+    fn resolve(&self, state: &ServeState, width: u32, height: u32) -> BgrResult<Background> {
+        Ok(match self {
+            Self::Color(color) => Background::Color(*color),
+            Self::Gradient(top, bottom) => {
+                Background::Image(vertical_gradient(*top, *bottom, width, height))
+            }
+            Self::Image(encoded) => {
+                Background::Image(decode_srgb(encoded, &state.limits, state.intent)?)
+            }
+        })
+    }
+}
+
+/// A `width` × `height` image fading from `top` to `bottom`.
+fn vertical_gradient(top: Rgb<u8>, bottom: Rgb<u8>, width: u32, height: u32) -> RgbImage {
+    let last = height.saturating_sub(1).max(1) as f32;
+    RgbImage::from_fn(width, height, |_, y| {
+        let t = y as f32 / last;
+        Rgb(std::array::from_fn(|channel| {
+            let (from, to) = (f32::from(top[channel]), f32::from(bottom[channel]));
+            (from + (to - from) * t).round() as u8
+        }))
+    })
+}
+
+/// `POST /remove`: the image as the raw body or a multipart field, answered with the cutout PNG
+/// and any warnings in the `x-bgr-warnings` header.
+///
+/// `?mode=draft` answers quickly from the lite model; `?mode=final` takes the draft response
+/// as a multipart `prior` field and runs the full model only around its subject. `?bg=`
+/// composites over a color or gradient, and a multipart `background` field over an image;
+/// with `--cache-entries` a re-upload doing only that skips inference.
+async fn remove(State(state): State<Arc<ServeState>>, request: Request) -> Response {
+    let params = match Query::<RemoveParams>::try_from_uri(request.uri()) {
+        Ok(Query(params)) => params,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let mode = params.mode;
+    let requested = match params
+        .bg
+        .as_deref()
+        .map(RequestBackground::parse)
+        .transpose()
+    {
+        Ok(requested) => requested,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let mut upload = match read_image(request).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let background = match (requested, upload.background.take()) {
+        (Some(_), Some(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "send either ?bg= or a background field, not both",
+            )
+                .into_response();
+        }
+        (requested, uploaded) => requested.or(uploaded.map(RequestBackground::Image)),
+    };
+    if mode == Mode::Draft && state.draft_pool.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "drafts need the server to be started with --draft",
+        )
+            .into_response();
+    }
+    if mode == Mode::Final && upload.prior.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "?mode=final needs the draft response as a multipart prior field",
+        )
+            .into_response();
+    }
+    // Inference is CPU-bound and checking out a session may block, so keep it off the runtime.
+    let cut = move || cut_bytes(&state, mode, &upload, background.as_ref());
+    match tokio::task::spawn_blocking(cut).await {
+        Ok(Ok((png, warnings))) => {
+            let mut response = ([(header::CONTENT_TYPE, "image/png")], png).into_response();
+            if !warnings.is_empty() {
+                let json = serde_json::to_string(&warnings).expect("warnings serialize");
+                let value = HeaderValue::from_str(&json).expect("JSON is a valid header value");
+                response.headers_mut().insert(WARNINGS_HEADER, value);
+            }
+            response
+        }
+        Ok(Err(err)) => {
+            let status = error_status(&err);
+            if status.is_server_error() {
+                eprintln!("{}", i18n::error(&err));
+            }
+            (status, err.to_string()).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "inference failed").into_response(),
+    }
+}
+
+/// Extract the encoded image from a raw body, or from the `image` (else first file) field of a
+/// multipart form, along with any `prior` and `background` fields.
+async fn read_image(request: Request) -> Result<Upload, Response> {
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_multipart);
+    let upload = if multipart {
+        let mut form = Multipart::from_request(request, &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let mut image = None;
+        let mut prior = None;
+        let mut background = None;
+        while let Some(field) = form
+            .next_field()
+            .await
+            .map_err(IntoResponse::into_response)?
+        {
+            if field.name() == Some("prior") {
+                prior = Some(field.bytes().await.map_err(IntoResponse::into_response)?);
+            } else if field.name() == Some("background") {
+                background = Some(field.bytes().await.map_err(IntoResponse::into_response)?);
+            } else if image.is_none()
+                && (field.name() == Some("image") || field.file_name().is_some())
+            {
+                image = Some(field.bytes().await.map_err(IntoResponse::into_response)?);
+            }
+        }
+        let Some(image) = image else {
+            return Err(
+                (StatusCode::BAD_REQUEST, "multipart form has no image field").into_response(),
+            );
+        };
+        Upload {
+            image,
+            prior,
+            background: background.filter(|bytes| !bytes.is_empty()),
+        }
+    } else {
+        let image = Bytes::from_request(request, &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Upload {
+            image,
+            prior: None,
+            background: None,
+        }
+    };
+    if upload.image.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "request has no image data").into_response());
+    }
+    Ok(upload)
+}
+
+fn is_multipart(content_type: &str) -> bool {
+    content_type
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("multipart/form-data")
+}
+
+/// Run one upload through the model and encode the result as PNG.
+///
+/// Drafts skip refinement, which would cost more than their inference.
+fn cut_bytes(
+    state: &ServeState,
+    mode: Mode,
+    upload: &Upload,
+    background: Option<&RequestBackground>,
+) -> BgrResult<(Vec<u8>, Vec<Warning>)> {
+    let inferred = match &state.cache {
+        Some(cache) => {
+            let prior = upload.prior.as_deref().unwrap_or_default();
+            let key = CacheKey::of(&[mode.tag(), &upload.image, prior]);
+            cache.get_or_infer(key, || infer(state, mode, upload))?
+        }
+        None => infer(state, mode, upload)?,
+    };
+    let refine = if mode == Mode::Draft {
+        None
+    } else {
+        state.refine
+    };
+    let matte = refine_matte(inferred.matte(), refine)?;
+    let foreground = match state.alpha_source {
+        MaskChoice::Raw => matte.foreground()?,
+        MaskChoice::Processed => matte.processed()?.foreground()?,
+    };
+
+    let (width, height) = foreground.image().dimensions();
+    let requested = background
+        .map(|background| background.resolve(state, width, height))
+        .transpose()?;
+    let png = match requested.as_ref().or(state.background.as_ref()) {
+        Some(background) => {
+            let mut png = Cursor::new(Vec::new());
+            foreground
+                .composite(background)
+                .write_to(&mut png, ImageFormat::Png)?;
+            png.into_inner()
+        }
+        None => foreground.encode(ImageFormat::Png)?,
+    };
+    Ok((png, inferred.warnings().to_vec()))
+}
+
+/// Decode an upload and run the pass `mode` asks for.
+fn infer(state: &ServeState, mode: Mode, upload: &Upload) -> BgrResult<InferencedMatte> {
+    let encoded = &upload.image;
+    // The session goes back to the pool as soon as inference is done.
+    match (mode, &state.draft_pool, &upload.prior) {
+        (Mode::Draft, Some(pool), _) => {
+            pool.run(|session| session.for_bytes_draft(encoded, &state.preview))
+        }
+        (Mode::Final, _, Some(prior)) => {
+            let prior = draft_matte(&image::load_from_memory(prior)?);
+            state
+                .pool
+                .run(|session| session.for_bytes_with_prior(encoded, &prior, &state.preview))
+        }
+        _ => state.pool.run(|session| session.for_bytes(encoded)),
+    }
+}
+
+/// Client mistakes get 4xx answers; anything else is the server's fault.
+fn error_status(err: &BgrError) -> StatusCode {
+    match err {
+        BgrError::LimitExceeded { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        BgrError::Image(ImageError::Unsupported(_)) | BgrError::UnsupportedFormat { .. } => {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        }
+        BgrError::Image(ImageError::Decoding(_) | ImageError::Limits(_)) | BgrError::Sandbox(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod error_status {
+        use super::*;
+
+        #[test]
+        fn limits_are_payload_too_large() {
+            let err = BgrError::LimitExceeded {
+                what: "width",
+                actual: 100_000,
+                limit: 32_768,
+            };
+            assert_eq!(error_status(&err), StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        #[test]
+        fn undecodable_input_is_a_client_error() {
+            let err = BgrError::Sandbox("worker exited with status 1".into());
+            assert_eq!(error_status(&err), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        #[test]
+        fn other_failures_are_server_errors() {
+            let err = BgrError::Io(std::io::Error::other("disk full"));
+            assert_eq!(error_status(&err), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    mod remove_params {
+        use super::*;
+
+        fn mode(uri: &str) -> Option<Mode> {
+            Query::<RemoveParams>::try_from_uri(&uri.parse().unwrap())
+                .ok()
+                .map(|Query(params)| params.mode)
+        }
+
+        #[test]
+        fn parses_a_background_override() {
+            let bg = |uri: &str| {
+                Query::<RemoveParams>::try_from_uri(&uri.parse().unwrap())
+                    .unwrap()
+                    .0
+                    .bg
+            };
+            assert_eq!(bg("/remove"), None);
+            assert_eq!(bg("/remove?bg=%23ff8800").as_deref(), Some("#ff8800"));
+        }
+
+        #[test]
+        fn parses_the_mode() {
+            assert_eq!(mode("/remove"), Some(Mode::Standard));
+            assert_eq!(mode("/remove?mode=draft"), Some(Mode::Draft));
+            assert_eq!(mode("/remove?mode=final"), Some(Mode::Final));
+            assert_eq!(mode("/remove?mode=fast"), None);
+        }
+    }
+
+    mod request_background {
+        use super::*;
+
+        #[test]
+        fn parses_colors_and_gradients() {
+            let white = Rgb([255, 255, 255]);
+            assert_eq!(
+                RequestBackground::parse("#fff"),
+                Ok(RequestBackground::Color(white))
+            );
+            assert_eq!(
+                RequestBackground::parse("#ffffff, #000000"),
+                Ok(RequestBackground::Gradient(white, Rgb([0, 0, 0])))
+            );
+            assert!(RequestBackground::parse("#ffffff,").is_err());
+            assert!(RequestBackground::parse("white").is_err());
+        }
+
+        #[test]
+        fn gradients_run_from_top_to_bottom() {
+            let gradient = vertical_gradient(Rgb([0, 0, 0]), Rgb([200, 100, 0]), 3, 5);
+            assert_eq!(gradient.dimensions(), (3, 5));
+            assert_eq!(*gradient.get_pixel(2, 0), Rgb([0, 0, 0]));
+            assert_eq!(*gradient.get_pixel(1, 2), Rgb([100, 50, 0]));
+            assert_eq!(*gradient.get_pixel(0, 4), Rgb([200, 100, 0]));
+            assert_eq!(
+                *vertical_gradient(Rgb([9, 9, 9]), Rgb([0, 0, 0]), 1, 1).get_pixel(0, 0),
+                Rgb([9, 9, 9])
+            );
+        }
+    }
+
+    mod is_multipart {
+        use super::*;
+
+        #[test]
+        fn detects_form_uploads() {
+            assert!(is_multipart("multipart/form-data; boundary=abc"));
+            assert!(is_multipart("Multipart/Form-Data; boundary=abc"));
+            assert!(!is_multipart("image/png"));
+            assert!(!is_multipart("application/octet-stream"));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bgr::{BgrResult, Context, InferenceEnd, Telemetry, Warning, subject_bounds};
+use image::{GrayImage, Luma, RgbaImage};
+use serde::Serialize;
+
+use crate::cli::GlobalOptions;
+use crate::i18n::tr;
+
+use super::utils::{report, requested_model};
+
+/// Alpha at or above which a pixel counts as subject.
+const SUBJECT_ALPHA: u8 = 128;
+
+/// Where the subject of an alpha channel is and how much of the image it covers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubjectStats {
+    /// Bounds of the subject pixels as `[x, y, width, height]`, or `None` when there are none.
+    pub bounding_box: Option<[u32; 4]>,
+    /// Number of subject pixels.
+    pub area: u64,
+    /// Subject pixels as a percentage of the image.
+    pub coverage_percent: f64,
+    /// Alpha-weighted center of the subject as `[x, y]`.
+    pub centroid: Option<[f64; 2]>,
+}
+
+impl SubjectStats {
+    /// Measure the subject of `alpha`; pixels at 50% alpha or more are subject.
+    pub fn measure(alpha: &GrayImage) -> Self {
+        let mut area = 0u64;
+        let (mut weight, mut sum_x, mut sum_y) = (0f64, 0f64, 0f64);
+        for (x, y, pixel) in alpha.enumerate_pixels() {
+            let value = pixel[0];
+            let w = f64::from(value);
+            weight += w;
+            sum_x += w * (f64::from(x) + 0.5);
+            sum_y += w * (f64::from(y) + 0.5);
+            if value >= SUBJECT_ALPHA {
+                area += 1;
+            }
+        }
+        let bounds = subject_bounds(alpha, 0, SUBJECT_ALPHA - 1);
+        let pixels = u64::from(alpha.width()) * u64::from(alpha.height());
+        Self {
+            bounding_box: bounds.map(|(x0, y0, x1, y1)| [x0, y0, x1 - x0 + 1, y1 - y0 + 1]),
+            area,
+            coverage_percent: area as f64 * 100.0 / pixels.max(1) as f64,
+            centroid: (weight > 0.0).then(|| [sum_x / weight, sum_y / weight]),
+        }
+    }
+}
+
+/// The alpha channel of a cutout.
+pub fn alpha_channel(rgba: &RgbaImage) -> GrayImage {
+    GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        Luma([rgba.get_pixel(x, y)[3]])
+    })
+}
+
+thread_local! {
+    /// Model time on this thread since the last [`Stopwatch::start`].
+    static INFERENCE: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Adds up the preprocessing and model time [`Telemetry`] reports for the input on each
+/// thread, which is where sessions run their hooks.
+pub struct InferenceTimer;
+
+impl Telemetry for InferenceTimer {
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        INFERENCE.with(|time| time.set(time.get() + event.preprocess + event.inference));
+    }
+}
+
+/// Times the work on one input for its sidecar.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    started: Instant,
+    inference: Duration,
+}
+
+impl Stopwatch {
+    /// Start timing an input on this thread.
+    pub fn start() -> Self {
+        INFERENCE.with(|time| time.set(Duration::ZERO));
+        Self {
+            started: Instant::now(),
+            inference: Duration::ZERO,
+        }
+    }
+
+    /// Start timing an input whose matte was inferred earlier, in `inference`.
+    pub fn inferred_earlier(inference: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            inference,
+        }
+    }
+
+    /// How long the matte took.
+    pub fn inference(&self) -> Duration {
+        self.inference
+    }
+
+    /// Record that the matte is ready, taking the model time since [`start`](Self::start).
+    pub fn inferred(mut self) -> Self {
+        self.inference = INFERENCE.with(Cell::get);
+        self
+    }
+}
+
+/// Milliseconds spent on an input.
+#[derive(Debug, Serialize)]
+struct Timings {
+    /// Preprocessing and model time, summed over every run the input took; 0 for a
+    /// supplied mask.
+    inference_ms: f64,
+    /// Everything up to the sidecar, including writing the outputs.
+    total_ms: f64,
+}
+
+/// What `--json-sidecar` writes next to an output.
+#[derive(Debug, Serialize)]
+struct Sidecar<'a> {
+    input: &'a Path,
+    output: &'a Path,
+    width: u32,
+    height: u32,
+    #[serde(flatten)]
+    subject: SubjectStats,
+    /// The `--model` that made the matte; `None` for supplied masks and `--via-daemon`.
+    model: Option<&'a str>,
+    /// The input's warnings, including `input_kept_whole` when `--on-empty` or `--on-full`
+    /// wrote it whole.
+    warnings: &'a [Warning],
+    timings: Timings,
+}
+
+/// The model a local session ran: `--model`, with `auto` resolved to its preset.
+///
+/// `None` under `--via-daemon`, where the daemon's own model applies.
+pub fn local_model<'a>(ctx: &Context, global: &'a GlobalOptions) -> Option<&'a str> {
+    #[cfg(unix)]
+    if global.via_daemon.is_some() {
+        return None;
+    }
+    Some(requested_model(ctx, global))
+}
+
+/// Write the stats of `alpha`, the alpha channel of `output`, and the input's `warnings` to
+/// `output` with a `.json` extension.
+pub fn write_sidecar(
+    ctx: &Context,
+    input: &Path,
+    output: &Path,
+    alpha: &GrayImage,
+    model: Option<&str>,
+    warnings: &[Warning],
+    stopwatch: Stopwatch,
+) -> BgrResult<()> {
+    let sidecar = Sidecar {
+        input,
+        output,
+        width: alpha.width(),
+        height: alpha.height(),
+        subject: SubjectStats::measure(alpha),
+        model,
+        warnings,
+        timings: Timings {
+            inference_ms: stopwatch.inference.as_secs_f64() * 1000.0,
+            total_ms: stopwatch.started.elapsed().as_secs_f64() * 1000.0,
+        },
+    };
+    let path = output.with_extension("json");
+    let json = serde_json::to_string_pretty(&sidecar).map_err(io::Error::from)?;
+    fs::write(&path, json)?;
+    report(ctx, tr!("saved-sidecar", path = path.display().to_string()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod subject_stats {
+        use super::*;
+
+        #[test]
+        fn measures_a_square() {
+            let mut alpha = GrayImage::new(10, 10);
+            for y in 2..6 {
+                for x in 4..8 {
+                    alpha.put_pixel(x, y, Luma([255]));
+                }
+            }
+            // A faint halo counts toward the centroid weight but not the subject.
+            alpha.put_pixel(0, 0, Luma([10]));
+            let stats = SubjectStats::measure(&alpha);
+            assert_eq!(stats.bounding_box, Some([4, 2, 4, 4]));
+            assert_eq!(stats.area, 16);
+            assert_eq!(stats.coverage_percent, 16.0);
+            let [cx, cy] = stats.centroid.unwrap();
+            assert!((cx - 6.0).abs() < 0.1 && (cy - 4.0).abs() < 0.1);
+        }
+
+        #[test]
+        fn empty_alpha_has_no_subject() {
+            let stats = SubjectStats::measure(&GrayImage::new(4, 4));
+            assert_eq!(stats.bounding_box, None);
+            assert_eq!(stats.area, 0);
+            assert_eq!(stats.centroid, None);
+        }
+    }
+}
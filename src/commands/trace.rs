@@ -1,42 +1,414 @@
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use bgr::{BgrResult, VtracerSvgVectorizer};
+use bgr::{
+    Affine, Bgr, BgrError, BgrResult, CenterlineOptions, CenterlineVectorizer, CollisionOptions,
+    CollisionShape, CollisionVectorizer, Context, ContourOptions, ContourVectorizer,
+    CoordinateOptions, InferencedMatte, LottieOptions, LottieVectorizer, MaskVectorizer,
+    MatteHandle, OutlineSequence, SequenceOptions, Warning, apply_coordinates,
+};
+#[cfg(feature = "vectorizer-vtracer")]
+use bgr::{TraceOptions, VtracerSvgVectorizer};
 
-use crate::cli::{GlobalOptions, MaskSourceArg, TraceCommand};
+use crate::cli::{
+    ContourMetadataArg, GlobalOptions, ProgressArg, TraceBackendArg, TraceCommand, TraceOptionsArgs,
+};
+use crate::i18n::tr;
 
+use super::batch::{
+    InputKind, MatteSource, collect_inputs, ensure_single_input, prepare_outputs, print_warnings,
+    run_batch, run_batch_without_model,
+};
 use super::utils::{
-    build_bgr, derive_svg_path, processing_requested, resolve_mask_source_arg,
-    warn_if_soft_conflict,
+    ExtentOutcome, MaskChoice, apply_extent_policy, build_bgr, container_input, derive_svg_path,
+    primary_output_path, processing_requested, read_matte_container, reject_target_profile, report,
+    resolve_mask_source_arg, warn_if_soft_conflict, write_claimed,
 };
 
-/// The main function to run the trace command.
-pub fn run(global: &GlobalOptions, cmd: TraceCommand) -> BgrResult<()> {
-    let bgr = build_bgr(global, &cmd.mask_processing)?;
-    let session = bgr.for_image(&cmd.input)?;
-    let matte = session.matte();
-    let output_path = cmd
-        .output
-        .clone()
-        .unwrap_or_else(|| derive_svg_path(&cmd.input));
+/// The vectorizer selected for a trace run, with its options.
+enum Tracer {
+    #[cfg(feature = "vectorizer-vtracer")]
+    Vtracer(TraceOptions),
+    Centerline(CenterlineOptions),
+    Collision(CollisionOptions),
+    Contour(ContourOptions),
+    Lottie(LottieOptions),
+}
 
-    let options = (&cmd.trace_options).into();
+/// Tracer and output settings shared by every input of a trace run.
+struct TracePlan {
+    tracer: Tracer,
+    coordinates: CoordinateOptions,
+    mask_source: MaskChoice,
+    /// Write contour metadata to a JSON file next to each SVG.
+    metadata_sidecar: bool,
+}
 
-    let vectorizer = VtracerSvgVectorizer;
-    let processing_requested = processing_requested(&cmd.mask_processing);
+/// The main function to run the trace command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: TraceCommand) -> BgrResult<()> {
+    reject_target_profile(global, "traced outlines")?;
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let inputs = collect_inputs(&cmd.inputs, InputKind::read_by(cmd.from, cmd.from_mask))?;
+    // A Lottie sequence is one animation, written to one file.
+    let single_output = cmd.sequence && cmd.lottie;
+    ensure_single_input(&inputs, "--output", cmd.output.is_some() && !single_output)?;
 
+    let processing_requested = processing_requested(&cmd.mask_processing);
     let mask_source = resolve_mask_source_arg(cmd.mask_source, processing_requested);
 
-    if matches!(mask_source, MaskSourceArg::Processed) {
+    if matches!(mask_source, MaskChoice::Processed) {
         warn_if_soft_conflict(&cmd.mask_processing, "tracing output");
     }
 
-    let svg = match mask_source {
-        MaskSourceArg::Raw => matte.trace(&vectorizer, &options)?,
-        MaskSourceArg::Processed => matte.clone().processed()?.trace(&vectorizer, &options)?,
-        MaskSourceArg::Auto => unreachable!(),
+    if cmd.sequence {
+        return trace_sequence(ctx, global, &cmd, &bgr, &inputs, mask_source);
+    }
+
+    let plan = TracePlan {
+        tracer: if cmd.lottie {
+            Tracer::Lottie(lottie_options(&cmd)?)
+        } else {
+            select_tracer(&cmd.trace_options)?
+        },
+        coordinates: (&cmd.coordinates).into(),
+        mask_source,
+        metadata_sidecar: cmd.trace_options.metadata == Some(ContourMetadataArg::Json),
+    };
+
+    if cmd.from.is_some() {
+        return run_batch_without_model(&inputs, global, |input| {
+            let container = read_matte_container(ctx, input)?;
+            let path = container_input(input, &container);
+            write_claimed(
+                global,
+                input,
+                output_path(ctx, global, &cmd, &path)?,
+                |output| {
+                    let inferred = bgr.for_matte_container(container)?;
+                    trace_one(ctx, global, &cmd, &plan, &path, output, inferred)
+                },
+            )
+        });
+    }
+    if cmd.from_mask {
+        let options = (&cmd.mask_input).into();
+        return run_batch_without_model(&inputs, global, |input| {
+            write_claimed(
+                global,
+                input,
+                output_path(ctx, global, &cmd, input)?,
+                |output| {
+                    let inferred = bgr.for_mask(input, &options)?;
+                    trace_one(ctx, global, &cmd, &plan, input, output, inferred)
+                },
+            )
+        });
+    }
+    run_batch(&bgr, &inputs, global, |session, input| {
+        write_claimed(
+            global,
+            input,
+            output_path(ctx, global, &cmd, input)?,
+            |output| {
+                let inferred = session.for_image(input)?;
+                trace_one(ctx, global, &cmd, &plan, input, output, inferred)
+            },
+        )
+    })
+}
+
+/// Batch options that a `--sequence` does not support, as its frames are one job rather than a
+/// batch of inputs.
+const SEQUENCE_UNSUPPORTED: &[(&str, fn(&GlobalOptions) -> bool)] = &[
+    ("--report", |global| global.report.is_some()),
+    ("--before", |global| global.before.is_some()),
+    ("--after", |global| global.after.is_some()),
+    ("--resume", |global| global.checkpoint.resume),
+    ("--debug-bundle", |global| global.debug_bundle.is_some()),
+    ("--progress json", |global| {
+        global.progress == ProgressArg::Json
+    }),
+];
+
+/// Trace `inputs` as the frames of one animation, writing an SVG per frame whose outlines
+/// match the other frames' node for node, or under `--lottie` one animation of them all.
+///
+/// Every matte is needed before the first outline can be placed, so the frames run one after
+/// another and a failed frame fails the sequence. Empty and full frames are traced as they
+/// are, since skipping one would leave a gap in the animation.
+fn trace_sequence(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &TraceCommand,
+    bgr: &Bgr,
+    inputs: &[PathBuf],
+    mask_source: MaskChoice,
+) -> BgrResult<()> {
+    if let Some((flag, _)) = SEQUENCE_UNSUPPORTED.iter().find(|(_, used)| used(global)) {
+        return Err(BgrError::invalid_input(format!(
+            "--sequence does not support {flag}"
+        )));
+    }
+    let options = outline_options(&cmd.trace_options)?;
+    prepare_outputs(inputs, global, cmd.output.as_deref())?;
+    let mut source = if cmd.from.is_none() && !cmd.from_mask {
+        Some(MatteSource::open(bgr, global)?)
+    } else {
+        None
     };
-    fs::write(&output_path, &svg)?;
-    println!("SVG saved to {}", output_path.display());
+    let mask_options = (&cmd.mask_input).into();
+    let mut frames = Vec::with_capacity(inputs.len());
+    let mut masks = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let (frame, inferred) = match &mut source {
+            Some(source) => (input.clone(), source.for_image(input)?),
+            None if cmd.from_mask => (input.clone(), bgr.for_mask(input, &mask_options)?),
+            None => {
+                let container = read_matte_container(ctx, input)?;
+                (
+                    container_input(input, &container),
+                    bgr.for_matte_container(container)?,
+                )
+            }
+        };
+        print_warnings(&frame, inferred.warnings());
+        masks.push(match mask_source {
+            MaskChoice::Raw => inferred.matte().into_image(),
+            MaskChoice::Processed => inferred.matte().processed()?.into_image(),
+        });
+        frames.push(frame);
+    }
 
+    let sequence = OutlineSequence::trace(&masks, &options)?;
+    if cmd.lottie {
+        let first = &frames[0];
+        let warnings = write_claimed(
+            global,
+            first,
+            output_path(ctx, global, cmd, first)?,
+            |output| {
+                fs::write(output, sequence.lottie(&lottie_options(cmd)?))?;
+                report(
+                    ctx,
+                    tr!("saved-lottie", path = output.display().to_string()),
+                );
+                Ok(Vec::new())
+            },
+        )?;
+        print_warnings(first, &warnings);
+        return Ok(());
+    }
+    let coordinates = CoordinateOptions::from(&cmd.coordinates);
+    for (frame, svg) in frames.iter().zip(sequence.svgs(&options)) {
+        let warnings = write_claimed(
+            global,
+            frame,
+            output_path(ctx, global, cmd, frame)?,
+            |output| {
+                let svg = apply_coordinates(&svg, sequence.width, sequence.height, &coordinates)?;
+                fs::write(output, svg)?;
+                report(ctx, tr!("saved-svg", path = output.display().to_string()));
+                Ok(Vec::new())
+            },
+        )?;
+        print_warnings(frame, &warnings);
+    }
     Ok(())
 }
+
+/// The outline settings of `--sequence` and `--lottie`, which only trace filled outlines.
+fn outline_options(args: &TraceOptionsArgs) -> BgrResult<SequenceOptions> {
+    if args.centerline() || args.collision() {
+        return Err(BgrError::invalid_input(
+            "--sequence and --lottie cannot be used with --mode centerline or collision",
+        ));
+    }
+    Ok(args.into())
+}
+
+/// The settings of a `--lottie` animation.
+fn lottie_options(cmd: &TraceCommand) -> BgrResult<LottieOptions> {
+    Ok(LottieOptions {
+        outline: outline_options(&cmd.trace_options)?,
+        frame_rate: f64::from(cmd.fps),
+    })
+}
+
+/// Pick the vectorizer from the mode and backend flags.
+fn select_tracer(args: &TraceOptionsArgs) -> BgrResult<Tracer> {
+    if args.collision() {
+        if args.metadata.is_some() || args.preserve_corners {
+            return Err(BgrError::invalid_input(
+                "--metadata and --preserve-corners are not supported with --mode collision",
+            ));
+        }
+        return Ok(Tracer::Collision(args.into()));
+    }
+    if args.max_vertices.is_some() || args.convexity.is_some() {
+        return Err(BgrError::invalid_input(
+            "--max-vertices and --convexity require --mode collision",
+        ));
+    }
+    if args.centerline() {
+        if args.metadata.is_some() {
+            return Err(BgrError::invalid_input(
+                "--metadata is not supported with --mode centerline",
+            ));
+        }
+        return Ok(Tracer::Centerline(args.into()));
+    }
+    match args.backend() {
+        #[cfg(feature = "vectorizer-vtracer")]
+        TraceBackendArg::Vtracer => {
+            if args.preserve_corners {
+                return Err(BgrError::invalid_input(
+                    "--preserve-corners requires --backend contour",
+                ));
+            }
+            if args.metadata.is_some() {
+                return Err(BgrError::invalid_input(
+                    "--metadata requires --backend contour",
+                ));
+            }
+            Ok(Tracer::Vtracer(args.into()))
+        }
+        TraceBackendArg::Contour => Ok(Tracer::Contour(args.into())),
+    }
+}
+
+/// Where the SVG, or the Lottie JSON, of `input` is written.
+fn output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &TraceCommand,
+    input: &Path,
+) -> BgrResult<PathBuf> {
+    match &cmd.output {
+        Some(output) => Ok(output.clone()),
+        None if cmd.lottie => primary_output_path(ctx, global, input, input.with_extension("json")),
+        None => primary_output_path(ctx, global, input, derive_svg_path(input)),
+    }
+}
+
+/// Trace a single input into the SVG, or Lottie, file `output_path`.
+fn trace_one(
+    ctx: &Context,
+    global: &GlobalOptions,
+    cmd: &TraceCommand,
+    plan: &TracePlan,
+    input: &Path,
+    output_path: &Path,
+    session: InferencedMatte,
+) -> BgrResult<Vec<Warning>> {
+    let session = match apply_extent_policy(&cmd.extent, session)? {
+        ExtentOutcome::Write(session) => session,
+        ExtentOutcome::Skip(warnings) => return Ok(warnings),
+    };
+    let matte = session.matte();
+
+    let mut metadata = None;
+    let mut shapes = None;
+    let svg = match &plan.tracer {
+        #[cfg(feature = "vectorizer-vtracer")]
+        Tracer::Vtracer(options) => {
+            trace_matte(&matte, plan.mask_source, &VtracerSvgVectorizer, options)?
+        }
+        Tracer::Centerline(options) => {
+            trace_matte(&matte, plan.mask_source, &CenterlineVectorizer, options)?
+        }
+        Tracer::Collision(options) => {
+            let trace = trace_matte(&matte, plan.mask_source, &CollisionVectorizer, options)?;
+            shapes = Some(trace.shapes);
+            trace.svg
+        }
+        Tracer::Contour(options) => {
+            let trace = trace_matte(&matte, plan.mask_source, &ContourVectorizer, options)?;
+            metadata = Some(trace.contours);
+            trace.svg
+        }
+        Tracer::Lottie(options) => {
+            let lottie = trace_matte(&matte, plan.mask_source, &LottieVectorizer, options)?;
+            fs::write(output_path, lottie)?;
+            report(
+                ctx,
+                tr!("saved-lottie", path = output_path.display().to_string()),
+            );
+            return Ok(session.warnings().to_vec());
+        }
+    };
+    let (width, height) = session.rgb_image().dimensions();
+    let svg = apply_coordinates(&svg, width, height, &plan.coordinates)?;
+    fs::write(output_path, &svg)?;
+    report(
+        ctx,
+        tr!("saved-svg", path = output_path.display().to_string()),
+    );
+
+    if let Some(shapes) = shapes {
+        let matrix = plan.coordinates.matrix(width, height);
+        let json_path = output_path.with_extension("json");
+        let json = serde_json::to_string_pretty(&transform_shapes(shapes, &matrix))
+            .map_err(io::Error::from)?;
+        fs::write(&json_path, json)?;
+        report(
+            ctx,
+            tr!(
+                "saved-collision-shapes",
+                path = json_path.display().to_string()
+            ),
+        );
+    }
+    if let Some(contours) = metadata.filter(|_| plan.metadata_sidecar) {
+        let json_path = output_path.with_extension("json");
+        let json = serde_json::to_string_pretty(&contours).map_err(io::Error::from)?;
+        fs::write(&json_path, json)?;
+        report(
+            ctx,
+            tr!(
+                "saved-contour-metadata",
+                path = json_path.display().to_string()
+            ),
+        );
+    }
+
+    Ok(session.warnings().to_vec())
+}
+
+/// Collision polygons moved into the output coordinate system, as the SVG paths are.
+fn transform_shapes(shapes: Vec<CollisionShape>, matrix: &Affine) -> Vec<CollisionShape> {
+    shapes
+        .into_iter()
+        .map(|shape| CollisionShape {
+            polygons: shape
+                .polygons
+                .into_iter()
+                .map(|polygon| {
+                    polygon
+                        .into_iter()
+                        .map(|[x, y]| {
+                            let (x, y) = matrix.apply(x, y);
+                            [x, y]
+                        })
+                        .collect()
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Trace the raw or processed matte with the given vectorizer.
+fn trace_matte<V>(
+    matte: &MatteHandle,
+    mask_source: MaskChoice,
+    vectorizer: &V,
+    options: &V::Options,
+) -> BgrResult<V::Output>
+where
+    V: MaskVectorizer,
+{
+    match mask_source {
+        MaskChoice::Raw => matte.trace(vectorizer, options),
+        MaskChoice::Processed => matte.clone().processed()?.trace(vectorizer, options),
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bgr::{BgrResult, Context, Device, InferenceEnd, ModelLoad, Telemetry};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{Commands, GlobalOptions, UsageAction, UsageCommand, UsageGroupArg};
+use crate::i18n::tr;
+
+use super::utils::{SECONDS_PER_DAY, requested_model, utc_date};
+
+/// Usage log file name inside the data directory.
+const USAGE_LOG_NAME: &str = "usage.jsonl";
+
+/// The main function to run the usage command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: UsageCommand) -> BgrResult<()> {
+    match cmd.action {
+        UsageAction::Report { by, json } => {
+            let path = log_path(ctx, global);
+            let (records, skipped) = read_log(&path)?;
+            if skipped > 0 {
+                tracing::warn!(
+                    "{}",
+                    tr!(
+                        "usage-skipped-lines",
+                        count = skipped,
+                        path = path.display().to_string()
+                    )
+                );
+            }
+            let totals = aggregate(&records, by);
+            if json {
+                let json = serde_json::to_string_pretty(&totals).map_err(io::Error::from)?;
+                println!("{json}");
+            } else {
+                print_table(by, &totals);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The log `--usage-log` names, or `usage.jsonl` in the data directory.
+fn log_path(ctx: &Context, global: &GlobalOptions) -> PathBuf {
+    global
+        .usage_log
+        .clone()
+        .flatten()
+        .unwrap_or_else(|| ctx.data_dir().join(USAGE_LOG_NAME))
+}
+
+/// Counts the work of every session a run loads, as their telemetry.
+#[derive(Clone, Default)]
+pub struct UsageMeter(Arc<Counters>);
+
+#[derive(Default)]
+struct Counters {
+    images: AtomicU64,
+    pixels: AtomicU64,
+    model_loads: AtomicU64,
+    load_nanos: AtomicU64,
+    inference_nanos: AtomicU64,
+    device: Mutex<Option<Device>>,
+}
+
+impl Telemetry for UsageMeter {
+    fn on_model_load(&self, event: &ModelLoad) {
+        self.0.model_loads.fetch_add(1, Ordering::Relaxed);
+        add_duration(&self.0.load_nanos, event.duration);
+        *self.0.device.lock().expect("usage meter lock poisoned") = Some(event.device);
+    }
+
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        let (width, height) = event.image_size;
+        self.0.images.fetch_add(1, Ordering::Relaxed);
+        self.0
+            .pixels
+            .fetch_add(u64::from(width) * u64::from(height), Ordering::Relaxed);
+        add_duration(&self.0.inference_nanos, event.preprocess + event.inference);
+    }
+}
+
+fn add_duration(counter: &AtomicU64, duration: Duration) {
+    let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    counter.fetch_add(nanos, Ordering::Relaxed);
+}
+
+/// One run, as a line of the usage log. Holds counts and timings only: no paths or pixels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Seconds since the Unix epoch when the run finished.
+    pub finished_at: u64,
+    /// The subcommand, such as `cut`.
+    pub command: String,
+    /// The `--usage-tag`, such as a team or project.
+    pub tag: Option<String>,
+    /// The `--model`, when the run loaded one.
+    pub model: Option<String>,
+    /// The device the model ran on, after any fallback to the CPU.
+    pub device: Option<String>,
+    /// Inferences run: one per image, or per frame of an animation or video.
+    pub images: u64,
+    /// Input pixels inferred, summed over the images.
+    pub pixels: u64,
+    pub model_loads: u64,
+    pub load_ms: f64,
+    /// Preprocessing and model time, summed over the images.
+    pub inference_ms: f64,
+    /// Wall-clock time of the whole run.
+    pub duration_ms: f64,
+    pub succeeded: bool,
+}
+
+impl UsageMeter {
+    /// The record of a run of `command` that started at `started`.
+    fn record(
+        &self,
+        ctx: &Context,
+        global: &GlobalOptions,
+        command: &str,
+        started: Instant,
+        succeeded: bool,
+    ) -> UsageRecord {
+        let counters = &self.0;
+        let millis = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as f64 / 1e6;
+        let model_loads = counters.model_loads.load(Ordering::Relaxed);
+        let device = *counters.device.lock().expect("usage meter lock poisoned");
+        UsageRecord {
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            command: command.to_string(),
+            tag: global.usage_tag.clone(),
+            model: (model_loads > 0).then(|| requested_model(ctx, global).to_string()),
+            device: device.map(|device| device.to_string()),
+            images: counters.images.load(Ordering::Relaxed),
+            pixels: counters.pixels.load(Ordering::Relaxed),
+            model_loads,
+            load_ms: millis(&counters.load_nanos),
+            inference_ms: millis(&counters.inference_nanos),
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            succeeded,
+        }
+    }
+
+    /// Append the record of this run to the usage log, warning instead of failing the run
+    /// when the log cannot be written.
+    pub fn append(
+        &self,
+        ctx: &Context,
+        global: &GlobalOptions,
+        command: &str,
+        started: Instant,
+        succeeded: bool,
+    ) {
+        let path = log_path(ctx, global);
+        let record = self.record(ctx, global, command, started, succeeded);
+        if let Err(err) = append_record(&path, &record) {
+            tracing::warn!(
+                "{}",
+                tr!(
+                    "usage-log-failed",
+                    path = path.display().to_string(),
+                    error = err.to_string()
+                )
+            );
+        }
+    }
+}
+
+/// Append `record` as one line, in a single write so concurrent runs do not interleave.
+fn append_record(path: &Path, record: &UsageRecord) -> io::Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Every record in the log at `path`, with the number of lines that were not records.
+fn read_log(path: &Path) -> BgrResult<(Vec<UsageRecord>, usize)> {
+    let file = fs::File::open(path).map_err(|err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "no usage log at {}; record one with --usage-log",
+                    path.display()
+                ),
+            )
+        } else {
+            err
+        }
+    })?;
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok((records, skipped))
+}
+
+/// The runs of one group in a usage report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct UsageTotals {
+    group: String,
+    runs: u64,
+    failed: u64,
+    images: u64,
+    megapixels: f64,
+    inference_seconds: f64,
+    wall_seconds: f64,
+}
+
+/// Sum `records` per group, in group order.
+fn aggregate(records: &[UsageRecord], by: UsageGroupArg) -> Vec<UsageTotals> {
+    let mut groups: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    for record in records {
+        let group = match by {
+            UsageGroupArg::Command => record.command.clone(),
+            UsageGroupArg::Model => record.model.clone().unwrap_or_else(|| "-".into()),
+            UsageGroupArg::Tag => record.tag.clone().unwrap_or_else(|| "-".into()),
+            UsageGroupArg::Device => record.device.clone().unwrap_or_else(|| "-".into()),
+            UsageGroupArg::Day => utc_date(record.finished_at),
+        };
+        let totals = groups.entry(group.clone()).or_insert_with(|| UsageTotals {
+            group,
+            ..UsageTotals::default()
+        });
+        totals.runs += 1;
+        totals.failed += u64::from(!record.succeeded);
+        totals.images += record.images;
+        totals.megapixels += record.pixels as f64 / 1e6;
+        totals.inference_seconds += record.inference_ms / 1000.0;
+        totals.wall_seconds += record.duration_ms / 1000.0;
+    }
+    groups.into_values().collect()
+}
+
+fn print_table(by: UsageGroupArg, totals: &[UsageTotals]) {
+    let heading = format!("{by:?}").to_uppercase();
+    println!(
+        "{heading:<20} {:>6} {:>6} {:>10} {:>12} {:>13} {:>12}",
+        "RUNS", "FAILED", "IMAGES", "MEGAPIXELS", "INFERENCE_S", "WALL_S"
+    );
+    for group in totals {
+        println!(
+            "{:<20} {:>6} {:>6} {:>10} {:>12.1} {:>13.1} {:>12.1}",
+            group.group,
+            group.runs,
+            group.failed,
+            group.images,
+            group.megapixels,
+            group.inference_seconds,
+            group.wall_seconds
+        );
+    }
+}
+
+/// The subcommand name of `command` as recorded in the usage log, or `None` for commands
+/// that are not accounted: `usage` itself and the internal decode worker.
+pub fn accounted_name(command: &Commands) -> Option<&'static str> {
+    Some(match command {
+        Commands::Mask(_) => "mask",
+        Commands::Cut(_) => "cut",
+        Commands::Trace(_) => "trace",
+        Commands::Verify(_) => "verify",
+        Commands::Models(_) => "models",
+        #[cfg(feature = "test-utils")]
+        Commands::Fixtures(_) => "fixtures",
+        #[cfg(unix)]
+        Commands::Daemon(_) => "daemon",
+        #[cfg(feature = "server")]
+        Commands::Serve(_) => "serve",
+        Commands::Watch(_) => "watch",
+        #[cfg(feature = "video")]
+        Commands::Video(_) => "video",
+        Commands::Examples(_) => "examples",
+        Commands::Config(_) => "config",
+        Commands::Completions(_) => "completions",
+        Commands::Manpage(_) => "manpage",
+        Commands::Run(_) => "run",
+        Commands::Doctor => "doctor",
+        Commands::Usage(_) => return None,
+        #[cfg(feature = "sandboxed-decode")]
+        Commands::DecodeWorker => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(command: &str, finished_at: u64, images: u64, succeeded: bool) -> UsageRecord {
+        UsageRecord {
+            finished_at,
+            command: command.into(),
+            tag: Some("catalog".into()),
+            model: Some("isnet".into()),
+            device: Some("cuda".into()),
+            images,
+            pixels: images * 2_000_000,
+            model_loads: 1,
+            load_ms: 300.0,
+            inference_ms: images as f64 * 500.0,
+            duration_ms: images as f64 * 700.0,
+            succeeded,
+        }
+    }
+
+    mod usage_meter {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn counts_loads_inferences_and_pixels() {
+                let meter = UsageMeter::default();
+                meter.on_model_load(&ModelLoad {
+                    model_path: "model.onnx".into(),
+                    device: Device::Cuda,
+                    duration: Duration::from_millis(250),
+                });
+                for _ in 0..3 {
+                    meter.on_inference_end(&InferenceEnd {
+                        image_size: (200, 100),
+                        input_shape: vec![1, 3, 1024, 1024],
+                        output_shape: vec![1, 1, 1024, 1024],
+                        device: Device::Cuda,
+                        preprocess: Duration::from_millis(10),
+                        inference: Duration::from_millis(40),
+                    });
+                }
+                let counters = &meter.0;
+                assert_eq!(counters.images.load(Ordering::Relaxed), 3);
+                assert_eq!(counters.pixels.load(Ordering::Relaxed), 60_000);
+                assert_eq!(counters.model_loads.load(Ordering::Relaxed), 1);
+                assert_eq!(
+                    counters.inference_nanos.load(Ordering::Relaxed),
+                    150_000_000
+                );
+                assert_eq!(*counters.device.lock().unwrap(), Some(Device::Cuda));
+            }
+        }
+    }
+
+    mod usage_log {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn appended_records_read_back() {
+                let temp = tempfile::tempdir().unwrap();
+                let dir = temp.path();
+                let path = dir.join("logs").join(USAGE_LOG_NAME);
+                append_record(&path, &record("cut", 0, 4, true)).unwrap();
+                append_record(&path, &record("mask", 0, 1, false)).unwrap();
+                fs::OpenOptions::new()
+                    .append(true)
+                    .open(&path)
+                    .unwrap()
+                    .write_all(b"not json\n")
+                    .unwrap();
+                let (records, skipped) = read_log(&path).unwrap();
+                assert_eq!(
+                    records,
+                    [record("cut", 0, 4, true), record("mask", 0, 1, false)]
+                );
+                assert_eq!(skipped, 1);
+            }
+
+            #[test]
+            fn aggregates_per_group() {
+                let records = [
+                    record("cut", 0, 4, true),
+                    record("cut", SECONDS_PER_DAY, 2, false),
+                    record("mask", SECONDS_PER_DAY + 5, 1, true),
+                ];
+                let by_command = aggregate(&records, UsageGroupArg::Command);
+                assert_eq!(by_command.len(), 2);
+                assert_eq!(by_command[0].group, "cut");
+                assert_eq!(by_command[0].runs, 2);
+                assert_eq!(by_command[0].failed, 1);
+                assert_eq!(by_command[0].images, 6);
+                assert_eq!(by_command[0].megapixels, 12.0);
+                assert_eq!(by_command[0].inference_seconds, 3.0);
+
+                let by_day = aggregate(&records, UsageGroupArg::Day);
+                let days: Vec<&str> = by_day.iter().map(|day| day.group.as_str()).collect();
+                assert_eq!(days, ["1970-01-01", "1970-01-02"]);
+                assert_eq!(by_day[1].runs, 2);
+            }
+        }
+    }
+}
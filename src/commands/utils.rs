@@ -1,72 +1,297 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use bgr::models::{ModelPreset, default_models_dir, download_model_sync, resolve_model_path};
-use bgr::{Bgr, BgrResult, MaskProcessingOptions};
+use bgr::models::{
+    AUTO_MODEL, ModelPreset, accept_license, file_sha256, license_accepted, require_license,
+};
+use bgr::{
+    Architecture, Backend, Bgr, BgrError, BgrResult, Context, InferenceSettings, InferencedMatte,
+    MaskProcessingOptions, MatteContainer, MatteHandle, MattingOptions, SharedAdapter,
+    SideOutputFusion, Verbosity, Warning,
+};
+use time::OffsetDateTime;
 
 use crate::cli::{
-    AlphaFromArg, BinaryOption, GlobalOptions, MaskExportSource, MaskProcessingArgs, MaskSourceArg,
+    AlphaFromArg, BinaryOption, ExtentPolicyArg, ExtentPolicyArgs, GlobalOptions, MaskExportSource,
+    MaskProcessingArgs, MaskSourceArg, ProgressArg, RefineArg, TargetProfile,
 };
+use crate::i18n::tr;
+use crate::output_template::{Field, OutputTemplate};
+
+use super::checkpoint;
+use super::progress::{download_with_progress, stage_progress};
+use super::sidecar::InferenceTimer;
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Build the shared context from the global options, once per run.
+/// When `needs_model` is set, resolves the model preset and downloads it if necessary.
+pub fn build_context(global: &GlobalOptions, needs_model: bool) -> BgrResult<Context> {
+    let mut ctx = Context::new()
+        .with_offline(global.offline)
+        .with_verbosity(global.verbosity());
+    if let Some(dir) = &global.models_dir {
+        ctx = ctx.with_models_dir(dir);
+    }
+    // The backend decides what a preset resolves to, so it is set before the model.
+    let backend = global.backend.map_or_else(Backend::default, Backend::from);
+    let settings = ctx.inference_settings().clone().with_backend(backend);
+    ctx = ctx.with_inference_settings(settings);
+    // Commands without a model may still decode inputs, under the same limits and sandbox.
+    let model_path = if needs_model {
+        resolve_model(&ctx, global)?
+    } else {
+        ctx.inference_settings().model_path.clone()
+    };
+
+    let settings = InferenceSettings::new(model_path)
+        .with_input_resize_filter(global.input_resample_filter.into())
+        .with_output_resize_filter(global.output_resample_filter.into())
+        .with_intra_threads(global.intra_threads)
+        .with_low_memory(global.low_memory)
+        .with_device(global.device.into())
+        .with_backend(backend)
+        .with_adapter(adapter(&ctx, global, requested_model(&ctx, global))?)
+        .with_limits((&global.limits).into())
+        .with_intent(global.intent.into())
+        .with_auto_orient(!global.no_auto_orient)
+        .with_progress(stage_progress(global))
+        .with_telemetry(InferenceTimer)
+        .with_cancellation(super::cancellation().clone());
+    #[cfg(feature = "sandboxed-decode")]
+    let settings = if global.sandbox_decode {
+        settings.with_sandbox(bgr::SandboxedDecoder::current_exe()?)
+    } else {
+        settings
+    };
+    Ok(ctx.with_inference_settings(settings))
+}
 
-/// Build a Bgr instance with the input global and mask processing options.
-/// Resolves model presets and downloads if necessary.
-pub fn build_bgr(global: &GlobalOptions, mask_args: &MaskProcessingArgs) -> BgrResult<Bgr> {
-    let models_dir = default_models_dir();
+/// The architecture of the model `specifier` names: its preset's, or `--model-arch` for a
+/// custom model file.
+fn architecture(ctx: &Context, global: &GlobalOptions, specifier: &str) -> Architecture {
+    let preset = match specifier {
+        AUTO_MODEL => Some(ModelPreset::auto(ctx.models_dir())),
+        name => ModelPreset::from_str(name),
+    };
+    match preset {
+        Some(preset) => preset.architecture(),
+        None => global
+            .model_arch
+            .map(Architecture::from)
+            .unwrap_or_default(),
+    }
+}
+
+/// How the model `specifier` names is pre- and post-processed: its architecture, with the
+/// side outputs fused under `--fuse-side-outputs`, which only U2-Net and IS-Net have.
+fn adapter(ctx: &Context, global: &GlobalOptions, specifier: &str) -> BgrResult<SharedAdapter> {
+    let architecture = architecture(ctx, global, specifier);
+    if !global.fuse_side_outputs {
+        return Ok(SharedAdapter::new(architecture));
+    }
+    match architecture {
+        Architecture::U2Net | Architecture::IsNet => {
+            Ok(SharedAdapter::new(SideOutputFusion::new(architecture)))
+        }
+        other => Err(BgrError::invalid_input(format!(
+            "--fuse-side-outputs needs a U2-Net or IS-Net model, and {specifier} is {}; \
+             pass --model-arch for a custom model",
+            other.name()
+        ))),
+    }
+}
+
+/// The run's inference settings for the model `specifier` names instead of `--model`,
+/// downloading the preset if needed.
+pub fn settings_for_model(
+    ctx: &Context,
+    global: &GlobalOptions,
+    specifier: &str,
+) -> BgrResult<InferenceSettings> {
+    let mut settings = ctx
+        .inference_settings()
+        .clone()
+        .with_adapter(adapter(ctx, global, specifier)?);
+    settings.model_path = resolve_specifier(ctx, global, specifier)?;
+    Ok(settings)
+}
+
+/// `--model`, with `auto` resolved to the preset it picks.
+pub fn requested_model<'a>(ctx: &Context, global: &'a GlobalOptions) -> &'a str {
+    match global.model.as_str() {
+        AUTO_MODEL => ModelPreset::auto(ctx.models_dir()).name(),
+        name => name,
+    }
+}
+
+/// Resolve `--model` to a file, downloading the preset first if necessary.
+fn resolve_model(ctx: &Context, global: &GlobalOptions) -> BgrResult<PathBuf> {
+    let specifier = requested_model(ctx, global);
+    if global.model == AUTO_MODEL {
+        tracing::info!(model = specifier, "{}", tr!("auto-model", name = specifier));
+    }
+    resolve_specifier(ctx, global, specifier)
+}
 
-    // First check if it's a preset and needs downloading
-    if let Some(preset) = ModelPreset::from_str(&global.model) {
-        let model_path = preset.local_path(&models_dir);
-        if !model_path.exists() {
+/// Resolve a preset name or model path, downloading the preset if needed.
+fn resolve_specifier(ctx: &Context, global: &GlobalOptions, specifier: &str) -> BgrResult<PathBuf> {
+    // Download presets here so the progress bar is shown; resolution below finds the file.
+    // The candle backend reads safetensors weights, which are never downloaded.
+    if !ctx.offline()
+        && ctx.inference_settings().backend != Backend::Candle
+        && let Some(preset) = ModelPreset::from_str(specifier)
+        && !preset.is_downloaded(ctx.models_dir())
+    {
+        confirm_licenses(ctx, &[preset], global)?;
+        let announce = global.progress_format() == ProgressArg::Bar;
+        if announce {
             eprintln!(
-                "Downloading model: {} ({} MB)...",
-                preset.name(),
-                preset.size_mb()
+                "{}",
+                tr!(
+                    "downloading-model",
+                    name = preset.name(),
+                    size = preset.size_mb()
+                )
             );
-            download_model_with_progress(preset, &models_dir)?;
-            eprintln!("Download complete!");
+        }
+        download_with_progress(&[preset], ctx.models_dir(), global)?;
+        if announce {
+            eprintln!("{}", tr!("download-complete"));
         }
     }
 
-    // Now resolve the path (will find the downloaded file or use as-is if it's a path)
-    let model_path = resolve_model_path(&global.model, &models_dir, false)?;
+    let model_path = ctx.resolve_model(specifier)?;
+    tracing::info!(
+        path = %model_path.display(),
+        "{}",
+        tr!("using-model", path = model_path.display().to_string())
+    );
+    Ok(model_path)
+}
 
-    let mask_processing = mask_args.into();
-    Ok(Bgr::new(model_path)
-        .with_input_resize_filter(global.input_resample_filter.into())
-        .with_output_resize_filter(global.output_resample_filter.into())
-        .with_intra_threads(global.intra_threads)
-        .with_default_mask_processing(mask_processing))
+/// Make sure the license of every restrictively licensed preset in `presets` is accepted
+/// before it is downloaded, recording `--accept-license` or asking on a terminal.
+pub fn confirm_licenses(
+    ctx: &Context,
+    presets: &[ModelPreset],
+    global: &GlobalOptions,
+) -> BgrResult<()> {
+    for &preset in presets {
+        if license_accepted(preset, ctx.data_dir())? {
+            continue;
+        }
+        if global.accept_license || ask_license(preset)? {
+            accept_license(preset, ctx.data_dir())?;
+        }
+        require_license(preset, ctx.data_dir())?;
+    }
+    Ok(())
 }
 
-/// Download a model with progress indication.
-fn download_model_with_progress(
-    preset: ModelPreset,
-    models_dir: &std::path::Path,
-) -> BgrResult<PathBuf> {
-    use indicatif::{ProgressBar, ProgressStyle};
-    use std::sync::Arc;
-
-    let pb = Arc::new(ProgressBar::new(0));
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"),
+/// Ask on the terminal whether the user accepts the license of `preset`; never when stdin
+/// is not a terminal.
+fn ask_license(preset: ModelPreset) -> io::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    let license = preset.metadata().license;
+    eprint!(
+        "{} ",
+        tr!(
+            "license-prompt",
+            name = preset.name(),
+            license = license.to_string(),
+            url = license.url()
+        )
     );
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
 
-    let pb_clone = Arc::clone(&pb);
-    let result = download_model_sync(
-        preset,
-        models_dir,
-        Some(Box::new(move |downloaded, total| {
-            if total > 0 {
-                pb_clone.set_length(total);
-            }
-            pb_clone.set_position(downloaded);
-        })),
-    );
+/// Build a Bgr instance from the shared context and the command's mask processing options.
+pub fn build_bgr(ctx: &Context, mask_args: &MaskProcessingArgs) -> Bgr {
+    Bgr::from_context(ctx).with_default_mask_processing(mask_args.into())
+}
+
+/// Read the `bgrm` stream at `input`, or on stdin for `-`, under the context's input limits.
+pub fn read_matte_container(ctx: &Context, input: &Path) -> BgrResult<MatteContainer> {
+    let limits = &ctx.inference_settings().limits;
+    if is_stdio(input) {
+        return MatteContainer::read_from(io::stdin().lock(), limits);
+    }
+    let file = File::open(input)?;
+    limits.check_file_size(file.metadata()?.len())?;
+    MatteContainer::read_from(BufReader::new(file), limits)
+}
 
-    pb.finish_with_message("done");
-    result.map_err(|e| e.into())
+/// The path to name the outputs of a piped matte after: the file itself or, for stdin, the
+/// source image recorded in the stream.
+pub fn container_input(input: &Path, container: &MatteContainer) -> PathBuf {
+    match &container.provenance.source {
+        Some(source) if is_stdio(input) => PathBuf::from(source),
+        _ => input.to_path_buf(),
+    }
+}
+
+/// Whether `path` is `-`, standing for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Apply the requested refinement pass to a freshly inferred matte.
+pub fn refine_matte(matte: MatteHandle, refine: Option<RefineArg>) -> BgrResult<MatteHandle> {
+    match refine {
+        Some(RefineArg::Matting) => matte.refine_matting(&MattingOptions::default()),
+        None => Ok(matte),
+    }
+}
+
+/// What `--on-empty` and `--on-full` leave of a fresh matte.
+pub enum ExtentOutcome {
+    /// Write the outputs from this matte, made opaque under `copy`.
+    Write(InferencedMatte),
+    /// Write nothing, reporting these warnings.
+    Skip(Vec<Warning>),
+}
+
+/// Apply `--on-empty` or `--on-full` when the model found no foreground or only foreground,
+/// going by the matte's own warnings.
+pub fn apply_extent_policy(
+    policy: &ExtentPolicyArgs,
+    session: InferencedMatte,
+) -> BgrResult<ExtentOutcome> {
+    let (chosen, error) = if session.warnings().contains(&Warning::EmptyMatte) {
+        (policy.on_empty, tr!("error-on-empty"))
+    } else if session.warnings().contains(&Warning::FullMatte) {
+        (policy.on_full, tr!("error-on-full"))
+    } else {
+        return Ok(ExtentOutcome::Write(session));
+    };
+    match chosen {
+        ExtentPolicyArg::Skip => {
+            let mut warnings = session.warnings().to_vec();
+            warnings.push(Warning::OutputSkipped);
+            Ok(ExtentOutcome::Skip(warnings))
+        }
+        ExtentPolicyArg::Copy => Ok(ExtentOutcome::Write(session.keep_whole())),
+        ExtentPolicyArg::Fail => Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            error,
+        ))),
+    }
+}
+
+/// Print a progress message unless the context is quiet.
+pub fn report(ctx: &Context, message: impl fmt::Display) {
+    if ctx.verbosity() > Verbosity::Quiet {
+        println!("{message}");
+    }
 }
 
 /// Derive a variant file path by appending a suffix before the extension.
@@ -81,18 +306,235 @@ pub fn derive_variant_path(input: &Path, suffix: &str, extension: &str) -> PathB
     derived
 }
 
+/// Move a derived output path into `out_dir` when one is given, keeping its file name.
+pub fn relocate(path: PathBuf, out_dir: Option<&Path>) -> PathBuf {
+    match (out_dir, path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path,
+    }
+}
+
+/// Where the primary output of `input` goes when `-o` is not given: named by
+/// `--output-template` when one is set, and `derived` moved into `--out-dir` otherwise.
+///
+/// A relative template lands in `--out-dir`, or next to the input, creating any
+/// directories it names.
+pub fn primary_output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    input: &Path,
+    derived: PathBuf,
+) -> BgrResult<PathBuf> {
+    let Some(template) = &global.output_template else {
+        return Ok(relocate(derived, global.out_dir_for(input)));
+    };
+    let rendered = render_template(ctx, global, template, input, &derived)?;
+    let path = match (global.out_dir_for(input), input.parent()) {
+        _ if rendered.is_absolute() => rendered,
+        (Some(dir), _) => dir.join(rendered),
+        (None, Some(parent)) => parent.join(rendered),
+        (None, None) => rendered,
+    };
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(path)
+}
+
+/// The name `template` gives the output of `input`, whose derived name is `derived`.
+pub fn render_template(
+    ctx: &Context,
+    global: &GlobalOptions,
+    template: &OutputTemplate,
+    input: &Path,
+    derived: &Path,
+) -> BgrResult<PathBuf> {
+    if is_stdio(input) && (template.uses(Field::Size) || template.uses(Field::Hash)) {
+        return Err(BgrError::invalid_input(
+            "{size} and {hash} need an input file, not stdin; give -o or drop them",
+        ));
+    }
+    let rendered = template.render(|field| template_field(ctx, global, input, derived, field))?;
+    Ok(PathBuf::from(rendered))
+}
+
+/// Where a side output of `input`, such as its exported matte, goes: `derived` moved into
+/// `--out-dir`, or under `--output-template` the main output's name with `-<suffix>` added.
+pub fn side_output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    input: &Path,
+    suffix: &str,
+    derived: PathBuf,
+) -> BgrResult<PathBuf> {
+    if global.output_template.is_none() {
+        return Ok(relocate(derived, global.out_dir_for(input)));
+    }
+    let extension = derived
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let main = primary_output_path(ctx, global, input, derived)?;
+    Ok(derive_variant_path(&main, suffix, &extension))
+}
+
+/// Whether, and where, an input's main output may be written.
+enum OutputClaim {
+    /// Write to this path, noting with the warning when it is not the one asked for.
+    Write(PathBuf, Option<Warning>),
+    /// Leave the existing output alone, skipping the input.
+    Skip(Warning),
+}
+
+/// Run `write` with the main output of `input` under `--overwrite`, `--skip-existing`, or
+/// `--suffix`, adding any rename to the warnings it returns; by default an existing output
+/// fails the input. Call it before the model runs, so skipped inputs cost nothing.
+///
+/// In a batch, the output is also checked against and recorded in the `--resume` journal.
+pub fn write_claimed(
+    global: &GlobalOptions,
+    input: &Path,
+    path: PathBuf,
+    write: impl FnOnce(&Path) -> BgrResult<Vec<Warning>>,
+) -> BgrResult<Vec<Warning>> {
+    if let Some(written) = checkpoint::already_written(input, &path) {
+        return Ok(vec![Warning::AlreadyWritten { path: written }]);
+    }
+    match claim_output(global, path.clone())? {
+        OutputClaim::Skip(warning) => Ok(vec![warning]),
+        OutputClaim::Write(written, renamed) => {
+            let result = write(&written);
+            // The placeholder `--suffix` created, left empty by a failed or skipped input,
+            // would otherwise hold the name forever.
+            if renamed.is_some() && fs::metadata(&written).is_ok_and(|meta| meta.len() == 0) {
+                let _ = fs::remove_file(&written);
+            }
+            let mut warnings = result?;
+            let skipped = warnings
+                .iter()
+                .any(|warning| matches!(warning, Warning::OutputSkipped));
+            // Exiting cleanly with nothing on stdout would hand the next command nothing.
+            if skipped && is_stdio(&written) {
+                let reason = warnings
+                    .iter()
+                    .find(|warning| !matches!(warning, Warning::OutputSkipped))
+                    .map_or_else(String::new, |warning| format!(": {warning}"));
+                return Err(BgrError::invalid_data(format!(
+                    "nothing was written to stdout{reason}; choose --on-empty or --on-full copy"
+                )));
+            }
+            if !skipped {
+                checkpoint::record(input, &path, &written)?;
+            }
+            warnings.extend(renamed);
+            Ok(warnings)
+        }
+    }
+}
+
+/// Where `path` may be written; `--suffix` creates the free name it picks, so parallel
+/// workers cannot pick the same one.
+fn claim_output(global: &GlobalOptions, path: PathBuf) -> BgrResult<OutputClaim> {
+    // An output the resumed run's journal does not list was cut short when it stopped.
+    let replace =
+        global.overwrite || (checkpoint::resuming() && !global.skip_existing && !global.suffix);
+    if replace || is_stdio(&path) || !path.exists() {
+        return Ok(OutputClaim::Write(path, None));
+    }
+    if global.skip_existing {
+        return Ok(OutputClaim::Skip(Warning::OutputExists { path }));
+    }
+    if !global.suffix {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            tr!("error-output-exists", path = path.display().to_string()),
+        )));
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut n = 0;
+    loop {
+        n += 1;
+        let candidate = path.with_file_name(format!("{stem}-{n}{extension}"));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => {
+                let warning = Warning::OutputRenamed {
+                    path: candidate.clone(),
+                };
+                return Ok(OutputClaim::Write(candidate, Some(warning)));
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// The value of one `--output-template` placeholder for `input`.
+fn template_field(
+    ctx: &Context,
+    global: &GlobalOptions,
+    input: &Path,
+    derived: &Path,
+    field: Field,
+) -> io::Result<String> {
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    Ok(match field {
+        Field::Stem => stem(input),
+        Field::Model => {
+            let name = requested_model(ctx, global);
+            ModelPreset::from_str(name)
+                .map_or_else(|| stem(Path::new(name)), |preset| preset.name().to_string())
+        }
+        Field::Date => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            utc_date(now.as_secs())
+        }
+        Field::Size => {
+            let (width, height) = image::image_dimensions(input).map_err(io::Error::other)?;
+            format!("{width}x{height}")
+        }
+        Field::Hash => {
+            let checksum = file_sha256(input).map_err(io::Error::other)?;
+            checksum[..8].to_string()
+        }
+        Field::Ext => derived
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    })
+}
+
 /// Resolve an export path from an optional double-Option field.
 /// Returns Some(path) if export is requested, None otherwise.
+/// Explicit paths are used as-is; otherwise the path is `derive`d.
 pub fn resolve_export_path(
     opt: &Option<Option<PathBuf>>,
-    input: &Path,
-    suffix: &str,
-) -> Option<PathBuf> {
-    opt.as_ref().map(|inner| {
-        inner
-            .clone()
-            .unwrap_or_else(|| derive_variant_path(input, suffix, "png"))
-    })
+    derive: impl FnOnce() -> BgrResult<PathBuf>,
+) -> BgrResult<Option<PathBuf>> {
+    match opt {
+        None => Ok(None),
+        Some(Some(path)) => Ok(Some(path.clone())),
+        Some(None) => derive().map(Some),
+    }
 }
 
 /// Derive an SVG file path by changing the extension to "svg".
@@ -102,6 +544,13 @@ pub fn derive_svg_path(input: &Path) -> PathBuf {
     path
 }
 
+/// The UTC calendar date of a Unix timestamp, as `YYYY-MM-DD`.
+pub fn utc_date(timestamp: u64) -> String {
+    let seconds = i64::try_from(timestamp).unwrap_or(i64::MAX);
+    let moment = OffsetDateTime::from_unix_timestamp(seconds).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    moment.date().to_string()
+}
+
 /// Determine if any mask processing is requested based on the provided arguments.
 pub fn processing_requested(args: &MaskProcessingArgs) -> bool {
     let derived: MaskProcessingOptions = args.into();
@@ -109,49 +558,62 @@ pub fn processing_requested(args: &MaskProcessingArgs) -> bool {
 }
 
 /// Check if there's a conflict between soft mask mode and operations that assume hard masks.
-/// Returns true if --no-binary is set but dilation or fill-holes are requested.
+/// Returns true if --no-binary is set but erosion, dilation, or fill-holes are requested.
 pub fn has_soft_conflict(args: &MaskProcessingArgs) -> bool {
-    args.binary == BinaryOption::Disabled && (args.dilate.is_some() || args.fill_holes)
+    args.binary == BinaryOption::Disabled
+        && (args.erode.is_some() || args.dilate.is_some() || args.fill_holes)
+}
+
+/// Fail when `--target-profile` asks for a color space that `outputs` are not written in.
+pub fn reject_target_profile(global: &GlobalOptions, outputs: &str) -> BgrResult<()> {
+    if global.target_profile == TargetProfile::Srgb {
+        return Ok(());
+    }
+    Err(BgrError::invalid_input(format!(
+        "{outputs} are not color managed; drop --target-profile"
+    )))
 }
 
 /// Emit a warning when dilation/fill-holes are requested but thresholding is disabled.
 pub fn warn_if_soft_conflict(args: &MaskProcessingArgs, context: &str) {
     if has_soft_conflict(args) {
-        eprintln!(
-            "Warning: --no-binary disables thresholding, but dilation/fill-holes assume a hard mask; {} may be unexpected.",
-            context
-        );
+        tracing::warn!("{}", tr!("soft-conflict", context = context));
+    }
+}
+
+/// Which mask of a matte a command uses, once an `auto` request is resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskChoice {
+    Raw,
+    Processed,
+}
+
+impl MaskChoice {
+    /// What `auto` means: the processed mask when any processing was asked for.
+    fn auto(processing_requested: bool) -> Self {
+        if processing_requested {
+            MaskChoice::Processed
+        } else {
+            MaskChoice::Raw
+        }
     }
 }
 
 /// Resolve alpha source with Auto behavior.
-pub fn resolve_alpha_source(requested: AlphaFromArg, processing_requested: bool) -> AlphaFromArg {
+pub fn resolve_alpha_source(requested: AlphaFromArg, processing_requested: bool) -> MaskChoice {
     match requested {
-        AlphaFromArg::Auto => {
-            if processing_requested {
-                AlphaFromArg::Processed
-            } else {
-                AlphaFromArg::Raw
-            }
-        }
-        other => other,
+        AlphaFromArg::Raw => MaskChoice::Raw,
+        AlphaFromArg::Processed => MaskChoice::Processed,
+        AlphaFromArg::Auto => MaskChoice::auto(processing_requested),
     }
 }
 
 /// Resolve mask source arg with Auto behavior (trace command).
-pub fn resolve_mask_source_arg(
-    requested: MaskSourceArg,
-    processing_requested: bool,
-) -> MaskSourceArg {
+pub fn resolve_mask_source_arg(requested: MaskSourceArg, processing_requested: bool) -> MaskChoice {
     match requested {
-        MaskSourceArg::Auto => {
-            if processing_requested {
-                MaskSourceArg::Processed
-            } else {
-                MaskSourceArg::Raw
-            }
-        }
-        other => other,
+        MaskSourceArg::Raw => MaskChoice::Raw,
+        MaskSourceArg::Processed => MaskChoice::Processed,
+        MaskSourceArg::Auto => MaskChoice::auto(processing_requested),
     }
 }
 
@@ -159,16 +621,11 @@ pub fn resolve_mask_source_arg(
 pub fn resolve_mask_export_source(
     requested: MaskExportSource,
     processing_requested: bool,
-) -> MaskExportSource {
+) -> MaskChoice {
     match requested {
-        MaskExportSource::Auto => {
-            if processing_requested {
-                MaskExportSource::Processed
-            } else {
-                MaskExportSource::Raw
-            }
-        }
-        other => other,
+        MaskExportSource::Raw => MaskChoice::Raw,
+        MaskExportSource::Processed => MaskChoice::Processed,
+        MaskExportSource::Auto => MaskChoice::auto(processing_requested),
     }
 }
 
@@ -216,51 +673,123 @@ mod tests {
         }
     }
 
+    mod relocate {
+        use super::*;
+
+        #[test]
+        fn without_out_dir_unchanged() {
+            let path = PathBuf::from("/path/to/image-mask.png");
+            assert_eq!(relocate(path.clone(), None), path);
+        }
+
+        #[test]
+        fn with_out_dir_keeps_file_name() {
+            let path = PathBuf::from("/path/to/image-mask.png");
+            let result = relocate(path, Some(Path::new("/out")));
+            assert_eq!(result, PathBuf::from("/out/image-mask.png"));
+        }
+    }
+
+    mod utc_date {
+        use super::*;
+
+        #[test]
+        fn civil_dates() {
+            assert_eq!(utc_date(0), "1970-01-01");
+            assert_eq!(utc_date(951_782_400), "2000-02-29");
+            assert_eq!(utc_date(1_791_936_000), "2026-10-14");
+        }
+    }
+
     mod resolve_export_path {
         use super::*;
 
         #[test]
         fn none_returns_none() {
             let opt: Option<Option<PathBuf>> = None;
-            let input = Path::new("/path/to/image.png");
-            let result = resolve_export_path(&opt, input, "matte");
+            let result = resolve_export_path(&opt, || panic!("nothing to derive")).unwrap();
             assert_eq!(result, None);
         }
 
         #[test]
-        fn some_none_uses_default() {
+        fn some_none_is_derived() {
             let opt: Option<Option<PathBuf>> = Some(None);
-            let input = Path::new("/path/to/image.png");
-            let result = resolve_export_path(&opt, input, "matte");
-            assert_eq!(result, Some(PathBuf::from("/path/to/image-matte.png")));
+            let result = resolve_export_path(&opt, || Ok(PathBuf::from("image-matte.png")));
+            assert_eq!(result.unwrap(), Some(PathBuf::from("image-matte.png")));
         }
 
         #[test]
         fn some_some_uses_custom() {
             let custom_path = PathBuf::from("/custom/output.png");
             let opt: Option<Option<PathBuf>> = Some(Some(custom_path.clone()));
-            let input = Path::new("/path/to/image.png");
-            let result = resolve_export_path(&opt, input, "matte");
+            let result = resolve_export_path(&opt, || panic!("nothing to derive")).unwrap();
             assert_eq!(result, Some(custom_path));
         }
+    }
 
-        #[test]
-        fn different_suffixes() {
-            let opt: Option<Option<PathBuf>> = Some(None);
-            let input = Path::new("photo.jpg");
+    mod output_paths {
+        use super::*;
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        fn global(args: &[&str]) -> GlobalOptions {
+            let argv = ["outline", "cut", "in.png"].iter().chain(args);
+            Cli::try_parse_from(argv).unwrap().global
+        }
+
+        fn primary(global: &GlobalOptions, input: &str) -> PathBuf {
+            let input = Path::new(input);
+            let derived = derive_variant_path(input, "foreground", "png");
+            primary_output_path(&Context::new(), global, input, derived).unwrap()
+        }
+
+        fn side(global: &GlobalOptions, input: &str) -> PathBuf {
+            let input = Path::new(input);
+            let derived = derive_variant_path(input, "matte", "png");
+            side_output_path(&Context::new(), global, input, "matte", derived).unwrap()
+        }
 
+        #[test]
+        fn derived_names_move_into_out_dir() {
+            let global = global(&["--out-dir", "/out"]);
             assert_eq!(
-                resolve_export_path(&opt, input, "foreground"),
-                Some(PathBuf::from("photo-foreground.png"))
+                primary(&global, "/path/to/image.png"),
+                Path::new("/out/image-foreground.png")
             );
             assert_eq!(
-                resolve_export_path(&opt, input, "mask"),
-                Some(PathBuf::from("photo-mask.png"))
+                side(&global, "/path/to/image.png"),
+                Path::new("/out/image-matte.png")
             );
+        }
+
+        #[test]
+        fn templates_land_in_out_dir_or_next_to_the_input() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let input = dir.join("shots").join("shoe.png");
+            let input = input.to_str().unwrap();
+            let template = ["--output-template", "{stem}_nobg.{ext}"];
+            let beside = global(&template);
             assert_eq!(
-                resolve_export_path(&opt, input, "bg-layer"),
-                Some(PathBuf::from("photo-bg-layer.png"))
+                primary(&beside, input),
+                dir.join("shots").join("shoe_nobg.png")
             );
+            assert_eq!(
+                side(&beside, input),
+                dir.join("shots").join("shoe_nobg-matte.png")
+            );
+
+            let out = dir.join("out");
+            let moved = global(&[template[0], template[1], "--out-dir", out.to_str().unwrap()]);
+            assert_eq!(primary(&moved, input), out.join("shoe_nobg.png"));
+        }
+
+        #[test]
+        fn stdin_has_no_size_or_hash() {
+            let global = global(&["--output-template", "{stem}-{hash}.{ext}"]);
+            let input = Path::new("-");
+            let derived = derive_variant_path(input, "foreground", "png");
+            assert!(primary_output_path(&Context::new(), &global, input, derived).is_err());
         }
     }
 
@@ -288,25 +817,25 @@ mod tests {
         #[test]
         fn auto_with_processing() {
             let result = resolve_alpha_source(AlphaFromArg::Auto, true);
-            assert!(matches!(result, AlphaFromArg::Processed));
+            assert_eq!(result, MaskChoice::Processed);
         }
 
         #[test]
         fn auto_without_processing() {
             let result = resolve_alpha_source(AlphaFromArg::Auto, false);
-            assert!(matches!(result, AlphaFromArg::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_raw() {
             let result = resolve_alpha_source(AlphaFromArg::Raw, true);
-            assert!(matches!(result, AlphaFromArg::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_processed() {
             let result = resolve_alpha_source(AlphaFromArg::Processed, false);
-            assert!(matches!(result, AlphaFromArg::Processed));
+            assert_eq!(result, MaskChoice::Processed);
         }
     }
 
@@ -316,25 +845,25 @@ mod tests {
         #[test]
         fn auto_with_processing() {
             let result = resolve_mask_source_arg(MaskSourceArg::Auto, true);
-            assert!(matches!(result, MaskSourceArg::Processed));
+            assert_eq!(result, MaskChoice::Processed);
         }
 
         #[test]
         fn auto_without_processing() {
             let result = resolve_mask_source_arg(MaskSourceArg::Auto, false);
-            assert!(matches!(result, MaskSourceArg::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_raw() {
             let result = resolve_mask_source_arg(MaskSourceArg::Raw, true);
-            assert!(matches!(result, MaskSourceArg::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_processed() {
             let result = resolve_mask_source_arg(MaskSourceArg::Processed, false);
-            assert!(matches!(result, MaskSourceArg::Processed));
+            assert_eq!(result, MaskChoice::Processed);
         }
     }
 
@@ -344,25 +873,67 @@ mod tests {
         #[test]
         fn auto_with_processing() {
             let result = resolve_mask_export_source(MaskExportSource::Auto, true);
-            assert!(matches!(result, MaskExportSource::Processed));
+            assert_eq!(result, MaskChoice::Processed);
         }
 
         #[test]
         fn auto_without_processing() {
             let result = resolve_mask_export_source(MaskExportSource::Auto, false);
-            assert!(matches!(result, MaskExportSource::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_raw() {
             let result = resolve_mask_export_source(MaskExportSource::Raw, true);
-            assert!(matches!(result, MaskExportSource::Raw));
+            assert_eq!(result, MaskChoice::Raw);
         }
 
         #[test]
         fn explicit_processed() {
             let result = resolve_mask_export_source(MaskExportSource::Processed, false);
-            assert!(matches!(result, MaskExportSource::Processed));
+            assert_eq!(result, MaskChoice::Processed);
+        }
+    }
+
+    mod adapter {
+        use super::*;
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        #[test]
+        fn side_outputs_fuse_only_for_the_u2net_family() {
+            let cli =
+                Cli::try_parse_from(["outline", "cut", "in.png", "--fuse-side-outputs"]).unwrap();
+            let ctx = Context::new();
+            assert!(adapter(&ctx, &cli.global, "u2netp").is_ok());
+            assert!(adapter(&ctx, &cli.global, "isnet").is_ok());
+            let Err(err) = adapter(&ctx, &cli.global, "birefnet") else {
+                panic!("BiRefNet has no side outputs to fuse");
+            };
+            assert!(err.to_string().contains("--fuse-side-outputs"));
+            assert!(adapter(&ctx, &cli.global, "custom.onnx").is_err());
+        }
+    }
+
+    mod reject_target_profile {
+        use super::*;
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        #[test]
+        fn only_srgb_passes() {
+            let cli = Cli::try_parse_from(["outline", "trace", "in.png"]).unwrap();
+            assert!(reject_target_profile(&cli.global, "traced outlines").is_ok());
+            let cli = Cli::try_parse_from([
+                "outline",
+                "trace",
+                "in.png",
+                "--target-profile",
+                "display-p3",
+            ])
+            .unwrap();
+            let err = reject_target_profile(&cli.global, "traced outlines").unwrap_err();
+            assert!(err.to_string().contains("--target-profile"));
         }
     }
 
@@ -378,8 +949,12 @@ mod tests {
                 blur: None,
                 mask_threshold: 120,
                 binary,
+                erode: None,
+                keep_largest: false,
+                min_area: None,
                 dilate,
                 fill_holes,
+                feather: None,
             }
         }
 
@@ -0,0 +1,76 @@
+use bgr::verify::{Fingerprint, GoldenSet, Tolerance};
+use bgr::{BgrError, BgrResult, Context};
+
+use crate::cli::VerifyCommand;
+use crate::i18n::tr;
+
+use super::batch::{InputKind, collect_inputs};
+use super::utils::report;
+
+/// The main function to run the verify command.
+pub fn run(ctx: &Context, cmd: VerifyCommand) -> BgrResult<()> {
+    let outputs = collect_inputs(std::slice::from_ref(&cmd.dir), InputKind::Image)?;
+
+    let mut current = GoldenSet::default();
+    for path in &outputs {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        current.images.insert(name, Fingerprint::of_path(path)?);
+    }
+
+    if cmd.update {
+        current.save(&cmd.golden)?;
+        report(
+            ctx,
+            tr!(
+                "verify-recorded",
+                count = current.images.len(),
+                path = cmd.golden.display().to_string()
+            ),
+        );
+        return Ok(());
+    }
+
+    let golden = GoldenSet::load(&cmd.golden)?;
+    let tolerance = Tolerance {
+        max_hash_distance: cmd.max_hash_distance,
+        max_coverage_delta: cmd.coverage_tolerance,
+    };
+
+    let mut failed = 0;
+    for (name, expected) in &golden.images {
+        let Some(actual) = current.images.get(name) else {
+            report(ctx, tr!("status-missing", name = name.as_str()));
+            failed += 1;
+            continue;
+        };
+        let mismatches = expected.compare(actual, &tolerance);
+        if mismatches.is_empty() {
+            report(ctx, tr!("status-ok", name = name.as_str()));
+        } else {
+            failed += 1;
+            for mismatch in mismatches {
+                let reason = mismatch.to_string();
+                report(
+                    ctx,
+                    tr!("status-changed", name = name.as_str(), reason = reason),
+                );
+            }
+        }
+    }
+    for name in current.images.keys() {
+        if !golden.images.contains_key(name) {
+            tracing::warn!("{}", tr!("verify-missing-golden", name = name.as_str()));
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(BgrError::Batch {
+            failed,
+            total: golden.images.len(),
+        }),
+    }
+}
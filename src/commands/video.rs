@@ -0,0 +1,841 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use bgr::{
+    Background, BgrError, BgrResult, BgrSession, Context, TemporalOptions, TemporalSmoother,
+    Verbosity,
+};
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{self, Flags};
+use ffmpeg::util::frame::video::Video as Frame;
+use ffmpeg::{Dictionary, Packet, Rational, codec, encoder, media};
+use ffmpeg_next as ffmpeg;
+use image::{RgbImage, RgbaImage};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{RefineArg, VideoCodecArg, VideoCommand};
+use crate::i18n::tr;
+
+use super::utils::{
+    MaskChoice, build_bgr, processing_requested, refine_matte, report, resolve_alpha_source,
+    warn_if_soft_conflict,
+};
+
+/// Frames encoded into each part of the output before the job's progress is saved.
+const CHECKPOINT_FRAMES: u64 = 240;
+/// The journal of finished parts inside the work directory.
+const JOURNAL_FILE: &str = "job.jsonl";
+
+/// The main function to run the video command.
+pub fn run(ctx: &Context, cmd: VideoCommand) -> BgrResult<()> {
+    let codec = cmd.codec.unwrap_or_else(|| default_codec(&cmd.output));
+    if codec == VideoCodecArg::H264 && cmd.bg_color.is_none() {
+        return Err(BgrError::Video(
+            "h264 has no alpha channel; pass --bg-color or use --codec vp9 or prores".into(),
+        ));
+    }
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let alpha_source =
+        resolve_alpha_source(cmd.alpha_source, processing_requested(&cmd.mask_processing));
+    if matches!(alpha_source, MaskChoice::Processed) {
+        warn_if_soft_conflict(&cmd.mask_processing, "frames");
+    }
+    let mut plan = FramePlan {
+        alpha_source,
+        background: cmd.bg_color.map(Background::Color),
+        refine: cmd.refine,
+        smoother: cmd.temporal_smooth.map(|strength| {
+            TemporalSmoother::new(TemporalOptions {
+                strength,
+                ..TemporalOptions::default()
+            })
+        }),
+    };
+
+    ffmpeg::init().map_err(video_error)?;
+    // One session serves every frame.
+    let mut session = bgr.session()?;
+    let mut input = VideoInput::open(&cmd.input)?;
+    let encoding = Encoding {
+        codec,
+        alpha: plan.background.is_none(),
+        shape: input.shape(),
+    };
+    let progress = if ctx.verbosity() > Verbosity::Quiet {
+        progress_bar(input.frame_count)
+    } else {
+        ProgressBar::hidden()
+    };
+    let mut frames = Frames {
+        session: &mut session,
+        input: &mut input,
+        plan: &mut plan,
+        progress: &progress,
+    };
+    let done = if cmd.resume {
+        let fingerprint = JobFingerprint {
+            input: cmd.input.clone(),
+            settings: job_settings(ctx, &cmd, &encoding, alpha_source),
+        };
+        let job = Job::open(&cmd.output, fingerprint)?;
+        frames.encode_in_parts(&encoding, job, &cmd.output)?
+    } else {
+        match fs::remove_dir_all(work_dir(&cmd.output)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => frames.encode(&encoding, &cmd.output)?,
+        }
+    };
+    progress.finish_and_clear();
+    report(
+        ctx,
+        tr!(
+            "saved-video",
+            path = cmd.output.display().to_string(),
+            frames = done
+        ),
+    );
+    Ok(())
+}
+
+/// Every option that changes the encoded frames, as text, so `--resume` only continues a
+/// job whose finished parts the current run would have encoded the same way.
+fn job_settings(
+    ctx: &Context,
+    cmd: &VideoCommand,
+    encoding: &Encoding,
+    alpha_source: MaskChoice,
+) -> String {
+    let inference = ctx.inference_settings();
+    format!(
+        "model={} filters={:?}/{:?} codec={:?} alpha={} alpha-source={alpha_source:?} \
+         bg-color={:?} refine={:?} temporal-smooth={:?} {:?}",
+        inference.model_path.display(),
+        inference.input_resize_filter,
+        inference.output_resize_filter,
+        encoding.codec,
+        encoding.alpha,
+        cmd.bg_color,
+        cmd.refine,
+        cmd.temporal_smooth,
+        cmd.mask_processing,
+    )
+}
+
+/// Pick the codec implied by the output file's extension.
+fn default_codec(output: &Path) -> VideoCodecArg {
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("webm") => VideoCodecArg::Vp9,
+        Some("mov") => VideoCodecArg::Prores,
+        _ => VideoCodecArg::H264,
+    }
+}
+
+fn video_error(err: ffmpeg::Error) -> BgrError {
+    BgrError::Video(err.to_string())
+}
+
+/// The job a work directory holds: the first line of its journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct JobFingerprint {
+    input: PathBuf,
+    /// See [`job_settings`].
+    settings: String,
+}
+
+/// A finished part: one line of the journal after the fingerprint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PartEntry {
+    /// File name of the part.
+    part: String,
+    /// Frames encoded into this part and the ones before it.
+    frames: u64,
+    /// File holding the `--temporal-smooth` running average after the part.
+    temporal: Option<String>,
+}
+
+/// Under `--resume`, the output is encoded in parts inside a work directory next to it,
+/// `<output>.parts/`, and joined once every frame is done, so an interrupted job loses at
+/// most one part. Finished parts are appended to a journal there, like the batch and watch
+/// journals, so a crash mid-write only loses the last line.
+struct Job {
+    dir: PathBuf,
+    /// Extension of the output, which picks the container of every part.
+    extension: String,
+    journal: File,
+    input: PathBuf,
+    parts: Vec<PartEntry>,
+}
+
+impl Job {
+    /// Continue the job saved for `output`, or start one when there is none.
+    fn open(output: &Path, fingerprint: JobFingerprint) -> BgrResult<Self> {
+        let dir = work_dir(output);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(JOURNAL_FILE);
+        let (saved, parts, valid) = match fs::read_to_string(&path) {
+            Ok(text) => read_journal(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (None, Vec::new(), 0),
+            Err(err) => return Err(err.into()),
+        };
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        match saved {
+            Some(saved) if saved != fingerprint => {
+                return Err(BgrError::Video(format!(
+                    "{} holds a job for other input or settings; \
+                     run without --resume to start over",
+                    dir.display()
+                )));
+            }
+            // Drop a line cut short by a crash, so the next one starts on its own line.
+            Some(_) => journal.set_len(valid as u64)?,
+            None => {
+                journal.set_len(0)?;
+                append_line(&mut journal, &fingerprint)?;
+            }
+        }
+        journal.seek(SeekFrom::End(0))?;
+        let extension = output
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self {
+            dir,
+            extension,
+            journal,
+            input: fingerprint.input,
+            parts,
+        })
+    }
+
+    /// Frames encoded into the finished parts.
+    fn frames(&self) -> u64 {
+        self.parts.last().map_or(0, |part| part.frames)
+    }
+
+    /// The saved running average for `--temporal-smooth`, empty when there is none.
+    fn temporal_state(&self) -> BgrResult<Vec<u8>> {
+        match self.parts.last().and_then(|part| part.temporal.as_ref()) {
+            Some(name) => Ok(fs::read(self.dir.join(name))?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Where the part after the finished ones is encoded.
+    fn next_part(&self) -> PathBuf {
+        self.dir.join(part_name(self.parts.len(), &self.extension))
+    }
+
+    /// Record the part from [`next_part`](Self::next_part) as finished, with `frames` done in
+    /// total and the smoother's state after them.
+    fn finish_part(&mut self, frames: u64, smoother: Option<&TemporalSmoother>) -> BgrResult<()> {
+        let index = self.parts.len();
+        // A state file no journal line names yet is ignored, so it needs no atomic write.
+        let temporal = match smoother {
+            Some(smoother) => {
+                let name = part_name(index, "temporal");
+                fs::write(self.dir.join(&name), smoother.state())?;
+                Some(name)
+            }
+            None => None,
+        };
+        let entry = PartEntry {
+            part: part_name(index, &self.extension),
+            frames,
+            temporal,
+        };
+        append_line(&mut self.journal, &entry)?;
+        let earlier = self.parts.last().and_then(|part| part.temporal.as_ref());
+        if let Some(earlier) = earlier {
+            let _ = fs::remove_file(self.dir.join(earlier));
+        }
+        self.parts.push(entry);
+        Ok(())
+    }
+
+    /// Join the finished parts into `output` without re-encoding, then remove the work
+    /// directory.
+    fn join(self, output: &Path) -> BgrResult<()> {
+        let parts: Vec<PathBuf> = self
+            .parts
+            .iter()
+            .map(|part| self.dir.join(&part.part))
+            .collect();
+        let Some(first) = parts.first() else {
+            return Err(BgrError::Video(format!(
+                "{} has no frames",
+                self.input.display()
+            )));
+        };
+        let mut context = ffmpeg::format::output(output).map_err(video_error)?;
+        {
+            let source = ffmpeg::format::input(first).map_err(video_error)?;
+            let parameters = source
+                .stream(0)
+                .ok_or_else(|| BgrError::Video(format!("{} has no stream", first.display())))?
+                .parameters();
+            let mut stream = context
+                .add_stream(encoder::find(codec::Id::None))
+                .map_err(video_error)?;
+            stream.set_parameters(parameters);
+            // The part's tag belongs to its muxer; let the output's muxer pick its own.
+            // SAFETY: `parameters()` wraps the stream's own live `codecpar`, which stays valid
+            // while `context` exists, and nothing reads it before `write_header`.
+            unsafe {
+                (*stream.parameters().as_mut_ptr()).codec_tag = 0;
+            }
+        }
+        context.write_header().map_err(video_error)?;
+        let time_base = context.stream(0).expect("one stream").time_base();
+        for part in &parts {
+            let mut input = ffmpeg::format::input(part).map_err(video_error)?;
+            for (stream, mut packet) in input.packets() {
+                packet.rescale_ts(stream.time_base(), time_base);
+                packet.set_position(-1);
+                packet.set_stream(0);
+                packet
+                    .write_interleaved(&mut context)
+                    .map_err(video_error)?;
+            }
+        }
+        context.write_trailer().map_err(video_error)?;
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
+
+/// The fingerprint and finished parts in a journal, and the length of the text up to the last
+/// complete line. A journal without a readable fingerprint holds no job.
+fn read_journal(text: &str) -> (Option<JobFingerprint>, Vec<PartEntry>, usize) {
+    let mut lines = text
+        .split_inclusive('\n')
+        .filter(|line| line.ends_with('\n'));
+    let Some(first) = lines.next() else {
+        return (None, Vec::new(), 0);
+    };
+    let Ok(fingerprint) = serde_json::from_str(first) else {
+        return (None, Vec::new(), 0);
+    };
+    let mut valid = first.len();
+    let mut parts = Vec::new();
+    for line in lines {
+        let Ok(part) = serde_json::from_str(line) else {
+            break;
+        };
+        parts.push(part);
+        valid += line.len();
+    }
+    (Some(fingerprint), parts, valid)
+}
+
+fn part_name(index: usize, extension: &str) -> String {
+    format!("part-{index:05}.{extension}")
+}
+
+fn append_line(journal: &mut File, entry: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_vec(entry).map_err(io::Error::from)?;
+    line.push(b'\n');
+    journal.write_all(&line)?;
+    journal.sync_data()
+}
+
+/// The work directory of the job writing `output`.
+fn work_dir(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".parts");
+    output.with_file_name(name)
+}
+
+/// How every frame is cut out, fixed for the whole video.
+struct FramePlan {
+    alpha_source: MaskChoice,
+    background: Option<Background>,
+    refine: Option<RefineArg>,
+    /// Running average of earlier mattes under `--temporal-smooth`.
+    smoother: Option<TemporalSmoother>,
+}
+
+/// One processed frame, with or without alpha.
+enum OutputFrame {
+    Rgba(RgbaImage),
+    Rgb(RgbImage),
+}
+
+impl FramePlan {
+    fn apply(&mut self, session: &mut BgrSession, rgb: RgbImage) -> BgrResult<OutputFrame> {
+        let inferred = session.for_rgb(rgb)?;
+        let mut matte = inferred.matte();
+        // Smooth the model's output so refinement and processing see a steady matte.
+        if let Some(smoother) = &mut self.smoother {
+            matte = matte.smooth_temporal(smoother);
+        }
+        let matte = refine_matte(matte, self.refine)?;
+        let foreground = match self.alpha_source {
+            MaskChoice::Raw => matte.foreground()?,
+            MaskChoice::Processed => matte.processed()?.foreground()?,
+        };
+        Ok(match &self.background {
+            Some(background) => OutputFrame::Rgb(foreground.composite(background)),
+            None => OutputFrame::Rgba(foreground.into_image()),
+        })
+    }
+}
+
+/// The frames of one video on their way through the model into an encoder.
+struct Frames<'a> {
+    session: &'a mut BgrSession,
+    input: &'a mut VideoInput,
+    plan: &'a mut FramePlan,
+    progress: &'a ProgressBar,
+}
+
+impl Frames<'_> {
+    /// Encode every frame straight into `output`, returning how many there were.
+    fn encode(&mut self, encoding: &Encoding, output: &Path) -> BgrResult<u64> {
+        let Self {
+            session,
+            input,
+            plan,
+            progress,
+        } = self;
+        let mut video = VideoOutput::create(output, encoding, false)?;
+        let mut done = 0;
+        let outcome = input.for_each_frame(|rgb, pts| {
+            video.write(&plan.apply(session, rgb)?, pts)?;
+            done += 1;
+            progress.inc(1);
+            Ok(())
+        });
+        // A cancelled run still leaves a playable video of the frames before it.
+        let finished = video.finish();
+        outcome.inspect_err(|_| progress.abandon())?;
+        finished?;
+        Ok(done)
+    }
+
+    /// Encode the frames `job` has not finished into its parts, then join them into
+    /// `output`, returning the total number of frames.
+    fn encode_in_parts(
+        &mut self,
+        encoding: &Encoding,
+        mut job: Job,
+        output: &Path,
+    ) -> BgrResult<u64> {
+        let Self {
+            session,
+            input,
+            plan,
+            progress,
+        } = self;
+        if let Some(smoother) = &mut plan.smoother {
+            smoother.restore(&job.temporal_state()?)?;
+        }
+        let skip = job.frames();
+        progress.set_position(skip);
+        let mut decoded = 0;
+        let mut done = skip;
+        let mut part: Option<VideoOutput> = None;
+        let mut part_frames = 0;
+        let outcome = input.for_each_frame(|rgb, pts| {
+            // Frames in finished parts are decoded again, but only to count them.
+            decoded += 1;
+            if decoded <= skip {
+                return Ok(());
+            }
+            let frame = plan.apply(session, rgb)?;
+            if part.is_none() {
+                part = Some(VideoOutput::create(&job.next_part(), encoding, true)?);
+            }
+            part.as_mut().expect("part is open").write(&frame, pts)?;
+            done += 1;
+            part_frames += 1;
+            progress.inc(1);
+            if part_frames == CHECKPOINT_FRAMES {
+                part.take().expect("part is open").finish()?;
+                job.finish_part(done, plan.smoother.as_ref())?;
+                part_frames = 0;
+            }
+            Ok(())
+        });
+
+        // Frames encoded before a Ctrl-C are kept, so the next run continues at the next one.
+        let stopped = match outcome {
+            Ok(()) => None,
+            Err(err) if err.is_cancelled() => Some(err),
+            Err(err) => return Err(err),
+        };
+        if let Some(video) = part.take() {
+            video.finish()?;
+            job.finish_part(done, plan.smoother.as_ref())?;
+        }
+        if let Some(err) = stopped {
+            progress.abandon();
+            tracing::warn!("{}", tr!("video-resumable", frames = done));
+            return Err(err);
+        }
+        job.join(output)?;
+        Ok(done)
+    }
+}
+
+/// What every frame is encoded as.
+#[derive(Clone, Copy)]
+struct Encoding {
+    codec: VideoCodecArg,
+    /// Keep the alpha channel, rather than encode frames composited over `--bg-color`.
+    alpha: bool,
+    shape: StreamShape,
+}
+
+/// Size and timing of the input stream, which every part of the output shares.
+#[derive(Clone, Copy)]
+struct StreamShape {
+    width: u32,
+    height: u32,
+    time_base: Rational,
+    frame_rate: Rational,
+}
+
+/// The best video stream of an input file and its decoder.
+struct VideoInput {
+    context: ffmpeg::format::context::Input,
+    stream_index: usize,
+    decoder: ffmpeg::decoder::Video,
+    to_rgb: scaling::Context,
+    time_base: Rational,
+    frame_rate: Rational,
+    /// Frames the container claims to hold; 0 when unknown.
+    frame_count: u64,
+}
+
+impl VideoInput {
+    fn open(path: &Path) -> BgrResult<Self> {
+        let context = ffmpeg::format::input(path).map_err(video_error)?;
+        let stream = context
+            .streams()
+            .best(media::Type::Video)
+            .ok_or_else(|| BgrError::Video(format!("{} has no video stream", path.display())))?;
+        let stream_index = stream.index();
+        let time_base = stream.time_base();
+        let frame_rate = stream.avg_frame_rate();
+        let frame_count = u64::try_from(stream.frames()).unwrap_or(0);
+        let decoder = codec::context::Context::from_parameters(stream.parameters())
+            .and_then(|context| context.decoder().video())
+            .map_err(video_error)?;
+        let to_rgb = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGB24,
+            decoder.width(),
+            decoder.height(),
+            Flags::BILINEAR,
+        )
+        .map_err(video_error)?;
+        Ok(Self {
+            context,
+            stream_index,
+            decoder,
+            to_rgb,
+            time_base,
+            frame_rate,
+            frame_count,
+        })
+    }
+
+    fn shape(&self) -> StreamShape {
+        StreamShape {
+            width: self.decoder.width(),
+            height: self.decoder.height(),
+            time_base: self.time_base,
+            frame_rate: self.frame_rate,
+        }
+    }
+
+    /// Decode every frame in presentation order and hand it to `process` with its timestamp.
+    fn for_each_frame(
+        &mut self,
+        mut process: impl FnMut(RgbImage, Option<i64>) -> BgrResult<()>,
+    ) -> BgrResult<()> {
+        let Self {
+            context,
+            stream_index,
+            decoder,
+            to_rgb,
+            ..
+        } = self;
+        for (stream, packet) in context.packets() {
+            if stream.index() == *stream_index {
+                decoder.send_packet(&packet).map_err(video_error)?;
+                drain(decoder, to_rgb, &mut process)?;
+            }
+        }
+        decoder.send_eof().map_err(video_error)?;
+        drain(decoder, to_rgb, &mut process)
+    }
+}
+
+/// Hand every frame the decoder has ready to `process`.
+fn drain(
+    decoder: &mut ffmpeg::decoder::Video,
+    to_rgb: &mut scaling::Context,
+    process: &mut impl FnMut(RgbImage, Option<i64>) -> BgrResult<()>,
+) -> BgrResult<()> {
+    let mut decoded = Frame::empty();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        let mut rgb = Frame::empty();
+        to_rgb.run(&decoded, &mut rgb).map_err(video_error)?;
+        let (width, height) = (rgb.width(), rgb.height());
+        let pixels = unpack_rows(rgb.data(0), rgb.stride(0), width as usize * 3, height);
+        let image = RgbImage::from_raw(width, height, pixels).expect("buffer matches frame");
+        process(image, decoded.timestamp())?;
+    }
+    Ok(())
+}
+
+/// An encoder writing processed frames into the output container.
+struct VideoOutput {
+    context: ffmpeg::format::context::Output,
+    encoder: encoder::Video,
+    from_rgb: scaling::Context,
+    source_format: Pixel,
+    encoder_time_base: Rational,
+    width: u32,
+    height: u32,
+}
+
+impl VideoOutput {
+    /// Open `path` for writing frames as `encoding` says. A `joinable` output is one part of
+    /// a longer video.
+    fn create(path: &Path, encoding: &Encoding, joinable: bool) -> BgrResult<Self> {
+        let Encoding {
+            codec,
+            alpha,
+            shape,
+        } = *encoding;
+        let (name, alpha_format, opaque_format) = match codec {
+            VideoCodecArg::Vp9 => ("libvpx-vp9", Some(Pixel::YUVA420P), Pixel::YUV420P),
+            VideoCodecArg::Prores => ("prores_ks", Some(Pixel::YUVA444P10LE), Pixel::YUV444P10LE),
+            VideoCodecArg::H264 => ("libx264", None, Pixel::YUV420P),
+        };
+        let (encoded_format, source_format) = match alpha_format.filter(|_| alpha) {
+            Some(format) => (format, Pixel::RGBA),
+            None => (opaque_format, Pixel::RGB24),
+        };
+        let found = encoder::find_by_name(name)
+            .ok_or_else(|| BgrError::Video(format!("this ffmpeg build has no {name} encoder")))?;
+        let mut context = ffmpeg::format::output(path).map_err(video_error)?;
+        let global_header = context
+            .format()
+            .flags()
+            .contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+
+        let (width, height) = (shape.width, shape.height);
+        let mut setup = codec::context::Context::new_with_codec(found)
+            .encoder()
+            .video()
+            .map_err(video_error)?;
+        setup.set_width(width);
+        setup.set_height(height);
+        setup.set_format(encoded_format);
+        setup.set_time_base(shape.time_base);
+        setup.set_frame_rate(Some(shape.frame_rate));
+        if global_header {
+            setup.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let mut options = Dictionary::new();
+        match codec {
+            // 4444 is the lowest ProRes profile that carries alpha.
+            VideoCodecArg::Prores => options.set("profile", "4444"),
+            // Constant quality; libvpx otherwise targets a low default bitrate.
+            VideoCodecArg::Vp9 => {
+                options.set("crf", "31");
+                options.set("b", "0");
+            }
+            VideoCodecArg::H264 => {
+                options.set("crf", "20");
+                // Parts are joined by timestamp, so decode order must match presentation
+                // order.
+                if joinable {
+                    options.set("bf", "0");
+                }
+            }
+        }
+        let encoder = setup.open_with(options).map_err(video_error)?;
+
+        let mut stream = context.add_stream(found).map_err(video_error)?;
+        stream.set_parameters(&encoder);
+        stream.set_time_base(shape.time_base);
+        context.write_header().map_err(video_error)?;
+
+        let from_rgb = scaling::Context::get(
+            source_format,
+            width,
+            height,
+            encoded_format,
+            width,
+            height,
+            Flags::BILINEAR,
+        )
+        .map_err(video_error)?;
+        Ok(Self {
+            context,
+            encoder,
+            from_rgb,
+            source_format,
+            encoder_time_base: shape.time_base,
+            width,
+            height,
+        })
+    }
+
+    fn write(&mut self, frame: &OutputFrame, pts: Option<i64>) -> BgrResult<()> {
+        let (pixels, channels) = match frame {
+            OutputFrame::Rgba(image) => (image.as_raw(), 4),
+            OutputFrame::Rgb(image) => (image.as_raw(), 3),
+        };
+        let mut source = Frame::new(self.source_format, self.width, self.height);
+        let stride = source.stride(0);
+        pack_rows(
+            source.data_mut(0),
+            stride,
+            pixels,
+            self.width as usize * channels,
+        );
+        let mut encoded = Frame::empty();
+        self.from_rgb
+            .run(&source, &mut encoded)
+            .map_err(video_error)?;
+        encoded.set_pts(pts);
+        self.encoder.send_frame(&encoded).map_err(video_error)?;
+        self.write_packets()
+    }
+
+    fn finish(mut self) -> BgrResult<()> {
+        self.encoder.send_eof().map_err(video_error)?;
+        self.write_packets()?;
+        self.context.write_trailer().map_err(video_error)
+    }
+
+    fn write_packets(&mut self) -> BgrResult<()> {
+        // The muxer may have picked its own stream time base in write_header.
+        let stream_time_base = self.context.stream(0).expect("one stream").time_base();
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.encoder_time_base, stream_time_base);
+            packet
+                .write_interleaved(&mut self.context)
+                .map_err(video_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn progress_bar(frames: u64) -> ProgressBar {
+    if frames == 0 {
+        return ProgressBar::new_spinner();
+    }
+    let progress = ProgressBar::new(frames);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} frames ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    progress
+}
+
+/// Copy `height` rows of `row` bytes out of a frame plane padded to `stride` bytes per row.
+fn unpack_rows(plane: &[u8], stride: usize, row: usize, height: u32) -> Vec<u8> {
+    plane
+        .chunks(stride)
+        .take(height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect()
+}
+
+/// Copy tightly packed rows of `row` bytes into a frame plane padded to `stride` bytes per row.
+fn pack_rows(plane: &mut [u8], stride: usize, pixels: &[u8], row: usize) {
+    for (line, pixels) in plane.chunks_mut(stride).zip(pixels.chunks(row)) {
+        line[..row].copy_from_slice(pixels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod default_codec {
+        use super::*;
+
+        #[test]
+        fn follows_the_extension() {
+            assert_eq!(default_codec(Path::new("out.webm")), VideoCodecArg::Vp9);
+            assert_eq!(default_codec(Path::new("out.MOV")), VideoCodecArg::Prores);
+            assert_eq!(default_codec(Path::new("out.mp4")), VideoCodecArg::H264);
+            assert_eq!(default_codec(Path::new("out")), VideoCodecArg::H264);
+        }
+    }
+
+    mod job {
+        use super::*;
+
+        #[test]
+        fn work_dir_sits_next_to_the_output() {
+            assert_eq!(
+                work_dir(Path::new("out/clip.webm")),
+                Path::new("out/clip.webm.parts")
+            );
+        }
+
+        #[test]
+        fn journal_keeps_complete_lines_only() {
+            let fingerprint = r#"{"input":"in.mp4","settings":"codec=Vp9"}"#;
+            let part = r#"{"part":"part-00000.webm","frames":240,"temporal":null}"#;
+            let text = format!("{fingerprint}\n{part}\n{{\"part\":\"part-0");
+            let (saved, parts, valid) = read_journal(&text);
+            assert_eq!(
+                saved,
+                Some(JobFingerprint {
+                    input: PathBuf::from("in.mp4"),
+                    settings: "codec=Vp9".into(),
+                })
+            );
+            assert_eq!(parts.len(), 1);
+            assert_eq!(parts[0].frames, 240);
+            assert_eq!(valid, fingerprint.len() + part.len() + 2);
+        }
+
+        #[test]
+        fn journal_without_a_fingerprint_holds_no_job() {
+            assert_eq!(read_journal(""), (None, Vec::new(), 0));
+            assert_eq!(read_journal("{\"input\""), (None, Vec::new(), 0));
+        }
+    }
+
+    mod rows {
+        use super::*;
+
+        #[test]
+        fn padding_is_dropped_and_restored() {
+            // Two rows of two RGB pixels, each padded to 8 bytes.
+            let plane = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0];
+            let pixels = unpack_rows(&plane, 8, 6, 2);
+            assert_eq!(pixels, (1..=12).collect::<Vec<u8>>());
+
+            let mut restored = [0u8; 16];
+            pack_rows(&mut restored, 8, &pixels, 6);
+            assert_eq!(restored, plane);
+        }
+    }
+}
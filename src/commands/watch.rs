@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use bgr::{Background, BgrError, BgrResult, ColorSettings, Context, Warning, save_for_profile};
+use image::DynamicImage;
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{GlobalOptions, RefineArg, WatchCommand};
+use crate::i18n::tr;
+
+use super::batch::{MatteSource, failed_to_process, is_supported_image, print_warnings};
+use super::utils::{
+    MaskChoice, build_bgr, processing_requested, refine_matte, render_template, report,
+    resolve_alpha_source, warn_if_soft_conflict,
+};
+
+/// Journal file name inside the output directory.
+const JOURNAL_NAME: &str = ".bgr-journal.jsonl";
+
+/// How each new image is cut out, fixed for the whole run.
+struct WatchPlan {
+    alpha_source: MaskChoice,
+    background: Option<Background>,
+    refine: Option<RefineArg>,
+    color: ColorSettings,
+}
+
+/// The main function to run the watch command.
+pub fn run(ctx: &Context, global: &GlobalOptions, cmd: WatchCommand) -> BgrResult<()> {
+    let input_root = fs::canonicalize(&cmd.dir)?;
+    fs::create_dir_all(&cmd.output)?;
+    let output_root = fs::canonicalize(&cmd.output)?;
+    if output_root.starts_with(&input_root) {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--output must not be inside the watched directory",
+        )));
+    }
+
+    let bgr = build_bgr(ctx, &cmd.mask_processing);
+    let alpha_source =
+        resolve_alpha_source(cmd.alpha_source, processing_requested(&cmd.mask_processing));
+    if matches!(alpha_source, MaskChoice::Processed) {
+        warn_if_soft_conflict(&cmd.mask_processing, "output");
+    }
+    let plan = WatchPlan {
+        alpha_source,
+        background: cmd.bg_color.map(Background::Color),
+        refine: cmd.refine,
+        color: global.color_settings()?,
+    };
+    let mut source = MatteSource::open(&bgr, global)?;
+    let journal_path = cmd
+        .journal
+        .clone()
+        .unwrap_or_else(|| output_root.join(JOURNAL_NAME));
+    let mut journal = Journal::open(&journal_path)?;
+
+    // Watch before scanning so nothing written during the scan is missed.
+    let (events, received) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).map_err(io::Error::other)?;
+    watcher
+        .watch(&input_root, RecursiveMode::Recursive)
+        .map_err(io::Error::other)?;
+    report(
+        ctx,
+        tr!(
+            "watch-started",
+            input = input_root.display().to_string(),
+            output = output_root.display().to_string()
+        ),
+    );
+
+    let debounce = Duration::from_millis(cmd.debounce);
+    // Files waiting to settle, with the time of their last change. Existing files are
+    // already settled; the journal skips the ones processed by an earlier run.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let settled = Instant::now()
+        .checked_sub(debounce)
+        .unwrap_or_else(Instant::now);
+    for path in scan(&input_root)? {
+        pending.insert(path, settled);
+    }
+
+    loop {
+        let event = match pending.values().min() {
+            Some(&changed) => received
+                .recv_timeout((changed + debounce).saturating_duration_since(Instant::now())),
+            None => received.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(Ok(event)) if is_change(&event.kind) => {
+                let now = Instant::now();
+                for path in event.paths.into_iter().filter(|path| is_candidate(path)) {
+                    pending.insert(path, now);
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(err)) => tracing::warn!("{}", tr!("watch-error", error = err.to_string())),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let now = Instant::now();
+        let mut ready: Vec<PathBuf> = pending
+            .iter()
+            .filter(|&(_, &changed)| now.duration_since(changed) >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for input in ready {
+            pending.remove(&input);
+            let Ok(relative) = input.strip_prefix(&input_root) else {
+                continue;
+            };
+            // Deleted or renamed away while it was settling.
+            let Ok(stamp) = Stamp::of(&input) else {
+                continue;
+            };
+            if journal.is_done(relative, stamp) {
+                continue;
+            }
+            let output = output_path(ctx, global, &output_root, &input, relative);
+            match output.and_then(|output| cut_one(&mut source, ctx, &plan, &input, &output)) {
+                Ok(warnings) => {
+                    print_warnings(&input, &warnings);
+                    journal.record(relative, stamp)?;
+                }
+                Err(err) => eprintln!("{}", failed_to_process(&input, &err)),
+            }
+        }
+    }
+}
+
+/// Every supported image under `dir`, recursively.
+fn scan(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(scan(&path)?);
+        } else if is_candidate(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether a watcher event may have left a file with new contents.
+fn is_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// Whether `path` is an image worth processing; temporary and hidden files are skipped.
+fn is_candidate(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && path.is_file() && is_supported_image(path)
+}
+
+/// Where the cutout of the input at `relative` goes in the output tree.
+fn mirrored_path(output_root: &Path, relative: &Path) -> PathBuf {
+    output_root.join(relative).with_extension("png")
+}
+
+/// Where the cutout of `input` goes: its mirrored path, renamed by
+/// `--output-template` when one is given.
+fn output_path(
+    ctx: &Context,
+    global: &GlobalOptions,
+    output_root: &Path,
+    input: &Path,
+    relative: &Path,
+) -> BgrResult<PathBuf> {
+    let mirrored = mirrored_path(output_root, relative);
+    let Some(template) = &global.output_template else {
+        return Ok(mirrored);
+    };
+    let rendered = render_template(ctx, global, template, input, &mirrored)?;
+    let dir = mirrored.parent().unwrap_or(output_root);
+    Ok(dir.join(rendered))
+}
+
+/// Cut a single input and write the foreground PNG to `output`.
+fn cut_one(
+    source: &mut MatteSource,
+    ctx: &Context,
+    plan: &WatchPlan,
+    input: &Path,
+    output: &Path,
+) -> BgrResult<Vec<Warning>> {
+    let inferred = source.for_image(input)?;
+    let matte = refine_matte(inferred.matte(), plan.refine)?;
+    let foreground = match plan.alpha_source {
+        MaskChoice::Raw => matte.foreground()?,
+        MaskChoice::Processed => matte.processed()?.foreground()?,
+    };
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match &plan.background {
+        Some(background) => save_for_profile(
+            DynamicImage::ImageRgb8(foreground.composite(background)),
+            output,
+            &plan.color,
+        )?,
+        None => foreground.save_in(output, &plan.color)?,
+    }
+    report(
+        ctx,
+        tr!("saved-foreground", path = output.display().to_string()),
+    );
+    Ok(inferred.warnings().to_vec())
+}
+
+/// Identifies one version of an input file; a changed file is processed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    modified_ms: u64,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified_ms = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Ok(Self {
+            modified_ms,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// One line of the journal.
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    input: PathBuf,
+    #[serde(flatten)]
+    stamp: Stamp,
+}
+
+/// Append-only record of processed inputs, so a restarted watcher skips finished work.
+///
+/// Inputs are keyed by their path relative to the watched directory.
+struct Journal {
+    file: File,
+    done: HashMap<PathBuf, Stamp>,
+}
+
+impl Journal {
+    /// Load the journal at `path`, creating it if needed.
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut done = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    // A line cut short by a crash mid-write is simply forgotten.
+                    if let Ok(entry) = serde_json::from_str::<JournalEntry>(&line?) {
+                        done.insert(entry.input, entry.stamp);
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, done })
+    }
+
+    fn is_done(&self, input: &Path, stamp: Stamp) -> bool {
+        self.done.get(input) == Some(&stamp)
+    }
+
+    fn record(&mut self, input: &Path, stamp: Stamp) -> io::Result<()> {
+        let entry = JournalEntry {
+            input: input.to_path_buf(),
+            stamp,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::from)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.done.insert(entry.input, stamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod journal {
+        use super::*;
+
+        #[test]
+        fn survives_a_restart() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let path = dir.join(JOURNAL_NAME);
+            let stamp = Stamp {
+                modified_ms: 1_700_000_000_000,
+                len: 42,
+            };
+            let mut journal = Journal::open(&path).unwrap();
+            journal.record(Path::new("a/b.jpg"), stamp).unwrap();
+            assert!(journal.is_done(Path::new("a/b.jpg"), stamp));
+            drop(journal);
+
+            let journal = Journal::open(&path).unwrap();
+            assert!(journal.is_done(Path::new("a/b.jpg"), stamp));
+            assert!(!journal.is_done(Path::new("c.jpg"), stamp));
+        }
+
+        #[test]
+        fn changed_file_is_not_done() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let mut journal = Journal::open(&dir.join(JOURNAL_NAME)).unwrap();
+            let stamp = Stamp {
+                modified_ms: 1,
+                len: 10,
+            };
+            journal.record(Path::new("a.jpg"), stamp).unwrap();
+            assert!(!journal.is_done(Path::new("a.jpg"), Stamp { len: 11, ..stamp }));
+        }
+
+        #[test]
+        fn torn_last_line_is_ignored() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            let path = dir.join(JOURNAL_NAME);
+            fs::write(
+                &path,
+                "{\"input\":\"a.jpg\",\"modified_ms\":1,\"len\":2}\n{\"input\":\"b.j",
+            )
+            .unwrap();
+            let journal = Journal::open(&path).unwrap();
+            let stamp = Stamp {
+                modified_ms: 1,
+                len: 2,
+            };
+            assert!(journal.is_done(Path::new("a.jpg"), stamp));
+            assert_eq!(journal.done.len(), 1);
+        }
+    }
+
+    mod mirrored_path {
+        use super::*;
+
+        #[test]
+        fn keeps_subdirectories_and_switches_to_png() {
+            assert_eq!(
+                mirrored_path(Path::new("/out"), Path::new("2024/june/a.jpg")),
+                PathBuf::from("/out/2024/june/a.png")
+            );
+        }
+    }
+
+    mod is_change {
+        use super::*;
+        use notify::event::{CreateKind, RemoveKind};
+
+        #[test]
+        fn writes_count_but_reads_and_removals_do_not() {
+            assert!(is_change(&EventKind::Create(CreateKind::File)));
+            assert!(is_change(&EventKind::Access(AccessKind::Close(
+                AccessMode::Write
+            ))));
+            assert!(!is_change(&EventKind::Access(AccessKind::Close(
+                AccessMode::Read
+            ))));
+            assert!(!is_change(&EventKind::Remove(RemoveKind::File)));
+        }
+    }
+
+    mod scan {
+        use super::*;
+
+        #[test]
+        fn recurses_and_skips_non_images() {
+            let temp = tempfile::tempdir().unwrap();
+            let dir = temp.path();
+            fs::create_dir_all(dir.join("nested")).unwrap();
+            for name in ["a.png", "nested/b.jpg", "notes.txt", ".hidden.png"] {
+                fs::write(dir.join(name), b"").unwrap();
+            }
+            let mut found = scan(&dir).unwrap();
+            found.sort();
+            assert_eq!(found, vec![dir.join("a.png"), dir.join("nested/b.jpg")]);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::sync::mpsc;
+
+use image::{GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use super::{Compositor, CpuCompositor};
+use crate::foreground::{Background, cover};
+use crate::{BgrError, BgrResult};
+
+/// Compute shaders over images stored one pixel per `u32`, channels in little-endian order.
+const SHADER: &str = r#"
+struct Params {
+    width: u32,
+    height: u32,
+    // blur: 0 is the horizontal pass over 8-bit values, 1 the vertical pass over f32 bits
+    mode: u32,
+    sigma: f32,
+}
+
+@group(0) @binding(0) var<storage, read> a: array<u32>;
+@group(0) @binding(1) var<storage, read> b: array<u32>;
+@group(0) @binding(2) var<storage, read_write> out: array<u32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(16, 16)
+fn compose(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let i = id.y * params.width + id.x;
+    out[i] = (a[i] & 0x00ffffffu) | ((b[i] & 0xffu) << 24u);
+}
+
+@compute @workgroup_size(16, 16)
+fn over(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let i = id.y * params.width + id.x;
+    let fg = a[i];
+    let bg = b[i];
+    let alpha = fg >> 24u;
+    var result = 0u;
+    for (var c = 0u; c < 3u; c++) {
+        let shift = c * 8u;
+        let f = (fg >> shift) & 0xffu;
+        let g = (bg >> shift) & 0xffu;
+        result |= ((f * alpha + g * (255u - alpha) + 127u) / 255u) << shift;
+    }
+    out[i] = result;
+}
+
+@compute @workgroup_size(16, 16)
+fn blur(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let radius = i32(ceil(params.sigma * 3.0));
+    let spread = 2.0 * params.sigma * params.sigma;
+    var sum = 0.0;
+    var weight = 0.0;
+    for (var k = -radius; k <= radius; k++) {
+        var x = i32(id.x);
+        var y = i32(id.y);
+        if (params.mode == 0u) {
+            x = clamp(x + k, 0, i32(params.width) - 1);
+        } else {
+            y = clamp(y + k, 0, i32(params.height) - 1);
+        }
+        let j = u32(y) * params.width + u32(x);
+        var value: f32;
+        if (params.mode == 0u) {
+            value = f32(a[j] & 0xffu);
+        } else {
+            value = bitcast<f32>(a[j]);
+        }
+        let w = exp(-f32(k * k) / spread);
+        sum += w * value;
+        weight += w;
+    }
+    let i = id.y * params.width + id.x;
+    if (params.mode == 0u) {
+        out[i] = bitcast<u32>(sum / weight);
+    } else {
+        out[i] = u32(clamp(round(sum / weight), 0.0, 255.0));
+    }
+}
+"#;
+
+/// Pixels covered by one workgroup along each axis.
+const WORKGROUP_SIDE: u32 = 16;
+
+/// Composites with compute shaders on the first high-performance adapter wgpu finds.
+///
+/// Results match [`CpuCompositor`] exactly for compositing and within rounding for blurs.
+/// Images whose buffers exceed the device's storage limits are processed on the CPU.
+pub struct WgpuCompositor {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    compose: wgpu::ComputePipeline,
+    over: wgpu::ComputePipeline,
+    blur: wgpu::ComputePipeline,
+    /// Largest buffer, in bytes, a shader may bind.
+    max_binding: u64,
+}
+
+impl WgpuCompositor {
+    /// Open a GPU and compile the shaders.
+    ///
+    /// Fails when no adapter is available, such as on headless machines without drivers.
+    pub fn new() -> BgrResult<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or_else(|| BgrError::Gpu("no GPU adapter found".into()))?;
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("bgr compositor"),
+                required_features: wgpu::Features::empty(),
+                required_limits: limits.clone(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|err| BgrError::Gpu(err.to_string()))?;
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bgr compositor"),
+            entries: &[
+                storage(0, true),
+                storage(1, true),
+                storage(2, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bgr compositor"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bgr compositor"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let (compose, over, blur) = (pipeline("compose"), pipeline("over"), pipeline("blur"));
+
+        Ok(Self {
+            max_binding: u64::from(limits.max_storage_buffer_binding_size)
+                .min(limits.max_buffer_size),
+            device,
+            queue,
+            layout,
+            compose,
+            over,
+            blur,
+        })
+    }
+
+    /// Whether a `width` × `height` image fits in one storage buffer.
+    fn fits(&self, width: u32, height: u32) -> bool {
+        u64::from(width) * u64::from(height) * 4 <= self.max_binding
+    }
+
+    /// Run `pipeline` over a `width` × `height` image and read back one `u32` per pixel.
+    fn run(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        (width, height): (u32, u32),
+        a: &[u32],
+        b: &[u32],
+        mode: u32,
+        sigma: f32,
+    ) -> BgrResult<Vec<u32>> {
+        let size = u64::from(width) * u64::from(height) * 4;
+        let input = |label, pixels: &[u32]| {
+            self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(label),
+                contents: &to_bytes(pixels),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        };
+        let (a, b) = (input("a", a), input("b", b));
+        let out = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("out"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut params = Vec::with_capacity(16);
+        for word in [width, height, mode, sigma.to_bits()] {
+            params.extend_from_slice(&word.to_le_bytes());
+        }
+        let params = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("params"),
+            contents: &params,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: a.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: b.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: out.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                width.div_ceil(WORKGROUP_SIDE),
+                height.div_ceil(WORKGROUP_SIDE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&out, 0, &staging, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|err| BgrError::Gpu(err.to_string()))?
+            .map_err(|err| BgrError::Gpu(err.to_string()))?;
+        let pixels = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        staging.unmap();
+        Ok(pixels)
+    }
+}
+
+impl Compositor for WgpuCompositor {
+    fn name(&self) -> &'static str {
+        "wgpu"
+    }
+
+    fn compose(&self, rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaImage> {
+        let (width, height) = rgb.dimensions();
+        if alpha.dimensions() != rgb.dimensions() || !self.fits(width, height) {
+            return CpuCompositor.compose(rgb, alpha);
+        }
+        let colors: Vec<u32> = rgb.pixels().map(pack_rgb).collect();
+        let alphas: Vec<u32> = alpha.pixels().map(|pixel| u32::from(pixel[0])).collect();
+        let pixels = self.run(&self.compose, (width, height), &colors, &alphas, 0, 0.0)?;
+        Ok(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba((pixels[(y * width + x) as usize]).to_le_bytes())
+        }))
+    }
+
+    fn composite(&self, foreground: &RgbaImage, background: &Background) -> BgrResult<RgbImage> {
+        let (width, height) = foreground.dimensions();
+        if !self.fits(width, height) {
+            return CpuCompositor.composite(foreground, background);
+        }
+        let backdrop: Vec<u32> = match background {
+            Background::Color(color) => vec![pack_rgb(color); (width * height) as usize],
+            Background::Image(image) => {
+                cover(image, width, height).pixels().map(pack_rgb).collect()
+            }
+        };
+        let colors: Vec<u32> = foreground
+            .pixels()
+            .map(|pixel| u32::from_le_bytes(pixel.0))
+            .collect();
+        let pixels = self.run(&self.over, (width, height), &colors, &backdrop, 0, 0.0)?;
+        Ok(RgbImage::from_fn(width, height, |x, y| {
+            let [r, g, b, _] = pixels[(y * width + x) as usize].to_le_bytes();
+            Rgb([r, g, b])
+        }))
+    }
+
+    fn blur(&self, mask: &GrayImage, sigma: f32) -> BgrResult<GrayImage> {
+        let (width, height) = mask.dimensions();
+        if sigma <= 0.0 || !self.fits(width, height) {
+            return CpuCompositor.blur(mask, sigma);
+        }
+        let values: Vec<u32> = mask.pixels().map(|pixel| u32::from(pixel[0])).collect();
+        let size = (width, height);
+        let rows = self.run(&self.blur, size, &values, &[0], 0, sigma)?;
+        let pixels = self.run(&self.blur, size, &rows, &[0], 1, sigma)?;
+        Ok(GrayImage::from_fn(width, height, |x, y| {
+            Luma([pixels[(y * width + x) as usize] as u8])
+        }))
+    }
+}
+
+/// An RGB pixel as a `u32` with an empty alpha byte.
+fn pack_rgb(pixel: &Rgb<u8>) -> u32 {
+    u32::from_le_bytes([pixel[0], pixel[1], pixel[2], 0])
+}
+
+fn to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod wgpu_compositor {
+        use super::*;
+
+        /// Needs a GPU; passes without checking anything when none is found.
+        mod integration {
+            use super::*;
+
+            #[test]
+            fn matches_the_cpu() {
+                let Ok(gpu) = WgpuCompositor::new() else {
+                    return;
+                };
+                let rgb = RgbImage::from_fn(37, 21, |x, y| Rgb([x as u8 * 6, y as u8 * 12, 90]));
+                let alpha = GrayImage::from_fn(37, 21, |x, _| Luma([(x * 7) as u8]));
+                let composed = gpu.compose(&rgb, &alpha).unwrap();
+                assert_eq!(composed, CpuCompositor.compose(&rgb, &alpha).unwrap());
+
+                let background = Background::Color(Rgb([255, 255, 255]));
+                assert_eq!(
+                    gpu.composite(&composed, &background).unwrap(),
+                    CpuCompositor.composite(&composed, &background).unwrap()
+                );
+
+                let blurred = gpu.blur(&alpha, 2.0).unwrap();
+                let expected = CpuCompositor.blur(&alpha, 2.0).unwrap();
+                for (a, b) in blurred.pixels().zip(expected.pixels()) {
+                    assert!(a[0].abs_diff(b[0]) <= 2);
+                }
+            }
+        }
+    }
+}
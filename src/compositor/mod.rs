@@ -0,0 +1,80 @@
+use image::{GrayImage, RgbImage, RgbaImage};
+use imageproc::filter::gaussian_blur_f32;
+
+use crate::BgrResult;
+use crate::foreground::{Background, compose_foreground, composite_over, composite_over_linear};
+
+#[cfg(feature = "compositor-wgpu")]
+mod gpu;
+
+#[cfg(feature = "compositor-wgpu")]
+pub use self::gpu::WgpuCompositor;
+
+/// The pixel work after inference: joining a matte to its image, flattening onto a
+/// background, and blurring masks.
+///
+/// [`CpuCompositor`] is the default. On very large images these steps can take longer than a
+/// GPU inference, so with the `compositor-wgpu` feature `WgpuCompositor` runs them as
+/// compute shaders. Pass a compositor to the `_with` methods of the handles, such as
+/// [`MatteHandle::foreground_with`](crate::MatteHandle::foreground_with).
+pub trait Compositor: Send + Sync {
+    /// A short name for messages, such as `cpu`.
+    fn name(&self) -> &'static str;
+
+    /// An RGBA foreground from `rgb` with `alpha` as its alpha channel.
+    fn compose(&self, rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaImage>;
+
+    /// `foreground` flattened onto `background`.
+    fn composite(&self, foreground: &RgbaImage, background: &Background) -> BgrResult<RgbImage>;
+
+    /// `mask` with a Gaussian blur of standard deviation `sigma`.
+    fn blur(&self, mask: &GrayImage, sigma: f32) -> BgrResult<GrayImage>;
+}
+
+/// Composites on the CPU; the behavior of the handle methods without `_with`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuCompositor;
+
+impl Compositor for CpuCompositor {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn compose(&self, rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaImage> {
+        compose_foreground(rgb, alpha)
+    }
+
+    fn composite(&self, foreground: &RgbaImage, background: &Background) -> BgrResult<RgbImage> {
+        Ok(composite_over(foreground, background))
+    }
+
+    fn blur(&self, mask: &GrayImage, sigma: f32) -> BgrResult<GrayImage> {
+        Ok(gaussian_blur_f32(mask, sigma))
+    }
+}
+
+/// Composites on the CPU in linear light, which keeps soft edges from darkening into halos
+/// over the background.
+///
+/// Masks hold coverage rather than color, so their blurs are the same as
+/// [`CpuCompositor`]'s.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinearCompositor;
+
+impl Compositor for LinearCompositor {
+    fn name(&self) -> &'static str {
+        "cpu-linear"
+    }
+
+    fn compose(&self, rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaImage> {
+        compose_foreground(rgb, alpha)
+    }
+
+    fn composite(&self, foreground: &RgbaImage, background: &Background) -> BgrResult<RgbImage> {
+        Ok(composite_over_linear(foreground, background))
+    }
+
+    fn blur(&self, mask: &GrayImage, sigma: f32) -> BgrResult<GrayImage> {
+        Ok(gaussian_blur_f32(mask, sigma))
+    }
+}
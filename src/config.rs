@@ -1,13 +1,183 @@
+use std::fmt;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use image::imageops::FilterType;
 
+use crate::adapter::{ModelAdapter, SharedAdapter};
+use crate::cancel::CancellationToken;
+use crate::color::RenderingIntent;
+use crate::limits::InputLimits;
+use crate::progress::{ProgressSink, SharedProgress};
+#[cfg(feature = "sandboxed-decode")]
+use crate::sandbox::SandboxedDecoder;
+use crate::telemetry::{SharedTelemetry, Telemetry};
+
 /// Environment variable name for specifying the model path.
 pub const ENV_MODEL_PATH: &str = "BGR_MODEL_PATH";
 
 /// Default model path used when no explicit path is provided.
 pub const DEFAULT_MODEL_PATH: &str = "model.onnx";
 
+/// Hardware that runs model inference.
+///
+/// Non-CPU devices need the matching cargo feature (`cuda`, `coreml`, `directml`, or
+/// `candle-cuda` and `candle-metal` with the candle backend). When a provider is not compiled
+/// in or fails to initialize, inference falls back to the CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Device {
+    #[default]
+    Cpu,
+    /// NVIDIA GPUs via CUDA.
+    Cuda,
+    /// Apple Neural Engine / GPU via Core ML.
+    CoreMl,
+    /// Windows GPUs via DirectML.
+    DirectMl,
+    /// Apple GPUs via Metal, with the candle backend.
+    Metal,
+    /// The first accelerator that initializes, otherwise the CPU.
+    Auto,
+}
+
+impl Device {
+    /// Every device, in the order `--device` lists them.
+    pub const ALL: [Device; 6] = [
+        Device::Cpu,
+        Device::Cuda,
+        Device::CoreMl,
+        Device::DirectMl,
+        Device::Metal,
+        Device::Auto,
+    ];
+
+    /// The name `--device` accepts for the device, such as `coreml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Device::Cpu => "cpu",
+            Device::Cuda => "cuda",
+            Device::CoreMl => "coreml",
+            Device::DirectMl => "directml",
+            Device::Metal => "metal",
+            Device::Auto => "auto",
+        }
+    }
+
+    /// The device [`name`](Device::name) gives, ignoring case.
+    pub fn from_name(name: &str) -> Option<Device> {
+        Self::ALL
+            .into_iter()
+            .find(|device| device.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Accelerators to try, in order, before falling back to the CPU.
+    pub fn candidates(self) -> &'static [Device] {
+        match self {
+            Device::Cpu => &[],
+            Device::Cuda => &[Device::Cuda],
+            Device::CoreMl => &[Device::CoreMl],
+            Device::DirectMl => &[Device::DirectMl],
+            Device::Metal => &[Device::Metal],
+            Device::Auto => &[
+                Device::Cuda,
+                Device::CoreMl,
+                Device::DirectMl,
+                Device::Metal,
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Library that runs the ONNX model.
+///
+/// Each backend needs its cargo feature: `backend-ort` (on by default) for ONNX Runtime with
+/// its accelerators, `backend-tract` for pure-Rust CPU inference that needs no shared library,
+/// and `backend-candle` for pure-Rust inference on U2-Net safetensors with CUDA or Metal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Ort,
+    Tract,
+    Candle,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Ort => "ort",
+            Backend::Tract => "tract",
+            Backend::Candle => "candle",
+        }
+    }
+
+    /// Whether this build includes the backend.
+    pub fn is_compiled(self) -> bool {
+        match self {
+            Backend::Ort => cfg!(feature = "backend-ort"),
+            Backend::Tract => cfg!(feature = "backend-tract"),
+            Backend::Candle => cfg!(feature = "backend-candle"),
+        }
+    }
+
+    /// The build information of the runtime library behind the backend, for ONNX Runtime;
+    /// the pure-Rust backends have none.
+    pub fn runtime_version(self) -> Option<String> {
+        crate::backend::runtime_version(self)
+    }
+
+    /// Whether the backend can run on `device` on this machine, or `None` when this build
+    /// cannot run it there at all. A compiled backend can always run on the CPU.
+    pub fn device_available(self, device: Device) -> Option<bool> {
+        crate::backend::device_available(self, device)
+    }
+}
+
+impl Default for Backend {
+    /// ONNX Runtime when it is compiled in, otherwise tract.
+    fn default() -> Self {
+        if cfg!(feature = "backend-ort") {
+            Backend::Ort
+        } else {
+            Backend::Tract
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The contents of a model file held in memory, as fetched by a browser page.
+///
+/// Cloning is cheap and shares the bytes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ModelBytes(Arc<[u8]>);
+
+impl ModelBytes {
+    pub fn new(bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self(bytes.into())
+    }
+}
+
+impl AsRef<[u8]> for ModelBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ModelBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ModelBytes({} bytes)", self.0.len())
+    }
+}
+
 /// Configuration for ONNX model inference and image preprocessing.
 ///
 /// Controls the model path, image resize filters for input/output, and threading behavior.
@@ -17,12 +187,40 @@ pub const DEFAULT_MODEL_PATH: &str = "model.onnx";
 pub struct InferenceSettings {
     /// Path to the ONNX model file.
     pub model_path: PathBuf,
+    /// The model file's contents, loaded instead of `model_path` when set.
+    pub model_bytes: Option<ModelBytes>,
     /// Filter to use when resizing the input image for the model.
     pub input_resize_filter: FilterType,
     /// Filter to use when resizing the output matte to the original image size.
     pub output_resize_filter: FilterType,
     /// Number of intra-op threads for the inference.
     pub intra_threads: Option<usize>,
+    /// Trade speed for a smaller peak memory footprint.
+    ///
+    /// Disables ONNX Runtime's memory pattern planning, resizes the matte at 8-bit
+    /// precision instead of `f32`, and releases inference buffers as soon as possible.
+    pub low_memory: bool,
+    /// Device to run inference on.
+    pub device: Device,
+    /// Library that runs the model.
+    pub backend: Backend,
+    /// How the model's input is normalized and its outputs become a matte.
+    pub adapter: SharedAdapter,
+    /// Bounds checked before an input image is decoded.
+    pub limits: InputLimits,
+    /// How inputs with an embedded color profile are converted to sRGB.
+    pub intent: RenderingIntent,
+    /// Rotate and flip inputs upright according to their EXIF orientation before inference.
+    pub auto_orient: bool,
+    /// Checked between decoding, inference, and matte upscaling.
+    pub cancellation: CancellationToken,
+    /// Told as each input is decoded, preprocessed, run through the model, and postprocessed.
+    pub progress: SharedProgress,
+    /// Told when a model session loads and around each model run.
+    pub telemetry: SharedTelemetry,
+    /// Decode inputs in a separate worker process instead of in-process.
+    #[cfg(feature = "sandboxed-decode")]
+    pub sandbox: Option<SandboxedDecoder>,
 }
 
 impl InferenceSettings {
@@ -30,12 +228,32 @@ impl InferenceSettings {
     pub fn new(model_path: impl Into<PathBuf>) -> Self {
         Self {
             model_path: model_path.into(),
+            model_bytes: None,
             input_resize_filter: FilterType::Triangle,
             output_resize_filter: FilterType::Lanczos3,
             intra_threads: None,
+            low_memory: false,
+            device: Device::Cpu,
+            backend: Backend::default(),
+            adapter: SharedAdapter::default(),
+            limits: InputLimits::default(),
+            intent: RenderingIntent::default(),
+            auto_orient: true,
+            cancellation: CancellationToken::new(),
+            progress: SharedProgress::default(),
+            telemetry: SharedTelemetry::default(),
+            #[cfg(feature = "sandboxed-decode")]
+            sandbox: None,
         }
     }
 
+    /// Load the model from `bytes` instead of reading `model_path`, as where there is no
+    /// filesystem.
+    pub fn with_model_bytes(mut self, bytes: impl Into<Arc<[u8]>>) -> Self {
+        self.model_bytes = Some(ModelBytes::new(bytes));
+        self
+    }
+
     /// Set the model resize filter.
     pub fn with_input_resize_filter(mut self, filter: FilterType) -> Self {
         self.input_resize_filter = filter;
@@ -53,24 +271,123 @@ impl InferenceSettings {
         self.intra_threads = intra_threads;
         self
     }
+
+    /// Enable or disable the low-memory inference mode.
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.low_memory = low_memory;
+        self
+    }
+
+    /// Set the inference device.
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Set the input size limits.
+    pub fn with_limits(mut self, limits: InputLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the rendering intent for converting inputs with an embedded color profile.
+    pub fn with_intent(mut self, intent: RenderingIntent) -> Self {
+        self.intent = intent;
+        self
+    }
+
+    /// Enable or disable applying the EXIF orientation of inputs; when disabled, inputs are
+    /// processed as their pixels are stored.
+    pub fn with_auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Run the model with `backend`; loading fails if its feature is not compiled in.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Pre- and post-process the model with `adapter`, such as the
+    /// [`Architecture`](crate::Architecture) of a custom model.
+    pub fn with_adapter(mut self, adapter: impl ModelAdapter + 'static) -> Self {
+        self.adapter = SharedAdapter::new(adapter);
+        self
+    }
+
+    /// Stop inference early with [`BgrError::Cancelled`](crate::BgrError::Cancelled) once
+    /// `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Report pipeline [`Stage`](crate::Stage)s to `sink`.
+    pub fn with_progress(mut self, sink: impl ProgressSink + 'static) -> Self {
+        self.progress = SharedProgress::new(sink);
+        self
+    }
+
+    /// Call `telemetry`'s hooks on model loads and inference runs, instead of any set before.
+    pub fn with_telemetry(mut self, telemetry: impl Telemetry + 'static) -> Self {
+        self.telemetry = SharedTelemetry::new(telemetry);
+        self
+    }
+
+    /// Call `telemetry`'s hooks too, after those of any telemetry set before.
+    pub fn add_telemetry(mut self, telemetry: impl Telemetry + 'static) -> Self {
+        self.telemetry = self.telemetry.and(telemetry);
+        self
+    }
+
+    /// Decode inputs with `sandbox` instead of in-process.
+    #[cfg(feature = "sandboxed-decode")]
+    pub fn with_sandbox(mut self, sandbox: SandboxedDecoder) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+}
+
+/// Minimum size of a connected mask region, in pixels or relative to the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentArea {
+    /// An absolute pixel count.
+    Pixels(u64),
+    /// A percentage of the image area (`0.0`–`100.0`).
+    Percent(f64),
+}
+
+impl ComponentArea {
+    /// Resolve to a pixel count for an image of the given size.
+    pub fn pixels(self, width: u32, height: u32) -> u64 {
+        match self {
+            ComponentArea::Pixels(pixels) => pixels,
+            ComponentArea::Percent(percent) => {
+                let total = u64::from(width) * u64::from(height);
+                (total as f64 * percent / 100.0).ceil() as u64
+            }
+        }
+    }
 }
 
 /// Configuration for mask post-processing operations.
 ///
-/// Defines the pipeline of blur, threshold, dilation, and hole-filling operations applied
-/// to raw mattes. Used as defaults in [`Bgr`](crate::Bgr) and can be overridden
-/// per operation via [`MatteHandle`](crate::MatteHandle) and [`MaskHandle`](crate::MaskHandle).
+/// Defines the pipeline of blur, threshold, erosion, component filtering, dilation,
+/// hole-filling, and feathering operations applied to raw mattes, in that order. Used as
+/// defaults in [`Bgr`](crate::Bgr) and can be overridden per operation via
+/// [`MatteHandle`](crate::MatteHandle) and [`MaskHandle`](crate::MaskHandle).
 ///
 /// # Explicit Configuration
 ///
-/// This struct does **not** apply automatic logic. For example, setting `dilate = true` or
-/// `fill_holes = true` will **not** automatically enable `binary`. If you need a binary mask
-/// for dilation or hole-filling to work meaningfully, you must explicitly set `binary = true`
-/// or call [`threshold`](crate::MatteHandle::threshold) in your processing chain.
+/// This struct does **not** apply automatic logic. For example, setting `erode`, `dilate`, or
+/// `fill_holes` to `true` will **not** automatically enable `binary`. If you need a binary mask
+/// for these to work meaningfully, you must explicitly set `binary = true` or call
+/// [`threshold`](crate::MatteHandle::threshold) in your processing chain.
 ///
 /// **Note**: The CLI's `--binary auto` mode *does* automatically enable thresholding when
-/// `--dilate` or `--fill-holes` is specified. The library leaves this decision to you for
-/// maximum control and predictability.
+/// `--threshold`, `--erode`, `--dilate`, or `--fill-holes` is specified. The library leaves
+/// this decision to you for maximum control and predictability.
 #[derive(Debug, Clone, PartialEq)]
 pub struct MaskProcessingOptions {
     pub binary: bool,
@@ -79,7 +396,16 @@ pub struct MaskProcessingOptions {
     pub mask_threshold: u8,
     pub dilate: bool,
     pub dilation_radius: f32,
+    pub erode: bool,
+    pub erosion_radius: f32,
     pub fill_holes: bool,
+    /// Keep only the largest connected region of the mask.
+    pub keep_largest: bool,
+    /// Drop connected regions smaller than this.
+    pub min_area: Option<ComponentArea>,
+    /// Soften the final mask edge; applied after every other operation.
+    pub feather: bool,
+    pub feather_radius: f32,
 }
 
 impl Default for MaskProcessingOptions {
@@ -91,7 +417,13 @@ impl Default for MaskProcessingOptions {
             mask_threshold: 120,
             dilate: false,
             dilation_radius: 5.0,
+            erode: false,
+            erosion_radius: 2.0,
             fill_holes: false,
+            keep_largest: false,
+            min_area: None,
+            feather: false,
+            feather_radius: 2.0,
         }
     }
 }
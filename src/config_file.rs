@@ -0,0 +1,437 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use clap::builder::ArgPredicate;
+
+/// Variable naming another config file than `config.toml` in the config directory.
+const CONFIG_ENV: &str = "BGR_CONFIG";
+/// Variable selecting a project profile, like `--project-profile`.
+pub const PROFILE_ENV: &str = "BGR_PROFILE";
+const CONFIG_FILE_NAME: &str = "config.toml";
+/// A project's shared defaults, found in the working directory or the nearest one above it.
+pub const PROJECT_FILE_NAME: &str = ".bgr.toml";
+/// The table of a project file holding its named profiles.
+const PROFILES: &str = "profile";
+
+/// A setting the config file holds a persistent default for.
+pub struct ConfigKey {
+    /// The key in the file, as the flag is spelled.
+    pub name: &'static str,
+    /// The id of the argument it defaults.
+    pub arg: &'static str,
+    /// The subcommand whose argument it is, for ids several subcommands use differently.
+    pub command: Option<&'static str>,
+    /// Whether the value is written as a TOML number.
+    numeric: bool,
+    /// Arguments in whose presence the default does not apply, as it would conflict.
+    unless: &'static [&'static str],
+}
+
+/// Every key the config file understands.
+pub const KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "model",
+        arg: "model",
+        command: None,
+        numeric: false,
+        unless: &[],
+    },
+    ConfigKey {
+        name: "device",
+        arg: "device",
+        command: None,
+        numeric: false,
+        unless: &[],
+    },
+    ConfigKey {
+        name: "models-dir",
+        arg: "models_dir",
+        command: None,
+        numeric: false,
+        unless: &[],
+    },
+    // The `--output` extension names the format, and palettes and drafts are PNG only.
+    ConfigKey {
+        name: "format",
+        arg: "format",
+        command: Some("cut"),
+        numeric: false,
+        unless: &["output", "palette", "draft"],
+    },
+    ConfigKey {
+        name: "feather",
+        arg: "feather",
+        command: None,
+        numeric: true,
+        unless: &[],
+    },
+];
+
+impl ConfigKey {
+    /// Reject `value` unless the flag this key defaults would accept it, checked by the flag's
+    /// own parser in `root`, the command line's root command.
+    pub fn check(&self, root: &Command, value: &str) -> clap::error::Result<()> {
+        let command = match self.command {
+            Some(name) => root
+                .find_subcommand(name)
+                .expect("config keys name existing subcommands"),
+            None => root,
+        };
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == self.arg)
+            .expect("config keys name existing arguments");
+        arg.get_value_parser()
+            .parse_ref(command, Some(arg), OsStr::new(value))
+            .map(drop)
+    }
+}
+
+/// The names of every key, for the `config` subcommand.
+pub fn key_names() -> Vec<&'static str> {
+    KEYS.iter().map(|key| key.name).collect()
+}
+
+/// The key called `name`.
+pub fn key(name: &str) -> Option<&'static ConfigKey> {
+    KEYS.iter().find(|key| key.name == name)
+}
+
+/// Persistent defaults from `config.toml`, which command-line flags and environment
+/// variables override.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    values: toml::Table,
+}
+
+impl ConfigFile {
+    /// Where the config file lives: `BGR_CONFIG`, else `config.toml` in the platform config
+    /// directory (`~/.config/bgr` on Linux).
+    pub fn default_path() -> PathBuf {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return PathBuf::from(path);
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join("bgr"))
+            .unwrap_or_else(|| {
+                dirs::home_dir()
+                    .unwrap_or_else(|| PathBuf::from("."))
+                    .join(".config")
+                    .join("bgr")
+            })
+            .join(CONFIG_FILE_NAME)
+    }
+
+    /// Read the config file at the default path; a missing file holds no defaults.
+    pub fn load() -> io::Result<Self> {
+        let path = Self::default_path();
+        let values = match read_table(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            values => values?,
+        };
+        Ok(Self { path, values })
+    }
+
+    /// Layer the project file for `dir` over these defaults: its top-level keys, then those
+    /// of its `[profile.<name>]` table when `profile` names one, which must exist.
+    pub fn with_project(mut self, dir: &Path, profile: Option<&str>) -> io::Result<Self> {
+        let Some(path) = find_project_file(dir) else {
+            return match profile {
+                Some(name) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "--project-profile {name} needs a {PROJECT_FILE_NAME} in {} or above it",
+                        dir.display()
+                    ),
+                )),
+                None => Ok(self),
+            };
+        };
+        let mut project = read_table(&path)?;
+        let profiles = project.remove(PROFILES);
+        self.values.extend(project);
+        if let Some(name) = profile {
+            let profile = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .and_then(toml::Value::as_table)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} has no [profile.{name}]", path.display()),
+                    )
+                })?;
+            self.values.extend(profile.clone());
+        }
+        Ok(self)
+    }
+
+    /// Install every known default as the default value of its argument, in every
+    /// subcommand that has it, so flags and environment variables still take precedence.
+    ///
+    /// Values the flag's parser rejects are left out, so that one bad value does not fail
+    /// every invocation, `bgr config unset` included; [`rejected`](Self::rejected) lists them.
+    pub fn apply(&self, mut command: Command) -> Command {
+        for key in KEYS {
+            if let Some(value) = self.get(key.name)
+                && key.check(&command, &value).is_ok()
+            {
+                command = with_default(command, key, &value);
+            }
+        }
+        command
+    }
+
+    /// The keys whose values [`apply`](Self::apply) leaves out of `root`, with why.
+    pub fn rejected(&self, root: &Command) -> Vec<(&'static str, String)> {
+        KEYS.iter()
+            .filter_map(|key| {
+                let value = self.get(key.name)?;
+                let err = key.check(root, &value).err()?;
+                let reason = err.kind().as_str().unwrap_or("invalid value").to_string();
+                Some((key.name, format!("{value:?}: {reason}")))
+            })
+            .collect()
+    }
+
+    /// The value set for `name`, as a flag would spell it.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match self.values.get(name)? {
+            toml::Value::String(value) => Some(value.clone()),
+            toml::Value::Integer(value) => Some(value.to_string()),
+            toml::Value::Float(value) => Some(value.to_string()),
+            toml::Value::Boolean(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Every value set, by key.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, String)> {
+        self.values
+            .keys()
+            .filter_map(|name| Some((name.as_str(), self.get(name)?)))
+    }
+
+    /// Set `key` to `value`, which the caller has validated.
+    pub fn set(&mut self, key: &ConfigKey, value: &str) {
+        let value = match value.parse::<f64>() {
+            Ok(number) if key.numeric => toml::Value::Float(number),
+            _ => toml::Value::String(value.to_string()),
+        };
+        self.values.insert(key.name.to_string(), value);
+    }
+
+    /// Remove `name`, returning whether it was set.
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.values.remove(name).is_some()
+    }
+
+    /// Write the file back, creating its directory. Comments in the file are not kept.
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(&self.values).map_err(io::Error::other)?;
+        fs::write(&self.path, text)
+    }
+}
+
+/// The project file in `dir` or the nearest directory above it, like `.gitignore`.
+pub fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// The profile `--project-profile` or `BGR_PROFILE` selects, read ahead of parsing as it decides the
+/// defaults the parser gets.
+pub fn selected_profile(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().skip(1).filter_map(|arg| arg.to_str());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--project-profile" {
+            return args.next().map(str::to_string);
+        }
+        if let Some(name) = arg.strip_prefix("--project-profile=") {
+            return Some(name.to_string());
+        }
+    }
+    std::env::var(PROFILE_ENV).ok()
+}
+
+fn read_table(path: &Path) -> io::Result<toml::Table> {
+    fs::read_to_string(path)?
+        .parse::<toml::Table>()
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", path.display()),
+            )
+        })
+}
+
+/// `command` and its subcommands with `value` as the default of the argument `key` names.
+fn with_default(mut command: Command, key: &ConfigKey, value: &str) -> Command {
+    let applies = key.command.is_none_or(|name| name == command.get_name());
+    if applies && command.get_arguments().any(|arg| arg.get_id() == key.arg) {
+        let value = value.to_string();
+        command = command.mut_arg(key.arg, |mut arg| {
+            arg = arg.default_value(value);
+            for &other in key.unless {
+                arg = arg.default_value_if(other, ArgPredicate::IsPresent, None);
+            }
+            arg
+        });
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        command = command.mut_subcommand(name, |sub| with_default(sub, key, value));
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands, DeviceArg};
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn config(text: &str) -> ConfigFile {
+        ConfigFile {
+            path: PathBuf::from("config.toml"),
+            values: text.parse().unwrap(),
+        }
+    }
+
+    fn parse(config: &ConfigFile, args: &[&str]) -> Result<Cli, clap::Error> {
+        let matches = config.apply(Cli::command()).try_get_matches_from(args)?;
+        Cli::from_arg_matches(&matches)
+    }
+
+    mod config_file {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn defaults_apply_under_flags() {
+                let config = config("model = \"isnet\"\ndevice = \"cuda\"\nfeather = 3.5\n");
+                let cli = parse(&config, &["outline", "cut", "in.png"]).unwrap();
+                assert_eq!(cli.global.model, "isnet");
+                assert_eq!(cli.global.device, DeviceArg::Cuda);
+                let Commands::Cut(cmd) = cli.command else {
+                    panic!("expected cut");
+                };
+                assert_eq!(cmd.mask_processing.feather, Some(3.5));
+
+                let cli =
+                    parse(&config, &["outline", "cut", "in.png", "--model", "u2net"]).unwrap();
+                assert_eq!(cli.global.model, "u2net");
+            }
+
+            #[test]
+            fn format_yields_to_the_output_extension() {
+                let config = config("format = \"webp\"\n");
+                let cli = parse(&config, &["outline", "cut", "in.png"]).unwrap();
+                let Commands::Cut(cmd) = cli.command else {
+                    panic!("expected cut");
+                };
+                assert!(cmd.format.is_some());
+                let cli = parse(&config, &["outline", "cut", "in.png", "-o", "out.jpg"]).unwrap();
+                let Commands::Cut(cmd) = cli.command else {
+                    panic!("expected cut");
+                };
+                assert!(cmd.format.is_none());
+            }
+
+            #[test]
+            fn format_only_defaults_cut() {
+                let config = config("format = \"webp\"\n");
+                assert!(parse(&config, &["outline", "mask", "in.png"]).is_ok());
+            }
+
+            #[test]
+            fn invalid_values_are_skipped_and_reported() {
+                let config = config("device = \"abacus\"\nmodel = \"isnet\"\n");
+                let cli = parse(&config, &["outline", "cut", "in.png"]).unwrap();
+                assert_eq!(cli.global.device, DeviceArg::Cpu);
+                assert_eq!(cli.global.model, "isnet");
+                assert!(parse(&config, &["outline", "config", "unset", "device"]).is_ok());
+                let rejected = config.rejected(&Cli::command());
+                assert_eq!(rejected.len(), 1);
+                assert_eq!(rejected[0].0, "device");
+            }
+
+            #[test]
+            fn project_profiles_layer_over_the_config_file() {
+                let temp = tempfile::tempdir().unwrap();
+                let root = temp.path();
+                let nested = root.join("shots").join("spring");
+                fs::create_dir_all(&nested).unwrap();
+                fs::write(
+                    root.join(PROJECT_FILE_NAME),
+                    "model = \"isnet\"\n[profile.ecommerce]\nformat = \"webp\"\nmodel = \"rmbg\"\n",
+                )
+                .unwrap();
+                let user = || config("model = \"u2net\"\ndevice = \"cuda\"\n");
+
+                let project = user().with_project(&nested, None).unwrap();
+                assert_eq!(project.get("model").as_deref(), Some("isnet"));
+                assert_eq!(project.get("device").as_deref(), Some("cuda"));
+                assert_eq!(project.get("format"), None);
+                assert_eq!(project.get(PROFILES), None);
+
+                let profile = user().with_project(&nested, Some("ecommerce")).unwrap();
+                assert_eq!(profile.get("model").as_deref(), Some("rmbg"));
+                assert_eq!(profile.get("format").as_deref(), Some("webp"));
+                assert!(user().with_project(&nested, Some("print")).is_err());
+            }
+
+            #[test]
+            fn profile_is_read_ahead_of_parsing() {
+                let args =
+                    |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+                let profile = |list: &[&str]| selected_profile(&args(list));
+                assert_eq!(
+                    profile(&["bgr", "--project-profile", "ecommerce", "cut", "a.png"]).as_deref(),
+                    Some("ecommerce")
+                );
+                assert_eq!(
+                    profile(&["bgr", "cut", "a.png", "--project-profile=print"]).as_deref(),
+                    Some("print")
+                );
+                if std::env::var_os(PROFILE_ENV).is_none() {
+                    assert_eq!(
+                        profile(&["bgr", "cut", "--", "--project-profile", "x"]),
+                        None
+                    );
+                    assert_eq!(
+                        profile(&["bgr", "trace", "a.png", "--profile", "sticker"]),
+                        None
+                    );
+                }
+            }
+
+            #[test]
+            fn numbers_stay_numbers() {
+                let mut config = ConfigFile::default();
+                config.set(key("feather").unwrap(), "2");
+                config.set(key("model").unwrap(), "isnet");
+                assert_eq!(config.values["feather"], toml::Value::Float(2.0));
+                assert_eq!(config.get("model").as_deref(), Some("isnet"));
+                assert!(config.unset("model"));
+                assert!(!config.unset("model"));
+            }
+        }
+    }
+}
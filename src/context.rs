@@ -0,0 +1,229 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{DEFAULT_MODEL_PATH, InferenceSettings};
+use crate::error::BgrResult;
+use crate::models::{default_data_dir, default_models_dir, resolve_model_path};
+
+/// How much progress information operations should report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only warnings and errors.
+    Quiet,
+    /// One line per written artifact.
+    #[default]
+    Normal,
+    /// Additional details such as the resolved model path.
+    Verbose,
+}
+
+/// Settings shared by every operation in a run, resolved once and passed by reference.
+///
+/// Bundles the models and data directories, network policy, verbosity, and the inference
+/// settings with the model already resolved to a local file, so individual commands and
+/// library calls don't re-derive them.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Bgr, Context, InferenceSettings};
+///
+/// let ctx = Context::new().with_offline(true);
+/// let model = ctx.resolve_model("isnet")?;
+/// let ctx = ctx.with_inference_settings(InferenceSettings::new(model));
+/// let bgr = Bgr::from_context(&ctx);
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Context {
+    models_dir: PathBuf,
+    data_dir: PathBuf,
+    offline: bool,
+    verbosity: Verbosity,
+    inference: InferenceSettings,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context {
+    /// Create a context using the platform models and data directories and default inference
+    /// settings.
+    pub fn new() -> Self {
+        Self {
+            models_dir: default_models_dir(),
+            data_dir: default_data_dir(),
+            offline: false,
+            verbosity: Verbosity::default(),
+            inference: InferenceSettings::new(DEFAULT_MODEL_PATH),
+        }
+    }
+
+    /// Set the directory where model presets are stored.
+    pub fn with_models_dir(mut self, models_dir: impl Into<PathBuf>) -> Self {
+        self.models_dir = models_dir.into();
+        self
+    }
+
+    /// Set the directory where bgr keeps its own state, such as accepted model licenses.
+    pub fn with_data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = data_dir.into();
+        self
+    }
+
+    /// Forbid network access; presets must already be downloaded.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Set the verbosity.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Set the inference settings, including the resolved model path.
+    pub fn with_inference_settings(mut self, settings: InferenceSettings) -> Self {
+        self.inference = settings;
+        self
+    }
+
+    /// Get the models directory.
+    pub fn models_dir(&self) -> &Path {
+        &self.models_dir
+    }
+
+    /// Get the data directory.
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Whether network access is disabled.
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Get the verbosity.
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Get the inference settings.
+    pub fn inference_settings(&self) -> &InferenceSettings {
+        &self.inference
+    }
+
+    /// Resolve a model preset name or path against this context's models directory.
+    ///
+    /// Missing presets are downloaded unless the context is offline (downloads require the
+    /// `download` feature). Presets under a restrictive license are only downloaded once their
+    /// license is accepted with [`accept_license`](crate::models::accept_license). `auto`
+    /// stands for [`ModelPreset::auto`](crate::models::ModelPreset::auto).
+    /// With the candle backend, the U2-Net presets resolve to their safetensors weights, which
+    /// are never downloaded, and other presets are rejected.
+    pub fn resolve_model(&self, specifier: &str) -> BgrResult<PathBuf> {
+        let specifier = if specifier == crate::models::AUTO_MODEL {
+            crate::models::ModelPreset::auto(&self.models_dir).name()
+        } else {
+            specifier
+        };
+        #[cfg(feature = "backend-candle")]
+        if self.inference.backend == crate::config::Backend::Candle
+            && let Some(preset) = crate::models::ModelPreset::from_str(specifier)
+        {
+            if !preset.runs_on_candle() {
+                return Err(crate::BgrError::invalid_input(format!(
+                    "the candle backend runs the u2net and u2netp presets only, not {}",
+                    preset.name()
+                )));
+            }
+            let path = preset.safetensors_path(&self.models_dir);
+            if !path.exists() {
+                return Err(crate::models::ModelError::NotFound(path).into());
+            }
+            return Ok(path);
+        }
+        #[cfg(feature = "download")]
+        if !self.offline
+            && let Some(preset) = crate::models::ModelPreset::from_str(specifier)
+            && !preset.is_downloaded(&self.models_dir)
+        {
+            crate::models::require_license(preset, &self.data_dir)?;
+            return Ok(crate::models::download_model_sync(
+                preset,
+                &self.models_dir,
+                &self.inference.progress,
+                &self.inference.cancellation,
+            )?);
+        }
+        Ok(resolve_model_path(specifier, &self.models_dir, false)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_ordering() {
+        assert!(Verbosity::Quiet < Verbosity::Normal);
+        assert!(Verbosity::Normal < Verbosity::Verbose);
+    }
+
+    #[test]
+    fn builder_sets_fields() {
+        let ctx = Context::new()
+            .with_models_dir("/tmp/models")
+            .with_data_dir("/tmp/bgr")
+            .with_offline(true)
+            .with_verbosity(Verbosity::Quiet);
+        assert_eq!(ctx.models_dir(), Path::new("/tmp/models"));
+        assert_eq!(ctx.data_dir(), Path::new("/tmp/bgr"));
+        assert!(ctx.offline());
+        assert_eq!(ctx.verbosity(), Verbosity::Quiet);
+    }
+
+    #[test]
+    fn offline_missing_preset_is_not_found() {
+        let ctx = Context::new()
+            .with_models_dir("/nonexistent/bgr-models")
+            .with_offline(true);
+        assert!(ctx.resolve_model("isnet").is_err());
+    }
+
+    #[cfg(feature = "backend-candle")]
+    #[test]
+    fn candle_runs_u2net_presets_only() {
+        let ctx = Context::new().with_models_dir("/nonexistent/bgr-models");
+        let settings = ctx
+            .inference_settings()
+            .clone()
+            .with_backend(crate::config::Backend::Candle);
+        let ctx = ctx.with_inference_settings(settings);
+        assert!(matches!(
+            ctx.resolve_model("u2net"),
+            Err(crate::BgrError::Model(crate::models::ModelError::NotFound(path)))
+                if path.extension().is_some_and(|extension| extension == "safetensors")
+        ));
+        assert!(matches!(
+            ctx.resolve_model("birefnet"),
+            Err(crate::BgrError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "download")]
+    #[test]
+    fn unaccepted_license_blocks_download() {
+        let ctx = Context::new()
+            .with_models_dir("/nonexistent/bgr-models")
+            .with_data_dir("/nonexistent/bgr");
+        assert!(matches!(
+            ctx.resolve_model("rmbg"),
+            Err(crate::BgrError::Model(
+                crate::models::ModelError::LicenseNotAccepted { .. }
+            ))
+        ));
+    }
+}
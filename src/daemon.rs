@@ -0,0 +1,456 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use image::{GrayImage, RgbImage};
+
+use crate::{
+    BgrError, BgrResult, CacheKey, InferencedMatte, InputLimits, MaskProcessingOptions, MatteCache,
+    SessionPool, Warning,
+};
+
+/// Longest input path a daemon accepts, in bytes.
+const MAX_PATH_BYTES: u32 = 64 << 10;
+/// Longest JSON warning list a client accepts, in bytes.
+const MAX_WARNINGS_BYTES: u32 = 64 << 10;
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+/// Where `bgr daemon` listens unless told otherwise: `$XDG_RUNTIME_DIR/bgr.sock`, or
+/// `bgr-<uid>/bgr.sock` in the temporary directory when that is unset.
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(private_temp_dir)
+        .join("bgr.sock")
+}
+
+/// The per-user directory in the shared temporary directory that holds the default socket.
+fn private_temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("bgr-{}", current_uid()))
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir` accessible to this user only, or check that an existing one is: owned by this
+/// user, not a symlink, and closed to the group and others.
+fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory private to this user; remove it or pass --socket",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Keeps model sessions loaded and runs inference for [`DaemonClient`]s over a unix socket.
+///
+/// Loading the model dominates the latency of a single image, so a daemon started once lets
+/// later invocations skip it. Clients send an input path and receive the decoded image, raw
+/// matte, and warnings; mask processing, compositing, and tracing stay on the client, so every
+/// request can use different options. The socket is only accessible to the user who started
+/// the daemon.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Bgr, Daemon};
+///
+/// let bgr = Bgr::new("model.onnx");
+/// Daemon::bind(bgr::default_socket_path(), bgr.session_pool(2)?)?.serve()?;
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub struct Daemon {
+    listener: UnixListener,
+    path: PathBuf,
+    pool: SessionPool,
+    /// The response cache, with the pool's input limits for checking inputs before reading.
+    cache: Option<(MatteCache, InputLimits)>,
+}
+
+impl Daemon {
+    /// Listen on `path`, replacing a stale socket left by a daemon that did not shut down.
+    ///
+    /// The socket is created accessible to this user only, and a file at `path` that belongs
+    /// to another user is refused rather than replaced. The default socket's directory in the
+    /// shared temporary directory is created private to this user.
+    pub fn bind(path: impl Into<PathBuf>, pool: SessionPool) -> BgrResult<Self> {
+        let path = path.into();
+        if path.parent() == Some(private_temp_dir().as_path()) {
+            ensure_private_dir(&private_temp_dir())?;
+        }
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if metadata.uid() != current_uid() {
+                return Err(BgrError::Io(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} belongs to another user", path.display()),
+                )));
+            }
+            if UnixStream::connect(&path).is_ok() {
+                return Err(BgrError::Io(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("a daemon is already listening on {}", path.display()),
+                )));
+            }
+            fs::remove_file(&path)?;
+        }
+        // Bind under a umask that leaves the socket to this user from the moment it exists,
+        // rather than narrowing its permissions afterwards.
+        // SAFETY: umask has no preconditions and cannot fail.
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(&path);
+        // SAFETY: as above; this restores the process's own mask.
+        unsafe { libc::umask(umask) };
+        let listener = listener?;
+        Ok(Self {
+            listener,
+            path,
+            pool,
+            cache: None,
+        })
+    }
+
+    /// Answer repeated requests for the same image content from `cache`, skipping the decode
+    /// and inference; the input is then read whole before it is decoded, once its size is
+    /// within the sessions' file size limit.
+    pub fn with_cache(mut self, cache: MatteCache) -> Self {
+        let limits = self.pool.run(|session| session.settings.limits);
+        self.cache = Some((cache, limits));
+        self
+    }
+
+    /// The socket path clients connect to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Answer clients until the process exits, one thread per connection.
+    ///
+    /// Requests wait for an idle session when every session in the pool is busy.
+    pub fn serve(&self) -> BgrResult<()> {
+        thread::scope(|scope| {
+            for stream in self.listener.incoming() {
+                let stream = stream?;
+                scope.spawn(move || {
+                    // A client that disconnects mid-request only ends its own connection.
+                    let _ = self.handle(stream);
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn handle(&self, stream: UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let mut writer = &stream;
+        while let Some(path) = read_request(&mut reader)? {
+            match self.infer(&path) {
+                Ok(inferred) => write_matte(&mut writer, &inferred)?,
+                Err(err) => write_error(&mut writer, &err.to_string())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn infer(&self, path: &Path) -> BgrResult<InferencedMatte> {
+        let Some((cache, limits)) = &self.cache else {
+            return self.pool.run(|session| session.for_image(path));
+        };
+        limits.check_file_size(fs::metadata(path)?.len())?;
+        let encoded = fs::read(path)?;
+        cache.get_or_infer(CacheKey::of(&[&encoded]), || {
+            self.pool.run(|session| session.for_bytes(&encoded))
+        })
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A connection to a [`Daemon`] that runs inference in place of a local
+/// [`BgrSession`](crate::BgrSession).
+///
+/// Created by [`Bgr::daemon_client`](crate::Bgr::daemon_client), which also supplies the default
+/// mask processing for the returned mattes.
+pub struct DaemonClient {
+    stream: UnixStream,
+    default_mask_processing: MaskProcessingOptions,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon listening on `path`.
+    pub fn connect(
+        path: impl AsRef<Path>,
+        default_mask_processing: MaskProcessingOptions,
+    ) -> BgrResult<Self> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path).map_err(|err| {
+            BgrError::Daemon(format!(
+                "cannot connect to {} ({err}); start one with `bgr daemon`",
+                path.display()
+            ))
+        })?;
+        Ok(Self {
+            stream,
+            default_mask_processing,
+        })
+    }
+
+    /// Run inference for a single image in the daemon.
+    ///
+    /// Relative paths are resolved against this process's working directory first.
+    pub fn for_image(&mut self, image_path: impl AsRef<Path>) -> BgrResult<InferencedMatte> {
+        let path = std::path::absolute(image_path.as_ref())?;
+        write_request(&mut self.stream, &path)?;
+        let (rgb, matte, warnings) = read_response(&mut self.stream)?;
+        Ok(InferencedMatte::new(
+            rgb,
+            matte,
+            warnings,
+            self.default_mask_processing.clone(),
+        ))
+    }
+}
+
+fn write_request(output: &mut impl Write, path: &Path) -> io::Result<()> {
+    let bytes = path.as_os_str().as_bytes();
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|&len| len <= MAX_PATH_BYTES)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "input path is too long"))?;
+    let mut request = len.to_le_bytes().to_vec();
+    request.extend_from_slice(bytes);
+    output.write_all(&request)?;
+    output.flush()
+}
+
+/// The next requested path, or `None` once the client hangs up.
+fn read_request(input: &mut impl Read) -> io::Result<Option<PathBuf>> {
+    let len = match read_u32(input) {
+        Ok(len) => len,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if len > MAX_PATH_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "input path is too long",
+        ));
+    }
+    let mut path = vec![0u8; len as usize];
+    input.read_exact(&mut path)?;
+    Ok(Some(OsString::from_vec(path).into()))
+}
+
+fn write_matte(output: &mut impl Write, inferred: &InferencedMatte) -> io::Result<()> {
+    let rgb = inferred.rgb_image();
+    let warnings = serde_json::to_vec(inferred.warnings()).map_err(io::Error::other)?;
+    let mut header = vec![STATUS_OK];
+    header.extend(rgb.width().to_le_bytes());
+    header.extend(rgb.height().to_le_bytes());
+    output.write_all(&header)?;
+    output.write_all(rgb.as_raw())?;
+    output.write_all(inferred.raw_matte().as_raw())?;
+    output.write_all(&(warnings.len() as u32).to_le_bytes())?;
+    output.write_all(&warnings)?;
+    output.flush()
+}
+
+fn write_error(output: &mut impl Write, message: &str) -> io::Result<()> {
+    let mut response = vec![STATUS_ERROR];
+    response.extend((message.len() as u32).to_le_bytes());
+    response.extend(message.as_bytes());
+    output.write_all(&response)?;
+    output.flush()
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut word = [0u8; 4];
+    input.read_exact(&mut word)?;
+    Ok(u32::from_le_bytes(word))
+}
+
+fn read_response(input: &mut impl Read) -> BgrResult<(RgbImage, GrayImage, Vec<Warning>)> {
+    let mut status = [0u8; 1];
+    input.read_exact(&mut status)?;
+    match status[0] {
+        STATUS_OK => {
+            let width = read_u32(input)?;
+            let height = read_u32(input)?;
+            let pixels = width as usize * height as usize;
+            let mut rgb = vec![0u8; pixels * 3];
+            input.read_exact(&mut rgb)?;
+            let mut matte = vec![0u8; pixels];
+            input.read_exact(&mut matte)?;
+            let len = read_u32(input)?;
+            if len > MAX_WARNINGS_BYTES {
+                return Err(BgrError::Daemon("warning list is too long".into()));
+            }
+            let mut warnings = vec![0u8; len as usize];
+            input.read_exact(&mut warnings)?;
+            let warnings = serde_json::from_slice(&warnings)
+                .map_err(|err| BgrError::Daemon(format!("malformed warnings: {err}")))?;
+            Ok((
+                RgbImage::from_raw(width, height, rgb).expect("buffer matches dimensions"),
+                GrayImage::from_raw(width, height, matte).expect("buffer matches dimensions"),
+                warnings,
+            ))
+        }
+        STATUS_ERROR => {
+            let mut message = vec![0u8; read_u32(input)? as usize];
+            input.read_exact(&mut message)?;
+            Err(BgrError::Daemon(
+                String::from_utf8_lossy(&message).into_owned(),
+            ))
+        }
+        other => Err(BgrError::Daemon(format!(
+            "unexpected response status {other}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod protocol {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn request_round_trip() {
+                let mut bytes = Vec::new();
+                write_request(&mut bytes, Path::new("/photos/a b.jpg")).unwrap();
+                write_request(&mut bytes, Path::new("/photos/c.png")).unwrap();
+
+                let mut input = &bytes[..];
+                assert_eq!(
+                    read_request(&mut input).unwrap(),
+                    Some(PathBuf::from("/photos/a b.jpg"))
+                );
+                assert_eq!(
+                    read_request(&mut input).unwrap(),
+                    Some(PathBuf::from("/photos/c.png"))
+                );
+                assert_eq!(read_request(&mut input).unwrap(), None);
+            }
+
+            #[test]
+            fn oversized_request_rejected() {
+                let bytes = (MAX_PATH_BYTES + 1).to_le_bytes();
+                assert!(read_request(&mut &bytes[..]).is_err());
+            }
+
+            #[test]
+            fn matte_round_trip() {
+                let rgb = RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 9]));
+                let matte = GrayImage::from_fn(3, 2, |x, y| image::Luma([(x + y * 3) as u8]));
+                let warnings = vec![Warning::OrientationApplied { exif: 6 }];
+                let inferred = InferencedMatte::new(
+                    rgb.clone(),
+                    matte.clone(),
+                    warnings.clone(),
+                    MaskProcessingOptions::default(),
+                );
+                let mut bytes = Vec::new();
+                write_matte(&mut bytes, &inferred).unwrap();
+
+                let (rgb_out, matte_out, warnings_out) = read_response(&mut &bytes[..]).unwrap();
+                assert_eq!(rgb_out, rgb);
+                assert_eq!(matte_out, matte);
+                assert_eq!(warnings_out, warnings);
+            }
+
+            #[test]
+            fn error_round_trip() {
+                let mut bytes = Vec::new();
+                write_error(&mut bytes, "no such file").unwrap();
+                let err = read_response(&mut &bytes[..]).unwrap_err();
+                assert!(matches!(err, BgrError::Daemon(ref message) if message == "no such file"));
+            }
+
+            #[test]
+            fn truncated_response_is_an_error() {
+                let inferred = InferencedMatte::new(
+                    RgbImage::new(4, 4),
+                    GrayImage::new(4, 4),
+                    Vec::new(),
+                    MaskProcessingOptions::default(),
+                );
+                let mut bytes = Vec::new();
+                write_matte(&mut bytes, &inferred).unwrap();
+                bytes.truncate(bytes.len() - 1);
+                assert!(read_response(&mut &bytes[..]).is_err());
+            }
+        }
+    }
+
+    mod bind {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn stale_socket_is_replaced_and_removed_on_drop() {
+                let path = std::env::temp_dir()
+                    .join(format!("bgr-daemon-test-{}.sock", std::process::id()));
+                // A socket file nobody listens on, as left behind by a killed daemon.
+                drop(UnixListener::bind(&path).unwrap());
+                assert!(path.exists());
+
+                let daemon = Daemon::bind(&path, SessionPool::from_sessions(Vec::new())).unwrap();
+                assert!(UnixStream::connect(daemon.path()).is_ok());
+                let second = Daemon::bind(&path, SessionPool::from_sessions(Vec::new()));
+                assert!(second.is_err());
+
+                drop(daemon);
+                assert!(!path.exists());
+            }
+
+            #[test]
+            fn socket_is_private_to_the_user() {
+                let path = std::env::temp_dir()
+                    .join(format!("bgr-daemon-test-mode-{}.sock", std::process::id()));
+                let daemon = Daemon::bind(&path, SessionPool::from_sessions(Vec::new())).unwrap();
+                let mode = fs::metadata(daemon.path()).unwrap().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+
+            #[test]
+            fn shared_directories_are_not_private() {
+                use std::os::unix::fs::PermissionsExt;
+
+                let dir = std::env::temp_dir()
+                    .join(format!("bgr-daemon-test-dir-{}", std::process::id()));
+                ensure_private_dir(&dir).unwrap();
+                assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+                fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+                let shared = ensure_private_dir(&dir);
+                fs::remove_dir(&dir).unwrap();
+                assert!(shared.is_err());
+            }
+        }
+    }
+}
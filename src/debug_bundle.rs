@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::RgbImage;
+use image::imageops::FilterType;
+use ndarray::{Array4, Axis};
+use serde::Serialize;
+
+use crate::error::BgrError;
+use crate::inference::ChannelLayout;
+
+/// Longest side of the input preview kept in a bundle.
+const PREVIEW_SIZE: u32 = 512;
+/// File holding the bundle's description.
+const BUNDLE_FILE: &str = "bundle.json";
+/// File holding the downscaled input.
+const PREVIEW_FILE: &str = "input.png";
+
+/// The range and mean of one channel of a preprocessed input tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChannelStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+/// What the model was fed: the input tensor's shape and per-channel statistics, which tell a
+/// wrong normalization or a corrupt input from a failing model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TensorStats {
+    pub shape: Vec<usize>,
+    /// Red, green, and blue, after normalization.
+    pub channels: Vec<ChannelStats>,
+    /// Values that are NaN or infinite, left out of the channel statistics.
+    pub non_finite: usize,
+}
+
+impl TensorStats {
+    /// Statistics of a preprocessed input tensor in `layout`.
+    pub(crate) fn of(tensor: &Array4<f32>, layout: ChannelLayout) -> Self {
+        let axis = match layout {
+            ChannelLayout::Nchw => Axis(1),
+            ChannelLayout::Nhwc => Axis(3),
+        };
+        let mut non_finite = 0;
+        let channels = tensor
+            .axis_iter(axis)
+            .map(|channel| {
+                let (mut min, mut max, mut sum, mut count) = (f32::MAX, f32::MIN, 0f64, 0usize);
+                for &value in channel.iter() {
+                    if !value.is_finite() {
+                        non_finite += 1;
+                        continue;
+                    }
+                    min = min.min(value);
+                    max = max.max(value);
+                    sum += f64::from(value);
+                    count += 1;
+                }
+                if count == 0 {
+                    return ChannelStats {
+                        min: f32::NAN,
+                        max: f32::NAN,
+                        mean: f32::NAN,
+                    };
+                }
+                ChannelStats {
+                    min,
+                    max,
+                    mean: (sum / count as f64) as f32,
+                }
+            })
+            .collect();
+        Self {
+            shape: tensor.shape().to_vec(),
+            channels,
+            non_finite,
+        }
+    }
+}
+
+/// Everything known about an input that failed, written to a directory so it can be attached
+/// to a bug report: the error and its causes, the settings, the input's size and a downscaled
+/// copy, and the statistics of the tensor the model was fed.
+///
+/// Built by [`BgrSession::debug_bundle`](crate::BgrSession::debug_bundle), or with
+/// [`DebugBundle::new`] when no session is at hand.
+#[derive(Debug, Serialize)]
+pub struct DebugBundle {
+    pub input: PathBuf,
+    pub error: String,
+    /// The error's sources, outermost first.
+    pub causes: Vec<String>,
+    pub bgr_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// The settings the input was processed with, by name.
+    pub settings: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<(u32, u32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tensor: Option<TensorStats>,
+    #[serde(skip)]
+    preview: Option<RgbImage>,
+}
+
+impl DebugBundle {
+    /// A bundle describing how `input` failed with `error`.
+    pub fn new(input: impl Into<PathBuf>, error: &BgrError) -> Self {
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Self {
+            input: input.into(),
+            error: error.to_string(),
+            causes,
+            bgr_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            settings: BTreeMap::new(),
+            input_size: None,
+            tensor: None,
+            preview: None,
+        }
+    }
+
+    /// Record a setting the input was processed with.
+    pub fn with_setting(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.settings.insert(name.into(), value.to_string());
+        self
+    }
+
+    /// Keep the input's size and a copy downscaled to at most 512 pixels a side.
+    pub fn with_input(mut self, rgb: &RgbImage) -> Self {
+        let (width, height) = rgb.dimensions();
+        self.input_size = Some((width, height));
+        let scale = (f64::from(PREVIEW_SIZE) / f64::from(width.max(height).max(1))).min(1.0);
+        let preview_width = ((f64::from(width) * scale).round() as u32).max(1);
+        let preview_height = ((f64::from(height) * scale).round() as u32).max(1);
+        self.preview = Some(image::imageops::resize(
+            rgb,
+            preview_width,
+            preview_height,
+            FilterType::Triangle,
+        ));
+        self
+    }
+
+    /// Keep the statistics of the tensor the model was fed.
+    pub fn with_tensor(mut self, stats: TensorStats) -> Self {
+        self.tensor = Some(stats);
+        self
+    }
+
+    /// Write the bundle to a new directory inside `dir`, named after the input and the time,
+    /// and return its path.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        let stem = self
+            .input
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "input".to_string());
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        let bundle_dir = dir.join(format!("{stem}-{millis}"));
+        std::fs::create_dir_all(&bundle_dir)?;
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::from)?;
+        std::fs::write(bundle_dir.join(BUNDLE_FILE), json)?;
+        if let Some(preview) = &self.preview {
+            preview
+                .save(bundle_dir.join(PREVIEW_FILE))
+                .map_err(io::Error::other)?;
+        }
+        Ok(bundle_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod tensor_stats {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn per_channel_in_either_layout() {
+                let nchw = Array4::from_shape_fn((1, 3, 2, 2), |(_, c, y, x)| {
+                    c as f32 + (y * 2 + x) as f32 / 10.0
+                });
+                let stats = TensorStats::of(&nchw, ChannelLayout::Nchw);
+                assert_eq!(stats.shape, [1, 3, 2, 2]);
+                assert_eq!(stats.channels.len(), 3);
+                assert_eq!(stats.channels[2].min, 2.0);
+                assert!((stats.channels[2].mean - 2.15).abs() < 1e-6);
+
+                let nhwc = nchw.clone().permuted_axes([0, 2, 3, 1]);
+                let nhwc = nhwc.as_standard_layout().to_owned();
+                assert_eq!(
+                    TensorStats::of(&nhwc, ChannelLayout::Nhwc).channels,
+                    stats.channels
+                );
+            }
+
+            #[test]
+            fn non_finite_values_are_counted_apart() {
+                let mut tensor = Array4::<f32>::zeros((1, 3, 1, 2));
+                tensor[[0, 0, 0, 0]] = f32::NAN;
+                let stats = TensorStats::of(&tensor, ChannelLayout::Nchw);
+                assert_eq!(stats.non_finite, 1);
+                assert_eq!(stats.channels[0].max, 0.0);
+            }
+        }
+    }
+
+    mod debug_bundle {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn writes_the_description_and_a_small_preview() {
+                let temp = tempfile::tempdir().unwrap();
+                let dir = temp.path();
+                let error = BgrError::Io(io::Error::other("model output was empty"));
+                let bundle = DebugBundle::new("shots/shoe.jpg", &error)
+                    .with_setting("device", "cpu")
+                    .with_input(&RgbImage::new(2048, 1024));
+                let written = bundle.write(&dir).unwrap();
+                assert!(
+                    written
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .starts_with("shoe-")
+                );
+
+                let json: serde_json::Value =
+                    serde_json::from_slice(&std::fs::read(written.join(BUNDLE_FILE)).unwrap())
+                        .unwrap();
+                assert_eq!(json["error"], "model output was empty");
+                assert_eq!(json["settings"]["device"], "cpu");
+                assert_eq!(json["input_size"], serde_json::json!([2048, 1024]));
+                assert!(json.get("tensor").is_none());
+                let preview = image::open(written.join(PREVIEW_FILE)).unwrap();
+                assert_eq!((preview.width(), preview.height()), (512, 256));
+            }
+        }
+    }
+}
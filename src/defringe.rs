@@ -0,0 +1,112 @@
+use image::{Rgba, RgbaImage};
+
+use crate::matting::box_mean;
+
+/// Window radii of the two estimation passes: a wide one to reach the colors deep inside the
+/// subject and the background, then a narrow one to follow local detail.
+const PASSES: [usize; 2] = [90, 6];
+
+/// Re-estimate the color of partially transparent pixels so they no longer carry the
+/// background's color.
+///
+/// Cutouts keep the input's color under soft edges and hair, which is a blend of subject and
+/// background; over a different background that shows as a fringe. This uses blur-fusion
+/// foreground estimation: local averages of the subject and background colors, weighted by
+/// alpha, are combined with each pixel's observed color to solve for the subject's own color.
+/// Opaque and fully transparent pixels are unchanged.
+pub fn defringe(cutout: &RgbaImage) -> RgbaImage {
+    let (width, height) = cutout.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let alpha: Vec<f32> = cutout
+        .pixels()
+        .map(|pixel| f32::from(pixel[3]) / 255.0)
+        .collect();
+    if alpha.iter().all(|&a| a == 0.0 || a == 1.0) {
+        return cutout.clone();
+    }
+    let inverse: Vec<f32> = alpha.iter().map(|a| 1.0 - a).collect();
+    let observed: [Vec<f32>; 3] = std::array::from_fn(|c| {
+        cutout
+            .pixels()
+            .map(|pixel| f32::from(pixel[c]) / 255.0)
+            .collect()
+    });
+
+    let mut foreground = observed.clone();
+    let mut background = observed.clone();
+    for radius in PASSES {
+        let blurred_alpha = box_mean(&alpha, w, h, radius);
+        let blurred_inverse = box_mean(&inverse, w, h, radius);
+        for c in 0..3 {
+            let weighted = |colors: &[f32], weights: &[f32]| -> Vec<f32> {
+                let products: Vec<f32> = colors.iter().zip(weights).map(|(v, a)| v * a).collect();
+                box_mean(&products, w, h, radius)
+            };
+            let fg_sum = weighted(&foreground[c], &alpha);
+            let bg_sum = weighted(&background[c], &inverse);
+            for i in 0..w * h {
+                let fg = fg_sum[i] / (blurred_alpha[i] + 1e-5);
+                let bg = bg_sum[i] / (blurred_inverse[i] + 1e-5);
+                let a = alpha[i];
+                let residual = observed[c][i] - a * fg - (1.0 - a) * bg;
+                foreground[c][i] = (fg + a * residual).clamp(0.0, 1.0);
+                background[c][i] = bg;
+            }
+        }
+    }
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let pixel = *cutout.get_pixel(x, y);
+        if pixel[3] == 0 || pixel[3] == 255 {
+            return pixel;
+        }
+        let i = y as usize * w + x as usize;
+        let channel = |c: usize| (foreground[c][i] * 255.0).round() as u8;
+        Rgba([channel(0), channel(1), channel(2), pixel[3]])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 40×20 red subject on the left half over a blue background, with a one-pixel soft edge
+    /// whose color is the half-and-half blend.
+    fn fringed() -> RgbaImage {
+        RgbaImage::from_fn(40, 20, |x, _| match x {
+            0..20 => Rgba([255, 0, 0, 255]),
+            20 => Rgba([128, 0, 127, 128]),
+            _ => Rgba([0, 0, 255, 0]),
+        })
+    }
+
+    mod defringe {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn edge_takes_the_subject_color() {
+                let cleaned = defringe(&fringed());
+                let edge = cleaned.get_pixel(20, 10);
+                assert_eq!(edge[3], 128);
+                assert!(edge[0] > 200 && edge[2] < 50, "{edge:?}");
+            }
+
+            #[test]
+            fn solid_pixels_are_unchanged() {
+                let cleaned = defringe(&fringed());
+                assert_eq!(*cleaned.get_pixel(5, 10), Rgba([255, 0, 0, 255]));
+                assert_eq!(*cleaned.get_pixel(35, 10), Rgba([0, 0, 255, 0]));
+            }
+
+            #[test]
+            fn hard_matte_is_unchanged() {
+                let hard =
+                    RgbaImage::from_fn(8, 8, |x, _| Rgba([9, 9, 9, if x < 4 { 255 } else { 0 }]));
+                assert_eq!(defringe(&hard), hard);
+            }
+        }
+    }
+}
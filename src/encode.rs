@@ -0,0 +1,307 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageFormat};
+
+use crate::{BgrError, BgrResult};
+
+/// AVIF quality when none is given, a good trade between size and visible loss.
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+/// JPEG quality when none is given; higher than the encoder's own 75 so flattened cutouts keep
+/// clean edges.
+pub(crate) const DEFAULT_JPEG_QUALITY: u8 = 90;
+/// AVIF encoder speed from 1 (slowest, smallest) to 10; 4 is the encoder's own default.
+const AVIF_SPEED: u8 = 4;
+
+/// Image formats a cutout can be written in. All but JPEG carry an alpha channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    WebP,
+    Avif,
+    Tiff,
+    /// Opaque only: cutouts must be composited over a background first.
+    Jpeg,
+}
+
+impl OutputFormat {
+    /// The format named by the extension of `path`, if it is one of these.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match ImageFormat::from_path(path).ok()? {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::WebP => Some(Self::WebP),
+            ImageFormat::Avif => Some(Self::Avif),
+            ImageFormat::Tiff => Some(Self::Tiff),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    /// The file extension outputs of this format are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+            Self::Tiff => "tiff",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    /// Whether the format stores transparency.
+    pub fn has_alpha(self) -> bool {
+        self != Self::Jpeg
+    }
+
+    /// The matching [`ImageFormat`].
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::WebP => ImageFormat::WebP,
+            Self::Avif => ImageFormat::Avif,
+            Self::Tiff => ImageFormat::Tiff,
+            Self::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+/// How much detail an encoder may give up for a smaller file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Lossless for PNG, WebP, and TIFF; quality 80 for AVIF and 90 for JPEG.
+    #[default]
+    Default,
+    /// Keep every pixel exactly. AVIF and JPEG have no lossless mode.
+    Lossless,
+    /// Lossy encoding at a quality from 1 to 100, for WebP, AVIF, and JPEG. Lossy WebP needs
+    /// the `webp-lossy` feature.
+    Quality(u8),
+}
+
+/// The format and compression an output is encoded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub format: OutputFormat,
+    pub compression: Compression,
+}
+
+impl EncodeOptions {
+    /// Encode as `format` with its default compression.
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            compression: Compression::Default,
+        }
+    }
+
+    /// Fail when the format cannot encode with the compression: a quality for PNG or TIFF,
+    /// or lossless AVIF or JPEG.
+    pub fn check(&self) -> BgrResult<()> {
+        match (self.format, self.compression) {
+            (OutputFormat::Png | OutputFormat::Tiff, Compression::Quality(_)) => {
+                Err(BgrError::invalid_input(format!(
+                    "{} is always lossless and takes no quality",
+                    self.format.extension().to_uppercase()
+                )))
+            }
+            (OutputFormat::Avif | OutputFormat::Jpeg, Compression::Lossless) => {
+                Err(BgrError::invalid_input(format!(
+                    "{} has no lossless mode; use PNG, WebP, or TIFF, or a quality of 100",
+                    self.format.extension().to_uppercase()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Encode `image` in memory as `options` asks, keeping its alpha channel.
+///
+/// Every format but JPEG stores straight (unpremultiplied) alpha: PNG and WebP always do, TIFF
+/// tags it as unassociated, and AVIF stores it in a separate auxiliary image. An image with
+/// alpha is not encoded as JPEG rather than silently made opaque; composite it first.
+pub fn encode_output(image: &DynamicImage, options: &EncodeOptions) -> BgrResult<Vec<u8>> {
+    options.check()?;
+    if !options.format.has_alpha() && image.color().has_alpha() {
+        return Err(BgrError::invalid_input(
+            "JPEG has no alpha channel; composite the cutout over a background first",
+        ));
+    }
+    let image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    match (options.format, options.compression) {
+        (OutputFormat::Png, _) => image.write_with_encoder(PngEncoder::new(&mut encoded))?,
+        (OutputFormat::Tiff, _) => image.write_with_encoder(TiffEncoder::new(&mut encoded))?,
+        (OutputFormat::WebP, Compression::Quality(quality)) => return lossy_webp(&image, quality),
+        (OutputFormat::WebP, _) => {
+            image.write_with_encoder(WebPEncoder::new_lossless(&mut encoded))?
+        }
+        (OutputFormat::Avif, compression) => {
+            let quality = match compression {
+                Compression::Quality(quality) => quality,
+                _ => DEFAULT_AVIF_QUALITY,
+            };
+            let encoder = AvifEncoder::new_with_speed_quality(&mut encoded, AVIF_SPEED, quality);
+            image.write_with_encoder(encoder)?
+        }
+        (OutputFormat::Jpeg, compression) => {
+            let quality = match compression {
+                Compression::Quality(quality) => quality,
+                _ => DEFAULT_JPEG_QUALITY,
+            };
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, quality))?
+        }
+    }
+    Ok(encoded.into_inner())
+}
+
+/// Encode `image` as lossy WebP with libwebp, which keeps the alpha channel lossless.
+#[cfg(feature = "webp-lossy")]
+fn lossy_webp(image: &DynamicImage, quality: u8) -> BgrResult<Vec<u8>> {
+    let encoder = match image {
+        DynamicImage::ImageRgba8(rgba) => {
+            webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+        }
+        DynamicImage::ImageRgb8(rgb) => {
+            webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height())
+        }
+        _ => unreachable!("converted to 8-bit RGB or RGBA"),
+    };
+    Ok(encoder.encode(f32::from(quality)).to_vec())
+}
+
+#[cfg(not(feature = "webp-lossy"))]
+fn lossy_webp(_image: &DynamicImage, _quality: u8) -> BgrResult<Vec<u8>> {
+    Err(BgrError::UnsupportedFormat {
+        format: "lossy WebP",
+        hint: "build bgr with the `webp-lossy` feature, or drop the quality for lossless WebP",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageReader, Rgba, RgbaImage};
+
+    /// A 6×4 cutout whose alpha runs through every column from transparent to opaque.
+    fn cutout() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(6, 4, |x, y| {
+            Rgba([200, (y * 60) as u8, 40, (x * 51) as u8])
+        }))
+    }
+
+    fn decode(bytes: &[u8]) -> RgbaImage {
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .unwrap()
+            .into_rgba8()
+    }
+
+    mod output_format {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn from_path_knows_output_formats() {
+                assert_eq!(
+                    OutputFormat::from_path("out.webp"),
+                    Some(OutputFormat::WebP)
+                );
+                assert_eq!(OutputFormat::from_path("out.tif"), Some(OutputFormat::Tiff));
+                assert_eq!(
+                    OutputFormat::from_path("out.AVIF"),
+                    Some(OutputFormat::Avif)
+                );
+                assert_eq!(OutputFormat::from_path("out.jpg"), Some(OutputFormat::Jpeg));
+                assert_eq!(OutputFormat::from_path("out.bmp"), None);
+                assert_eq!(OutputFormat::from_path("out"), None);
+            }
+        }
+    }
+
+    mod encode_output {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn lossless_formats_keep_alpha_exactly() {
+                for format in [OutputFormat::Png, OutputFormat::WebP, OutputFormat::Tiff] {
+                    let encoded = encode_output(&cutout(), &EncodeOptions::new(format)).unwrap();
+                    let decoded = decode(&encoded);
+                    let expected = cutout().into_rgba8();
+                    for (actual, expected) in decoded.pixels().zip(expected.pixels()) {
+                        assert_eq!(actual[3], expected[3], "{format:?}");
+                        if expected[3] > 0 {
+                            assert_eq!(actual, expected, "{format:?}");
+                        }
+                    }
+                }
+            }
+
+            #[test]
+            fn jpeg_needs_an_opaque_image() {
+                let options = EncodeOptions::new(OutputFormat::Jpeg);
+                assert!(encode_output(&cutout(), &options).is_err());
+                let opaque = DynamicImage::ImageRgb8(cutout().to_rgb8());
+                let encoded = encode_output(&opaque, &options).unwrap();
+                assert_eq!(encoded[..2], [0xff, 0xd8]);
+            }
+
+            #[test]
+            fn avif_is_written_with_its_brand() {
+                let encoded =
+                    encode_output(&cutout(), &EncodeOptions::new(OutputFormat::Avif)).unwrap();
+                assert_eq!(&encoded[4..12], b"ftypavif");
+            }
+
+            #[test]
+            fn quality_needs_a_lossy_format() {
+                let options = EncodeOptions {
+                    format: OutputFormat::Png,
+                    compression: Compression::Quality(80),
+                };
+                assert!(options.check().is_err());
+                assert!(encode_output(&cutout(), &options).is_err());
+                let options = EncodeOptions {
+                    format: OutputFormat::Avif,
+                    compression: Compression::Lossless,
+                };
+                assert!(encode_output(&cutout(), &options).is_err());
+                let options = EncodeOptions {
+                    format: OutputFormat::WebP,
+                    compression: Compression::Lossless,
+                };
+                assert!(options.check().is_ok());
+            }
+
+            #[cfg(feature = "webp-lossy")]
+            #[test]
+            fn lossy_webp_keeps_alpha() {
+                let options = EncodeOptions {
+                    format: OutputFormat::WebP,
+                    compression: Compression::Quality(75),
+                };
+                let decoded = decode(&encode_output(&cutout(), &options).unwrap());
+                let expected = cutout().into_rgba8();
+                for (actual, expected) in decoded.pixels().zip(expected.pixels()) {
+                    assert!(actual[3].abs_diff(expected[3]) <= 2, "{actual:?}");
+                }
+            }
+        }
+    }
+}
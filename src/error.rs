@@ -10,8 +10,27 @@ pub type BgrResult<T> = std::result::Result<T, BgrError>;
 #[derive(Debug, Error)]
 pub enum BgrError {
     /// ONNX Runtime inference error.
+    #[cfg(feature = "backend-ort")]
     #[error("ONNX Runtime error: {0}")]
     Ort(#[from] ort::Error),
+    /// tract inference error.
+    #[cfg(feature = "backend-tract")]
+    #[error("tract error: {0}")]
+    Tract(#[from] tract_onnx::prelude::TractError),
+    /// candle inference error.
+    #[cfg(feature = "backend-candle")]
+    #[error("candle error: {0}")]
+    Candle(#[from] candle_core::Error),
+    /// The GPU compositor could not start or failed to run a shader.
+    #[cfg(feature = "compositor-wgpu")]
+    #[error("GPU compositing failed: {0}")]
+    Gpu(String),
+    /// An ICC profile could not be read or a color transform could not be built.
+    #[error("Color management failed: {0}")]
+    Color(String),
+    /// The selected inference backend was not compiled in.
+    #[error("The {0} inference backend is not available; rebuild with the backend-{0} feature")]
+    BackendUnavailable(&'static str),
     /// Image loading, decoding, or encoding error.
     #[error("Image processing failed: {0}")]
     Image(#[from] image::ImageError),
@@ -33,4 +52,64 @@ pub enum BgrError {
     /// Model-related error (not found, download failed, etc.)
     #[error("{0}")]
     Model(#[from] crate::models::ModelError),
+    /// An input exceeded one of the configured [`InputLimits`](crate::InputLimits).
+    #[error("Input {what} {actual} exceeds the limit of {limit}")]
+    LimitExceeded {
+        what: &'static str,
+        actual: u64,
+        limit: u64,
+    },
+    /// The input's contents are an image format this build cannot decode.
+    #[error("Unsupported input format {format}: {hint}")]
+    UnsupportedFormat {
+        format: &'static str,
+        hint: &'static str,
+    },
+    /// The daemon could not be reached or failed to process a request.
+    #[error("Daemon request failed: {0}")]
+    Daemon(String),
+    /// The sandboxed decoder process failed, timed out, or sent a malformed response.
+    #[error("Sandboxed decoding failed: {0}")]
+    Sandbox(String),
+    /// A video could not be decoded or encoded.
+    #[error("Video processing failed: {0}")]
+    Video(String),
+    /// One or more inputs of a batch failed; each failure has already been reported.
+    #[error("{failed} of {total} inputs failed")]
+    Batch { failed: usize, total: usize },
+    /// A model missed the ideal cutout of some golden fixtures, or gave different mattes for
+    /// the same input.
+    #[error("{failed} of {total} fixtures failed")]
+    FixturesFailed { failed: usize, total: usize },
+    /// A [`CancellationToken`](crate::CancellationToken) stopped the operation.
+    #[error("Operation cancelled")]
+    Cancelled,
+}
+
+impl BgrError {
+    /// An [`Io`](Self::Io) error of kind `InvalidInput`, for options or arguments that cannot
+    /// be used, or used together.
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            message.into(),
+        ))
+    }
+
+    /// An [`Io`](Self::Io) error of kind `InvalidData`, for files and streams whose contents
+    /// are malformed.
+    pub fn invalid_data(message: impl Into<String>) -> Self {
+        Self::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            message.into(),
+        ))
+    }
+
+    /// Whether the error means the operation was cancelled, including a cancelled download.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(
+            self,
+            BgrError::Cancelled | BgrError::Model(crate::models::ModelError::Cancelled)
+        )
+    }
 }
@@ -0,0 +1,351 @@
+/// A copy-pasteable recipe shown by `bgr examples` and in the `--help` of its subcommand.
+pub struct Example {
+    /// What the recipe does.
+    pub title: &'static str,
+    /// The arguments after `bgr`; the first is the subcommand the recipe belongs to.
+    pub args: &'static [&'static str],
+}
+
+impl Example {
+    /// The subcommand this recipe is listed under.
+    pub fn command(&self) -> &'static str {
+        self.args[0]
+    }
+
+    /// The command line, quoted for a POSIX shell.
+    pub fn command_line(&self) -> String {
+        command_line(self.args)
+    }
+}
+
+/// `bgr` followed by `args`, quoted for a POSIX shell.
+pub fn command_line(args: &[impl AsRef<str>]) -> String {
+    let mut line = String::from("bgr");
+    for arg in args {
+        line.push(' ');
+        line.push_str(&shell_quote(arg.as_ref()));
+    }
+    line
+}
+
+/// Every recipe, grouped by subcommand.
+pub fn all() -> Vec<Example> {
+    #[allow(unused_mut)]
+    let mut examples = vec![
+        Example {
+            title: "Cut out a single photo",
+            args: &["cut", "photo.jpg"],
+        },
+        Example {
+            title: "Cut out every image in a directory with four sessions",
+            args: &["cut", "photos/", "--out-dir", "cutouts/", "-j", "4"],
+        },
+        Example {
+            title: "Replace the background with white for product shots",
+            args: &["cut", "product.jpg", "--bg-color", "#ffffff"],
+        },
+        Example {
+            title: "Replace the background with another photo",
+            args: &["cut", "portrait.jpg", "--bg-image", "beach.jpg"],
+        },
+        Example {
+            title: "Cut out an animated GIF into a transparent animated PNG",
+            args: &["cut", "party.gif", "-o", "party.png"],
+        },
+        Example {
+            title: "Apply a mask made in another tool instead of running the model",
+            args: &["cut", "photo.jpg", "--mask", "photo-mask.png"],
+        },
+        Example {
+            title: "Save the matte and processed mask next to the cutout",
+            args: &["cut", "photo.jpg", "--export-matte", "--export-mask"],
+        },
+        Example {
+            title: "Export a clean binary mask of the largest subject",
+            args: &["mask", "photo.jpg", "--keep-largest", "--fill-holes"],
+        },
+        Example {
+            title: "Export the soft matte for compositing",
+            args: &["mask", "photo.jpg", "--mask-source", "raw"],
+        },
+        Example {
+            title: "Trace a die-cut sticker outline as SVG",
+            args: &[
+                "trace",
+                "pet.jpg",
+                "--profile",
+                "sticker",
+                "--keep-largest",
+                "--dilate",
+                "12",
+            ],
+        },
+        Example {
+            title: "Trace an existing black-and-white mask",
+            args: &["trace", "logo-mask.png", "--from-mask"],
+        },
+        Example {
+            title: "Trace a cut line in millimetres at 300 DPI",
+            args: &[
+                "trace",
+                "logo.png",
+                "--profile",
+                "dieline",
+                "--coords",
+                "physical",
+                "--dpi",
+                "300",
+            ],
+        },
+        Example {
+            title: "Cut out new images as they land in a folder",
+            args: &["watch", "inbox/", "-o", "cutouts/"],
+        },
+        Example {
+            title: "Ask which preset suits portraits when speed matters",
+            args: &["models", "advise", "--for", "portrait", "--prefer", "speed"],
+        },
+        Example {
+            title: "Download every model preset ahead of time",
+            args: &["models", "pull", "--all"],
+        },
+        Example {
+            title: "Total the runs recorded with --usage-log per team tag",
+            args: &["usage", "report", "--by", "tag"],
+        },
+        Example {
+            title: "Make isnet the default model for every later run",
+            args: &["config", "set", "model", "isnet"],
+        },
+        Example {
+            title: "Check that CUDA inference works before a long run",
+            args: &["doctor", "--device", "cuda"],
+        },
+        Example {
+            title: "Print the commands a checked-in job file runs, without running them",
+            args: &["run", "assets/job.toml", "--dry-run"],
+        },
+        Example {
+            title: "Print the zsh completion script, to save as `_bgr` on your `$fpath`",
+            args: &["completions", "zsh"],
+        },
+        Example {
+            title: "Write a man page per subcommand for packaging",
+            args: &["manpage", "--dir", "share/man/man1"],
+        },
+        Example {
+            title: "Record golden fingerprints, then check later runs against them",
+            args: &["verify", "cutouts/", "--golden", "golden.json", "--update"],
+        },
+    ];
+    #[cfg(unix)]
+    examples.extend([
+        Example {
+            title: "Keep the model loaded between invocations",
+            args: &["daemon", "-j", "2"],
+        },
+        Example {
+            title: "Cut out a photo using a running daemon",
+            args: &["cut", "photo.jpg", "--via-daemon"],
+        },
+    ]);
+    #[cfg(feature = "server")]
+    examples.push(Example {
+        title: "Serve the HTTP API on all interfaces",
+        args: &["serve", "--bind", "0.0.0.0:8080", "-j", "4"],
+    });
+    #[cfg(feature = "test-utils")]
+    examples.push(Example {
+        title: "Check a custom model export before publishing it",
+        args: &[
+            "fixtures",
+            "generate",
+            "my-model.onnx",
+            "--out-dir",
+            "fixtures/",
+        ],
+    });
+    #[cfg(feature = "video")]
+    examples.push(Example {
+        title: "Remove the background from a video, keeping transparency",
+        args: &["video", "clip.mp4", "-o", "clip.webm"],
+    });
+    #[cfg(feature = "video")]
+    examples.push(Example {
+        title: "Cut out an image sequence with less edge flicker",
+        args: &[
+            "video",
+            "frames/%04d.png",
+            "-o",
+            "sequence.webm",
+            "--temporal-smooth",
+        ],
+    });
+    examples
+}
+
+/// The subcommands with at least one recipe, in registry order.
+pub fn commands() -> Vec<&'static str> {
+    let mut commands: Vec<&'static str> = Vec::new();
+    for example in all() {
+        if !commands.contains(&example.command()) {
+            commands.push(example.command());
+        }
+    }
+    commands
+}
+
+/// The recipes listed under `command`.
+pub fn for_command(command: &str) -> Vec<Example> {
+    all()
+        .into_iter()
+        .filter(|example| example.command() == command)
+        .collect()
+}
+
+/// The examples section appended to a subcommand's `--help`.
+pub fn epilog(command: &str) -> String {
+    let examples = for_command(command);
+    if examples.is_empty() {
+        return String::new();
+    }
+    let mut text = String::from("Examples:\n");
+    text.push_str(&render(&examples));
+    text.push_str("\nMore recipes: bgr examples");
+    text
+}
+
+/// Format recipes as a comment line followed by the command line, separated by blank lines.
+pub fn render(examples: &[Example]) -> String {
+    examples
+        .iter()
+        .map(|example| format!("  # {}\n  {}\n", example.title, example.command_line()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Quote `arg` for a POSIX shell when it contains anything beyond plain path characters.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands};
+    use clap::{CommandFactory, Parser};
+
+    mod all {
+        use super::*;
+
+        #[test]
+        fn every_example_parses() {
+            for example in all() {
+                let argv = std::iter::once("bgr").chain(example.args.iter().copied());
+                if let Err(err) = Cli::try_parse_from(argv) {
+                    panic!("`{}` does not parse: {err}", example.command_line());
+                }
+            }
+        }
+
+        #[test]
+        fn every_example_names_a_subcommand() {
+            let cli = Cli::command();
+            for example in all() {
+                assert!(
+                    cli.find_subcommand(example.command()).is_some(),
+                    "{} is not a subcommand",
+                    example.command()
+                );
+            }
+        }
+
+        #[test]
+        fn workflows_are_covered() {
+            let examples = all();
+            let has = |flag: &str| examples.iter().any(|example| example.args.contains(&flag));
+            assert!(has("--out-dir"), "batch cut");
+            assert!(has("--bg-color"), "background replacement");
+            assert!(has("sticker"), "SVG sticker");
+        }
+
+        #[test]
+        fn background_example_parses_the_color() {
+            let example = all()
+                .into_iter()
+                .find(|example| example.args.contains(&"#ffffff"))
+                .unwrap();
+            let cli =
+                Cli::try_parse_from(std::iter::once("bgr").chain(example.args.iter().copied()))
+                    .unwrap();
+            let Commands::Cut(cmd) = cli.command else {
+                panic!("expected cut");
+            };
+            assert_eq!(cmd.bg_color, Some(image::Rgb([255, 255, 255])));
+        }
+    }
+
+    mod commands {
+        use super::*;
+
+        #[test]
+        fn listed_once_in_registry_order() {
+            let commands = commands();
+            assert_eq!(&commands[..3], ["cut", "mask", "trace"]);
+            assert_eq!(commands.iter().filter(|&&name| name == "cut").count(), 1);
+        }
+    }
+
+    mod epilog {
+        use super::*;
+
+        #[test]
+        fn lists_only_the_commands_examples() {
+            let text = epilog("mask");
+            assert!(text.starts_with("Examples:\n"));
+            assert!(text.contains("bgr mask photo.jpg --mask-source raw"));
+            assert!(!text.contains("bgr cut"));
+        }
+
+        #[test]
+        fn empty_without_examples() {
+            assert_eq!(epilog("no-such-command"), "");
+        }
+
+        #[test]
+        fn shown_in_subcommand_help() {
+            let mut cli = Cli::command();
+            let help = cli
+                .find_subcommand_mut("cut")
+                .unwrap()
+                .render_help()
+                .to_string();
+            assert!(help.contains("bgr cut photo.jpg"), "{help}");
+        }
+    }
+
+    mod shell_quote {
+        use super::*;
+
+        #[test]
+        fn plain_arguments_stay_bare() {
+            assert_eq!(shell_quote("photos/"), "photos/");
+            assert_eq!(shell_quote("0.0.0.0:8080"), "0.0.0.0:8080");
+        }
+
+        #[test]
+        fn shell_syntax_is_quoted() {
+            assert_eq!(shell_quote("#ffffff"), "'#ffffff'");
+            assert_eq!(shell_quote("my photo.jpg"), "'my photo.jpg'");
+            assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        }
+    }
+}
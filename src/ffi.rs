@@ -0,0 +1,376 @@
+//! C bindings for the `bgr-ffi` feature, so C, C++, and Swift applications can embed
+//! background removal without spawning the CLI.
+//!
+//! Every function returns a [`BgrStatus`]; on failure, [`bgr_last_error`] describes what went
+//! wrong. Pixel buffers are owned by the caller, so nothing but the remover itself needs
+//! freeing. Generate the header with `cbindgen --config cbindgen.toml --output bgr.h`.
+//!
+//! ```c
+//! BgrRemover *remover = NULL;
+//! if (bgr_remover_new("u2net.onnx", &remover) != BGR_STATUS_OK) {
+//!     fprintf(stderr, "%s\n", bgr_last_error());
+//!     return 1;
+//! }
+//! uint8_t *cutout = malloc(width * height * 4);
+//! bgr_remove_rgba(remover, pixels, width, height, cutout);
+//! bgr_remover_free(remover);
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::{BgrError, Remover};
+
+/// The outcome of a call; anything but `Ok` leaves a message for [`bgr_last_error`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgrStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// An argument was malformed, such as a path that is not UTF-8 or a zero size.
+    InvalidArgument = 2,
+    /// The model could not be found, downloaded, or loaded.
+    Model = 3,
+    /// The image could not be decoded, encoded, or matched to its mask.
+    Image = 4,
+    /// The inference backend failed.
+    Inference = 5,
+    /// The input exceeded the remover's limits.
+    LimitExceeded = 6,
+    /// The operation was cancelled.
+    Cancelled = 7,
+    /// bgr panicked; the remover should not be used again.
+    Panic = 8,
+    /// Any other failure.
+    Other = 9,
+}
+
+impl From<&BgrError> for BgrStatus {
+    fn from(error: &BgrError) -> Self {
+        if error.is_cancelled() {
+            return BgrStatus::Cancelled;
+        }
+        match error {
+            BgrError::Model(_) | BgrError::BackendUnavailable(_) => BgrStatus::Model,
+            BgrError::Image(_)
+            | BgrError::UnsupportedFormat { .. }
+            | BgrError::AlphaMismatch { .. } => BgrStatus::Image,
+            #[cfg(feature = "backend-ort")]
+            BgrError::Ort(_) => BgrStatus::Inference,
+            #[cfg(feature = "backend-tract")]
+            BgrError::Tract(_) => BgrStatus::Inference,
+            #[cfg(feature = "backend-candle")]
+            BgrError::Candle(_) => BgrStatus::Inference,
+            BgrError::Shape(_) => BgrStatus::Inference,
+            BgrError::LimitExceeded { .. } => BgrStatus::LimitExceeded,
+            _ => BgrStatus::Other,
+        }
+    }
+}
+
+/// A loaded model, created by [`bgr_remover_new`] and released with [`bgr_remover_free`].
+///
+/// A remover is not thread-safe; use one per thread.
+pub struct BgrRemover {
+    remover: Remover,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Record `message` for [`bgr_last_error`] and return `status`.
+fn fail(status: BgrStatus, message: impl Display) -> BgrStatus {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// Record `error` and return its status.
+fn bgr_error(error: BgrError) -> BgrStatus {
+    fail(BgrStatus::from(&error), error)
+}
+
+/// Run `body`, turning a panic into [`BgrStatus::Panic`] instead of unwinding into C.
+fn guard(body: impl FnOnce() -> Result<(), BgrStatus>) -> BgrStatus {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => BgrStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => fail(BgrStatus::Panic, "bgr panicked"),
+    }
+}
+
+fn non_null<T>(pointer: *const T, name: &str) -> Result<(), BgrStatus> {
+    if pointer.is_null() {
+        return Err(fail(BgrStatus::NullPointer, format!("{name} is null")));
+    }
+    Ok(())
+}
+
+/// The byte length of a `width` × `height` image with `channels` bytes per pixel.
+fn buffer_len(width: u32, height: u32, channels: usize) -> Result<usize, BgrStatus> {
+    if width == 0 || height == 0 {
+        return Err(fail(
+            BgrStatus::InvalidArgument,
+            format!("image size {width}x{height} is empty"),
+        ));
+    }
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| fail(BgrStatus::InvalidArgument, "image size overflows"))
+}
+
+/// Store `remover` in `*out`.
+///
+/// # Safety
+/// `out` must be null or valid for writes.
+unsafe fn create(
+    out: *mut *mut BgrRemover,
+    build: impl FnOnce() -> Result<Remover, BgrStatus>,
+) -> BgrStatus {
+    guard(|| {
+        non_null(out, "out")?;
+        let remover = build()?;
+        // SAFETY: `out` is non-null and the caller guarantees it is writable.
+        unsafe { *out = Box::into_raw(Box::new(BgrRemover { remover })) };
+        Ok(())
+    })
+}
+
+/// Load the ONNX model at `model_path`, a NUL-terminated UTF-8 path, into `*out`.
+///
+/// # Safety
+/// `model_path` must be null or a NUL-terminated string, and `out` must be null or valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bgr_remover_new(
+    model_path: *const c_char,
+    out: *mut *mut BgrRemover,
+) -> BgrStatus {
+    // SAFETY: forwarded from the caller.
+    unsafe {
+        create(out, || {
+            non_null(model_path, "model_path")?;
+            // SAFETY: `model_path` is non-null and NUL-terminated.
+            let path = CStr::from_ptr(model_path)
+                .to_str()
+                .map_err(|e| fail(BgrStatus::InvalidArgument, e))?;
+            Remover::builder()
+                .model_path(Path::new(path))
+                .build()
+                .map_err(bgr_error)
+        })
+    }
+}
+
+/// Load an ONNX model from `len` bytes at `model` into `*out`; the bytes are copied.
+///
+/// # Safety
+/// `model` must be null or valid for reads of `len` bytes, and `out` must be null or valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bgr_remover_new_from_memory(
+    model: *const u8,
+    len: usize,
+    out: *mut *mut BgrRemover,
+) -> BgrStatus {
+    // SAFETY: forwarded from the caller.
+    unsafe {
+        create(out, || {
+            non_null(model, "model")?;
+            // SAFETY: the caller guarantees `len` readable bytes at `model`.
+            let bytes = std::slice::from_raw_parts(model, len).to_vec();
+            Remover::builder()
+                .model_bytes(bytes)
+                .build()
+                .map_err(bgr_error)
+        })
+    }
+}
+
+/// Cut the subject out of `width` × `height` RGBA pixels, writing the cutout's RGBA pixels to
+/// `out`. Input alpha is ignored.
+///
+/// # Safety
+/// `remover` must come from [`bgr_remover_new`] and not be freed; `pixels` must be valid for
+/// reads and `out` for writes of `width * height * 4` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bgr_remove_rgba(
+    remover: *mut BgrRemover,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    out: *mut u8,
+) -> BgrStatus {
+    guard(|| {
+        // SAFETY: forwarded from the caller.
+        let (remover, image) = unsafe { inputs(remover, pixels, width, height)? };
+        non_null(out, "out")?;
+        let cutout = remover.remover.remove(&image).map_err(bgr_error)?;
+        // SAFETY: the caller guarantees `out` holds as many bytes as the RGBA cutout.
+        unsafe { ptr::copy_nonoverlapping(cutout.as_ptr(), out, cutout.len()) };
+        Ok(())
+    })
+}
+
+/// The alpha mask of `width` × `height` RGBA pixels' subject, written to `out` as one byte per
+/// pixel.
+///
+/// # Safety
+/// `remover` must come from [`bgr_remover_new`] and not be freed; `pixels` must be valid for
+/// reads of `width * height * 4` bytes and `out` for writes of `width * height` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bgr_mask_rgba(
+    remover: *mut BgrRemover,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    out: *mut u8,
+) -> BgrStatus {
+    guard(|| {
+        // SAFETY: forwarded from the caller.
+        let (remover, image) = unsafe { inputs(remover, pixels, width, height)? };
+        non_null(out, "out")?;
+        let mask = remover.remover.mask(&image).map_err(bgr_error)?;
+        // SAFETY: the caller guarantees `out` holds one byte per pixel.
+        unsafe { ptr::copy_nonoverlapping(mask.as_ptr(), out, mask.len()) };
+        Ok(())
+    })
+}
+
+/// Check and borrow the remover and RGBA pixels of a removal call.
+///
+/// # Safety
+/// As for [`bgr_remove_rgba`].
+unsafe fn inputs<'a>(
+    remover: *mut BgrRemover,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+) -> Result<(&'a mut BgrRemover, DynamicImage), BgrStatus> {
+    non_null(remover, "remover")?;
+    non_null(pixels, "pixels")?;
+    let len = buffer_len(width, height, 4)?;
+    // SAFETY: both pointers are non-null and the caller guarantees their validity.
+    let (remover, pixels) = unsafe { (&mut *remover, std::slice::from_raw_parts(pixels, len)) };
+    let image = RgbaImage::from_raw(width, height, pixels.to_vec())
+        .expect("buffer length matches the image size");
+    Ok((remover, DynamicImage::ImageRgba8(image)))
+}
+
+/// Release a remover; null is ignored.
+///
+/// # Safety
+/// `remover` must be null or come from [`bgr_remover_new`], and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bgr_remover_free(remover: *mut BgrRemover) {
+    if !remover.is_null() {
+        // SAFETY: the caller passes ownership of a remover from `Box::into_raw`.
+        drop(unsafe { Box::from_raw(remover) });
+    }
+}
+
+/// A description of the last failure on this thread, as a NUL-terminated UTF-8 string.
+///
+/// The string is empty if nothing has failed, and stays valid until the next failing call on
+/// the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn bgr_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        // SAFETY: `bgr_last_error` always returns a NUL-terminated string.
+        unsafe { CStr::from_ptr(bgr_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    mod remover {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn null_arguments_rejected() {
+                let mut remover = ptr::null_mut();
+                let status = unsafe { bgr_remover_new(ptr::null(), &mut remover) };
+                assert_eq!(status, BgrStatus::NullPointer);
+                assert!(remover.is_null());
+                assert_eq!(last_error(), "model_path is null");
+
+                let mut out = [0u8; 4];
+                let status = unsafe {
+                    bgr_remove_rgba(ptr::null_mut(), out.as_ptr(), 1, 1, out.as_mut_ptr())
+                };
+                assert_eq!(status, BgrStatus::NullPointer);
+            }
+
+            #[test]
+            fn missing_model_is_a_model_error() {
+                let path = CString::new("/nonexistent/bgr-ffi.onnx").unwrap();
+                let mut remover = ptr::null_mut();
+                let status = unsafe { bgr_remover_new(path.as_ptr(), &mut remover) };
+                assert_ne!(status, BgrStatus::Ok);
+                assert!(remover.is_null());
+                assert!(!last_error().is_empty());
+            }
+
+            #[test]
+            fn free_ignores_null() {
+                unsafe { bgr_remover_free(ptr::null_mut()) };
+            }
+
+            #[test]
+            fn empty_images_rejected() {
+                assert!(buffer_len(0, 4, 4).is_err());
+                assert_eq!(buffer_len(3, 2, 4), Ok(24));
+            }
+        }
+
+        #[cfg(feature = "test-utils")]
+        mod integration {
+            use super::*;
+            use crate::testing::{fixtures, tiny_model};
+
+            #[test]
+            fn cutout_and_mask_fill_caller_buffers() {
+                let model = tiny_model();
+                let mut remover = ptr::null_mut();
+                let status = unsafe {
+                    bgr_remover_new_from_memory(model.as_ptr(), model.len(), &mut remover)
+                };
+                assert_eq!(status, BgrStatus::Ok, "{}", last_error());
+
+                let image = DynamicImage::ImageRgb8(fixtures().remove(0).image).to_rgba8();
+                let (width, height) = image.dimensions();
+                let mut cutout = vec![0u8; image.len()];
+                let mut mask = vec![0u8; (width * height) as usize];
+                let cut = unsafe {
+                    bgr_remove_rgba(remover, image.as_ptr(), width, height, cutout.as_mut_ptr())
+                };
+                let masked = unsafe {
+                    bgr_mask_rgba(remover, image.as_ptr(), width, height, mask.as_mut_ptr())
+                };
+                unsafe { bgr_remover_free(remover) };
+                assert_eq!(cut, BgrStatus::Ok, "{}", last_error());
+                assert_eq!(masked, BgrStatus::Ok, "{}", last_error());
+                assert!(mask.iter().any(|&value| value > 0));
+            }
+        }
+    }
+}
@@ -1,7 +1,20 @@
-use image::{GrayImage, RgbImage, Rgba, RgbaImage};
+use std::io::Cursor;
 
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, ImageFormat, Rgb, RgbImage, Rgba, Rgba32FImage, RgbaImage};
+
+use crate::color::{linear_to_srgb, srgb_to_linear};
 use crate::{BgrError, BgrResult};
 
+/// What to place behind a foreground when flattening it.
+#[derive(Debug, Clone)]
+pub enum Background {
+    /// A solid color.
+    Color(Rgb<u8>),
+    /// An image, scaled to cover the foreground and center-cropped.
+    Image(RgbImage),
+}
+
 /// Compose an RGBA foreground image from an RGB image and a grayscale alpha matte.
 pub fn compose_foreground(rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaImage> {
     let expected = rgb.dimensions();
@@ -20,6 +33,121 @@ pub fn compose_foreground(rgb: &RgbImage, alpha: &GrayImage) -> BgrResult<RgbaIm
     Ok(rgba)
 }
 
+/// Flatten an RGBA foreground onto a background, producing an opaque RGB image.
+pub fn composite_over(foreground: &RgbaImage, background: &Background) -> RgbImage {
+    flatten(foreground, background, blend)
+}
+
+/// Flatten an RGBA foreground onto a background, blending in linear light.
+///
+/// Blending the sRGB-encoded values, as [`composite_over`] does, darkens partly transparent
+/// edges such as hair and feathered masks into a dark halo; blending the light they stand
+/// for keeps the brightness of both sides.
+pub fn composite_over_linear(foreground: &RgbaImage, background: &Background) -> RgbImage {
+    let linear: [f32; 256] = std::array::from_fn(|value| srgb_to_linear(value as f32 / 255.0));
+    flatten(foreground, background, |fg, bg| {
+        blend_linear(fg, bg, &linear)
+    })
+}
+
+fn flatten(
+    foreground: &RgbaImage,
+    background: &Background,
+    blend: impl Fn(Rgba<u8>, Rgb<u8>) -> Rgb<u8>,
+) -> RgbImage {
+    let (w, h) = foreground.dimensions();
+    match background {
+        Background::Color(color) => {
+            RgbImage::from_fn(w, h, |x, y| blend(*foreground.get_pixel(x, y), *color))
+        }
+        Background::Image(image) => {
+            let fitted = cover(image, w, h);
+            RgbImage::from_fn(w, h, |x, y| {
+                blend(*foreground.get_pixel(x, y), *fitted.get_pixel(x, y))
+            })
+        }
+    }
+}
+
+/// Scale `image` so it covers `width` × `height`, then crop the centre to that size.
+pub fn cover(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let (iw, ih) = image.dimensions();
+    if (iw, ih) == (width, height) {
+        return image.clone();
+    }
+    if iw == 0 || ih == 0 {
+        return RgbImage::new(width, height);
+    }
+    let scale = (f64::from(width) / f64::from(iw)).max(f64::from(height) / f64::from(ih));
+    let sw = ((f64::from(iw) * scale).round() as u32).max(width);
+    let sh = ((f64::from(ih) * scale).round() as u32).max(height);
+    let scaled = imageops::resize(image, sw, sh, FilterType::Triangle);
+    imageops::crop_imm(&scaled, (sw - width) / 2, (sh - height) / 2, width, height).to_image()
+}
+
+/// Blur what lies behind the subject of `foreground` into a backdrop for fake bokeh.
+///
+/// The blur is weighted by transparency, so the subject's colors do not smear into the
+/// backdrop as a halo; under large opaque subjects, where no background shows, the subject's
+/// own blurred colors fill in. `sigma` is the Gaussian standard deviation in pixels.
+pub fn blur_background(foreground: &RgbaImage, sigma: f32) -> RgbImage {
+    let (w, h) = foreground.dimensions();
+    if sigma <= 0.0 {
+        return DynamicImage::ImageRgba8(foreground.clone()).into_rgb8();
+    }
+    // Float images blur within [0, 1], so colors are normalized; a small floor keeps the
+    // weights from vanishing under the subject.
+    let weighted = Rgba32FImage::from_fn(w, h, |x, y| {
+        let pixel = foreground.get_pixel(x, y);
+        let weight = (1.0 - f32::from(pixel[3]) / 255.0).max(1e-3);
+        let channel = |c: usize| f32::from(pixel[c]) / 255.0 * weight;
+        Rgba([channel(0), channel(1), channel(2), weight])
+    });
+    let blurred = imageops::blur(&weighted, sigma);
+    RgbImage::from_fn(w, h, |x, y| {
+        let pixel = blurred.get_pixel(x, y);
+        let weight = pixel[3].max(f32::EPSILON);
+        Rgb(std::array::from_fn(|c| {
+            (pixel[c] / weight * 255.0).round().clamp(0.0, 255.0) as u8
+        }))
+    })
+}
+
+/// Encode `image` as `format` in memory.
+///
+/// Formats that cannot store the image's channels, such as JPEG for RGBA, fail instead of
+/// silently dropping the alpha channel.
+pub fn encode_image(image: DynamicImage, format: ImageFormat) -> BgrResult<Vec<u8>> {
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)?;
+    Ok(encoded.into_inner())
+}
+
+/// Alpha-blend one foreground pixel over an opaque background pixel.
+fn blend(fg: Rgba<u8>, bg: Rgb<u8>) -> Rgb<u8> {
+    let alpha = u32::from(fg[3]);
+    Rgb(std::array::from_fn(|i| {
+        ((u32::from(fg[i]) * alpha + u32::from(bg[i]) * (255 - alpha) + 127) / 255) as u8
+    }))
+}
+
+/// Alpha-blend one foreground pixel over an opaque background pixel in linear light, with
+/// `linear` the decoded value of each 8-bit level.
+fn blend_linear(fg: Rgba<u8>, bg: Rgb<u8>, linear: &[f32; 256]) -> Rgb<u8> {
+    match fg[3] {
+        0 => bg,
+        255 => Rgb([fg[0], fg[1], fg[2]]),
+        alpha => {
+            let alpha = f32::from(alpha) / 255.0;
+            Rgb(std::array::from_fn(|i| {
+                let mixed =
+                    linear[usize::from(fg[i])] * alpha + linear[usize::from(bg[i])] * (1.0 - alpha);
+                (linear_to_srgb(mixed) * 255.0).round().clamp(0.0, 255.0) as u8
+            }))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -229,4 +357,162 @@ mod tests {
             }
         }
     }
+
+    mod composite_over {
+        use super::*;
+
+        fn rgba_image(w: u32, h: u32, px: [u8; 4]) -> RgbaImage {
+            RgbaImage::from_pixel(w, h, Rgba(px))
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn opaque_foreground_wins() {
+                let fg = rgba_image(2, 2, [10, 20, 30, 255]);
+                let out = composite_over(&fg, &Background::Color(Rgb([255, 255, 255])));
+                assert!(out.pixels().all(|px| px.0 == [10, 20, 30]));
+            }
+
+            #[test]
+            fn transparent_foreground_shows_color() {
+                let fg = rgba_image(2, 2, [10, 20, 30, 0]);
+                let out = composite_over(&fg, &Background::Color(Rgb([1, 2, 3])));
+                assert!(out.pixels().all(|px| px.0 == [1, 2, 3]));
+            }
+
+            #[test]
+            fn half_alpha_blends() {
+                let fg = rgba_image(1, 1, [255, 0, 0, 128]);
+                let out = composite_over(&fg, &Background::Color(Rgb([0, 0, 255])));
+                assert_eq!(out.get_pixel(0, 0).0, [128, 0, 127]);
+            }
+
+            #[test]
+            fn linear_light_keeps_edges_bright() {
+                let fg = rgba_image(1, 1, [255, 255, 255, 128]);
+                let black = Background::Color(Rgb([0, 0, 0]));
+                assert_eq!(composite_over(&fg, &black).get_pixel(0, 0).0, [128; 3]);
+                assert_eq!(
+                    composite_over_linear(&fg, &black).get_pixel(0, 0).0,
+                    [188; 3]
+                );
+                let opaque = rgba_image(1, 1, [10, 20, 30, 255]);
+                assert_eq!(
+                    composite_over_linear(&opaque, &black).get_pixel(0, 0).0,
+                    [10, 20, 30]
+                );
+            }
+
+            #[test]
+            fn background_image_is_fitted() {
+                let fg = rgba_image(4, 2, [0, 0, 0, 0]);
+                let bg = rgb_image(10, 10, [7, 8, 9]);
+                let out = composite_over(&fg, &Background::Image(bg));
+                assert_eq!(out.dimensions(), (4, 2));
+                assert!(out.pixels().all(|px| px.0 == [7, 8, 9]));
+            }
+        }
+    }
+
+    mod encode_image {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn png_round_trip_keeps_alpha() {
+                let image = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 40]));
+                let encoded =
+                    encode_image(DynamicImage::ImageRgba8(image.clone()), ImageFormat::Png)
+                        .unwrap();
+                let decoded = image::load_from_memory(&encoded).unwrap();
+                assert_eq!(decoded.into_rgba8(), image);
+            }
+
+            #[test]
+            fn jpeg_rejects_alpha() {
+                let image = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+                assert!(encode_image(image, ImageFormat::Jpeg).is_err());
+            }
+        }
+    }
+
+    mod blur_background {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn subject_does_not_bleed_into_the_backdrop() {
+                // An opaque red subject on the left over a transparent green background.
+                let fg = RgbaImage::from_fn(40, 10, |x, _| {
+                    if x < 20 {
+                        Rgba([255, 0, 0, 255])
+                    } else {
+                        Rgba([0, 255, 0, 0])
+                    }
+                });
+                let backdrop = blur_background(&fg, 4.0);
+                assert_eq!(backdrop.dimensions(), (40, 10));
+                let edge = backdrop.get_pixel(21, 5);
+                assert!(edge[1] > 240 && edge[0] < 15, "{edge:?}");
+            }
+
+            #[test]
+            fn zero_sigma_keeps_the_colors() {
+                let fg = RgbaImage::from_pixel(3, 3, Rgba([10, 20, 30, 0]));
+                assert_eq!(blur_background(&fg, 0.0), rgb_image(3, 3, [10, 20, 30]));
+            }
+        }
+    }
+
+    mod cover {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn crops_centre_of_wider_image() {
+                // Left third red, middle green, right third blue.
+                let bg = RgbImage::from_fn(30, 10, |x, _| match x / 10 {
+                    0 => Rgb([255, 0, 0]),
+                    1 => Rgb([0, 255, 0]),
+                    _ => Rgb([0, 0, 255]),
+                });
+                let out = cover(&bg, 10, 10);
+                assert_eq!(out.dimensions(), (10, 10));
+                assert_eq!(out.get_pixel(5, 5).0, [0, 255, 0]);
+            }
+
+            #[test]
+            fn upscales_small_image() {
+                let bg = rgb_image(2, 3, [50, 60, 70]);
+                assert_eq!(cover(&bg, 40, 20).dimensions(), (40, 20));
+            }
+        }
+
+        mod prop {
+            use super::*;
+            use proptest::prelude::*;
+
+            proptest! {
+                /// cover: output always has the requested dimensions
+                #[test]
+                fn output_matches_target(
+                    iw in 1u32..40,
+                    ih in 1u32..40,
+                    w in 1u32..40,
+                    h in 1u32..40
+                ) {
+                    let bg = RgbImage::from_pixel(iw, ih, Rgb([1, 2, 3]));
+                    prop_assert_eq!(cover(&bg, w, h).dimensions(), (w, h));
+                }
+            }
+        }
+    }
 }
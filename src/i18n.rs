@@ -0,0 +1,276 @@
+use std::sync::OnceLock;
+
+use bgr::{BgrError, Edge, Warning};
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use unic_langid::LanguageIdentifier;
+
+/// Translations compiled into the binary. The first entry is the fallback for messages a
+/// locale does not translate; add a locale by adding its `locales/<tag>/bgr.ftl` here.
+const LOCALES: &[(&str, &str)] = &[
+    ("en-US", include_str!("../locales/en-US/bgr.ftl")),
+    ("es", include_str!("../locales/es/bgr.ftl")),
+];
+
+static LOCALIZER: OnceLock<Localizer> = OnceLock::new();
+
+/// Look up a localized message by id, with optional `name = value` arguments.
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+pub(crate) use tr;
+
+/// Select the message locale for this run: `--lang`/`BGR_LANG` when given, else the POSIX
+/// locale variables. Must be called before the first message is printed to take effect.
+pub fn init(requested: Option<&str>) {
+    let requested = requested.map(str::to_owned).or_else(system_locale);
+    let _ = LOCALIZER.set(Localizer::new(requested.as_deref()));
+}
+
+/// Format message `id` in the selected locale, falling back to English.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    LOCALIZER
+        .get_or_init(|| Localizer::new(system_locale().as_deref()))
+        .format(id, args)
+}
+
+/// A localized description of a per-input warning.
+pub fn warning(warning: &Warning) -> String {
+    match warning {
+        Warning::IccProfileIgnored => tr!("warning-icc-profile-ignored"),
+        Warning::OrientationApplied { exif } => tr!("warning-orientation-applied", exif = *exif),
+        Warning::SubjectTouchesEdge { edges } => {
+            let edges: Vec<String> = edges.iter().map(|&edge| edge_name(edge)).collect();
+            tr!("warning-subject-touches-edge", edges = edges.join(", "))
+        }
+        Warning::LowConfidence { confidence } => {
+            tr!(
+                "warning-low-confidence",
+                percent = (confidence * 100.0).round()
+            )
+        }
+        Warning::EmptyMatte => tr!("warning-empty-matte"),
+        Warning::FullMatte => tr!("warning-full-matte"),
+        Warning::InputKeptWhole => tr!("warning-input-kept-whole"),
+        Warning::OutputSkipped => tr!("warning-output-skipped"),
+        Warning::OutputExists { path } => {
+            tr!("warning-output-exists", path = path.display().to_string())
+        }
+        Warning::OutputRenamed { path } => {
+            tr!("warning-output-renamed", path = path.display().to_string())
+        }
+        Warning::AlreadyWritten { path } => {
+            tr!("warning-already-written", path = path.display().to_string())
+        }
+        Warning::MaskResized { width, height } => {
+            tr!("warning-mask-resized", width = *width, height = *height)
+        }
+        Warning::MaskBinarized => tr!("warning-mask-binarized"),
+        Warning::AlphaFlattened { color: [r, g, b] } => {
+            tr!(
+                "warning-alpha-flattened",
+                color = format!("#{r:02x}{g:02x}{b:02x}")
+            )
+        }
+    }
+}
+
+fn edge_name(edge: Edge) -> String {
+    match edge {
+        Edge::Top => tr!("edge-top"),
+        Edge::Bottom => tr!("edge-bottom"),
+        Edge::Left => tr!("edge-left"),
+        Edge::Right => tr!("edge-right"),
+    }
+}
+
+/// A localized error line, as printed when a run fails.
+pub fn error(err: &BgrError) -> String {
+    tr!("error", message = describe(err))
+}
+
+/// A localized description of `err`; errors without a translation keep their English text.
+pub fn describe(err: &BgrError) -> String {
+    match err {
+        BgrError::Batch { failed, total } => tr!("error-batch", failed = *failed, total = *total),
+        BgrError::FixturesFailed { failed, total } => {
+            tr!("error-fixtures", failed = *failed, total = *total)
+        }
+        err if err.is_cancelled() => tr!("error-cancelled"),
+        BgrError::LimitExceeded {
+            what,
+            actual,
+            limit,
+        } => tr!(
+            "error-limit-exceeded",
+            what = *what,
+            actual = *actual,
+            limit = *limit
+        ),
+        BgrError::UnsupportedFormat { format, hint } => {
+            tr!("error-unsupported-format", format = *format, hint = *hint)
+        }
+        other => other.to_string(),
+    }
+}
+
+/// The locale from `LC_ALL`, `LC_MESSAGES`, or `LANG`, in POSIX precedence order.
+fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// The compiled-in locale that best matches `requested`, such as `es` for `es_MX.UTF-8`.
+fn negotiate(requested: &str) -> Option<&'static str> {
+    let tag = requested.split(['.', '@']).next().unwrap_or_default();
+    let wanted: LanguageIdentifier = tag.replace('_', "-").parse().ok()?;
+    let available = || {
+        LOCALES.iter().map(|&(name, _)| {
+            let id: LanguageIdentifier = name.parse().expect("valid locale tag");
+            (name, id)
+        })
+    };
+    available()
+        .find(|(_, id)| *id == wanted)
+        .or_else(|| available().find(|(_, id)| id.language == wanted.language))
+        .map(|(name, _)| name)
+}
+
+/// The selected locale's messages, then the English fallback.
+struct Localizer {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Localizer {
+    fn new(requested: Option<&str>) -> Self {
+        let (fallback, _) = LOCALES[0];
+        let selected = requested.and_then(negotiate).unwrap_or(fallback);
+        let bundles = LOCALES
+            .iter()
+            .filter(|&&(name, _)| name == selected || name == fallback)
+            // The selected locale is consulted first.
+            .rev()
+            .map(|&(name, source)| bundle(name, source))
+            .collect();
+        Self { bundles }
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        for bundle in &self.bundles {
+            if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+                let mut errors = Vec::new();
+                return bundle
+                    .format_pattern(pattern, args, &mut errors)
+                    .into_owned();
+            }
+        }
+        id.to_owned()
+    }
+}
+
+fn bundle(name: &str, source: &str) -> FluentBundle<FluentResource> {
+    let id: LanguageIdentifier = name.parse().expect("valid locale tag");
+    let resource = FluentResource::try_new(source.to_owned()).expect("bundled messages parse");
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Unicode isolation marks around arguments show up as garbage in many terminals.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("bundled message ids are unique");
+    bundle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Message ids defined at the top level of a Fluent file.
+    fn ids(source: &str) -> Vec<&str> {
+        let mut ids: Vec<&str> = source
+            .lines()
+            .filter(|line| !line.starts_with([' ', '#']))
+            .filter_map(|line| line.split_once(" = ").map(|(id, _)| id))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    mod negotiate {
+        use super::*;
+
+        #[test]
+        fn posix_locales_match_by_language() {
+            assert_eq!(negotiate("es_MX.UTF-8"), Some("es"));
+            assert_eq!(negotiate("es"), Some("es"));
+            assert_eq!(negotiate("en_GB.UTF-8"), Some("en-US"));
+            assert_eq!(negotiate("en-US"), Some("en-US"));
+        }
+
+        #[test]
+        fn unknown_locales_have_no_match() {
+            assert_eq!(negotiate("C"), None);
+            assert_eq!(negotiate("POSIX"), None);
+            assert_eq!(negotiate("ja_JP.UTF-8"), None);
+        }
+    }
+
+    mod localizer {
+        use super::*;
+
+        #[test]
+        fn every_locale_translates_every_message() {
+            let (_, english) = LOCALES[0];
+            for &(name, source) in &LOCALES[1..] {
+                assert_eq!(
+                    ids(source),
+                    ids(english),
+                    "{name} is out of sync with en-US"
+                );
+            }
+        }
+
+        #[test]
+        fn formats_arguments_and_plurals() {
+            let english = Localizer::new(None);
+            let mut args = FluentArgs::new();
+            args.set("path", "/run/bgr.sock");
+            args.set("sessions", 1);
+            assert_eq!(
+                english.format("daemon-listening", Some(&args)),
+                "Daemon listening on /run/bgr.sock (1 session); use --via-daemon to connect"
+            );
+            args.set("sessions", 4);
+            assert!(
+                english
+                    .format("daemon-listening", Some(&args))
+                    .contains("(4 sessions)")
+            );
+        }
+
+        #[test]
+        fn selected_locale_used() {
+            let spanish = Localizer::new(Some("es_ES.UTF-8"));
+            assert_eq!(
+                spanish.format("download-complete", None),
+                "¡Descarga completada!"
+            );
+        }
+
+        #[test]
+        fn unknown_ids_are_returned_verbatim() {
+            assert_eq!(
+                Localizer::new(None).format("no-such-message", None),
+                "no-such-message"
+            );
+        }
+    }
+}
@@ -1,17 +1,28 @@
 use std::convert::TryFrom;
 use std::io;
 use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 use image::imageops::FilterType;
-use image::{DynamicImage, GrayImage, ImageBuffer, ImageDecoder, ImageReader, Luma, RgbImage};
+use image::metadata::Orientation;
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageDecoder, Luma, RgbImage};
 use ndarray::{Array2, Array4, ArrayViewD, Axis, Ix2};
-use ort::session::Session;
-use ort::session::builder::GraphOptimizationLevel;
-use ort::value::Tensor;
+// `std::time::Instant` panics in browsers.
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
-use crate::config::InferenceSettings;
+use crate::adapter::{ModelAdapter, Normalization};
+use crate::backend::{self, InferenceBackend};
+use crate::color::RenderingIntent;
+use crate::config::{Device, InferenceSettings};
+use crate::debug_bundle::TensorStats;
 use crate::error::BgrResult;
+use crate::limits::{InputLimits, limited_decoder, limited_decoder_from_bytes};
 use crate::mask::array_to_gray_image;
+use crate::progress::{ProgressSink, Stage};
+use crate::telemetry::{InferenceEnd, InferenceStart, ModelLoad, Telemetry};
+use crate::warnings::Warning;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChannelLayout {
@@ -26,85 +37,116 @@ pub struct ModelInputSpec {
     pub layout: ChannelLayout,
 }
 
+impl ModelInputSpec {
+    /// The shape of the input tensor for one image.
+    pub fn shape(&self) -> Vec<usize> {
+        match self.layout {
+            ChannelLayout::Nchw => vec![1, 3, self.height, self.width],
+            ChannelLayout::Nhwc => vec![1, self.height, self.width, 3],
+        }
+    }
+}
+
 pub const DEFAULT_MODEL_INPUT_SPEC: ModelInputSpec = ModelInputSpec {
     width: 320,
     height: 320,
     layout: ChannelLayout::Nchw,
 };
 
-/// Try to figure out the model input spec from the session and falls back to the default.
-pub fn determine_model_input_spec(session: &Session) -> ModelInputSpec {
-    infer_model_input_spec(session).unwrap_or(DEFAULT_MODEL_INPUT_SPEC)
+/// A decoded input and the warnings noticed while decoding it.
+pub struct DecodedImage {
+    pub rgb: RgbImage,
+    pub warnings: Vec<Warning>,
 }
 
-/// Infer the model input spec from the ONNX session input tensor shape.
-fn infer_model_input_spec(session: &Session) -> Option<ModelInputSpec> {
-    let input = session.inputs.first()?;
-    let shape = input.input_type.tensor_shape()?;
-    let dims: &[i64] = shape;
+/// Load the input image for `settings`, in the sandboxed decoder when one is configured.
+pub fn load_input(path: &Path, settings: &InferenceSettings) -> BgrResult<DecodedImage> {
+    settings.progress.enter(Stage::Decode);
+    let _span = tracing::debug_span!("decode", path = %path.display()).entered();
+    #[cfg(feature = "sandboxed-decode")]
+    if let Some(sandbox) = &settings.sandbox {
+        let (rgb, warnings) = sandbox.decode(
+            path,
+            &settings.limits,
+            settings.intent,
+            settings.auto_orient,
+        )?;
+        return Ok(DecodedImage { rgb, warnings });
+    }
+    decode_rgb_with_orientation(
+        limited_decoder(path, &settings.limits)?,
+        settings.intent,
+        settings.auto_orient,
+    )
+}
 
-    if dims.len() >= 4 {
-        if let Some(spec) = infer_nchw_spec(dims) {
-            return Some(spec);
-        }
-        if let Some(spec) = infer_nhwc_spec(dims) {
-            return Some(spec);
-        }
+/// Decode an in-memory input for `settings`, in the sandboxed decoder when one is configured.
+pub fn load_input_bytes(encoded: &[u8], settings: &InferenceSettings) -> BgrResult<DecodedImage> {
+    settings.progress.enter(Stage::Decode);
+    let _span = tracing::debug_span!("decode", bytes = encoded.len()).entered();
+    #[cfg(feature = "sandboxed-decode")]
+    if let Some(sandbox) = &settings.sandbox {
+        let (rgb, warnings) = sandbox.decode_bytes(
+            encoded,
+            &settings.limits,
+            settings.intent,
+            settings.auto_orient,
+        )?;
+        return Ok(DecodedImage { rgb, warnings });
     }
+    decode_rgb_with_orientation(
+        limited_decoder_from_bytes(encoded, &settings.limits)?,
+        settings.intent,
+        settings.auto_orient,
+    )
+}
 
-    None
+/// Load an RGB image from the given path within `limits`, applying orientation from EXIF data
+/// and converting an embedded color profile to sRGB.
+pub fn load_rgb_with_orientation(path: &Path, limits: &InputLimits) -> BgrResult<DecodedImage> {
+    decode_rgb_with_orientation(
+        limited_decoder(path, limits)?,
+        RenderingIntent::default(),
+        true,
+    )
 }
 
-/// Check for an NCHW layout and returns a matching spec when dimensions line up.
-fn infer_nchw_spec(dims: &[i64]) -> Option<ModelInputSpec> {
-    let channels = *dims.get(1)?;
-    if channels != 3 && channels != -1 {
-        return None;
-    }
-    let height = *dims.get(2)?;
-    let width = *dims.get(3)?;
-    let height = positive_dim_to_usize(height)?;
-    let width = positive_dim_to_usize(width)?;
-    Some(ModelInputSpec {
-        width,
-        height,
-        layout: ChannelLayout::Nchw,
-    })
-}
-
-/// Check for an NHWC layout and returns a matching spec when dimensions line up.
-fn infer_nhwc_spec(dims: &[i64]) -> Option<ModelInputSpec> {
-    let channels = *dims.get(3)?;
-    if channels != 3 && channels != -1 {
-        return None;
-    }
-    let height = *dims.get(1)?;
-    let width = *dims.get(2)?;
-    let height = positive_dim_to_usize(height)?;
-    let width = positive_dim_to_usize(width)?;
-    Some(ModelInputSpec {
-        width,
-        height,
-        layout: ChannelLayout::Nhwc,
-    })
-}
-
-/// Convert a positive i64 dimension to usize, returning None for non-positive or overflow.
-fn positive_dim_to_usize(dim: i64) -> Option<usize> {
-    if dim > 0 {
-        usize::try_from(dim).ok()
+/// Decode to sRGB, applying orientation from EXIF data when `auto_orient` is set and
+/// converting from an embedded color profile with `intent`.
+pub(crate) fn decode_rgb_with_orientation(
+    mut decoder: impl ImageDecoder,
+    intent: RenderingIntent,
+    auto_orient: bool,
+) -> BgrResult<DecodedImage> {
+    let mut warnings = Vec::new();
+    let profile = decoder.icc_profile().ok().flatten();
+    let orientation = if auto_orient {
+        decoder.orientation()?
     } else {
-        None
+        Orientation::NoTransforms
+    };
+    if orientation != Orientation::NoTransforms {
+        warnings.push(Warning::OrientationApplied {
+            exif: orientation.to_exif(),
+        });
     }
-}
-
-/// Load an RGB image from the given path, applying orientation from EXIF data.
-fn load_rgb_with_orientation(path: &Path) -> BgrResult<RgbImage> {
-    let mut decoder = ImageReader::open(path)?.into_decoder()?;
-    let orientation = decoder.orientation()?;
     let mut image = DynamicImage::from_decoder(decoder)?;
     image.apply_orientation(orientation);
-    Ok(image.into_rgb8())
+    let mut rgb = image.into_rgb8();
+    if let Some(profile) = profile {
+        // Models expect sRGB; a profile that cannot be read leaves the pixels as they are.
+        #[cfg(feature = "color-management")]
+        let applied = crate::color::to_working_space(&mut rgb, &profile, intent).is_ok();
+        #[cfg(not(feature = "color-management"))]
+        let applied = {
+            let _ = (profile, intent);
+            false
+        };
+        if !applied {
+            warnings.push(Warning::IccProfileIgnored);
+        }
+    }
+    Ok(DecodedImage { rgb, warnings })
 }
 
 /// Resize and normalizes the RGB image into a tensor that matches the model spec.
@@ -112,7 +154,8 @@ pub fn preprocess_image_to_tensor(
     rgb: &RgbImage,
     filter: FilterType,
     spec: ModelInputSpec,
-) -> BgrResult<Tensor<f32>> {
+    normalization: Normalization,
+) -> BgrResult<Array4<f32>> {
     let target_w = u32::try_from(spec.width).map_err(|_| {
         io::Error::new(
             io::ErrorKind::InvalidInput,
@@ -129,8 +172,7 @@ pub fn preprocess_image_to_tensor(
     let resized = image::imageops::resize(rgb, target_w, target_h, filter);
     let w = resized.width() as usize;
     let h = resized.height() as usize;
-    let mean = [0.485f32, 0.456, 0.406];
-    let std = [0.229f32, 0.224, 0.225];
+    let Normalization { mean, std } = normalization;
     let inv255 = 1.0 / 255.0;
 
     let (shape, data) = match spec.layout {
@@ -163,8 +205,7 @@ pub fn preprocess_image_to_tensor(
         }
     };
 
-    let array = Array4::from_shape_vec(shape, data)?;
-    Ok(Tensor::from_array(array)?)
+    Ok(Array4::from_shape_vec(shape, data)?)
 }
 
 /// Remove singleton axes to get the raw H×W matte from the model output.
@@ -211,30 +252,187 @@ pub fn resize_matte(
     Ok(out)
 }
 
-/// Run the full matte inference pipeline and return the RGB image and raw matte.
+/// Resample the matte at 8-bit precision.
+///
+/// Quantizes at model resolution before resizing, so no full-resolution `f32` buffers are
+/// allocated. Used by the low-memory mode.
+pub fn resize_matte_u8(
+    matte: &Array2<f32>,
+    target_w: u32,
+    target_h: u32,
+    filter: FilterType,
+) -> GrayImage {
+    let quantized = array_to_gray_image(matte);
+    image::imageops::resize(&quantized, target_w, target_h, filter)
+}
+
+/// A loaded model, on the inference backend selected in its settings.
+///
+/// Building a session dominates the cost of a single inference, so batch callers keep one
+/// `ModelSession` per worker and reuse it across images.
+pub struct ModelSession {
+    backend: Box<dyn InferenceBackend>,
+}
+
+impl ModelSession {
+    /// Build a session for the model configured in `settings`.
+    ///
+    /// With ONNX Runtime, each candidate accelerator for [`InferenceSettings::device`] is tried
+    /// in turn; if none initializes, the session runs on the CPU. Check
+    /// [`device`](Self::device) for the outcome.
+    pub fn load(settings: &InferenceSettings) -> BgrResult<Self> {
+        let _span =
+            tracing::debug_span!("load_model", path = %settings.model_path.display()).entered();
+        let started = Instant::now();
+        let backend = backend::load(settings)?;
+        let duration = started.elapsed();
+        tracing::info!(device = ?backend.device(), ?duration, "model loaded");
+        settings.telemetry.on_model_load(&ModelLoad {
+            model_path: settings.model_path.clone(),
+            device: backend.device(),
+            duration,
+        });
+        Ok(Self { backend })
+    }
+
+    /// The device this session actually runs on.
+    pub fn device(&self) -> Device {
+        self.backend.device()
+    }
+
+    /// Statistics of the tensor `rgb` is preprocessed into for this model, as a
+    /// [`DebugBundle`](crate::DebugBundle) records them.
+    pub fn tensor_stats(
+        &self,
+        rgb: &RgbImage,
+        settings: &InferenceSettings,
+    ) -> BgrResult<TensorStats> {
+        let input_spec = self.backend.input_spec();
+        let tensor = preprocess_image_to_tensor(
+            rgb,
+            settings.input_resize_filter,
+            input_spec,
+            settings.adapter.normalization(),
+        )?;
+        Ok(TensorStats::of(&tensor, input_spec.layout))
+    }
+
+    /// Run the model on an RGB image and return the H×W matte at model resolution.
+    pub fn predict(
+        &mut self,
+        rgb: &RgbImage,
+        settings: &InferenceSettings,
+    ) -> BgrResult<Array2<f32>> {
+        settings.progress.enter(Stage::Preprocess);
+        let started = Instant::now();
+        let input_spec = self.backend.input_spec();
+        let input_tensor = tracing::debug_span!("preprocess").in_scope(|| {
+            preprocess_image_to_tensor(
+                rgb,
+                settings.input_resize_filter,
+                input_spec,
+                settings.adapter.normalization(),
+            )
+        })?;
+        let preprocess = started.elapsed();
+        settings.cancellation.check()?;
+
+        settings.progress.enter(Stage::Inference);
+        let image_size = rgb.dimensions();
+        let input_shape = input_spec.shape();
+        let device = self.backend.device();
+        settings.telemetry.on_inference_start(&InferenceStart {
+            image_size,
+            input_shape: input_shape.clone(),
+            device,
+        });
+        let started = Instant::now();
+        let outputs = tracing::debug_span!("inference", ?device, ?input_shape)
+            .in_scope(|| self.backend.run(input_tensor))?;
+        let inference = started.elapsed();
+        settings.telemetry.on_inference_end(&InferenceEnd {
+            image_size,
+            input_shape,
+            output_shape: outputs
+                .first()
+                .map(|output| output.shape().to_vec())
+                .unwrap_or_default(),
+            device,
+            preprocess,
+            inference,
+        });
+        settings.adapter.matte(outputs)
+    }
+
+    /// Run the model and return the matte resized to the dimensions of `rgb`.
+    pub fn infer_matte(
+        &mut self,
+        rgb: &RgbImage,
+        settings: &InferenceSettings,
+    ) -> BgrResult<GrayImage> {
+        settings.cancellation.check()?;
+        let matte_hw = self.predict(rgb, settings)?;
+        settings.cancellation.check()?;
+        upscale_matte(&matte_hw, rgb.width(), rgb.height(), settings)
+    }
+
+    /// Run the model and return the matte resized to the dimensions of `rgb` without
+    /// quantizing it, even in low-memory mode.
+    pub fn infer_matte_precise(
+        &mut self,
+        rgb: &RgbImage,
+        settings: &InferenceSettings,
+    ) -> BgrResult<Array2<f32>> {
+        settings.cancellation.check()?;
+        let matte_hw = self.predict(rgb, settings)?;
+        settings.cancellation.check()?;
+        settings.progress.enter(Stage::Postprocess);
+        let _span = tracing::debug_span!("postprocess").entered();
+        resize_matte(
+            &matte_hw,
+            rgb.width(),
+            rgb.height(),
+            settings.output_resize_filter,
+        )
+    }
+}
+
+/// Resize a model-resolution matte to the original image size and quantize it to 8 bits.
+fn upscale_matte(
+    matte_hw: &Array2<f32>,
+    width: u32,
+    height: u32,
+    settings: &InferenceSettings,
+) -> BgrResult<GrayImage> {
+    settings.progress.enter(Stage::Postprocess);
+    let _span = tracing::debug_span!("postprocess").entered();
+    if settings.low_memory {
+        return Ok(resize_matte_u8(
+            matte_hw,
+            width,
+            height,
+            settings.output_resize_filter,
+        ));
+    }
+    let matte_orig = resize_matte(matte_hw, width, height, settings.output_resize_filter)?;
+    Ok(array_to_gray_image(&matte_orig))
+}
+
+/// Run the full matte inference pipeline and return the decoded image and raw matte.
 pub fn run_matte_pipeline(
     settings: &InferenceSettings,
     image_path: &Path,
-) -> BgrResult<(RgbImage, GrayImage)> {
-    let mut builder =
-        Session::builder()?.with_optimization_level(GraphOptimizationLevel::Level3)?;
-    if let Some(n) = settings.intra_threads {
-        builder = builder.with_intra_threads(n)?;
-    }
-    let mut session = builder.commit_from_file(&settings.model_path)?;
-
-    let rgb_input = load_rgb_with_orientation(image_path)?;
-    let orig_w = rgb_input.width();
-    let orig_h = rgb_input.height();
-
-    let input_spec = determine_model_input_spec(&session);
-    let input_tensor =
-        preprocess_image_to_tensor(&rgb_input, settings.input_resize_filter, input_spec)?;
-    let outputs = session.run(ort::inputs![input_tensor])?;
-    let matte = outputs[0].try_extract_array::<f32>()?;
-    let matte_hw = extract_matte_hw(matte)?;
-    let matte_orig = resize_matte(&matte_hw, orig_w, orig_h, settings.output_resize_filter)?;
-    let raw_matte = array_to_gray_image(&matte_orig);
-
-    Ok((rgb_input, raw_matte))
+) -> BgrResult<(DecodedImage, GrayImage)> {
+    let mut session = ModelSession::load(settings)?;
+    settings.cancellation.check()?;
+    let decoded = load_input(image_path, settings)?;
+    settings.cancellation.check()?;
+    let rgb_input = &decoded.rgb;
+    let matte_hw = session.predict(rgb_input, settings)?;
+    // Release the session before allocating full-resolution buffers.
+    drop(session);
+    settings.cancellation.check()?;
+
+    let raw_matte = upscale_matte(&matte_hw, rgb_input.width(), rgb_input.height(), settings)?;
+    Ok((decoded, raw_matte))
 }
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::output_template::{Piece, tokenize};
+
+/// The subcommands a job step can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepCommand {
+    Cut,
+    Mask,
+    Trace,
+}
+
+impl StepCommand {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Cut => "cut",
+            Self::Mask => "mask",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+/// A reproducible batch recipe for `bgr run`, read from TOML or YAML: the inputs, the model,
+/// and steps that each run one subcommand over them.
+///
+/// Every string in `inputs`, `out_dir`, and `args` may name a variable as `{name}`; `{{` and
+/// `}}` stand for literal braces.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobFile {
+    /// Values for `{name}` placeholders, overridden by `bgr run --var`.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    /// Input images and directories.
+    pub inputs: Vec<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub jobs: Option<usize>,
+    /// Refinement for every step that does not name its own.
+    pub refine: Option<String>,
+    /// Flags passed to every step.
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub steps: Vec<JobStep>,
+}
+
+/// One subcommand run over the job's inputs.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobStep {
+    pub command: StepCommand,
+    /// Inputs for this step in place of the job's.
+    pub inputs: Option<Vec<String>>,
+    pub out_dir: Option<String>,
+    /// `cut --emit` artifacts, all written from the one inference per input.
+    #[serde(default)]
+    pub emit: Vec<String>,
+    pub refine: Option<String>,
+    /// Flags passed to this step after the job's.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl JobFile {
+    /// Read a job file, as YAML for `.yaml` and `.yml` files and as TOML otherwise.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        let parsed = if yaml {
+            serde_yaml_ng::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.to_string())
+        };
+        parsed.map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", path.display()),
+            )
+        })
+    }
+
+    /// The arguments after `bgr` for each step, with `overrides` taking precedence over the
+    /// job's variables.
+    pub fn step_args(&self, overrides: &BTreeMap<String, String>) -> io::Result<Vec<Vec<String>>> {
+        let mut vars = self.vars.clone();
+        vars.extend(overrides.clone());
+        let expand_all = |values: &[String]| -> io::Result<Vec<String>> {
+            values.iter().map(|value| expand(value, &vars)).collect()
+        };
+        self.steps
+            .iter()
+            .map(|step| {
+                let mut args = vec![step.command.name().to_string()];
+                args.extend(expand_all(step.inputs.as_deref().unwrap_or(&self.inputs))?);
+                if let Some(model) = &self.model {
+                    args.extend(["--model".to_string(), model.clone()]);
+                }
+                if let Some(device) = &self.device {
+                    args.extend(["--device".to_string(), device.clone()]);
+                }
+                if let Some(jobs) = self.jobs {
+                    args.extend(["--jobs".to_string(), jobs.to_string()]);
+                }
+                if let Some(refine) = step.refine.as_ref().or(self.refine.as_ref()) {
+                    args.extend(["--refine".to_string(), refine.clone()]);
+                }
+                if let Some(out_dir) = &step.out_dir {
+                    args.extend(["--out-dir".to_string(), expand(out_dir, &vars)?]);
+                }
+                if !step.emit.is_empty() {
+                    args.extend(["--emit".to_string(), step.emit.join(",")]);
+                }
+                args.extend(expand_all(&self.args)?);
+                args.extend(expand_all(&step.args)?);
+                Ok(args)
+            })
+            .collect()
+    }
+}
+
+/// `template` with every `{name}` replaced by its value in `vars`.
+fn expand(template: &str, vars: &BTreeMap<String, String>) -> io::Result<String> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut expanded = String::with_capacity(template.len());
+    for piece in tokenize(template).map_err(invalid)? {
+        match piece {
+            Piece::Text(text) => expanded.push_str(&text),
+            Piece::Placeholder(name) => expanded.push_str(vars.get(name).ok_or_else(|| {
+                invalid(format!("`{template}` uses the undefined variable {name}"))
+            })?),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Split a `--var` argument into its name and value.
+pub fn parse_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got `{value}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::Parser;
+
+    const JOB: &str = r##"
+inputs = ["{assets}/"]
+model = "isnet"
+refine = "matting"
+args = ["--order", "size"]
+
+[vars]
+assets = "products"
+out = "build"
+
+[[steps]]
+command = "cut"
+out_dir = "{out}/white"
+emit = ["cut", "svg", "json"]
+args = ["--bg-color", "#ffffff"]
+
+[[steps]]
+command = "mask"
+out_dir = "{out}/masks"
+"##;
+
+    mod job_file {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn steps_become_command_lines() {
+                let job: JobFile = toml::from_str(JOB).unwrap();
+                let overrides = BTreeMap::from([("out".to_string(), "dist".to_string())]);
+                let steps = job.step_args(&overrides).unwrap();
+                assert_eq!(
+                    steps[0],
+                    [
+                        "cut",
+                        "products/",
+                        "--model",
+                        "isnet",
+                        "--refine",
+                        "matting",
+                        "--out-dir",
+                        "dist/white",
+                        "--emit",
+                        "cut,svg,json",
+                        "--order",
+                        "size",
+                        "--bg-color",
+                        "#ffffff",
+                    ]
+                );
+                assert_eq!(
+                    steps[1][..6],
+                    [
+                        "mask",
+                        "products/",
+                        "--model",
+                        "isnet",
+                        "--refine",
+                        "matting"
+                    ]
+                );
+                for args in steps {
+                    let argv = std::iter::once("bgr".to_string()).chain(args);
+                    assert!(Cli::try_parse_from(argv).is_ok());
+                }
+            }
+
+            #[test]
+            fn yaml_and_toml_jobs_match() {
+                let yaml = "inputs: [a.jpg]\nsteps:\n  - command: trace\n    args: [--quiet]\n";
+                let job: JobFile = serde_yaml_ng::from_str(yaml).unwrap();
+                assert_eq!(
+                    job.step_args(&BTreeMap::new()).unwrap(),
+                    [["trace", "a.jpg", "--quiet"]]
+                );
+                assert!(toml::from_str::<JobFile>("inputs = []\nstep = []\n").is_err());
+            }
+        }
+    }
+
+    mod expand {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn substitutes_and_escapes() {
+                let vars = BTreeMap::from([("name".to_string(), "shoe".to_string())]);
+                assert_eq!(expand("out/{name}.png", &vars).unwrap(), "out/shoe.png");
+                assert_eq!(expand("{{name}}", &vars).unwrap(), "{name}");
+                assert!(expand("{size}", &vars).is_err());
+                assert!(expand("{name", &vars).is_err());
+                assert!(expand("name}", &vars).is_err());
+            }
+        }
+    }
+
+    mod parse_var {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn splits_at_the_first_equals_sign() {
+                assert_eq!(
+                    parse_var("out=a=b").unwrap(),
+                    ("out".to_string(), "a=b".to_string())
+                );
+                assert!(parse_var("out").is_err());
+                assert!(parse_var("=x").is_err());
+            }
+        }
+    }
+}
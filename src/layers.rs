@@ -0,0 +1,387 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::Path;
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage,
+};
+use image::{GrayImage, RgbImage, RgbaImage};
+use tiff::encoder::{TiffEncoder, colortype};
+use tiff::tags::Tag;
+
+use crate::color::srgb_to_linear;
+use crate::mask::subject_labels;
+use crate::matting::{MattingOptions, trimap};
+use crate::{BgrError, BgrResult};
+
+/// A cutout with its raw matte and trimap, for writing as one multi-channel file.
+///
+/// Compositing applications read the extra layers as channels (EXR) or pages (TIFF), so the
+/// matte and trimap travel with the cutout instead of as sidecar files.
+#[derive(Debug, Clone)]
+pub struct OutputLayers {
+    /// The cutout, with the alpha used for the foreground.
+    pub rgba: RgbaImage,
+    /// The model's soft matte, before any processing.
+    pub matte: GrayImage,
+    /// Sure foreground, sure background, and unknown band derived from the matte.
+    pub trimap: GrayImage,
+}
+
+/// Alpha at or above which a pixel belongs to a subject rather than to its soft edge.
+const SUBJECT_THRESHOLD: u8 = 128;
+
+/// File formats that hold several layers in one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayersFormat {
+    /// OpenEXR with `R`, `G`, `B`, `A`, `matte`, and `trimap` channels as 32-bit floats.
+    Exr,
+    /// TIFF with three pages: the RGBA cutout, the matte, and the trimap.
+    Tiff,
+}
+
+impl LayersFormat {
+    /// The format for a file name, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "exr" => Some(LayersFormat::Exr),
+            "tif" | "tiff" => Some(LayersFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+
+impl OutputLayers {
+    /// Pair a cutout with its raw matte, deriving the trimap with the default
+    /// [`MattingOptions`] threshold and band.
+    pub fn new(rgba: RgbaImage, matte: GrayImage) -> BgrResult<Self> {
+        let expected = rgba.dimensions();
+        let found = matte.dimensions();
+        if expected != found {
+            return Err(BgrError::AlphaMismatch { expected, found });
+        }
+        let options = MattingOptions::default();
+        let trimap = trimap(&matte, options.threshold, options.band_radius);
+        Ok(Self {
+            rgba,
+            matte,
+            trimap,
+        })
+    }
+
+    /// Write the layers to `path`, in the format its extension names (`.exr`, `.tif`, `.tiff`).
+    pub fn save(&self, path: impl AsRef<Path>) -> BgrResult<()> {
+        let path = path.as_ref();
+        let format = LayersFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} must end in .exr, .tif, or .tiff to hold layers",
+                    path.display()
+                ),
+            )
+        })?;
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(format, &mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write the layers as `format`.
+    ///
+    /// EXR color is linear and premultiplied by alpha, as the format expects; TIFF pages keep
+    /// the cutout's 8-bit sRGB values unchanged.
+    pub fn write(&self, format: LayersFormat, writer: impl Write + Seek) -> BgrResult<()> {
+        match format {
+            LayersFormat::Exr => self.write_exr(writer),
+            LayersFormat::Tiff => self.write_tiff(writer),
+        }
+    }
+
+    fn write_exr(&self, writer: impl Write + Seek) -> BgrResult<()> {
+        let (width, height) = self.rgba.dimensions();
+        let alpha: Vec<f32> = self.rgba.pixels().map(|p| unit(p[3])).collect();
+        let color = |channel: usize| -> Vec<f32> {
+            self.rgba
+                .pixels()
+                .zip(&alpha)
+                .map(|(p, a)| srgb_to_linear(unit(p[channel])) * a)
+                .collect()
+        };
+        let gray = |image: &GrayImage| -> Vec<f32> { image.pixels().map(|p| unit(p[0])).collect() };
+        let channels = vec![
+            AnyChannel::new("R", FlatSamples::F32(color(0))),
+            AnyChannel::new("G", FlatSamples::F32(color(1))),
+            AnyChannel::new("B", FlatSamples::F32(color(2))),
+            AnyChannel::new("A", FlatSamples::F32(alpha.clone())),
+            AnyChannel::new("matte", FlatSamples::F32(gray(&self.matte))),
+            AnyChannel::new("trimap", FlatSamples::F32(gray(&self.trimap))),
+        ];
+        let layer = Layer::new(
+            (width as usize, height as usize),
+            LayerAttributes::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels.into()),
+        );
+        Image::from_layer(layer)
+            .write()
+            .to_buffered(writer)
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn write_tiff(&self, writer: impl Write + Seek) -> BgrResult<()> {
+        let (width, height) = self.rgba.dimensions();
+        let tiff_error = |e: tiff::TiffError| BgrError::Io(io::Error::other(e));
+        let mut encoder = TiffEncoder::new(writer).map_err(tiff_error)?;
+
+        let mut page = encoder
+            .new_image::<colortype::RGBA8>(width, height)
+            .map_err(tiff_error)?;
+        page.encoder()
+            .write_tag(Tag::ImageDescription, "cutout")
+            .map_err(tiff_error)?;
+        page.write_data(self.rgba.as_raw()).map_err(tiff_error)?;
+
+        for (name, image) in [("matte", &self.matte), ("trimap", &self.trimap)] {
+            let mut page = encoder
+                .new_image::<colortype::Gray8>(width, height)
+                .map_err(tiff_error)?;
+            page.encoder()
+                .write_tag(Tag::ImageDescription, name)
+                .map_err(tiff_error)?;
+            page.write_data(image.as_raw()).map_err(tiff_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The original image with each separate subject of its cutout on a layer of its own, for
+/// editors that want everything in one document.
+///
+/// Subjects are the regions of the cutout's alpha that do not touch, each with the soft
+/// edge around it.
+#[derive(Debug, Clone)]
+pub struct SubjectLayers {
+    /// The input as it was, the base layer.
+    pub original: RgbImage,
+    /// One cutout per subject, in the original's frame, largest first.
+    pub subjects: Vec<RgbaImage>,
+}
+
+impl SubjectLayers {
+    /// Split `cutout` into its subjects of at least `min_pixels` opaque pixels, over
+    /// `original`.
+    pub fn new(original: RgbImage, cutout: &RgbaImage, min_pixels: u64) -> BgrResult<Self> {
+        let expected = original.dimensions();
+        let found = cutout.dimensions();
+        if expected != found {
+            return Err(BgrError::AlphaMismatch { expected, found });
+        }
+        let alpha = GrayImage::from_fn(found.0, found.1, |x, y| {
+            image::Luma([cutout.get_pixel(x, y)[3]])
+        });
+        let (labels, count) = subject_labels(&alpha, SUBJECT_THRESHOLD, min_pixels);
+        let subjects = (1..=count)
+            .map(|subject| {
+                let mut layer = cutout.clone();
+                for (pixel, &label) in layer.pixels_mut().zip(&labels) {
+                    if label != subject {
+                        pixel.0 = [0; 4];
+                    }
+                }
+                layer
+            })
+            .collect();
+        Ok(Self { original, subjects })
+    }
+
+    /// Write the layers to `path`, which must end in `.tif` or `.tiff`.
+    pub fn save(&self, path: impl AsRef<Path>) -> BgrResult<()> {
+        let path = path.as_ref();
+        if LayersFormat::from_path(path) != Some(LayersFormat::Tiff) {
+            return Err(BgrError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} must end in .tif or .tiff to hold subject layers",
+                    path.display()
+                ),
+            )));
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write the layers as a multi-page TIFF: the original first, named `original`, then
+    /// each subject, named `subject 1`, `subject 2`, and so on.
+    pub fn write(&self, writer: impl Write + Seek) -> BgrResult<()> {
+        let (width, height) = self.original.dimensions();
+        let tiff_error = |e: tiff::TiffError| BgrError::Io(io::Error::other(e));
+        let mut encoder = TiffEncoder::new(writer).map_err(tiff_error)?;
+
+        let mut page = encoder
+            .new_image::<colortype::RGB8>(width, height)
+            .map_err(tiff_error)?;
+        page.encoder()
+            .write_tag(Tag::ImageDescription, "original")
+            .map_err(tiff_error)?;
+        page.write_data(self.original.as_raw())
+            .map_err(tiff_error)?;
+
+        for (index, subject) in self.subjects.iter().enumerate() {
+            let mut page = encoder
+                .new_image::<colortype::RGBA8>(width, height)
+                .map_err(tiff_error)?;
+            page.encoder()
+                .write_tag(
+                    Tag::ImageDescription,
+                    format!("subject {}", index + 1).as_str(),
+                )
+                .map_err(tiff_error)?;
+            page.write_data(subject.as_raw()).map_err(tiff_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// An 8-bit value scaled to `[0, 1]`.
+fn unit(value: u8) -> f32 {
+    f32::from(value) / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgba};
+    use std::io::Cursor;
+
+    fn layers() -> OutputLayers {
+        let rgba = RgbaImage::from_pixel(4, 3, Rgba([255, 128, 0, 255]));
+        let matte = GrayImage::from_pixel(4, 3, Luma([255]));
+        OutputLayers::new(rgba, matte).unwrap()
+    }
+
+    mod output_layers {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn mismatched_matte_rejected() {
+                let rgba = RgbaImage::new(4, 3);
+                let matte = GrayImage::new(3, 4);
+                assert!(matches!(
+                    OutputLayers::new(rgba, matte),
+                    Err(BgrError::AlphaMismatch { .. })
+                ));
+            }
+
+            #[test]
+            fn tiff_has_a_page_per_layer() {
+                let mut buffer = Cursor::new(Vec::new());
+                layers().write(LayersFormat::Tiff, &mut buffer).unwrap();
+                buffer.set_position(0);
+                let mut decoder = tiff::decoder::Decoder::new(buffer).unwrap();
+                let mut pages = 1;
+                while decoder.more_images() {
+                    decoder.next_image().unwrap();
+                    pages += 1;
+                }
+                assert_eq!(pages, 3);
+            }
+
+            #[test]
+            fn exr_has_matte_and_trimap_channels() {
+                let mut buffer = Cursor::new(Vec::new());
+                layers().write(LayersFormat::Exr, &mut buffer).unwrap();
+                buffer.set_position(0);
+                let image = exr::prelude::read()
+                    .no_deep_data()
+                    .largest_resolution_level()
+                    .all_channels()
+                    .first_valid_layer()
+                    .all_attributes()
+                    .from_buffered(buffer)
+                    .unwrap();
+                let names: Vec<String> = image
+                    .layer_data
+                    .channel_data
+                    .list
+                    .iter()
+                    .map(|channel| channel.name.to_string())
+                    .collect();
+                for name in ["R", "G", "B", "A", "matte", "trimap"] {
+                    assert!(names.iter().any(|n| n == name), "missing {name}");
+                }
+            }
+
+            #[test]
+            fn format_from_extension() {
+                assert_eq!(
+                    LayersFormat::from_path(Path::new("a.EXR")),
+                    Some(LayersFormat::Exr)
+                );
+                assert_eq!(
+                    LayersFormat::from_path(Path::new("a.tif")),
+                    Some(LayersFormat::Tiff)
+                );
+                assert_eq!(LayersFormat::from_path(Path::new("a.png")), None);
+            }
+        }
+    }
+
+    mod subject_layers {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            /// Two opaque squares, of 9 and 4 pixels, in a transparent 8x4 cutout.
+            fn two_subjects() -> RgbaImage {
+                RgbaImage::from_fn(8, 4, |x, y| match (x, y) {
+                    (0..3, 0..3) | (5..7, 0..2) => Rgba([255, 0, 0, 255]),
+                    _ => Rgba([0; 4]),
+                })
+            }
+
+            #[test]
+            fn one_layer_per_subject_largest_first() {
+                let original = RgbImage::new(8, 4);
+                let layers = SubjectLayers::new(original, &two_subjects(), 1).unwrap();
+                assert_eq!(layers.subjects.len(), 2);
+                assert_eq!(layers.subjects[0].get_pixel(1, 1)[3], 255);
+                assert_eq!(layers.subjects[0].get_pixel(5, 0)[3], 0);
+                assert_eq!(layers.subjects[1].get_pixel(5, 0)[3], 255);
+
+                let layers = SubjectLayers::new(RgbImage::new(8, 4), &two_subjects(), 5).unwrap();
+                assert_eq!(layers.subjects.len(), 1);
+            }
+
+            #[test]
+            fn tiff_has_the_original_and_a_page_per_subject() {
+                let layers = SubjectLayers::new(RgbImage::new(8, 4), &two_subjects(), 1).unwrap();
+                let mut buffer = Cursor::new(Vec::new());
+                layers.write(&mut buffer).unwrap();
+                buffer.set_position(0);
+                let mut decoder = tiff::decoder::Decoder::new(buffer).unwrap();
+                assert_eq!(decoder.colortype().unwrap(), tiff::ColorType::RGB(8));
+                let mut pages = 1;
+                while decoder.more_images() {
+                    decoder.next_image().unwrap();
+                    pages += 1;
+                }
+                assert_eq!(pages, 3);
+            }
+
+            #[test]
+            fn only_tiff_paths_hold_subjects() {
+                let layers = SubjectLayers::new(RgbImage::new(8, 4), &two_subjects(), 1).unwrap();
+                assert!(layers.save(Path::new("subjects.exr")).is_err());
+            }
+        }
+    }
+}
@@ -17,27 +17,204 @@
 //! foreground.save("output.png")?;
 //! # Ok::<_, bgr::BgrError>(())
 //! ```
+//!
+//! Applications working with in-memory images can use [`Remover`], which resolves a model
+//! preset and keeps one session loaded:
+//!
+//! ```no_run
+//! use bgr::models::ModelPreset;
+//! use bgr::{Device, Remover};
+//!
+//! let mut remover = Remover::builder()
+//!     .model(ModelPreset::IsNet)
+//!     .device(Device::Cpu)
+//!     .build()?;
+//! let cutout = remover.remove(&image::open("input.jpg")?)?;
+//! cutout.save("output.png")?;
+//! # Ok::<_, bgr::BgrError>(())
+//! ```
 
+mod adapter;
+mod animation;
+mod backend;
+mod bgrm;
+mod cancel;
+mod canvas;
+mod color;
+mod compositor;
 mod config;
+mod context;
+#[cfg(unix)]
+mod daemon;
+mod debug_bundle;
+mod defringe;
+mod encode;
 mod error;
+#[cfg(feature = "bgr-ffi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bgr-ffi")))]
+pub mod ffi;
 mod foreground;
 mod inference;
+mod layers;
+mod limits;
 mod mask;
+mod mask_input;
+mod matte_cache;
+mod matte_storage;
+mod matting;
+mod metadata;
 pub mod models;
+mod palette;
+mod pool;
+mod precise;
+mod preview;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+mod quality;
+mod remover;
+#[cfg(feature = "sandboxed-decode")]
+mod sandbox;
+mod selection;
+mod shadow;
+mod sniff;
+mod stroke;
+mod telemetry;
+mod temporal;
+#[cfg(feature = "test-utils")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-utils")))]
+pub mod testing;
+mod trim;
 mod vectorizer;
+pub mod verify;
+mod warnings;
+#[cfg(feature = "wasm")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm")))]
+pub mod wasm;
 
+#[doc(inline)]
+pub use crate::adapter::{
+    Architecture, ModelAdapter, Normalization, SharedAdapter, SideOutputFusion,
+};
+#[doc(inline)]
+pub use crate::animation::Animation;
+#[doc(inline)]
+pub use crate::bgrm::{BGRM_MAGIC, MatteContainer, MatteProvenance};
+#[doc(inline)]
+pub use crate::cancel::CancellationToken;
+#[doc(inline)]
+pub use crate::canvas::{Anchor, CanvasOptions, place_on_canvas};
+#[doc(inline)]
+pub use crate::color::{
+    ColorProfile, ColorSettings, RenderingIntent, decode_srgb, encode_for_profile,
+    encode_output_for_profile, open_srgb, save_for_profile,
+};
+#[cfg(feature = "compositor-wgpu")]
+#[cfg_attr(docsrs, doc(cfg(feature = "compositor-wgpu")))]
+#[doc(inline)]
+pub use crate::compositor::WgpuCompositor;
+#[doc(inline)]
+pub use crate::compositor::{Compositor, CpuCompositor, LinearCompositor};
 #[doc(inline)]
 pub use crate::config::{
-    DEFAULT_MODEL_PATH, ENV_MODEL_PATH, InferenceSettings, MaskProcessingOptions,
+    Backend, ComponentArea, DEFAULT_MODEL_PATH, Device, ENV_MODEL_PATH, InferenceSettings,
+    MaskProcessingOptions, ModelBytes,
 };
 #[doc(inline)]
+pub use crate::context::{Context, Verbosity};
+#[cfg(unix)]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[doc(inline)]
+pub use crate::daemon::{Daemon, DaemonClient, default_socket_path};
+#[doc(inline)]
+pub use crate::debug_bundle::{ChannelStats, DebugBundle, TensorStats};
+#[doc(inline)]
+pub use crate::defringe::defringe;
+#[doc(inline)]
+pub use crate::encode::{Compression, EncodeOptions, OutputFormat, encode_output};
+#[doc(inline)]
 pub use crate::error::{BgrError, BgrResult};
+#[doc(inline)]
+pub use crate::foreground::{Background, blur_background};
+#[doc(inline)]
+pub use crate::layers::{LayersFormat, OutputLayers, SubjectLayers};
+#[doc(inline)]
+pub use crate::limits::InputLimits;
+#[doc(inline)]
+pub use crate::mask_input::{
+    LoadedMask, MaskBinarize, MaskLoadOptions, MaskResize, load_mask, mask_from_image,
+};
+#[doc(inline)]
+pub use crate::matte_cache::{CacheKey, MatteCache};
+#[doc(inline)]
+pub use crate::matte_storage::{BGRL_MAGIC, StorageBenchmark, StorageFormat, StorageResult};
+#[doc(inline)]
+pub use crate::matting::MattingOptions;
+#[doc(inline)]
+pub use crate::metadata::ImageMetadata;
+#[doc(inline)]
+pub use crate::palette::{AlphaDither, PaletteOptions, encode_paletted_png};
+#[doc(inline)]
+pub use crate::pool::{PooledSession, SessionPool};
+#[doc(inline)]
+pub use crate::precise::{MatteDepth, PreciseMatte};
+#[doc(inline)]
+pub use crate::preview::{DRAFT_PRESET, PreviewOptions, draft_matte};
+#[doc(inline)]
+pub use crate::progress::{
+    BatchProgress, DownloadEvent, InputEvent, InputId, NoProgress, ProgressSink, SharedProgress,
+    Stage,
+};
+#[doc(inline)]
+pub use crate::quality::{QualityMetric, QualityTarget};
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[doc(inline)]
+pub use crate::remover::AsyncRemover;
+#[doc(inline)]
+pub use crate::remover::{Remover, RemoverBuilder};
+#[cfg(feature = "sandboxed-decode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sandboxed-decode")))]
+#[doc(inline)]
+pub use crate::sandbox::{DECODE_WORKER_COMMAND, SandboxedDecoder, run_decode_worker};
+#[doc(inline)]
+pub use crate::selection::SelectionFormat;
+#[doc(inline)]
+pub use crate::shadow::{ShadowOptions, add_shadow};
+#[doc(inline)]
+pub use crate::stroke::{StrokeOptions, add_stroke};
+#[doc(inline)]
+pub use crate::telemetry::{
+    InferenceEnd, InferenceStart, ModelLoad, NoTelemetry, SharedTelemetry, Telemetry,
+};
+#[doc(inline)]
+pub use crate::temporal::{TemporalOptions, TemporalSmoother};
+#[doc(inline)]
+pub use crate::trim::{TrimMargin, subject_bounds, trim_to_subject};
 // Re-export old names for compatibility
 #[doc(hidden)]
 pub use crate::error::BgrError as OutlineError;
 #[doc(hidden)]
 pub use crate::error::BgrResult as OutlineResult;
+#[doc(inline)]
+pub use crate::warnings::{Edge, Warning};
 pub use vectorizer::MaskVectorizer;
+#[doc(inline)]
+pub use vectorizer::centerline::{CenterlineOptions, CenterlineVectorizer};
+#[doc(inline)]
+pub use vectorizer::collision::{
+    CollisionOptions, CollisionShape, CollisionTrace, CollisionVectorizer,
+};
+#[doc(inline)]
+pub use vectorizer::contour::{ContourMetadata, ContourOptions, ContourTrace, ContourVectorizer};
+#[doc(inline)]
+pub use vectorizer::lottie::{LottieOptions, LottieVectorizer};
+#[doc(inline)]
+pub use vectorizer::sequence::{OutlineFrame, OutlineSequence, SequenceOptions};
+#[doc(inline)]
+pub use vectorizer::transform::{
+    Affine, CoordinateOptions, CoordinateSpace, PhysicalUnit, apply_coordinates,
+};
 
 #[cfg(feature = "vectorizer-vtracer")]
 #[cfg_attr(docsrs, doc(cfg(feature = "vectorizer-vtracer")))]
@@ -49,11 +226,14 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use image::imageops::FilterType;
-use image::{GrayImage, RgbImage, RgbaImage};
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, RgbImage, RgbaImage};
 
-use crate::foreground::compose_foreground;
-use crate::inference::run_matte_pipeline;
-use crate::mask::{MaskOperation, apply_operations, operations_from_options};
+use crate::foreground::{compose_foreground, composite_over, encode_image};
+use crate::inference::{
+    DecodedImage, ModelSession, load_input, load_input_bytes, run_matte_pipeline,
+};
+use crate::mask::{MaskOperation, apply_operations, apply_operations_on, operations_from_options};
+use crate::warnings::matte_warnings;
 
 /// Entry point for configuring and running background removal inference.
 ///
@@ -80,6 +260,14 @@ impl Bgr {
         }
     }
 
+    /// Construct Bgr from the inference settings of a shared [`Context`].
+    pub fn from_context(ctx: &Context) -> Self {
+        Self {
+            settings: ctx.inference_settings().clone(),
+            default_mask_processing: MaskProcessingOptions::default(),
+        }
+    }
+
     /// Construct Bgr using env var `ENV_MODEL_PATH` or fallback to `DEFAULT_MODEL_PATH`.
     pub fn from_env_or_default() -> Self {
         let resolved = std::env::var_os(ENV_MODEL_PATH)
@@ -102,6 +290,13 @@ impl Bgr {
         )))
     }
 
+    /// Load the model from `bytes` instead of the model path, see
+    /// [`InferenceSettings::model_bytes`].
+    pub fn with_model_bytes(mut self, bytes: impl Into<Arc<[u8]>>) -> Self {
+        self.settings = self.settings.with_model_bytes(bytes);
+        self
+    }
+
     /// Set the filter used to resize the input image for the model.
     pub fn with_input_resize_filter(mut self, filter: FilterType) -> Self {
         self.settings.input_resize_filter = filter;
@@ -120,6 +315,38 @@ impl Bgr {
         self
     }
 
+    /// Enable the low-memory mode, see [`InferenceSettings::low_memory`].
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.settings.low_memory = low_memory;
+        self
+    }
+
+    /// Set the inference device, see [`Device`].
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.settings.device = device;
+        self
+    }
+
+    /// Stop inference early with [`BgrError::Cancelled`] once `token` is cancelled, see
+    /// [`CancellationToken`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.settings.cancellation = token;
+        self
+    }
+
+    /// Report pipeline stages of every input to `sink`, see [`ProgressSink`].
+    pub fn with_progress(mut self, sink: impl ProgressSink + 'static) -> Self {
+        self.settings.progress = SharedProgress::new(sink);
+        self
+    }
+
+    /// Call `telemetry`'s hooks when sessions load and around each model run, see
+    /// [`Telemetry`].
+    pub fn with_telemetry(mut self, telemetry: impl Telemetry + 'static) -> Self {
+        self.settings.telemetry = SharedTelemetry::new(telemetry);
+        self
+    }
+
     /// Set the default mask processing options to use when none are specified.
     pub fn with_default_mask_processing(mut self, options: MaskProcessingOptions) -> Self {
         self.default_mask_processing = options;
@@ -134,9 +361,373 @@ impl Bgr {
     /// Run the inference pipeline for a single image, returning the orginal image, raw matte, and processing options,
     /// wrapped in an `InferencedMatte`.
     pub fn for_image(&self, image_path: impl AsRef<Path>) -> BgrResult<InferencedMatte> {
-        let (rgb, matte) = run_matte_pipeline(&self.settings, image_path.as_ref())?;
-        Ok(InferencedMatte::new(
+        let (decoded, matte) = run_matte_pipeline(&self.settings, image_path.as_ref())?;
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            matte,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    /// Run the inference pipeline for an encoded image held in memory, loading the model for
+    /// this call only. Use [`BgrSession::for_bytes`] to process many images.
+    pub fn for_bytes(&self, encoded: &[u8]) -> BgrResult<InferencedMatte> {
+        self.session()?.for_bytes(encoded)
+    }
+
+    /// Run inference for a single image at full precision, loading the model for this call
+    /// only. See [`BgrSession::for_image_precise`].
+    pub fn for_image_precise(&self, image_path: impl AsRef<Path>) -> BgrResult<PreciseMatte> {
+        self.session()?.for_image_precise(image_path)
+    }
+
+    /// Pair an image with a mask file instead of running the model, as when applying a mask
+    /// made in another tool. No model is loaded.
+    ///
+    /// The mask is fitted to the image according to `options`; what was done to it is
+    /// reported in [`InferencedMatte::warnings`].
+    pub fn for_image_with_mask(
+        &self,
+        image_path: impl AsRef<Path>,
+        mask_path: impl AsRef<Path>,
+        options: &MaskLoadOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let mut decoded = load_input(image_path.as_ref(), &self.settings)?;
+        let size = decoded.rgb.dimensions();
+        let loaded = self.load_mask_file(mask_path.as_ref(), Some(size), options)?;
+        decoded.warnings.extend(loaded.warnings);
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            loaded.mask,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    /// Treat a mask file as the matte of itself, so that mask processing and tracing can run
+    /// on masks made elsewhere. No model is loaded.
+    pub fn for_mask(
+        &self,
+        mask_path: impl AsRef<Path>,
+        options: &MaskLoadOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let loaded = self.load_mask_file(mask_path.as_ref(), None, options)?;
+        let rgb = DynamicImage::ImageLuma8(loaded.mask.clone()).into_rgb8();
+        let decoded = DecodedImage {
+            rgb,
+            warnings: loaded.warnings,
+        };
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            loaded.mask,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    /// Treat the matte of a [`MatteContainer`] as the matte of itself, like
+    /// [`for_mask`](Bgr::for_mask), for mattes piped in from another bgr invocation. No model
+    /// is loaded.
+    pub fn for_matte_container(&self, container: MatteContainer) -> BgrResult<InferencedMatte> {
+        let (width, height) = container.matte.dimensions();
+        self.settings.limits.check_dimensions(width, height)?;
+        let rgb = DynamicImage::ImageLuma8(container.matte.clone()).into_rgb8();
+        let decoded = DecodedImage {
+            rgb,
+            warnings: Vec::new(),
+        };
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            container.matte,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    /// Load the model once and return a [`BgrSession`] that can process many images.
+    ///
+    /// Prefer this over repeated [`for_image`](Bgr::for_image) calls when processing batches,
+    /// since building the ONNX session is the most expensive step.
+    pub fn session(&self) -> BgrResult<BgrSession> {
+        Ok(BgrSession {
+            model: ModelSession::load(&self.settings)?,
+            settings: self.settings.clone(),
+            default_mask_processing: self.default_mask_processing.clone(),
+        })
+    }
+
+    /// Load `size` sessions up front and share them through a [`SessionPool`].
+    ///
+    /// Suited to services with bursty, concurrent requests: a request only waits when every
+    /// session is busy. A `size` of `0` loads one session per available core.
+    pub fn session_pool(&self, size: usize) -> BgrResult<SessionPool> {
+        SessionPool::new(self, size)
+    }
+
+    /// Connect to a running [`Daemon`] instead of loading the model in this process.
+    ///
+    /// Mattes returned by the client use this instance's default mask processing.
+    #[cfg(unix)]
+    pub fn daemon_client(&self, socket: impl AsRef<Path>) -> BgrResult<DaemonClient> {
+        DaemonClient::connect(socket, self.default_mask_processing.clone())
+    }
+
+    /// Read a mask file, refusing to when inputs are decoded in the sandbox: its worker returns
+    /// RGB pixels only, which would drop the alpha of a cutout used as a mask, and decoding the
+    /// mask here instead would bypass it.
+    fn load_mask_file(
+        &self,
+        path: &Path,
+        size: Option<(u32, u32)>,
+        options: &MaskLoadOptions,
+    ) -> BgrResult<LoadedMask> {
+        #[cfg(feature = "sandboxed-decode")]
+        if self.settings.sandbox.is_some() {
+            return Err(BgrError::invalid_input(
+                "mask files cannot be read while inputs are decoded in the sandbox",
+            ));
+        }
+        load_mask(path, size, options, &self.settings.limits)
+    }
+}
+
+/// A loaded model that runs inference on many images without rebuilding the ONNX session.
+///
+/// Created by [`Bgr::session`]. A session is `Send`, so batch callers typically create one per
+/// worker thread.
+///
+/// # Example
+/// ```no_run
+/// use bgr::Bgr;
+///
+/// let bgr = Bgr::new("model.onnx");
+/// let mut session = bgr.session()?;
+/// for input in ["a.jpg", "b.jpg"] {
+///     session.for_image(input)?.matte().foreground()?.save(format!("{input}.png"))?;
+/// }
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub struct BgrSession {
+    model: ModelSession,
+    settings: InferenceSettings,
+    default_mask_processing: MaskProcessingOptions,
+}
+
+impl BgrSession {
+    /// The device the model runs on, after any fallback to the CPU.
+    pub fn device(&self) -> Device {
+        self.model.device()
+    }
+
+    /// Carry this session's loaded model over to `bgr`'s settings, when `bgr` would load the
+    /// same model file on the same backend, device, threads, and memory mode; otherwise the
+    /// session is handed back unchanged.
+    ///
+    /// Saves reloading the model between runs that differ only in how inputs and mattes are
+    /// processed.
+    pub fn reuse_for(self, bgr: &Bgr) -> Result<BgrSession, BgrSession> {
+        let (loaded, wanted) = (&self.settings, &bgr.settings);
+        let same_model = loaded.model_bytes.is_none()
+            && wanted.model_bytes.is_none()
+            && loaded.model_path == wanted.model_path
+            && loaded.backend == wanted.backend
+            && loaded.device == wanted.device
+            && loaded.intra_threads == wanted.intra_threads
+            && loaded.low_memory == wanted.low_memory;
+        if !same_model {
+            return Err(self);
+        }
+        Ok(BgrSession {
+            model: self.model,
+            settings: wanted.clone(),
+            default_mask_processing: bgr.default_mask_processing.clone(),
+        })
+    }
+
+    /// Where this session reports progress.
+    pub(crate) fn progress(&self) -> &SharedProgress {
+        &self.settings.progress
+    }
+
+    /// Describe how `image_path` failed with `error`, for a bug report: the session's settings
+    /// and, when the input still decodes, a downscaled copy and the statistics of the tensor
+    /// the model is fed.
+    pub fn debug_bundle(&self, image_path: impl AsRef<Path>, error: &BgrError) -> DebugBundle {
+        let image_path = image_path.as_ref();
+        let settings = &self.settings;
+        let mut bundle = DebugBundle::new(image_path, error)
+            .with_setting("model", settings.model_path.display())
+            .with_setting("device", format!("{:?}", settings.device))
+            .with_setting("active_device", format!("{:?}", self.device()))
+            .with_setting("backend", format!("{:?}", settings.backend))
+            .with_setting(
+                "input_resize_filter",
+                format!("{:?}", settings.input_resize_filter),
+            )
+            .with_setting(
+                "output_resize_filter",
+                format!("{:?}", settings.output_resize_filter),
+            )
+            .with_setting("intra_threads", format!("{:?}", settings.intra_threads))
+            .with_setting("low_memory", settings.low_memory)
+            .with_setting("intent", format!("{:?}", settings.intent))
+            .with_setting("auto_orient", settings.auto_orient);
+        if let Ok(decoded) = load_input(image_path, settings) {
+            if let Ok(stats) = self.model.tensor_stats(&decoded.rgb, settings) {
+                bundle = bundle.with_tensor(stats);
+            }
+            bundle = bundle.with_input(&decoded.rgb);
+        }
+        bundle
+    }
+
+    /// Run inference for a single image with the loaded model.
+    pub fn for_image(&mut self, image_path: impl AsRef<Path>) -> BgrResult<InferencedMatte> {
+        let decoded = load_input(image_path.as_ref(), &self.settings)?;
+        self.infer(decoded)
+    }
+
+    /// Run inference for an encoded image held in memory, such as an upload.
+    ///
+    /// The format is detected from the contents; the same input limits apply as for files.
+    pub fn for_bytes(&mut self, encoded: &[u8]) -> BgrResult<InferencedMatte> {
+        let decoded = load_input_bytes(encoded, &self.settings)?;
+        self.infer(decoded)
+    }
+
+    /// Run inference for an image that is already decoded, such as a video frame.
+    ///
+    /// The dimension limits still apply.
+    pub fn for_rgb(&mut self, rgb: RgbImage) -> BgrResult<InferencedMatte> {
+        self.settings
+            .limits
+            .check_dimensions(rgb.width(), rgb.height())?;
+        self.infer(DecodedImage {
             rgb,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Run inference for an image decoded by the caller, in any color type.
+    ///
+    /// Transparency in `image` is ignored for inference; the dimension limits apply.
+    pub fn for_dynamic_image(&mut self, image: &DynamicImage) -> BgrResult<InferencedMatte> {
+        self.for_rgb(image.to_rgb8())
+    }
+
+    /// Run low-resolution inference for a quick preview, see [`PreviewOptions`].
+    ///
+    /// The matte is at the image's full size but only as detailed as the shrunken input; load
+    /// the session with [`DRAFT_PRESET`] for the lowest latency.
+    pub fn for_image_draft(
+        &mut self,
+        image_path: impl AsRef<Path>,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let decoded = load_input(image_path.as_ref(), &self.settings)?;
+        self.infer_draft(decoded, options)
+    }
+
+    /// Run low-resolution preview inference for an encoded image held in memory.
+    pub fn for_bytes_draft(
+        &mut self,
+        encoded: &[u8],
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let decoded = load_input_bytes(encoded, &self.settings)?;
+        self.infer_draft(decoded, options)
+    }
+
+    /// Run the final pass over a draft: inference on the part of the image where `prior`, the
+    /// draft's matte, found the subject, with everything else background.
+    ///
+    /// The prior is resized to the image when the sizes differ. Without a subject in the prior,
+    /// the whole image is used.
+    pub fn for_image_with_prior(
+        &mut self,
+        image_path: impl AsRef<Path>,
+        prior: &GrayImage,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let decoded = load_input(image_path.as_ref(), &self.settings)?;
+        self.infer_with_prior(decoded, prior, options)
+    }
+
+    /// Run the final pass over a draft for an encoded image held in memory.
+    pub fn for_bytes_with_prior(
+        &mut self,
+        encoded: &[u8],
+        prior: &GrayImage,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let decoded = load_input_bytes(encoded, &self.settings)?;
+        self.infer_with_prior(decoded, prior, options)
+    }
+
+    /// Run inference for a single image and keep the matte's full precision, for writing
+    /// mattes at 16 bits or as floats with [`PreciseMatte::save`].
+    ///
+    /// The matte is resampled in `f32` at the image's full size regardless of
+    /// [`with_low_memory`](Bgr::with_low_memory).
+    pub fn for_image_precise(&mut self, image_path: impl AsRef<Path>) -> BgrResult<PreciseMatte> {
+        let decoded = load_input(image_path.as_ref(), &self.settings)?;
+        let samples = self
+            .model
+            .infer_matte_precise(&decoded.rgb, &self.settings)?;
+        Ok(PreciseMatte::new(samples, decoded.warnings))
+    }
+
+    fn infer(&mut self, decoded: DecodedImage) -> BgrResult<InferencedMatte> {
+        let matte = self.model.infer_matte(&decoded.rgb, &self.settings)?;
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            matte,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    fn infer_draft(
+        &mut self,
+        decoded: DecodedImage,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let Some(small) = preview::shrink(&decoded.rgb, options.draft_max_side) else {
+            return self.infer(decoded);
+        };
+        let matte = self.model.infer_matte(&small, &self.settings)?;
+        let (width, height) = decoded.rgb.dimensions();
+        let matte = preview::enlarge(&matte, width, height);
+        Ok(InferencedMatte::analyzed(
+            decoded,
+            matte,
+            self.default_mask_processing.clone(),
+        ))
+    }
+
+    fn infer_with_prior(
+        &mut self,
+        decoded: DecodedImage,
+        prior: &GrayImage,
+        options: &PreviewOptions,
+    ) -> BgrResult<InferencedMatte> {
+        let (width, height) = decoded.rgb.dimensions();
+        let region = if prior.dimensions() == (width, height) {
+            preview::prior_region(prior, options)
+        } else {
+            preview::prior_region(&preview::enlarge(prior, width, height), options)
+        };
+        let Some(region) = region else {
+            return self.infer(decoded);
+        };
+        let crop = image::imageops::crop_imm(
+            &decoded.rgb,
+            region.x,
+            region.y,
+            region.width,
+            region.height,
+        )
+        .to_image();
+        let matte = self.model.infer_matte(&crop, &self.settings)?;
+        let matte = preview::paste(&matte, region, width, height);
+        Ok(InferencedMatte::analyzed(
+            decoded,
             matte,
             self.default_mask_processing.clone(),
         ))
@@ -166,6 +757,7 @@ impl Bgr {
 pub struct InferencedMatte {
     rgb_image: Arc<RgbImage>,
     raw_matte: Arc<GrayImage>,
+    warnings: Vec<Warning>,
     default_mask_processing: MaskProcessingOptions,
 }
 
@@ -173,15 +765,33 @@ impl InferencedMatte {
     fn new(
         rgb_image: RgbImage,
         raw_matte: GrayImage,
+        warnings: Vec<Warning>,
         default_mask_processing: MaskProcessingOptions,
     ) -> Self {
         Self {
             rgb_image: Arc::new(rgb_image),
             raw_matte: Arc::new(raw_matte),
+            warnings,
             default_mask_processing,
         }
     }
 
+    /// Combine a decoded input with its fresh matte, adding the matte's own warnings.
+    fn analyzed(
+        decoded: DecodedImage,
+        raw_matte: GrayImage,
+        default_mask_processing: MaskProcessingOptions,
+    ) -> Self {
+        let mut warnings = decoded.warnings;
+        warnings.extend(matte_warnings(&raw_matte));
+        Self::new(decoded.rgb, raw_matte, warnings, default_mask_processing)
+    }
+
+    /// Non-fatal issues noticed while decoding the input and inspecting the matte.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
     /// Get a reference to the original RGB image.
     pub fn rgb_image(&self) -> &RgbImage {
         self.rgb_image.as_ref()
@@ -200,6 +810,28 @@ impl InferencedMatte {
             operations: Vec::new(),
         }
     }
+
+    /// Replace the raw matte with a fully opaque one, so every output keeps the whole input,
+    /// noting it with [`Warning::InputKeptWhole`].
+    pub fn keep_whole(mut self) -> Self {
+        let (width, height) = self.rgb_image.dimensions();
+        self.raw_matte = Arc::new(GrayImage::from_pixel(width, height, Luma([255])));
+        self.warnings.push(Warning::InputKeptWhole);
+        self
+    }
+
+    /// Replace the raw matte with one refined by alpha matting, like
+    /// [`MatteHandle::refine_matting`], keeping the warnings.
+    pub fn refine_matting(mut self, options: &MattingOptions) -> BgrResult<Self> {
+        let refined = matting::refine_matte(&self.rgb_image, &self.raw_matte, options)?;
+        self.raw_matte = Arc::new(refined);
+        Ok(self)
+    }
+
+    /// Score the raw matte on `metric`, see [`QualityMetric`].
+    pub fn quality(&self, metric: QualityMetric) -> f32 {
+        metric.score(&self.rgb_image, &self.raw_matte)
+    }
 }
 
 /// Builder for chaining mask processing operations on the raw matte.
@@ -238,8 +870,10 @@ impl MatteHandle {
     }
 
     /// Consume the handle and return the raw grayscale matte.
+    ///
+    /// The matte is only cloned if other handles still share it.
     pub fn into_image(self) -> GrayImage {
-        (*self.raw_matte).clone()
+        Arc::try_unwrap(self.raw_matte).unwrap_or_else(|shared| (*shared).clone())
     }
 
     /// Save the raw grayscale matte to the specified path.
@@ -248,6 +882,39 @@ impl MatteHandle {
         Ok(())
     }
 
+    /// Encode the raw grayscale matte as `format` in memory.
+    pub fn encode(&self, format: ImageFormat) -> BgrResult<Vec<u8>> {
+        encode_image(DynamicImage::ImageLuma8(self.raw()), format)
+    }
+
+    /// Save the raw matte as a selection that image editors can load, see [`SelectionFormat`].
+    pub fn save_selection(&self, path: impl AsRef<Path>, format: SelectionFormat) -> BgrResult<()> {
+        std::fs::write(path, self.encode_selection(format)?)?;
+        Ok(())
+    }
+
+    /// Encode the raw matte as a selection in memory.
+    pub fn encode_selection(&self, format: SelectionFormat) -> BgrResult<Vec<u8>> {
+        selection::encode_selection(&self.rgb_image, &self.raw_matte, format)
+    }
+
+    /// Replace the raw matte with one refined by alpha matting against the original image.
+    ///
+    /// Edges become soft and follow the image colors, which recovers hair and fur that the
+    /// model cut off or haloed. Queued operations are kept and run on the refined matte.
+    pub fn refine_matting(mut self, options: &MattingOptions) -> BgrResult<Self> {
+        let refined = matting::refine_matte(&self.rgb_image, &self.raw_matte, options)?;
+        self.raw_matte = Arc::new(refined);
+        Ok(self)
+    }
+
+    /// Replace the raw matte with one blended into `smoother`'s running average of earlier
+    /// frames, which steadies flickering edges in video. Queued operations are kept.
+    pub fn smooth_temporal(mut self, smoother: &mut TemporalSmoother) -> Self {
+        self.raw_matte = Arc::new(smoother.smooth(&self.raw_matte));
+        self
+    }
+
     /// Add a blur operation using the default sigma.
     pub fn blur(mut self) -> Self {
         let sigma = self.default_mask_processing.blur_sigma;
@@ -293,6 +960,66 @@ impl MatteHandle {
         self
     }
 
+    /// Add an erosion operation using the default radius.
+    ///
+    /// **Note**: Erosion typically works best on binary masks. Consider calling
+    /// [`threshold`](MatteHandle::threshold) before `erode` if working with a soft matte.
+    pub fn erode(mut self) -> Self {
+        let radius = self.default_mask_processing.erosion_radius;
+        self.operations.push(MaskOperation::Erode { radius });
+        self
+    }
+
+    /// Add an erosion operation with a custom radius.
+    ///
+    /// **Note**: Erosion typically works best on binary masks. Consider calling
+    /// [`threshold`](MatteHandle::threshold) before `erode` if working with a soft matte.
+    pub fn erode_with(mut self, radius: f32) -> Self {
+        self.operations.push(MaskOperation::Erode { radius });
+        self
+    }
+
+    /// Add a feathering operation using the default radius.
+    pub fn feather(mut self) -> Self {
+        let radius = self.default_mask_processing.feather_radius;
+        self.operations.push(MaskOperation::Feather { radius });
+        self
+    }
+
+    /// Add a feathering operation that softens edges over roughly `radius` pixels.
+    pub fn feather_with(mut self, radius: f32) -> Self {
+        self.operations.push(MaskOperation::Feather { radius });
+        self
+    }
+
+    /// Add an operation that keeps only the largest connected region.
+    ///
+    /// **Note**: Regions are split at `mask_threshold`. The surviving pixels keep their values, so
+    /// this also works on a soft matte.
+    pub fn keep_largest(mut self) -> Self {
+        let threshold = self.default_mask_processing.mask_threshold;
+        self.operations.push(MaskOperation::FilterComponents {
+            threshold,
+            min_area: None,
+            keep_largest: true,
+        });
+        self
+    }
+
+    /// Add an operation that drops connected regions smaller than `min_area`.
+    ///
+    /// **Note**: Regions are split at `mask_threshold`. The surviving pixels keep their values, so
+    /// this also works on a soft matte.
+    pub fn min_area(mut self, min_area: ComponentArea) -> Self {
+        let threshold = self.default_mask_processing.mask_threshold;
+        self.operations.push(MaskOperation::FilterComponents {
+            threshold,
+            min_area: Some(min_area),
+            keep_largest: false,
+        });
+        self
+    }
+
     /// Add a hole-filling operation to the processing pipeline.
     ///
     /// **Note**: Hole-filling typically works best on binary masks. Consider calling
@@ -305,18 +1032,25 @@ impl MatteHandle {
 
     /// Process the raw matte with the accumulated operations and default options.
     pub fn processed(self) -> BgrResult<MaskHandle> {
-        self.process_with_options(None)
+        self.process_with_options(None, &CpuCompositor)
     }
 
     /// Process the raw matte with the accumulated operations and custom options.
     pub fn processed_with(self, options: &MaskProcessingOptions) -> BgrResult<MaskHandle> {
-        self.process_with_options(Some(options))
+        self.process_with_options(Some(options), &CpuCompositor)
+    }
+
+    /// Process the raw matte like [`processed`](MatteHandle::processed), blurring on
+    /// `compositor`.
+    pub fn processed_on(self, compositor: &dyn Compositor) -> BgrResult<MaskHandle> {
+        self.process_with_options(None, compositor)
     }
 
     /// Helper function to process with options.
     fn process_with_options(
         mut self,
         options: Option<&MaskProcessingOptions>,
+        compositor: &dyn Compositor,
     ) -> BgrResult<MaskHandle> {
         let mut ops = std::mem::take(&mut self.operations);
         match options {
@@ -327,7 +1061,7 @@ impl MatteHandle {
             None => {}
         }
 
-        let mask = apply_operations(self.raw_matte.as_ref(), &ops);
+        let mask = apply_operations_on(self.raw_matte.as_ref(), &ops, compositor)?;
         Ok(MaskHandle::new(
             Arc::clone(&self.rgb_image),
             mask,
@@ -341,6 +1075,12 @@ impl MatteHandle {
         Ok(ForegroundHandle { image: rgba })
     }
 
+    /// Compose the foreground from the raw matte on `compositor`.
+    pub fn foreground_with(&self, compositor: &dyn Compositor) -> BgrResult<ForegroundHandle> {
+        let rgba = compositor.compose(self.rgb_image.as_ref(), self.raw_matte.as_ref())?;
+        Ok(ForegroundHandle { image: rgba })
+    }
+
     /// Trace the raw matte using the specified vectorizer and options.
     pub fn trace<V>(&self, vectorizer: &V, options: &V::Options) -> BgrResult<V::Output>
     where
@@ -415,6 +1155,22 @@ impl MaskHandle {
         Ok(())
     }
 
+    /// Encode the mask as `format` in memory.
+    pub fn encode(&self, format: ImageFormat) -> BgrResult<Vec<u8>> {
+        encode_image(DynamicImage::ImageLuma8(self.mask.clone()), format)
+    }
+
+    /// Save the mask as a selection that image editors can load, see [`SelectionFormat`].
+    pub fn save_selection(&self, path: impl AsRef<Path>, format: SelectionFormat) -> BgrResult<()> {
+        std::fs::write(path, self.encode_selection(format)?)?;
+        Ok(())
+    }
+
+    /// Encode the mask as a selection in memory.
+    pub fn encode_selection(&self, format: SelectionFormat) -> BgrResult<Vec<u8>> {
+        selection::encode_selection(&self.rgb_image, &self.mask, format)
+    }
+
     /// Add a blur operation using the default sigma.
     pub fn blur(mut self) -> Self {
         let sigma = self.default_mask_processing.blur_sigma;
@@ -460,6 +1216,66 @@ impl MaskHandle {
         self
     }
 
+    /// Add an erosion operation using the default radius.
+    ///
+    /// **Note**: Erosion typically works best on binary masks. If this mask is still grayscale,
+    /// consider calling [`threshold`](MaskHandle::threshold) first.
+    pub fn erode(mut self) -> Self {
+        let radius = self.default_mask_processing.erosion_radius;
+        self.operations.push(MaskOperation::Erode { radius });
+        self
+    }
+
+    /// Add an erosion operation with a custom radius.
+    ///
+    /// **Note**: Erosion typically works best on binary masks. If this mask is still grayscale,
+    /// consider calling [`threshold`](MaskHandle::threshold) first.
+    pub fn erode_with(mut self, radius: f32) -> Self {
+        self.operations.push(MaskOperation::Erode { radius });
+        self
+    }
+
+    /// Add a feathering operation using the default radius.
+    pub fn feather(mut self) -> Self {
+        let radius = self.default_mask_processing.feather_radius;
+        self.operations.push(MaskOperation::Feather { radius });
+        self
+    }
+
+    /// Add a feathering operation that softens edges over roughly `radius` pixels.
+    pub fn feather_with(mut self, radius: f32) -> Self {
+        self.operations.push(MaskOperation::Feather { radius });
+        self
+    }
+
+    /// Add an operation that keeps only the largest connected region.
+    ///
+    /// **Note**: Regions are split at `mask_threshold`. The surviving pixels keep their values, so
+    /// this also works on a grayscale mask.
+    pub fn keep_largest(mut self) -> Self {
+        let threshold = self.default_mask_processing.mask_threshold;
+        self.operations.push(MaskOperation::FilterComponents {
+            threshold,
+            min_area: None,
+            keep_largest: true,
+        });
+        self
+    }
+
+    /// Add an operation that drops connected regions smaller than `min_area`.
+    ///
+    /// **Note**: Regions are split at `mask_threshold`. The surviving pixels keep their values, so
+    /// this also works on a grayscale mask.
+    pub fn min_area(mut self, min_area: ComponentArea) -> Self {
+        let threshold = self.default_mask_processing.mask_threshold;
+        self.operations.push(MaskOperation::FilterComponents {
+            threshold,
+            min_area: Some(min_area),
+            keep_largest: false,
+        });
+        self
+    }
+
     /// Add a hole-filling operation to the processing pipeline.
     ///
     /// **Note**: Hole-filling typically works best on binary masks. If this mask is still grayscale,
@@ -508,6 +1324,12 @@ impl MaskHandle {
         Ok(ForegroundHandle { image: rgba })
     }
 
+    /// Compose the foreground from the current mask on `compositor`.
+    pub fn foreground_with(&self, compositor: &dyn Compositor) -> BgrResult<ForegroundHandle> {
+        let rgba = compositor.compose(self.rgb_image.as_ref(), &self.mask)?;
+        Ok(ForegroundHandle { image: rgba })
+    }
+
     /// Trace the current mask using the specified vectorizer and options.
     pub fn trace<V>(&self, vectorizer: &V, options: &V::Options) -> BgrResult<V::Output>
     where
@@ -563,4 +1385,99 @@ impl ForegroundHandle {
         self.image.save(path)?;
         Ok(())
     }
+
+    /// Encode the RGBA foreground as `format` in memory, such as for an HTTP response.
+    ///
+    /// The format must store transparency (PNG, WebP, TIFF); flatten the foreground with
+    /// [`composite`](ForegroundHandle::composite) before encoding it as JPEG.
+    pub fn encode(&self, format: ImageFormat) -> BgrResult<Vec<u8>> {
+        encode_image(DynamicImage::ImageRgba8(self.image.clone()), format)
+    }
+
+    /// Save the foreground as PNG, WebP, AVIF, or TIFF with its alpha, see [`EncodeOptions`].
+    pub fn save_as(&self, path: impl AsRef<Path>, options: &EncodeOptions) -> BgrResult<()> {
+        std::fs::write(path, self.encode_as(options)?)?;
+        Ok(())
+    }
+
+    /// Encode the foreground in memory as [`save_as`](ForegroundHandle::save_as) does.
+    pub fn encode_as(&self, options: &EncodeOptions) -> BgrResult<Vec<u8>> {
+        encode_output(&DynamicImage::ImageRgba8(self.image.clone()), options)
+    }
+
+    /// Save the foreground converted to and tagged with a color profile, see [`ColorSettings`].
+    pub fn save_in(&self, path: impl AsRef<Path>, color: &ColorSettings) -> BgrResult<()> {
+        save_for_profile(DynamicImage::ImageRgba8(self.image.clone()), path, color)
+    }
+
+    /// Encode the foreground in memory, converted to and tagged with a color profile.
+    pub fn encode_in(&self, format: ImageFormat, color: &ColorSettings) -> BgrResult<Vec<u8>> {
+        encode_for_profile(DynamicImage::ImageRgba8(self.image.clone()), format, color)
+    }
+
+    /// Save the foreground as a paletted PNG-8 with binary transparency, see [`PaletteOptions`].
+    pub fn save_paletted(&self, path: impl AsRef<Path>, options: &PaletteOptions) -> BgrResult<()> {
+        std::fs::write(path, self.encode_paletted(options)?)?;
+        Ok(())
+    }
+
+    /// Encode the foreground as a paletted PNG-8 in memory.
+    pub fn encode_paletted(&self, options: &PaletteOptions) -> BgrResult<Vec<u8>> {
+        encode_paletted_png(&self.image, options)
+    }
+
+    /// Crop the foreground to its subject plus `margin`, see [`trim_to_subject`].
+    pub fn trimmed(&self, margin: TrimMargin) -> ForegroundHandle {
+        ForegroundHandle {
+            image: trim_to_subject(&self.image, margin),
+        }
+    }
+
+    /// Remove background color bleeding into the soft edges, see [`defringe`].
+    pub fn defringed(&self) -> ForegroundHandle {
+        ForegroundHandle {
+            image: defringe(&self.image),
+        }
+    }
+
+    /// Draw a colored border around the subject, see [`add_stroke`].
+    pub fn with_stroke(&self, options: &StrokeOptions) -> ForegroundHandle {
+        ForegroundHandle {
+            image: add_stroke(&self.image, options),
+        }
+    }
+
+    /// Draw a soft shadow of the subject beneath it, see [`add_shadow`].
+    pub fn with_shadow(&self, options: &ShadowOptions) -> ForegroundHandle {
+        ForegroundHandle {
+            image: add_shadow(&self.image, options),
+        }
+    }
+
+    /// Place the subject on a fixed-size canvas, see [`place_on_canvas`].
+    pub fn on_canvas(&self, options: &CanvasOptions) -> ForegroundHandle {
+        ForegroundHandle {
+            image: place_on_canvas(&self.image, options),
+        }
+    }
+
+    /// Blur what lies behind the subject into a backdrop to composite over, see
+    /// [`blur_background`].
+    pub fn blurred_background(&self, sigma: f32) -> Background {
+        Background::Image(blur_background(&self.image, sigma))
+    }
+
+    /// Flatten the foreground onto a solid color or image, replacing the transparency.
+    pub fn composite(&self, background: &Background) -> RgbImage {
+        composite_over(&self.image, background)
+    }
+
+    /// Flatten the foreground onto a background on `compositor`.
+    pub fn composite_with(
+        &self,
+        background: &Background,
+        compositor: &dyn Compositor,
+    ) -> BgrResult<RgbImage> {
+        compositor.composite(&self.image, background)
+    }
 }
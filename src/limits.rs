@@ -0,0 +1,258 @@
+use std::fs;
+use std::io::{BufRead, Cursor, Seek};
+use std::path::Path;
+
+use image::{ImageDecoder, ImageReader};
+
+use crate::sniff::detect_format;
+use crate::{BgrError, BgrResult};
+
+/// Upper bounds checked before an input image is decoded.
+///
+/// The file size is checked before the file is read, and the dimensions and decoded size are
+/// read from the image header, so an oversized or crafted input (such as a tiny PNG declaring
+/// 100,000×100,000 pixels) is rejected without allocating its pixel buffer. `None` disables
+/// a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputLimits {
+    /// Maximum width or height, in pixels.
+    pub max_dimension: Option<u32>,
+    /// Maximum total pixel count.
+    pub max_pixels: Option<u64>,
+    /// Maximum size of the encoded file, in bytes.
+    pub max_file_bytes: Option<u64>,
+    /// Maximum memory the decoder may allocate, in bytes.
+    pub max_decoded_bytes: Option<u64>,
+}
+
+impl Default for InputLimits {
+    /// Generous limits that admit any real photograph: 32,768 px per side, 150 megapixels,
+    /// 256 MiB files, and 2 GiB of decoded pixels.
+    fn default() -> Self {
+        Self {
+            max_dimension: Some(32_768),
+            max_pixels: Some(150_000_000),
+            max_file_bytes: Some(256 << 20),
+            max_decoded_bytes: Some(2 << 30),
+        }
+    }
+}
+
+impl InputLimits {
+    /// No limits at all; only use with trusted inputs.
+    pub fn unlimited() -> Self {
+        Self {
+            max_dimension: None,
+            max_pixels: None,
+            max_file_bytes: None,
+            max_decoded_bytes: None,
+        }
+    }
+
+    /// Reject an encoded file larger than [`max_file_bytes`](InputLimits::max_file_bytes).
+    pub fn check_file_size(&self, bytes: u64) -> BgrResult<()> {
+        check("file size in bytes", bytes, self.max_file_bytes)
+    }
+
+    /// Reject dimensions over [`max_dimension`](InputLimits::max_dimension) or
+    /// [`max_pixels`](InputLimits::max_pixels).
+    pub fn check_dimensions(&self, width: u32, height: u32) -> BgrResult<()> {
+        let max_dimension = self.max_dimension.map(u64::from);
+        check("width", u64::from(width), max_dimension)?;
+        check("height", u64::from(height), max_dimension)?;
+        check(
+            "pixel count",
+            u64::from(width) * u64::from(height),
+            self.max_pixels,
+        )
+    }
+
+    /// Reject a decoded buffer larger than [`max_decoded_bytes`](InputLimits::max_decoded_bytes).
+    pub fn check_decoded_size(&self, bytes: u64) -> BgrResult<()> {
+        check("decoded size in bytes", bytes, self.max_decoded_bytes)
+    }
+
+    /// The same limits in the form the `image` decoders enforce while decoding.
+    pub(crate) fn decoder_limits(&self) -> image::Limits {
+        let mut limits = image::Limits::no_limits();
+        limits.max_image_width = self.max_dimension;
+        limits.max_image_height = self.max_dimension;
+        limits.max_alloc = self.max_decoded_bytes;
+        limits
+    }
+}
+
+fn check(what: &'static str, actual: u64, limit: Option<u64>) -> BgrResult<()> {
+    match limit {
+        Some(limit) if actual > limit => Err(BgrError::LimitExceeded {
+            what,
+            actual,
+            limit,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Open a decoder for `path` after checking its size and header against `limits`.
+///
+/// The decoder is chosen from the file's contents, so a JPEG saved as `.png` still loads; the
+/// extension is only used when the contents are not recognised. The returned decoder also
+/// enforces the decoded-size limit for its own allocations.
+pub fn limited_decoder(path: &Path, limits: &InputLimits) -> BgrResult<impl ImageDecoder> {
+    limits.check_file_size(fs::metadata(path)?.len())?;
+    limit_reader(ImageReader::open(path)?, limits)
+}
+
+/// Open a decoder for an encoded image held in memory, guessing its format from the contents.
+pub(crate) fn limited_decoder_from_bytes<'a>(
+    bytes: &'a [u8],
+    limits: &InputLimits,
+) -> BgrResult<impl ImageDecoder + 'a> {
+    limits.check_file_size(bytes.len() as u64)?;
+    limit_reader(ImageReader::new(Cursor::new(bytes)), limits)
+}
+
+fn limit_reader<'a, R: BufRead + Seek + 'a>(
+    reader: ImageReader<R>,
+    limits: &InputLimits,
+) -> BgrResult<impl ImageDecoder + 'a> {
+    let mut reader = detect_format(reader)?;
+    // Only the header is read here; the checks below report a clearer error than the decoder.
+    reader.no_limits();
+    let mut decoder = reader.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    limits.check_dimensions(width, height)?;
+    limits.check_decoded_size(decoder.total_bytes())?;
+    decoder.set_limits(limits.decoder_limits())?;
+    Ok(decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod check_dimensions {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn default_accepts_large_photo() {
+                assert!(
+                    InputLimits::default()
+                        .check_dimensions(8_000, 6_000)
+                        .is_ok()
+                );
+            }
+
+            #[test]
+            fn oversized_side_rejected() {
+                let err = InputLimits::default()
+                    .check_dimensions(100_000, 10)
+                    .unwrap_err();
+                assert!(matches!(
+                    err,
+                    BgrError::LimitExceeded {
+                        what: "width",
+                        actual: 100_000,
+                        limit: 32_768
+                    }
+                ));
+            }
+
+            #[test]
+            fn pixel_count_rejected_even_when_sides_fit() {
+                let limits = InputLimits {
+                    max_pixels: Some(1_000),
+                    ..InputLimits::unlimited()
+                };
+                assert!(limits.check_dimensions(40, 25).is_ok());
+                assert!(matches!(
+                    limits.check_dimensions(40, 26),
+                    Err(BgrError::LimitExceeded {
+                        what: "pixel count",
+                        ..
+                    })
+                ));
+            }
+
+            #[test]
+            fn unlimited_accepts_anything() {
+                let limits = InputLimits::unlimited();
+                assert!(limits.check_dimensions(u32::MAX, u32::MAX).is_ok());
+                assert!(limits.check_file_size(u64::MAX).is_ok());
+                assert!(limits.check_decoded_size(u64::MAX).is_ok());
+            }
+        }
+    }
+
+    mod limited_decoder {
+        use super::*;
+
+        fn write_png(name: &str, width: u32, height: u32) -> std::path::PathBuf {
+            let path =
+                std::env::temp_dir().join(format!("bgr-limits-{name}-{}.png", std::process::id()));
+            image::RgbImage::new(width, height).save(&path).unwrap();
+            path
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn loads_within_limits() {
+                let path = write_png("ok", 16, 8);
+                let decoder = limited_decoder(&path, &InputLimits::default()).unwrap();
+                assert_eq!(decoder.dimensions(), (16, 8));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn header_dimensions_rejected_before_decoding() {
+                let path = write_png("wide", 64, 4);
+                let limits = InputLimits {
+                    max_dimension: Some(32),
+                    ..InputLimits::default()
+                };
+                let result = limited_decoder(&path, &limits);
+                assert!(matches!(
+                    result,
+                    Err(BgrError::LimitExceeded { what: "width", .. })
+                ));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn decodes_from_bytes_with_guessed_format() {
+                let path = write_png("bytes-ok", 5, 3);
+                let bytes = fs::read(&path).unwrap();
+                let decoder = limited_decoder_from_bytes(&bytes, &InputLimits::default()).unwrap();
+                assert_eq!(decoder.dimensions(), (5, 3));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn misnamed_file_decoded_by_contents() {
+                let png = write_png("misnamed", 4, 2);
+                let path = png.with_extension("jpg");
+                fs::rename(&png, &path).unwrap();
+                let decoder = limited_decoder(&path, &InputLimits::default()).unwrap();
+                assert_eq!(decoder.dimensions(), (4, 2));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn file_size_rejected() {
+                let path = write_png("bytes", 8, 8);
+                let limits = InputLimits {
+                    max_file_bytes: Some(10),
+                    ..InputLimits::default()
+                };
+                let err = limited_decoder(&path, &limits).err().unwrap();
+                assert!(err.to_string().contains("file size"));
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
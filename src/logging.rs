@@ -0,0 +1,25 @@
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use crate::cli::{GlobalOptions, LogFormatArg};
+
+/// Variable overriding the log level of `-q`/`-v` with `tracing` directives, such as
+/// `bgr::inference=trace`.
+const LOG_ENV: &str = "BGR_LOG";
+
+/// Send `tracing` events to stderr, as text or as JSON lines for `--log-format json`, at the
+/// level `-q` and `-v` select. Pipeline stage spans are logged with their timings on closing,
+/// from `-vv` on.
+pub fn init(global: &GlobalOptions) {
+    let filter = EnvFilter::try_from_env(LOG_ENV)
+        .unwrap_or_else(|_| EnvFilter::default().add_directive(global.log_level().into()));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+    // A subscriber installed by an embedding process takes precedence.
+    let _ = match global.log_format {
+        LogFormatArg::Text => builder.without_time().with_target(false).try_init(),
+        LogFormatArg::Json => builder.json().with_current_span(true).try_init(),
+    };
+}
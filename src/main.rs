@@ -1,10 +1,62 @@
 mod cli;
 mod commands;
+mod config_file;
+mod examples;
+mod i18n;
+mod job_file;
+mod logging;
+mod output_template;
 
-use bgr::BgrResult;
-use clap::Parser;
+use std::process::ExitCode;
 
-fn main() -> BgrResult<()> {
-    let cli = cli::Cli::parse();
-    commands::run(cli)
+use clap::{CommandFactory, FromArgMatches};
+
+fn main() -> ExitCode {
+    // Config file defaults sit under flags and environment variables, and a project's
+    // `.bgr.toml` and its profile over the user's config file.
+    let args: Vec<_> = std::env::args_os().collect();
+    let profile = config_file::selected_profile(&args);
+    let config = config_file::ConfigFile::load().and_then(|config| {
+        let dir = std::env::current_dir()?;
+        config.with_project(&dir, profile.as_deref())
+    });
+    let command = match &config {
+        Ok(config) => config.apply(cli::Cli::command()),
+        Err(_) => cli::Cli::command(),
+    };
+    let matches = command.get_matches_from(args);
+    let cli = cli::Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    i18n::init(cli.global.lang.as_deref());
+    match &config {
+        // Without the profile's settings the run would not be the one asked for.
+        Err(err) if profile.is_some() => {
+            eprintln!("{}", i18n::tr!("error", message = err.to_string()));
+            return ExitCode::FAILURE;
+        }
+        Err(err) => eprintln!(
+            "{}",
+            i18n::tr!("config-unreadable", error = err.to_string())
+        ),
+        Ok(_) => {}
+    }
+    logging::init(&cli.global);
+    if let Ok(config) = &config {
+        for (key, reason) in config.rejected(&cli::Cli::command()) {
+            tracing::warn!(
+                "{}",
+                i18n::tr!("config-value-ignored", key = key, reason = reason)
+            );
+        }
+    }
+    match commands::run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if err.is_cancelled() => {
+            eprintln!("{}", i18n::describe(&err));
+            ExitCode::from(130)
+        }
+        Err(err) => {
+            eprintln!("{}", i18n::error(&err));
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -6,7 +6,9 @@ use imageproc::distance_transform::euclidean_squared_distance_transform;
 use imageproc::filter::gaussian_blur_f32;
 use ndarray::Array2;
 
-use crate::config::MaskProcessingOptions;
+use crate::BgrResult;
+use crate::compositor::Compositor;
+use crate::config::{ComponentArea, MaskProcessingOptions};
 
 #[cfg(feature = "vectorizer-vtracer")]
 use vtracer::ColorImage;
@@ -14,10 +16,29 @@ use vtracer::ColorImage;
 /// A single transformation step applied to a grayscale mask image.
 #[derive(Debug, Clone)]
 pub enum MaskOperation {
-    Blur { sigma: f32 },
-    Threshold { value: u8 },
-    Dilate { radius: f32 },
-    FillHoles { threshold: u8 },
+    Blur {
+        sigma: f32,
+    },
+    Threshold {
+        value: u8,
+    },
+    Dilate {
+        radius: f32,
+    },
+    Erode {
+        radius: f32,
+    },
+    FilterComponents {
+        threshold: u8,
+        min_area: Option<ComponentArea>,
+        keep_largest: bool,
+    },
+    FillHoles {
+        threshold: u8,
+    },
+    Feather {
+        radius: f32,
+    },
 }
 
 impl MaskOperation {
@@ -26,7 +47,18 @@ impl MaskOperation {
             MaskOperation::Blur { sigma } => gaussian_blur_f32(input, *sigma),
             MaskOperation::Threshold { value } => threshold_mask(input, *value),
             MaskOperation::Dilate { radius } => dilate_euclidean(input, *radius),
+            MaskOperation::Erode { radius } => erode_euclidean(input, *radius),
+            MaskOperation::FilterComponents {
+                threshold,
+                min_area,
+                keep_largest,
+            } => {
+                let (w, h) = input.dimensions();
+                let min_pixels = min_area.map_or(0, |area| area.pixels(w, h));
+                filter_components(input, *threshold, min_pixels, *keep_largest)
+            }
             MaskOperation::FillHoles { threshold } => fill_mask_holes(input, *threshold),
+            MaskOperation::Feather { radius } => feather_mask(input, *radius),
         }
     }
 }
@@ -40,6 +72,22 @@ pub fn apply_operations(source: &GrayImage, operations: &[MaskOperation]) -> Gra
     current
 }
 
+/// Run `operations` like [`apply_operations`], with blurs on `compositor`.
+pub fn apply_operations_on(
+    source: &GrayImage,
+    operations: &[MaskOperation],
+    compositor: &dyn Compositor,
+) -> BgrResult<GrayImage> {
+    let mut current = source.clone();
+    for op in operations {
+        current = match op {
+            MaskOperation::Blur { sigma } => compositor.blur(&current, *sigma)?,
+            op => op.apply(&current),
+        };
+    }
+    Ok(current)
+}
+
 /// Produce a standard operation sequence based on simple mask processing options.
 pub fn operations_from_options(options: &MaskProcessingOptions) -> Vec<MaskOperation> {
     let mut operations = Vec::new();
@@ -53,6 +101,18 @@ pub fn operations_from_options(options: &MaskProcessingOptions) -> Vec<MaskOpera
             value: options.mask_threshold,
         });
     }
+    if options.erode {
+        operations.push(MaskOperation::Erode {
+            radius: options.erosion_radius,
+        });
+    }
+    if options.keep_largest || options.min_area.is_some() {
+        operations.push(MaskOperation::FilterComponents {
+            threshold: options.mask_threshold,
+            min_area: options.min_area,
+            keep_largest: options.keep_largest,
+        });
+    }
     if options.dilate {
         operations.push(MaskOperation::Dilate {
             radius: options.dilation_radius,
@@ -63,6 +123,11 @@ pub fn operations_from_options(options: &MaskProcessingOptions) -> Vec<MaskOpera
             threshold: options.mask_threshold,
         });
     }
+    if options.feather {
+        operations.push(MaskOperation::Feather {
+            radius: options.feather_radius,
+        });
+    }
     operations
 }
 
@@ -131,6 +196,137 @@ pub fn dilate_euclidean(mask_bin: &GrayImage, r: f32) -> GrayImage {
     out
 }
 
+/// Shrink a binary mask, keeping only foreground pixels farther than `r` from the background.
+pub fn erode_euclidean(mask_bin: &GrayImage, r: f32) -> GrayImage {
+    let mut background = mask_bin.clone();
+    for pixel in background.pixels_mut() {
+        pixel[0] = if pixel[0] == 0 { 255 } else { 0 };
+    }
+    let d2 = euclidean_squared_distance_transform(&background);
+    let r2: f64 = (r as f64) * (r as f64);
+    let (w, h) = mask_bin.dimensions();
+    let mut out = GrayImage::new(w, h);
+    for (o_pixel, d2pixel) in out.pixels_mut().zip(d2.pixels()) {
+        let v: u8 = if d2pixel[0] > r2 { 255 } else { 0 };
+        *o_pixel = Luma([v]);
+    }
+    out
+}
+
+/// Soften mask edges with a Gaussian ramp roughly `radius` pixels wide.
+pub fn feather_mask(mask: &GrayImage, radius: f32) -> GrayImage {
+    if radius <= 0.0 {
+        return mask.clone();
+    }
+    gaussian_blur_f32(mask, radius / 2.0)
+}
+
+/// Remove connected foreground regions (8-connected, at or above `threshold`) that are smaller
+/// than `min_pixels`, or all but the largest when `keep_largest` is set.
+///
+/// Pixels of surviving regions keep their original values; removed pixels become black.
+pub fn filter_components(
+    mask: &GrayImage,
+    threshold: u8,
+    min_pixels: u64,
+    keep_largest: bool,
+) -> GrayImage {
+    let (w, h) = mask.dimensions();
+    let raw = mask.as_raw();
+    let (labels, sizes) = label_components(mask, threshold);
+
+    let largest = sizes
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, size)| *size)
+        .map(|(index, _)| index + 1);
+    let keep: Vec<bool> = sizes
+        .iter()
+        .enumerate()
+        .map(|(index, &size)| size >= min_pixels && (!keep_largest || largest == Some(index + 1)))
+        .collect();
+
+    let mut out = GrayImage::new(w, h);
+    for ((out_pixel, &label), &value) in out.pixels_mut().zip(&labels).zip(raw) {
+        if label != 0 && keep[label - 1] {
+            *out_pixel = Luma([value]);
+        }
+    }
+    out
+}
+
+/// Label the 8-connected regions of `mask` at or above `threshold`: each pixel gets 0 for
+/// background, otherwise 1 + the index of its region in the returned sizes.
+fn label_components(mask: &GrayImage, threshold: u8) -> (Vec<usize>, Vec<u64>) {
+    let (w, h) = (mask.width() as usize, mask.height() as usize);
+    let raw = mask.as_raw();
+    let mut labels = vec![0usize; raw.len()];
+    let mut sizes: Vec<u64> = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..raw.len() {
+        if raw[start] < threshold || labels[start] != 0 {
+            continue;
+        }
+        sizes.push(0);
+        let label = sizes.len();
+        labels[start] = label;
+        stack.push(start);
+        while let Some(id) = stack.pop() {
+            sizes[label - 1] += 1;
+            for nid in neighbors(id, w, h) {
+                if labels[nid] == 0 && raw[nid] >= threshold {
+                    labels[nid] = label;
+                    stack.push(nid);
+                }
+            }
+        }
+    }
+    (labels, sizes)
+}
+
+/// The 8-connected neighbors of pixel `id` in a `w`×`h` image, and the pixel itself.
+fn neighbors(id: usize, w: usize, h: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (id % w, id / w);
+    (y.saturating_sub(1)..(y + 2).min(h))
+        .flat_map(move |ny| (x.saturating_sub(1)..(x + 2).min(w)).map(move |nx| ny * w + nx))
+}
+
+/// Split `mask` into its subjects: the regions at or above `threshold` with at least
+/// `min_pixels` pixels, each grown into the softer pixels around it so its edges stay
+/// feathered. Returns each pixel's subject (0 for none, else 1-based, largest first) and the
+/// number of subjects.
+pub(crate) fn subject_labels(
+    mask: &GrayImage,
+    threshold: u8,
+    min_pixels: u64,
+) -> (Vec<usize>, usize) {
+    let (w, h) = (mask.width() as usize, mask.height() as usize);
+    let raw = mask.as_raw();
+    let (labels, sizes) = label_components(mask, threshold);
+    let mut order: Vec<usize> = (0..sizes.len())
+        .filter(|&index| sizes[index] >= min_pixels)
+        .collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(sizes[index]));
+    let mut subject_of = vec![0usize; sizes.len() + 1];
+    for (rank, &index) in order.iter().enumerate() {
+        subject_of[index + 1] = rank + 1;
+    }
+    let mut subjects: Vec<usize> = labels.iter().map(|&label| subject_of[label]).collect();
+
+    // Breadth-first, so a soft pixel between two subjects joins the nearer one.
+    let mut queue: VecDeque<usize> = (0..raw.len()).filter(|&id| subjects[id] != 0).collect();
+    while let Some(id) = queue.pop_front() {
+        for nid in neighbors(id, w, h) {
+            if subjects[nid] == 0 && labels[nid] == 0 && raw[nid] > 0 {
+                subjects[nid] = subjects[id];
+                queue.push_back(nid);
+            }
+        }
+    }
+    (subjects, order.len())
+}
+
 /// Fill holes in a binary mask using a flood-fill algorithm from the borders.
 pub fn fill_mask_holes(mask: &GrayImage, threshold: u8) -> GrayImage {
     let (w, h) = mask.dimensions();
@@ -621,6 +817,242 @@ mod tests {
         }
     }
 
+    mod erode_euclidean {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn solid_white_stays_white() {
+                let input = gray_image(4, 4, 255);
+                let result = erode_euclidean(&input, 2.0);
+                for px in result.pixels() {
+                    assert_eq!(px.0[0], 255);
+                }
+            }
+
+            #[test]
+            fn solid_black_stays_black() {
+                let input = gray_image(4, 4, 0);
+                let result = erode_euclidean(&input, 2.0);
+                for px in result.pixels() {
+                    assert_eq!(px.0[0], 0);
+                }
+            }
+
+            #[test]
+            fn square_shrinks_by_radius() {
+                // 9x9 black image with a white 5x5 square in the middle
+                let input = GrayImage::from_fn(9, 9, |x, y| {
+                    let inside = (2..7).contains(&x) && (2..7).contains(&y);
+                    Luma([if inside { 255 } else { 0 }])
+                });
+
+                let result = erode_euclidean(&input, 1.0);
+
+                // The square's outer ring is within 1px of the background
+                assert_eq!(result.get_pixel(2, 4).0[0], 0);
+                assert_eq!(result.get_pixel(4, 2).0[0], 0);
+                // The 3x3 core survives
+                assert_eq!(result.get_pixel(3, 3).0[0], 255);
+                assert_eq!(result.get_pixel(4, 4).0[0], 255);
+                assert_eq!(result.get_pixel(5, 5).0[0], 255);
+            }
+
+            #[test]
+            fn opening_restores_square() {
+                let input = GrayImage::from_fn(12, 12, |x, y| {
+                    let inside = (3..9).contains(&x) && (3..9).contains(&y);
+                    Luma([if inside { 255 } else { 0 }])
+                });
+
+                // The diagonal radius brings back the corners lost to erosion
+                let result = dilate_euclidean(&erode_euclidean(&input, 1.0), 1.5);
+
+                assert_eq!(result, input);
+            }
+        }
+
+        mod prop {
+            use super::*;
+            use proptest::prelude::*;
+
+            proptest! {
+                /// erode_euclidean: output is binary and never grows the foreground
+                #[test]
+                fn output_is_subset_of_input(
+                    w in 1u32..15,
+                    h in 1u32..15,
+                    seed in proptest::num::u64::ANY,
+                    radius in 0.0f32..5.0f32
+                ) {
+                    let input = GrayImage::from_fn(w, h, |x, y| {
+                        let bit = (seed >> ((x * 7 + y * 3) % 64)) & 1;
+                        Luma([if bit == 1 { 255 } else { 0 }])
+                    });
+                    let result = erode_euclidean(&input, radius);
+
+                    prop_assert_eq!(result.dimensions(), (w, h));
+                    for (out, inp) in result.pixels().zip(input.pixels()) {
+                        prop_assert!(out.0[0] == 0 || out.0[0] == 255);
+                        prop_assert!(out.0[0] <= inp.0[0]);
+                    }
+                }
+            }
+        }
+    }
+
+    mod filter_components {
+        use super::*;
+
+        /// Black 12x8 image with a 4x4 square at (1,1), a 2x2 square at (8,1), and one pixel.
+        fn blobs() -> GrayImage {
+            let mut image = gray_image(12, 8, 0);
+            for y in 1..5 {
+                for x in 1..5 {
+                    image.put_pixel(x, y, Luma([255]));
+                }
+            }
+            for y in 1..3 {
+                for x in 8..10 {
+                    image.put_pixel(x, y, Luma([200]));
+                }
+            }
+            image.put_pixel(10, 6, Luma([255]));
+            image
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn min_area_drops_small_regions() {
+                let result = filter_components(&blobs(), 128, 4, false);
+                assert_eq!(result.get_pixel(2, 2).0[0], 255);
+                assert_eq!(result.get_pixel(8, 1).0[0], 200);
+                assert_eq!(result.get_pixel(10, 6).0[0], 0);
+            }
+
+            #[test]
+            fn keep_largest_drops_everything_else() {
+                let result = filter_components(&blobs(), 128, 0, true);
+                assert_eq!(result.get_pixel(2, 2).0[0], 255);
+                assert_eq!(result.get_pixel(8, 1).0[0], 0);
+                assert_eq!(result.get_pixel(10, 6).0[0], 0);
+            }
+
+            #[test]
+            fn diagonal_pixels_form_one_region() {
+                let mut input = gray_image(4, 4, 0);
+                for i in 0..4 {
+                    input.put_pixel(i, i, Luma([255]));
+                }
+                let result = filter_components(&input, 128, 4, false);
+                assert_eq!(result, input);
+            }
+
+            #[test]
+            fn pixels_below_threshold_are_background() {
+                let result = filter_components(&blobs(), 220, 0, true);
+                assert_eq!(result.get_pixel(8, 1).0[0], 0);
+            }
+
+            #[test]
+            fn empty_mask_unchanged() {
+                let input = gray_image(5, 5, 0);
+                assert_eq!(filter_components(&input, 128, 10, true), input);
+            }
+
+            #[test]
+            fn subjects_are_numbered_largest_first() {
+                let (subjects, count) = subject_labels(&blobs(), 128, 2);
+                assert_eq!(count, 2);
+                assert_eq!(subjects[2 * 12 + 2], 1);
+                assert_eq!(subjects[12 + 8], 2);
+                assert_eq!(subjects[6 * 12 + 10], 0);
+            }
+
+            #[test]
+            fn subjects_keep_their_soft_edges() {
+                let mut mask = blobs();
+                mask.put_pixel(5, 2, Luma([40]));
+                mask.put_pixel(6, 2, Luma([40]));
+                let (subjects, _) = subject_labels(&mask, 128, 2);
+                assert_eq!(subjects[2 * 12 + 5], 1);
+                assert_eq!(subjects[2 * 12 + 6], 1);
+                assert_eq!(subjects[2 * 12 + 7], 0);
+            }
+
+            #[test]
+            fn percent_area_resolves_against_image_size() {
+                // 1% of 12x8 = 0.96 px rounds up to 1; 5% = 4.8 rounds up to 5.
+                assert_eq!(ComponentArea::Percent(1.0).pixels(12, 8), 1);
+                let min = ComponentArea::Percent(5.0).pixels(12, 8);
+                let result = filter_components(&blobs(), 128, min, false);
+                assert_eq!(result.get_pixel(8, 1).0[0], 0);
+                assert_eq!(result.get_pixel(2, 2).0[0], 255);
+            }
+        }
+
+        mod prop {
+            use super::*;
+            use proptest::prelude::*;
+
+            proptest! {
+                /// filter_components: only ever removes pixels, never changes kept values
+                #[test]
+                fn output_is_subset_of_input(
+                    w in 1u32..15,
+                    h in 1u32..15,
+                    seed in proptest::num::u64::ANY,
+                    min_pixels in 0u64..10,
+                    keep_largest in proptest::bool::ANY
+                ) {
+                    let input = GrayImage::from_fn(w, h, |x, y| {
+                        let bit = (seed >> ((x * 5 + y * 11) % 64)) & 1;
+                        Luma([if bit == 1 { 255 } else { 0 }])
+                    });
+                    let result = filter_components(&input, 128, min_pixels, keep_largest);
+
+                    prop_assert_eq!(result.dimensions(), (w, h));
+                    for (out, inp) in result.pixels().zip(input.pixels()) {
+                        prop_assert!(out.0[0] == 0 || out.0[0] == inp.0[0]);
+                    }
+                }
+            }
+        }
+    }
+
+    mod feather_mask {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn zero_radius_is_identity() {
+                let mut input = gray_image(5, 5, 0);
+                input.put_pixel(2, 2, Luma([255]));
+                assert_eq!(feather_mask(&input, 0.0), input);
+            }
+
+            #[test]
+            fn hard_edge_becomes_ramp() {
+                let input = GrayImage::from_fn(20, 1, |x, _| Luma([if x < 10 { 0 } else { 255 }]));
+
+                let result = feather_mask(&input, 4.0);
+
+                let edge = result.get_pixel(9, 0).0[0];
+                assert!(edge > 0 && edge < 255);
+                assert!(result.get_pixel(8, 0).0[0] < edge);
+                assert!(result.get_pixel(10, 0).0[0] > edge);
+                assert!(result.get_pixel(0, 0).0[0] < 5);
+                assert!(result.get_pixel(19, 0).0[0] > 250);
+            }
+        }
+    }
+
     mod dilate_euclidean {
         use super::*;
 
@@ -899,6 +1331,54 @@ mod tests {
                 ));
             }
 
+            #[test]
+            fn erode_precedes_dilate_and_feather_runs_last() {
+                let opts = MaskProcessingOptions {
+                    binary: true,
+                    erode: true,
+                    erosion_radius: 3.0,
+                    dilate: true,
+                    fill_holes: true,
+                    feather: true,
+                    feather_radius: 4.0,
+                    ..Default::default()
+                };
+                let ops = operations_from_options(&opts);
+                assert_eq!(ops.len(), 5);
+                assert!(matches!(ops[0], MaskOperation::Threshold { .. }));
+                assert!(
+                    matches!(ops[1], MaskOperation::Erode { radius } if (radius - 3.0).abs() < 1e-6)
+                );
+                assert!(matches!(ops[2], MaskOperation::Dilate { .. }));
+                assert!(matches!(ops[3], MaskOperation::FillHoles { .. }));
+                assert!(
+                    matches!(ops[4], MaskOperation::Feather { radius } if (radius - 4.0).abs() < 1e-6)
+                );
+            }
+
+            #[test]
+            fn component_filter_runs_between_erode_and_dilate() {
+                let opts = MaskProcessingOptions {
+                    erode: true,
+                    keep_largest: true,
+                    min_area: Some(ComponentArea::Pixels(50)),
+                    dilate: true,
+                    ..Default::default()
+                };
+                let ops = operations_from_options(&opts);
+                assert_eq!(ops.len(), 3);
+                assert!(matches!(ops[0], MaskOperation::Erode { .. }));
+                assert!(matches!(
+                    ops[1],
+                    MaskOperation::FilterComponents {
+                        threshold: 120,
+                        min_area: Some(ComponentArea::Pixels(50)),
+                        keep_largest: true,
+                    }
+                ));
+                assert!(matches!(ops[2], MaskOperation::Dilate { .. }));
+            }
+
             #[test]
             fn partial_pipeline_skips_disabled() {
                 let opts = MaskProcessingOptions {
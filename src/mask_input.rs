@@ -0,0 +1,397 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::limits::{InputLimits, limited_decoder};
+use crate::matte_storage::{StorageFormat, is_bgrl};
+use crate::warnings::Warning;
+use crate::{BgrError, BgrResult};
+
+/// Mask values inside this range are neither clearly foreground nor clearly background.
+const UNDECIDED: std::ops::RangeInclusive<u8> = 16..=239;
+/// Masks with at most this share of undecided pixels are treated as binary masks with
+/// compression noise, such as a black-and-white mask saved as JPEG.
+const MAX_NOISE_SHARE: f64 = 0.02;
+
+/// When a loaded mask is thresholded to pure black and white.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaskBinarize {
+    /// Threshold masks that are almost binary, with a warning; keep soft mattes as they are.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How a mask whose size differs from its image is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaskResize {
+    /// Stretch the mask to the image size, with a warning.
+    #[default]
+    Stretch,
+    /// Fail with [`BgrError::AlphaMismatch`].
+    Reject,
+}
+
+/// Settings for reading user-supplied mask files.
+///
+/// Masks come in every shape: grayscale, RGB, paletted, or a cutout whose alpha channel is
+/// the mask. A translucent alpha channel is used as the mask; otherwise the luminance is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskLoadOptions {
+    pub binarize: MaskBinarize,
+    /// Threshold used when binarizing; values above it become foreground.
+    pub threshold: u8,
+    pub resize: MaskResize,
+}
+
+impl Default for MaskLoadOptions {
+    fn default() -> Self {
+        Self {
+            binarize: MaskBinarize::Auto,
+            threshold: 127,
+            resize: MaskResize::Stretch,
+        }
+    }
+}
+
+/// A mask read from a file, with what was done to make it usable.
+#[derive(Debug, Clone)]
+pub struct LoadedMask {
+    pub mask: GrayImage,
+    pub warnings: Vec<Warning>,
+}
+
+/// Read a mask file within `limits`, fitting it to `size` (width, height) when given.
+///
+/// The decoder is chosen from the file's contents, as for inputs, so `bgrl` run-length
+/// mattes from `mask --storage rle` are read whatever their extension.
+pub fn load_mask(
+    path: impl AsRef<Path>,
+    size: Option<(u32, u32)>,
+    options: &MaskLoadOptions,
+    limits: &InputLimits,
+) -> BgrResult<LoadedMask> {
+    let path = path.as_ref();
+    if is_bgrl(path)? {
+        limits.check_file_size(fs::metadata(path)?.len())?;
+        let matte = StorageFormat::Rle.decode(&fs::read(path)?, limits)?;
+        return mask_from_image(&DynamicImage::ImageLuma8(matte.to_luma8()), size, options);
+    }
+    let image = DynamicImage::from_decoder(limited_decoder(path, limits)?)?;
+    mask_from_image(&image, size, options)
+}
+
+/// Turn an already decoded image into a mask, fitting it to `size` (width, height) when given.
+pub fn mask_from_image(
+    image: &DynamicImage,
+    size: Option<(u32, u32)>,
+    options: &MaskLoadOptions,
+) -> BgrResult<LoadedMask> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(BgrError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "mask image is empty",
+        )));
+    }
+    let mut warnings = Vec::new();
+    let mut mask = mask_channel(image);
+
+    if let Some((width, height)) = size {
+        let found = mask.dimensions();
+        if found != (width, height) {
+            if options.resize == MaskResize::Reject {
+                return Err(BgrError::AlphaMismatch {
+                    expected: (width, height),
+                    found,
+                });
+            }
+            mask = imageops::resize(&mask, width, height, FilterType::Triangle);
+            warnings.push(Warning::MaskResized {
+                width: found.0,
+                height: found.1,
+            });
+        }
+    }
+
+    let binarize = match options.binarize {
+        MaskBinarize::Always => true,
+        MaskBinarize::Never => false,
+        MaskBinarize::Auto => {
+            let noisy = is_noisy_binary(&mask);
+            if noisy {
+                warnings.push(Warning::MaskBinarized);
+            }
+            noisy
+        }
+    };
+    if binarize {
+        for pixel in mask.pixels_mut() {
+            *pixel = Luma([if pixel[0] > options.threshold { 255 } else { 0 }]);
+        }
+    }
+    Ok(LoadedMask { mask, warnings })
+}
+
+/// The alpha channel when it carries the mask, otherwise the luminance.
+fn mask_channel(image: &DynamicImage) -> GrayImage {
+    if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        if rgba.pixels().any(|pixel| pixel[3] != 255) {
+            let (width, height) = rgba.dimensions();
+            return GrayImage::from_fn(width, height, |x, y| Luma([rgba.get_pixel(x, y)[3]]));
+        }
+    }
+    image.to_luma8()
+}
+
+/// Whether the mask is meant to be binary but has values between black and white.
+fn is_noisy_binary(mask: &GrayImage) -> bool {
+    let exact = |value: u8| value == 0 || value == 255;
+    if mask.pixels().all(|pixel| exact(pixel[0])) {
+        return false;
+    }
+    let undecided = mask
+        .pixels()
+        .filter(|pixel| UNDECIDED.contains(&pixel[0]))
+        .count();
+    let pixels = u64::from(mask.width()) * u64::from(mask.height());
+    undecided as f64 / pixels as f64 <= MAX_NOISE_SHARE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    /// A 10x10 mask that is white on the left half, with `noise` added to the first row.
+    fn half(noise: u8) -> GrayImage {
+        GrayImage::from_fn(10, 10, |x, y| {
+            let value = if x < 5 { 255 } else { 0 };
+            Luma([if y == 0 && x == 0 {
+                value - noise
+            } else {
+                value
+            }])
+        })
+    }
+
+    mod mask_from_image {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn binary_gray_mask_unchanged() {
+                let mask = half(0);
+                let loaded = mask_from_image(
+                    &DynamicImage::ImageLuma8(mask.clone()),
+                    None,
+                    &MaskLoadOptions::default(),
+                )
+                .unwrap();
+                assert_eq!(loaded.mask, mask);
+                assert!(loaded.warnings.is_empty());
+            }
+
+            #[test]
+            fn rgb_mask_uses_luminance() {
+                let rgb = RgbImage::from_fn(4, 1, |x, _| {
+                    if x < 2 {
+                        Rgb([255, 255, 255])
+                    } else {
+                        Rgb([0, 0, 0])
+                    }
+                });
+                let loaded = mask_from_image(
+                    &DynamicImage::ImageRgb8(rgb),
+                    None,
+                    &MaskLoadOptions::default(),
+                )
+                .unwrap();
+                let values: Vec<u8> = loaded.mask.pixels().map(|pixel| pixel[0]).collect();
+                assert_eq!(values, [255, 255, 0, 0]);
+            }
+
+            #[test]
+            fn translucent_alpha_is_the_mask() {
+                let cutout = RgbaImage::from_fn(2, 1, |x, _| Rgba([9, 9, 9, [255, 0][x as usize]]));
+                let loaded = mask_from_image(
+                    &DynamicImage::ImageRgba8(cutout),
+                    None,
+                    &MaskLoadOptions::default(),
+                )
+                .unwrap();
+                assert_eq!(loaded.mask.as_raw(), &[255, 0]);
+            }
+
+            #[test]
+            fn opaque_alpha_falls_back_to_luminance() {
+                let opaque = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 255]));
+                let loaded = mask_from_image(
+                    &DynamicImage::ImageRgba8(opaque),
+                    None,
+                    &MaskLoadOptions::default(),
+                )
+                .unwrap();
+                assert!(loaded.mask.pixels().all(|pixel| pixel[0] == 0));
+            }
+
+            #[test]
+            fn noisy_binary_mask_binarized_with_warning() {
+                let loaded = mask_from_image(
+                    &DynamicImage::ImageLuma8(half(3)),
+                    None,
+                    &MaskLoadOptions::default(),
+                )
+                .unwrap();
+                assert_eq!(loaded.mask, half(0));
+                assert_eq!(loaded.warnings, vec![Warning::MaskBinarized]);
+            }
+
+            #[test]
+            fn soft_matte_kept_unless_asked() {
+                let ramp = GrayImage::from_fn(256, 1, |x, _| Luma([x as u8]));
+                let image = DynamicImage::ImageLuma8(ramp.clone());
+                let loaded = mask_from_image(&image, None, &MaskLoadOptions::default()).unwrap();
+                assert_eq!(loaded.mask, ramp);
+                assert!(loaded.warnings.is_empty());
+
+                let always = MaskLoadOptions {
+                    binarize: MaskBinarize::Always,
+                    ..MaskLoadOptions::default()
+                };
+                let loaded = mask_from_image(&image, None, &always).unwrap();
+                assert!(
+                    loaded
+                        .mask
+                        .pixels()
+                        .all(|pixel| matches!(pixel[0], 0 | 255))
+                );
+                assert!(loaded.warnings.is_empty());
+            }
+
+            #[test]
+            fn size_mismatch_stretched_or_rejected() {
+                let image = DynamicImage::ImageLuma8(half(0));
+                let loaded =
+                    mask_from_image(&image, Some((20, 5)), &MaskLoadOptions::default()).unwrap();
+                assert_eq!(loaded.mask.dimensions(), (20, 5));
+                assert_eq!(
+                    loaded.warnings,
+                    vec![Warning::MaskResized {
+                        width: 10,
+                        height: 10
+                    }]
+                );
+
+                let reject = MaskLoadOptions {
+                    resize: MaskResize::Reject,
+                    ..MaskLoadOptions::default()
+                };
+                let err = mask_from_image(&image, Some((20, 5)), &reject).unwrap_err();
+                assert!(matches!(
+                    err,
+                    BgrError::AlphaMismatch {
+                        expected: (20, 5),
+                        found: (10, 10)
+                    }
+                ));
+            }
+
+            #[test]
+            fn empty_image_rejected() {
+                let empty = DynamicImage::ImageLuma8(GrayImage::new(0, 0));
+                assert!(
+                    mask_from_image(&empty, Some((4, 4)), &MaskLoadOptions::default()).is_err()
+                );
+            }
+        }
+
+        mod prop {
+            use super::*;
+            use proptest::prelude::*;
+
+            proptest! {
+                /// mask_from_image: any RGBA buffer yields a mask of the requested size
+                #[test]
+                fn any_input_fits_the_target(
+                    w in 1u32..12,
+                    h in 1u32..12,
+                    target_w in 1u32..24,
+                    target_h in 1u32..24,
+                    seed in proptest::num::u32::ANY
+                ) {
+                    let rgba = RgbaImage::from_fn(w, h, |x, y| {
+                        let v = seed.wrapping_mul(x * 31 + y * 17 + 1).to_le_bytes();
+                        Rgba(v)
+                    });
+                    let loaded = mask_from_image(
+                        &DynamicImage::ImageRgba8(rgba),
+                        Some((target_w, target_h)),
+                        &MaskLoadOptions::default(),
+                    )
+                    .unwrap();
+                    prop_assert_eq!(loaded.mask.dimensions(), (target_w, target_h));
+                }
+            }
+        }
+    }
+
+    mod load_mask {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn paletted_png_loads() {
+                let temp = tempfile::tempdir().unwrap();
+                let path = temp.path().join("mask.png");
+                let file = std::fs::File::create(&path).unwrap();
+                let mut encoder = png::Encoder::new(file, 2, 1);
+                encoder.set_color(png::ColorType::Indexed);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_palette(vec![0, 0, 0, 255, 255, 255]);
+                let mut writer = encoder.write_header().unwrap();
+                writer.write_image_data(&[1, 0]).unwrap();
+                writer.finish().unwrap();
+
+                let loaded = load_mask(
+                    &path,
+                    Some((2, 1)),
+                    &MaskLoadOptions::default(),
+                    &InputLimits::default(),
+                );
+                assert_eq!(loaded.unwrap().mask.as_raw(), &[255, 0]);
+            }
+
+            #[test]
+            fn run_length_mattes_load() {
+                let temp = tempfile::tempdir().unwrap();
+                let path = temp.path().join("mask.bgrl");
+                let samples =
+                    ndarray::Array2::from_shape_fn(
+                        (10, 10),
+                        |(_, x)| {
+                            if x < 5 { 1.0 } else { 0.0 }
+                        },
+                    );
+                let matte = crate::PreciseMatte::new(samples, Vec::new());
+                std::fs::write(&path, StorageFormat::Rle.encode(&matte).unwrap()).unwrap();
+
+                let loaded = load_mask(
+                    &path,
+                    None,
+                    &MaskLoadOptions::default(),
+                    &InputLimits::default(),
+                );
+                assert_eq!(loaded.unwrap().mask, half(0));
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use sha2::{Digest, Sha256};
+// `std::time::Instant` panics in browsers.
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::{BgrResult, InferencedMatte};
+
+/// What a [`MatteCache`] entry is stored under: a SHA-256 digest of the encoded input and of
+/// anything else that changes the matte, such as the pass it was inferred with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    /// The key of `parts`, each hashed with its length so they cannot run into each other.
+    pub fn of(parts: &[&[u8]]) -> Self {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+/// Recently inferred mattes, with their decoded inputs, by the content of the input.
+///
+/// Long-running services answer a follow-up request for the same image, such as a
+/// re-composite over another background, without decoding it or running the model again.
+/// Entries expire `ttl` after they were stored, and the oldest is dropped to make room beyond
+/// `capacity`; each holds the full-resolution RGB input and matte, so keep it small.
+#[derive(Debug)]
+pub struct MatteCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, (InferencedMatte, Instant)>>,
+}
+
+impl MatteCache {
+    /// A cache of up to `capacity` mattes, each kept for `ttl`.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The matte stored under `key`, or the one `infer` returns, stored unless it failed.
+    ///
+    /// The cache is not locked while `infer` runs, so identical requests arriving together
+    /// may both infer.
+    pub fn get_or_infer(
+        &self,
+        key: CacheKey,
+        infer: impl FnOnce() -> BgrResult<InferencedMatte>,
+    ) -> BgrResult<InferencedMatte> {
+        let now = Instant::now();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|_, (_, stored)| now.duration_since(*stored) < self.ttl);
+            if let Some((matte, _)) = entries.get(&key) {
+                return Ok(matte.clone());
+            }
+        }
+        let matte = infer()?;
+        if self.capacity > 0 {
+            let mut entries = self.entries.lock().unwrap();
+            while entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (_, stored))| *stored)
+                    .map(|(key, _)| *key);
+                match oldest {
+                    Some(oldest) => entries.remove(&oldest),
+                    None => break,
+                };
+            }
+            entries.insert(key, (matte.clone(), Instant::now()));
+        }
+        Ok(matte)
+    }
+
+    /// The number of mattes stored, including expired ones not yet dropped.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no matte is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BgrError, MaskProcessingOptions};
+    use image::{GrayImage, RgbImage};
+
+    fn matte(value: u8) -> InferencedMatte {
+        InferencedMatte::new(
+            RgbImage::new(2, 2),
+            GrayImage::from_pixel(2, 2, image::Luma([value])),
+            Vec::new(),
+            MaskProcessingOptions::default(),
+        )
+    }
+
+    fn value(matte: &InferencedMatte) -> u8 {
+        matte.raw_matte().get_pixel(0, 0)[0]
+    }
+
+    mod cache_key {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn parts_do_not_run_together() {
+                assert_eq!(
+                    CacheKey::of(&[&b"ab"[..], b"c"]),
+                    CacheKey::of(&[&b"ab"[..], b"c"])
+                );
+                assert_ne!(
+                    CacheKey::of(&[&b"ab"[..], b"c"]),
+                    CacheKey::of(&[&b"a"[..], b"bc"])
+                );
+            }
+        }
+    }
+
+    mod matte_cache {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn repeated_keys_skip_inference() {
+                let cache = MatteCache::new(4, Duration::from_secs(60));
+                let key = CacheKey::of(&[&b"photo"[..]]);
+                let first = cache.get_or_infer(key, || Ok(matte(7))).unwrap();
+                let second = cache
+                    .get_or_infer(key, || panic!("the matte is cached"))
+                    .unwrap();
+                assert_eq!((value(&first), value(&second)), (7, 7));
+            }
+
+            #[test]
+            fn oldest_entry_makes_room() {
+                let cache = MatteCache::new(2, Duration::from_secs(60));
+                for part in [&b"a"[..], b"b", b"c"] {
+                    cache
+                        .get_or_infer(CacheKey::of(&[part]), || Ok(matte(part[0])))
+                        .unwrap();
+                }
+                assert_eq!(cache.len(), 2);
+                let a = cache
+                    .get_or_infer(CacheKey::of(&[&b"a"[..]]), || Ok(matte(0)))
+                    .unwrap();
+                assert_eq!(value(&a), 0);
+            }
+
+            #[test]
+            fn failures_and_expired_entries_are_not_kept() {
+                let cache = MatteCache::new(2, Duration::ZERO);
+                let key = CacheKey::of(&[&b"photo"[..]]);
+                assert!(
+                    cache
+                        .get_or_infer(key, || Err(BgrError::Daemon("busy".into())))
+                        .is_err()
+                );
+                cache.get_or_infer(key, || Ok(matte(1))).unwrap();
+                let again = cache.get_or_infer(key, || Ok(matte(2))).unwrap();
+                assert_eq!(value(&again), 2);
+
+                let disabled = MatteCache::new(0, Duration::from_secs(60));
+                disabled.get_or_infer(key, || Ok(matte(1))).unwrap();
+                assert!(disabled.is_empty());
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Storage formats for mattes, and a benchmark of their size and fidelity over a batch.
+//!
+//! Teams keeping millions of mattes pay for every byte. A soft matte compresses best as
+//! lossless WebP or PNG, while a thresholded mask is mostly long runs of 0 and 255, which the
+//! `bgrl` run-length format stores in a few bytes per row. [`StorageBenchmark`] encodes
+//! sample mattes every way and decodes them back so the choice rests on measured sizes and
+//! errors rather than guesses.
+//!
+//! A `bgrl` file is a 13-byte header followed by the runs of the samples, row by row and
+//! running on across rows:
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0 | 4 | Magic `BGRL` |
+//! | 4 | 1 | Version, `1` |
+//! | 5 | 4 | Width, little-endian `u32` |
+//! | 9 | 4 | Height, little-endian `u32` |
+//! | 13 | … | Runs: a LEB128 length of at least 1, then the 8-bit sample repeated |
+
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use exr::prelude::FlatSamples;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, GrayImage, ImageFormat, ImageReader};
+use ndarray::Array2;
+
+use crate::limits::InputLimits;
+use crate::precise::{MatteDepth, PreciseMatte};
+use crate::{BgrError, BgrResult};
+
+/// The first bytes of every `bgrl` run-length matte.
+pub const BGRL_MAGIC: [u8; 4] = *b"BGRL";
+/// The run-length format version this build writes and reads.
+const RLE_VERSION: u8 = 1;
+const RLE_HEADER_LEN: usize = 13;
+
+/// A file format a matte can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageFormat {
+    /// 8-bit grayscale PNG.
+    Png8,
+    /// 16-bit grayscale PNG.
+    Png16,
+    /// OpenEXR with one 32-bit float `Y` channel, exact but large.
+    Exr,
+    /// 8-bit lossless WebP.
+    WebP,
+    /// 8-bit `bgrl` run-length encoding, for hard-edged masks.
+    Rle,
+}
+
+impl StorageFormat {
+    /// Every format, in the order benchmarks report them.
+    pub const ALL: [Self; 5] = [Self::Png8, Self::Png16, Self::Exr, Self::WebP, Self::Rle];
+
+    /// The name benchmarks and `--storage` use.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png8 => "png8",
+            Self::Png16 => "png16",
+            Self::Exr => "exr",
+            Self::WebP => "webp",
+            Self::Rle => "rle",
+        }
+    }
+
+    /// The file extension mattes of this format are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png8 | Self::Png16 => "png",
+            Self::Exr => "exr",
+            Self::WebP => "webp",
+            Self::Rle => "bgrl",
+        }
+    }
+
+    /// The matte encoded in this format.
+    pub fn encode(self, matte: &PreciseMatte) -> BgrResult<Vec<u8>> {
+        match self {
+            Self::Png8 => matte.encode(MatteDepth::Eight),
+            Self::Png16 => matte.encode(MatteDepth::Sixteen),
+            Self::Exr => matte.encode(MatteDepth::Float),
+            Self::WebP => {
+                let eight = matte.to_luma8();
+                let mut encoded = Vec::new();
+                WebPEncoder::new_lossless(&mut encoded).encode(
+                    eight.as_raw(),
+                    eight.width(),
+                    eight.height(),
+                    ExtendedColorType::L8,
+                )?;
+                Ok(encoded)
+            }
+            Self::Rle => Ok(encode_rle(&matte.to_luma8())),
+        }
+    }
+
+    /// Read back a matte encoded in this format, rejecting dimensions beyond `limits`.
+    pub fn decode(self, bytes: &[u8], limits: &InputLimits) -> BgrResult<PreciseMatte> {
+        let samples = match self {
+            Self::Png8 | Self::Png16 | Self::WebP => {
+                let format = match self {
+                    Self::WebP => ImageFormat::WebP,
+                    _ => ImageFormat::Png,
+                };
+                let reader = || ImageReader::with_format(Cursor::new(bytes), format);
+                let (width, height) = reader().into_dimensions()?;
+                limits.check_dimensions(width, height)?;
+                let image = reader().decode()?.into_luma32f();
+                Array2::from_shape_vec((height as usize, width as usize), image.into_raw())
+                    .expect("buffer matches size")
+            }
+            Self::Exr => decode_exr(bytes, limits)?,
+            Self::Rle => {
+                let matte = decode_rle(bytes, limits)?;
+                let (width, height) = matte.dimensions();
+                let samples = matte
+                    .iter()
+                    .map(|&value| f32::from(value) / 255.0)
+                    .collect();
+                Array2::from_shape_vec((height as usize, width as usize), samples)
+                    .expect("buffer matches size")
+            }
+        };
+        Ok(PreciseMatte::new(samples, Vec::new()))
+    }
+}
+
+impl From<MatteDepth> for StorageFormat {
+    /// The format `mask --depth` writes mattes of this depth in.
+    fn from(depth: MatteDepth) -> Self {
+        match depth {
+            MatteDepth::Eight => Self::Png8,
+            MatteDepth::Sixteen => Self::Png16,
+            MatteDepth::Float => Self::Exr,
+        }
+    }
+}
+
+/// How one [`StorageFormat`] did over the mattes of a [`StorageBenchmark`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageResult {
+    pub format: StorageFormat,
+    /// Encoded size of every matte together.
+    pub bytes: u64,
+    /// The largest difference from a full-precision sample, in 8-bit levels.
+    pub max_error: f32,
+    squared_error: f64,
+    samples: u64,
+    /// Time spent encoding every matte.
+    pub encode_time: Duration,
+}
+
+impl StorageResult {
+    /// The root-mean-square difference from the full-precision samples, in 8-bit levels.
+    pub fn rms_error(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        (self.squared_error / self.samples as f64).sqrt()
+    }
+}
+
+/// Sizes and errors of every [`StorageFormat`] over sample mattes, measured by encoding each
+/// and decoding it again.
+#[derive(Debug, Clone)]
+pub struct StorageBenchmark {
+    mattes: usize,
+    results: Vec<StorageResult>,
+}
+
+impl Default for StorageBenchmark {
+    fn default() -> Self {
+        let results = StorageFormat::ALL
+            .into_iter()
+            .map(|format| StorageResult {
+                format,
+                bytes: 0,
+                max_error: 0.0,
+                squared_error: 0.0,
+                samples: 0,
+                encode_time: Duration::ZERO,
+            })
+            .collect();
+        Self { mattes: 0, results }
+    }
+}
+
+impl StorageBenchmark {
+    /// Encode `matte` in every format and measure how far each decodes from it.
+    pub fn add(&mut self, matte: &PreciseMatte) -> BgrResult<()> {
+        let limits = InputLimits::unlimited();
+        for result in &mut self.results {
+            let started = Instant::now();
+            let encoded = result.format.encode(matte)?;
+            result.encode_time += started.elapsed();
+            result.bytes += encoded.len() as u64;
+            let decoded = result.format.decode(&encoded, &limits)?;
+            for (&stored, &exact) in decoded.samples().iter().zip(matte.samples()) {
+                let error = (stored - exact).abs() * 255.0;
+                result.max_error = result.max_error.max(error);
+                result.squared_error += f64::from(error) * f64::from(error);
+            }
+            result.samples += matte.samples().len() as u64;
+        }
+        self.mattes += 1;
+        Ok(())
+    }
+
+    /// How many mattes were measured.
+    pub fn mattes(&self) -> usize {
+        self.mattes
+    }
+
+    /// One result per format, in [`StorageFormat::ALL`] order.
+    pub fn results(&self) -> &[StorageResult] {
+        &self.results
+    }
+
+    /// The format with the smallest total size whose error nowhere exceeds `max_error`
+    /// 8-bit levels, or `None` before any matte is measured.
+    pub fn choose(&self, max_error: f32) -> Option<StorageFormat> {
+        if self.mattes == 0 {
+            return None;
+        }
+        self.results
+            .iter()
+            .filter(|result| result.max_error <= max_error)
+            .min_by_key(|result| result.bytes)
+            .map(|result| result.format)
+    }
+}
+
+/// Whether the file at `path` starts with [`BGRL_MAGIC`].
+pub(crate) fn is_bgrl(path: &Path) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(BGRL_MAGIC.len());
+    File::open(path)?
+        .take(BGRL_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(magic == BGRL_MAGIC)
+}
+
+fn encode_rle(matte: &GrayImage) -> Vec<u8> {
+    let (width, height) = matte.dimensions();
+    let mut bytes = Vec::with_capacity(RLE_HEADER_LEN + height as usize * 4);
+    bytes.extend_from_slice(&BGRL_MAGIC);
+    bytes.push(RLE_VERSION);
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    let samples = matte.as_raw();
+    let mut at = 0;
+    while at < samples.len() {
+        let value = samples[at];
+        let run = samples[at..].iter().take_while(|&&s| s == value).count();
+        let mut length = run as u64;
+        while length >= 0x80 {
+            bytes.push(length as u8 | 0x80);
+            length >>= 7;
+        }
+        bytes.push(length as u8);
+        bytes.push(value);
+        at += run;
+    }
+    bytes
+}
+
+fn decode_rle(bytes: &[u8], limits: &InputLimits) -> BgrResult<GrayImage> {
+    if bytes.len() < RLE_HEADER_LEN || bytes[..4] != BGRL_MAGIC {
+        return Err(BgrError::invalid_data("not a bgrl matte"));
+    }
+    if bytes[4] != RLE_VERSION {
+        return Err(BgrError::invalid_data(format!(
+            "unsupported bgrl version {}",
+            bytes[4]
+        )));
+    }
+    let field = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("four bytes"));
+    let (width, height) = (field(5), field(9));
+    limits.check_dimensions(width, height)?;
+
+    let total = width as usize * height as usize;
+    let mut samples = Vec::with_capacity(total);
+    let mut rest = &bytes[RLE_HEADER_LEN..];
+    while samples.len() < total {
+        let mut run = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, tail) = rest
+                .split_first()
+                .ok_or_else(|| BgrError::invalid_data("bgrl matte is cut short"))?;
+            rest = tail;
+            if shift > 56 {
+                return Err(BgrError::invalid_data("bgrl run length is too long"));
+            }
+            run |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let (&value, tail) = rest
+            .split_first()
+            .ok_or_else(|| BgrError::invalid_data("bgrl matte is cut short"))?;
+        rest = tail;
+        if run == 0 || run > (total - samples.len()) as u64 {
+            return Err(BgrError::invalid_data("bgrl run does not fit the matte"));
+        }
+        samples.resize(samples.len() + run as usize, value);
+    }
+    if !rest.is_empty() {
+        return Err(BgrError::invalid_data(
+            "bgrl matte has bytes after its last run",
+        ));
+    }
+    Ok(GrayImage::from_raw(width, height, samples).expect("buffer matches size"))
+}
+
+fn decode_exr(bytes: &[u8], limits: &InputLimits) -> BgrResult<Array2<f32>> {
+    let image = exr::prelude::read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(Cursor::new(bytes))
+        .map_err(io::Error::other)?;
+    let size = image.layer_data.size;
+    limits.check_dimensions(size.0 as u32, size.1 as u32)?;
+    let channel = image
+        .layer_data
+        .channel_data
+        .list
+        .iter()
+        .find(|channel| channel.name.to_string() == "Y")
+        .ok_or_else(|| BgrError::invalid_data("the EXR matte has no Y channel"))?;
+    let FlatSamples::F32(samples) = &channel.sample_data else {
+        return Err(BgrError::invalid_data(
+            "the EXR matte's Y channel does not hold 32-bit floats",
+        ));
+    };
+    Array2::from_shape_vec((size.1, size.0), samples.clone())
+        .map_err(|_| BgrError::invalid_data("the EXR matte's Y channel does not match its size"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 32×8 matte: a hard-edged block with a soft ramp along its right side.
+    fn matte() -> PreciseMatte {
+        let samples = Array2::from_shape_fn((8, 32), |(_, x)| match x {
+            0..8 => 0.0,
+            8..20 => 1.0,
+            _ => (31 - x) as f32 / 12.3,
+        });
+        PreciseMatte::new(samples, Vec::new())
+    }
+
+    mod storage_format {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn every_format_round_trips() {
+                let matte = matte();
+                let limits = InputLimits::unlimited();
+                for format in StorageFormat::ALL {
+                    let decoded = format
+                        .decode(&format.encode(&matte).unwrap(), &limits)
+                        .unwrap();
+                    assert_eq!(decoded.dimensions(), (32, 8), "{format:?}");
+                    if matches!(format, StorageFormat::WebP | StorageFormat::Rle) {
+                        assert_eq!(decoded.to_luma8(), matte.to_luma8(), "{format:?}");
+                    }
+                }
+            }
+
+            #[test]
+            fn rle_rejects_runs_past_the_matte() {
+                let mut encoded = StorageFormat::Rle.encode(&matte()).unwrap();
+                let limits = InputLimits::unlimited();
+                // Lengthen the first run so the runs after it overflow the matte.
+                encoded[RLE_HEADER_LEN] = 0x7f;
+                assert!(StorageFormat::Rle.decode(&encoded, &limits).is_err());
+                encoded.truncate(RLE_HEADER_LEN + 1);
+                assert!(StorageFormat::Rle.decode(&encoded, &limits).is_err());
+            }
+        }
+    }
+
+    mod storage_benchmark {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn chooses_the_smallest_format_within_the_error() {
+                let mut benchmark = StorageBenchmark::default();
+                assert_eq!(benchmark.choose(1.0), None);
+                benchmark.add(&matte()).unwrap();
+                let result = |format| {
+                    benchmark
+                        .results()
+                        .iter()
+                        .find(|result| result.format == format)
+                        .unwrap()
+                        .clone()
+                };
+                assert_eq!(result(StorageFormat::Exr).max_error, 0.0);
+                assert!(result(StorageFormat::Png8).max_error <= 0.5 + 1e-3);
+                assert!(result(StorageFormat::Png16).max_error < 0.01);
+                let loose = benchmark.choose(1.0).unwrap();
+                assert!(!matches!(loose, StorageFormat::Png16 | StorageFormat::Exr));
+                assert!(matches!(
+                    benchmark.choose(0.01),
+                    Some(StorageFormat::Png16 | StorageFormat::Exr)
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use image::{GrayImage, Luma, RgbImage};
+
+use crate::mask::{dilate_euclidean, erode_euclidean};
+use crate::{BgrError, BgrResult};
+
+/// Trimap value for pixels that are certainly background.
+pub const TRIMAP_BACKGROUND: u8 = 0;
+/// Trimap value for pixels whose alpha is solved by the matting pass.
+pub const TRIMAP_UNKNOWN: u8 = 128;
+/// Trimap value for pixels that are certainly foreground.
+pub const TRIMAP_FOREGROUND: u8 = 255;
+
+/// Settings for the alpha matting refinement pass.
+///
+/// The coarse matte is split into a trimap: pixels farther than `band_radius` from the
+/// thresholded edge keep their hard value. In the band in between, alpha is estimated from how
+/// close each pixel's color is to the nearby foreground versus background, then smoothed with a
+/// color guided filter so that it follows the edges of the original image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MattingOptions {
+    /// Threshold that separates foreground from background in the coarse matte.
+    pub threshold: u8,
+    /// Half-width of the unknown band around the coarse edge, in pixels.
+    pub band_radius: f32,
+    /// Radius of the guided filter window, in pixels.
+    pub filter_radius: u32,
+    /// Regularization of the guided filter; smaller values keep finer detail such as hair.
+    pub epsilon: f32,
+}
+
+impl Default for MattingOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            band_radius: 8.0,
+            filter_radius: 8,
+            epsilon: 1e-3,
+        }
+    }
+}
+
+/// Build a trimap from a coarse matte.
+///
+/// The result holds [`TRIMAP_FOREGROUND`], [`TRIMAP_BACKGROUND`], or [`TRIMAP_UNKNOWN`] for
+/// every pixel.
+pub fn trimap(matte: &GrayImage, threshold: u8, band_radius: f32) -> GrayImage {
+    let (w, h) = matte.dimensions();
+    let binary = GrayImage::from_fn(w, h, |x, y| {
+        Luma([if matte.get_pixel(x, y)[0] > threshold {
+            255
+        } else {
+            0
+        }])
+    });
+    let sure_foreground = erode_euclidean(&binary, band_radius);
+    let maybe_foreground = dilate_euclidean(&binary, band_radius);
+    GrayImage::from_fn(w, h, |x, y| {
+        let value = if sure_foreground.get_pixel(x, y)[0] > 0 {
+            TRIMAP_FOREGROUND
+        } else if maybe_foreground.get_pixel(x, y)[0] == 0 {
+            TRIMAP_BACKGROUND
+        } else {
+            TRIMAP_UNKNOWN
+        };
+        Luma([value])
+    })
+}
+
+/// Refine a coarse matte against the original image, producing soft alpha along the edges.
+pub fn refine_matte(
+    rgb: &RgbImage,
+    matte: &GrayImage,
+    options: &MattingOptions,
+) -> BgrResult<GrayImage> {
+    let expected = rgb.dimensions();
+    let found = matte.dimensions();
+    if expected != found {
+        return Err(BgrError::AlphaMismatch { expected, found });
+    }
+
+    let (w, h) = expected;
+    let (wu, hu) = (w as usize, h as usize);
+    let trimap = trimap(matte, options.threshold, options.band_radius);
+    let guide: [Vec<f32>; 3] =
+        std::array::from_fn(|c| rgb.pixels().map(|p| f32::from(p[c]) / 255.0).collect());
+    let coarse: Vec<f32> = matte.pixels().map(|p| f32::from(p[0]) / 255.0).collect();
+    let known: Vec<u8> = trimap.pixels().map(|p| p[0]).collect();
+
+    let estimate = estimate_alpha(&guide, &coarse, &known, wu, hu, options);
+    let filtered = guided_filter(
+        &guide,
+        &estimate,
+        wu,
+        hu,
+        options.filter_radius as usize,
+        options.epsilon,
+    );
+
+    let mut out = GrayImage::new(w, h);
+    for (i, pixel) in out.pixels_mut().enumerate() {
+        pixel[0] = match known[i] {
+            TRIMAP_UNKNOWN => (filtered[i].clamp(0.0, 1.0) * 255.0).round() as u8,
+            value => value,
+        };
+    }
+    Ok(out)
+}
+
+/// Initial alpha for the unknown band: each pixel's color projected onto the line between the
+/// mean colors of the nearby certain foreground and background.
+///
+/// Pixels with no certain region of either kind in reach keep their coarse value.
+fn estimate_alpha(
+    guide: &[Vec<f32>; 3],
+    coarse: &[f32],
+    known: &[u8],
+    w: usize,
+    h: usize,
+    options: &MattingOptions,
+) -> Vec<f32> {
+    // Reach across the whole band from its centre, plus the filter window.
+    let reach = (2.0 * options.band_radius).ceil() as usize + options.filter_radius as usize;
+    let local_mean = |value: u8| -> ([Vec<f32>; 3], Vec<f32>) {
+        let weight: Vec<f32> = known.iter().map(|&k| f32::from(k == value)).collect();
+        let sums = std::array::from_fn(|c| {
+            let masked: Vec<f32> = guide[c].iter().zip(&weight).map(|(g, m)| g * m).collect();
+            box_mean(&masked, w, h, reach)
+        });
+        (sums, box_mean(&weight, w, h, reach))
+    };
+    let (fg_sum, fg_weight) = local_mean(TRIMAP_FOREGROUND);
+    let (bg_sum, bg_weight) = local_mean(TRIMAP_BACKGROUND);
+
+    (0..w * h)
+        .map(|i| match known[i] {
+            TRIMAP_FOREGROUND => 1.0,
+            TRIMAP_BACKGROUND => 0.0,
+            _ if fg_weight[i] <= 0.0 || bg_weight[i] <= 0.0 => coarse[i],
+            _ => {
+                let fg = [0, 1, 2].map(|c| fg_sum[c][i] / fg_weight[i]);
+                let bg = [0, 1, 2].map(|c| bg_sum[c][i] / bg_weight[i]);
+                let (mut along, mut length) = (0.0, 0.0);
+                for c in 0..3 {
+                    let axis = fg[c] - bg[c];
+                    along += (guide[c][i] - bg[c]) * axis;
+                    length += axis * axis;
+                }
+                // Indistinguishable colors give no evidence either way.
+                if length < 1e-4 {
+                    coarse[i]
+                } else {
+                    (along / length).clamp(0.0, 1.0)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Edge-preserving filter of `input` steered by a three-channel `guide` (He et al., 2010).
+///
+/// Every window fits `input` as a linear function of the guide colors, so the output inherits
+/// the guide's edges while staying close to `input` elsewhere.
+fn guided_filter(
+    guide: &[Vec<f32>; 3],
+    input: &[f32],
+    w: usize,
+    h: usize,
+    radius: usize,
+    epsilon: f32,
+) -> Vec<f32> {
+    let mean = |data: &[f32]| box_mean(data, w, h, radius);
+    let product =
+        |a: &[f32], b: &[f32]| -> Vec<f32> { a.iter().zip(b).map(|(x, y)| x * y).collect() };
+
+    let mean_i: [Vec<f32>; 3] = std::array::from_fn(|c| mean(&guide[c]));
+    let mean_p = mean(input);
+    let mean_ip: [Vec<f32>; 3] = std::array::from_fn(|c| mean(&product(&guide[c], input)));
+    // Upper triangle of the guide covariance: rr, rg, rb, gg, gb, bb.
+    let pairs = [(0, 0), (0, 1), (0, 2), (1, 1), (1, 2), (2, 2)];
+    let mean_ii: Vec<Vec<f32>> = pairs
+        .iter()
+        .map(|&(a, b)| mean(&product(&guide[a], &guide[b])))
+        .collect();
+
+    let n = w * h;
+    let mut coef: [Vec<f32>; 3] = std::array::from_fn(|_| vec![0.0; n]);
+    let mut offset = vec![0.0; n];
+    for i in 0..n {
+        let mu = [0, 1, 2].map(|c| f64::from(mean_i[c][i]));
+        let mean_pi = f64::from(mean_p[i]);
+        let cov_ip = [0, 1, 2].map(|c| f64::from(mean_ip[c][i]) - mu[c] * mean_pi);
+        let var = |k: usize| {
+            let (a, b) = pairs[k];
+            f64::from(mean_ii[k][i]) - mu[a] * mu[b]
+        };
+        let eps = f64::from(epsilon);
+        let sigma = [
+            [var(0) + eps, var(1), var(2)],
+            [var(1), var(3) + eps, var(4)],
+            [var(2), var(4), var(5) + eps],
+        ];
+        let a = solve_symmetric(sigma, cov_ip);
+        for c in 0..3 {
+            coef[c][i] = a[c] as f32;
+        }
+        offset[i] = (mean_pi - a[0] * mu[0] - a[1] * mu[1] - a[2] * mu[2]) as f32;
+    }
+
+    let mean_coef: [Vec<f32>; 3] = std::array::from_fn(|c| mean(&coef[c]));
+    let mean_offset = mean(&offset);
+    (0..n)
+        .map(|i| {
+            mean_coef[0][i] * guide[0][i]
+                + mean_coef[1][i] * guide[1][i]
+                + mean_coef[2][i] * guide[2][i]
+                + mean_offset[i]
+        })
+        .collect()
+}
+
+/// Solve `m · x = v` for a symmetric positive-definite 3×3 matrix.
+///
+/// Works in `f64`: with a small epsilon and strongly correlated channels the determinant is
+/// close to `epsilon²`, well below what `f32` cofactors resolve.
+fn solve_symmetric(m: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    let cof = [
+        m[1][1] * m[2][2] - m[1][2] * m[2][1],
+        m[1][2] * m[2][0] - m[1][0] * m[2][2],
+        m[1][0] * m[2][1] - m[1][1] * m[2][0],
+        m[0][2] * m[2][1] - m[0][1] * m[2][2],
+        m[0][0] * m[2][2] - m[0][2] * m[2][0],
+        m[0][1] * m[2][0] - m[0][0] * m[2][1],
+        m[0][1] * m[1][2] - m[0][2] * m[1][1],
+        m[0][2] * m[1][0] - m[0][0] * m[1][2],
+        m[0][0] * m[1][1] - m[0][1] * m[1][0],
+    ];
+    let det = m[0][0] * cof[0] + m[0][1] * cof[1] + m[0][2] * cof[2];
+    if det == 0.0 {
+        return [0.0; 3];
+    }
+    [
+        (cof[0] * v[0] + cof[3] * v[1] + cof[6] * v[2]) / det,
+        (cof[1] * v[0] + cof[4] * v[1] + cof[7] * v[2]) / det,
+        (cof[2] * v[0] + cof[5] * v[1] + cof[8] * v[2]) / det,
+    ]
+}
+
+/// Mean over a `(2r + 1)²` window, shrunk at the image border, via a summed-area table.
+pub(crate) fn box_mean(data: &[f32], w: usize, h: usize, r: usize) -> Vec<f32> {
+    let stride = w + 1;
+    let mut sums = vec![0.0f64; stride * (h + 1)];
+    for y in 0..h {
+        let mut row = 0.0f64;
+        for x in 0..w {
+            row += f64::from(data[y * w + x]);
+            sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+        }
+    }
+
+    let mut out = vec![0.0; w * h];
+    for y in 0..h {
+        let (y0, y1) = (y.saturating_sub(r), (y + r + 1).min(h));
+        for x in 0..w {
+            let (x0, x1) = (x.saturating_sub(r), (x + r + 1).min(w));
+            let total = sums[y1 * stride + x1] - sums[y0 * stride + x1] - sums[y1 * stride + x0]
+                + sums[y0 * stride + x0];
+            let count = ((y1 - y0) * (x1 - x0)) as f64;
+            out[y * w + x] = (total / count) as f32;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// A matte that is white from column `edge` onwards.
+    fn step_matte(w: u32, h: u32, edge: u32) -> GrayImage {
+        GrayImage::from_fn(w, h, |x, _| Luma([if x >= edge { 255 } else { 0 }]))
+    }
+
+    mod box_mean {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn constant_input_unchanged() {
+                let data = vec![0.25; 20];
+                for value in box_mean(&data, 5, 4, 2) {
+                    assert!((value - 0.25).abs() < 1e-6);
+                }
+            }
+
+            #[test]
+            fn window_shrinks_at_border() {
+                // 1D ramp 0..5: the corner window covers columns 0 and 1 only.
+                let data: Vec<f32> = (0..5).map(|v| v as f32).collect();
+                let result = box_mean(&data, 5, 1, 1);
+                assert!((result[0] - 0.5).abs() < 1e-6);
+                assert!((result[2] - 2.0).abs() < 1e-6);
+                assert!((result[4] - 3.5).abs() < 1e-6);
+            }
+        }
+    }
+
+    mod trimap {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn band_surrounds_edge() {
+                let result = trimap(&step_matte(30, 3, 15), 128, 3.0);
+                assert_eq!(result.get_pixel(5, 1)[0], TRIMAP_BACKGROUND);
+                assert_eq!(result.get_pixel(14, 1)[0], TRIMAP_UNKNOWN);
+                assert_eq!(result.get_pixel(16, 1)[0], TRIMAP_UNKNOWN);
+                assert_eq!(result.get_pixel(25, 1)[0], TRIMAP_FOREGROUND);
+            }
+
+            #[test]
+            fn empty_matte_is_all_background() {
+                let result = trimap(&GrayImage::new(6, 6), 128, 2.0);
+                assert!(result.pixels().all(|p| p[0] == TRIMAP_BACKGROUND));
+            }
+        }
+    }
+
+    mod refine_matte {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn dimension_mismatch_rejected() {
+                let rgb = RgbImage::new(4, 4);
+                let matte = GrayImage::new(4, 5);
+                let result = refine_matte(&rgb, &matte, &MattingOptions::default());
+                assert!(matches!(result, Err(BgrError::AlphaMismatch { .. })));
+            }
+
+            #[test]
+            fn known_regions_keep_hard_values() {
+                let rgb = RgbImage::from_fn(40, 4, |x, _| {
+                    Rgb(if x >= 20 {
+                        [240, 200, 40]
+                    } else {
+                        [20, 30, 60]
+                    })
+                });
+                let refined =
+                    refine_matte(&rgb, &step_matte(40, 4, 20), &MattingOptions::default()).unwrap();
+                assert_eq!(refined.get_pixel(2, 2)[0], 0);
+                assert_eq!(refined.get_pixel(37, 2)[0], 255);
+            }
+
+            #[test]
+            fn alpha_snaps_to_color_edge() {
+                // The subject's colors start at column 20, but the coarse matte only at 23.
+                let rgb = RgbImage::from_fn(48, 6, |x, _| {
+                    Rgb(if x >= 20 {
+                        [230, 220, 210]
+                    } else {
+                        [10, 20, 30]
+                    })
+                });
+                let options = MattingOptions {
+                    filter_radius: 4,
+                    epsilon: 1e-4,
+                    ..MattingOptions::default()
+                };
+                let refined = refine_matte(&rgb, &step_matte(48, 6, 23), &options).unwrap();
+                assert!(refined.get_pixel(21, 3)[0] > 128);
+                assert!(refined.get_pixel(18, 3)[0] < 64);
+            }
+        }
+    }
+
+    mod solve_symmetric {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn solves_diagonal_system() {
+                let m = [[2.0, 0.0, 0.0], [0.0, 4.0, 0.0], [0.0, 0.0, 0.5]];
+                let x = solve_symmetric(m, [1.0, 2.0, 3.0]);
+                assert!((x[0] - 0.5).abs() < 1e-6);
+                assert!((x[1] - 0.5).abs() < 1e-6);
+                assert!((x[2] - 6.0).abs() < 1e-6);
+            }
+
+            #[test]
+            fn singular_matrix_yields_zero() {
+                let x = solve_symmetric([[0.0; 3]; 3], [1.0, 1.0, 1.0]);
+                assert_eq!(x, [0.0; 3]);
+            }
+        }
+    }
+}
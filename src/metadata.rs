@@ -0,0 +1,395 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use image::{ImageDecoder, ImageFormat};
+
+use crate::BgrResult;
+use crate::limits::{InputLimits, limited_decoder};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// How much of an input is searched for its density; PNG `pHYs` chunks come before the image
+/// data, and JPEG JFIF segments first of all.
+const DENSITY_SEARCH_BYTES: u64 = 1 << 16;
+const EXIF_ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_SHORT: u16 = 3;
+/// The JPEG segment length field counts itself, and EXIF segments start with `Exif\0\0`.
+const JPEG_MAX_EXIF_BYTES: usize = u16::MAX as usize - 8;
+const INCHES_PER_METER: f64 = 39.3701;
+
+/// Metadata an output carries over from its input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageMetadata {
+    /// EXIF data as a TIFF structure.
+    pub exif: Option<Vec<u8>>,
+    /// The embedded ICC profile.
+    pub icc: Option<Vec<u8>>,
+    /// Horizontal and vertical pixels per inch.
+    pub dpi: Option<[u32; 2]>,
+}
+
+impl ImageMetadata {
+    /// Read the metadata of the image at `path` within `limits`. Metadata that cannot be read
+    /// is left out rather than failing, as it does when decoding.
+    pub fn read(path: impl AsRef<Path>, limits: &InputLimits) -> BgrResult<Self> {
+        let path = path.as_ref();
+        let mut decoder = limited_decoder(path, limits)?;
+        let exif = decoder.exif_metadata().ok().flatten().map(|mut exif| {
+            if exif.starts_with(b"Exif\0\0") {
+                exif.drain(..6);
+            }
+            exif
+        });
+        let icc = decoder.icc_profile().ok().flatten();
+        let mut head = Vec::new();
+        File::open(path)?
+            .take(DENSITY_SEARCH_BYTES)
+            .read_to_end(&mut head)?;
+        Ok(Self {
+            exif,
+            icc,
+            dpi: read_dpi(&head),
+        })
+    }
+
+    /// Reset the EXIF orientation to upright, for outputs of inputs that decoding already
+    /// rotated.
+    pub fn upright(mut self) -> Self {
+        if let Some(exif) = &mut self.exif {
+            reset_orientation(exif);
+        }
+        self
+    }
+
+    /// Add the EXIF data and density to `encoded`, for PNG, JPEG, and WebP; other formats are
+    /// returned as they are, as is a file whose layout is not the one bgr's encoders write.
+    ///
+    /// The ICC profile is not embedded here: it only describes pixels that were converted
+    /// into it, which [`encode_for_profile`](crate::encode_for_profile) does.
+    pub fn embed(&self, encoded: Vec<u8>) -> Vec<u8> {
+        if self.exif.is_none() && self.dpi.is_none() {
+            return encoded;
+        }
+        match image::guess_format(&encoded) {
+            Ok(ImageFormat::Png) => self.embed_png(encoded),
+            Ok(ImageFormat::Jpeg) => self.embed_jpeg(encoded),
+            Ok(ImageFormat::WebP) => self.embed_webp(encoded),
+            _ => encoded,
+        }
+    }
+
+    /// Insert `pHYs` and `eXIf` chunks after the header.
+    fn embed_png(&self, mut encoded: Vec<u8>) -> Vec<u8> {
+        let header_end = PNG_SIGNATURE.len() + 8 + 13 + 4;
+        if encoded.get(12..16) != Some(b"IHDR".as_slice()) || encoded.len() < header_end {
+            return encoded;
+        }
+        let mut chunks = Vec::new();
+        if let Some([x, y]) = self.dpi {
+            let mut data = Vec::with_capacity(9);
+            data.extend_from_slice(&per_meter(x).to_be_bytes());
+            data.extend_from_slice(&per_meter(y).to_be_bytes());
+            data.push(1);
+            png_chunk(&mut chunks, b"pHYs", &data);
+        }
+        if let Some(exif) = &self.exif {
+            png_chunk(&mut chunks, b"eXIf", exif);
+        }
+        encoded.splice(header_end..header_end, chunks);
+        encoded
+    }
+
+    /// Set the JFIF density and insert an EXIF segment after it.
+    fn embed_jpeg(&self, mut encoded: Vec<u8>) -> Vec<u8> {
+        if encoded.get(2..4) != Some([0xff, 0xe0].as_slice())
+            || encoded.get(6..11) != Some(b"JFIF\0".as_slice())
+        {
+            return encoded;
+        }
+        if let (Some([x, y]), Some(density)) = (self.dpi, encoded.get_mut(13..18)) {
+            density[0] = 1;
+            density[1..3].copy_from_slice(&clamp_u16(x).to_be_bytes());
+            density[3..5].copy_from_slice(&clamp_u16(y).to_be_bytes());
+        }
+        if let Some(exif) = self
+            .exif
+            .as_ref()
+            .filter(|exif| exif.len() <= JPEG_MAX_EXIF_BYTES)
+        {
+            let jfif_end = 4 + usize::from(u16::from_be_bytes([encoded[4], encoded[5]]));
+            let mut segment = vec![0xff, 0xe1];
+            segment.extend_from_slice(&((exif.len() + 8) as u16).to_be_bytes());
+            segment.extend_from_slice(b"Exif\0\0");
+            segment.extend_from_slice(exif);
+            encoded.splice(jfif_end..jfif_end, segment);
+        }
+        encoded
+    }
+
+    /// Append an `EXIF` chunk, turning a simple file into the extended format that allows
+    /// one. WebP has no density.
+    fn embed_webp(&self, encoded: Vec<u8>) -> Vec<u8> {
+        let Some(exif) = &self.exif else {
+            return encoded;
+        };
+        let (Some(first), Some(bitstream)) = (encoded.get(12..16), encoded.get(20..)) else {
+            return encoded;
+        };
+        let mut webp = encoded[..12].to_vec();
+        match first {
+            b"VP8X" if !bitstream.is_empty() => {
+                webp.extend_from_slice(&encoded[12..]);
+                webp[20] |= 0x08;
+            }
+            b"VP8L" | b"VP8 " => {
+                let Some((width, height, alpha)) = webp_canvas(first, bitstream) else {
+                    return encoded;
+                };
+                let mut extended = vec![if alpha { 0x18 } else { 0x08 }, 0, 0, 0];
+                extended.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+                extended.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+                riff_chunk(&mut webp, b"VP8X", &extended);
+                webp.extend_from_slice(&encoded[12..]);
+            }
+            _ => return encoded,
+        }
+        riff_chunk(&mut webp, b"EXIF", exif);
+        let riff_size = (webp.len() - 8) as u32;
+        webp[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        webp
+    }
+}
+
+/// The density a PNG `pHYs` chunk or JPEG JFIF segment at the start of a file gives, if it
+/// is in physical units.
+fn read_dpi(head: &[u8]) -> Option<[u32; 2]> {
+    if let Some(mut chunks) = head.strip_prefix(PNG_SIGNATURE) {
+        while chunks.len() >= 8 {
+            let length = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+            let data = chunks.get(8..8 + length)?;
+            match &chunks[4..8] {
+                b"pHYs" if data.len() == 9 && data[8] == 1 => {
+                    let x = u32::from_be_bytes(data[..4].try_into().ok()?);
+                    let y = u32::from_be_bytes(data[4..8].try_into().ok()?);
+                    return Some([per_inch(x), per_inch(y)]);
+                }
+                b"IDAT" => return None,
+                _ => chunks = chunks.get(12 + length..)?,
+            }
+        }
+        None
+    } else if head.get(..4) == Some([0xff, 0xd8, 0xff, 0xe0].as_slice())
+        && head.get(6..11) == Some(b"JFIF\0".as_slice())
+    {
+        let x = u16::from_be_bytes([*head.get(14)?, *head.get(15)?]);
+        let y = u16::from_be_bytes([*head.get(16)?, *head.get(17)?]);
+        match head.get(13)? {
+            1 => Some([u32::from(x), u32::from(y)]),
+            2 => Some([u32::from(x), u32::from(y)].map(|cm| (f64::from(cm) * 2.54).round() as u32)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Set the EXIF orientation to 1, upright, leaving data that is not a TIFF structure alone.
+fn reset_orientation(exif: &mut [u8]) {
+    let big_endian = match exif.get(..4) {
+        Some(b"MM\0*") => true,
+        Some(b"II*\0") => false,
+        _ => return,
+    };
+    let u16_at = |exif: &[u8], at: usize| {
+        let bytes = [*exif.get(at)?, *exif.get(at + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let Some(&[a, b, c, d]) = exif.get(4..8) else {
+        return;
+    };
+    let offset = [a, b, c, d];
+    let ifd = if big_endian {
+        u32::from_be_bytes(offset)
+    } else {
+        u32::from_le_bytes(offset)
+    } as usize;
+    let Some(entries) = u16_at(exif, ifd) else {
+        return;
+    };
+    for entry in 0..usize::from(entries) {
+        let at = ifd + 2 + 12 * entry;
+        if u16_at(exif, at) == Some(EXIF_ORIENTATION_TAG)
+            && u16_at(exif, at + 2) == Some(EXIF_SHORT)
+        {
+            let upright = if big_endian {
+                1u16.to_be_bytes()
+            } else {
+                1u16.to_le_bytes()
+            };
+            if let Some(value) = exif.get_mut(at + 8..at + 10) {
+                value.copy_from_slice(&upright);
+            }
+            return;
+        }
+    }
+}
+
+/// The width, height, and alpha of a simple WebP's `VP8L` or `VP8 ` bitstream.
+fn webp_canvas(kind: &[u8], bitstream: &[u8]) -> Option<(u32, u32, bool)> {
+    if kind == b"VP8L" {
+        if *bitstream.first()? != 0x2f {
+            return None;
+        }
+        let bits = u32::from_le_bytes(bitstream.get(1..5)?.try_into().ok()?);
+        Some((
+            (bits & 0x3fff) + 1,
+            ((bits >> 14) & 0x3fff) + 1,
+            bits & (1 << 28) != 0,
+        ))
+    } else {
+        if bitstream.get(3..6)? != [0x9d, 0x01, 0x2a] {
+            return None;
+        }
+        let width = u16::from_le_bytes([bitstream[6], *bitstream.get(7)?]) & 0x3fff;
+        let height = u16::from_le_bytes([*bitstream.get(8)?, *bitstream.get(9)?]) & 0x3fff;
+        Some((u32::from(width), u32::from(height), false))
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Append a RIFF chunk, padded to an even length.
+fn riff_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+fn per_meter(dpi: u32) -> u32 {
+    (f64::from(dpi) * INCHES_PER_METER).round() as u32
+}
+
+fn per_inch(per_meter: u32) -> u32 {
+    (f64::from(per_meter) / INCHES_PER_METER).round() as u32
+}
+
+fn clamp_u16(value: u32) -> u16 {
+    value.min(u32::from(u16::MAX)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::png::PngEncoder;
+    use image::codecs::webp::WebPEncoder;
+    use image::metadata::Orientation;
+    use image::{DynamicImage, ImageReader, RgbaImage};
+    use std::io::Cursor;
+
+    /// Little-endian EXIF holding only an orientation of 6 (rotated 90°).
+    fn rotated_exif() -> Vec<u8> {
+        let mut exif = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        exif.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0, 0, 0, 0x06, 0, 0, 0]);
+        exif.extend_from_slice(&[0, 0, 0, 0]);
+        exif
+    }
+
+    fn metadata() -> ImageMetadata {
+        ImageMetadata {
+            exif: Some(rotated_exif()),
+            icc: None,
+            dpi: Some([300, 150]),
+        }
+        .upright()
+    }
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(5, 3));
+        let mut encoded = Cursor::new(Vec::new());
+        match format {
+            ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut encoded)),
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(JpegEncoder::new(&mut encoded)),
+            _ => image.write_with_encoder(WebPEncoder::new_lossless(&mut encoded)),
+        }
+        .unwrap();
+        encoded.into_inner()
+    }
+
+    /// The EXIF data and dimensions of `encoded` as the image crate reads them back.
+    fn read_back(encoded: &[u8]) -> (Option<Vec<u8>>, (u32, u32)) {
+        let mut decoder = ImageReader::new(Cursor::new(encoded))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        let exif = decoder.exif_metadata().unwrap();
+        (exif, decoder.dimensions())
+    }
+
+    mod image_metadata {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn orientation_is_reset_to_upright() {
+                let mut exif = rotated_exif();
+                assert_eq!(
+                    Orientation::from_exif_chunk(&exif),
+                    Some(Orientation::Rotate90)
+                );
+                reset_orientation(&mut exif);
+                assert_eq!(
+                    Orientation::from_exif_chunk(&exif),
+                    Some(Orientation::NoTransforms)
+                );
+            }
+
+            #[test]
+            fn png_and_jpeg_keep_exif_and_density() {
+                for format in [ImageFormat::Png, ImageFormat::Jpeg] {
+                    let embedded = metadata().embed(encoded(format));
+                    let (exif, dimensions) = read_back(&embedded);
+                    assert_eq!(dimensions, (5, 3), "{format:?}");
+                    let exif = exif.expect("EXIF read back");
+                    assert!(exif.ends_with(&metadata().exif.unwrap()), "{format:?}");
+                    assert_eq!(read_dpi(&embedded), Some([300, 150]), "{format:?}");
+                }
+            }
+
+            #[test]
+            fn simple_webp_becomes_extended_for_exif() {
+                let embedded = metadata().embed(encoded(ImageFormat::WebP));
+                assert_eq!(&embedded[12..16], b"VP8X");
+                let (exif, dimensions) = read_back(&embedded);
+                assert_eq!(dimensions, (5, 3));
+                assert_eq!(exif, metadata().exif);
+            }
+
+            #[test]
+            fn nothing_to_embed_leaves_the_file_alone() {
+                let png = encoded(ImageFormat::Png);
+                assert_eq!(ImageMetadata::default().embed(png.clone()), png);
+                assert_eq!(read_dpi(&png), None);
+            }
+        }
+    }
+}
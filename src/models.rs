@@ -3,19 +3,36 @@
 //! Provides named model presets (birefnet, isnet, u2net, etc.) that automatically
 //! download from HuggingFace on first use.
 
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-/// Default models directory (~/.bgr/models)
-pub fn default_models_dir() -> PathBuf {
-    directories::ProjectDirs::from("", "", "bgr")
-        .map(|dirs| dirs.data_dir().to_path_buf())
+#[cfg(feature = "download")]
+use crate::CancellationToken;
+use crate::adapter::Architecture;
+#[cfg(feature = "download")]
+use crate::progress::{DownloadEvent, ProgressSink};
+
+/// File in the data directory that records accepted model licenses.
+const ACCEPTED_LICENSES_FILE: &str = "accepted-licenses.json";
+
+/// Default data directory for bgr's own state (~/.bgr)
+pub fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("bgr"))
         .unwrap_or_else(|| {
             dirs::home_dir()
                 .unwrap_or_else(|| PathBuf::from("."))
                 .join(".bgr")
         })
-        .join("models")
+}
+
+/// Default models directory (~/.bgr/models)
+pub fn default_models_dir() -> PathBuf {
+    default_data_dir().join("models")
 }
 
 /// Known model presets with their HuggingFace sources.
@@ -58,18 +75,106 @@ impl ModelPreset {
         }
     }
 
-    /// Human-readable description.
+    /// Human-readable description of the weights the preset downloads.
     pub fn description(&self) -> &'static str {
         match self {
-            ModelPreset::BiRefNet => "U2Net full - best quality, general purpose",
-            ModelPreset::BiRefNetLite => "U2Netp - fast, good for portraits",
-            ModelPreset::IsNet => "IS-Net - anime/illustration focused",
-            ModelPreset::U2Net => "U2Net full - classic, well-tested",
-            ModelPreset::U2NetP => "U2Netp - lightweight, portrait optimized",
-            ModelPreset::Rmbg => "U2Net - reliable general purpose",
+            ModelPreset::BiRefNet => "U2-Net weights until BiRefNet is mirrored as ONNX",
+            ModelPreset::BiRefNetLite => "U2-Netp weights; small and fast, rougher edges",
+            ModelPreset::IsNet => "IS-Net trained on anime; best for illustrations",
+            ModelPreset::U2Net => "U2-Net; the classic general-purpose model",
+            ModelPreset::U2NetP => "U2-Netp; small and fast, for portraits",
+            ModelPreset::Rmbg => "RMBG 1.4 by BRIA AI; non-commercial license",
+        }
+    }
+
+    /// Structured quality, speed, use, and license information.
+    pub fn metadata(&self) -> PresetMetadata {
+        let (quality, speed, use_case, license) = match self {
+            ModelPreset::BiRefNet => (
+                QualityTier::Standard,
+                SpeedTier::Slow,
+                UseCase::General,
+                License::Apache2,
+            ),
+            ModelPreset::BiRefNetLite => (
+                QualityTier::Basic,
+                SpeedTier::Fast,
+                UseCase::General,
+                License::Apache2,
+            ),
+            ModelPreset::IsNet => (
+                QualityTier::High,
+                SpeedTier::Moderate,
+                UseCase::Illustration,
+                License::Apache2,
+            ),
+            ModelPreset::U2Net => (
+                QualityTier::Standard,
+                SpeedTier::Slow,
+                UseCase::General,
+                License::Apache2,
+            ),
+            ModelPreset::U2NetP => (
+                QualityTier::Basic,
+                SpeedTier::Fast,
+                UseCase::Portrait,
+                License::Apache2,
+            ),
+            ModelPreset::Rmbg => (
+                QualityTier::Standard,
+                SpeedTier::Slow,
+                UseCase::General,
+                License::BriaRmbg,
+            ),
+        };
+        PresetMetadata {
+            quality,
+            speed,
+            use_case,
+            license,
         }
     }
 
+    /// The preset best suited to `use_case`, by `priority` and then by the other criterion.
+    ///
+    /// Presets for a different use are only chosen when none matches.
+    pub fn recommend(use_case: UseCase, priority: Priority) -> ModelPreset {
+        Self::ranked(use_case, priority)[0]
+    }
+
+    /// Every preset, best first for `use_case` and `priority`; ties keep [`ALL`](Self::ALL)
+    /// order.
+    pub fn ranked(use_case: UseCase, priority: Priority) -> Vec<ModelPreset> {
+        let mut presets = Self::ALL.to_vec();
+        presets.sort_by_key(|preset| {
+            let meta = preset.metadata();
+            let (first, second) = match priority {
+                Priority::Quality => (meta.quality as u8, meta.speed as u8),
+                Priority::Speed => (meta.speed as u8, meta.quality as u8),
+            };
+            (
+                meta.use_case != use_case,
+                std::cmp::Reverse(first),
+                std::cmp::Reverse(second),
+            )
+        });
+        presets
+    }
+
+    /// The preset `--model auto` stands for: the best general-purpose preset already in
+    /// `models_dir`, or the best one overall when none is downloaded. Presets with a
+    /// restrictive license are never picked automatically.
+    pub fn auto(models_dir: &Path) -> ModelPreset {
+        Self::ranked(UseCase::General, Priority::Quality)
+            .into_iter()
+            .filter(|preset| {
+                let meta = preset.metadata();
+                meta.use_case == UseCase::General && !meta.license.is_restrictive()
+            })
+            .find(|preset| preset.is_downloaded(models_dir))
+            .unwrap_or_else(|| Self::recommend(UseCase::General, Priority::Quality))
+    }
+
     /// Approximate model size in MB.
     pub fn size_mb(&self) -> u32 {
         match self {
@@ -114,6 +219,21 @@ impl ModelPreset {
         }
     }
 
+    /// The architecture of the weights [`download_url`](Self::download_url) serves, which
+    /// decides how they are pre- and post-processed.
+    ///
+    /// The BiRefNet and RMBG mirrors serve U2-Net exports, so they are handled as U2-Net.
+    pub fn architecture(&self) -> Architecture {
+        match self {
+            ModelPreset::IsNet => Architecture::IsNet,
+            ModelPreset::BiRefNet
+            | ModelPreset::BiRefNetLite
+            | ModelPreset::U2Net
+            | ModelPreset::U2NetP
+            | ModelPreset::Rmbg => Architecture::U2Net,
+        }
+    }
+
     /// Local filename for the model.
     pub fn filename(&self) -> &'static str {
         match self {
@@ -144,12 +264,136 @@ impl ModelPreset {
         models_dir.join(self.filename())
     }
 
+    /// Get the path where the candle backend looks for this preset's safetensors weights.
+    ///
+    /// These are not downloaded; convert the upstream PyTorch checkpoint once and place it
+    /// here.
+    pub fn safetensors_path(&self, models_dir: &Path) -> PathBuf {
+        self.local_path(models_dir).with_extension("safetensors")
+    }
+
+    /// Whether the candle backend can run this preset, whose network it rebuilds: only the
+    /// U2-Net and U2-Netp presets.
+    pub fn runs_on_candle(&self) -> bool {
+        matches!(self, ModelPreset::U2Net | ModelPreset::U2NetP)
+    }
+
     /// Check if the model is already downloaded.
     pub fn is_downloaded(&self, models_dir: &Path) -> bool {
         self.local_path(models_dir).exists()
     }
 }
 
+/// The model specifier that picks a preset with [`ModelPreset::auto`].
+pub const AUTO_MODEL: &str = "auto";
+
+/// How good a preset's mattes are, compared with the other presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QualityTier {
+    Basic,
+    Standard,
+    High,
+}
+
+/// How quickly a preset runs on the CPU, compared with the other presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpeedTier {
+    Slow,
+    Moderate,
+    Fast,
+}
+
+/// The kind of image a preset was trained for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UseCase {
+    /// Photos of products, people, animals, and scenes.
+    General,
+    /// Photos of people.
+    Portrait,
+    /// Anime, drawings, and other illustrations.
+    Illustration,
+}
+
+/// The license a preset's weights are published under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum License {
+    Apache2,
+    /// BRIA AI's RMBG license, which allows non-commercial use only.
+    BriaRmbg,
+}
+
+impl License {
+    /// Whether the license limits how outputs may be used, such as to non-commercial work.
+    pub fn is_restrictive(self) -> bool {
+        matches!(self, License::BriaRmbg)
+    }
+
+    /// Where the license terms can be read.
+    pub fn url(self) -> &'static str {
+        match self {
+            License::Apache2 => "https://www.apache.org/licenses/LICENSE-2.0",
+            License::BriaRmbg => "https://huggingface.co/briaai/RMBG-1.4",
+        }
+    }
+}
+
+/// What to favor when recommending a preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    #[default]
+    Quality,
+    Speed,
+}
+
+/// Structured facts about a preset, as shown by `models list --detailed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresetMetadata {
+    pub quality: QualityTier,
+    pub speed: SpeedTier,
+    /// What the preset is recommended for.
+    pub use_case: UseCase,
+    pub license: License,
+}
+
+impl fmt::Display for QualityTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QualityTier::Basic => "basic",
+            QualityTier::Standard => "standard",
+            QualityTier::High => "high",
+        })
+    }
+}
+
+impl fmt::Display for SpeedTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SpeedTier::Slow => "slow",
+            SpeedTier::Moderate => "moderate",
+            SpeedTier::Fast => "fast",
+        })
+    }
+}
+
+impl fmt::Display for UseCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UseCase::General => "general",
+            UseCase::Portrait => "portrait",
+            UseCase::Illustration => "illustration",
+        })
+    }
+}
+
+impl fmt::Display for License {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            License::Apache2 => "Apache-2.0",
+            License::BriaRmbg => "BRIA RMBG (non-commercial)",
+        })
+    }
+}
+
 /// Errors that can occur during model operations.
 #[derive(Debug, Error)]
 pub enum ModelError {
@@ -164,16 +408,128 @@ pub enum ModelError {
         source: std::io::Error,
     },
 
+    #[error(
+        "Cannot write to models directory {path}: {source}; check its permissions or set --models-dir"
+    )]
+    NotWritable {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[error("Failed to download model from {url}: {message}")]
     Download { url: String, message: String },
 
     #[error("Model file not found: {0}")]
     NotFound(PathBuf),
 
+    #[error("Download cancelled; the partial file is kept and resumed next time")]
+    Cancelled,
+
+    #[error(
+        "Not enough disk space in {path}: the download needs {needed} bytes but {available} are available; free some space or set --models-dir"
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error(
+        "{model} is published under the {license} license ({url}); review it and pass --accept-license to download it"
+    )]
+    LicenseNotAccepted {
+        model: &'static str,
+        license: License,
+        url: &'static str,
+    },
+
+    #[error(
+        "The download of {model} is corrupt: its SHA-256 is {actual}, not the published {expected}; download it again"
+    )]
+    ChecksumMismatch {
+        model: &'static str,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Cannot read accepted licenses from {path}: {message}")]
+    LicenseRecord { path: PathBuf, message: String },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// One accepted license, as recorded in the data directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseAcceptance {
+    /// The preset name, such as `rmbg`.
+    pub model: String,
+    /// The license as displayed, such as `BRIA RMBG (non-commercial)`.
+    pub license: String,
+    /// Seconds since the Unix epoch.
+    pub accepted_at: u64,
+}
+
+/// Every license accepted so far in `data_dir`, oldest first.
+pub fn accepted_licenses(data_dir: &Path) -> Result<Vec<LicenseAcceptance>, ModelError> {
+    let path = data_dir.join(ACCEPTED_LICENSES_FILE);
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| ModelError::LicenseRecord {
+            path,
+            message: e.to_string(),
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Whether `preset` may be downloaded: its license is not restrictive, or its current license
+/// was accepted in `data_dir`.
+pub fn license_accepted(preset: ModelPreset, data_dir: &Path) -> Result<bool, ModelError> {
+    let license = preset.metadata().license;
+    if !license.is_restrictive() {
+        return Ok(true);
+    }
+    let license = license.to_string();
+    Ok(accepted_licenses(data_dir)?
+        .iter()
+        .any(|record| record.model == preset.name() && record.license == license))
+}
+
+/// Fail with [`ModelError::LicenseNotAccepted`] unless [`license_accepted`] holds.
+pub fn require_license(preset: ModelPreset, data_dir: &Path) -> Result<(), ModelError> {
+    if license_accepted(preset, data_dir)? {
+        return Ok(());
+    }
+    let license = preset.metadata().license;
+    Err(ModelError::LicenseNotAccepted {
+        model: preset.name(),
+        license,
+        url: license.url(),
+    })
+}
+
+/// Record in `data_dir` that the license of `preset` was accepted now.
+///
+/// Earlier acceptances are kept, so the file doubles as an audit trail.
+pub fn accept_license(preset: ModelPreset, data_dir: &Path) -> Result<(), ModelError> {
+    let mut records = accepted_licenses(data_dir)?;
+    records.push(LicenseAcceptance {
+        model: preset.name().to_string(),
+        license: preset.metadata().license.to_string(),
+        accepted_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+    });
+    std::fs::create_dir_all(data_dir).map_err(|e| ModelError::CreateDir {
+        path: data_dir.to_path_buf(),
+        source: e,
+    })?;
+    let json = serde_json::to_vec_pretty(&records).map_err(std::io::Error::other)?;
+    std::fs::write(data_dir.join(ACCEPTED_LICENSES_FILE), json)?;
+    Ok(())
+}
+
 /// Resolve a model specifier to a local path.
 ///
 /// If `specifier` is a known preset name, returns the path in models_dir
@@ -224,17 +580,94 @@ pub fn resolve_model_path(
     }
 }
 
-/// Download a model from HuggingFace.
-#[cfg(feature = "cli")]
+/// Compute the SHA-256 checksum of a file as lowercase hex.
+pub fn file_sha256(path: &Path) -> Result<String, ModelError> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The SHA-256 a downloaded preset was verified against when it was downloaded, as lowercase
+/// hex, or `None` for presets downloaded without a published digest or by older versions.
+pub fn recorded_sha256(preset: ModelPreset, models_dir: &Path) -> Option<String> {
+    let recorded = std::fs::read_to_string(sha256_path(&preset.local_path(models_dir))).ok()?;
+    Some(recorded.trim().to_string())
+}
+
+/// Where the digest of the model file at `path` is kept.
+fn sha256_path(path: &Path) -> PathBuf {
+    path.with_extension("onnx.sha256")
+}
+
+/// Delete a downloaded preset from the models directory.
+///
+/// Returns `false` if the preset was not downloaded.
+pub fn remove_model(preset: ModelPreset, models_dir: &Path) -> Result<bool, ModelError> {
+    let path = preset.local_path(models_dir);
+    if !path.exists() {
+        return Ok(false);
+    }
+    std::fs::remove_file(&path)?;
+    let _ = std::fs::remove_file(sha256_path(&path));
+    Ok(true)
+}
+
+/// Fail unless a file can be created in `dir`, as the models directory and batch output
+/// directories must allow before anything is downloaded or processed.
+pub fn check_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".bgr-write-check-{}", std::process::id()));
+    std::fs::File::create(&probe)?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Download a model from HuggingFace, reporting to `progress`.
+///
+/// The file is written next to its final path with a `.onnx.tmp` extension and renamed once
+/// complete. When such a partial file is left from an interrupted download, the transfer
+/// resumes after it with an `If-Range` request on the ETag recorded when it started, so a
+/// model changed upstream in the meantime is downloaded afresh. The finished file is checked
+/// against the SHA-256 HuggingFace publishes for it, failing with
+/// [`ModelError::ChecksumMismatch`], and the digest is kept for [`recorded_sha256`]. The
+/// download fails before writing anything if the models directory lacks the space for it,
+/// and stops with [`ModelError::Cancelled`] between chunks once `cancel` is cancelled.
+#[cfg(feature = "download")]
 pub async fn download_model(
     preset: ModelPreset,
     models_dir: &Path,
-    progress_callback: Option<Box<dyn Fn(u64, u64) + Send>>,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<PathBuf, ModelError> {
+    let result = fetch(preset, models_dir, progress, cancel).await;
+    if let Err(err) = &result {
+        progress.download(&DownloadEvent::Failed {
+            model: preset.name(),
+            error: err.to_string(),
+        });
+    }
+    result
+}
+
+#[cfg(feature = "download")]
+async fn fetch(
+    preset: ModelPreset,
+    models_dir: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
 ) -> Result<PathBuf, ModelError> {
+    use futures_util::StreamExt;
+    use reqwest::StatusCode;
     use tokio::io::AsyncWriteExt;
 
     let url = preset.download_url();
     let local_path = preset.local_path(models_dir);
+    let download_error = |message: String| ModelError::Download {
+        url: url.to_string(),
+        message,
+    };
 
     // Create models directory if needed
     if !models_dir.exists() {
@@ -244,74 +677,206 @@ pub async fn download_model(
         })?;
     }
 
-    // Download with progress
+    check_writable(models_dir).map_err(|source| ModelError::NotWritable {
+        path: models_dir.to_path_buf(),
+        source,
+    })?;
+
+    // Write to temp file first, then rename. A leftover one is resumed only while the file
+    // it came from is unchanged, which the validators recorded next to it tell.
+    let temp_path = local_path.with_extension("onnx.tmp");
+    let record_path = local_path.with_extension("onnx.tmp.json");
+    let published = published_sha256(url).await;
+    let record = std::fs::read(&record_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<PartialDownload>(&bytes).ok())
+        .filter(|record| record.sha256 == published);
+    let partial = match &record {
+        Some(_) => tokio::fs::metadata(&temp_path)
+            .await
+            .map_or(0, |metadata| metadata.len()),
+        None => 0,
+    };
+
     let client = reqwest::Client::new();
-    let response = client
-        .get(url)
+    let mut request = client.get(url);
+    if partial > 0
+        && let Some(etag) = record.as_ref().and_then(|record| record.etag.as_deref())
+    {
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={partial}-"))
+            .header(reqwest::header::IF_RANGE, etag);
+    }
+    let mut response = request
         .send()
         .await
-        .map_err(|e| ModelError::Download {
-            url: url.to_string(),
-            message: e.to_string(),
-        })?;
-
+        .map_err(|e| download_error(e.to_string()))?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is not a prefix of the current model; start over.
+        response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| download_error(e.to_string()))?;
+    }
     if !response.status().is_success() {
-        return Err(ModelError::Download {
-            url: url.to_string(),
-            message: format!("HTTP {}", response.status()),
-        });
+        return Err(download_error(format!("HTTP {}", response.status())));
     }
 
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded: u64 = 0;
-
-    // Write to temp file first, then rename
-    let temp_path = local_path.with_extension("onnx.tmp");
-    let mut file = tokio::fs::File::create(&temp_path)
-        .await
-        .map_err(ModelError::Io)?;
+    let resumed_from = if response.status() == StatusCode::PARTIAL_CONTENT {
+        partial
+    } else {
+        0
+    };
+    let remaining = response.content_length();
+    if let Some(remaining) = remaining {
+        check_disk_space(models_dir, remaining)?;
+    }
+    let total = remaining.map(|remaining| remaining + resumed_from);
+    progress.download(&DownloadEvent::Started {
+        model: preset.name(),
+        total,
+        resumed_from,
+    });
+
+    let mut file = if resumed_from > 0 {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&temp_path)
+            .await
+    } else {
+        let record = PartialDownload {
+            etag: response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            sha256: published.clone(),
+        };
+        std::fs::write(
+            &record_path,
+            serde_json::to_vec(&record).map_err(std::io::Error::from)?,
+        )?;
+        tokio::fs::File::create(&temp_path).await
+    }
+    .map_err(ModelError::Io)?;
 
+    let mut downloaded = resumed_from;
     let mut stream = response.bytes_stream();
-    use futures_util::StreamExt;
-
     while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| ModelError::Download {
-            url: url.to_string(),
-            message: e.to_string(),
-        })?;
-
+        if cancel.is_cancelled() {
+            file.flush().await.map_err(ModelError::Io)?;
+            return Err(ModelError::Cancelled);
+        }
+        let chunk = chunk.map_err(|e| download_error(e.to_string()))?;
         file.write_all(&chunk).await.map_err(ModelError::Io)?;
         downloaded += chunk.len() as u64;
-
-        if let Some(ref cb) = progress_callback {
-            cb(downloaded, total_size);
-        }
+        progress.download(&DownloadEvent::Progress {
+            model: preset.name(),
+            downloaded,
+            total,
+        });
     }
 
     file.flush().await.map_err(ModelError::Io)?;
     drop(file);
 
-    // Rename temp to final
+    let actual = file_sha256(&temp_path)?;
+    if let Some(expected) = published
+        && actual != expected
+    {
+        let _ = std::fs::remove_file(&temp_path);
+        let _ = std::fs::remove_file(&record_path);
+        return Err(ModelError::ChecksumMismatch {
+            model: preset.name(),
+            expected,
+            actual,
+        });
+    }
+
+    // Rename temp to final, keeping its digest for later checks
     tokio::fs::rename(&temp_path, &local_path)
         .await
         .map_err(ModelError::Io)?;
+    std::fs::write(sha256_path(&local_path), format!("{actual}\n"))?;
+    let _ = std::fs::remove_file(&record_path);
 
+    progress.download(&DownloadEvent::Finished {
+        model: preset.name(),
+        path: local_path.clone(),
+    });
     Ok(local_path)
 }
 
+/// The validators of a partial download, kept next to it so a resume can tell whether the
+/// file upstream is still the one it started on.
+#[cfg(feature = "download")]
+#[derive(Debug, Serialize, Deserialize)]
+struct PartialDownload {
+    etag: Option<String>,
+    sha256: Option<String>,
+}
+
+/// The SHA-256 HuggingFace publishes for the LFS file at `url`, read from the
+/// `X-Linked-Etag` of its response before the redirect to the storage host.
+#[cfg(feature = "download")]
+async fn published_sha256(url: &str) -> Option<String> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .ok()?;
+    let response = client.head(url).send().await.ok()?;
+    let etag = response.headers().get("x-linked-etag")?.to_str().ok()?;
+    let digest = etag.trim_start_matches("W/").trim_matches('"');
+    (digest.len() == 64 && digest.bytes().all(|byte| byte.is_ascii_hexdigit()))
+        .then(|| digest.to_ascii_lowercase())
+}
+
+/// Fail unless the filesystem holding `dir` has `needed` bytes available.
+#[cfg(feature = "download")]
+fn check_disk_space(dir: &Path, needed: u64) -> Result<(), ModelError> {
+    let available = fs4::available_space(dir)?;
+    if available < needed {
+        return Err(ModelError::InsufficientSpace {
+            path: dir.to_path_buf(),
+            needed,
+            available,
+        });
+    }
+    Ok(())
+}
+
 /// Synchronous download wrapper for non-async contexts.
-#[cfg(feature = "cli")]
+#[cfg(feature = "download")]
 pub fn download_model_sync(
     preset: ModelPreset,
     models_dir: &Path,
-    progress_callback: Option<Box<dyn Fn(u64, u64) + Send>>,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
 ) -> Result<PathBuf, ModelError> {
+    download_models_sync(&[preset], models_dir, progress, cancel).map(|mut paths| paths.remove(0))
+}
+
+/// Download several presets at once, returning their paths in the same order.
+///
+/// The first failure cancels the other downloads; their partial files are kept for resuming.
+#[cfg(feature = "download")]
+pub fn download_models_sync(
+    presets: &[ModelPreset],
+    models_dir: &Path,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>, ModelError> {
     let rt = tokio::runtime::Runtime::new().map_err(|e| ModelError::Download {
-        url: preset.download_url().to_string(),
+        url: presets
+            .first()
+            .map_or_else(String::new, |preset| preset.download_url().to_string()),
         message: format!("Failed to create async runtime: {e}"),
     })?;
 
-    rt.block_on(download_model(preset, models_dir, progress_callback))
+    rt.block_on(futures_util::future::try_join_all(presets.iter().map(
+        |&preset| download_model(preset, models_dir, progress, cancel),
+    )))
 }
 
 #[cfg(test)]
@@ -333,6 +898,59 @@ mod tests {
         assert_eq!(ModelPreset::from_str("unknown"), None);
     }
 
+    #[test]
+    fn sha256_of_known_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("abc.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let digest = file_sha256(&path).unwrap();
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[cfg(feature = "download")]
+    #[test]
+    fn disk_space_checked_before_download() {
+        let dir = std::env::temp_dir();
+        assert!(check_disk_space(&dir, 0).is_ok());
+        assert!(matches!(
+            check_disk_space(&dir, u64::MAX),
+            Err(ModelError::InsufficientSpace {
+                needed: u64::MAX,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn unwritable_models_dir_fails_early() {
+        let dir = std::env::temp_dir().join("bgr-no-such-models-dir/nested");
+        assert!(check_writable(&dir).is_err());
+        assert!(check_writable(&std::env::temp_dir()).is_ok());
+    }
+
+    #[test]
+    fn recorded_digest_is_read_back_and_removed_with_the_model() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = ModelPreset::U2NetP.local_path(&dir);
+        std::fs::write(&path, b"model").unwrap();
+        std::fs::write(sha256_path(&path), "ab12\n").unwrap();
+        let recorded = recorded_sha256(ModelPreset::U2NetP, &dir);
+        remove_model(ModelPreset::U2NetP, &dir).unwrap();
+        let removed = sha256_path(&path).exists();
+        assert_eq!(recorded.as_deref(), Some("ab12"));
+        assert!(!removed);
+    }
+
+    #[test]
+    fn remove_missing_model_is_noop() {
+        let dir = std::env::temp_dir().join("bgr-no-such-models-dir");
+        assert!(!remove_model(ModelPreset::IsNet, &dir).unwrap());
+    }
+
     #[test]
     fn all_presets_have_valid_data() {
         for preset in ModelPreset::ALL {
@@ -343,4 +961,54 @@ mod tests {
             assert!(preset.size_mb() > 0);
         }
     }
+
+    #[test]
+    fn recommendations_follow_use_and_priority() {
+        assert_eq!(
+            ModelPreset::recommend(UseCase::Illustration, Priority::Quality),
+            ModelPreset::IsNet
+        );
+        assert_eq!(
+            ModelPreset::recommend(UseCase::Portrait, Priority::Speed),
+            ModelPreset::U2NetP
+        );
+        let general = ModelPreset::ranked(UseCase::General, Priority::Speed);
+        assert_eq!(general[0], ModelPreset::BiRefNetLite);
+        assert_eq!(general.len(), ModelPreset::ALL.len());
+    }
+
+    #[test]
+    fn auto_prefers_downloaded_unrestricted_presets() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let empty = ModelPreset::auto(&dir);
+        std::fs::write(ModelPreset::Rmbg.local_path(&dir), b"").unwrap();
+        let restricted_only = ModelPreset::auto(&dir);
+        std::fs::write(ModelPreset::BiRefNetLite.local_path(&dir), b"").unwrap();
+        let downloaded = ModelPreset::auto(&dir);
+
+        assert_eq!(empty, ModelPreset::BiRefNet);
+        assert_eq!(restricted_only, ModelPreset::BiRefNet);
+        assert_eq!(downloaded, ModelPreset::BiRefNetLite);
+    }
+
+    #[test]
+    fn restrictive_licenses_need_recorded_acceptance() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        assert!(license_accepted(ModelPreset::U2Net, &dir).unwrap());
+        assert!(!license_accepted(ModelPreset::Rmbg, &dir).unwrap());
+        assert!(matches!(
+            require_license(ModelPreset::Rmbg, &dir),
+            Err(ModelError::LicenseNotAccepted { model: "rmbg", .. })
+        ));
+
+        accept_license(ModelPreset::Rmbg, &dir).unwrap();
+        let accepted = license_accepted(ModelPreset::Rmbg, &dir).unwrap();
+        let records = accepted_licenses(&dir).unwrap();
+
+        assert!(accepted);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].model, "rmbg");
+    }
 }
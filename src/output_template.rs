@@ -0,0 +1,214 @@
+use std::io;
+
+/// A value an [`OutputTemplate`] can name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The input file name without its extension.
+    Stem,
+    /// The model preset name, or the file stem of a model path.
+    Model,
+    /// Today's UTC date, as `YYYY-MM-DD`.
+    Date,
+    /// The input's dimensions, as `WIDTHxHEIGHT`.
+    Size,
+    /// The first eight hex digits of the input's SHA-256 checksum.
+    Hash,
+    /// The extension the output format would be written with.
+    Ext,
+}
+
+impl Field {
+    const ALL: [Self; 6] = [
+        Self::Stem,
+        Self::Model,
+        Self::Date,
+        Self::Size,
+        Self::Hash,
+        Self::Ext,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Stem => "stem",
+            Self::Model => "model",
+            Self::Date => "date",
+            Self::Size => "size",
+            Self::Hash => "hash",
+            Self::Ext => "ext",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+/// A piece of a `{name}` template: literal text, or the name of a placeholder.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Piece<'a> {
+    Text(String),
+    Placeholder(&'a str),
+}
+
+/// Split `template` into literal text and `{name}` placeholders, with `{{` and `}}` standing
+/// for literal braces, failing on an unmatched brace.
+pub fn tokenize(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let brace = &rest[start..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            text.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+        let end = brace
+            .find('}')
+            .filter(|_| brace.starts_with('{'))
+            .ok_or_else(|| format!("unmatched brace in `{template}`"))?;
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(Piece::Placeholder(&brace[1..end]));
+        rest = &brace[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// A `--output-template` such as `{stem}_{model}_nobg.{ext}`, checked when it is parsed so a
+/// typo fails before the first input rather than after the model loads.
+///
+/// `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    segments: Vec<Segment>,
+}
+
+impl OutputTemplate {
+    /// Parse a template, rejecting unknown placeholders and unmatched braces.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let segments = tokenize(template)?
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => Ok(Segment::Text(text)),
+                Piece::Placeholder(name) => Field::ALL
+                    .into_iter()
+                    .find(|field| field.name() == name)
+                    .map(Segment::Field)
+                    .ok_or_else(|| {
+                        format!(
+                            "unknown placeholder {{{name}}}; \
+                             expected stem, model, date, size, hash, or ext"
+                        )
+                    }),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if segments.is_empty() {
+            return Err("the output template is empty".to_string());
+        }
+        Ok(Self { segments })
+    }
+
+    /// Whether the template names `field`.
+    pub fn uses(&self, field: Field) -> bool {
+        self.segments.contains(&Segment::Field(field))
+    }
+
+    /// The template with each placeholder replaced by `value(field)`, asked only for the
+    /// fields it names, so a template without `{hash}` never reads the input.
+    pub fn render(&self, mut value: impl FnMut(Field) -> io::Result<String>) -> io::Result<String> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Field(field) => rendered.push_str(&value(*field)?),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod tokenize {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn splits_text_and_placeholders() {
+                assert_eq!(
+                    tokenize("{{a}} {b}.c").unwrap(),
+                    [
+                        Piece::Text("{a} ".to_string()),
+                        Piece::Placeholder("b"),
+                        Piece::Text(".c".to_string()),
+                    ]
+                );
+                assert!(tokenize("{b").is_err());
+                assert!(tokenize("b}").is_err());
+            }
+        }
+    }
+
+    mod output_template {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            fn sample(field: Field) -> io::Result<String> {
+                Ok(match field {
+                    Field::Stem => "shoe",
+                    Field::Model => "isnet",
+                    Field::Date => "2026-10-14",
+                    Field::Size => "640x480",
+                    Field::Hash => "0123abcd",
+                    Field::Ext => "png",
+                }
+                .to_string())
+            }
+
+            #[test]
+            fn renders_every_placeholder() {
+                let template =
+                    OutputTemplate::parse("{date}/{stem}_{model}_{size}_{hash}.{ext}").unwrap();
+                assert_eq!(
+                    template.render(sample).unwrap(),
+                    "2026-10-14/shoe_isnet_640x480_0123abcd.png"
+                );
+                let braces = OutputTemplate::parse("{{{stem}}}.{ext}").unwrap();
+                assert_eq!(braces.render(sample).unwrap(), "{shoe}.png");
+            }
+
+            #[test]
+            fn only_named_fields_are_computed() {
+                let template = OutputTemplate::parse("{stem}.{ext}").unwrap();
+                let rendered = template.render(|field| match field {
+                    Field::Hash | Field::Size => panic!("{field:?} is not in the template"),
+                    field => sample(field),
+                });
+                assert_eq!(rendered.unwrap(), "shoe.png");
+            }
+
+            #[test]
+            fn rejects_typos_and_stray_braces() {
+                assert!(OutputTemplate::parse("{stme}.png").is_err());
+                assert!(OutputTemplate::parse("{stem.png").is_err());
+                assert!(OutputTemplate::parse("stem}.png").is_err());
+                assert!(OutputTemplate::parse("").is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+use crate::BgrResult;
+
+/// How a soft alpha channel becomes the binary transparency of paletted output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlphaDither {
+    /// Opaque at or above the threshold: a hard, stair-stepped edge.
+    #[default]
+    Threshold,
+    /// An 8×8 Bayer pattern whose density follows alpha, keeping the fade of soft edges in a
+    /// regular texture.
+    Ordered,
+    /// Floyd–Steinberg error diffusion of alpha, for the least patterned soft edges.
+    Diffusion,
+}
+
+/// The 8×8 Bayer matrix, thresholds 0 to 63 spread evenly over the tile.
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Settings for paletted PNG-8 output.
+///
+/// Colors are reduced with median cut, and alpha becomes binary: one fully transparent palette
+/// entry and opaque colors, split by `alpha_dither`. Quantize after compositing so the palette
+/// is built from the colors actually written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteOptions {
+    /// Palette size including the transparent entry, from 2 to 256.
+    pub colors: u16,
+    /// Diffuse quantization error with Floyd–Steinberg dithering, trading banding for noise.
+    pub dither: bool,
+    /// Alpha at or above which a pixel is opaque, for [`AlphaDither::Threshold`] and as the
+    /// midpoint of [`AlphaDither::Diffusion`].
+    pub alpha_threshold: u8,
+    /// How soft alpha becomes binary.
+    pub alpha_dither: AlphaDither,
+}
+
+impl Default for PaletteOptions {
+    fn default() -> Self {
+        Self {
+            colors: 256,
+            dither: false,
+            alpha_threshold: 128,
+            alpha_dither: AlphaDither::Threshold,
+        }
+    }
+}
+
+/// Encode `image` as a paletted PNG with 1-bit transparency.
+///
+/// The smallest bit depth that fits the palette is used, down to 1 bit for two colors.
+pub fn encode_paletted_png(image: &RgbaImage, options: &PaletteOptions) -> BgrResult<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let opaque = binary_alpha(image, options);
+    let transparent = opaque.iter().any(|&opaque| !opaque);
+
+    let budget = usize::from(options.colors.clamp(2, 256)) - usize::from(transparent);
+    let mut palette = median_cut(image, budget.max(1), &opaque);
+    if transparent {
+        palette.insert(0, [0, 0, 0]);
+    }
+    let first_opaque = usize::from(transparent);
+    let indices = map_pixels(image, &palette, first_opaque, options.dither, &opaque);
+
+    let depth = match palette.len() {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    };
+    let bits = depth as usize;
+    let row_bytes = (width as usize * bits).div_ceil(8);
+    let mut packed = vec![0u8; row_bytes * height as usize];
+    for (y, row) in indices.chunks(width as usize).enumerate() {
+        let out = &mut packed[y * row_bytes..(y + 1) * row_bytes];
+        for (x, &index) in row.iter().enumerate() {
+            let bit = x * bits;
+            out[bit / 8] |= index << (8 - bits - bit % 8);
+        }
+    }
+
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(palette.concat());
+    if transparent {
+        encoder.set_trns(vec![0]);
+    }
+    let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+    writer
+        .write_image_data(&packed)
+        .map_err(std::io::Error::other)?;
+    writer.finish().map_err(std::io::Error::other)?;
+    Ok(encoded)
+}
+
+/// Whether each pixel of `image`, in row order, is opaque in the paletted output.
+fn binary_alpha(image: &RgbaImage, options: &PaletteOptions) -> Vec<bool> {
+    let (width, height) = image.dimensions();
+    match options.alpha_dither {
+        AlphaDither::Threshold => image
+            .pixels()
+            .map(|pixel| pixel[3] >= options.alpha_threshold)
+            .collect(),
+        AlphaDither::Ordered => image
+            .enumerate_pixels()
+            .map(|(x, y, pixel)| {
+                let level = BAYER_8X8[y as usize % 8][x as usize % 8];
+                // Thresholds at the centers of 64 even steps, so alpha 0 and 255 stay put.
+                u16::from(pixel[3]) * 64 > u16::from(level) * 256 + 128
+            })
+            .collect(),
+        AlphaDither::Diffusion => {
+            let width = width as usize;
+            let threshold = f32::from(options.alpha_threshold);
+            let mut opaque = Vec::with_capacity(width * height as usize);
+            let mut current = vec![0f32; width + 2];
+            let mut next = vec![0f32; width + 2];
+            for y in 0..height {
+                for x in 0..width {
+                    let wanted = f32::from(image.get_pixel(x as u32, y)[3]) + current[x + 1];
+                    let on = wanted >= threshold;
+                    opaque.push(on);
+                    let error = wanted - if on { 255.0 } else { 0.0 };
+                    current[x + 2] += error * 7.0 / 16.0;
+                    next[x] += error * 3.0 / 16.0;
+                    next[x + 1] += error * 5.0 / 16.0;
+                    next[x + 2] += error / 16.0;
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.fill(0.0);
+            }
+            opaque
+        }
+    }
+}
+
+/// A palette of at most `size` colors for the opaque pixels of `image`.
+///
+/// The box of colors with the widest channel range is split at its weighted median until the
+/// palette is full or every box holds a single color; each entry is its box's weighted mean.
+fn median_cut(image: &RgbaImage, size: usize, opaque: &[bool]) -> Vec<[u8; 3]> {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    let pixels = image.pixels().zip(opaque).filter(|&(_, &opaque)| opaque);
+    for (pixel, _) in pixels {
+        *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+    }
+    if histogram.is_empty() {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![histogram.into_iter().collect::<Vec<_>>()];
+    while boxes.len() < size {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range);
+        let Some((i, (channel, _))) = widest else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total: u64 = colors.iter().map(|&(_, count)| u64::from(count)).sum();
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|&(_, count)| {
+                seen += u64::from(count);
+                seen * 2 >= total
+            })
+            .map_or(1, |i| i + 1)
+            .clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|colors| {
+            let total: u64 = colors.iter().map(|&(_, count)| u64::from(count)).sum();
+            let mean = |channel: usize| {
+                let sum: u64 = colors
+                    .iter()
+                    .map(|&(color, count)| u64::from(color[channel]) * u64::from(count))
+                    .sum();
+                ((sum + total / 2) / total) as u8
+            };
+            [mean(0), mean(1), mean(2)]
+        })
+        .collect()
+}
+
+/// The channel with the largest spread in `colors`, and that spread.
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+/// The palette index of every pixel; transparent pixels get index 0.
+fn map_pixels(
+    image: &RgbaImage,
+    palette: &[[u8; 3]],
+    first_opaque: usize,
+    dither: bool,
+    opaque: &[bool],
+) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let width = width as usize;
+    let colors = &palette[first_opaque..];
+    let mut nearest_cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut nearest = |color: [u8; 3]| -> u8 {
+        *nearest_cache.entry(color).or_insert_with(|| {
+            let distance = |entry: &[u8; 3]| -> u32 {
+                (0..3)
+                    .map(|c| (i32::from(entry[c]) - i32::from(color[c])).pow(2) as u32)
+                    .sum()
+            };
+            let (i, _) = colors
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| distance(entry))
+                .expect("an opaque pixel gives the palette an opaque color");
+            (i + first_opaque) as u8
+        })
+    };
+
+    let mut indices = Vec::with_capacity(width * height as usize);
+    // Error carried to the current and next row, per pixel and channel.
+    let mut current = vec![[0f32; 3]; width + 2];
+    let mut next = vec![[0f32; 3]; width + 2];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel(x as u32, y);
+            if !opaque[y as usize * width + x] {
+                indices.push(0);
+                continue;
+            }
+            let wanted: [f32; 3] = std::array::from_fn(|c| f32::from(pixel[c]) + current[x + 1][c]);
+            let color = wanted.map(|value| value.round().clamp(0.0, 255.0) as u8);
+            let index = nearest(color);
+            indices.push(index);
+            if dither {
+                let chosen = palette[usize::from(index)];
+                for c in 0..3 {
+                    let error = wanted[c] - f32::from(chosen[c]);
+                    current[x + 2][c] += error * 7.0 / 16.0;
+                    next[x][c] += error * 3.0 / 16.0;
+                    next[x + 1][c] += error * 5.0 / 16.0;
+                    next[x + 2][c] += error / 16.0;
+                }
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.fill([0.0; 3]);
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(32, 8, |x, y| {
+            let alpha = if y < 2 { 0 } else { 255 };
+            Rgba([(x * 8) as u8, (y * 32) as u8, 128, alpha])
+        })
+    }
+
+    /// The payload of the first `chunk` in `png`.
+    fn chunk<'a>(png: &'a [u8], chunk: &[u8; 4]) -> Option<&'a [u8]> {
+        let at = png.windows(4).position(|window| window == chunk)?;
+        let len = u32::from_be_bytes(png[at - 4..at].try_into().unwrap()) as usize;
+        Some(&png[at + 4..at + 4 + len])
+    }
+
+    /// The bit depth and color type.
+    fn header(png: &[u8]) -> (u8, u8) {
+        let ihdr = chunk(png, b"IHDR").unwrap();
+        (ihdr[8], ihdr[9])
+    }
+
+    mod encode_paletted_png {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn palette_respects_the_color_budget() {
+                let options = PaletteOptions {
+                    colors: 16,
+                    ..PaletteOptions::default()
+                };
+                let png = encode_paletted_png(&gradient(), &options).unwrap();
+                // Indexed color at 4 bits per pixel.
+                assert_eq!(header(&png), (4, 3));
+                assert!(chunk(&png, b"PLTE").unwrap().len() / 3 <= 16);
+            }
+
+            #[test]
+            fn alpha_becomes_binary() {
+                let mut image = gradient();
+                image.put_pixel(0, 4, Rgba([10, 10, 10, 100]));
+                image.put_pixel(1, 4, Rgba([10, 10, 10, 200]));
+                let png = encode_paletted_png(&image, &PaletteOptions::default()).unwrap();
+                let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                assert_eq!(chunk(&png, b"tRNS"), Some(&[0][..]));
+                assert!(decoded.pixels().all(|p| p[3] == 0 || p[3] == 255));
+                assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+                assert_eq!(decoded.get_pixel(0, 4)[3], 0);
+                assert_eq!(decoded.get_pixel(1, 4)[3], 255);
+            }
+
+            #[test]
+            fn opaque_images_have_no_transparency() {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([200, 100, 50, 255]));
+                let png = encode_paletted_png(&image, &PaletteOptions::default()).unwrap();
+                let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                assert!(chunk(&png, b"tRNS").is_none());
+                assert_eq!(header(&png), (1, 3));
+                assert_eq!(*decoded.get_pixel(3, 3), Rgba([200, 100, 50, 255]));
+            }
+
+            #[test]
+            fn dithering_keeps_the_size() {
+                let options = PaletteOptions {
+                    colors: 4,
+                    dither: true,
+                    ..PaletteOptions::default()
+                };
+                let png = encode_paletted_png(&gradient(), &options).unwrap();
+                let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                assert_eq!(decoded.dimensions(), (32, 8));
+            }
+
+            #[test]
+            fn dithered_alpha_keeps_the_coverage_of_soft_edges() {
+                let image = RgbaImage::from_fn(64, 64, |x, _| {
+                    Rgba([200, 100, 50, if x < 32 { 64 } else { 255 }])
+                });
+                for alpha_dither in [AlphaDither::Ordered, AlphaDither::Diffusion] {
+                    let options = PaletteOptions {
+                        alpha_dither,
+                        ..PaletteOptions::default()
+                    };
+                    let png = encode_paletted_png(&image, &options).unwrap();
+                    let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                    let soft = decoded.enumerate_pixels().filter(|&(x, _, _)| x < 32);
+                    let opaque = soft.filter(|(_, _, p)| p[3] == 255).count();
+                    // A quarter of the 32×64 soft half, where thresholding keeps none.
+                    assert!(
+                        (opaque as i32 - 512).abs() <= 16,
+                        "{alpha_dither:?}: {opaque}"
+                    );
+                    assert!(
+                        decoded
+                            .enumerate_pixels()
+                            .filter(|&(x, _, _)| x >= 32)
+                            .all(|(_, _, p)| p[3] == 255)
+                    );
+                }
+                let png = encode_paletted_png(&image, &PaletteOptions::default()).unwrap();
+                let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+            }
+
+            #[test]
+            fn fully_transparent_image() {
+                let image = RgbaImage::new(3, 3);
+                let png = encode_paletted_png(&image, &PaletteOptions::default()).unwrap();
+                let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+                assert!(decoded.pixels().all(|p| p[3] == 0));
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use crate::{Bgr, BgrResult, BgrSession};
+
+/// A fixed set of pre-warmed sessions shared between threads.
+///
+/// Each caller checks out whichever session is idle and returns it when done, so concurrent
+/// requests run side by side instead of queuing behind one session. When every session is busy,
+/// [`checkout`](SessionPool::checkout) waits for the first one to come back. Long-running
+/// services typically build one pool at startup with [`Bgr::session_pool`] and share it.
+///
+/// # Example
+/// ```no_run
+/// use bgr::Bgr;
+///
+/// let pool = Bgr::new("model.onnx").session_pool(4)?;
+/// std::thread::scope(|scope| {
+///     for input in ["a.jpg", "b.jpg", "c.jpg"] {
+///         let pool = &pool;
+///         scope.spawn(move || {
+///             let mut session = pool.checkout();
+///             session.for_image(input)?.matte().foreground()?.save(format!("{input}.png"))
+///         });
+///     }
+/// });
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub struct SessionPool<S = BgrSession> {
+    idle: Mutex<Vec<S>>,
+    returned: Condvar,
+    size: usize,
+}
+
+impl SessionPool {
+    /// Load `size` sessions of the model in parallel (`0` = one per available core).
+    pub fn new(bgr: &Bgr, size: usize) -> BgrResult<Self> {
+        let size = if size == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            size
+        };
+        let sessions = thread::scope(|scope| {
+            let loaders: Vec<_> = (0..size).map(|_| scope.spawn(|| bgr.session())).collect();
+            loaders
+                .into_iter()
+                .map(|loader| {
+                    loader
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<BgrResult<Vec<_>>>()
+        })?;
+        Ok(Self::from_sessions(sessions))
+    }
+}
+
+impl<S> SessionPool<S> {
+    /// Build a pool from sessions that are already loaded.
+    pub fn from_sessions(sessions: Vec<S>) -> Self {
+        Self {
+            size: sessions.len(),
+            idle: Mutex::new(sessions),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Total number of sessions, busy or idle.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of sessions not currently checked out.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Take an idle session, waiting for one to be returned if all are busy.
+    ///
+    /// # Panics
+    /// Panics if the pool is empty, since no session could ever become available.
+    pub fn checkout(&self) -> PooledSession<'_, S> {
+        assert!(self.size > 0, "checkout from an empty session pool");
+        let mut idle = self.lock();
+        loop {
+            if let Some(session) = idle.pop() {
+                return PooledSession::new(self, session);
+            }
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Take an idle session without waiting.
+    pub fn try_checkout(&self) -> Option<PooledSession<'_, S>> {
+        let session = self.lock().pop()?;
+        Some(PooledSession::new(self, session))
+    }
+
+    /// Run `f` with an idle session, waiting for one if necessary.
+    pub fn run<T>(&self, f: impl FnOnce(&mut S) -> T) -> T {
+        f(&mut self.checkout())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<S>> {
+        // The lock only guards pushes and pops, so a poisoned list is still consistent.
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn give_back(&self, session: S) {
+        self.lock().push(session);
+        self.returned.notify_one();
+    }
+}
+
+/// A session checked out of a [`SessionPool`]; it returns to the pool when dropped.
+pub struct PooledSession<'a, S = BgrSession> {
+    pool: &'a SessionPool<S>,
+    session: Option<S>,
+}
+
+impl<'a, S> PooledSession<'a, S> {
+    fn new(pool: &'a SessionPool<S>, session: S) -> Self {
+        Self {
+            pool,
+            session: Some(session),
+        }
+    }
+}
+
+impl<S> Deref for PooledSession<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.session.as_ref().expect("session present until drop")
+    }
+}
+
+impl<S> DerefMut for PooledSession<'_, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        self.session.as_mut().expect("session present until drop")
+    }
+}
+
+impl<S> Drop for PooledSession<'_, S> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.give_back(session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    mod checkout {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn session_returns_on_drop() {
+                let pool = SessionPool::from_sessions(vec![1, 2]);
+                {
+                    let session = pool.checkout();
+                    assert!(*session == 1 || *session == 2);
+                    assert_eq!(pool.idle(), 1);
+                }
+                assert_eq!(pool.idle(), 2);
+            }
+
+            #[test]
+            fn try_checkout_fails_when_exhausted() {
+                let pool = SessionPool::from_sessions(vec![7]);
+                let held = pool.try_checkout().unwrap();
+                assert!(pool.try_checkout().is_none());
+                drop(held);
+                assert!(pool.try_checkout().is_some());
+            }
+
+            #[test]
+            fn changes_to_a_session_persist() {
+                let pool = SessionPool::from_sessions(vec![0u32]);
+                pool.run(|count| *count += 5);
+                assert_eq!(pool.run(|count| *count), 5);
+            }
+
+            #[test]
+            fn waiting_checkout_wakes_on_return() {
+                let pool = SessionPool::from_sessions(vec![()]);
+                let held = pool.checkout();
+                thread::scope(|scope| {
+                    let waiter = scope.spawn(|| pool.run(|_| ()));
+                    thread::sleep(Duration::from_millis(20));
+                    assert!(!waiter.is_finished());
+                    drop(held);
+                    waiter.join().unwrap();
+                });
+                assert_eq!(pool.idle(), 1);
+            }
+
+            #[test]
+            fn concurrent_callers_use_distinct_sessions() {
+                let pool =
+                    SessionPool::from_sessions((0..4).map(|_| AtomicUsize::new(0)).collect());
+                let busy = AtomicUsize::new(0);
+                let peak = AtomicUsize::new(0);
+                thread::scope(|scope| {
+                    for _ in 0..16 {
+                        scope.spawn(|| {
+                            pool.run(|uses| {
+                                let now = busy.fetch_add(1, Ordering::SeqCst) + 1;
+                                peak.fetch_max(now, Ordering::SeqCst);
+                                uses.fetch_add(1, Ordering::SeqCst);
+                                thread::sleep(Duration::from_millis(2));
+                                busy.fetch_sub(1, Ordering::SeqCst);
+                            });
+                        });
+                    }
+                });
+                assert!(peak.load(Ordering::SeqCst) <= 4);
+                let sessions = pool.lock();
+                let total: usize = sessions.iter().map(|s| s.load(Ordering::SeqCst)).sum();
+                assert_eq!(total, 16);
+                assert_eq!(sessions.len(), 4);
+            }
+
+            #[test]
+            #[should_panic(expected = "empty session pool")]
+            fn empty_pool_panics() {
+                let pool: SessionPool<()> = SessionPool::from_sessions(Vec::new());
+                let _ = pool.checkout();
+            }
+        }
+    }
+}
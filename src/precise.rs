@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Seek, Write};
+use std::path::Path;
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage,
+};
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, GrayImage, ImageBuffer, ImageEncoder, Luma};
+use ndarray::Array2;
+
+use crate::BgrResult;
+use crate::mask::array_to_gray_image;
+use crate::warnings::Warning;
+
+/// Sample precision a matte is written at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatteDepth {
+    /// 8-bit grayscale PNG, as every other matte bgr writes.
+    #[default]
+    Eight,
+    /// 16-bit grayscale PNG.
+    Sixteen,
+    /// OpenEXR with one 32-bit float `Y` channel.
+    Float,
+}
+
+impl MatteDepth {
+    /// The file extension mattes of this depth are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Eight | Self::Sixteen => "png",
+            Self::Float => "exr",
+        }
+    }
+}
+
+/// The model's matte at full resolution as probabilities from 0 to 1, before it is quantized
+/// to 8 bits for processing.
+///
+/// Returned by [`BgrSession::for_image_precise`](crate::BgrSession::for_image_precise) for
+/// compositing work that needs smoother gradients than 256 levels give.
+#[derive(Debug, Clone)]
+pub struct PreciseMatte {
+    samples: Array2<f32>,
+    warnings: Vec<Warning>,
+}
+
+impl PreciseMatte {
+    /// Wrap an H×W matte, clamping resampling overshoot back into 0 to 1.
+    pub(crate) fn new(mut samples: Array2<f32>, warnings: Vec<Warning>) -> Self {
+        samples.mapv_inplace(|value| value.clamp(0.0, 1.0));
+        Self { samples, warnings }
+    }
+
+    /// Width and height of the matte.
+    pub fn dimensions(&self) -> (u32, u32) {
+        let (height, width) = self.samples.dim();
+        (width as u32, height as u32)
+    }
+
+    /// The probabilities, row by row.
+    pub fn samples(&self) -> &Array2<f32> {
+        &self.samples
+    }
+
+    /// Warnings raised while decoding the input.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The matte quantized to 8 bits, as [`InferencedMatte::raw_matte`](crate::InferencedMatte::raw_matte)
+    /// holds it.
+    pub fn to_luma8(&self) -> GrayImage {
+        array_to_gray_image(&self.samples)
+    }
+
+    /// The matte quantized to 16 bits.
+    pub fn to_luma16(&self) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        let (width, height) = self.dimensions();
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let value = self.samples[[y as usize, x as usize]];
+            Luma([(value * f32::from(u16::MAX) + 0.5) as u16])
+        })
+    }
+
+    /// Write the matte to `path` at `depth`.
+    pub fn save(&self, path: impl AsRef<Path>, depth: MatteDepth) -> BgrResult<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(depth, &mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// The matte encoded at `depth`.
+    pub fn encode(&self, depth: MatteDepth) -> BgrResult<Vec<u8>> {
+        let mut encoded = Cursor::new(Vec::new());
+        self.write(depth, &mut encoded)?;
+        Ok(encoded.into_inner())
+    }
+
+    /// Write the matte to `writer` at `depth`: a grayscale PNG for 8 and 16 bits, a
+    /// single-channel EXR for floats.
+    pub fn write(&self, depth: MatteDepth, mut writer: impl Write + Seek) -> BgrResult<()> {
+        let (width, height) = self.dimensions();
+        match depth {
+            MatteDepth::Eight => PngEncoder::new(&mut writer).write_image(
+                self.to_luma8().as_raw(),
+                width,
+                height,
+                ExtendedColorType::L8,
+            )?,
+            MatteDepth::Sixteen => {
+                let bytes: Vec<u8> = self
+                    .to_luma16()
+                    .as_raw()
+                    .iter()
+                    .flat_map(|sample| sample.to_be_bytes())
+                    .collect();
+                PngEncoder::new(&mut writer).write_image(
+                    &bytes,
+                    width,
+                    height,
+                    ExtendedColorType::L16,
+                )?
+            }
+            MatteDepth::Float => {
+                let samples = self.samples.iter().copied().collect();
+                let channel = AnyChannel::new("Y", FlatSamples::F32(samples));
+                let layer = Layer::new(
+                    (width as usize, height as usize),
+                    LayerAttributes::default(),
+                    Encoding::FAST_LOSSLESS,
+                    AnyChannels::sort(vec![channel].into()),
+                );
+                Image::from_layer(layer)
+                    .write()
+                    .to_buffered(writer)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageReader;
+
+    /// A 4×2 matte whose values sit between 8-bit levels.
+    fn matte() -> PreciseMatte {
+        let samples = Array2::from_shape_fn((2, 4), |(y, x)| (x as f32 + 4.0 * y as f32) / 1000.0);
+        PreciseMatte::new(samples, Vec::new())
+    }
+
+    mod precise_matte {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn sixteen_bits_keep_levels_eight_bits_merge() {
+                let decoded =
+                    ImageReader::new(Cursor::new(matte().encode(MatteDepth::Sixteen).unwrap()))
+                        .with_guessed_format()
+                        .unwrap()
+                        .decode()
+                        .unwrap()
+                        .into_luma16();
+                let levels: Vec<u16> = decoded.pixels().map(|p| p[0]).collect();
+                assert_eq!(&levels[..4], &[0, 66, 131, 197]);
+                let eight = matte().to_luma8();
+                assert_eq!(eight.get_pixel(0, 0), eight.get_pixel(1, 0));
+            }
+
+            #[test]
+            fn float_keeps_exact_probabilities() {
+                let encoded = matte().encode(MatteDepth::Float).unwrap();
+                let image = exr::prelude::read()
+                    .no_deep_data()
+                    .largest_resolution_level()
+                    .all_channels()
+                    .first_valid_layer()
+                    .all_attributes()
+                    .from_buffered(Cursor::new(encoded))
+                    .unwrap();
+                let channel = &image.layer_data.channel_data.list[0];
+                assert_eq!(channel.name.to_string(), "Y");
+                let FlatSamples::F32(samples) = &channel.sample_data else {
+                    panic!("expected f32 samples");
+                };
+                assert_eq!(samples[5], 0.005);
+            }
+
+            #[test]
+            fn overshoot_is_clamped() {
+                let samples = Array2::from_shape_vec((1, 2), vec![-0.1, 1.2]).unwrap();
+                let matte = PreciseMatte::new(samples, Vec::new());
+                assert_eq!(matte.samples().as_slice().unwrap(), &[0.0, 1.0]);
+            }
+        }
+    }
+}
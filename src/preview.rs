@@ -0,0 +1,255 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, RgbImage};
+
+use crate::models::ModelPreset;
+use crate::trim::subject_bounds;
+
+/// The preset drafts are meant to run on: the smallest and fastest model.
+pub const DRAFT_PRESET: ModelPreset = ModelPreset::U2NetP;
+
+/// Settings for low-latency draft mattes and for the final pass that starts from a draft.
+///
+/// A draft shrinks the input before inference and enlarges the matte with a cheap filter, so
+/// with [`DRAFT_PRESET`] a preview takes well under 100 ms on a CPU. The final pass runs the
+/// full model on the draft's subject plus a margin only, which spends the model's input
+/// resolution on the subject instead of the backdrop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewOptions {
+    /// Longest side the input is shrunk to before draft inference, in pixels.
+    pub draft_max_side: u32,
+    /// Draft matte value above which a pixel counts as subject for the final pass.
+    pub prior_threshold: u8,
+    /// Context kept around the draft's subject, as a fraction of its larger side.
+    pub prior_margin: f32,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        Self {
+            draft_max_side: 512,
+            prior_threshold: 32,
+            prior_margin: 0.1,
+        }
+    }
+}
+
+/// The matte held by a draft output: its alpha channel, or its gray levels when it has none.
+pub fn draft_matte(draft: &DynamicImage) -> GrayImage {
+    if draft.color().has_alpha() {
+        let rgba = draft.to_rgba8();
+        GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            image::Luma([rgba.get_pixel(x, y)[3]])
+        })
+    } else {
+        draft.to_luma8()
+    }
+}
+
+/// A rectangle of an image, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Region {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// `rgb` shrunk so its longest side is `max_side`, or `None` when it is already that small.
+pub(crate) fn shrink(rgb: &RgbImage, max_side: u32) -> Option<RgbImage> {
+    let (width, height) = rgb.dimensions();
+    let longest = width.max(height);
+    if max_side == 0 || longest <= max_side {
+        return None;
+    }
+    let scale = max_side as f32 / longest as f32;
+    let small_width = ((width as f32 * scale).round() as u32).max(1);
+    let small_height = ((height as f32 * scale).round() as u32).max(1);
+    Some(imageops::resize(
+        rgb,
+        small_width,
+        small_height,
+        FilterType::Triangle,
+    ))
+}
+
+/// Enlarge a draft's matte to the full image with a cheap filter.
+pub(crate) fn enlarge(matte: &GrayImage, width: u32, height: u32) -> GrayImage {
+    imageops::resize(matte, width, height, FilterType::Triangle)
+}
+
+/// The part of the image the final pass runs on: the prior's subject with a margin.
+///
+/// `None` when the prior has no subject, or when the region would cover the whole image.
+pub(crate) fn prior_region(prior: &GrayImage, options: &PreviewOptions) -> Option<Region> {
+    let (width, height) = prior.dimensions();
+    let (x0, y0, x1, y1) = subject_bounds(prior, 0, options.prior_threshold)?;
+    let side = (x1 - x0 + 1).max(y1 - y0 + 1);
+    let margin = (side as f32 * options.prior_margin.max(0.0)).ceil() as u32;
+    let left = x0.saturating_sub(margin);
+    let top = y0.saturating_sub(margin);
+    let right = (x1 + margin).min(width - 1);
+    let bottom = (y1 + margin).min(height - 1);
+    let region = Region {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    };
+    (region.width < width || region.height < height).then_some(region)
+}
+
+/// A full-size matte holding `matte` at `region` and background everywhere else.
+pub(crate) fn paste(matte: &GrayImage, region: Region, width: u32, height: u32) -> GrayImage {
+    let mut full = GrayImage::new(width, height);
+    imageops::replace(&mut full, matte, i64::from(region.x), i64::from(region.y));
+    full
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgba, RgbaImage};
+
+    fn options() -> PreviewOptions {
+        PreviewOptions {
+            prior_margin: 0.0,
+            ..PreviewOptions::default()
+        }
+    }
+
+    mod prior_region {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn bounds_the_subject() {
+                let mut prior = GrayImage::new(100, 80);
+                for (x, y) in [(20, 10), (59, 49)] {
+                    prior.put_pixel(x, y, Luma([255]));
+                }
+                let region = prior_region(&prior, &options()).unwrap();
+                assert_eq!(
+                    region,
+                    Region {
+                        x: 20,
+                        y: 10,
+                        width: 40,
+                        height: 40
+                    }
+                );
+            }
+
+            #[test]
+            fn margin_is_clamped_to_the_image() {
+                let mut prior = GrayImage::new(100, 100);
+                prior.put_pixel(5, 50, Luma([255]));
+                let options = PreviewOptions {
+                    prior_margin: 10.0,
+                    ..PreviewOptions::default()
+                };
+                let region = prior_region(&prior, &options).unwrap();
+                assert_eq!((region.x, region.width), (0, 16));
+            }
+
+            #[test]
+            fn empty_or_full_priors_have_no_region() {
+                assert_eq!(prior_region(&GrayImage::new(10, 10), &options()), None);
+                let full = GrayImage::from_pixel(10, 10, Luma([255]));
+                assert_eq!(prior_region(&full, &options()), None);
+            }
+        }
+    }
+
+    mod shrink {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn keeps_the_aspect_ratio() {
+                let small = shrink(&RgbImage::new(2000, 1000), 500).unwrap();
+                assert_eq!(small.dimensions(), (500, 250));
+                assert!(shrink(&RgbImage::new(400, 300), 500).is_none());
+            }
+        }
+    }
+
+    mod paste {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn places_the_crop() {
+                let crop = GrayImage::from_pixel(2, 2, Luma([200]));
+                let region = Region {
+                    x: 3,
+                    y: 1,
+                    width: 2,
+                    height: 2,
+                };
+                let full = paste(&crop, region, 6, 4);
+                assert_eq!(full.get_pixel(3, 1)[0], 200);
+                assert_eq!(full.get_pixel(4, 2)[0], 200);
+                assert_eq!(full.get_pixel(0, 0)[0], 0);
+            }
+        }
+    }
+
+    mod draft_matte {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn reads_alpha_of_cutouts() {
+                let cutout = RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 64]));
+                let matte = draft_matte(&DynamicImage::ImageRgba8(cutout));
+                assert_eq!(matte.get_pixel(1, 1)[0], 64);
+                let gray = GrayImage::from_pixel(2, 2, Luma([9]));
+                let matte = draft_matte(&DynamicImage::ImageLuma8(gray));
+                assert_eq!(matte.get_pixel(0, 0)[0], 9);
+            }
+        }
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod session {
+        use super::*;
+        use crate::Bgr;
+        use crate::foreground::encode_image;
+        use crate::testing::{TinyModelDir, fixtures};
+        use image::ImageFormat;
+
+        /// Runs the tiny model; needs the ONNX Runtime library.
+        mod integration {
+            use super::*;
+
+            #[test]
+            fn draft_then_final_keep_the_image_size() {
+                let model = TinyModelDir::new().unwrap();
+                let mut session = Bgr::new(model.model()).session().unwrap();
+
+                let fixture = fixtures().remove(0);
+                let size = fixture.image.dimensions();
+                let png =
+                    encode_image(DynamicImage::ImageRgb8(fixture.image), ImageFormat::Png).unwrap();
+                let options = PreviewOptions {
+                    draft_max_side: 16,
+                    ..PreviewOptions::default()
+                };
+                let draft = session.for_bytes_draft(&png, &options).unwrap();
+                assert_eq!(draft.raw_matte().dimensions(), size);
+                let last = session
+                    .for_bytes_with_prior(&png, draft.raw_matte(), &options)
+                    .unwrap();
+                assert_eq!(last.raw_matte().dimensions(), size);
+            }
+        }
+    }
+}
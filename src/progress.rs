@@ -0,0 +1,321 @@
+use std::cell::Cell;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Something that happened while downloading a model preset.
+///
+/// Events of concurrent downloads interleave; `model` tells them apart.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    /// The server answered. `total` is the full file size when known; `resumed_from` is the
+    /// number of bytes kept from an interrupted download (0 for a fresh one).
+    Started {
+        model: &'static str,
+        total: Option<u64>,
+        resumed_from: u64,
+    },
+    /// `downloaded` bytes of the file are on disk, counting resumed bytes.
+    Progress {
+        model: &'static str,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// The file is complete and in place.
+    Finished { model: &'static str, path: PathBuf },
+    /// The download stopped; a partial file is kept so the next attempt can resume.
+    Failed { model: &'static str, error: String },
+}
+
+/// A step of processing one input, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Reading and decoding the input file or bytes.
+    Decode,
+    /// Resizing and normalizing the image into the model's input tensor.
+    Preprocess,
+    /// Running the model.
+    Inference,
+    /// Scaling the matte back to the input size.
+    Postprocess,
+    /// Encoding an output image, by calls such as [`Remover::remove_bytes`](crate::Remover::remove_bytes)
+    /// that return encoded bytes.
+    Encode,
+}
+
+/// Tells apart the inputs whose [`Stage`]s interleave, such as those of concurrent sessions;
+/// unique within the process.
+///
+/// An input starts at its [`Stage::Decode`], or at [`Stage::Preprocess`] when it is handed
+/// over already decoded, and its later stages follow from the same thread with the same id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct InputId(pub u64);
+
+/// The id given to the next input.
+static NEXT_INPUT: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// The input and stage this thread last reported.
+    static LAST_STAGE: Cell<Option<(InputId, Stage)>> = const { Cell::new(None) };
+}
+
+/// The input entering `stage` on this thread: the one before it, unless `stage` starts a new
+/// input.
+fn input_entering(stage: Stage) -> InputId {
+    LAST_STAGE.with(|last| {
+        let input = match (last.get(), stage) {
+            (Some((input, Stage::Decode)), Stage::Preprocess) => input,
+            (Some((input, _)), Stage::Inference | Stage::Postprocess | Stage::Encode) => input,
+            _ => InputId(NEXT_INPUT.fetch_add(1, Ordering::Relaxed)),
+        };
+        last.set(Some((input, stage)));
+        input
+    })
+}
+
+/// A batch input starting or ending, reported before the [`BatchProgress`] it adds to.
+///
+/// Inputs of concurrent workers interleave; `input` tells them apart.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InputEvent {
+    /// Work on the `index`th of `total` inputs started; its stages follow from the same thread.
+    FileStarted {
+        input: PathBuf,
+        index: usize,
+        total: usize,
+    },
+    /// The input's outputs are written, `seconds` after it started.
+    FileFinished { input: PathBuf, seconds: f64 },
+    /// The input could not be processed.
+    FileFailed { input: PathBuf, error: String },
+}
+
+/// How far a batch of inputs has got, counting failed inputs as done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BatchProgress {
+    pub done: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+/// Receives progress from model downloads, the inference pipeline, and batches, such as to
+/// draw progress bars, update a GUI, or log machine-readable lines.
+///
+/// Every method does nothing by default, so a sink implements only what it shows. Closures
+/// taking `&DownloadEvent` are sinks for downloads, and [`NoProgress`] ignores everything.
+/// Sinks are called from worker threads, and events of concurrent work interleave.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{CancellationToken, DownloadEvent};
+/// use bgr::models::{ModelPreset, default_models_dir, download_model_sync};
+///
+/// let sink = |event: &DownloadEvent| {
+///     if let DownloadEvent::Progress { downloaded, .. } = event {
+///         eprintln!("{downloaded} bytes");
+///     }
+/// };
+/// let cancel = CancellationToken::new();
+/// download_model_sync(ModelPreset::IsNet, &default_models_dir(), &sink, &cancel)?;
+/// # Ok::<_, bgr::models::ModelError>(())
+/// ```
+pub trait ProgressSink: Send + Sync {
+    /// A model download started, advanced, finished, or failed.
+    fn download(&self, event: &DownloadEvent) {
+        let _ = event;
+    }
+
+    /// `input` entered `stage`; the previous stage of that input is complete.
+    fn stage(&self, input: InputId, stage: Stage) {
+        let _ = (input, stage);
+    }
+
+    /// A batch input started, finished, or failed.
+    fn input(&self, event: &InputEvent) {
+        let _ = event;
+    }
+
+    /// A batch input finished, successfully or not.
+    fn batch(&self, progress: BatchProgress) {
+        let _ = progress;
+    }
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(&DownloadEvent) + Send + Sync,
+{
+    fn download(&self, event: &DownloadEvent) {
+        self(event)
+    }
+}
+
+/// A sink that discards every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// A cheaply cloned handle to a [`ProgressSink`], as carried by
+/// [`InferenceSettings`](crate::InferenceSettings).
+#[derive(Clone)]
+pub struct SharedProgress(Arc<dyn ProgressSink>);
+
+impl SharedProgress {
+    pub fn new(sink: impl ProgressSink + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    /// Report that the input processed on this thread entered `stage`.
+    pub(crate) fn enter(&self, stage: Stage) {
+        self.0.stage(input_entering(stage), stage);
+    }
+}
+
+impl Default for SharedProgress {
+    fn default() -> Self {
+        Self::new(NoProgress)
+    }
+}
+
+impl fmt::Debug for SharedProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedProgress")
+    }
+}
+
+impl ProgressSink for SharedProgress {
+    fn download(&self, event: &DownloadEvent) {
+        self.0.download(event);
+    }
+
+    fn stage(&self, input: InputId, stage: Stage) {
+        self.0.stage(input, stage);
+    }
+
+    fn input(&self, event: &InputEvent) {
+        self.0.input(event);
+    }
+
+    fn batch(&self, progress: BatchProgress) {
+        self.0.batch(progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod download_event {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn serialized_with_an_event_tag() {
+                let event = DownloadEvent::Progress {
+                    model: "isnet",
+                    downloaded: 10,
+                    total: Some(20),
+                };
+                assert_eq!(
+                    serde_json::to_string(&event).unwrap(),
+                    r#"{"event":"progress","model":"isnet","downloaded":10,"total":20}"#
+                );
+            }
+        }
+    }
+
+    mod input_event {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn input_events_share_the_tag() {
+                let event = InputEvent::FileFailed {
+                    input: PathBuf::from("a.jpg"),
+                    error: "not an image".into(),
+                };
+                assert_eq!(
+                    serde_json::to_string(&event).unwrap(),
+                    r#"{"event":"file_failed","input":"a.jpg","error":"not an image"}"#
+                );
+            }
+        }
+    }
+
+    mod progress_sink {
+        use super::*;
+        use std::sync::Mutex;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn closures_receive_events() {
+                let seen = Mutex::new(Vec::new());
+                let sink = |event: &DownloadEvent| seen.lock().unwrap().push(event.clone());
+                let event = DownloadEvent::Failed {
+                    model: "u2net",
+                    error: "HTTP 503".into(),
+                };
+                sink.download(&event);
+                sink.stage(InputId(1), Stage::Decode);
+                NoProgress.download(&event);
+                assert_eq!(seen.into_inner().unwrap(), [event]);
+            }
+
+            #[test]
+            fn shared_progress_forwards_every_method() {
+                #[derive(Clone, Default)]
+                struct Recorder(Arc<Mutex<Vec<String>>>);
+                impl ProgressSink for Recorder {
+                    fn stage(&self, input: InputId, stage: Stage) {
+                        self.0
+                            .lock()
+                            .unwrap()
+                            .push(format!("{stage:?} {}", input.0));
+                    }
+                    fn batch(&self, progress: BatchProgress) {
+                        self.0
+                            .lock()
+                            .unwrap()
+                            .push(format!("{}/{}", progress.done, progress.total));
+                    }
+                }
+
+                let recorder = Recorder::default();
+                let shared = SharedProgress::new(recorder.clone());
+                shared.clone().stage(InputId(7), Stage::Inference);
+                shared.batch(BatchProgress {
+                    done: 1,
+                    failed: 0,
+                    total: 3,
+                });
+                assert_eq!(*recorder.0.lock().unwrap(), ["Inference 7", "1/3"]);
+            }
+
+            #[test]
+            fn inputs_start_at_decode_or_undecoded_preprocess() {
+                let decoded = input_entering(Stage::Decode);
+                assert_eq!(input_entering(Stage::Preprocess), decoded);
+                assert_eq!(input_entering(Stage::Inference), decoded);
+                assert_eq!(input_entering(Stage::Postprocess), decoded);
+                let handed_over = input_entering(Stage::Preprocess);
+                assert_ne!(handed_over, decoded);
+                assert_eq!(input_entering(Stage::Inference), handed_over);
+                assert_ne!(input_entering(Stage::Decode), handed_over);
+            }
+        }
+    }
+}
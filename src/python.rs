@@ -0,0 +1,142 @@
+//! Python bindings for the `python` feature, built into a wheel with maturin
+//! (`maturin build --release`, configured by `pyproject.toml`).
+//!
+//! The module mirrors rembg's entry point, so data pipelines can switch by changing the import:
+//!
+//! ```python
+//! import bgr
+//!
+//! png = bgr.remove(open("photo.jpg", "rb").read())
+//!
+//! remover = bgr.Remover(model="isnet", device="auto")
+//! mask = remover.mask(data)
+//! ```
+//!
+//! Inference releases the GIL, so removers can be shared by Python threads; calls on one
+//! remover take turns.
+
+use std::sync::Mutex;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::models::ModelPreset;
+use crate::{BgrError, Device, Remover};
+
+/// The remover behind the module-level [`remove`], loaded on first use.
+static DEFAULT: Mutex<Option<Remover>> = Mutex::new(None);
+
+fn runtime_error(error: BgrError) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+/// Parse a device name as the CLI's `--device` accepts it.
+fn parse_device(name: &str) -> PyResult<Device> {
+    Device::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = Device::ALL.iter().map(|device| device.name()).collect();
+        PyValueError::new_err(format!(
+            "unknown device `{name}`; expected one of {}",
+            names.join(", ")
+        ))
+    })
+}
+
+/// A loaded model that cuts subjects out of encoded images.
+#[pyclass(name = "Remover", module = "bgr")]
+pub struct PyRemover {
+    remover: Mutex<Remover>,
+}
+
+#[pymethods]
+impl PyRemover {
+    /// Load `model`, a preset name (downloaded on first use) or the path of an ONNX file, on
+    /// `device`. Unavailable accelerators fall back to the CPU.
+    #[new]
+    #[pyo3(signature = (model = None, device = "cpu", models_dir = None, offline = false))]
+    fn new(
+        py: Python<'_>,
+        model: Option<String>,
+        device: &str,
+        models_dir: Option<String>,
+        offline: bool,
+    ) -> PyResult<Self> {
+        let mut builder = Remover::builder()
+            .device(parse_device(device)?)
+            .offline(offline);
+        if let Some(dir) = models_dir {
+            builder = builder.models_dir(dir);
+        }
+        if let Some(model) = model {
+            builder = match ModelPreset::from_str(&model) {
+                Some(preset) => builder.model(preset),
+                None => builder.model_path(model),
+            };
+        }
+        let remover = py
+            .allow_threads(|| builder.build())
+            .map_err(runtime_error)?;
+        Ok(Self {
+            remover: Mutex::new(remover),
+        })
+    }
+
+    /// Cut the subject out of an encoded image, returned as a PNG with transparency.
+    fn remove<'py>(&self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        let png = py
+            .allow_threads(|| self.lock().remove_bytes(data))
+            .map_err(runtime_error)?;
+        Ok(PyBytes::new(py, &png))
+    }
+
+    /// The alpha mask of an encoded image's subject, as a grayscale PNG.
+    fn mask<'py>(&self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        let png = py
+            .allow_threads(|| self.lock().mask_bytes(data))
+            .map_err(runtime_error)?;
+        Ok(PyBytes::new(py, &png))
+    }
+
+    /// The device the model runs on, after any fallback to the CPU.
+    #[getter]
+    fn device(&self) -> String {
+        self.lock().device().name().to_string()
+    }
+}
+
+impl PyRemover {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Remover> {
+        self.remover
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Cut the subject out of an encoded image with the default model, returned as a PNG with
+/// transparency.
+#[pyfunction]
+fn remove<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let png = py
+        .allow_threads(|| {
+            let mut default = DEFAULT
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let remover = match &mut *default {
+                Some(remover) => remover,
+                None => default.insert(Remover::builder().build()?),
+            };
+            remover.remove_bytes(data)
+        })
+        .map_err(runtime_error)?;
+    Ok(PyBytes::new(py, &png))
+}
+
+/// The `bgr` Python module.
+#[pymodule]
+#[pyo3(name = "bgr")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyRemover>()?;
+    module.add_function(wrap_pyfunction!(remove, module)?)?;
+    module.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use image::imageops::grayscale;
+use image::{GrayImage, RgbImage};
+use imageproc::gradients::sobel_gradients;
+
+/// Matte values at or below this, or at or above its complement, count as decided.
+const DECIDED: u8 = 12;
+
+/// A score from 0 (worst) to 1 (best) of how good a matte looks for its image, for
+/// escalating to a costlier model or refinement when a cheap one falls short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityMetric {
+    /// How closely the matte's edges follow the image's own edges: the cosine similarity of
+    /// the two gradient magnitudes wherever the matte changes. Blurry, misplaced, or missing
+    /// outlines score low.
+    EdgeGradient,
+    /// The share of pixels the matte decides as clearly subject or clearly background, which
+    /// drops when the model is unsure.
+    Confidence,
+}
+
+impl QualityMetric {
+    /// All metrics.
+    pub const ALL: &'static [QualityMetric] =
+        &[QualityMetric::EdgeGradient, QualityMetric::Confidence];
+
+    /// Metric name for CLI display.
+    pub fn name(self) -> &'static str {
+        match self {
+            QualityMetric::EdgeGradient => "edge-gradient",
+            QualityMetric::Confidence => "confidence",
+        }
+    }
+
+    /// Parse a metric from its name.
+    pub fn from_name(name: &str) -> Option<QualityMetric> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|metric| metric.name() == name)
+    }
+
+    /// Score `matte` as the matte of `rgb`, from 0 to 1.
+    pub fn score(self, rgb: &RgbImage, matte: &GrayImage) -> f32 {
+        match self {
+            QualityMetric::EdgeGradient => edge_gradient(rgb, matte),
+            QualityMetric::Confidence => confidence(matte),
+        }
+    }
+}
+
+/// A minimum score on a [`QualityMetric`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityTarget {
+    pub metric: QualityMetric,
+    /// The lowest acceptable score, from 0 to 1.
+    pub value: f32,
+}
+
+fn edge_gradient(rgb: &RgbImage, matte: &GrayImage) -> f32 {
+    let image_gradient = sobel_gradients(&grayscale(rgb));
+    let matte_gradient = sobel_gradients(matte);
+    let (mut dot, mut image_norm, mut matte_norm) = (0.0f64, 0.0f64, 0.0f64);
+    for (m, i) in matte_gradient.pixels().zip(image_gradient.pixels()) {
+        if m[0] == 0 {
+            continue;
+        }
+        let (m, i) = (f64::from(m[0]), f64::from(i[0]));
+        dot += m * i;
+        matte_norm += m * m;
+        image_norm += i * i;
+    }
+    if matte_norm == 0.0 || image_norm == 0.0 {
+        return 0.0;
+    }
+    (dot / (matte_norm.sqrt() * image_norm.sqrt())) as f32
+}
+
+fn confidence(matte: &GrayImage) -> f32 {
+    let pixels = matte.as_raw();
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    let decided = pixels
+        .iter()
+        .filter(|&&value| value <= DECIDED || value >= u8::MAX - DECIDED)
+        .count();
+    decided as f32 / pixels.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb};
+
+    /// A 32×32 image that is white left of column 16 and black from it on.
+    fn split() -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, _| {
+            Rgb(if x < 16 { [255, 255, 255] } else { [0, 0, 0] })
+        })
+    }
+
+    /// A hard matte covering the columns left of `edge`.
+    fn matte(edge: u32) -> GrayImage {
+        GrayImage::from_fn(32, 32, |x, _| Luma([if x < edge { 255 } else { 0 }]))
+    }
+
+    mod quality_metric {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn edge_on_the_image_edge_scores_higher() {
+                let metric = QualityMetric::EdgeGradient;
+                let aligned = metric.score(&split(), &matte(16));
+                let shifted = metric.score(&split(), &matte(24));
+                assert!(aligned > 0.9, "{aligned}");
+                assert!(shifted < 0.1, "{shifted}");
+            }
+
+            #[test]
+            fn empty_matte_scores_zero() {
+                let score = QualityMetric::EdgeGradient.score(&split(), &matte(0));
+                assert_eq!(score, 0.0);
+            }
+
+            #[test]
+            fn confidence_counts_decided_pixels() {
+                assert_eq!(QualityMetric::Confidence.score(&split(), &matte(16)), 1.0);
+                let unsure = GrayImage::from_pixel(32, 32, Luma([128]));
+                assert_eq!(QualityMetric::Confidence.score(&split(), &unsure), 0.0);
+            }
+
+            #[test]
+            fn names_round_trip() {
+                for &metric in QualityMetric::ALL {
+                    assert_eq!(QualityMetric::from_name(metric.name()), Some(metric));
+                }
+                assert_eq!(QualityMetric::from_name("sharpness"), None);
+            }
+        }
+    }
+}
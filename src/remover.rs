@@ -0,0 +1,520 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbaImage};
+
+#[cfg(feature = "tokio")]
+use crate::SessionPool;
+use crate::foreground::encode_image;
+use crate::models::ModelPreset;
+use crate::{
+    Bgr, BgrResult, BgrSession, CancellationToken, Context, DEFAULT_MODEL_PATH, Device,
+    ForegroundHandle, InferenceSettings, InferencedMatte, InputLimits, MaskProcessingOptions,
+    ModelBytes, ProgressSink, SharedProgress, SharedTelemetry, Stage, Telemetry,
+};
+
+/// Where a [`Remover`] gets its model.
+#[derive(Debug, Clone, PartialEq)]
+enum ModelSource {
+    Preset(ModelPreset),
+    Path(PathBuf),
+    Bytes(ModelBytes),
+}
+
+/// Background removal for in-memory images with one model session reused across calls.
+///
+/// The high-level entry point for applications: pick a model preset and device, then call
+/// [`remove`](Remover::remove) or [`mask`](Remover::mask) as often as needed. Presets are
+/// downloaded on first use (with the `download` feature) unless the builder is set offline. For
+/// file paths, mask processing chains, or tracing, use [`Bgr`] and [`BgrSession`] directly.
+///
+/// # Example
+/// ```no_run
+/// use bgr::models::ModelPreset;
+/// use bgr::{Device, Remover};
+///
+/// let mut remover = Remover::builder()
+///     .model(ModelPreset::IsNet)
+///     .device(Device::Cpu)
+///     .build()?;
+/// let photo = image::open("photo.jpg")?;
+/// remover.remove(&photo)?.save("cutout.png")?;
+/// remover.mask(&photo)?.save("mask.png")?;
+///
+/// // Encoded bytes in and out, as for an upload
+/// let png = remover.remove_bytes(&std::fs::read("upload.jpg")?)?;
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub struct Remover {
+    session: BgrSession,
+    processed: bool,
+}
+
+impl Remover {
+    /// Start configuring a remover; the default is the BiRefNet preset on the CPU.
+    pub fn builder() -> RemoverBuilder {
+        RemoverBuilder::default()
+    }
+
+    /// The device the model runs on, after any fallback to the CPU.
+    pub fn device(&self) -> Device {
+        self.session.device()
+    }
+
+    /// Cut the subject out of `image`, returning it on a transparent background.
+    pub fn remove(&mut self, image: &DynamicImage) -> BgrResult<RgbaImage> {
+        remove(&mut self.session, self.processed, image)
+    }
+
+    /// The alpha mask of `image`'s subject, at the image's size.
+    pub fn mask(&mut self, image: &DynamicImage) -> BgrResult<GrayImage> {
+        mask(&mut self.session, self.processed, image)
+    }
+
+    /// Cut the subject out of an encoded image and return the cutout encoded as PNG, without
+    /// touching the filesystem.
+    ///
+    /// The input format is detected from its contents, and the input limits apply.
+    pub fn remove_bytes(&mut self, encoded: &[u8]) -> BgrResult<Vec<u8>> {
+        remove_bytes(&mut self.session, self.processed, encoded)
+    }
+
+    /// The alpha mask of an encoded image's subject, encoded as a grayscale PNG.
+    pub fn mask_bytes(&mut self, encoded: &[u8]) -> BgrResult<Vec<u8>> {
+        mask_bytes(&mut self.session, self.processed, encoded)
+    }
+}
+
+/// The async counterpart of [`Remover`], for servers: calls run on tokio's blocking thread
+/// pool against a shared [`SessionPool`], so concurrent requests use idle sessions side by
+/// side and only wait when every session is busy.
+///
+/// Cloning is cheap and shares the sessions. Requires the `tokio` feature.
+///
+/// # Example
+/// ```no_run
+/// use bgr::models::ModelPreset;
+/// use bgr::Remover;
+///
+/// # async fn serve(uploads: Vec<Vec<u8>>) -> bgr::BgrResult<()> {
+/// // Loading blocks, so do it before serving or inside spawn_blocking.
+/// let remover = Remover::builder().model(ModelPreset::IsNet).build_async(4)?;
+/// let mut requests = Vec::new();
+/// for upload in uploads {
+///     let remover = remover.clone();
+///     requests.push(tokio::spawn(async move { remover.remove_bytes_async(upload).await }));
+/// }
+/// for request in requests {
+///     let png = request.await.expect("request task panicked")?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[derive(Clone)]
+pub struct AsyncRemover {
+    pool: Arc<SessionPool>,
+    processed: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRemover {
+    /// Number of sessions requests are spread over.
+    pub fn sessions(&self) -> usize {
+        self.pool.size()
+    }
+
+    /// Cut the subject out of `image`, returning it on a transparent background.
+    pub async fn remove_async(&self, image: DynamicImage) -> BgrResult<RgbaImage> {
+        self.run(move |session, processed| remove(session, processed, &image))
+            .await
+    }
+
+    /// The alpha mask of `image`'s subject, at the image's size.
+    pub async fn mask_async(&self, image: DynamicImage) -> BgrResult<GrayImage> {
+        self.run(move |session, processed| mask(session, processed, &image))
+            .await
+    }
+
+    /// Cut the subject out of an encoded image and return the cutout encoded as PNG.
+    pub async fn remove_bytes_async(&self, encoded: Vec<u8>) -> BgrResult<Vec<u8>> {
+        self.run(move |session, processed| remove_bytes(session, processed, &encoded))
+            .await
+    }
+
+    /// The alpha mask of an encoded image's subject, encoded as a grayscale PNG.
+    pub async fn mask_bytes_async(&self, encoded: Vec<u8>) -> BgrResult<Vec<u8>> {
+        self.run(move |session, processed| mask_bytes(session, processed, &encoded))
+            .await
+    }
+
+    /// Run `f` with an idle session on the blocking thread pool; checking out may wait.
+    async fn run<T, F>(&self, f: F) -> BgrResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut BgrSession, bool) -> BgrResult<T> + Send + 'static,
+    {
+        let pool = Arc::clone(&self.pool);
+        let processed = self.processed;
+        tokio::task::spawn_blocking(move || pool.run(|session| f(session, processed)))
+            .await
+            .unwrap_or_else(|err| match err.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(err) => Err(std::io::Error::other(err).into()),
+            })
+    }
+}
+
+fn remove(session: &mut BgrSession, processed: bool, image: &DynamicImage) -> BgrResult<RgbaImage> {
+    let inferred = session.for_dynamic_image(image)?;
+    Ok(foreground(&inferred, processed)?.into_image())
+}
+
+fn mask(session: &mut BgrSession, processed: bool, image: &DynamicImage) -> BgrResult<GrayImage> {
+    let inferred = session.for_dynamic_image(image)?;
+    alpha(&inferred, processed)
+}
+
+fn remove_bytes(session: &mut BgrSession, processed: bool, encoded: &[u8]) -> BgrResult<Vec<u8>> {
+    let inferred = session.for_bytes(encoded)?;
+    let foreground = foreground(&inferred, processed)?;
+    session.progress().enter(Stage::Encode);
+    tracing::debug_span!("encode").in_scope(|| foreground.encode(ImageFormat::Png))
+}
+
+fn mask_bytes(session: &mut BgrSession, processed: bool, encoded: &[u8]) -> BgrResult<Vec<u8>> {
+    let inferred = session.for_bytes(encoded)?;
+    let mask = alpha(&inferred, processed)?;
+    session.progress().enter(Stage::Encode);
+    tracing::debug_span!("encode")
+        .in_scope(|| encode_image(DynamicImage::ImageLuma8(mask), ImageFormat::Png))
+}
+
+/// The cutout from the raw matte, or from the processed mask when processing was configured.
+fn foreground(inferred: &InferencedMatte, processed: bool) -> BgrResult<ForegroundHandle> {
+    let matte = inferred.matte();
+    if processed {
+        matte.processed()?.foreground()
+    } else {
+        matte.foreground()
+    }
+}
+
+fn alpha(inferred: &InferencedMatte, processed: bool) -> BgrResult<GrayImage> {
+    let matte = inferred.matte();
+    if processed {
+        Ok(matte.processed()?.into_image())
+    } else {
+        Ok(matte.into_image())
+    }
+}
+
+/// Configures and loads a [`Remover`]; created by [`Remover::builder`].
+#[derive(Debug, Clone)]
+pub struct RemoverBuilder {
+    model: ModelSource,
+    device: Device,
+    models_dir: Option<PathBuf>,
+    offline: bool,
+    intra_threads: Option<usize>,
+    limits: InputLimits,
+    mask_processing: Option<MaskProcessingOptions>,
+    cancellation: CancellationToken,
+    progress: SharedProgress,
+    telemetry: SharedTelemetry,
+}
+
+impl Default for RemoverBuilder {
+    fn default() -> Self {
+        Self {
+            model: ModelSource::Preset(ModelPreset::BiRefNet),
+            device: Device::Cpu,
+            models_dir: None,
+            offline: false,
+            intra_threads: None,
+            limits: InputLimits::default(),
+            mask_processing: None,
+            cancellation: CancellationToken::new(),
+            progress: SharedProgress::default(),
+            telemetry: SharedTelemetry::default(),
+        }
+    }
+}
+
+impl RemoverBuilder {
+    /// Use a model preset, downloaded into the models directory when missing.
+    ///
+    /// Presets under a restrictive license are only downloaded once
+    /// [`accept_license`](crate::models::accept_license) has recorded their acceptance.
+    pub fn model(mut self, preset: ModelPreset) -> Self {
+        self.model = ModelSource::Preset(preset);
+        self
+    }
+
+    /// Use an ONNX model file instead of a preset.
+    pub fn model_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.model = ModelSource::Path(path.into());
+        self
+    }
+
+    /// Load the model from `bytes`, such as a preset fetched by a browser page, instead of
+    /// from a file.
+    pub fn model_bytes(mut self, bytes: impl Into<Arc<[u8]>>) -> Self {
+        self.model = ModelSource::Bytes(ModelBytes::new(bytes));
+        self
+    }
+
+    /// Set the execution device; unavailable accelerators fall back to the CPU.
+    pub fn device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Store presets in `dir` instead of the platform models directory.
+    pub fn models_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.models_dir = Some(dir.into());
+        self
+    }
+
+    /// Never download; presets must already be in the models directory.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Set the number of threads ONNX Runtime uses within an operator.
+    pub fn intra_threads(mut self, intra_threads: usize) -> Self {
+        self.intra_threads = Some(intra_threads);
+        self
+    }
+
+    /// Set the limits checked against each image before inference.
+    pub fn limits(mut self, limits: InputLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Process every matte with `options` before it is returned or applied, instead of using
+    /// the model's soft matte as is.
+    pub fn mask_processing(mut self, options: MaskProcessingOptions) -> Self {
+        self.mask_processing = Some(options);
+        self
+    }
+
+    /// Stop the model download and later calls with
+    /// [`BgrError::Cancelled`](crate::BgrError::Cancelled) once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Report the preset download and the stages of every call to `sink`.
+    pub fn progress(mut self, sink: impl ProgressSink + 'static) -> Self {
+        self.progress = SharedProgress::new(sink);
+        self
+    }
+
+    /// Call `telemetry`'s hooks when the session loads and around each model run.
+    pub fn telemetry(mut self, telemetry: impl Telemetry + 'static) -> Self {
+        self.telemetry = SharedTelemetry::new(telemetry);
+        self
+    }
+
+    /// Resolve the model, downloading a preset if needed, and load its session.
+    pub fn build(self) -> BgrResult<Remover> {
+        Ok(Remover {
+            session: self.bgr()?.session()?,
+            processed: self.mask_processing.is_some(),
+        })
+    }
+
+    /// Resolve the model like [`build`](RemoverBuilder::build) and load `sessions` sessions
+    /// for concurrent async use (`0` = one per available core).
+    ///
+    /// This blocks while models download and load.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub fn build_async(self, sessions: usize) -> BgrResult<AsyncRemover> {
+        Ok(AsyncRemover {
+            pool: Arc::new(self.bgr()?.session_pool(sessions)?),
+            processed: self.mask_processing.is_some(),
+        })
+    }
+
+    fn bgr(&self) -> BgrResult<Bgr> {
+        let mut ctx = Context::new().with_offline(self.offline);
+        if let Some(dir) = &self.models_dir {
+            ctx = ctx.with_models_dir(dir);
+        }
+        let mut defaults = ctx
+            .inference_settings()
+            .clone()
+            .with_cancellation(self.cancellation.clone());
+        defaults.progress = self.progress.clone();
+        ctx = ctx.with_inference_settings(defaults);
+        let model_path = match &self.model {
+            ModelSource::Preset(preset) => ctx.resolve_model(preset.name())?,
+            ModelSource::Path(path) => path.clone(),
+            ModelSource::Bytes(_) => PathBuf::from(DEFAULT_MODEL_PATH),
+        };
+        let mut settings = InferenceSettings::new(model_path)
+            .with_device(self.device)
+            .with_intra_threads(self.intra_threads)
+            .with_limits(self.limits)
+            .with_cancellation(self.cancellation.clone());
+        settings.progress = self.progress.clone();
+        settings.telemetry = self.telemetry.clone();
+        if let ModelSource::Bytes(bytes) = &self.model {
+            settings.model_bytes = Some(bytes.clone());
+        }
+        if let ModelSource::Preset(preset) = &self.model {
+            settings = settings.with_adapter(preset.architecture());
+        }
+        Ok(Bgr::from_context(&ctx.with_inference_settings(settings))
+            .with_default_mask_processing(self.mask_processing.clone().unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod remover_builder {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn defaults_to_birefnet_on_cpu() {
+                let builder = Remover::builder();
+                assert_eq!(builder.model, ModelSource::Preset(ModelPreset::BiRefNet));
+                assert_eq!(builder.device, Device::Cpu);
+                assert!(builder.mask_processing.is_none());
+            }
+
+            #[test]
+            fn later_model_choice_wins() {
+                let builder = Remover::builder()
+                    .model(ModelPreset::IsNet)
+                    .model_path("custom.onnx");
+                assert_eq!(builder.model, ModelSource::Path("custom.onnx".into()));
+                let builder = builder.model(ModelPreset::U2Net);
+                assert_eq!(builder.model, ModelSource::Preset(ModelPreset::U2Net));
+            }
+
+            #[test]
+            fn offline_missing_preset_fails() {
+                let dir = std::env::temp_dir().join("bgr-remover-no-models");
+                let result = Remover::builder()
+                    .model(ModelPreset::IsNet)
+                    .models_dir(&dir)
+                    .offline(true)
+                    .build();
+                assert!(result.is_err());
+            }
+        }
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod remove_bytes {
+        use super::*;
+        use crate::InputId;
+        use crate::testing::{TinyModelDir, fixtures};
+        use std::sync::{Arc, Mutex};
+
+        /// Runs the tiny model; needs the ONNX Runtime library.
+        mod integration {
+            use super::*;
+
+            #[test]
+            fn reports_every_stage_in_order() {
+                let model = TinyModelDir::new().unwrap();
+                let stages = Arc::new(Mutex::new(Vec::new()));
+
+                #[derive(Clone)]
+                struct Recorder(Arc<Mutex<Vec<(InputId, Stage)>>>);
+                impl ProgressSink for Recorder {
+                    fn stage(&self, input: InputId, stage: Stage) {
+                        self.0.lock().unwrap().push((input, stage));
+                    }
+                }
+
+                let mut remover = Remover::builder()
+                    .model_path(model.model())
+                    .progress(Recorder(Arc::clone(&stages)))
+                    .build()
+                    .unwrap();
+                let fixture = fixtures().remove(0);
+                let png =
+                    encode_image(DynamicImage::ImageRgb8(fixture.image), ImageFormat::Png).unwrap();
+                remover.remove_bytes(&png).unwrap();
+                let stages = stages.lock().unwrap();
+                assert!(stages.iter().all(|(input, _)| *input == stages[0].0));
+                assert_eq!(
+                    stages.iter().map(|(_, stage)| *stage).collect::<Vec<_>>(),
+                    [
+                        Stage::Decode,
+                        Stage::Preprocess,
+                        Stage::Inference,
+                        Stage::Postprocess,
+                        Stage::Encode
+                    ]
+                );
+            }
+
+            #[test]
+            fn model_bytes_need_no_model_file() {
+                let model = std::fs::read(TinyModelDir::new().unwrap().model()).unwrap();
+
+                let mut remover = Remover::builder().model_bytes(model).build().unwrap();
+                let fixture = fixtures().remove(0);
+                let png =
+                    encode_image(DynamicImage::ImageRgb8(fixture.image), ImageFormat::Png).unwrap();
+                assert!(!remover.mask_bytes(&png).unwrap().is_empty());
+            }
+        }
+    }
+
+    #[cfg(all(feature = "tokio", feature = "test-utils"))]
+    mod async_remover {
+        use super::*;
+        use crate::testing::{TinyModelDir, fixtures};
+
+        /// Runs concurrent requests with the tiny model; needs the ONNX Runtime library.
+        mod integration {
+            use super::*;
+
+            #[test]
+            fn concurrent_requests_share_the_pool() {
+                let model = TinyModelDir::new().unwrap();
+                let remover = Remover::builder()
+                    .model_path(model.model())
+                    .build_async(2)
+                    .unwrap();
+                assert_eq!(remover.sessions(), 2);
+
+                let runtime = tokio::runtime::Runtime::new().unwrap();
+                let cutouts = runtime.block_on(async {
+                    let requests: Vec<_> = fixtures()
+                        .into_iter()
+                        .map(|fixture| {
+                            let remover = remover.clone();
+                            let image = DynamicImage::ImageRgb8(fixture.image);
+                            tokio::spawn(async move { remover.remove_async(image).await })
+                        })
+                        .collect();
+                    let mut cutouts = Vec::new();
+                    for request in requests {
+                        cutouts.push(request.await.unwrap().unwrap());
+                    }
+                    cutouts
+                });
+                for (cutout, fixture) in cutouts.iter().zip(fixtures()) {
+                    assert_eq!(cutout.dimensions(), fixture.image.dimensions());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::RgbImage;
+
+use crate::color::RenderingIntent;
+use crate::inference::decode_rgb_with_orientation;
+use crate::limits::{InputLimits, limited_decoder_from_bytes};
+use crate::{BgrError, BgrResult, Warning};
+
+/// Subcommand of the `bgr` binary that runs [`run_decode_worker`] on its stdin and stdout.
+pub const DECODE_WORKER_COMMAND: &str = "decode-worker";
+
+const REQUEST_MAGIC: &[u8; 4] = b"BGRD";
+const RESPONSE_MAGIC: &[u8; 4] = b"BGRP";
+/// Stand-in for a disabled limit on the wire.
+const NO_LIMIT: u64 = u64::MAX;
+/// Only the start of the worker's stderr is kept for the error message.
+const MAX_STDERR_BYTES: u64 = 64 << 10;
+/// Upper bound on the JSON warning list trailing the pixels.
+const MAX_WARNINGS_BYTES: u32 = 64 << 10;
+
+/// Decodes untrusted inputs in a separate worker process instead of the calling process.
+///
+/// Image decoders are a common source of memory-safety bugs, so services that accept images
+/// from the public internet can keep that code out of the process holding the model. The
+/// parent reads the file and pipes its encoded bytes to the worker, which sees neither the
+/// path nor the parent's environment, decodes within the same [`InputLimits`], and returns
+/// raw RGB pixels over its stdout. The parent re-checks the returned dimensions and kills a
+/// worker that exceeds the timeout.
+///
+/// The worker is any program that calls [`run_decode_worker`]; the `bgr` binary provides one
+/// as its hidden `decode-worker` subcommand. For stronger isolation, point `program` at a
+/// wrapper such as `bwrap` or `nsjail` and pass the worker command as arguments.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{InferenceSettings, SandboxedDecoder};
+///
+/// let settings = InferenceSettings::new("model.onnx")
+///     .with_sandbox(SandboxedDecoder::current_exe()?);
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SandboxedDecoder {
+    program: PathBuf,
+    args: Vec<OsString>,
+    timeout: Duration,
+    #[cfg(unix)]
+    user: Option<(u32, u32)>,
+}
+
+impl SandboxedDecoder {
+    /// Run `program` as the worker, with a 30 second timeout per image.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout: Duration::from_secs(30),
+            #[cfg(unix)]
+            user: None,
+        }
+    }
+
+    /// Run the current executable's `decode-worker` subcommand as the worker.
+    pub fn current_exe() -> BgrResult<Self> {
+        Ok(Self::new(std::env::current_exe()?).with_arg(DECODE_WORKER_COMMAND))
+    }
+
+    /// Append an argument to the worker command line.
+    pub fn with_arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Set how long a single decode may take before the worker is killed.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the worker as another user and group, typically an unprivileged one.
+    ///
+    /// The calling process must be allowed to switch to them (usually it runs as root).
+    #[cfg(unix)]
+    pub fn with_user(mut self, uid: u32, gid: u32) -> Self {
+        self.user = Some((uid, gid));
+        self
+    }
+
+    /// Decode the image at `path` in a worker process, within `limits`, along with any warnings
+    /// the worker noticed. An embedded color profile is converted to sRGB with `intent`, so
+    /// the profile is parsed in the worker too, as is the EXIF orientation applied with
+    /// `auto_orient`.
+    pub fn decode(
+        &self,
+        path: &Path,
+        limits: &InputLimits,
+        intent: RenderingIntent,
+        auto_orient: bool,
+    ) -> BgrResult<(RgbImage, Vec<Warning>)> {
+        limits.check_file_size(fs::metadata(path)?.len())?;
+        self.decode_bytes(&fs::read(path)?, limits, intent, auto_orient)
+    }
+
+    /// Decode an encoded image held in memory in a worker process, within `limits`.
+    pub fn decode_bytes(
+        &self,
+        encoded: &[u8],
+        limits: &InputLimits,
+        intent: RenderingIntent,
+        auto_orient: bool,
+    ) -> BgrResult<(RgbImage, Vec<Warning>)> {
+        limits.check_file_size(encoded.len() as u64)?;
+
+        let mut child = self.command().spawn().map_err(|err| {
+            sandbox_error(format!("cannot start {}: {err}", self.program.display()))
+        })?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let (status, response, message) = thread::scope(|scope| {
+            scope.spawn(move || {
+                // A worker that rejects the input exits early; its stderr explains why.
+                let _ = write_request(&mut stdin, limits, intent, auto_orient, encoded);
+            });
+            let response = scope.spawn(move || read_response(stdout, limits));
+            let message = scope.spawn(move || {
+                let mut message = String::new();
+                let _ = stderr.take(MAX_STDERR_BYTES).read_to_string(&mut message);
+                message
+            });
+            let status = wait_with_timeout(&mut child, self.timeout);
+            (status, response.join(), message.join())
+        });
+
+        let message = message.unwrap_or_default();
+        match status? {
+            Some(status) if status.success() => {
+                response.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            }
+            Some(status) => Err(sandbox_error(format!(
+                "worker exited with {status}: {}",
+                message.trim()
+            ))),
+            None => Err(sandbox_error(format!(
+                "worker timed out after {:?}",
+                self.timeout
+            ))),
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .current_dir(std::env::temp_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            // Windows processes need part of the environment to start, so it is only cleared here.
+            command.env_clear();
+            if let Some((uid, gid)) = self.user {
+                command.uid(uid).gid(gid);
+            }
+        }
+        command
+    }
+}
+
+/// Serve one decode request from a [`SandboxedDecoder`]: read the encoded image and limits from
+/// `input`, then write the decoded RGB pixels and any warnings to `output`.
+pub fn run_decode_worker(mut input: impl Read, mut output: impl Write) -> BgrResult<()> {
+    let mut header = [0u8; 38];
+    input.read_exact(&mut header)?;
+    if &header[..4] != REQUEST_MAGIC {
+        return Err(sandbox_error("unrecognised request"));
+    }
+    let field = |index: usize| {
+        let start = 4 + index * 8;
+        let value = u64::from_le_bytes(header[start..start + 8].try_into().unwrap());
+        (value != NO_LIMIT).then_some(value)
+    };
+    let limits = InputLimits {
+        max_dimension: field(0).map(|value| u32::try_from(value).unwrap_or(u32::MAX)),
+        max_pixels: field(1),
+        max_file_bytes: field(2),
+        max_decoded_bytes: field(3),
+    };
+    let intent =
+        RenderingIntent::from_byte(header[36]).ok_or_else(|| sandbox_error("unknown intent"))?;
+    let auto_orient = header[37] != 0;
+
+    let mut encoded = Vec::new();
+    // One byte over the limit is enough to reject the file without reading all of it.
+    let cap = limits.max_file_bytes.map_or(u64::MAX, |max| max + 1);
+    input.take(cap).read_to_end(&mut encoded)?;
+    let decoded = decode_rgb_with_orientation(
+        limited_decoder_from_bytes(&encoded, &limits)?,
+        intent,
+        auto_orient,
+    )?;
+    let warnings = serde_json::to_vec(&decoded.warnings).map_err(io::Error::other)?;
+
+    output.write_all(RESPONSE_MAGIC)?;
+    output.write_all(&decoded.rgb.width().to_le_bytes())?;
+    output.write_all(&decoded.rgb.height().to_le_bytes())?;
+    output.write_all(decoded.rgb.as_raw())?;
+    output.write_all(&(warnings.len() as u32).to_le_bytes())?;
+    output.write_all(&warnings)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn write_request(
+    output: &mut impl Write,
+    limits: &InputLimits,
+    intent: RenderingIntent,
+    auto_orient: bool,
+    encoded: &[u8],
+) -> io::Result<()> {
+    output.write_all(REQUEST_MAGIC)?;
+    for limit in [
+        limits.max_dimension.map(u64::from),
+        limits.max_pixels,
+        limits.max_file_bytes,
+        limits.max_decoded_bytes,
+    ] {
+        output.write_all(&limit.unwrap_or(NO_LIMIT).to_le_bytes())?;
+    }
+    output.write_all(&[intent.to_byte(), u8::from(auto_orient)])?;
+    output.write_all(encoded)?;
+    output.flush()
+}
+
+/// Read the worker's answer, trusting none of it until it passes `limits`.
+fn read_response(
+    mut input: impl Read,
+    limits: &InputLimits,
+) -> BgrResult<(RgbImage, Vec<Warning>)> {
+    let mut header = [0u8; 12];
+    input
+        .read_exact(&mut header)
+        .map_err(|err| sandbox_error(format!("truncated response: {err}")))?;
+    if &header[..4] != RESPONSE_MAGIC {
+        return Err(sandbox_error("unrecognised response"));
+    }
+    let width = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let height = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    limits.check_dimensions(width, height)?;
+    let len = u64::from(width) * u64::from(height) * 3;
+    limits.check_decoded_size(len)?;
+
+    let len = usize::try_from(len).map_err(|_| sandbox_error("response too large"))?;
+    let mut pixels = vec![0u8; len];
+    input
+        .read_exact(&mut pixels)
+        .map_err(|err| sandbox_error(format!("truncated response: {err}")))?;
+    let rgb = RgbImage::from_raw(width, height, pixels).expect("buffer matches dimensions");
+
+    let mut len = [0u8; 4];
+    input
+        .read_exact(&mut len)
+        .map_err(|err| sandbox_error(format!("truncated response: {err}")))?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_WARNINGS_BYTES {
+        return Err(sandbox_error("response too large"));
+    }
+    let mut warnings = vec![0u8; len as usize];
+    input
+        .read_exact(&mut warnings)
+        .map_err(|err| sandbox_error(format!("truncated response: {err}")))?;
+    let warnings = serde_json::from_slice(&warnings)
+        .map_err(|err| sandbox_error(format!("malformed warnings: {err}")))?;
+    Ok((rgb, warnings))
+}
+
+/// Wait for `child` to exit, killing it once `timeout` passes (`Ok(None)`).
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> BgrResult<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            // Killing closes the pipes, which also unblocks the reader threads.
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn sandbox_error(message: impl Into<String>) -> BgrError {
+    BgrError::Sandbox(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encoded_png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn request(limits: &InputLimits, encoded: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_request(
+            &mut bytes,
+            limits,
+            RenderingIntent::default(),
+            true,
+            encoded,
+        )
+        .unwrap();
+        bytes
+    }
+
+    mod run_decode_worker {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn round_trips_pixels() {
+                let limits = InputLimits::default();
+                let mut response = Vec::new();
+                run_decode_worker(&request(&limits, &encoded_png(6, 4))[..], &mut response)
+                    .unwrap();
+
+                let (rgb, warnings) = read_response(&response[..], &limits).unwrap();
+                assert!(warnings.is_empty());
+                assert_eq!(rgb.dimensions(), (6, 4));
+                assert_eq!(rgb.get_pixel(5, 3).0, [5, 3, 7]);
+            }
+
+            #[test]
+            fn enforces_forwarded_limits() {
+                let limits = InputLimits {
+                    max_dimension: Some(4),
+                    ..InputLimits::default()
+                };
+                let result =
+                    run_decode_worker(&request(&limits, &encoded_png(6, 4))[..], io::sink());
+                assert!(matches!(
+                    result,
+                    Err(BgrError::LimitExceeded { what: "width", .. })
+                ));
+            }
+
+            #[test]
+            fn unlimited_survives_the_wire() {
+                let limits = InputLimits::unlimited();
+                let mut response = Vec::new();
+                run_decode_worker(&request(&limits, &encoded_png(2, 2))[..], &mut response)
+                    .unwrap();
+                // Header, pixels, then an empty warning list.
+                assert_eq!(response.len(), 12 + 2 * 2 * 3 + 4 + 2);
+            }
+
+            #[test]
+            fn rejects_garbage() {
+                let result = run_decode_worker(&[0u8; 64][..], io::sink());
+                assert!(matches!(result, Err(BgrError::Sandbox(_))));
+            }
+        }
+    }
+
+    mod read_response {
+        use super::*;
+
+        fn response(width: u32, height: u32, pixels: usize) -> Vec<u8> {
+            let mut bytes = RESPONSE_MAGIC.to_vec();
+            bytes.extend(width.to_le_bytes());
+            bytes.extend(height.to_le_bytes());
+            bytes.resize(bytes.len() + pixels, 0);
+            bytes.extend(2u32.to_le_bytes());
+            bytes.extend(b"[]");
+            bytes
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn claimed_dimensions_are_checked() {
+                let result = read_response(&response(100_000, 1, 0)[..], &InputLimits::default());
+                assert!(matches!(
+                    result,
+                    Err(BgrError::LimitExceeded { what: "width", .. })
+                ));
+            }
+
+            #[test]
+            fn truncated_pixels_rejected() {
+                let result = read_response(&response(4, 4, 10)[..], &InputLimits::default());
+                assert!(matches!(result, Err(BgrError::Sandbox(_))));
+            }
+
+            #[test]
+            fn wrong_magic_rejected() {
+                let mut bytes = response(1, 1, 3);
+                bytes[0] = b'X';
+                let result = read_response(&bytes[..], &InputLimits::default());
+                assert!(matches!(result, Err(BgrError::Sandbox(_))));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    mod decode {
+        use super::*;
+
+        fn write_png(name: &str) -> PathBuf {
+            let path =
+                std::env::temp_dir().join(format!("bgr-sandbox-{name}-{}.png", std::process::id()));
+            fs::write(&path, encoded_png(3, 3)).unwrap();
+            path
+        }
+
+        fn shell(script: &str) -> SandboxedDecoder {
+            SandboxedDecoder::new("/bin/sh")
+                .with_arg("-c")
+                .with_arg(script)
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn failing_worker_reports_stderr() {
+                let path = write_png("fail");
+                let decoder = shell("cat >/dev/null; echo 'bad input' >&2; exit 3");
+                let err = decoder
+                    .decode(
+                        &path,
+                        &InputLimits::default(),
+                        RenderingIntent::default(),
+                        true,
+                    )
+                    .unwrap_err();
+                let message = err.to_string();
+                assert!(message.contains("bad input"), "{message}");
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn hung_worker_is_killed() {
+                let path = write_png("hang");
+                let decoder = shell("exec sleep 5").with_timeout(Duration::from_millis(50));
+                let started = Instant::now();
+                let err = decoder
+                    .decode(
+                        &path,
+                        &InputLimits::default(),
+                        RenderingIntent::default(),
+                        true,
+                    )
+                    .unwrap_err();
+                assert!(err.to_string().contains("timed out"));
+                assert!(started.elapsed() < Duration::from_secs(4));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn oversized_file_never_reaches_worker() {
+                let path = write_png("big");
+                let limits = InputLimits {
+                    max_file_bytes: Some(8),
+                    ..InputLimits::default()
+                };
+                let err = shell("exit 9")
+                    .decode(&path, &limits, RenderingIntent::default(), true)
+                    .unwrap_err();
+                assert!(matches!(
+                    err,
+                    BgrError::LimitExceeded {
+                        what: "file size in bytes",
+                        ..
+                    }
+                ));
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+
+use crate::foreground::encode_image;
+use crate::{BgrError, BgrResult};
+
+/// Name of the alpha channel that holds the selection in a PSD.
+const CHANNEL_NAME: &str = "bgr selection";
+
+/// Image resource ID of the alpha channel names, as Pascal strings.
+const RESOURCE_ALPHA_NAMES: u16 = 1006;
+
+/// Files that image editors load as a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionFormat {
+    /// A Photoshop document of the original image with the matte as a named alpha channel.
+    ///
+    /// Load it with *Select > Load Selection* in Photoshop or *Channel to Selection* in GIMP.
+    Psd,
+    /// An 8-bit grayscale BMP, the clip channel format older retouching tools import.
+    Bmp,
+}
+
+impl SelectionFormat {
+    /// The usual file extension.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SelectionFormat::Psd => "psd",
+            SelectionFormat::Bmp => "bmp",
+        }
+    }
+}
+
+/// Encode `mask` as a selection over `rgb`; white is selected.
+pub(crate) fn encode_selection(
+    rgb: &RgbImage,
+    mask: &GrayImage,
+    format: SelectionFormat,
+) -> BgrResult<Vec<u8>> {
+    let expected = rgb.dimensions();
+    let found = mask.dimensions();
+    if expected != found {
+        return Err(BgrError::AlphaMismatch { expected, found });
+    }
+    match format {
+        SelectionFormat::Psd => Ok(encode_psd(rgb, mask)),
+        SelectionFormat::Bmp => {
+            encode_image(DynamicImage::ImageLuma8(mask.clone()), ImageFormat::Bmp)
+        }
+    }
+}
+
+/// An uncompressed 8-bit RGB PSD whose fourth channel is `mask`, named [`CHANNEL_NAME`].
+fn encode_psd(rgb: &RgbImage, mask: &GrayImage) -> Vec<u8> {
+    let (width, height) = rgb.dimensions();
+    let pixels = rgb.as_raw().len() / 3;
+    let mut out = Vec::with_capacity(64 + pixels * 4);
+
+    // Header: signature, version 1, reserved, channels, size, depth, RGB color mode.
+    out.extend_from_slice(b"8BPS");
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&[0; 6]);
+    out.extend_from_slice(&4u16.to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&8u16.to_be_bytes());
+    out.extend_from_slice(&3u16.to_be_bytes());
+
+    // No color mode data.
+    out.extend_from_slice(&0u32.to_be_bytes());
+
+    // Image resources: the alpha channel's name.
+    let mut names = vec![CHANNEL_NAME.len() as u8];
+    names.extend_from_slice(CHANNEL_NAME.as_bytes());
+    let mut resources = Vec::new();
+    resources.extend_from_slice(b"8BIM");
+    resources.extend_from_slice(&RESOURCE_ALPHA_NAMES.to_be_bytes());
+    resources.extend_from_slice(&[0, 0]); // empty, padded resource name
+    resources.extend_from_slice(&(names.len() as u32).to_be_bytes());
+    resources.extend_from_slice(&names);
+    if names.len() % 2 == 1 {
+        resources.push(0);
+    }
+    out.extend_from_slice(&(resources.len() as u32).to_be_bytes());
+    out.extend_from_slice(&resources);
+
+    // No layers, so the channels below are the document itself.
+    out.extend_from_slice(&0u32.to_be_bytes());
+
+    // Raw image data, one plane per channel.
+    out.extend_from_slice(&0u16.to_be_bytes());
+    for channel in 0..3 {
+        out.extend(rgb.pixels().map(|pixel| pixel[channel]));
+    }
+    out.extend_from_slice(mask.as_raw());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, Rgb};
+
+    mod encode_selection {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn psd_has_rgb_and_a_named_alpha_channel() {
+                let rgb = RgbImage::from_pixel(3, 2, Rgb([10, 20, 30]));
+                let mask = GrayImage::from_pixel(3, 2, Luma([255]));
+                let psd = encode_selection(&rgb, &mask, SelectionFormat::Psd).unwrap();
+                assert_eq!(&psd[..4], b"8BPS");
+                assert_eq!(u16::from_be_bytes([psd[12], psd[13]]), 4);
+                assert!(
+                    psd.windows(CHANNEL_NAME.len())
+                        .any(|window| window == CHANNEL_NAME.as_bytes())
+                );
+                // The image data ends with the R, G, B, and mask planes.
+                assert_eq!(&psd[psd.len() - 24..psd.len() - 18], &[10; 6]);
+                assert_eq!(&psd[psd.len() - 6..], &[255; 6]);
+            }
+
+            #[test]
+            fn bmp_is_8_bit() {
+                let rgb = RgbImage::new(4, 4);
+                let mask = GrayImage::from_pixel(4, 4, Luma([128]));
+                let bmp = encode_selection(&rgb, &mask, SelectionFormat::Bmp).unwrap();
+                assert_eq!(&bmp[..2], b"BM");
+                assert_eq!(u16::from_le_bytes([bmp[28], bmp[29]]), 8);
+            }
+
+            #[test]
+            fn mismatched_mask_rejected() {
+                let rgb = RgbImage::new(4, 4);
+                let mask = GrayImage::new(2, 2);
+                assert!(encode_selection(&rgb, &mask, SelectionFormat::Psd).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use image::{GrayImage, Luma, Rgb, Rgba, RgbaImage};
+use imageproc::filter::gaussian_blur_f32;
+
+/// A soft drop shadow cast by the subject, for catalog-style cutouts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowOptions {
+    /// Shift of the shadow from the subject in pixels; positive values go right and down.
+    pub offset_x: i32,
+    pub offset_y: i32,
+    /// Standard deviation of the Gaussian blur softening the shadow, in pixels.
+    pub blur: f32,
+    /// Shadow alpha where the subject is fully opaque, from 0 to 1.
+    pub opacity: f32,
+    pub color: Rgb<u8>,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            offset_x: 8,
+            offset_y: 12,
+            blur: 10.0,
+            opacity: 0.5,
+            color: Rgb([0, 0, 0]),
+        }
+    }
+}
+
+/// Render a shadow from the alpha channel of `cutout` and place the cutout over it.
+///
+/// The shadow is the alpha channel shifted by the offset, blurred, and scaled by the opacity;
+/// it stays within the cutout's frame, so leave transparent room around the subject (or trim
+/// afterwards) when the offset and blur are large.
+pub fn add_shadow(cutout: &RgbaImage, options: &ShadowOptions) -> RgbaImage {
+    let (width, height) = cutout.dimensions();
+    let shifted = GrayImage::from_fn(width, height, |x, y| {
+        let sx = i64::from(x) - i64::from(options.offset_x);
+        let sy = i64::from(y) - i64::from(options.offset_y);
+        if (0..i64::from(width)).contains(&sx) && (0..i64::from(height)).contains(&sy) {
+            Luma([cutout.get_pixel(sx as u32, sy as u32)[3]])
+        } else {
+            Luma([0])
+        }
+    });
+    let shadow = if options.blur > 0.0 {
+        gaussian_blur_f32(&shifted, options.blur)
+    } else {
+        shifted
+    };
+
+    let opacity = options.opacity.clamp(0.0, 1.0);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let shadow_alpha = f32::from(shadow.get_pixel(x, y)[0]) / 255.0 * opacity;
+        over(*cutout.get_pixel(x, y), options.color, shadow_alpha)
+    })
+}
+
+/// Alpha-composite `fg` over a pixel of `color` with alpha `bg_alpha`.
+pub(crate) fn over(fg: Rgba<u8>, color: Rgb<u8>, bg_alpha: f32) -> Rgba<u8> {
+    let fg_alpha = f32::from(fg[3]) / 255.0;
+    let alpha = fg_alpha + bg_alpha * (1.0 - fg_alpha);
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| {
+        let value = (f32::from(fg[i]) * fg_alpha
+            + f32::from(color[i]) * bg_alpha * (1.0 - fg_alpha))
+            / alpha;
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha * 255.0).round() as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 40×40 cutout with an opaque white 10×10 subject at (10, 10).
+    fn cutout() -> RgbaImage {
+        RgbaImage::from_fn(40, 40, |x, y| {
+            let inside = (10..20).contains(&x) && (10..20).contains(&y);
+            Rgba([255, 255, 255, if inside { 255 } else { 0 }])
+        })
+    }
+
+    mod add_shadow {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn shadow_follows_the_offset() {
+                let options = ShadowOptions {
+                    offset_x: 10,
+                    offset_y: 5,
+                    blur: 0.0,
+                    opacity: 0.5,
+                    color: Rgb([0, 0, 0]),
+                };
+                let shadowed = add_shadow(&cutout(), &options);
+                // The subject is untouched, the uncovered shadow is half-transparent black.
+                assert_eq!(*shadowed.get_pixel(15, 15), Rgba([255, 255, 255, 255]));
+                assert_eq!(*shadowed.get_pixel(25, 20), Rgba([0, 0, 0, 128]));
+                assert_eq!(shadowed.get_pixel(5, 5)[3], 0);
+                assert_eq!(shadowed.get_pixel(35, 35)[3], 0);
+            }
+
+            #[test]
+            fn blur_softens_the_edge() {
+                let options = ShadowOptions {
+                    offset_x: 0,
+                    offset_y: 0,
+                    blur: 3.0,
+                    ..ShadowOptions::default()
+                };
+                let shadowed = add_shadow(&cutout(), &options);
+                let halo = shadowed.get_pixel(21, 15)[3];
+                assert!(halo > 0 && halo < 128, "{halo}");
+            }
+
+            #[test]
+            fn transparent_cutout_casts_nothing() {
+                let shadowed = add_shadow(&RgbaImage::new(8, 8), &ShadowOptions::default());
+                assert!(shadowed.pixels().all(|pixel| pixel[3] == 0));
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use image::{ImageFormat, ImageReader};
+
+use crate::{BgrError, BgrResult};
+
+/// Bytes read from the start of an input to identify it; covers an ISO-BMFF `ftyp` box with a
+/// few compatible brands.
+const MAGIC_LEN: usize = 32;
+
+/// HEIF brands that are not AVIF; the `image` crate has no decoder for them.
+const HEIF_BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+const AVIF_BRANDS: [&[u8; 4]; 2] = [b"avif", b"avis"];
+
+/// What the leading bytes of an input say it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sniffed {
+    Format(ImageFormat),
+    Heif,
+    Unknown,
+}
+
+/// Identify an input from its magic bytes, whatever its file extension claims.
+fn sniff(magic: &[u8]) -> Sniffed {
+    // ISO-BMFF: box size, `ftyp`, major brand, minor version, then compatible brands.
+    if magic.len() >= 12 && &magic[4..8] == b"ftyp" {
+        let box_len = u32::from_be_bytes(magic[..4].try_into().unwrap()) as usize;
+        let brands = magic[8..box_len.clamp(12, magic.len())]
+            .chunks_exact(4)
+            .enumerate()
+            // Skip the minor version, which sits where a brand would.
+            .filter(|&(index, _)| index != 1)
+            .map(|(_, brand)| brand);
+        let mut heif = false;
+        for brand in brands {
+            if AVIF_BRANDS.iter().any(|avif| brand == *avif) {
+                return Sniffed::Format(ImageFormat::Avif);
+            }
+            heif |= HEIF_BRANDS.iter().any(|other| brand == *other);
+        }
+        if heif {
+            return Sniffed::Heif;
+        }
+    }
+    image::guess_format(magic).map_or(Sniffed::Unknown, Sniffed::Format)
+}
+
+/// Pick the decoder for `reader` from its contents, falling back to the format it was opened
+/// with (from the file extension) when the contents are not recognised.
+pub(crate) fn detect_format<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+) -> BgrResult<ImageReader<R>> {
+    let fallback = reader.format();
+    let mut inner = reader.into_inner();
+    let start = inner.stream_position()?;
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    inner
+        .by_ref()
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    inner.seek(SeekFrom::Start(start))?;
+
+    let format = match sniff(&magic) {
+        Sniffed::Format(format) => Some(format),
+        Sniffed::Heif => {
+            return Err(BgrError::UnsupportedFormat {
+                format: "HEIF/HEIC",
+                hint: "convert it to JPEG or PNG first",
+            });
+        }
+        Sniffed::Unknown => fallback,
+    };
+    if format == Some(ImageFormat::Avif) && !cfg!(feature = "avif") {
+        return Err(BgrError::UnsupportedFormat {
+            format: "AVIF",
+            hint: "build bgr with the `avif` feature to decode it",
+        });
+    }
+    Ok(match format {
+        Some(format) => ImageReader::with_format(inner, format),
+        None => ImageReader::new(inner),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let len = 16 + 4 * compatible.len();
+        let mut bytes = (len as u32).to_be_bytes().to_vec();
+        bytes.extend(b"ftyp");
+        bytes.extend(major);
+        bytes.extend([0, 0, 0, 0]);
+        for brand in compatible {
+            bytes.extend(*brand);
+        }
+        bytes
+    }
+
+    mod sniff {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn recognises_common_formats() {
+                assert_eq!(
+                    sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+                    Sniffed::Format(ImageFormat::Png)
+                );
+                assert_eq!(
+                    sniff(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F']),
+                    Sniffed::Format(ImageFormat::Jpeg)
+                );
+                assert_eq!(
+                    sniff(b"RIFF\0\0\0\0WEBPVP8 "),
+                    Sniffed::Format(ImageFormat::WebP)
+                );
+            }
+
+            #[test]
+            fn avif_by_major_or_compatible_brand() {
+                assert_eq!(
+                    sniff(&ftyp(b"avif", &[b"mif1"])),
+                    Sniffed::Format(ImageFormat::Avif)
+                );
+                assert_eq!(
+                    sniff(&ftyp(b"mif1", &[b"avif", b"miaf"])),
+                    Sniffed::Format(ImageFormat::Avif)
+                );
+            }
+
+            #[test]
+            fn heic_is_heif() {
+                assert_eq!(sniff(&ftyp(b"heic", &[b"mif1", b"heic"])), Sniffed::Heif);
+                assert_eq!(sniff(&ftyp(b"mif1", &[b"heic"])), Sniffed::Heif);
+            }
+
+            #[test]
+            fn other_iso_media_is_unknown() {
+                assert_eq!(sniff(&ftyp(b"isom", &[b"mp41"])), Sniffed::Unknown);
+            }
+
+            #[test]
+            fn garbage_is_unknown() {
+                assert_eq!(sniff(b"hello, world"), Sniffed::Unknown);
+                assert_eq!(sniff(&[]), Sniffed::Unknown);
+            }
+        }
+    }
+
+    mod detect_format {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn contents_override_the_extension() {
+                let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+                let reader = ImageReader::with_format(Cursor::new(png), ImageFormat::Jpeg);
+                let reader = detect_format(reader).unwrap();
+                assert_eq!(reader.format(), Some(ImageFormat::Png));
+            }
+
+            #[test]
+            fn extension_used_when_contents_unknown() {
+                let reader =
+                    ImageReader::with_format(Cursor::new(b"????".to_vec()), ImageFormat::Tga);
+                assert_eq!(
+                    detect_format(reader).unwrap().format(),
+                    Some(ImageFormat::Tga)
+                );
+            }
+
+            #[test]
+            fn stream_position_is_restored() {
+                let mut reader =
+                    detect_format(ImageReader::new(Cursor::new(b"GIF89a...".to_vec())))
+                        .unwrap()
+                        .into_inner();
+                let mut first = [0u8; 3];
+                reader.read_exact(&mut first).unwrap();
+                assert_eq!(&first, b"GIF");
+            }
+
+            #[test]
+            fn heif_rejected_with_a_clear_error() {
+                let reader = ImageReader::new(Cursor::new(ftyp(b"heic", &[b"mif1"])));
+                let err = detect_format(reader).err().unwrap();
+                assert!(err.to_string().contains("HEIF/HEIC"), "{err}");
+            }
+
+            #[test]
+            fn avif_needs_the_feature() {
+                let reader = ImageReader::new(Cursor::new(ftyp(b"avif", &[b"mif1"])));
+                let result = detect_format(reader);
+                if cfg!(feature = "avif") {
+                    assert_eq!(result.unwrap().format(), Some(ImageFormat::Avif));
+                } else {
+                    assert!(matches!(
+                        result,
+                        Err(BgrError::UnsupportedFormat { format: "AVIF", .. })
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use image::{GrayImage, Luma, Rgb, RgbaImage};
+use imageproc::distance_transform::euclidean_squared_distance_transform;
+
+use crate::mask::threshold_mask;
+use crate::shadow::over;
+
+/// A colored border around the subject, for die-cut sticker looks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeOptions {
+    /// Border width in pixels, measured from the subject's edge.
+    pub width: f32,
+    pub color: Rgb<u8>,
+}
+
+impl Default for StrokeOptions {
+    fn default() -> Self {
+        Self {
+            width: 12.0,
+            color: Rgb([255, 255, 255]),
+        }
+    }
+}
+
+/// Dilate the alpha channel of `cutout` by the stroke width and fill the grown area with the
+/// stroke color beneath the subject.
+///
+/// The subject is the pixels at 50% alpha or more; the border's outer edge is antialiased.
+/// Like shadows, the border stays within the cutout's frame.
+pub fn add_stroke(cutout: &RgbaImage, options: &StrokeOptions) -> RgbaImage {
+    let (width, height) = cutout.dimensions();
+    let alpha = GrayImage::from_fn(width, height, |x, y| Luma([cutout.get_pixel(x, y)[3]]));
+    let subject = threshold_mask(&alpha, 127);
+    if options.width <= 0.0 || subject.pixels().all(|pixel| pixel[0] == 0) {
+        return cutout.clone();
+    }
+    let distances = euclidean_squared_distance_transform(&subject);
+    let reach = f64::from(options.width) + 0.5;
+    RgbaImage::from_fn(width, height, |x, y| {
+        let distance = distances.get_pixel(x, y)[0].sqrt();
+        let coverage = (reach - distance).clamp(0.0, 1.0) as f32;
+        over(*cutout.get_pixel(x, y), options.color, coverage)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A 40×40 cutout with an opaque red 10×10 subject at (15, 15).
+    fn cutout() -> RgbaImage {
+        RgbaImage::from_fn(40, 40, |x, y| {
+            let inside = (15..25).contains(&x) && (15..25).contains(&y);
+            Rgba([255, 0, 0, if inside { 255 } else { 0 }])
+        })
+    }
+
+    mod add_stroke {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn borders_the_subject() {
+                let options = StrokeOptions {
+                    width: 4.0,
+                    color: Rgb([255, 255, 255]),
+                };
+                let stroked = add_stroke(&cutout(), &options);
+                assert_eq!(*stroked.get_pixel(20, 20), Rgba([255, 0, 0, 255]));
+                assert_eq!(*stroked.get_pixel(27, 20), Rgba([255, 255, 255, 255]));
+                assert_eq!(stroked.get_pixel(30, 20)[3], 0);
+                // Corners are rounded: (28, 28) is over 4 px from the subject's corner.
+                assert_eq!(stroked.get_pixel(28, 28)[3], 0);
+            }
+
+            #[test]
+            fn zero_width_is_unchanged() {
+                let options = StrokeOptions {
+                    width: 0.0,
+                    ..StrokeOptions::default()
+                };
+                assert_eq!(add_stroke(&cutout(), &options), cutout());
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Device;
+
+/// A model session finished loading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelLoad {
+    pub model_path: PathBuf,
+    /// The device the session runs on, after any fallback to the CPU.
+    pub device: Device,
+    /// Time spent building the ONNX Runtime session, including failed accelerator attempts.
+    pub duration: Duration,
+}
+
+/// The model is about to run on one input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferenceStart {
+    /// Width and height of the decoded input image.
+    pub image_size: (u32, u32),
+    /// Shape of the tensor fed to the model, such as `[1, 3, 1024, 1024]`.
+    pub input_shape: Vec<usize>,
+    pub device: Device,
+}
+
+/// The model ran on one input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferenceEnd {
+    pub image_size: (u32, u32),
+    pub input_shape: Vec<usize>,
+    /// Shape of the model's matte output, such as `[1, 1, 1024, 1024]`.
+    pub output_shape: Vec<usize>,
+    pub device: Device,
+    /// Time spent resizing and normalizing the input into its tensor.
+    pub preprocess: Duration,
+    /// Time spent in the ONNX Runtime call itself.
+    pub inference: Duration,
+}
+
+/// Hooks for wiring sessions into an application's own metrics or tracing.
+///
+/// Every method does nothing by default. Hooks run on the thread doing the work, between
+/// pipeline steps, so they should return quickly.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Bgr, InferenceEnd, Telemetry};
+///
+/// struct Log;
+///
+/// impl Telemetry for Log {
+///     fn on_inference_end(&self, event: &InferenceEnd) {
+///         eprintln!("{:?} on {:?} took {:?}", event.input_shape, event.device, event.inference);
+///     }
+/// }
+///
+/// let mut session = Bgr::new("model.onnx").with_telemetry(Log).session()?;
+/// session.for_image("photo.jpg")?;
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+pub trait Telemetry: Send + Sync {
+    fn on_model_load(&self, event: &ModelLoad) {
+        let _ = event;
+    }
+
+    fn on_inference_start(&self, event: &InferenceStart) {
+        let _ = event;
+    }
+
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        let _ = event;
+    }
+}
+
+/// Telemetry that records nothing; the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoTelemetry;
+
+impl Telemetry for NoTelemetry {}
+
+/// A cheaply cloned handle to a [`Telemetry`] implementation, as carried by
+/// [`InferenceSettings`](crate::InferenceSettings).
+#[derive(Clone)]
+pub struct SharedTelemetry(Arc<dyn Telemetry>);
+
+impl SharedTelemetry {
+    pub fn new(telemetry: impl Telemetry + 'static) -> Self {
+        Self(Arc::new(telemetry))
+    }
+
+    /// These hooks followed by `next`'s, so both see every event.
+    pub fn and(self, next: impl Telemetry + 'static) -> Self {
+        Self::new(Both(self, next))
+    }
+}
+
+/// Two telemetry hooks called in turn.
+struct Both<A, B>(A, B);
+
+impl<A: Telemetry, B: Telemetry> Telemetry for Both<A, B> {
+    fn on_model_load(&self, event: &ModelLoad) {
+        self.0.on_model_load(event);
+        self.1.on_model_load(event);
+    }
+
+    fn on_inference_start(&self, event: &InferenceStart) {
+        self.0.on_inference_start(event);
+        self.1.on_inference_start(event);
+    }
+
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        self.0.on_inference_end(event);
+        self.1.on_inference_end(event);
+    }
+}
+
+impl Default for SharedTelemetry {
+    fn default() -> Self {
+        Self::new(NoTelemetry)
+    }
+}
+
+impl fmt::Debug for SharedTelemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTelemetry")
+    }
+}
+
+impl Telemetry for SharedTelemetry {
+    fn on_model_load(&self, event: &ModelLoad) {
+        self.0.on_model_load(event);
+    }
+
+    fn on_inference_start(&self, event: &InferenceStart) {
+        self.0.on_inference_start(event);
+    }
+
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        self.0.on_inference_end(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records the name of every hook called.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Telemetry for Recorder {
+        fn on_model_load(&self, _event: &ModelLoad) {
+            self.0.lock().unwrap().push("load");
+        }
+
+        fn on_inference_start(&self, _event: &InferenceStart) {
+            self.0.lock().unwrap().push("start");
+        }
+
+        fn on_inference_end(&self, event: &InferenceEnd) {
+            assert!(!event.output_shape.is_empty());
+            self.0.lock().unwrap().push("end");
+        }
+    }
+
+    mod shared_telemetry {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn forwards_to_the_wrapped_hooks() {
+                let recorder = Recorder::default();
+                let shared = SharedTelemetry::new(recorder.clone());
+                shared.clone().on_model_load(&ModelLoad {
+                    model_path: PathBuf::from("model.onnx"),
+                    device: Device::Cpu,
+                    duration: Duration::ZERO,
+                });
+                NoTelemetry.on_model_load(&ModelLoad {
+                    model_path: PathBuf::from("model.onnx"),
+                    device: Device::Cpu,
+                    duration: Duration::ZERO,
+                });
+                assert_eq!(*recorder.0.lock().unwrap(), ["load"]);
+            }
+
+            #[test]
+            fn and_keeps_the_earlier_hooks() {
+                let (first, second) = (Recorder::default(), Recorder::default());
+                let shared = SharedTelemetry::new(first.clone()).and(second.clone());
+                shared.on_model_load(&ModelLoad {
+                    model_path: PathBuf::from("model.onnx"),
+                    device: Device::Cpu,
+                    duration: Duration::ZERO,
+                });
+                assert_eq!(*first.0.lock().unwrap(), ["load"]);
+                assert_eq!(*second.0.lock().unwrap(), ["load"]);
+            }
+        }
+
+        /// Runs the tiny model; needs the ONNX Runtime library.
+        #[cfg(feature = "test-utils")]
+        mod integration {
+            use super::*;
+            use crate::Bgr;
+            use crate::testing::{TinyModelDir, fixtures};
+
+            #[test]
+            fn session_reports_load_and_each_inference() {
+                let model = TinyModelDir::new().unwrap();
+                let recorder = Recorder::default();
+                let mut session = Bgr::new(model.model())
+                    .with_telemetry(recorder.clone())
+                    .session()
+                    .unwrap();
+                for fixture in fixtures().into_iter().take(2) {
+                    session.for_rgb(fixture.image).unwrap();
+                }
+                assert_eq!(
+                    *recorder.0.lock().unwrap(),
+                    ["load", "start", "end", "start", "end"]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::io;
+
+use image::{GrayImage, Luma};
+
+use crate::{BgrError, BgrResult};
+
+/// Per-pixel change, in matte levels, above which a pixel is treated as moving.
+const DEFAULT_MOTION_THRESHOLD: u8 = 64;
+
+/// Settings for temporal smoothing of consecutive mattes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalOptions {
+    /// Weight of the running average against each new matte, from 0 (off) to below 1.
+    ///
+    /// Higher values suppress more flicker but let edges lag behind fast motion.
+    pub strength: f32,
+    /// Pixels whose matte changes by more than this between frames take the new value
+    /// unblended, so moving subjects leave no ghost trail.
+    pub motion_threshold: u8,
+}
+
+impl Default for TemporalOptions {
+    fn default() -> Self {
+        Self {
+            strength: 0.6,
+            motion_threshold: DEFAULT_MOTION_THRESHOLD,
+        }
+    }
+}
+
+/// Reduces frame-to-frame flicker in the mattes of a video or image sequence.
+///
+/// Each matte is blended with an exponential moving average of the previous ones. Where a
+/// pixel changed by more than [`TemporalOptions::motion_threshold`] the subject has moved, so
+/// the average restarts from the new value there instead of smearing it.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{Bgr, TemporalOptions, TemporalSmoother};
+///
+/// let bgr = Bgr::new("model.onnx");
+/// let mut session = bgr.session()?;
+/// let mut smoother = TemporalSmoother::new(TemporalOptions::default());
+/// for (i, frame) in ["0001.png", "0002.png"].iter().enumerate() {
+///     let inferred = session.for_image(frame)?;
+///     let foreground = inferred.matte().smooth_temporal(&mut smoother).foreground()?;
+///     foreground.save(format!("cut-{i}.png"))?;
+/// }
+/// # Ok::<_, bgr::BgrError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TemporalSmoother {
+    options: TemporalOptions,
+    /// The running average with its dimensions, kept in floating point so that slow fades
+    /// are not lost to rounding.
+    average: Option<(u32, u32, Vec<f32>)>,
+}
+
+impl TemporalSmoother {
+    pub fn new(options: TemporalOptions) -> Self {
+        Self {
+            options,
+            average: None,
+        }
+    }
+
+    /// Blend `matte` into the running average and return the smoothed matte.
+    ///
+    /// The first matte, and any matte whose size differs from the previous one, passes
+    /// through unchanged and restarts the average.
+    pub fn smooth(&mut self, matte: &GrayImage) -> GrayImage {
+        let (width, height) = matte.dimensions();
+        let strength = self.options.strength.clamp(0.0, 0.99);
+        let motion = f32::from(self.options.motion_threshold);
+        match &mut self.average {
+            Some((w, h, average)) if (*w, *h) == (width, height) => {
+                for (value, pixel) in average.iter_mut().zip(matte.pixels()) {
+                    let current = f32::from(pixel[0]);
+                    *value = if (current - *value).abs() > motion {
+                        current
+                    } else {
+                        strength * *value + (1.0 - strength) * current
+                    };
+                }
+            }
+            _ => {
+                let average = matte.pixels().map(|pixel| f32::from(pixel[0])).collect();
+                self.average = Some((width, height, average));
+            }
+        }
+        let (_, _, average) = self.average.as_ref().expect("average was just set");
+        GrayImage::from_fn(width, height, |x, y| {
+            let value = average[(y * width + x) as usize];
+            Luma([value.round().clamp(0.0, 255.0) as u8])
+        })
+    }
+
+    /// Forget the running average, such as at a scene cut.
+    pub fn reset(&mut self) {
+        self.average = None;
+    }
+
+    /// The running average as bytes, to save with a checkpoint and continue from with
+    /// [`restore`](Self::restore) in a later process.
+    ///
+    /// The layout is the width and height as little-endian `u32`s followed by one
+    /// little-endian `f32` per pixel; without an average the buffer is empty.
+    pub fn state(&self) -> Vec<u8> {
+        let Some((width, height, average)) = &self.average else {
+            return Vec::new();
+        };
+        let mut bytes = Vec::with_capacity(8 + average.len() * 4);
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        for value in average {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Continue from a running average saved by [`state`](Self::state).
+    pub fn restore(&mut self, state: &[u8]) -> BgrResult<()> {
+        if state.is_empty() {
+            self.average = None;
+            return Ok(());
+        }
+        let invalid = || {
+            BgrError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "temporal smoothing state is truncated",
+            ))
+        };
+        let (header, values) = state.split_at_checked(8).ok_or_else(invalid)?;
+        let width = u32::from_le_bytes(header[..4].try_into().expect("four bytes"));
+        let height = u32::from_le_bytes(header[4..].try_into().expect("four bytes"));
+        if values.len() != width as usize * height as usize * 4 {
+            return Err(invalid());
+        }
+        let average = values
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().expect("four bytes")))
+            .collect();
+        self.average = Some((width, height, average));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(value: u8) -> GrayImage {
+        GrayImage::from_pixel(4, 4, Luma([value]))
+    }
+
+    mod temporal_smoother {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn first_matte_passes_through() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions::default());
+                assert_eq!(smoother.smooth(&flat(200)), flat(200));
+            }
+
+            #[test]
+            fn small_changes_are_averaged() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions {
+                    strength: 0.5,
+                    motion_threshold: 64,
+                });
+                smoother.smooth(&flat(200));
+                assert_eq!(smoother.smooth(&flat(220)), flat(210));
+                // Flicker back and forth settles between the two values.
+                assert_eq!(smoother.smooth(&flat(200)), flat(205));
+            }
+
+            #[test]
+            fn motion_takes_the_new_value() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions {
+                    strength: 0.9,
+                    motion_threshold: 64,
+                });
+                smoother.smooth(&flat(0));
+                assert_eq!(smoother.smooth(&flat(255)), flat(255));
+            }
+
+            #[test]
+            fn zero_strength_is_a_no_op() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions {
+                    strength: 0.0,
+                    motion_threshold: 255,
+                });
+                smoother.smooth(&flat(10));
+                assert_eq!(smoother.smooth(&flat(90)), flat(90));
+            }
+
+            #[test]
+            fn size_change_restarts() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions::default());
+                smoother.smooth(&flat(200));
+                let larger = GrayImage::from_pixel(8, 2, Luma([100]));
+                assert_eq!(smoother.smooth(&larger), larger);
+            }
+
+            #[test]
+            fn restored_state_continues_the_average() {
+                let options = TemporalOptions {
+                    strength: 0.5,
+                    motion_threshold: 64,
+                };
+                let mut smoother = TemporalSmoother::new(options.clone());
+                smoother.smooth(&flat(201));
+                let mut resumed = TemporalSmoother::new(options);
+                resumed.restore(&smoother.state()).unwrap();
+                assert_eq!(resumed.smooth(&flat(220)), smoother.smooth(&flat(220)));
+                assert!(resumed.restore(&smoother.state()[..10]).is_err());
+            }
+
+            #[test]
+            fn reset_forgets_history() {
+                let mut smoother = TemporalSmoother::new(TemporalOptions::default());
+                smoother.smooth(&flat(200));
+                smoother.reset();
+                assert_eq!(smoother.smooth(&flat(180)), flat(180));
+            }
+        }
+    }
+}
@@ -0,0 +1,528 @@
+//! Golden-image checks of the whole pipeline with a tiny model, for validating builds.
+//!
+//! Real matting models are hundreds of megabytes, so the harness generates a 1×1-convolution
+//! ONNX model ([`tiny_model`]) that keys out blue and keeps red, and procedurally drawn
+//! [`fixtures`] whose ideal cutout is known exactly. Running the fixtures through a build of
+//! the library exercises ONNX Runtime, preprocessing, matte upscaling, and foreground
+//! composition; [`GoldenHarness::check`] compares the results against the ideal cutouts with
+//! the perceptual [`Fingerprint`] used by `bgr verify`.
+//!
+//! Model authors can run the same fixtures through their own export with
+//! [`GoldenHarness::report`], which `bgr fixtures generate` writes out as a
+//! [`CompatibilityReport`].
+//!
+//! ```no_run
+//! use bgr::Bgr;
+//! use bgr::testing::{GoldenHarness, TinyModelDir, fixtures};
+//!
+//! let model = TinyModelDir::new()?;
+//! let mut harness = GoldenHarness::new(&Bgr::new(model.model()))?;
+//! let failures = harness.check(&fixtures())?;
+//! assert!(failures.is_empty(), "{failures:?}");
+//! # Ok::<_, bgr::BgrError>(())
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::foreground::compose_foreground;
+use crate::telemetry::{InferenceEnd, Telemetry};
+use crate::verify::{Fingerprint, Mismatch, Tolerance};
+use crate::{Bgr, BgrResult, BgrSession};
+
+/// Side of the square input the tiny model runs at.
+pub const TINY_MODEL_SIZE: i64 = 32;
+/// File name [`write_tiny_model`] uses.
+pub const TINY_MODEL_FILE: &str = "bgr-tiny.onnx";
+
+/// Subject and background colors of every fixture.
+const SUBJECT: Rgb<u8> = Rgb([255, 0, 0]);
+const BACKDROP: Rgb<u8> = Rgb([0, 0, 255]);
+
+/// The ONNX encoding of a model that predicts foreground where red outweighs blue.
+///
+/// The graph is `Sigmoid(Conv(input, W, B))` with a `[1, 3, 32, 32]` input and
+/// `W = [4, 0, -4]`, so on the normalized input pure red maps to about 1 and pure blue to
+/// about 0.
+pub fn tiny_model() -> Vec<u8> {
+    let size = TINY_MODEL_SIZE;
+    let weights = tensor("W", &[1, 3, 1, 1], &[4.0, 0.0, -4.0]);
+    let bias = tensor("B", &[1], &[0.0]);
+    let conv = node("Conv", &["input", "W", "B"], "logits");
+    let sigmoid = node("Sigmoid", &["logits"], "matte");
+
+    let mut graph = Proto::default();
+    graph.message(1, conv);
+    graph.message(1, sigmoid);
+    graph.string(2, "bgr-tiny");
+    graph.message(5, weights);
+    graph.message(5, bias);
+    graph.message(11, value_info("input", &[1, 3, size, size]));
+    graph.message(12, value_info("matte", &[1, 1, size, size]));
+
+    let mut opset = Proto::default();
+    opset.string(1, "");
+    opset.varint(2, 13);
+
+    let mut model = Proto::default();
+    model.varint(1, 8);
+    model.string(2, "bgr");
+    model.message(7, graph);
+    model.message(8, opset);
+    model.0
+}
+
+/// Write [`tiny_model`] into `dir` and return its path.
+pub fn write_tiny_model(dir: impl AsRef<Path>) -> BgrResult<PathBuf> {
+    let path = dir.as_ref().join(TINY_MODEL_FILE);
+    std::fs::write(&path, tiny_model())?;
+    Ok(path)
+}
+
+/// [`tiny_model`] written into a temporary directory, removed on drop.
+#[derive(Debug)]
+pub struct TinyModelDir {
+    dir: tempfile::TempDir,
+    model: PathBuf,
+}
+
+impl TinyModelDir {
+    /// Write the tiny model into a fresh temporary directory.
+    pub fn new() -> BgrResult<Self> {
+        let dir = tempfile::tempdir()?;
+        let model = write_tiny_model(dir.path())?;
+        Ok(Self { dir, model })
+    }
+
+    /// The directory holding the model.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The model file.
+    pub fn model(&self) -> &Path {
+        &self.model
+    }
+}
+
+/// A synthetic input and the cutout a correct pipeline produces for it.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: &'static str,
+    pub image: RgbImage,
+    /// The input with the backdrop made transparent.
+    pub expected: RgbaImage,
+}
+
+impl Fixture {
+    /// Draw a red subject, wherever `inside` holds, on a blue backdrop.
+    fn draw(
+        name: &'static str,
+        width: u32,
+        height: u32,
+        inside: impl Fn(u32, u32) -> bool,
+    ) -> Self {
+        let image = RgbImage::from_fn(
+            width,
+            height,
+            |x, y| {
+                if inside(x, y) { SUBJECT } else { BACKDROP }
+            },
+        );
+        let expected = RgbaImage::from_fn(width, height, |x, y| {
+            let Rgb([r, g, b]) = *image.get_pixel(x, y);
+            Rgba([r, g, b, if inside(x, y) { 255 } else { 0 }])
+        });
+        Self {
+            name,
+            image,
+            expected,
+        }
+    }
+}
+
+/// The bundled fixtures: a centered disk, a subject cut off by the frame, an off-square
+/// half split, and an empty frame.
+pub fn fixtures() -> Vec<Fixture> {
+    vec![
+        Fixture::draw("disk", 128, 128, |x, y| {
+            let (dx, dy) = (x as i64 - 64, y as i64 - 64);
+            dx * dx + dy * dy <= 40 * 40
+        }),
+        Fixture::draw("corner", 160, 120, |x, y| x < 80 && y < 60),
+        Fixture::draw("split", 96, 192, |x, _| x >= 48),
+        Fixture::draw("empty", 64, 64, |_, _| false),
+    ]
+}
+
+/// A fixture whose cutout is not close enough to the ideal one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub fixture: &'static str,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reasons: Vec<String> = self.mismatches.iter().map(Mismatch::to_string).collect();
+        write!(f, "{}: {}", self.fixture, reasons.join("; "))
+    }
+}
+
+/// Runs fixtures through a model session and compares the cutouts with their ideal ones.
+pub struct GoldenHarness {
+    session: BgrSession,
+    tolerance: Tolerance,
+    shapes: Shapes,
+}
+
+/// How a model handled the fixtures, from [`GoldenHarness::report`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompatibilityReport {
+    /// The device the session ran on, after any fallback to the CPU.
+    pub device: String,
+    /// Shape of the tensor bgr fed the model, such as `[1, 3, 1024, 1024]`.
+    pub input_shape: Vec<usize>,
+    /// Shape of the model's matte output.
+    pub output_shape: Vec<usize>,
+    /// Whether running the first fixture twice gave the same matte.
+    pub deterministic: bool,
+    pub fixtures: Vec<FixtureReport>,
+}
+
+impl CompatibilityReport {
+    /// Whether the model is deterministic and every cutout matched its ideal one.
+    pub fn passed(&self) -> bool {
+        self.deterministic && self.fixtures.iter().all(FixtureReport::passed)
+    }
+}
+
+/// How the cutout of one fixture compares with its ideal one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FixtureReport {
+    pub fixture: &'static str,
+    pub expected: Fingerprint,
+    pub actual: Fingerprint,
+    /// Lowest and highest matte values; a narrow range hints at missing normalization.
+    pub matte_range: (u8, u8),
+    /// Ways the cutout drifts beyond the tolerance; empty when it matches.
+    pub mismatches: Vec<String>,
+    /// The cutout matches once the matte is inverted, so the model predicts the background.
+    pub inverted: bool,
+    /// The model's cutout, for writing next to the fixture.
+    #[serde(skip)]
+    pub cutout: RgbaImage,
+}
+
+impl FixtureReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Records the tensor shapes of the latest model run.
+#[derive(Clone, Default)]
+struct Shapes(Arc<Mutex<Option<(Vec<usize>, Vec<usize>)>>>);
+
+impl Telemetry for Shapes {
+    fn on_inference_end(&self, event: &InferenceEnd) {
+        if let Ok(mut shapes) = self.0.lock() {
+            *shapes = Some((event.input_shape.clone(), event.output_shape.clone()));
+        }
+    }
+}
+
+impl GoldenHarness {
+    /// Load a session for `bgr`'s model, normally the one from [`write_tiny_model`].
+    ///
+    /// The default tolerance allows for the soft edges left by upscaling the 32×32 matte.
+    /// The harness records tensor shapes through its own [`Telemetry`], replacing any set on
+    /// `bgr`.
+    pub fn new(bgr: &Bgr) -> BgrResult<Self> {
+        let shapes = Shapes::default();
+        Ok(Self {
+            session: bgr.clone().with_telemetry(shapes.clone()).session()?,
+            tolerance: Tolerance {
+                max_hash_distance: 6,
+                max_coverage_delta: 0.03,
+            },
+            shapes,
+        })
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// The foreground cutout of one fixture.
+    pub fn cut(&mut self, fixture: &Fixture) -> BgrResult<RgbaImage> {
+        let inferred = self.session.for_rgb(fixture.image.clone())?;
+        Ok(inferred.matte().foreground()?.into_image())
+    }
+
+    /// Cut out every fixture and return those that drift beyond the tolerance.
+    pub fn check(&mut self, fixtures: &[Fixture]) -> BgrResult<Vec<Failure>> {
+        let mut failures = Vec::new();
+        for fixture in fixtures {
+            let actual = Fingerprint::of_image(&DynamicImage::ImageRgba8(self.cut(fixture)?));
+            let golden = Fingerprint::of_image(&DynamicImage::ImageRgba8(fixture.expected.clone()));
+            let mismatches = golden.compare(&actual, &self.tolerance);
+            if !mismatches.is_empty() {
+                failures.push(Failure {
+                    fixture: fixture.name,
+                    mismatches,
+                });
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Cut out every fixture and describe how the model handled them, for model authors
+    /// checking that an export works with bgr's preprocessing and postprocessing.
+    pub fn report(&mut self, fixtures: &[Fixture]) -> BgrResult<CompatibilityReport> {
+        let mut reports = Vec::with_capacity(fixtures.len());
+        let mut first_matte: Option<GrayImage> = None;
+        for fixture in fixtures {
+            let matte = self
+                .session
+                .for_rgb(fixture.image.clone())?
+                .raw_matte()
+                .clone();
+            let cutout = compose_foreground(&fixture.image, &matte)?;
+            let inverse = GrayImage::from_fn(matte.width(), matte.height(), |x, y| {
+                Luma([255 - matte.get_pixel(x, y)[0]])
+            });
+            let fingerprint =
+                |image: &RgbaImage| Fingerprint::of_image(&DynamicImage::ImageRgba8(image.clone()));
+            let expected = fingerprint(&fixture.expected);
+            let actual = fingerprint(&cutout);
+            let mismatches = expected.compare(&actual, &self.tolerance);
+            let inverted = !mismatches.is_empty()
+                && expected
+                    .compare(
+                        &fingerprint(&compose_foreground(&fixture.image, &inverse)?),
+                        &self.tolerance,
+                    )
+                    .is_empty();
+            let values = matte.pixels().map(|pixel| pixel[0]);
+            let matte_range = (values.clone().min().unwrap_or(0), values.max().unwrap_or(0));
+            first_matte.get_or_insert(matte);
+            reports.push(FixtureReport {
+                fixture: fixture.name,
+                expected,
+                actual,
+                matte_range,
+                mismatches: mismatches.iter().map(Mismatch::to_string).collect(),
+                inverted,
+                cutout,
+            });
+        }
+
+        let deterministic = match (fixtures.first(), first_matte) {
+            (Some(fixture), Some(matte)) => {
+                *self.session.for_rgb(fixture.image.clone())?.raw_matte() == matte
+            }
+            _ => true,
+        };
+        let (input_shape, output_shape) = self
+            .shapes
+            .0
+            .lock()
+            .ok()
+            .and_then(|shapes| shapes.clone())
+            .unwrap_or_default();
+        Ok(CompatibilityReport {
+            device: self.session.device().to_string(),
+            input_shape,
+            output_shape,
+            deterministic,
+            fixtures: reports,
+        })
+    }
+}
+
+/// An ONNX `NodeProto` with a single output.
+fn node(op_type: &str, inputs: &[&str], output: &str) -> Proto {
+    let mut node = Proto::default();
+    for input in inputs {
+        node.string(1, input);
+    }
+    node.string(2, output);
+    node.string(3, output);
+    node.string(4, op_type);
+    node
+}
+
+/// An ONNX float `TensorProto` initializer.
+fn tensor(name: &str, dims: &[i64], values: &[f32]) -> Proto {
+    let mut tensor = Proto::default();
+    for &dim in dims {
+        tensor.varint(1, dim as u64);
+    }
+    // TensorProto.DataType.FLOAT
+    tensor.varint(2, 1);
+    let data: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    tensor.bytes(4, &data);
+    tensor.string(8, name);
+    tensor
+}
+
+/// An ONNX `ValueInfoProto` for a float tensor of fixed shape.
+fn value_info(name: &str, dims: &[i64]) -> Proto {
+    let mut shape = Proto::default();
+    for &dim in dims {
+        let mut dimension = Proto::default();
+        dimension.varint(1, dim as u64);
+        shape.message(1, dimension);
+    }
+    let mut tensor_type = Proto::default();
+    tensor_type.varint(1, 1);
+    tensor_type.message(2, shape);
+    let mut type_proto = Proto::default();
+    type_proto.message(1, tensor_type);
+
+    let mut info = Proto::default();
+    info.string(1, name);
+    info.message(2, type_proto);
+    info
+}
+
+/// Just enough of the protobuf wire format to write an ONNX model.
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint((u64::from(field) << 3) | u64::from(wire_type));
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.raw_varint(value);
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        self.raw_varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: Proto) {
+        self.bytes(field, &message.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod proto {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn varints_use_seven_bit_groups() {
+                let mut proto = Proto::default();
+                proto.varint(1, 300);
+                assert_eq!(proto.0, [0x08, 0xac, 0x02]);
+            }
+
+            #[test]
+            fn strings_are_length_delimited() {
+                let mut proto = Proto::default();
+                proto.string(2, "bgr");
+                assert_eq!(proto.0, [0x12, 3, b'b', b'g', b'r']);
+            }
+        }
+    }
+
+    mod fixtures {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn expected_cutout_keys_out_the_backdrop() {
+                for fixture in fixtures() {
+                    for (source, expected) in fixture.image.pixels().zip(fixture.expected.pixels())
+                    {
+                        assert_eq!(expected[3] == 255, *source == SUBJECT, "{}", fixture.name);
+                    }
+                }
+            }
+
+            #[test]
+            fn names_are_unique() {
+                let mut names: Vec<&str> = fixtures().iter().map(|fixture| fixture.name).collect();
+                names.sort();
+                names.dedup();
+                assert_eq!(names.len(), fixtures().len());
+            }
+        }
+    }
+
+    mod tiny_model {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn encodes_both_nodes_and_the_opset() {
+                let model = tiny_model();
+                let contains = |needle: &[u8]| model.windows(needle.len()).any(|w| w == needle);
+                assert!(contains(b"Conv"));
+                assert!(contains(b"Sigmoid"));
+                // ir_version 8 leads the model.
+                assert_eq!(&model[..2], [0x08, 8]);
+            }
+        }
+
+        /// Runs the harness end to end; needs the ONNX Runtime library.
+        mod integration {
+            use super::*;
+
+            #[test]
+            fn fixtures_match_their_ideal_cutouts() {
+                let model = TinyModelDir::new().unwrap();
+                let mut harness = GoldenHarness::new(&Bgr::new(model.model())).unwrap();
+                let failures = harness.check(&fixtures()).unwrap();
+                assert!(failures.is_empty(), "{failures:?}");
+            }
+
+            #[test]
+            fn report_describes_the_tiny_model() {
+                let model = TinyModelDir::new().unwrap();
+                let mut harness = GoldenHarness::new(&Bgr::new(model.model())).unwrap();
+                let report = harness.report(&fixtures()).unwrap();
+                assert!(report.passed(), "{report:?}");
+                let size = TINY_MODEL_SIZE as usize;
+                assert_eq!(report.input_shape, [1, 3, size, size]);
+                assert!(report.fixtures.iter().all(|fixture| !fixture.inverted));
+            }
+        }
+    }
+}
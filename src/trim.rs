@@ -0,0 +1,136 @@
+use image::imageops;
+use image::{ImageBuffer, Pixel, RgbaImage};
+
+/// Transparent border kept around the subject when trimming a cutout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimMargin {
+    /// An absolute width in pixels.
+    Pixels(u32),
+    /// A percentage of the subject's longer side (`0.0`–`100.0`).
+    Percent(f64),
+}
+
+impl Default for TrimMargin {
+    fn default() -> Self {
+        TrimMargin::Pixels(0)
+    }
+}
+
+impl TrimMargin {
+    /// Resolve to pixels for a subject of the given size.
+    pub fn pixels(self, subject_width: u32, subject_height: u32) -> u32 {
+        match self {
+            TrimMargin::Pixels(pixels) => pixels,
+            TrimMargin::Percent(percent) => {
+                let side = f64::from(subject_width.max(subject_height));
+                (side * percent.max(0.0) / 100.0).round() as u32
+            }
+        }
+    }
+}
+
+/// Crop `image` to the bounding box of its non-transparent pixels plus `margin`.
+///
+/// The margin stops at the image edges, so trimming never adds canvas. A fully transparent
+/// image is returned unchanged.
+pub fn trim_to_subject(image: &RgbaImage, margin: TrimMargin) -> RgbaImage {
+    let Some((x0, y0, x1, y1)) = subject_bounds(image, 3, 0) else {
+        return image.clone();
+    };
+    let margin = margin.pixels(x1 - x0 + 1, y1 - y0 + 1);
+    let left = x0.saturating_sub(margin);
+    let top = y0.saturating_sub(margin);
+    let right = x1.saturating_add(margin).min(image.width() - 1);
+    let bottom = y1.saturating_add(margin).min(image.height() - 1);
+    imageops::crop_imm(image, left, top, right - left + 1, bottom - top + 1).to_image()
+}
+
+/// Inclusive corners `(x0, y0, x1, y1)` of the pixels whose `channel` is above `threshold`,
+/// such as the alpha channel of a cutout or the only one of a matte.
+pub fn subject_bounds<P>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    channel: usize,
+    threshold: u8,
+) -> Option<(u32, u32, u32, u32)>
+where
+    P: Pixel<Subpixel = u8>,
+{
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel.channels()[channel] > threshold {
+            let (x0, y0, x1, y1) = bounds.get_or_insert((x, y, x, y));
+            *x0 = (*x0).min(x);
+            *y0 = (*y0).min(y);
+            *x1 = (*x1).max(x);
+            *y1 = (*y1).max(y);
+        }
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgba};
+
+    /// A 100×80 cutout with an opaque 20×10 subject at (30, 40).
+    fn cutout() -> RgbaImage {
+        RgbaImage::from_fn(100, 80, |x, y| {
+            let inside = (30..50).contains(&x) && (40..50).contains(&y);
+            Rgba([200, 100, 50, if inside { 255 } else { 0 }])
+        })
+    }
+
+    mod trim_to_subject {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn crops_to_the_subject() {
+                let trimmed = trim_to_subject(&cutout(), TrimMargin::default());
+                assert_eq!(trimmed.dimensions(), (20, 10));
+                assert!(trimmed.pixels().all(|pixel| pixel[3] == 255));
+            }
+
+            #[test]
+            fn pixel_margin_is_clamped_to_the_image() {
+                let trimmed = trim_to_subject(&cutout(), TrimMargin::Pixels(35));
+                // 30 px fit on the left, 35 on the right; 35 above, 30 below.
+                assert_eq!(trimmed.dimensions(), (30 + 20 + 35, 35 + 10 + 30));
+            }
+
+            #[test]
+            fn percent_margin_follows_the_longer_side() {
+                let trimmed = trim_to_subject(&cutout(), TrimMargin::Percent(10.0));
+                assert_eq!(trimmed.dimensions(), (24, 14));
+            }
+
+            #[test]
+            fn transparent_image_is_unchanged() {
+                let empty = RgbaImage::new(8, 6);
+                assert_eq!(trim_to_subject(&empty, TrimMargin::Pixels(4)), empty);
+            }
+        }
+    }
+
+    mod subject_bounds {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn only_values_above_the_threshold_count() {
+                assert_eq!(subject_bounds(&cutout(), 3, 0), Some((30, 40, 49, 49)));
+                let mut matte = GrayImage::new(8, 8);
+                matte.put_pixel(1, 2, Luma([100]));
+                matte.put_pixel(5, 6, Luma([200]));
+                assert_eq!(subject_bounds(&matte, 0, 99), Some((1, 2, 5, 6)));
+                assert_eq!(subject_bounds(&matte, 0, 100), Some((5, 6, 5, 6)));
+                assert_eq!(subject_bounds(&matte, 0, 200), None);
+            }
+        }
+    }
+}
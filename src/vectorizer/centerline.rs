@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use image::GrayImage;
+
+use crate::BgrResult;
+
+use super::MaskVectorizer;
+use super::geometry::{
+    MAX_SIMPLIFY_STEPS, Point, format_coord, node_budget_error, polyline_length, simplify_polyline,
+};
+
+/// Neighbour offsets in clockwise order starting north (P2..P9 in Zhang-Suen notation).
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// Neighbour indices visited when walking a skeleton: edge-adjacent before diagonal.
+const WALK_ORDER: [usize; 8] = [0, 2, 4, 6, 1, 3, 5, 7];
+
+/// Configuration for centerline (skeleton) tracing.
+///
+/// Used with [`CenterlineVectorizer`] to turn thin, stroke-like masks into open polylines.
+#[derive(Debug, Clone)]
+pub struct CenterlineOptions {
+    /// Mask values at or above this are treated as foreground.
+    pub threshold: u8,
+    /// Strokes shorter than this many pixels are dropped.
+    pub min_length: f64,
+    /// Douglas-Peucker tolerance in pixels.
+    pub simplify_tolerance: f64,
+    /// Stroke width written to the SVG paths.
+    pub stroke_width: f64,
+    /// Decimal places in path coordinates.
+    pub path_precision: u32,
+    /// Maximum nodes allowed in any single path; the tolerance is raised until it fits.
+    pub max_nodes: Option<usize>,
+}
+
+impl Default for CenterlineOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            min_length: 4.0,
+            simplify_tolerance: 1.0,
+            stroke_width: 1.0,
+            path_precision: 2,
+            max_nodes: None,
+        }
+    }
+}
+
+/// Traces the skeleton of a mask into an SVG of stroked, unfilled polylines.
+///
+/// Suited to wires, handwriting, and other strokes where a filled outline would trace both edges.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CenterlineVectorizer;
+
+impl MaskVectorizer for CenterlineVectorizer {
+    type Options = CenterlineOptions;
+    type Output = String;
+
+    fn vectorize(&self, mask: &GrayImage, options: &Self::Options) -> BgrResult<Self::Output> {
+        let strokes = centerline_polylines(mask, options.threshold, options.min_length);
+        let mut tolerance = options.simplify_tolerance;
+        let mut simplified = simplify_all(&strokes, tolerance);
+        if let Some(budget) = options.max_nodes {
+            let mut steps = 0;
+            while largest_stroke(&simplified) > budget {
+                if steps == MAX_SIMPLIFY_STEPS {
+                    return Err(node_budget_error(budget, largest_stroke(&simplified)));
+                }
+                tolerance = tolerance.max(0.5) * 2.0;
+                simplified = simplify_all(&strokes, tolerance);
+                steps += 1;
+            }
+        }
+        Ok(polylines_to_svg(
+            &simplified,
+            mask.width(),
+            mask.height(),
+            options,
+        ))
+    }
+}
+
+fn simplify_all(strokes: &[Vec<Point>], tolerance: f64) -> Vec<Vec<Point>> {
+    strokes
+        .iter()
+        .map(|stroke| simplify_polyline(stroke, tolerance))
+        .collect()
+}
+
+fn largest_stroke(strokes: &[Vec<Point>]) -> usize {
+    strokes.iter().map(Vec::len).max().unwrap_or(0)
+}
+
+/// Extract skeleton polylines from a mask, in pixel-centre coordinates.
+///
+/// The foreground is thinned to a one-pixel skeleton, which is then split into strokes at
+/// endpoints and junctions. Closed loops become polylines whose first and last points coincide.
+fn centerline_polylines(mask: &GrayImage, threshold: u8, min_length: f64) -> Vec<Vec<Point>> {
+    let mut skeleton = Skeleton::from_mask(mask, threshold);
+    skeleton.thin();
+    skeleton
+        .strokes()
+        .into_iter()
+        .filter(|stroke| stroke.len() >= 2 && polyline_length(stroke) >= min_length)
+        .collect()
+}
+
+/// A binary pixel grid that is thinned in place.
+struct Skeleton {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Skeleton {
+    fn from_mask(mask: &GrayImage, threshold: u8) -> Self {
+        Self {
+            width: mask.width() as usize,
+            height: mask.height() as usize,
+            pixels: mask.pixels().map(|p| p[0] >= threshold).collect(),
+        }
+    }
+
+    fn get(&self, x: i64, y: i64) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.pixels[y as usize * self.width + x as usize]
+    }
+
+    fn neighbours(&self, index: usize) -> [bool; 8] {
+        let (x, y) = self.coords(index);
+        NEIGHBOURS.map(|(dx, dy)| self.get(x + dx, y + dy))
+    }
+
+    fn coords(&self, index: usize) -> (i64, i64) {
+        ((index % self.width) as i64, (index / self.width) as i64)
+    }
+
+    fn neighbour_index(&self, index: usize, direction: usize) -> Option<usize> {
+        let (x, y) = self.coords(index);
+        let (dx, dy) = NEIGHBOURS[direction];
+        self.get(x + dx, y + dy)
+            .then(|| (y + dy) as usize * self.width + (x + dx) as usize)
+    }
+
+    /// Zhang-Suen thinning.
+    fn thin(&mut self) {
+        let mut cleared = Vec::new();
+        loop {
+            let mut changed = false;
+            for pass in 0..2 {
+                cleared.clear();
+                for index in 0..self.pixels.len() {
+                    if !self.pixels[index] {
+                        continue;
+                    }
+                    let n = self.neighbours(index);
+                    let count = n.iter().filter(|&&set| set).count();
+                    if !(2..=6).contains(&count) || transitions(&n) != 1 {
+                        continue;
+                    }
+                    let (p2, p4, p6, p8) = (n[0], n[2], n[4], n[6]);
+                    let removable = if pass == 0 {
+                        !(p4 && p6 && (p2 || p8))
+                    } else {
+                        !(p2 && p8 && (p4 || p6))
+                    };
+                    if removable {
+                        cleared.push(index);
+                    }
+                }
+                for &index in &cleared {
+                    self.pixels[index] = false;
+                }
+                changed |= !cleared.is_empty();
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Whether a skeleton pixel ends or joins strokes rather than continuing one.
+    fn is_node(&self, index: usize) -> bool {
+        transitions(&self.neighbours(index)) != 2
+    }
+
+    fn point(&self, index: usize) -> Point {
+        let (x, y) = self.coords(index);
+        Point::new(x as f64 + 0.5, y as f64 + 0.5)
+    }
+
+    /// Split the skeleton into strokes between nodes, followed by any closed loops.
+    fn strokes(&self) -> Vec<Vec<Point>> {
+        let mut visited = vec![false; self.pixels.len()];
+        let mut linked = HashSet::new();
+        let mut strokes = Vec::new();
+
+        let nodes: Vec<usize> = (0..self.pixels.len())
+            .filter(|&i| self.pixels[i] && self.is_node(i))
+            .collect();
+        for &node in &nodes {
+            visited[node] = true;
+        }
+        for &node in &nodes {
+            for direction in WALK_ORDER {
+                let Some(next) = self.neighbour_index(node, direction) else {
+                    continue;
+                };
+                if self.is_node(next) {
+                    if node < next && linked.insert((node, next)) {
+                        strokes.push(vec![self.point(node), self.point(next)]);
+                    }
+                    continue;
+                }
+                if !visited[next] {
+                    strokes.push(self.walk(node, next, &mut visited));
+                }
+            }
+        }
+
+        for start in 0..self.pixels.len() {
+            if self.pixels[start] && !visited[start] {
+                visited[start] = true;
+                let Some(next) = self.next_unvisited(start, start, &visited) else {
+                    continue;
+                };
+                let mut stroke = self.walk(start, next, &mut visited);
+                stroke.push(self.point(start));
+                strokes.push(stroke);
+            }
+        }
+        strokes
+    }
+
+    /// Follow a stroke from `from` through `next` until it reaches a node or runs out.
+    fn walk(&self, from: usize, mut next: usize, visited: &mut [bool]) -> Vec<Point> {
+        let mut stroke = vec![self.point(from)];
+        let mut previous = from;
+        loop {
+            stroke.push(self.point(next));
+            if self.is_node(next) {
+                break;
+            }
+            visited[next] = true;
+            match self.next_unvisited(next, previous, visited) {
+                Some(following) => {
+                    previous = next;
+                    next = following;
+                }
+                None => break,
+            }
+        }
+        stroke
+    }
+
+    /// The next pixel of a stroke: an unvisited pixel or a node, other than where we came from.
+    fn next_unvisited(&self, current: usize, previous: usize, visited: &[bool]) -> Option<usize> {
+        WALK_ORDER.into_iter().find_map(|direction| {
+            self.neighbour_index(current, direction)
+                .filter(|&candidate| {
+                    candidate != previous && (!visited[candidate] || self.is_node(candidate))
+                })
+        })
+    }
+}
+
+/// Number of background-to-foreground transitions around a pixel's neighbourhood.
+fn transitions(neighbours: &[bool; 8]) -> usize {
+    (0..8)
+        .filter(|&i| !neighbours[i] && neighbours[(i + 1) % 8])
+        .count()
+}
+
+fn polylines_to_svg(
+    strokes: &[Vec<Point>],
+    width: u32,
+    height: u32,
+    options: &CenterlineOptions,
+) -> String {
+    let precision = options.path_precision;
+    let mut svg = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        svg,
+        "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">"
+    );
+    for stroke in strokes {
+        let mut data = String::new();
+        for (i, point) in stroke.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            let _ = write!(
+                data,
+                "{command}{} {} ",
+                format_coord(point.x, precision),
+                format_coord(point.y, precision)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<path d=\"{}\" fill=\"none\" stroke=\"#000000\" stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"/>",
+            data.trim_end(),
+            format_coord(options.stroke_width, precision.max(2))
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn draw_rect(mask: &mut GrayImage, x0: u32, y0: u32, x1: u32, y1: u32) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                mask.put_pixel(x, y, Luma([255]));
+            }
+        }
+    }
+
+    mod centerline_polylines {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn empty_mask_has_no_strokes() {
+                let mask = GrayImage::new(16, 16);
+                assert!(centerline_polylines(&mask, 128, 0.0).is_empty());
+            }
+
+            #[test]
+            fn horizontal_bar_is_one_stroke_near_its_middle() {
+                let mut mask = GrayImage::new(60, 20);
+                draw_rect(&mut mask, 5, 7, 55, 13);
+                let strokes: Vec<_> = centerline_polylines(&mask, 128, 4.0)
+                    .into_iter()
+                    .filter(|stroke| polyline_length(stroke) > 20.0)
+                    .collect();
+                assert_eq!(strokes.len(), 1);
+                assert!(polyline_length(&strokes[0]) > 35.0);
+                assert!(strokes[0].iter().all(|p| (p.y - 10.0).abs() <= 2.0));
+            }
+
+            #[test]
+            fn plus_sign_has_four_arms() {
+                let mut mask = GrayImage::new(61, 61);
+                draw_rect(&mut mask, 28, 5, 33, 56);
+                draw_rect(&mut mask, 5, 28, 56, 33);
+                let arms = centerline_polylines(&mask, 128, 4.0)
+                    .into_iter()
+                    .filter(|stroke| polyline_length(stroke) > 15.0)
+                    .count();
+                assert_eq!(arms, 4);
+            }
+
+            #[test]
+            fn ring_becomes_closed_loop() {
+                let mut mask = GrayImage::new(40, 40);
+                draw_rect(&mut mask, 5, 5, 35, 35);
+                for y in 9..31 {
+                    for x in 9..31 {
+                        mask.put_pixel(x, y, Luma([0]));
+                    }
+                }
+                let strokes = centerline_polylines(&mask, 128, 4.0);
+                let longest = strokes
+                    .iter()
+                    .max_by(|a, b| polyline_length(a).total_cmp(&polyline_length(b)))
+                    .unwrap();
+                assert!(polyline_length(longest) > 70.0);
+                let (first, last) = (longest[0], longest[longest.len() - 1]);
+                assert!(first.distance(last) <= 1.5);
+            }
+        }
+    }
+
+    mod centerline_vectorizer {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn emits_unfilled_paths() {
+                let mut mask = GrayImage::new(60, 20);
+                draw_rect(&mut mask, 5, 7, 55, 13);
+                let svg = CenterlineVectorizer
+                    .vectorize(&mask, &CenterlineOptions::default())
+                    .unwrap();
+                assert!(svg.contains("<svg"));
+                assert!(svg.contains("fill=\"none\""));
+                assert!(svg.contains("<path d=\"M"));
+            }
+
+            #[test]
+            fn node_budget_is_respected() {
+                let mut mask = GrayImage::new(80, 80);
+                for i in 0..70 {
+                    let y = 10 + (i % 20) * if (i / 20) % 2 == 0 { 1 } else { 0 };
+                    draw_rect(&mut mask, 5 + i, y, 7 + i, y + 3);
+                }
+                let options = CenterlineOptions {
+                    simplify_tolerance: 0.0,
+                    max_nodes: Some(6),
+                    ..CenterlineOptions::default()
+                };
+                let svg = CenterlineVectorizer.vectorize(&mask, &options).unwrap();
+                for path in svg.split("d=\"").skip(1) {
+                    let data = path.split('"').next().unwrap();
+                    assert!(data.matches(['M', 'L']).count() <= 6);
+                }
+            }
+        }
+    }
+}
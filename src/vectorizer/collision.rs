@@ -0,0 +1,465 @@
+use std::fmt::Write as _;
+
+use image::GrayImage;
+use serde::Serialize;
+
+use crate::BgrResult;
+
+use super::MaskVectorizer;
+use super::contour::extract_contours;
+use super::geometry::{MAX_SIMPLIFY_STEPS, Point, format_coord, simplify_polyline};
+
+/// Turns smaller than this (in squared pixels) count as straight.
+const COLLINEAR: f64 = 1e-9;
+
+/// Configuration for collision-shape tracing.
+///
+/// Used with [`CollisionVectorizer`] to turn a subject into the few polygons a physics engine
+/// wants, trading outline fidelity for vertex count.
+#[derive(Debug, Clone)]
+pub struct CollisionOptions {
+    /// Mask values at or above this are treated as foreground.
+    pub threshold: u8,
+    /// Shapes enclosing fewer pixels than this are dropped.
+    pub min_area: f64,
+    /// Most vertices in any polygon (at least 3); Box2D allows 8.
+    pub max_vertices: usize,
+    /// Emit convex polygons only: a shape whose outline strays at most this many pixels from
+    /// its convex hull becomes the hull, a deeper one is split into convex pieces. `None`
+    /// keeps one low-vertex polygon per shape, which may be concave.
+    pub convexity: Option<f64>,
+    /// Decimal places in path coordinates.
+    pub path_precision: u32,
+}
+
+impl Default for CollisionOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            min_area: 16.0,
+            max_vertices: 8,
+            convexity: None,
+            path_precision: 2,
+        }
+    }
+}
+
+/// The polygons standing in for one connected part of the subject.
+///
+/// Holes are filled in, as physics bodies are solid. Vertices are in image pixels, clockwise
+/// on screen.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollisionShape {
+    pub polygons: Vec<Vec<[f64; 2]>>,
+}
+
+/// Output of [`CollisionVectorizer`]: an SVG preview plus the polygons of every shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionTrace {
+    pub svg: String,
+    pub shapes: Vec<CollisionShape>,
+}
+
+impl From<CollisionTrace> for String {
+    fn from(trace: CollisionTrace) -> Self {
+        trace.svg
+    }
+}
+
+/// Traces a mask into simplified polygons for collision detection.
+///
+/// Unlike the visual tracers, this one aims at the fewest vertices that still bound the
+/// subject: every outer boundary is simplified until it fits [`CollisionOptions::max_vertices`],
+/// and with [`CollisionOptions::convexity`] the result is made of convex pieces.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CollisionVectorizer;
+
+impl MaskVectorizer for CollisionVectorizer {
+    type Options = CollisionOptions;
+    type Output = CollisionTrace;
+
+    fn vectorize(&self, mask: &GrayImage, options: &Self::Options) -> BgrResult<Self::Output> {
+        let max_vertices = options.max_vertices.max(3);
+        let shapes: Vec<CollisionShape> = extract_contours(mask, options.threshold, false)
+            .into_iter()
+            .filter(|contour| !contour.is_hole() && contour.area >= options.min_area)
+            .map(|contour| {
+                let polygons = match options.convexity {
+                    None => vec![low_vertex_polygon(&contour.points, max_vertices)],
+                    Some(tolerance) => convex_pieces(&contour.points, tolerance, max_vertices),
+                };
+                CollisionShape {
+                    polygons: polygons
+                        .into_iter()
+                        .filter(|polygon| polygon.len() >= 3)
+                        .map(|polygon| polygon.iter().map(|p| [p.x, p.y]).collect())
+                        .collect(),
+                }
+            })
+            .filter(|shape| !shape.polygons.is_empty())
+            .collect();
+
+        let precision = options.path_precision;
+        let mut svg = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            svg,
+            "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            mask.width(),
+            mask.height()
+        );
+        for polygon in shapes.iter().flat_map(|shape| &shape.polygons) {
+            let mut data = String::new();
+            for (i, [x, y]) in polygon.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(
+                    data,
+                    "{command}{} {} ",
+                    format_coord(*x, precision),
+                    format_coord(*y, precision)
+                );
+            }
+            let _ = writeln!(
+                svg,
+                "<path d=\"{}Z\" fill=\"#000000\" fill-opacity=\"0.5\" stroke=\"#000000\"/>",
+                data
+            );
+        }
+        svg.push_str("</svg>\n");
+        Ok(CollisionTrace { svg, shapes })
+    }
+}
+
+/// `ring` simplified with the smallest tolerance that leaves at most `max_vertices` points.
+fn low_vertex_polygon(ring: &[Point], max_vertices: usize) -> Vec<Point> {
+    let mut tolerance = 0.5;
+    for _ in 0..=MAX_SIMPLIFY_STEPS * 2 {
+        let simplified = strip_collinear(simplify_ring(ring, tolerance));
+        if simplified.len() < 3 {
+            break;
+        }
+        if simplified.len() <= max_vertices {
+            return simplified;
+        }
+        tolerance *= 1.5;
+    }
+    // The outline collapsed before fitting, as thin slivers do; its hull still bounds it.
+    limit_convex(convex_hull(ring), max_vertices)
+}
+
+/// Convex polygons covering `ring`, each with at most `max_vertices` points.
+fn convex_pieces(ring: &[Point], tolerance: f64, max_vertices: usize) -> Vec<Vec<Point>> {
+    let simplified = strip_collinear(simplify_ring(ring, tolerance.max(0.5)));
+    let hull = convex_hull(&simplified);
+    let depth = simplified
+        .iter()
+        .map(|&p| distance_to_ring(p, &hull))
+        .fold(0.0, f64::max);
+    if simplified.len() < 4 || depth <= tolerance {
+        return vec![limit_convex(hull, max_vertices)];
+    }
+    decompose(&simplified, max_vertices)
+}
+
+/// Simplify a closed ring with Douglas-Peucker, splitting it at the point farthest from the
+/// start so both halves are open polylines.
+fn simplify_ring(ring: &[Point], tolerance: f64) -> Vec<Point> {
+    if ring.len() < 4 {
+        return ring.to_vec();
+    }
+    let far = (1..ring.len())
+        .max_by(|&a, &b| {
+            ring[0]
+                .distance(ring[a])
+                .total_cmp(&ring[0].distance(ring[b]))
+        })
+        .unwrap_or(1);
+    let mut second: Vec<Point> = ring[far..].to_vec();
+    second.push(ring[0]);
+    let mut simplified = simplify_polyline(&ring[..=far], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_polyline(&second, tolerance));
+    simplified.pop();
+    simplified
+}
+
+/// Twice the signed area of the turn `a`, `b`, `c`; positive where the ring turns clockwise
+/// on screen, as outer contours do.
+fn cross(a: Point, b: Point, c: Point) -> f64 {
+    (b.x - a.x) * (c.y - b.y) - (b.y - a.y) * (c.x - b.x)
+}
+
+/// Drop vertices where the ring runs straight on.
+fn strip_collinear(ring: Vec<Point>) -> Vec<Point> {
+    let n = ring.len();
+    if n < 4 {
+        return ring;
+    }
+    (0..n)
+        .filter(|&i| cross(ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]).abs() > COLLINEAR)
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Convex hull by Andrew's monotone chain, in the orientation of outer contours.
+fn convex_hull(points: &[Point]) -> Vec<Point> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+    let mut hull: Vec<Point> = Vec::with_capacity(sorted.len() * 2);
+    for pass in [sorted.clone(), sorted.into_iter().rev().collect()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= COLLINEAR
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+/// Remove the vertices whose removal loses the least area until `hull` has at most `max`.
+fn limit_convex(mut hull: Vec<Point>, max: usize) -> Vec<Point> {
+    while hull.len() > max {
+        let n = hull.len();
+        let cheapest = (0..n)
+            .min_by(|&a, &b| {
+                let area = |i: usize| cross(hull[(i + n - 1) % n], hull[i], hull[(i + 1) % n]);
+                area(a).total_cmp(&area(b))
+            })
+            .expect("hull has vertices");
+        hull.remove(cheapest);
+    }
+    hull
+}
+
+/// Distance from `p` to the boundary of `ring`.
+fn distance_to_ring(p: Point, ring: &[Point]) -> f64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| distance_to_segment(p, ring[i], ring[(i + 1) % n]))
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length2 = dx * dx + dy * dy;
+    if length2 == 0.0 {
+        return p.distance(a);
+    }
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / length2).clamp(0.0, 1.0);
+    p.distance(Point::new(a.x + t * dx, a.y + t * dy))
+}
+
+/// Split a simple polygon into convex pieces: ear-clip it into triangles, then merge
+/// neighbours while the union stays convex and within `max_vertices` (Hertel-Mehlhorn).
+fn decompose(polygon: &[Point], max_vertices: usize) -> Vec<Vec<Point>> {
+    let mut pieces: Vec<Vec<usize>> = Vec::new();
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |i: usize| {
+            (
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            )
+        };
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = corner(i);
+            cross(polygon[a], polygon[b], polygon[c]) > COLLINEAR
+                && !remaining.iter().any(|&j| {
+                    j != a
+                        && j != b
+                        && j != c
+                        && in_triangle(polygon[j], polygon[a], polygon[b], polygon[c])
+                })
+        });
+        let Some(i) = ear else { break };
+        let (a, b, c) = corner(i);
+        pieces.push(vec![a, b, c]);
+        remaining.remove(i);
+    }
+    // An outline that crosses itself can leave a part no ear fits; its hull covers it.
+    let leftover = if remaining.len() == 3 {
+        pieces.push(remaining);
+        None
+    } else {
+        let points: Vec<Point> = remaining.iter().map(|&i| polygon[i]).collect();
+        Some(limit_convex(convex_hull(&points), max_vertices))
+    };
+
+    let corners = |piece: &[usize]| {
+        let points: Vec<Point> = piece.iter().map(|&i| polygon[i]).collect();
+        strip_collinear(points)
+    };
+    'merging: loop {
+        for i in 0..pieces.len() {
+            for j in i + 1..pieces.len() {
+                let Some(merged) = merge(&pieces[i], &pieces[j]) else {
+                    continue;
+                };
+                let shape = corners(&merged);
+                if shape.len() <= max_vertices && is_convex(&shape) {
+                    pieces[i] = merged;
+                    pieces.remove(j);
+                    continue 'merging;
+                }
+            }
+        }
+        break;
+    }
+    pieces
+        .iter()
+        .map(|piece| corners(piece))
+        .chain(leftover)
+        .collect()
+}
+
+/// Whether `p` lies inside or on the triangle `a`, `b`, `c`.
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+fn is_convex(ring: &[Point]) -> bool {
+    let n = ring.len();
+    (0..n).all(|i| cross(ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]) >= -COLLINEAR)
+}
+
+/// Join two pieces along the edge they share, walked in opposite directions.
+fn merge(p: &[usize], q: &[usize]) -> Option<Vec<usize>> {
+    let (np, nq) = (p.len(), q.len());
+    for k in 0..np {
+        let (a, b) = (p[k], p[(k + 1) % np]);
+        let Some(l) = (0..nq).find(|&l| q[l] == b && q[(l + 1) % nq] == a) else {
+            continue;
+        };
+        // `p` from `b` round to `a`, then `q` from after `a` up to before `b`.
+        let mut merged: Vec<usize> = (0..np).map(|i| p[(k + 1 + i) % np]).collect();
+        merged.extend((0..nq - 2).map(|i| q[(l + 2 + i) % nq]));
+        return Some(merged);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn draw_rect(mask: &mut GrayImage, x0: u32, y0: u32, x1: u32, y1: u32) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                mask.put_pixel(x, y, Luma([255]));
+            }
+        }
+    }
+
+    /// An L made of a 40×10 bar and a 10×40 post, meeting at the bottom left.
+    fn l_shape() -> GrayImage {
+        let mut mask = GrayImage::new(60, 60);
+        draw_rect(&mut mask, 5, 45, 45, 55);
+        draw_rect(&mut mask, 5, 15, 15, 45);
+        mask
+    }
+
+    fn area(polygon: &[[f64; 2]]) -> f64 {
+        let n = polygon.len();
+        (0..n)
+            .map(|i| {
+                let ([ax, ay], [bx, by]) = (polygon[i], polygon[(i + 1) % n]);
+                ax * by - bx * ay
+            })
+            .sum::<f64>()
+            / 2.0
+    }
+
+    fn convex(polygon: &[[f64; 2]]) -> bool {
+        let points: Vec<Point> = polygon.iter().map(|&[x, y]| Point::new(x, y)).collect();
+        is_convex(&points)
+    }
+
+    mod collision_vectorizer {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn rectangle_becomes_four_vertices() {
+                let mut mask = GrayImage::new(40, 30);
+                draw_rect(&mut mask, 5, 5, 35, 25);
+                let trace = CollisionVectorizer
+                    .vectorize(&mask, &CollisionOptions::default())
+                    .unwrap();
+                assert_eq!(trace.shapes.len(), 1);
+                let polygon = &trace.shapes[0].polygons[0];
+                assert!(polygon.len() <= 8, "{polygon:?}");
+                assert!((area(polygon) - 600.0).abs() < 40.0, "{}", area(polygon));
+                assert!(trace.svg.contains("<path d=\"M"));
+            }
+
+            #[test]
+            fn vertex_budget_is_respected() {
+                let mut mask = GrayImage::new(80, 80);
+                for (x, y, pixel) in mask.enumerate_pixels_mut() {
+                    let (dx, dy) = (f64::from(x) - 40.0, f64::from(y) - 40.0);
+                    if dx.hypot(dy) < 30.0 {
+                        *pixel = Luma([255]);
+                    }
+                }
+                let options = CollisionOptions {
+                    max_vertices: 6,
+                    ..CollisionOptions::default()
+                };
+                let trace = CollisionVectorizer.vectorize(&mask, &options).unwrap();
+                let polygon = &trace.shapes[0].polygons[0];
+                assert!((3..=6).contains(&polygon.len()), "{polygon:?}");
+            }
+
+            #[test]
+            fn shallow_concavity_becomes_the_hull() {
+                let options = CollisionOptions {
+                    convexity: Some(50.0),
+                    ..CollisionOptions::default()
+                };
+                let trace = CollisionVectorizer.vectorize(&l_shape(), &options).unwrap();
+                let polygons = &trace.shapes[0].polygons;
+                assert_eq!(polygons.len(), 1);
+                assert!(convex(&polygons[0]));
+            }
+
+            #[test]
+            fn deep_concavity_is_decomposed() {
+                let options = CollisionOptions {
+                    convexity: Some(2.0),
+                    ..CollisionOptions::default()
+                };
+                let trace = CollisionVectorizer.vectorize(&l_shape(), &options).unwrap();
+                let polygons = &trace.shapes[0].polygons;
+                assert!(polygons.len() >= 2, "{polygons:?}");
+                assert!(polygons.iter().all(|polygon| convex(polygon)));
+                assert!(polygons.iter().all(|polygon| polygon.len() <= 8));
+                // The pieces tile the L (700 px) without covering the notch.
+                let total: f64 = polygons.iter().map(|polygon| area(polygon)).sum();
+                assert!((total - 700.0).abs() < 60.0, "{total}");
+            }
+
+            #[test]
+            fn specks_are_dropped() {
+                let mut mask = GrayImage::new(20, 20);
+                draw_rect(&mut mask, 2, 2, 4, 4);
+                let trace = CollisionVectorizer
+                    .vectorize(&mask, &CollisionOptions::default())
+                    .unwrap();
+                assert!(trace.shapes.is_empty());
+            }
+        }
+    }
+}
@@ -0,0 +1,753 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use image::GrayImage;
+use serde::Serialize;
+
+use crate::BgrResult;
+
+use super::MaskVectorizer;
+use super::geometry::{
+    MAX_SIMPLIFY_STEPS, Point, format_coord, node_budget_error, simplify_polyline,
+};
+
+/// Points on either side of a vertex used to measure its turning angle.
+const CORNER_WINDOW: usize = 3;
+
+/// Corner positions are only snapped to the intersection of the adjacent edges within this
+/// distance, so nearly-parallel edges don't push the anchor far away.
+const MAX_CORNER_SNAP: f64 = 2.0;
+
+/// Configuration for the built-in contour tracer.
+///
+/// Used with [`ContourVectorizer`] to trace mask boundaries without external dependencies.
+#[derive(Debug, Clone)]
+pub struct ContourOptions {
+    /// Mask values at or above this are treated as foreground.
+    pub threshold: u8,
+    /// Contours enclosing fewer pixels than this are dropped.
+    pub min_area: f64,
+    /// Douglas-Peucker tolerance in pixels.
+    pub simplify_tolerance: f64,
+    /// Fit smooth cubic curves through the simplified outline instead of straight segments.
+    pub smooth: bool,
+    /// Detect sharp corners and keep them as anchor points instead of rounding them off.
+    pub preserve_corners: bool,
+    /// Minimum turning angle in degrees for a boundary point to count as a corner.
+    pub corner_threshold: f64,
+    /// Decimal places in path coordinates.
+    pub path_precision: u32,
+    /// Trace the background instead of the foreground.
+    pub invert: bool,
+    /// Maximum nodes allowed in any single path; the tolerance is raised until it fits.
+    pub max_nodes: Option<usize>,
+    /// Add `data-area`, `data-perimeter`, `data-bbox`, and `data-holes` attributes to each path.
+    pub annotate: bool,
+}
+
+impl Default for ContourOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            min_area: 4.0,
+            simplify_tolerance: 1.0,
+            smooth: true,
+            preserve_corners: false,
+            corner_threshold: 60.0,
+            path_precision: 2,
+            invert: false,
+            max_nodes: None,
+            annotate: false,
+        }
+    }
+}
+
+/// Traces mask boundaries with marching squares into filled SVG paths.
+///
+/// Each outer boundary becomes one `<path>` with its holes as even-odd subpaths. The output
+/// also carries per-boundary [`ContourMetadata`], so callers can filter small contours without
+/// re-analyzing the geometry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContourVectorizer;
+
+impl MaskVectorizer for ContourVectorizer {
+    type Options = ContourOptions;
+    type Output = ContourTrace;
+
+    fn vectorize(&self, mask: &GrayImage, options: &Self::Options) -> BgrResult<Self::Output> {
+        let shapes = group_shapes(
+            extract_contours(mask, options.threshold, options.invert)
+                .into_iter()
+                .filter(|contour| contour.area.abs() >= options.min_area)
+                .collect(),
+        );
+
+        let mut tolerance = options.simplify_tolerance;
+        let mut paths = shape_paths(&shapes, options, tolerance);
+        if let Some(budget) = options.max_nodes {
+            let mut steps = 0;
+            loop {
+                let largest = paths.iter().map(|path| path.nodes).max().unwrap_or(0);
+                if largest <= budget {
+                    break;
+                }
+                if steps == MAX_SIMPLIFY_STEPS {
+                    return Err(node_budget_error(budget, largest));
+                }
+                tolerance = tolerance.max(0.5) * 2.0;
+                paths = shape_paths(&shapes, options, tolerance);
+                steps += 1;
+            }
+        }
+
+        let mut svg = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            svg,
+            "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            mask.width(),
+            mask.height()
+        );
+        let mut contours = Vec::new();
+        for (index, path) in paths.iter().enumerate() {
+            let shape = &shapes[path.shape];
+            let outer = ContourStats::of(&shape.outer);
+            let holes: Vec<ContourStats> = shape.holes.iter().map(ContourStats::of).collect();
+            let _ = write!(
+                svg,
+                "<path d=\"{}\" fill=\"#000000\" fill-rule=\"evenodd\"",
+                path.data
+            );
+            if options.annotate {
+                let area = outer.area - holes.iter().map(|h| h.area).sum::<f64>();
+                let perimeter = outer.perimeter + holes.iter().map(|h| h.perimeter).sum::<f64>();
+                let [x, y, w, h] = outer.bbox.map(|v| format_coord(v, 2));
+                let _ = write!(
+                    svg,
+                    " data-area=\"{}\" data-perimeter=\"{}\" data-bbox=\"{x} {y} {w} {h}\" data-holes=\"{}\"",
+                    format_coord(area, 2),
+                    format_coord(perimeter, 2),
+                    holes.len()
+                );
+            }
+            svg.push_str("/>\n");
+            contours.push(ContourMetadata::new(index, false, outer));
+            contours.extend(
+                holes
+                    .into_iter()
+                    .map(|stats| ContourMetadata::new(index, true, stats)),
+            );
+        }
+        svg.push_str("</svg>\n");
+        Ok(ContourTrace { svg, contours })
+    }
+}
+
+/// Output of [`ContourVectorizer`]: the SVG document plus geometry for every traced boundary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourTrace {
+    pub svg: String,
+    /// One entry per boundary, outer boundaries first within each path.
+    pub contours: Vec<ContourMetadata>,
+}
+
+impl From<ContourTrace> for String {
+    fn from(trace: ContourTrace) -> Self {
+        trace.svg
+    }
+}
+
+/// Geometry of one traced boundary, measured in source-image pixels before simplification.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ContourMetadata {
+    /// Index of the `<path>` element this boundary belongs to.
+    pub path: usize,
+    /// Whether this boundary is a hole inside its path's outer boundary.
+    pub hole: bool,
+    /// Enclosed area (always positive).
+    pub area: f64,
+    pub perimeter: f64,
+    /// Bounding box as `[x, y, width, height]`.
+    pub bbox: [f64; 4],
+}
+
+impl ContourMetadata {
+    fn new(path: usize, hole: bool, stats: ContourStats) -> Self {
+        Self {
+            path,
+            hole,
+            area: stats.area,
+            perimeter: stats.perimeter,
+            bbox: stats.bbox,
+        }
+    }
+}
+
+/// Area, perimeter, and bounding box of a contour.
+#[derive(Debug, Clone, Copy)]
+struct ContourStats {
+    area: f64,
+    perimeter: f64,
+    bbox: [f64; 4],
+}
+
+impl ContourStats {
+    fn of(contour: &Contour) -> Self {
+        let points = &contour.points;
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for p in points {
+            min_x = min_x.min(p.x);
+            min_y = min_y.min(p.y);
+            max_x = max_x.max(p.x);
+            max_y = max_y.max(p.y);
+        }
+        let n = points.len();
+        let perimeter = (0..n)
+            .map(|i| points[i].distance(points[(i + 1) % n]))
+            .sum();
+        Self {
+            area: contour.area.abs(),
+            perimeter,
+            bbox: [min_x, min_y, max_x - min_x, max_y - min_y],
+        }
+    }
+}
+
+/// A closed boundary between foreground and background.
+#[derive(Debug, Clone)]
+pub(crate) struct Contour {
+    /// Boundary points, with the foreground on the right when walked in order on screen.
+    pub points: Vec<Point>,
+    /// Signed area: positive for outer boundaries, negative for holes.
+    pub area: f64,
+}
+
+impl Contour {
+    pub fn is_hole(&self) -> bool {
+        self.area < 0.0
+    }
+
+    /// Even-odd ray casting test.
+    fn contains(&self, p: Point) -> bool {
+        let mut inside = false;
+        let n = self.points.len();
+        for i in 0..n {
+            let (a, b) = (self.points[i], self.points[(i + 1) % n]);
+            if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// An outer boundary together with the holes directly inside it.
+#[derive(Debug, Clone)]
+pub(crate) struct Shape {
+    pub outer: Contour,
+    pub holes: Vec<Contour>,
+}
+
+/// Assign every hole to the smallest outer boundary that contains it.
+pub(crate) fn group_shapes(contours: Vec<Contour>) -> Vec<Shape> {
+    let (holes, outers): (Vec<_>, Vec<_>) = contours.into_iter().partition(Contour::is_hole);
+    let mut shapes: Vec<Shape> = outers
+        .into_iter()
+        .map(|outer| Shape {
+            outer,
+            holes: Vec::new(),
+        })
+        .collect();
+    for hole in holes {
+        let probe = hole.points[0];
+        let parent = shapes
+            .iter_mut()
+            .filter(|shape| shape.outer.area >= -hole.area && shape.outer.contains(probe))
+            .min_by(|a, b| a.outer.area.total_cmp(&b.outer.area));
+        if let Some(shape) = parent {
+            shape.holes.push(hole);
+        }
+    }
+    shapes
+}
+
+/// Identifies the boundary crossing between two neighbouring pixel centres: `(x, y, vertical)`
+/// names the edge from `(x, y)` to `(x + 1, y)`, or to `(x, y + 1)` when `vertical`.
+type EdgeKey = (i64, i64, bool);
+
+/// Extract every closed boundary of the mask with marching squares.
+///
+/// Crossings are interpolated between pixel centres, so soft mattes yield sub-pixel boundaries.
+/// Pixels outside the image count as background, which closes shapes touching the border.
+pub(crate) fn extract_contours(mask: &GrayImage, threshold: u8, invert: bool) -> Vec<Contour> {
+    let (w, h) = (i64::from(mask.width()), i64::from(mask.height()));
+    let value = |x: i64, y: i64| -> f64 {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            return 0.0;
+        }
+        let v = mask.get_pixel(x as u32, y as u32)[0];
+        f64::from(if invert { 255 - v } else { v })
+    };
+    let level = f64::from(threshold);
+    let inside = |x: i64, y: i64| value(x, y) >= level;
+
+    let crossing = |(x, y, vertical): EdgeKey| -> Point {
+        let (bx, by) = if vertical { (x, y + 1) } else { (x + 1, y) };
+        let (va, vb) = (value(x, y), value(bx, by));
+        let t = if va == vb {
+            0.5
+        } else {
+            ((level - va) / (vb - va)).clamp(0.0, 1.0)
+        };
+        Point::new(
+            x as f64 + 0.5 + t * (bx - x) as f64,
+            y as f64 + 0.5 + t * (by - y) as f64,
+        )
+    };
+
+    let mut next: BTreeMap<EdgeKey, EdgeKey> = BTreeMap::new();
+    for y in -1..h {
+        for x in -1..w {
+            // Cell edges walked clockwise: top, right, bottom, left.
+            let edges = [
+                ((x, y), (x + 1, y), (x, y, false)),
+                ((x + 1, y), (x + 1, y + 1), (x + 1, y, true)),
+                ((x + 1, y + 1), (x, y + 1), (x, y + 1, false)),
+                ((x, y + 1), (x, y), (x, y, true)),
+            ];
+            // (edge index, exits the foreground)
+            let crossings: Vec<(usize, bool)> = edges
+                .iter()
+                .enumerate()
+                .filter_map(|(i, &(a, b, _))| {
+                    let a_in = inside(a.0, a.1);
+                    (a_in != inside(b.0, b.1)).then_some((i, a_in))
+                })
+                .collect();
+
+            let connect_following = crossings.len() == 4 && {
+                let centre =
+                    (value(x, y) + value(x + 1, y) + value(x, y + 1) + value(x + 1, y + 1)) / 4.0;
+                centre >= level
+            };
+            for (k, &(edge, exits)) in crossings.iter().enumerate() {
+                if !exits {
+                    continue;
+                }
+                let n = crossings.len();
+                let partner = if connect_following {
+                    crossings[(k + 1) % n].0
+                } else {
+                    crossings[(k + n - 1) % n].0
+                };
+                next.insert(edges[edge].2, edges[partner].2);
+            }
+        }
+    }
+
+    let mut contours = Vec::new();
+    while let Some((&start, _)) = next.iter().next() {
+        let mut points = Vec::new();
+        let mut key = start;
+        while let Some(following) = next.remove(&key) {
+            points.push(crossing(key));
+            key = following;
+        }
+        if points.len() >= 3 {
+            let area = signed_area(&points);
+            contours.push(Contour { points, area });
+        }
+    }
+    contours
+}
+
+/// Shoelace area in image coordinates; positive for clockwise loops on screen.
+fn signed_area(points: &[Point]) -> f64 {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Path data rendered for one shape.
+struct RenderedPath {
+    /// Index into the shapes the path was rendered from.
+    shape: usize,
+    data: String,
+    nodes: usize,
+}
+
+/// Render every shape to path data, skipping shapes that simplify away entirely.
+fn shape_paths(shapes: &[Shape], options: &ContourOptions, tolerance: f64) -> Vec<RenderedPath> {
+    shapes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, shape)| {
+            let mut data = String::new();
+            let mut nodes = 0;
+            for contour in std::iter::once(&shape.outer).chain(&shape.holes) {
+                nodes += push_contour(&mut data, &contour.points, options, tolerance);
+            }
+            (nodes > 0).then(|| RenderedPath {
+                shape: index,
+                data: data.trim_end().to_string(),
+                nodes,
+            })
+        })
+        .collect()
+}
+
+/// Append one closed subpath for `points`, returning the number of nodes written.
+fn push_contour(
+    data: &mut String,
+    points: &[Point],
+    options: &ContourOptions,
+    tolerance: f64,
+) -> usize {
+    let corners = if options.preserve_corners {
+        corner_indices(points, options.corner_threshold)
+    } else {
+        Vec::new()
+    };
+
+    let pieces: Vec<Vec<Point>> = if corners.is_empty() {
+        let mut closed = points.to_vec();
+        closed.push(points[0]);
+        vec![simplify_polyline(&closed, tolerance)]
+    } else {
+        let n = points.len();
+        let anchors: Vec<Point> = corners.iter().map(|&i| snap_corner(points, i)).collect();
+        (0..corners.len())
+            .map(|c| {
+                let (start, end) = (corners[c], corners[(c + 1) % corners.len()]);
+                let len = (end + n - start) % n;
+                let len = if len == 0 { n } else { len };
+                let mut piece: Vec<Point> = (0..=len).map(|i| points[(start + i) % n]).collect();
+                piece[0] = anchors[c];
+                piece[len] = anchors[(c + 1) % anchors.len()];
+                simplify_polyline(&piece, tolerance)
+            })
+            .collect()
+    };
+
+    let precision = options.path_precision;
+    if corners.is_empty() {
+        let mut ring = pieces.into_iter().next().unwrap_or_default();
+        ring.pop();
+        if ring.len() < 3 {
+            return 0;
+        }
+        push_point(data, 'M', ring[0], precision);
+        let n = ring.len();
+        for j in 0..n {
+            if options.smooth {
+                let (p0, p1, p2, p3) = (
+                    ring[(j + n - 1) % n],
+                    ring[j],
+                    ring[(j + 1) % n],
+                    ring[(j + 2) % n],
+                );
+                push_cubic(data, p0, p1, p2, p3, precision);
+            } else if j + 1 < n {
+                push_point(data, 'L', ring[j + 1], precision);
+            }
+        }
+        data.push_str("Z ");
+        return if options.smooth { n + 1 } else { n };
+    }
+
+    let mut nodes = 1;
+    push_point(data, 'M', pieces[0][0], precision);
+    for piece in &pieces {
+        let m = piece.len();
+        for j in 0..m - 1 {
+            if options.smooth && m > 2 {
+                let p0 = piece[j.saturating_sub(1)];
+                let p3 = piece[(j + 2).min(m - 1)];
+                push_cubic(data, p0, piece[j], piece[j + 1], p3, precision);
+            } else {
+                push_point(data, 'L', piece[j + 1], precision);
+            }
+            nodes += 1;
+        }
+    }
+    data.push_str("Z ");
+    nodes
+}
+
+pub(super) fn push_point(data: &mut String, command: char, p: Point, precision: u32) {
+    let _ = write!(
+        data,
+        "{command}{} {} ",
+        format_coord(p.x, precision),
+        format_coord(p.y, precision)
+    );
+}
+
+/// Append the Catmull-Rom segment from `p1` to `p2` as a cubic Bezier.
+pub(super) fn push_cubic(
+    data: &mut String,
+    p0: Point,
+    p1: Point,
+    p2: Point,
+    p3: Point,
+    precision: u32,
+) {
+    let c1 = Point::new(p1.x + (p2.x - p0.x) / 6.0, p1.y + (p2.y - p0.y) / 6.0);
+    let c2 = Point::new(p2.x - (p3.x - p1.x) / 6.0, p2.y - (p3.y - p1.y) / 6.0);
+    let _ = write!(
+        data,
+        "C{} {} {} {} {} {} ",
+        format_coord(c1.x, precision),
+        format_coord(c1.y, precision),
+        format_coord(c2.x, precision),
+        format_coord(c2.y, precision),
+        format_coord(p2.x, precision),
+        format_coord(p2.y, precision)
+    );
+}
+
+/// Indices of boundary points whose turning angle is a local maximum above `threshold` degrees.
+pub(crate) fn corner_indices(points: &[Point], threshold: f64) -> Vec<usize> {
+    let n = points.len();
+    let k = CORNER_WINDOW;
+    if n < 4 * k + 1 {
+        return Vec::new();
+    }
+    let turn: Vec<f64> = (0..n)
+        .map(|i| turning_angle(points[(i + n - k) % n], points[i], points[(i + k) % n]))
+        .collect();
+    (0..n)
+        .filter(|&i| {
+            turn[i] >= threshold
+                && (1..=k).all(|d| turn[i] > turn[(i + n - d) % n] && turn[i] >= turn[(i + d) % n])
+        })
+        .collect()
+}
+
+/// Angle in degrees between the directions `a → b` and `b → c`.
+fn turning_angle(a: Point, b: Point, c: Point) -> f64 {
+    let (ux, uy) = (b.x - a.x, b.y - a.y);
+    let (vx, vy) = (c.x - b.x, c.y - b.y);
+    let lengths = ux.hypot(uy) * vx.hypot(vy);
+    if lengths == 0.0 {
+        return 0.0;
+    }
+    ((ux * vx + uy * vy) / lengths)
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+/// Move a corner onto the intersection of the straight runs leading into and out of it.
+///
+/// Marching squares bevels square corners by half a pixel; extending the adjacent edges
+/// restores the sharp point.
+fn snap_corner(points: &[Point], i: usize) -> Point {
+    let n = points.len();
+    let k = CORNER_WINDOW;
+    let (a1, a2) = (points[(i + n - 2 * k) % n], points[(i + n - k) % n]);
+    let (b1, b2) = (points[(i + k) % n], points[(i + 2 * k) % n]);
+    let (dax, day) = (a2.x - a1.x, a2.y - a1.y);
+    let (dbx, dby) = (b2.x - b1.x, b2.y - b1.y);
+    let denom = dax * dby - day * dbx;
+    if denom.abs() < 1e-9 {
+        return points[i];
+    }
+    let t = ((b1.x - a1.x) * dby - (b1.y - a1.y) * dbx) / denom;
+    let snapped = Point::new(a1.x + t * dax, a1.y + t * day);
+    if snapped.distance(points[i]) <= MAX_CORNER_SNAP {
+        snapped
+    } else {
+        points[i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn square(size: u32, x0: u32, x1: u32) -> GrayImage {
+        GrayImage::from_fn(size, size, |x, y| {
+            let inside = (x0..x1).contains(&x) && (x0..x1).contains(&y);
+            Luma([if inside { 255 } else { 0 }])
+        })
+    }
+
+    fn ring(size: u32) -> GrayImage {
+        let (a, b) = (size / 4, size * 3 / 4);
+        let (c, d) = (size * 3 / 8, size * 5 / 8);
+        GrayImage::from_fn(size, size, |x, y| {
+            let outer = (a..b).contains(&x) && (a..b).contains(&y);
+            let hole = (c..d).contains(&x) && (c..d).contains(&y);
+            Luma([if outer && !hole { 255 } else { 0 }])
+        })
+    }
+
+    mod extract_contours {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn empty_mask_has_no_contours() {
+                assert!(extract_contours(&GrayImage::new(8, 8), 128, false).is_empty());
+            }
+
+            #[test]
+            fn square_area_matches_pixels() {
+                let contours = extract_contours(&square(20, 5, 15), 128, false);
+                assert_eq!(contours.len(), 1);
+                assert!(!contours[0].is_hole());
+                // A 10×10 block, minus the half-pixel bevel at each corner.
+                assert!((contours[0].area - 99.5).abs() < 0.5);
+            }
+
+            #[test]
+            fn ring_has_outer_and_hole() {
+                let contours = extract_contours(&ring(32), 128, false);
+                assert_eq!(contours.len(), 2);
+                assert_eq!(contours.iter().filter(|c| c.is_hole()).count(), 1);
+                let shapes = group_shapes(contours);
+                assert_eq!(shapes.len(), 1);
+                assert_eq!(shapes[0].holes.len(), 1);
+            }
+
+            #[test]
+            fn border_touching_shape_is_closed() {
+                let full = GrayImage::from_pixel(6, 6, Luma([255]));
+                let contours = extract_contours(&full, 128, false);
+                assert_eq!(contours.len(), 1);
+                assert!(contours[0].area > 30.0);
+            }
+
+            #[test]
+            fn invert_traces_background() {
+                let contours = extract_contours(&square(20, 5, 15), 128, true);
+                assert_eq!(contours.iter().filter(|c| c.is_hole()).count(), 1);
+            }
+        }
+    }
+
+    mod corner_indices {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn square_has_four_corners() {
+                let contours = extract_contours(&square(40, 10, 30), 128, false);
+                assert_eq!(corner_indices(&contours[0].points, 60.0).len(), 4);
+            }
+
+            #[test]
+            fn circle_has_no_corners() {
+                let disc = GrayImage::from_fn(64, 64, |x, y| {
+                    let d = ((x as f64 - 32.0).powi(2) + (y as f64 - 32.0).powi(2)).sqrt();
+                    Luma([if d < 24.0 { 255 } else { 0 }])
+                });
+                let contours = extract_contours(&disc, 128, false);
+                assert!(corner_indices(&contours[0].points, 60.0).is_empty());
+            }
+
+            #[test]
+            fn snapped_corner_lands_on_pixel_corner() {
+                let contours = extract_contours(&square(40, 10, 30), 128, false);
+                let points = &contours[0].points;
+                for i in corner_indices(points, 60.0) {
+                    let p = snap_corner(points, i);
+                    let near = [(10.0, 10.0), (30.0, 10.0), (30.0, 30.0), (10.0, 30.0)]
+                        .iter()
+                        .any(|&(x, y)| Point::new(x, y).distance(p) < 0.1);
+                    assert!(near, "corner {p:?} is not sharp");
+                }
+            }
+        }
+    }
+
+    mod contour_vectorizer {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn emits_one_path_per_shape() {
+                let svg = ContourVectorizer
+                    .vectorize(&ring(32), &ContourOptions::default())
+                    .unwrap()
+                    .svg;
+                assert_eq!(svg.matches("<path").count(), 1);
+                assert_eq!(svg.matches('M').count(), 2);
+                assert!(svg.contains("fill-rule=\"evenodd\""));
+            }
+
+            #[test]
+            fn preserved_corners_are_anchors() {
+                let options = ContourOptions {
+                    preserve_corners: true,
+                    ..ContourOptions::default()
+                };
+                let svg = ContourVectorizer
+                    .vectorize(&square(40, 10, 30), &options)
+                    .unwrap()
+                    .svg;
+                assert!(svg.contains("10 10 "));
+                assert!(svg.contains("30 30 "));
+            }
+
+            #[test]
+            fn metadata_describes_outer_and_hole() {
+                let trace = ContourVectorizer
+                    .vectorize(&ring(32), &ContourOptions::default())
+                    .unwrap();
+                assert_eq!(trace.contours.len(), 2);
+                let outer = trace.contours.iter().find(|c| !c.hole).unwrap();
+                let hole = trace.contours.iter().find(|c| c.hole).unwrap();
+                assert_eq!((outer.path, hole.path), (0, 0));
+                assert!(outer.area > hole.area);
+                assert!((outer.bbox[2] - 16.0).abs() < 0.1);
+                assert!((outer.perimeter - 64.0).abs() < 2.0);
+            }
+
+            #[test]
+            fn annotate_adds_data_attributes() {
+                let options = ContourOptions {
+                    annotate: true,
+                    ..ContourOptions::default()
+                };
+                let svg = ContourVectorizer
+                    .vectorize(&ring(32), &options)
+                    .unwrap()
+                    .svg;
+                assert!(svg.contains("data-holes=\"1\""));
+                assert!(svg.contains("data-bbox=\"8 8 16 16\""));
+                assert!(svg.contains("data-area="));
+            }
+
+            #[test]
+            fn node_budget_is_respected() {
+                let disc = GrayImage::from_fn(128, 128, |x, y| {
+                    let d = ((x as f64 - 64.0).powi(2) + (y as f64 - 64.0).powi(2)).sqrt();
+                    Luma([if d < 50.0 { 255 } else { 0 }])
+                });
+                let options = ContourOptions {
+                    simplify_tolerance: 0.0,
+                    smooth: false,
+                    max_nodes: Some(8),
+                    ..ContourOptions::default()
+                };
+                let svg = ContourVectorizer.vectorize(&disc, &options).unwrap().svg;
+                let data = svg.split("d=\"").nth(1).unwrap().split('"').next().unwrap();
+                assert!(data.matches(['M', 'L', 'C']).count() <= 8);
+            }
+        }
+    }
+}
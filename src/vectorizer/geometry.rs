@@ -0,0 +1,178 @@
+//! Small 2D geometry helpers shared by the built-in tracers.
+
+use crate::BgrError;
+
+/// Number of simplification passes attempted before giving up on a node budget.
+pub const MAX_SIMPLIFY_STEPS: usize = 12;
+
+/// A point in image space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    /// Euclidean distance to another point.
+    pub fn distance(self, other: Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+/// Total length of a polyline.
+pub fn polyline_length(points: &[Point]) -> f64 {
+    points.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+/// Simplify a polyline with the Douglas-Peucker algorithm.
+///
+/// The first and last points are always kept; interior points closer than `tolerance` to the
+/// simplified line are dropped.
+pub fn simplify_polyline(points: &[Point], tolerance: f64) -> Vec<Point> {
+    if points.len() < 3 || tolerance <= 0.0 {
+        return points.to_vec();
+    }
+
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+
+    let mut stack = vec![(0, last)];
+    while let Some((start, end)) = stack.pop() {
+        let mut max_distance = 0.0;
+        let mut index = start;
+        for (i, point) in points.iter().enumerate().take(end).skip(start + 1) {
+            let distance = perpendicular_distance(*point, points[start], points[end]);
+            if distance > max_distance {
+                max_distance = distance;
+                index = i;
+            }
+        }
+        if max_distance > tolerance {
+            keep[index] = true;
+            stack.push((start, index));
+            stack.push((index, end));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+/// Distance from `p` to the line through `a` and `b` (or to `a` when they coincide).
+fn perpendicular_distance(p: Point, a: Point, b: Point) -> f64 {
+    let length = a.distance(b);
+    if length == 0.0 {
+        return p.distance(a);
+    }
+    ((b.x - a.x) * (a.y - p.y) - (a.x - p.x) * (b.y - a.y)).abs() / length
+}
+
+/// Error for a trace that still exceeds its node budget after maximum simplification.
+pub fn node_budget_error(budget: usize, largest: usize) -> BgrError {
+    BgrError::Trace(format!(
+        "could not fit paths within {budget} nodes; largest path still has {largest}"
+    ))
+}
+
+/// Format a coordinate with at most `precision` decimals, trimming trailing zeros.
+pub fn format_coord(value: f64, precision: u32) -> String {
+    let text = format!("{value:.*}", precision as usize);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    mod simplify_polyline {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn collinear_points_collapse() {
+                let line: Vec<_> = (0..10).map(|i| Point::new(i as f64, 0.0)).collect();
+                let simplified = simplify_polyline(&line, 0.5);
+                assert_eq!(simplified, vec![line[0], line[9]]);
+            }
+
+            #[test]
+            fn corner_is_kept() {
+                let points = [
+                    Point::new(0.0, 0.0),
+                    Point::new(5.0, 0.0),
+                    Point::new(10.0, 0.0),
+                    Point::new(10.0, 5.0),
+                    Point::new(10.0, 10.0),
+                ];
+                let simplified = simplify_polyline(&points, 0.5);
+                assert_eq!(simplified, vec![points[0], points[2], points[4]]);
+            }
+
+            #[test]
+            fn closed_loop_keeps_far_point() {
+                let points = [
+                    Point::new(0.0, 0.0),
+                    Point::new(10.0, 0.0),
+                    Point::new(10.0, 10.0),
+                    Point::new(0.0, 0.0),
+                ];
+                assert!(simplify_polyline(&points, 1.0).len() >= 3);
+            }
+        }
+
+        mod prop {
+            use super::*;
+
+            proptest! {
+                #[test]
+                fn endpoints_preserved_and_never_grows(
+                    coords in prop::collection::vec((0.0f64..100.0, 0.0f64..100.0), 2..50),
+                    tolerance in 0.0f64..10.0,
+                ) {
+                    let points: Vec<_> = coords.iter().map(|&(x, y)| Point::new(x, y)).collect();
+                    let simplified = simplify_polyline(&points, tolerance);
+                    prop_assert!(simplified.len() <= points.len());
+                    prop_assert_eq!(simplified.first(), points.first());
+                    prop_assert_eq!(simplified.last(), points.last());
+                }
+            }
+        }
+    }
+
+    mod format_coord {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn trims_trailing_zeros() {
+                assert_eq!(format_coord(1.50, 2), "1.5");
+                assert_eq!(format_coord(2.0, 2), "2");
+                assert_eq!(format_coord(-0.001, 2), "0");
+                assert_eq!(format_coord(3.25, 0), "3");
+            }
+        }
+    }
+}
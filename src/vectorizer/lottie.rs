@@ -0,0 +1,243 @@
+use image::GrayImage;
+use serde_json::{Value, json};
+
+use crate::BgrResult;
+
+use super::MaskVectorizer;
+use super::sequence::{OutlineFrame, OutlineSequence, SequenceOptions};
+
+/// Lottie format version the exported animations declare.
+const LOTTIE_VERSION: &str = "5.7.4";
+
+/// Configuration for [`OutlineSequence::lottie`] and [`LottieVectorizer`].
+#[derive(Debug, Clone)]
+pub struct LottieOptions {
+    /// How the outlines are traced and resampled.
+    pub outline: SequenceOptions,
+    /// Frames per second the animation plays at.
+    pub frame_rate: f64,
+}
+
+impl Default for LottieOptions {
+    fn default() -> Self {
+        Self {
+            outline: SequenceOptions::default(),
+            frame_rate: 24.0,
+        }
+    }
+}
+
+/// Traces a single mask into a one-frame Lottie animation, for tools that take Lottie
+/// rather than SVG.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LottieVectorizer;
+
+impl MaskVectorizer for LottieVectorizer {
+    type Options = LottieOptions;
+    type Output = String;
+
+    fn vectorize(&self, mask: &GrayImage, options: &Self::Options) -> BgrResult<Self::Output> {
+        OutlineSequence::trace(std::slice::from_ref(mask), &options.outline)
+            .map(|sequence| sequence.lottie(options))
+    }
+}
+
+impl OutlineSequence {
+    /// The sequence as a Lottie animation: one shape layer with a filled path per outline,
+    /// keyframed on every frame.
+    ///
+    /// Outlines keep their point count and order from frame to frame, so the player tweens
+    /// them point by point; a single frame is a still path.
+    pub fn lottie(&self, options: &LottieOptions) -> String {
+        let frames = self.frames.len();
+        let outlines = self.frames.first().map_or(0, |frame| frame.outlines.len());
+        let groups: Vec<Value> = (0..outlines)
+            .map(|index| {
+                let path = match self.frames.as_slice() {
+                    [frame] => json!({ "a": 0, "k": shape(frame, index, &options.outline) }),
+                    frames => json!({ "a": 1, "k": keyframes(frames, index, &options.outline) }),
+                };
+                json!({
+                    "ty": "gr",
+                    "nm": format!("outline-{index}"),
+                    "it": [
+                        { "ty": "sh", "nm": "path", "ks": path },
+                        {
+                            "ty": "fl",
+                            "c": { "a": 0, "k": [0, 0, 0, 1] },
+                            "o": { "a": 0, "k": 100 },
+                            "r": 1
+                        },
+                        {
+                            "ty": "tr",
+                            "p": { "a": 0, "k": [0, 0] },
+                            "a": { "a": 0, "k": [0, 0] },
+                            "s": { "a": 0, "k": [100, 100] },
+                            "r": { "a": 0, "k": 0 },
+                            "o": { "a": 0, "k": 100 }
+                        }
+                    ]
+                })
+            })
+            .collect();
+        json!({
+            "v": LOTTIE_VERSION,
+            "fr": options.frame_rate,
+            "ip": 0,
+            "op": frames,
+            "w": self.width,
+            "h": self.height,
+            "nm": "bgr outline",
+            "ddd": 0,
+            "assets": [],
+            "layers": [{
+                "ddd": 0,
+                "ind": 1,
+                "ty": 4,
+                "nm": "outline",
+                "sr": 1,
+                "ks": {
+                    "o": { "a": 0, "k": 100 },
+                    "r": { "a": 0, "k": 0 },
+                    "p": { "a": 0, "k": [0, 0, 0] },
+                    "a": { "a": 0, "k": [0, 0, 0] },
+                    "s": { "a": 0, "k": [100, 100, 100] }
+                },
+                "ao": 0,
+                "shapes": groups,
+                "ip": 0,
+                "op": frames,
+                "st": 0,
+                "bm": 0
+            }]
+        })
+        .to_string()
+    }
+}
+
+/// One keyframe per frame for outline `index`, eased linearly into the next.
+fn keyframes(frames: &[OutlineFrame], index: usize, options: &SequenceOptions) -> Vec<Value> {
+    let last = frames.len() - 1;
+    frames
+        .iter()
+        .enumerate()
+        .map(|(time, frame)| {
+            let value = json!([shape(frame, index, options)]);
+            if time == last {
+                json!({ "t": time, "s": value })
+            } else {
+                json!({
+                    "t": time,
+                    "s": value,
+                    "i": { "x": [1], "y": [1] },
+                    "o": { "x": [0], "y": [0] }
+                })
+            }
+        })
+        .collect()
+}
+
+/// Outline `index` of `frame` as a closed Lottie path, with the Catmull-Rom tangents the SVG
+/// export uses when smoothing, relative to each vertex.
+fn shape(frame: &OutlineFrame, index: usize, options: &SequenceOptions) -> Value {
+    let ring = &frame.outlines[index];
+    let n = ring.len();
+    let round = |value: f64| {
+        let scale = 10f64.powi(options.path_precision as i32);
+        (value * scale).round() / scale
+    };
+    let mut vertices = Vec::with_capacity(n);
+    let mut in_tangents = Vec::with_capacity(n);
+    let mut out_tangents = Vec::with_capacity(n);
+    for j in 0..n {
+        let [x, y] = ring[j];
+        let (before, after) = (ring[(j + n - 1) % n], ring[(j + 1) % n]);
+        let (dx, dy) = if options.smooth {
+            ((after[0] - before[0]) / 6.0, (after[1] - before[1]) / 6.0)
+        } else {
+            (0.0, 0.0)
+        };
+        vertices.push([round(x), round(y)]);
+        in_tangents.push([round(-dx), round(-dy)]);
+        out_tangents.push([round(dx), round(dy)]);
+    }
+    json!({ "c": true, "v": vertices, "i": in_tangents, "o": out_tangents })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn square(x0: u32) -> GrayImage {
+        GrayImage::from_fn(48, 48, |x, y| {
+            let inside = (x0..x0 + 16).contains(&x) && (8..24).contains(&y);
+            Luma([if inside { 255 } else { 0 }])
+        })
+    }
+
+    mod outline_sequence {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn sequences_keyframe_every_frame() {
+                let options = LottieOptions {
+                    outline: SequenceOptions {
+                        nodes: 12,
+                        ..SequenceOptions::default()
+                    },
+                    frame_rate: 12.0,
+                };
+                let sequence =
+                    OutlineSequence::trace(&[square(4), square(12), square(20)], &options.outline)
+                        .unwrap();
+                let lottie: Value = serde_json::from_str(&sequence.lottie(&options)).unwrap();
+                assert_eq!(
+                    (lottie["fr"].as_f64(), lottie["op"].as_u64()),
+                    (Some(12.0), Some(3))
+                );
+                let path = &lottie["layers"][0]["shapes"][0]["it"][0]["ks"];
+                assert_eq!(path["a"], 1);
+                let keyframes = path["k"].as_array().unwrap();
+                assert_eq!(keyframes.len(), 3);
+                for keyframe in keyframes {
+                    assert_eq!(keyframe["s"][0]["v"].as_array().unwrap().len(), 12);
+                }
+            }
+        }
+    }
+
+    mod lottie_vectorizer {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn single_masks_are_still_paths() {
+                let options = LottieOptions {
+                    outline: SequenceOptions {
+                        smooth: false,
+                        ..SequenceOptions::default()
+                    },
+                    ..LottieOptions::default()
+                };
+                let lottie = LottieVectorizer.vectorize(&square(4), &options).unwrap();
+                let lottie: Value = serde_json::from_str(&lottie).unwrap();
+                let path = &lottie["layers"][0]["shapes"][0]["it"][0]["ks"];
+                assert_eq!(path["a"], 0);
+                assert_eq!(path["k"]["c"], true);
+                assert!(
+                    path["k"]["o"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .all(|o| o[0] == 0.0 && o[1] == 0.0)
+                );
+            }
+        }
+    }
+}
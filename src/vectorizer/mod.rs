@@ -10,5 +10,12 @@ pub trait MaskVectorizer {
     fn vectorize(&self, mask: &GrayImage, options: &Self::Options) -> BgrResult<Self::Output>;
 }
 
+pub mod centerline;
+pub mod collision;
+pub mod contour;
+mod geometry;
+pub mod lottie;
+pub mod sequence;
+pub mod transform;
 #[cfg(feature = "vectorizer-vtracer")]
 pub mod vtracer;
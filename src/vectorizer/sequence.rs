@@ -0,0 +1,401 @@
+use std::fmt::Write as _;
+
+use image::GrayImage;
+
+use crate::{BgrError, BgrResult};
+
+use super::contour::{extract_contours, push_cubic, push_point};
+use super::geometry::Point;
+
+/// Points per outline unless [`SequenceOptions::nodes`] says otherwise.
+const DEFAULT_SEQUENCE_NODES: usize = 64;
+
+/// Configuration for tracing a mask sequence with [`OutlineSequence::trace`].
+#[derive(Debug, Clone)]
+pub struct SequenceOptions {
+    /// Mask values at or above this are treated as foreground.
+    pub threshold: u8,
+    /// Outlines enclosing fewer pixels than this are dropped.
+    pub min_area: f64,
+    /// Points every outline is resampled to, in every frame.
+    pub nodes: usize,
+    /// Join the points with smooth cubic curves instead of straight segments.
+    pub smooth: bool,
+    /// Decimal places in path coordinates.
+    pub path_precision: u32,
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            min_area: 4.0,
+            nodes: DEFAULT_SEQUENCE_NODES,
+            smooth: true,
+            path_precision: 2,
+        }
+    }
+}
+
+/// The outer outlines of a mask sequence, traced so they can be tweened from frame to frame.
+///
+/// Every frame has the same outlines in the same order, each with [`SequenceOptions::nodes`]
+/// points spaced evenly along it, walked the same way round and started where the previous
+/// frame's started. An outline is matched to the nearest one of the frame before; one that
+/// appears or vanishes is collapsed to a point where it is first or last seen, so it grows
+/// out of or shrinks into nothing. Holes are not traced, since a tween cannot add subpaths.
+///
+/// # Example
+/// ```no_run
+/// use bgr::{OutlineSequence, SequenceOptions};
+///
+/// let masks: Vec<image::GrayImage> = (1..=24)
+///     .map(|frame| image::open(format!("mask-{frame:02}.png")).map(|mask| mask.into_luma8()))
+///     .collect::<Result<_, _>>()?;
+/// let options = SequenceOptions::default();
+/// let sequence = OutlineSequence::trace(&masks, &options)?;
+/// for (frame, svg) in sequence.svgs(&options).iter().enumerate() {
+///     std::fs::write(format!("outline-{:02}.svg", frame + 1), svg)?;
+/// }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineSequence {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<OutlineFrame>,
+}
+
+/// One frame of an [`OutlineSequence`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineFrame {
+    /// The points of each outline, as `[x, y]` in pixels; for an outline absent from this
+    /// frame, all at one point.
+    pub outlines: Vec<Vec<[f64; 2]>>,
+}
+
+/// An outline followed through the sequence.
+struct Track {
+    /// Its points in each frame so far, when it was there.
+    rings: Vec<Option<Vec<Point>>>,
+    /// Its points in the latest frame it was there.
+    latest: Vec<Point>,
+}
+
+impl OutlineSequence {
+    /// Trace `masks`, the frames of one animation in order, which must all be the same size.
+    pub fn trace(masks: &[GrayImage], options: &SequenceOptions) -> BgrResult<Self> {
+        let Some(first) = masks.first() else {
+            return Err(BgrError::Trace("a sequence needs at least one mask".into()));
+        };
+        let (width, height) = first.dimensions();
+        if let Some(mask) = masks
+            .iter()
+            .find(|mask| mask.dimensions() != (width, height))
+        {
+            return Err(BgrError::Trace(format!(
+                "every frame of a sequence must be {width}x{height}, not {}x{}",
+                mask.width(),
+                mask.height()
+            )));
+        }
+        if options.nodes < 3 {
+            return Err(BgrError::Trace(format!(
+                "outlines need at least 3 nodes, not {}",
+                options.nodes
+            )));
+        }
+
+        let mut tracks: Vec<Track> = Vec::new();
+        for (frame, mask) in masks.iter().enumerate() {
+            let rings = frame_rings(mask, options);
+            for track in &mut tracks {
+                track.rings.push(None);
+            }
+            let matched = match_tracks(&tracks, &rings);
+            for (ring, track) in rings.into_iter().zip(matched) {
+                match track {
+                    Some(index) => {
+                        let track = &mut tracks[index];
+                        let ring = align(ring, &track.latest);
+                        track.rings[frame] = Some(ring.clone());
+                        track.latest = ring;
+                    }
+                    None => {
+                        let mut rings = vec![None; frame];
+                        rings.push(Some(ring.clone()));
+                        tracks.push(Track {
+                            rings,
+                            latest: ring,
+                        });
+                    }
+                }
+            }
+        }
+
+        let frames = (0..masks.len())
+            .map(|frame| OutlineFrame {
+                outlines: tracks
+                    .iter()
+                    .map(|track| {
+                        let ring = match &track.rings[frame] {
+                            Some(ring) => ring.clone(),
+                            None => {
+                                vec![centroid(nearest_ring(&track.rings, frame)); options.nodes]
+                            }
+                        };
+                        ring.into_iter().map(|p| [p.x, p.y]).collect()
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            frames,
+        })
+    }
+
+    /// One SVG document per frame, with a `<path id="outline-N">` for each outline.
+    ///
+    /// The path data of an outline has the same commands in every frame, so SMIL, CSS, or
+    /// Lottie shape tweens can interpolate between frames point by point.
+    pub fn svgs(&self, options: &SequenceOptions) -> Vec<String> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                let mut svg = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+                let _ = writeln!(
+                    svg,
+                    "<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+                    self.width, self.height
+                );
+                for (index, outline) in frame.outlines.iter().enumerate() {
+                    let points: Vec<Point> =
+                        outline.iter().map(|&[x, y]| Point::new(x, y)).collect();
+                    let _ = writeln!(
+                        svg,
+                        "<path id=\"outline-{index}\" d=\"{}\" fill=\"#000000\"/>",
+                        ring_data(&points, options)
+                    );
+                }
+                svg.push_str("</svg>\n");
+                svg
+            })
+            .collect()
+    }
+}
+
+/// The outer outlines of `mask`, largest first, each resampled from its topmost point.
+fn frame_rings(mask: &GrayImage, options: &SequenceOptions) -> Vec<Vec<Point>> {
+    let mut outers: Vec<_> = extract_contours(mask, options.threshold, false)
+        .into_iter()
+        .filter(|contour| !contour.is_hole() && contour.area >= options.min_area)
+        .collect();
+    outers.sort_by(|a, b| b.area.total_cmp(&a.area));
+    outers
+        .into_iter()
+        .map(|outer| {
+            let mut points = outer.points;
+            let top = (0..points.len())
+                .min_by(|&a, &b| {
+                    let (a, b) = (points[a], points[b]);
+                    a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+                })
+                .unwrap_or(0);
+            points.rotate_left(top);
+            resample(&points, options.nodes)
+        })
+        .collect()
+}
+
+/// The track each ring continues, pairing the closest centroids first; `None` starts a new one.
+fn match_tracks(tracks: &[Track], rings: &[Vec<Point>]) -> Vec<Option<usize>> {
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+    for (t, track) in tracks.iter().enumerate() {
+        let from = centroid(&track.latest);
+        for (r, ring) in rings.iter().enumerate() {
+            pairs.push((from.distance(centroid(ring)), t, r));
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut taken = vec![false; tracks.len()];
+    let mut matched = vec![None; rings.len()];
+    for (_, t, r) in pairs {
+        if !taken[t] && matched[r].is_none() {
+            taken[t] = true;
+            matched[r] = Some(t);
+        }
+    }
+    matched
+}
+
+/// `nodes` points spaced evenly along the closed outline `points`, starting at its first.
+fn resample(points: &[Point], nodes: usize) -> Vec<Point> {
+    let n = points.len();
+    let edge_length = |i: usize| points[i].distance(points[(i + 1) % n]);
+    let step = (0..n).map(edge_length).sum::<f64>() / nodes as f64;
+    let mut ring = Vec::with_capacity(nodes);
+    let (mut edge, mut walked) = (0, 0.0);
+    for k in 0..nodes {
+        let target = k as f64 * step;
+        while edge + 1 < n && walked + edge_length(edge) < target {
+            walked += edge_length(edge);
+            edge += 1;
+        }
+        let (a, b) = (points[edge], points[(edge + 1) % n]);
+        let length = edge_length(edge);
+        let t = if length == 0.0 {
+            0.0
+        } else {
+            ((target - walked) / length).clamp(0.0, 1.0)
+        };
+        ring.push(Point::new(a.x + t * (b.x - a.x), a.y + t * (b.y - a.y)));
+    }
+    ring
+}
+
+/// `ring` started at the point that keeps it closest to `previous`, point for point.
+fn align(mut ring: Vec<Point>, previous: &[Point]) -> Vec<Point> {
+    let n = ring.len();
+    let cost = |shift: usize| -> f64 {
+        (0..n)
+            .map(|i| {
+                let (a, b) = (ring[(i + shift) % n], previous[i]);
+                (a.x - b.x).powi(2) + (a.y - b.y).powi(2)
+            })
+            .sum()
+    };
+    let best = (0..n)
+        .min_by(|&a, &b| cost(a).total_cmp(&cost(b)))
+        .unwrap_or(0);
+    ring.rotate_left(best);
+    ring
+}
+
+/// The ring of the frame nearest `frame` that has one, preferring earlier frames.
+fn nearest_ring(rings: &[Option<Vec<Point>>], frame: usize) -> &[Point] {
+    (1..rings.len())
+        .flat_map(|d| [frame.checked_sub(d), Some(frame + d)])
+        .flatten()
+        .find_map(|i| rings.get(i)?.as_deref())
+        .unwrap_or_default()
+}
+
+/// The mean of `points`.
+fn centroid(points: &[Point]) -> Point {
+    let n = points.len().max(1) as f64;
+    let (x, y) = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + p.x, y + p.y));
+    Point::new(x / n, y / n)
+}
+
+/// Path data for one outline: a move, then one segment per point back round to the start.
+fn ring_data(ring: &[Point], options: &SequenceOptions) -> String {
+    let precision = options.path_precision;
+    let n = ring.len();
+    let mut data = String::new();
+    push_point(&mut data, 'M', ring[0], precision);
+    for j in 0..n {
+        if options.smooth {
+            let (p0, p1, p2, p3) = (
+                ring[(j + n - 1) % n],
+                ring[j],
+                ring[(j + 1) % n],
+                ring[(j + 2) % n],
+            );
+            push_cubic(&mut data, p0, p1, p2, p3, precision);
+        } else {
+            push_point(&mut data, 'L', ring[(j + 1) % n], precision);
+        }
+    }
+    data.push('Z');
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn blobs(size: u32, squares: &[(u32, u32, u32)]) -> GrayImage {
+        GrayImage::from_fn(size, size, |x, y| {
+            let inside = squares.iter().any(|&(x0, y0, side)| {
+                (x0..x0 + side).contains(&x) && (y0..y0 + side).contains(&y)
+            });
+            Luma([if inside { 255 } else { 0 }])
+        })
+    }
+
+    fn commands(svg: &str) -> String {
+        svg.chars().filter(char::is_ascii_uppercase).collect()
+    }
+
+    mod outline_sequence {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn frames_share_node_counts_and_commands() {
+                let masks = [
+                    blobs(64, &[(10, 10, 20)]),
+                    blobs(64, &[(14, 12, 24)]),
+                    blobs(64, &[(20, 16, 16)]),
+                ];
+                let options = SequenceOptions {
+                    nodes: 24,
+                    ..SequenceOptions::default()
+                };
+                let sequence = OutlineSequence::trace(&masks, &options).unwrap();
+                assert!(
+                    sequence
+                        .frames
+                        .iter()
+                        .all(|frame| frame.outlines.len() == 1 && frame.outlines[0].len() == 24)
+                );
+                let svgs = sequence.svgs(&options);
+                assert!(svgs.iter().all(|svg| commands(svg) == commands(&svgs[0])));
+                assert!(svgs[0].contains("id=\"outline-0\""));
+            }
+
+            #[test]
+            fn moving_outlines_keep_their_start() {
+                let masks = [blobs(64, &[(10, 10, 20)]), blobs(64, &[(30, 10, 20)])];
+                let sequence = OutlineSequence::trace(&masks, &SequenceOptions::default()).unwrap();
+                let (before, after) = (
+                    &sequence.frames[0].outlines[0],
+                    &sequence.frames[1].outlines[0],
+                );
+                for (a, b) in before.iter().zip(after) {
+                    assert!((b[0] - a[0] - 20.0).abs() < 1.0 && (b[1] - a[1]).abs() < 1.0);
+                }
+            }
+
+            #[test]
+            fn appearing_outlines_grow_from_a_point() {
+                let masks = [
+                    blobs(64, &[(4, 4, 20)]),
+                    blobs(64, &[(4, 4, 20), (40, 40, 12)]),
+                ];
+                let sequence = OutlineSequence::trace(&masks, &SequenceOptions::default()).unwrap();
+                assert_eq!(sequence.frames[0].outlines.len(), 2);
+                let collapsed = &sequence.frames[0].outlines[1];
+                assert!(collapsed.iter().all(|p| p == &collapsed[0]));
+                assert!((collapsed[0][0] - 46.0).abs() < 1.0);
+                let first = &sequence.frames[0].outlines[0];
+                let second = &sequence.frames[1].outlines[0];
+                assert!((first[0][0] - second[0][0]).abs() < 0.5);
+            }
+
+            #[test]
+            fn rejects_frames_of_different_sizes() {
+                let masks = [GrayImage::new(8, 8), GrayImage::new(8, 9)];
+                assert!(OutlineSequence::trace(&masks, &SequenceOptions::default()).is_err());
+                assert!(OutlineSequence::trace(&[], &SequenceOptions::default()).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{BgrError, BgrResult};
+
+/// A 2D affine transform, laid out like SVG's `matrix(a b c d e f)`.
+///
+/// Maps `(x, y)` to `(a*x + c*y + e, b*x + d*y + f)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl Affine {
+    /// The identity transform.
+    pub const IDENTITY: Affine = Affine {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 1.0,
+        e: 0.0,
+        f: 0.0,
+    };
+
+    /// Scale by `sx` horizontally and `sy` vertically.
+    pub fn scale(sx: f64, sy: f64) -> Self {
+        Self {
+            a: sx,
+            d: sy,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Translate by `(tx, ty)`.
+    pub fn translate(tx: f64, ty: f64) -> Self {
+        Self {
+            e: tx,
+            f: ty,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Mirror vertically within a canvas of the given height, putting the origin bottom-left.
+    pub fn flip_y(height: f64) -> Self {
+        Self {
+            d: -1.0,
+            f: height,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Compose two transforms: the result applies `self` first, then `next`.
+    pub fn then(self, next: Affine) -> Self {
+        Self {
+            a: next.a * self.a + next.c * self.b,
+            b: next.b * self.a + next.d * self.b,
+            c: next.a * self.c + next.c * self.d,
+            d: next.b * self.c + next.d * self.d,
+            e: next.a * self.e + next.c * self.f + next.e,
+            f: next.b * self.e + next.d * self.f + next.f,
+        }
+    }
+
+    /// Transform a point.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    /// Whether this is the identity transform.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+}
+
+impl fmt::Display for Affine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "matrix({} {} {} {} {} {})",
+            format_number(self.a),
+            format_number(self.b),
+            format_number(self.c),
+            format_number(self.d),
+            format_number(self.e),
+            format_number(self.f)
+        )
+    }
+}
+
+impl FromStr for Affine {
+    type Err = String;
+
+    /// Parse six comma- or space-separated numbers `a,b,c,d,e,f`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                part.parse::<f64>()
+                    .map_err(|_| format!("invalid transform component `{part}`"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match values.as_slice() {
+            &[a, b, c, d, e, f] => Ok(Self { a, b, c, d, e, f }),
+            _ => Err(format!(
+                "transform needs 6 numbers `a,b,c,d,e,f`, got {}",
+                values.len()
+            )),
+        }
+    }
+}
+
+/// Physical units for traced output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalUnit {
+    Millimeters,
+    Centimeters,
+    Inches,
+    Points,
+}
+
+impl PhysicalUnit {
+    /// Number of units in one inch.
+    pub fn per_inch(&self) -> f64 {
+        match self {
+            PhysicalUnit::Millimeters => 25.4,
+            PhysicalUnit::Centimeters => 2.54,
+            PhysicalUnit::Inches => 1.0,
+            PhysicalUnit::Points => 72.0,
+        }
+    }
+
+    /// SVG length suffix.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            PhysicalUnit::Millimeters => "mm",
+            PhysicalUnit::Centimeters => "cm",
+            PhysicalUnit::Inches => "in",
+            PhysicalUnit::Points => "pt",
+        }
+    }
+}
+
+/// The coordinate space traced paths are expressed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordinateSpace {
+    /// Image pixels, origin top-left (the tracer's native space).
+    Pixels,
+    /// Both axes scaled to `[0, 1]`.
+    Normalized,
+    /// Physical lengths computed from the image resolution.
+    Physical { unit: PhysicalUnit, dpi: f64 },
+}
+
+/// Coordinate system and extra transform applied to traced output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateOptions {
+    pub space: CoordinateSpace,
+    /// Flip the Y axis so it points up (math convention), with the origin bottom-left.
+    pub flip_y: bool,
+    /// Extra affine transform applied last.
+    pub transform: Option<Affine>,
+}
+
+impl Default for CoordinateOptions {
+    fn default() -> Self {
+        Self {
+            space: CoordinateSpace::Pixels,
+            flip_y: false,
+            transform: None,
+        }
+    }
+}
+
+impl CoordinateOptions {
+    /// Build the full transform for a traced image of `width` × `height` pixels.
+    pub fn matrix(&self, width: u32, height: u32) -> Affine {
+        let (w, h) = (f64::from(width), f64::from(height));
+        let mut matrix = Affine::IDENTITY;
+        if self.flip_y {
+            matrix = matrix.then(Affine::flip_y(h));
+        }
+        matrix = match self.space {
+            CoordinateSpace::Pixels => matrix,
+            CoordinateSpace::Normalized => {
+                matrix.then(Affine::scale(1.0 / w.max(1.0), 1.0 / h.max(1.0)))
+            }
+            CoordinateSpace::Physical { unit, dpi } => {
+                let per_px = unit.per_inch() / dpi;
+                matrix.then(Affine::scale(per_px, per_px))
+            }
+        };
+        match self.transform {
+            Some(extra) => matrix.then(extra),
+            None => matrix,
+        }
+    }
+
+    /// Whether these options leave the tracer output untouched.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Re-express a traced SVG in the requested coordinate system.
+///
+/// Wraps the SVG body in a `<g transform="matrix(...)">` group and rewrites the root element's
+/// size and `viewBox` to the transformed image bounds.
+pub fn apply_coordinates(
+    svg: &str,
+    width: u32,
+    height: u32,
+    options: &CoordinateOptions,
+) -> BgrResult<String> {
+    if options.is_identity() {
+        return Ok(svg.to_string());
+    }
+
+    let open_start = svg
+        .find("<svg")
+        .ok_or_else(|| BgrError::Trace("SVG root element not found".to_string()))?;
+    let open_end = svg[open_start..]
+        .find('>')
+        .map(|i| open_start + i + 1)
+        .ok_or_else(|| BgrError::Trace("SVG root element is not closed".to_string()))?;
+    let close_start = svg
+        .rfind("</svg>")
+        .ok_or_else(|| BgrError::Trace("SVG closing tag not found".to_string()))?;
+
+    let matrix = options.matrix(width, height);
+    let (w, h) = (f64::from(width), f64::from(height));
+    let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)].map(|(x, y)| matrix.apply(x, y));
+    let min_x = corners.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let max_x = corners
+        .iter()
+        .map(|p| p.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_y = corners.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_y = corners
+        .iter()
+        .map(|p| p.1)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let (view_w, view_h) = (max_x - min_x, max_y - min_y);
+
+    let (width_attr, height_attr) = match options.space {
+        // Keep the on-screen size of the original image for unit-square coordinates
+        CoordinateSpace::Normalized => (width.to_string(), height.to_string()),
+        CoordinateSpace::Physical { unit, .. } => (
+            format!("{}{}", format_number(view_w), unit.suffix()),
+            format!("{}{}", format_number(view_h), unit.suffix()),
+        ),
+        CoordinateSpace::Pixels => (format_number(view_w), format_number(view_h)),
+    };
+
+    let mut out = String::with_capacity(svg.len() + 256);
+    out.push_str(&svg[..open_start]);
+    out.push_str(&format!(
+        r#"<svg version="1.1" xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        width_attr,
+        height_attr,
+        format_number(min_x),
+        format_number(min_y),
+        format_number(view_w),
+        format_number(view_h)
+    ));
+    out.push_str(&format!("\n<g transform=\"{matrix}\">"));
+    out.push_str(&svg[open_end..close_start]);
+    out.push_str("</g>\n");
+    out.push_str(&svg[close_start..]);
+    Ok(out)
+}
+
+/// Format a number with up to six decimals, trimming trailing zeros.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{value:.6}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = "<?xml version=\"1.0\"?>\n<svg version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" width=\"200\" height=\"100\">\n<path d=\"M0 0 L10 10 Z\"/>\n</svg>\n";
+
+    mod affine {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn then_applies_in_order() {
+                let m = Affine::translate(10.0, 0.0).then(Affine::scale(2.0, 2.0));
+                assert_eq!(m.apply(1.0, 1.0), (22.0, 2.0));
+            }
+
+            #[test]
+            fn flip_y_maps_top_to_bottom() {
+                let m = Affine::flip_y(100.0);
+                assert_eq!(m.apply(5.0, 0.0), (5.0, 100.0));
+                assert_eq!(m.apply(5.0, 100.0), (5.0, 0.0));
+            }
+
+            #[test]
+            fn parse_six_numbers() {
+                let m: Affine = "1, 0, 0, -1, 0 50".parse().unwrap();
+                assert_eq!(m, Affine::flip_y(50.0));
+            }
+
+            #[test]
+            fn parse_rejects_wrong_count() {
+                assert!("1,0,0".parse::<Affine>().is_err());
+                assert!("1,0,0,1,0,x".parse::<Affine>().is_err());
+            }
+
+            #[test]
+            fn display_as_svg_matrix() {
+                let m = Affine::scale(0.5, 2.0);
+                assert_eq!(m.to_string(), "matrix(0.5 0 0 2 0 0)");
+            }
+        }
+
+        mod prop {
+            use super::*;
+            use proptest::prelude::*;
+
+            proptest! {
+                /// Affine::then: composing with identity leaves points unchanged
+                #[test]
+                fn identity_is_neutral(
+                    sx in -10.0f64..10.0,
+                    tx in -100.0f64..100.0,
+                    x in -100.0f64..100.0,
+                    y in -100.0f64..100.0
+                ) {
+                    let m = Affine::scale(sx, sx).then(Affine::translate(tx, tx));
+                    let a = m.then(Affine::IDENTITY).apply(x, y);
+                    let b = m.apply(x, y);
+                    prop_assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    mod apply_coordinates {
+        use super::*;
+
+        #[test]
+        fn identity_is_passthrough() {
+            let out = apply_coordinates(SVG, 200, 100, &CoordinateOptions::default()).unwrap();
+            assert_eq!(out, SVG);
+        }
+
+        #[test]
+        fn normalized_uses_unit_view_box() {
+            let options = CoordinateOptions {
+                space: CoordinateSpace::Normalized,
+                ..CoordinateOptions::default()
+            };
+            let out = apply_coordinates(SVG, 200, 100, &options).unwrap();
+            assert!(out.contains(r#"viewBox="0 0 1 1""#));
+            assert!(out.contains(r#"<g transform="matrix(0.005 0 0 0.01 0 0)">"#));
+            assert!(out.trim_end().ends_with("</g>\n</svg>"));
+        }
+
+        #[test]
+        fn physical_sizes_in_units() {
+            let options = CoordinateOptions {
+                space: CoordinateSpace::Physical {
+                    unit: PhysicalUnit::Inches,
+                    dpi: 100.0,
+                },
+                ..CoordinateOptions::default()
+            };
+            let out = apply_coordinates(SVG, 200, 100, &options).unwrap();
+            assert!(out.contains(r#"width="2in" height="1in""#));
+        }
+
+        #[test]
+        fn flip_y_keeps_positive_view_box() {
+            let options = CoordinateOptions {
+                flip_y: true,
+                ..CoordinateOptions::default()
+            };
+            let out = apply_coordinates(SVG, 200, 100, &options).unwrap();
+            assert!(out.contains(r#"viewBox="0 0 200 100""#));
+            assert!(out.contains("matrix(1 0 0 -1 0 100)"));
+        }
+
+        #[test]
+        fn missing_root_is_error() {
+            let options = CoordinateOptions {
+                flip_y: true,
+                ..CoordinateOptions::default()
+            };
+            assert!(apply_coordinates("<path/>", 1, 1, &options).is_err());
+        }
+    }
+}
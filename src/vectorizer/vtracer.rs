@@ -6,6 +6,7 @@ use crate::mask::gray_to_color_image_rgba;
 use crate::{BgrError, BgrResult};
 
 use super::MaskVectorizer;
+use super::geometry::{MAX_SIMPLIFY_STEPS, node_budget_error};
 
 /// Configuration for vectorizing masks into SVG paths using VTracer.
 ///
@@ -24,6 +25,8 @@ pub struct TraceOptions {
     pub tracer_max_iterations: usize,
     pub tracer_splice_threshold: i32,
     pub tracer_path_precision: Option<u32>,
+    /// Maximum nodes allowed in any single path; simplification is increased until it fits.
+    pub tracer_max_nodes: Option<usize>,
     pub invert_svg: bool,
 }
 
@@ -41,6 +44,7 @@ impl Default for TraceOptions {
             tracer_max_iterations: 10,
             tracer_splice_threshold: 45,
             tracer_path_precision: Some(2),
+            tracer_max_nodes: None,
             invert_svg: false,
         }
     }
@@ -63,10 +67,57 @@ impl MaskVectorizer for VtracerSvgVectorizer {
 }
 
 /// The helper function that uses VTracer to trace a grayscale mask to an SVG string.
+///
+/// When [`TraceOptions::tracer_max_nodes`] is set, the mask is re-traced with progressively
+/// coarser settings until every path fits within the budget.
 pub fn trace_to_svg_string(mask_image: &GrayImage, options: &TraceOptions) -> BgrResult<String> {
     let color_img = gray_to_color_image_rgba(mask_image, None, options.invert_svg);
-    let svg_file = trace(color_img, options)?;
-    Ok(svg_file.to_string())
+    let Some(budget) = options.tracer_max_nodes else {
+        return Ok(trace(color_img, options)?.to_string());
+    };
+
+    let mut attempt = options.clone();
+    let mut largest = 0;
+    for _ in 0..MAX_SIMPLIFY_STEPS {
+        let svg = trace(color_img.clone(), &attempt)?.to_string();
+        largest = max_path_nodes(&svg);
+        if largest <= budget {
+            return Ok(svg);
+        }
+        attempt = simplify_further(attempt);
+    }
+    Err(node_budget_error(budget, largest))
+}
+
+/// Coarsen tracing settings by one step.
+fn simplify_further(mut options: TraceOptions) -> TraceOptions {
+    // Splines need far fewer nodes than pixel or polygon outlines, so switch to them first.
+    if !matches!(options.tracer_mode, PathSimplifyMode::Spline) {
+        options.tracer_mode = PathSimplifyMode::Spline;
+        return options;
+    }
+    options.tracer_length_threshold *= 1.5;
+    options.tracer_max_iterations = options.tracer_max_iterations.saturating_sub(1).max(1);
+    options.tracer_corner_threshold = (options.tracer_corner_threshold + 15).min(180);
+    options.tracer_splice_threshold = (options.tracer_splice_threshold + 15).min(180);
+    options.tracer_filter_speckle = options.tracer_filter_speckle.max(1) * 2;
+    options
+}
+
+/// Largest node count among the `<path>` elements of an SVG document.
+///
+/// Every drawing command (`M`, `L`, `C`, ...) counts as one node; `Z` does not.
+pub fn max_path_nodes(svg: &str) -> usize {
+    svg.split(" d=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .map(|data| {
+            data.chars()
+                .filter(|c| c.is_ascii_alphabetic() && !matches!(c, 'Z' | 'z'))
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
 }
 
 /// Trace a ColorImage into an SVG using VTracer with the given options.
@@ -88,3 +139,65 @@ pub fn trace(img: ColorImage, options: &TraceOptions) -> BgrResult<SvgFile> {
     let svg_file = convert(img, cfg).map_err(BgrError::Trace)?;
     Ok(svg_file)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    mod max_path_nodes {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn counts_largest_path() {
+                let svg =
+                    r#"<svg><path d="M0 0 L1 0 L1 1 Z" /><path d="M0 0 C1 1 2 2 3 3 Z " /></svg>"#;
+                assert_eq!(max_path_nodes(svg), 3);
+            }
+
+            #[test]
+            fn empty_document_has_no_nodes() {
+                assert_eq!(max_path_nodes("<svg></svg>"), 0);
+            }
+        }
+    }
+
+    mod trace_to_svg_string {
+        use super::*;
+
+        fn disc(size: u32) -> GrayImage {
+            let c = size as f32 / 2.0;
+            GrayImage::from_fn(size, size, |x, y| {
+                let d = ((x as f32 - c).powi(2) + (y as f32 - c).powi(2)).sqrt();
+                Luma([if d < c * 0.8 { 255 } else { 0 }])
+            })
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn budget_is_respected() {
+                let options = TraceOptions {
+                    tracer_mode: PathSimplifyMode::None,
+                    tracer_max_nodes: Some(40),
+                    ..TraceOptions::default()
+                };
+                let svg = trace_to_svg_string(&disc(128), &options).unwrap();
+                assert!(max_path_nodes(&svg) <= 40);
+            }
+
+            #[test]
+            fn impossible_budget_errors() {
+                let options = TraceOptions {
+                    tracer_max_nodes: Some(0),
+                    ..TraceOptions::default()
+                };
+                assert!(trace_to_svg_string(&disc(64), &options).is_err());
+            }
+        }
+    }
+}
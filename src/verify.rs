@@ -0,0 +1,280 @@
+//! Regression checks of generated outputs against stored golden fingerprints.
+//!
+//! A [`Fingerprint`] captures a 64-bit difference hash of an image together with its
+//! alpha coverage, which is enough to catch visible changes after a model or dependency
+//! upgrade while tolerating encoder-level noise.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::error::BgrResult;
+
+/// Perceptual summary of a single output image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub width: u32,
+    pub height: u32,
+    /// Difference hash of the luminance, premultiplied by alpha.
+    pub dhash: u64,
+    /// Mean alpha (or luminance for images without alpha) in `[0.0, 1.0]`.
+    pub coverage: f64,
+}
+
+/// Allowed drift between a golden fingerprint and a fresh one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Maximum number of differing hash bits (out of 64).
+    pub max_hash_distance: u32,
+    /// Maximum absolute coverage difference.
+    pub max_coverage_delta: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            max_hash_distance: 4,
+            max_coverage_delta: 0.01,
+        }
+    }
+}
+
+/// A single way in which an output deviates from its golden fingerprint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    Dimensions {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    HashDistance {
+        distance: u32,
+        max: u32,
+    },
+    Coverage {
+        delta: f64,
+        max: f64,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Dimensions { expected, found } => {
+                write!(f, "size {found:?} differs from golden {expected:?}")
+            }
+            Mismatch::HashDistance { distance, max } => {
+                write!(f, "perceptual hash distance {distance} exceeds {max}")
+            }
+            Mismatch::Coverage { delta, max } => {
+                write!(f, "coverage differs by {delta:.4} (max {max:.4})")
+            }
+        }
+    }
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of an image.
+    pub fn of_image(image: &DynamicImage) -> Self {
+        let has_alpha = image.color().has_alpha();
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+
+        let mut signal_sum = 0u64;
+        let premultiplied = GrayImage::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let luma = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+            signal_sum += u64::from(if has_alpha { u32::from(a) } else { luma });
+            Luma([(luma * u32::from(a) / 255) as u8])
+        });
+
+        let pixels = u64::from(width) * u64::from(height);
+        let coverage = if pixels == 0 {
+            0.0
+        } else {
+            signal_sum as f64 / (pixels as f64 * 255.0)
+        };
+
+        Self {
+            width,
+            height,
+            dhash: difference_hash(&premultiplied),
+            coverage,
+        }
+    }
+
+    /// Load an image from disk and compute its fingerprint.
+    pub fn of_path(path: impl AsRef<Path>) -> BgrResult<Self> {
+        Ok(Self::of_image(&image::open(path)?))
+    }
+
+    /// Compare a fresh fingerprint against this golden one.
+    pub fn compare(&self, actual: &Fingerprint, tolerance: &Tolerance) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        if (self.width, self.height) != (actual.width, actual.height) {
+            mismatches.push(Mismatch::Dimensions {
+                expected: (self.width, self.height),
+                found: (actual.width, actual.height),
+            });
+        }
+        let distance = (self.dhash ^ actual.dhash).count_ones();
+        if distance > tolerance.max_hash_distance {
+            mismatches.push(Mismatch::HashDistance {
+                distance,
+                max: tolerance.max_hash_distance,
+            });
+        }
+        let delta = (self.coverage - actual.coverage).abs();
+        if delta > tolerance.max_coverage_delta {
+            mismatches.push(Mismatch::Coverage {
+                delta,
+                max: tolerance.max_coverage_delta,
+            });
+        }
+        mismatches
+    }
+}
+
+/// 64-bit difference hash: compares horizontally adjacent pixels of a 9×8 thumbnail.
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let thumb = imageops::resize(gray, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if thumb.get_pixel(x, y)[0] < thumb.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// A set of golden fingerprints keyed by output file name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GoldenSet {
+    pub images: BTreeMap<String, Fingerprint>,
+}
+
+impl GoldenSet {
+    /// Read a golden set from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> BgrResult<Self> {
+        let file = std::fs::File::open(path)?;
+        let set = serde_json::from_reader(io::BufReader::new(file)).map_err(io::Error::from)?;
+        Ok(set)
+    }
+
+    /// Write the golden set as pretty-printed JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> BgrResult<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn half_alpha_image(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, _| {
+            let a = if x < w / 2 { 255 } else { 0 };
+            Rgba([200, 200, 200, a])
+        }))
+    }
+
+    mod fingerprint {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn coverage_from_alpha() {
+                let fp = Fingerprint::of_image(&half_alpha_image(10, 4));
+                assert!((fp.coverage - 0.5).abs() < 1e-9);
+            }
+
+            #[test]
+            fn coverage_from_luma_without_alpha() {
+                let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([255])));
+                let fp = Fingerprint::of_image(&gray);
+                assert!((fp.coverage - 1.0).abs() < 1e-9);
+            }
+
+            #[test]
+            fn identical_images_match() {
+                let golden = Fingerprint::of_image(&half_alpha_image(32, 32));
+                let actual = Fingerprint::of_image(&half_alpha_image(32, 32));
+                assert!(golden.compare(&actual, &Tolerance::default()).is_empty());
+            }
+
+            #[test]
+            fn mirrored_image_exceeds_hash_distance() {
+                let golden = Fingerprint::of_image(&half_alpha_image(32, 32));
+                let mirrored = DynamicImage::ImageRgba8(imageops::flip_horizontal(
+                    &half_alpha_image(32, 32).to_rgba8(),
+                ));
+                let actual = Fingerprint::of_image(&mirrored);
+                let mismatches = golden.compare(&actual, &Tolerance::default());
+                assert!(
+                    mismatches
+                        .iter()
+                        .any(|m| matches!(m, Mismatch::HashDistance { .. }))
+                );
+            }
+
+            #[test]
+            fn dimension_change_reported() {
+                let golden = Fingerprint::of_image(&half_alpha_image(32, 32));
+                let actual = Fingerprint::of_image(&half_alpha_image(16, 32));
+                let mismatches = golden.compare(&actual, &Tolerance::default());
+                assert!(mismatches.contains(&Mismatch::Dimensions {
+                    expected: (32, 32),
+                    found: (16, 32),
+                }));
+            }
+
+            #[test]
+            fn coverage_tolerance_respected() {
+                let golden = Fingerprint {
+                    width: 1,
+                    height: 1,
+                    dhash: 0,
+                    coverage: 0.50,
+                };
+                let actual = Fingerprint {
+                    coverage: 0.55,
+                    ..golden
+                };
+                let loose = Tolerance {
+                    max_coverage_delta: 0.1,
+                    ..Tolerance::default()
+                };
+                assert!(golden.compare(&actual, &loose).is_empty());
+                assert_eq!(golden.compare(&actual, &Tolerance::default()).len(), 1);
+            }
+        }
+    }
+
+    mod golden_set {
+        use super::*;
+
+        #[test]
+        fn json_round_trip() {
+            let mut set = GoldenSet::default();
+            set.images.insert(
+                "a-foreground.png".to_string(),
+                Fingerprint::of_image(&half_alpha_image(8, 8)),
+            );
+            let json = serde_json::to_string(&set).unwrap();
+            let parsed: GoldenSet = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, set);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+/// Matte values at or above this count as foreground.
+const FOREGROUND: u8 = 128;
+/// Matte values inside this range are neither clearly foreground nor clearly background.
+const UNDECIDED: std::ops::RangeInclusive<u8> = 32..=223;
+/// Below this share of decisive pixels the matte is reported as low confidence.
+const MIN_CONFIDENCE: f32 = 0.8;
+
+/// A side of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+            Edge::Left => "left",
+            Edge::Right => "right",
+        })
+    }
+}
+
+/// A non-fatal issue noticed while processing one input.
+///
+/// Collected on [`InferencedMatte::warnings`](crate::InferencedMatte::warnings); the CLI prints
+/// them per file and writes them to `--report`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// The input embeds an ICC color profile that could not be applied, or bgr was built
+    /// without color management; pixels are treated as sRGB.
+    IccProfileIgnored,
+    /// The input was rotated or flipped according to its EXIF orientation (1-8).
+    OrientationApplied { exif: u8 },
+    /// Foreground reaches these image edges, so the subject may be cropped.
+    SubjectTouchesEdge { edges: Vec<Edge> },
+    /// Only this share (0-1) of the subject's pixels is clearly foreground or background.
+    LowConfidence { confidence: f32 },
+    /// The model found no foreground at all.
+    EmptyMatte,
+    /// The model found foreground everywhere, leaving nothing to remove.
+    FullMatte,
+    /// The matte was made fully opaque, so the outputs keep the whole input
+    /// ([`InferencedMatte::keep_whole`](crate::InferencedMatte::keep_whole)).
+    InputKeptWhole,
+    /// No output was written for the input, as asked for an empty or full matte.
+    OutputSkipped,
+    /// The output already existed, so the input was left alone.
+    OutputExists { path: PathBuf },
+    /// The output already existed, so this free name beside it was written instead.
+    OutputRenamed { path: PathBuf },
+    /// The batch being resumed already wrote this output from the same input, so it was left
+    /// alone.
+    AlreadyWritten { path: PathBuf },
+    /// A supplied mask of this size was stretched to the size of its image.
+    MaskResized { width: u32, height: u32 },
+    /// A supplied mask was almost binary, so its stray gray values were thresholded away.
+    MaskBinarized,
+    /// The output format has no alpha channel, so the cutout was composited over this color.
+    AlphaFlattened { color: [u8; 3] },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::IccProfileIgnored => {
+                f.write_str("embedded ICC profile ignored; colors are treated as sRGB")
+            }
+            Warning::OrientationApplied { exif } => {
+                write!(
+                    f,
+                    "EXIF orientation {exif} applied; outputs are rotated to match"
+                )
+            }
+            Warning::SubjectTouchesEdge { edges } => {
+                let edges: Vec<String> = edges.iter().map(Edge::to_string).collect();
+                write!(
+                    f,
+                    "subject touches the {} edge; it may be cropped",
+                    edges.join(", ")
+                )
+            }
+            Warning::LowConfidence { confidence } => write!(
+                f,
+                "low matte confidence ({:.0}%); edges may be unreliable",
+                confidence * 100.0
+            ),
+            Warning::EmptyMatte => f.write_str("no foreground detected"),
+            Warning::FullMatte => f.write_str("the whole image was detected as foreground"),
+            Warning::InputKeptWhole => {
+                f.write_str("the matte was made opaque; the input is kept whole")
+            }
+            Warning::OutputSkipped => f.write_str("no output written"),
+            Warning::OutputExists { path } => {
+                write!(f, "{} already exists; skipped", path.display())
+            }
+            Warning::OutputRenamed { path } => {
+                write!(
+                    f,
+                    "the output already existed; wrote {} instead",
+                    path.display()
+                )
+            }
+            Warning::AlreadyWritten { path } => {
+                write!(
+                    f,
+                    "{} was written by the interrupted run; skipped",
+                    path.display()
+                )
+            }
+            Warning::MaskResized { width, height } => write!(
+                f,
+                "mask was {width}x{height} and has been stretched to the image size"
+            ),
+            Warning::MaskBinarized => {
+                f.write_str("mask was nearly black and white; stray gray values were thresholded")
+            }
+            Warning::AlphaFlattened { color: [r, g, b] } => write!(
+                f,
+                "the output format has no transparency; flattened over #{r:02x}{g:02x}{b:02x}"
+            ),
+        }
+    }
+}
+
+/// Inspect a raw matte for an empty or full result, a subject cut off by the frame, or a
+/// hesitant model.
+pub(crate) fn matte_warnings(matte: &GrayImage) -> Vec<Warning> {
+    let (width, height) = matte.dimensions();
+    let mut present = 0u64;
+    let mut undecided = 0u64;
+    for pixel in matte.pixels() {
+        let value = pixel.0[0];
+        if value >= *UNDECIDED.start() {
+            present += 1;
+        }
+        if UNDECIDED.contains(&value) {
+            undecided += 1;
+        }
+    }
+    if !matte.pixels().any(|pixel| pixel.0[0] >= FOREGROUND) {
+        return vec![Warning::EmptyMatte];
+    }
+
+    let mut warnings = Vec::new();
+    let row = |y: u32| (0..width).any(|x| matte.get_pixel(x, y).0[0] >= FOREGROUND);
+    let column = |x: u32| (0..height).any(|y| matte.get_pixel(x, y).0[0] >= FOREGROUND);
+    let edges: Vec<Edge> = [
+        (Edge::Top, row(0)),
+        (Edge::Bottom, row(height - 1)),
+        (Edge::Left, column(0)),
+        (Edge::Right, column(width - 1)),
+    ]
+    .into_iter()
+    .filter_map(|(edge, touches)| touches.then_some(edge))
+    .collect();
+    if !edges.is_empty() {
+        warnings.push(Warning::SubjectTouchesEdge { edges });
+    }
+    if matte.pixels().all(|pixel| pixel.0[0] >= FOREGROUND) {
+        warnings.push(Warning::FullMatte);
+    }
+
+    let confidence = 1.0 - undecided as f32 / present as f32;
+    if confidence < MIN_CONFIDENCE {
+        warnings.push(Warning::LowConfidence { confidence });
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    mod matte_warnings {
+        use super::*;
+
+        /// A 10x10 matte with a solid square from (lo, lo) to (hi, hi) inclusive.
+        fn square(lo: u32, hi: u32, value: u8) -> GrayImage {
+            GrayImage::from_fn(10, 10, |x, y| {
+                let inside = (lo..=hi).contains(&x) && (lo..=hi).contains(&y);
+                Luma([if inside { value } else { 0 }])
+            })
+        }
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn centered_confident_subject_is_clean() {
+                assert!(matte_warnings(&square(2, 7, 255)).is_empty());
+            }
+
+            #[test]
+            fn empty_matte_reported_alone() {
+                assert_eq!(matte_warnings(&square(2, 7, 0)), vec![Warning::EmptyMatte]);
+                // Faint haze is still empty.
+                assert_eq!(matte_warnings(&square(2, 7, 60)), vec![Warning::EmptyMatte]);
+            }
+
+            #[test]
+            fn edges_reported_in_order() {
+                let warnings = matte_warnings(&square(0, 4, 255));
+                assert_eq!(
+                    warnings,
+                    vec![Warning::SubjectTouchesEdge {
+                        edges: vec![Edge::Top, Edge::Left]
+                    }]
+                );
+                let warnings = matte_warnings(&square(0, 9, 255));
+                assert!(matches!(
+                    &warnings[0],
+                    Warning::SubjectTouchesEdge { edges } if edges.len() == 4
+                ));
+            }
+
+            #[test]
+            fn full_matte_reported() {
+                assert!(matte_warnings(&square(0, 9, 255)).contains(&Warning::FullMatte));
+                assert!(!matte_warnings(&square(0, 8, 255)).contains(&Warning::FullMatte));
+            }
+
+            #[test]
+            fn grey_matte_is_low_confidence() {
+                let warnings = matte_warnings(&square(2, 7, 150));
+                assert!(matches!(
+                    warnings.as_slice(),
+                    [Warning::LowConfidence { confidence }] if *confidence < 0.01
+                ));
+            }
+        }
+    }
+
+    mod warning {
+        use super::*;
+
+        mod unit {
+            use super::*;
+
+            #[test]
+            fn serializes_with_kind_tag() {
+                let json = serde_json::to_string(&Warning::SubjectTouchesEdge {
+                    edges: vec![Edge::Left],
+                })
+                .unwrap();
+                assert_eq!(json, r#"{"kind":"subject_touches_edge","edges":["left"]}"#);
+                let json = serde_json::to_string(&Warning::EmptyMatte).unwrap();
+                assert_eq!(json, r#"{"kind":"empty_matte"}"#);
+            }
+
+            #[test]
+            fn json_round_trip() {
+                let warnings = vec![
+                    Warning::IccProfileIgnored,
+                    Warning::OrientationApplied { exif: 6 },
+                    Warning::LowConfidence { confidence: 0.5 },
+                ];
+                let json = serde_json::to_string(&warnings).unwrap();
+                let back: Vec<Warning> = serde_json::from_str(&json).unwrap();
+                assert_eq!(back, warnings);
+            }
+
+            #[test]
+            fn messages_name_the_issue() {
+                let edges = Warning::SubjectTouchesEdge {
+                    edges: vec![Edge::Top, Edge::Right],
+                };
+                assert_eq!(
+                    edges.to_string(),
+                    "subject touches the top, right edge; it may be cropped"
+                );
+                let low = Warning::LowConfidence { confidence: 0.42 };
+                assert!(low.to_string().contains("42%"));
+            }
+        }
+    }
+}
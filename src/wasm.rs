@@ -0,0 +1,37 @@
+//! Browser bindings for the `wasm` feature, built for `wasm32-unknown-unknown` with
+//! wasm-bindgen.
+//!
+//! There is no filesystem or network access in the browser, so the page fetches the ONNX model
+//! itself (such as from a preset's [`download_url`](crate::models::ModelPreset::download_url))
+//! and passes the bytes in. Inference runs on the tract backend, on the page's thread.
+
+use wasm_bindgen::prelude::*;
+
+use crate::Remover;
+
+/// A loaded model that cuts subjects out of encoded images.
+#[wasm_bindgen]
+pub struct WasmRemover {
+    remover: Remover,
+}
+
+#[wasm_bindgen]
+impl WasmRemover {
+    /// Load the ONNX model in `model`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(model: Vec<u8>) -> Result<WasmRemover, JsError> {
+        Ok(Self {
+            remover: Remover::builder().model_bytes(model).build()?,
+        })
+    }
+
+    /// Cut the subject out of an encoded image, returned as a PNG with transparency.
+    pub fn remove(&mut self, image: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.remover.remove_bytes(image)?)
+    }
+
+    /// The alpha mask of an encoded image's subject, as a grayscale PNG.
+    pub fn mask(&mut self, image: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.remover.mask_bytes(image)?)
+    }
+}
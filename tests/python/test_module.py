@@ -0,0 +1,28 @@
+"""Checks of the `bgr` extension module that need no model: `maturin develop && pytest`."""
+
+import pytest
+
+import bgr
+
+
+def test_version():
+    assert bgr.__version__
+
+
+def test_unknown_device_rejected():
+    with pytest.raises(ValueError, match="expected one of cpu, cuda"):
+        bgr.Remover(device="tpu")
+
+
+def test_offline_presets_are_not_downloaded(tmp_path):
+    with pytest.raises(RuntimeError, match="not found"):
+        bgr.Remover(model="isnet", models_dir=str(tmp_path), offline=True)
+
+
+def test_missing_presets_are_downloaded(tmp_path):
+    # A models directory that is a file fails the download before any request is made, which
+    # only happens when the wheel was built with downloads.
+    models_dir = tmp_path / "models"
+    models_dir.write_bytes(b"")
+    with pytest.raises(RuntimeError, match="Cannot write to models directory"):
+        bgr.Remover(model="isnet", models_dir=str(models_dir))